@@ -0,0 +1,688 @@
+use bevy::prelude::*;
+use bevy_atmosphere::*;
+use rand::prelude::*;
+use std::f32::consts::PI;
+
+use crate::{
+    cli::LaunchOptions,
+    game::{
+        GameTime, HideLifebarsEvent, InGameEntity, InitLifebarsEvent, RunSeed, StageClearEvent,
+        UpdateLifebarsEvent,
+    },
+    settings::Settings,
+    weather::LightningEvent,
+    AppState,
+};
+
+pub struct BackdropPlugin;
+
+impl Plugin for BackdropPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SkyTransitionEvent>()
+            .init_resource::<Backdrop>()
+            .init_resource::<SkyController>()
+            .init_resource::<BossFightState>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(backdrop_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_backdrop_stage)
+                    .with_system(update_boss_fight)
+                    .with_system(
+                        update_sun
+                            .after(update_backdrop_stage)
+                            .after(update_boss_fight),
+                    )
+                    .with_system(
+                        scroll_parallax_layers
+                            .after(update_backdrop_stage)
+                            .after(update_boss_fight),
+                    ),
+            );
+    }
+}
+
+/// Arrangement of the quads of a [`ParallaxLayer`].
+enum LayerLayout {
+    /// Quads laid edge to edge into a continuous horizontal band.
+    Tiled {
+        /// Vertical position of the band, in world units.
+        y: f32,
+    },
+    /// Quads scattered at random positions and scales.
+    Scattered {
+        /// Number of quads per world unit of width.
+        density: f32,
+        /// Range of the vertical position of the quads, in world units.
+        height: (f32, f32),
+        /// Range of the random scale applied to the quad size.
+        scale: (f32, f32),
+    },
+}
+
+/// Layer of the backdrop scrolling at its own speed, made of textured quads recycled to
+/// the right once they scrolled past the left.
+struct ParallaxLayer {
+    texture: &'static str,
+    /// Tint of the layer. A low alpha pushes far layers into the sky, or keeps near
+    /// layers from hiding the gameplay.
+    color: Color,
+    /// Scroll speed, in world units per second. Farther layers scroll slower.
+    speed: f32,
+    /// Depth of the layer, which also orders the blending of the layers.
+    depth: f32,
+    /// Size of a quad, in world units.
+    size: (f32, f32),
+    layout: LayerLayout,
+}
+
+impl ParallaxLayer {
+    /// Number of quads of a [`LayerLayout::Tiled`] layer, with one extra quad to cover
+    /// the gap left by the one wrapping around.
+    fn tile_count(&self) -> u32 {
+        let (left, right) = PARALLAX_EXTENT;
+        ((right - left) / self.size.0).ceil() as u32 + 1
+    }
+}
+
+/// Decorative parameters of the backdrop of a stage. The actual values are drawn from
+/// the given ranges with the seeded RNG of the run.
+struct StageBackdrop {
+    /// Parallax layers, from the farthest to the nearest.
+    layers: &'static [ParallaxLayer],
+    /// Keyframes of the time of day along the stage, as pairs of the time since the
+    /// stage started, in seconds, and the time of day. See [`SkyController`].
+    sky: &'static [(f32, f32)],
+    /// Maximum tilt of the sun path away from its default plane, in radians.
+    sun_jitter: f32,
+}
+
+/// Backdrop of each stage, indexed by stage. Stages past the end use the last entry.
+const STAGE_BACKDROPS: &[StageBackdrop] = &[
+    StageBackdrop {
+        layers: &[
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(1., 1., 1., 0.3),
+                speed: 0.4,
+                depth: -0.3,
+                size: (6., 1.2),
+                layout: LayerLayout::Tiled { y: -1.6 },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::WHITE,
+                speed: 6.,
+                depth: -0.1,
+                size: (2., 0.3),
+                layout: LayerLayout::Scattered {
+                    density: 1.,
+                    height: (-1.5, 1.5),
+                    scale: (0.3, 1.7),
+                },
+            },
+        ],
+        sky: &[(0., 0.3), (150., 0.55)],
+        sun_jitter: 0.15,
+    },
+    StageBackdrop {
+        layers: &[
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(1., 0.9, 0.85, 0.35),
+                speed: 0.6,
+                depth: -0.3,
+                size: (6., 1.5),
+                layout: LayerLayout::Tiled { y: -1.4 },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::WHITE,
+                speed: 8.,
+                depth: -0.1,
+                size: (2., 0.3),
+                layout: LayerLayout::Scattered {
+                    density: 1.4,
+                    height: (-2., 0.5),
+                    scale: (0.3, 1.7),
+                },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(1., 1., 1., 0.15),
+                speed: 12.,
+                depth: 0.2,
+                size: (3., 0.6),
+                layout: LayerLayout::Scattered {
+                    density: 0.2,
+                    height: (-2., 2.),
+                    scale: (0.8, 1.5),
+                },
+            },
+        ],
+        sky: &[(0., 0.55), (150., 0.75)],
+        sun_jitter: 0.3,
+    },
+    StageBackdrop {
+        layers: &[
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(0.8, 0.8, 0.9, 0.4),
+                speed: 0.8,
+                depth: -0.3,
+                size: (6., 1.8),
+                layout: LayerLayout::Tiled { y: 1.5 },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(0.8, 0.8, 0.9, 0.4),
+                speed: 0.8,
+                depth: -0.3,
+                size: (6., 1.8),
+                layout: LayerLayout::Tiled { y: -1.5 },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::WHITE,
+                speed: 10.,
+                depth: -0.1,
+                size: (2., 0.3),
+                layout: LayerLayout::Scattered {
+                    density: 1.8,
+                    height: (-1.8, 1.8),
+                    scale: (0.3, 1.7),
+                },
+            },
+            ParallaxLayer {
+                texture: "textures/clouds2.png",
+                color: Color::rgba(0.9, 0.9, 1., 0.2),
+                speed: 15.,
+                depth: 0.2,
+                size: (3., 0.6),
+                layout: LayerLayout::Scattered {
+                    density: 0.3,
+                    height: (-2., 2.),
+                    scale: (0.8, 1.5),
+                },
+            },
+        ],
+        sky: &[(0., 0.75), (60., 0.8), (150., 0.9)],
+        sun_jitter: 0.45,
+    },
+];
+
+/// Horizontal extent of the parallax layers, in world units, wide enough to cover the
+/// screen at any aspect ratio. Quads scrolling past the left edge wrap to the right.
+const PARALLAX_EXTENT: (f32, f32) = (-5., 5.);
+
+/// Base stream of the [`RunSeed`] generators of the backdrop. The stage index is added
+/// to it, so that each stage draws its own sequence.
+const BACKDROP_STREAM: u64 = 0x1000;
+
+/// Illuminance of the sun outside of flashes, in lux.
+const SUN_ILLUMINANCE: f32 = 8000.;
+
+/// Illuminance added at the peak of a lightning flash, relative to the base one.
+const FLASH_BOOST: f32 = 2.5;
+
+/// Intensity lost per second by a lightning flash, from 1 at its peak.
+const FLASH_DECAY: f32 = 6.;
+
+/// Color of the sunlight when the sun touches the horizon, graded toward white as it
+/// rises.
+const HORIZON_SUN_COLOR: Color = Color::rgb(1., 0.5, 0.25);
+
+/// Fraction of [`SUN_ILLUMINANCE`] left when the sun touches the horizon.
+const HORIZON_ILLUMINANCE_RATIO: f32 = 0.2;
+
+/// Fraction of the sky brightness lost at the peak of a boss fight.
+const BOSS_SKY_DARKENING: f32 = 0.6;
+
+/// Color the sunlight shifts toward at the peak of a boss fight.
+const BOSS_SUN_COLOR: Color = Color::rgb(1., 0.25, 0.15);
+
+/// Fraction of the sunlight color shifted toward [`BOSS_SUN_COLOR`] at the peak of a
+/// boss fight.
+const BOSS_SUN_REDNESS: f32 = 0.7;
+
+/// Scroll speed added to the parallax layers at the peak of a boss fight, relative to
+/// their own speed.
+const BOSS_SCROLL_BOOST: f32 = 1.5;
+
+/// Intensity change per second of the boss fight feedback, so that each lifebar lost
+/// eases the backdrop into its next step instead of snapping to it.
+const BOSS_INTENSITY_RATE: f32 = 0.5;
+
+/// Event to move the sun to another time of day, overriding the keyframes of the stage
+/// until the next one starts.
+#[derive(Debug, Clone, Copy)]
+pub struct SkyTransitionEvent {
+    /// Time of day to reach. See [`SkyController`].
+    pub time_of_day: f32,
+    /// Duration of the transition, in seconds.
+    pub duration: f32,
+}
+
+/// Transition of the sky triggered by a [`SkyTransitionEvent`].
+#[derive(Debug, Clone, Copy)]
+struct SkyTransition {
+    from: f32,
+    to: f32,
+    duration: f32,
+    elapsed: f32,
+}
+
+/// Position of the sun along the day, driving the direction and the color grading of
+/// the sunlight, and the sky.
+///
+/// The time of day goes from 0 at sunrise to 1 at sunset, through noon at 0.5. It
+/// follows the keyframes of the current stage, unless a transition from the timeline
+/// took over.
+#[derive(Debug, Default)]
+struct SkyController {
+    /// Time since the current stage started, in seconds.
+    stage_time: f32,
+    /// Current time of day.
+    time_of_day: f32,
+    /// Tilt of the sun path, in radians.
+    sun_tilt: f32,
+    /// Transition in progress or completed during the current stage, if any.
+    transition: Option<SkyTransition>,
+}
+
+impl SkyController {
+    /// Restart the sky for a new stage.
+    fn reset(&mut self, sun_tilt: f32) {
+        self.stage_time = 0.;
+        self.sun_tilt = sun_tilt;
+        self.transition = None;
+    }
+
+    /// Advance the time of day of the given stage.
+    fn update(&mut self, dt: f32, params: &StageBackdrop) {
+        self.stage_time += dt;
+        self.time_of_day = if let Some(transition) = &mut self.transition {
+            transition.elapsed = (transition.elapsed + dt).min(transition.duration);
+            let ratio = if transition.duration > 0. {
+                transition.elapsed / transition.duration
+            } else {
+                1.
+            };
+            // Smoothstep, so that the sun doesn't start or stop abruptly
+            let ratio = ratio * ratio * (3. - 2. * ratio);
+            transition.from + (transition.to - transition.from) * ratio
+        } else {
+            sample_keyframes(params.sky, self.stage_time)
+        };
+    }
+
+    /// Start a transition from the current time of day.
+    fn start_transition(&mut self, time_of_day: f32, duration: f32) {
+        self.transition = Some(SkyTransition {
+            from: self.time_of_day,
+            to: time_of_day.clamp(0., 1.),
+            duration: duration.max(0.),
+            elapsed: 0.,
+        });
+    }
+
+    /// Rotation of the sunlight, rising from one side of the screen and setting on the
+    /// other one.
+    fn sun_rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.sun_tilt) * Quat::from_rotation_x(-PI + PI * self.time_of_day)
+    }
+
+    /// Color and illuminance of the sunlight, warmer and dimmer near the horizon.
+    fn sun_grading(&self) -> (Color, f32) {
+        let elevation = (self.time_of_day * PI).sin().max(0.);
+        let t = elevation.sqrt();
+        let horizon = Vec4::from(HORIZON_SUN_COLOR);
+        let color = horizon + (Vec4::from(Color::WHITE) - horizon) * t;
+        let ratio = HORIZON_ILLUMINANCE_RATIO + (1. - HORIZON_ILLUMINANCE_RATIO) * t;
+        (Color::from(color), SUN_ILLUMINANCE * ratio)
+    }
+}
+
+/// Progress of the boss fight in progress, if any, driving the backdrop toward a darker
+/// and redder sky and faster clouds as the boss loses its lifebars.
+///
+/// The fight starts when the lifebars of a boss are initialized, follows their updates,
+/// and ends when they're hidden, after which the backdrop eases back to normal.
+#[derive(Debug, Default)]
+struct BossFightState {
+    /// Entity holding the lifebars of the boss fought, if any.
+    lifebar: Option<Entity>,
+    /// Number of lifebars of the boss.
+    bar_count: usize,
+    /// Life of each lifebar.
+    life_per_bar: f32,
+    /// Remaining life of the boss.
+    remain_life: f32,
+    /// Current intensity of the feedback, in `[0:1]`, easing toward [`target()`].
+    ///
+    /// [`target()`]: BossFightState::target
+    intensity: f32,
+}
+
+impl BossFightState {
+    /// Intensity of the feedback for the lifebars lost so far, from 0 with all of them
+    /// full to 1 once the last one starts depleting.
+    fn target(&self) -> f32 {
+        if self.lifebar.is_none() || self.bar_count <= 1 || self.life_per_bar <= 0. {
+            return 0.;
+        }
+        let total_life = self.life_per_bar * self.bar_count as f32;
+        let bars_lost = ((total_life - self.remain_life) / self.life_per_bar).floor();
+        (bars_lost / (self.bar_count - 1) as f32).clamp(0., 1.)
+    }
+}
+
+/// Linearly interpolate keyframes of `(time, value)` pairs sorted by time, holding the
+/// first and last values outside of their range.
+fn sample_keyframes(keys: &[(f32, f32)], time: f32) -> f32 {
+    let index = keys.partition_point(|&(key_time, _)| key_time <= time);
+    match (index.checked_sub(1).map(|i| keys[i]), keys.get(index)) {
+        (Some((t0, v0)), Some(&(t1, v1))) => v0 + (v1 - v0) * (time - t0) / (t1 - t0),
+        (Some((_, v)), None) | (None, Some(&(_, v))) => v,
+        (None, None) => 0.5,
+    }
+}
+
+/// Decorative state of the backdrop for the current stage.
+struct Backdrop {
+    /// Index of the current stage.
+    stage: usize,
+    /// Index of the stage whose parameters are applied, if any.
+    applied_stage: Option<usize>,
+    /// Generator of the current stage, seeded from the [`RunSeed`].
+    rng: StdRng,
+    /// Unit quad scaled into each quad of the parallax layers.
+    quad_mesh: Handle<Mesh>,
+    /// Current intensity of the lightning flash, in `[0:1]`.
+    flash: f32,
+}
+
+impl Default for Backdrop {
+    fn default() -> Self {
+        Backdrop {
+            stage: 0,
+            applied_stage: None,
+            rng: StdRng::seed_from_u64(0),
+            quad_mesh: Handle::default(),
+            flash: 0.,
+        }
+    }
+}
+
+fn stage_backdrop(stage: usize) -> &'static StageBackdrop {
+    &STAGE_BACKDROPS[stage.min(STAGE_BACKDROPS.len() - 1)]
+}
+
+#[derive(Component)]
+struct Sun;
+
+/// Quad of a [`ParallaxLayer`].
+#[derive(Component)]
+struct ParallaxQuad {
+    /// Index of the layer in the [`StageBackdrop`] of the current stage.
+    layer: usize,
+    /// Width of the quad, in world units.
+    width: f32,
+}
+
+fn backdrop_setup(
+    mut commands: Commands,
+    mut backdrop: ResMut<Backdrop>,
+    mut sky: ResMut<SkyController>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    launch: Res<LaunchOptions>,
+    settings: Res<Settings>,
+) {
+    *backdrop = Backdrop::default();
+    *sky = SkyController::default();
+    commands.insert_resource(BossFightState::default());
+    backdrop.stage = launch.start_stage();
+    backdrop.quad_mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::ONE,
+        flip: false,
+    }));
+
+    commands
+        .spawn_bundle(DirectionalLightBundle {
+            directional_light: DirectionalLight {
+                color: Color::WHITE,
+                illuminance: SUN_ILLUMINANCE,
+                shadows_enabled: settings.video.graphics_quality.shadow_map_size().is_some(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(Name::new("Sun"))
+        .insert(Sun)
+        .insert(InGameEntity);
+}
+
+/// Track the current stage, and re-roll the parallax layers and the sun path from the
+/// seeded RNG whenever it changes.
+fn update_backdrop_stage(
+    mut commands: Commands,
+    mut backdrop: ResMut<Backdrop>,
+    run_seed: Res<RunSeed>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    q_quads: Query<Entity, With<ParallaxQuad>>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sky: ResMut<SkyController>,
+    settings: Res<Settings>,
+) {
+    for ev in stage_clear_events.iter() {
+        if !ev.is_last {
+            backdrop.stage = ev.stage + 1;
+        }
+    }
+    if backdrop.applied_stage == Some(backdrop.stage) {
+        return;
+    }
+    let stage = backdrop.stage;
+    backdrop.applied_stage = Some(stage);
+    backdrop.rng = run_seed.rng(BACKDROP_STREAM + stage as u64);
+    debug!(target: "backdrop", "BACKDROP: stage={}", stage + 1);
+
+    for entity in q_quads.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let params = stage_backdrop(stage);
+    let (left, right) = PARALLAX_EXTENT;
+    for (index, layer) in params.layers.iter().enumerate() {
+        let material = materials.add(StandardMaterial {
+            base_color: layer.color,
+            base_color_texture: Some(asset_server.load(layer.texture)),
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        });
+        let (width, height) = layer.size;
+
+        // Position and scale of each quad of the layer
+        let quads: Vec<(Vec2, Vec2)> = match layer.layout {
+            LayerLayout::Tiled { y } => (0..layer.tile_count())
+                .map(|i| {
+                    let x = left + width * (i as f32 + 0.5);
+                    (Vec2::new(x, y), Vec2::new(width, height))
+                })
+                .collect(),
+            LayerLayout::Scattered {
+                density,
+                height: (min_y, max_y),
+                scale: (min_scale, max_scale),
+            } => {
+                let density = density * settings.video.graphics_quality.cloud_density();
+                let count = (density * (right - left)).ceil() as u32;
+                let rng = &mut backdrop.rng;
+                (0..count)
+                    .map(|_| {
+                        let x = rng.gen_range(left..right);
+                        let y = rng.gen_range(min_y..=max_y);
+                        let s = rng.gen_range(min_scale..=max_scale);
+                        let sx = 0.8 + rng.gen::<f32>() * 0.4;
+                        let sy = 0.8 + rng.gen::<f32>() * 0.4;
+                        (Vec2::new(x, y), Vec2::new(width * sx * s, height * sy * s))
+                    })
+                    .collect()
+            }
+        };
+
+        for (position, size) in quads {
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: backdrop.quad_mesh.clone(),
+                    material: material.clone(),
+                    transform: Transform::from_xyz(position.x, position.y, layer.depth)
+                        .with_scale(size.extend(1.)),
+                    ..Default::default()
+                })
+                .insert(Name::new(format!("parallax{}", index)))
+                .insert(ParallaxQuad {
+                    layer: index,
+                    width: size.x,
+                })
+                .insert(InGameEntity);
+        }
+    }
+
+    let sun_tilt = backdrop
+        .rng
+        .gen_range(-params.sun_jitter..=params.sun_jitter);
+    sky.reset(sun_tilt);
+}
+
+/// Follow the lifebars of the boss fought, and ease the intensity of the boss fight
+/// feedback toward the lifebars lost.
+fn update_boss_fight(
+    mut fight: ResMut<BossFightState>,
+    mut init_events: EventReader<InitLifebarsEvent>,
+    mut update_events: EventReader<UpdateLifebarsEvent>,
+    mut hide_events: EventReader<HideLifebarsEvent>,
+    game_time: Res<GameTime>,
+) {
+    for ev in init_events.iter() {
+        debug!(target: "backdrop", "BACKDROP: boss fight start, {} lifebars", ev.colors.len());
+        fight.lifebar = Some(ev.entity);
+        fight.bar_count = ev.colors.len();
+        fight.life_per_bar = ev.life_per_bar;
+        fight.remain_life = ev.life_per_bar * ev.colors.len() as f32;
+    }
+    for ev in update_events.iter() {
+        if fight.lifebar == Some(ev.entity) {
+            fight.remain_life = ev.remain_life;
+        }
+    }
+    for ev in hide_events.iter() {
+        if fight.lifebar == Some(ev.entity) {
+            debug!(target: "backdrop", "BACKDROP: boss fight end");
+            fight.lifebar = None;
+        }
+    }
+
+    let step = BOSS_INTENSITY_RATE * game_time.delta_seconds();
+    let target = fight.target();
+    fight.intensity += (target - fight.intensity).clamp(-step, step);
+}
+
+/// Scroll the quads of the parallax layers, wrapping them to the right once past the
+/// left edge. Scattered quads get a new height when wrapping, so that the pattern
+/// doesn't visibly repeat. Layers speed up as a boss fight intensifies.
+fn scroll_parallax_layers(
+    mut backdrop: ResMut<Backdrop>,
+    mut query: Query<(&ParallaxQuad, &mut Transform)>,
+    fight: Res<BossFightState>,
+    game_time: Res<GameTime>,
+) {
+    let dt = game_time.delta_seconds() * (1. + BOSS_SCROLL_BOOST * fight.intensity);
+    let params = stage_backdrop(backdrop.stage);
+    let (left, right) = PARALLAX_EXTENT;
+    for (quad, mut transform) in query.iter_mut() {
+        // Quads of the previous stage are despawned but still around this frame
+        let layer = if let Some(layer) = params.layers.get(quad.layer) {
+            layer
+        } else {
+            continue;
+        };
+        transform.translation.x -= layer.speed * dt;
+        if transform.translation.x + quad.width / 2. >= left {
+            continue;
+        }
+        match layer.layout {
+            LayerLayout::Tiled { .. } => {
+                transform.translation.x += layer.size.0 * layer.tile_count() as f32;
+            }
+            LayerLayout::Scattered {
+                height: (min_y, max_y),
+                ..
+            } => {
+                transform.translation.x = right + quad.width / 2.;
+                transform.translation.y = backdrop.rng.gen_range(min_y..=max_y);
+            }
+        }
+    }
+}
+
+/// Move the sun along its path, flash it when lightning strikes, and update the sky
+/// from its direction. A boss fight darkens the sky and reddens the sunlight as it
+/// intensifies.
+fn update_sun(
+    mut backdrop: ResMut<Backdrop>,
+    mut sky: ResMut<SkyController>,
+    fight: Res<BossFightState>,
+    mut sky_mat: ResMut<AtmosphereMat>,
+    mut query: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    mut transition_events: EventReader<SkyTransitionEvent>,
+    mut lightning_events: EventReader<LightningEvent>,
+    game_time: Res<GameTime>,
+    settings: Res<Settings>,
+) {
+    let dt = game_time.delta_seconds();
+
+    for ev in transition_events.iter() {
+        debug!(
+            target: "backdrop",
+            "BACKDROP: sky transition to {} over {}s", ev.time_of_day, ev.duration
+        );
+        sky.start_transition(ev.time_of_day, ev.duration);
+    }
+    sky.update(dt, stage_backdrop(backdrop.stage));
+
+    backdrop.flash = (backdrop.flash - FLASH_DECAY * dt).max(0.);
+    if lightning_events.iter().count() > 0 && !settings.accessibility.reduce_flashing {
+        backdrop.flash = 1.;
+    }
+
+    if let Some((mut light_trans, mut directional)) = query.single_mut().into() {
+        light_trans.rotation = sky.sun_rotation();
+
+        // Update sky from sun direction
+        let pos = light_trans.rotation.mul_vec3(Vec3::Z);
+        sky_mat.sun_position = pos;
+        let sun_intensity =
+            AtmosphereMat::default().sun_intensity * (1. - BOSS_SKY_DARKENING * fight.intensity);
+        if sky_mat.sun_intensity != sun_intensity {
+            sky_mat.sun_intensity = sun_intensity;
+        }
+
+        let (color, illuminance) = sky.sun_grading();
+        let redness = BOSS_SUN_REDNESS * fight.intensity;
+        let color = Vec4::from(color) + (Vec4::from(BOSS_SUN_COLOR) - Vec4::from(color)) * redness;
+        let color = Color::from(color);
+        let illuminance = illuminance * (1. + FLASH_BOOST * backdrop.flash);
+        if directional.illuminance != illuminance {
+            directional.illuminance = illuminance;
+        }
+        if directional.color != color {
+            directional.color = color;
+        }
+    }
+}
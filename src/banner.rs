@@ -0,0 +1,538 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use bevy_tweening::{lens::*, *};
+use std::{collections::VecDeque, time::Duration};
+
+use crate::{
+    bonus::{PopupKind, ScoreAward},
+    game::{InGameEntity, Lives, PlayerController, StageClearEvent, StageStartEvent},
+    sfx::{Sfx, SfxEvent},
+    stats::{RunStats, StatCounters},
+    timestep::{FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+/// Banners announcing the start and the end of each stage, with the stage statistics
+/// and clear bonus.
+pub struct BannerPlugin;
+
+impl Plugin for BannerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StageBanners>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(banner_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_banner_visuals)
+                    .with_system(update_bonus_tally.after(update_banner_visuals)),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame).with_system(update_stage_banners),
+            );
+    }
+}
+
+/// Time a stage start banner stays on screen, in seconds.
+const START_DURATION: f32 = 2.;
+
+/// Time a stage clear banner stays on screen, in seconds, long enough to tally the
+/// bonus.
+const CLEAR_DURATION: f32 = 4.5;
+
+/// Duration of the appear and disappear animations of a banner, in seconds.
+const SLIDE_DURATION: f32 = 0.35;
+
+/// Delay between the start of two lines of the bonus tally, in seconds. The first line
+/// starts once the banner appeared.
+const TALLY_LINE_INTERVAL: f32 = 0.4;
+
+/// Duration of the fade in of a line of the bonus tally, in seconds.
+const TALLY_FADE_DURATION: f32 = 0.15;
+
+/// Time a line of the bonus tally counts up from zero, in seconds.
+const TALLY_COUNT_DURATION: f32 = 0.3;
+
+/// Interval between two ticks of the bonus tally while counting up, in seconds.
+const TALLY_TICK_INTERVAL: f32 = 0.05;
+
+/// Time the player cannot shoot nor bomb after a banner appears, in seconds. The ship
+/// can still move, to dodge the bullets left on screen.
+const INPUT_LOCK_DURATION: f32 = 1.;
+
+/// Score bonus for clearing a stage.
+const CLEAR_BONUS: u32 = 10_000;
+
+/// Score bonus per extra life remaining when clearing a stage.
+const LIFE_BONUS: u32 = 5_000;
+
+/// Score bonus per bomb in stock when clearing a stage.
+const BOMB_BONUS: u32 = 2_000;
+
+/// Score bonus per percent of accuracy over the stage.
+const ACCURACY_BONUS: u32 = 100;
+
+/// Score bonus per enemy bullet grazed during the stage.
+const GRAZE_BONUS: u32 = 10;
+
+/// Score bonus per kill of the longest kill chain of the stage.
+const CHAIN_BONUS: u32 = 500;
+
+const BANNER_TEXT_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+const BANNER_BAND_COLOR: Color = Color::rgba(0.05, 0.05, 0.1, 0.6);
+
+/// Stage clear bonus, detailed for display.
+#[derive(Debug, Clone, Copy)]
+struct ClearTally {
+    lives: u32,
+    bombs: u32,
+    /// Statistics of the stage.
+    stats: StatCounters,
+    /// Time spent in the stage, in seconds.
+    time: f32,
+}
+
+impl ClearTally {
+    /// Accuracy over the stage, in whole percents.
+    fn accuracy(&self) -> u32 {
+        (self.stats.accuracy() * 100.).round() as u32
+    }
+
+    fn bonus(&self) -> u32 {
+        CLEAR_BONUS
+            + self.lives * LIFE_BONUS
+            + self.bombs * BOMB_BONUS
+            + self.accuracy() * ACCURACY_BONUS
+            + self.stats.grazes * GRAZE_BONUS
+            + self.stats.max_chain * CHAIN_BONUS
+    }
+
+    /// Label and value of each line of the tally, ending with the total bonus.
+    fn lines(&self) -> Vec<(String, u32)> {
+        vec![
+            ("CLEAR".to_string(), CLEAR_BONUS),
+            (
+                format!("LIVES {} x {}", self.lives, LIFE_BONUS),
+                self.lives * LIFE_BONUS,
+            ),
+            (
+                format!("BOMBS {} x {}", self.bombs, BOMB_BONUS),
+                self.bombs * BOMB_BONUS,
+            ),
+            (
+                format!("ACCURACY {} x {}", self.accuracy(), ACCURACY_BONUS),
+                self.accuracy() * ACCURACY_BONUS,
+            ),
+            (
+                format!("GRAZE {} x {}", self.stats.grazes, GRAZE_BONUS),
+                self.stats.grazes * GRAZE_BONUS,
+            ),
+            (
+                format!("CHAIN {} x {}", self.stats.max_chain, CHAIN_BONUS),
+                self.stats.max_chain * CHAIN_BONUS,
+            ),
+            ("BONUS".to_string(), self.bonus()),
+        ]
+    }
+}
+
+/// Time since the banner appeared at which a line of the bonus tally starts, in
+/// seconds.
+fn tally_line_start(index: usize) -> f32 {
+    SLIDE_DURATION + index as f32 * TALLY_LINE_INTERVAL
+}
+
+/// Text of a line of the bonus tally, with the value counted up so far.
+fn tally_line_text(label: &str, value: u32) -> String {
+    format!("\n{:<24}{:>8}", label, value)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Banner {
+    Start {
+        stage: usize,
+    },
+    Clear {
+        stage: usize,
+        is_last: bool,
+        tally: ClearTally,
+    },
+}
+
+impl Banner {
+    fn duration(&self) -> f32 {
+        match self {
+            Banner::Start { .. } => START_DURATION,
+            Banner::Clear { .. } => CLEAR_DURATION,
+        }
+    }
+
+    /// Height of the band behind the text, in world units, and top of the text, in
+    /// percent of the screen height. Clear banners have a line of statistics and the
+    /// lines of the bonus tally.
+    fn layout(&self) -> (f32, f32) {
+        match self {
+            Banner::Start { .. } => (1.2, 44.),
+            Banner::Clear { .. } => (4.6, 29.),
+        }
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Banner::Start { stage } => format!("STAGE {} — START", stage + 1),
+            Banner::Clear { is_last: true, .. } => "ALL STAGES CLEAR".to_string(),
+            Banner::Clear { stage, .. } => format!("STAGE {} CLEAR", stage + 1),
+        }
+    }
+
+    fn subtitle(&self) -> String {
+        match self {
+            Banner::Start { .. } => String::new(),
+            Banner::Clear { tally, .. } => {
+                let time = tally.time as u32;
+                format!(
+                    "KILLS {}  ACCURACY {}%  GRAZE {}  MAX CHAIN {}  TIME {}:{:02}\n",
+                    tally.stats.kills,
+                    tally.accuracy(),
+                    tally.stats.grazes,
+                    tally.stats.max_chain,
+                    time / 60,
+                    time % 60,
+                )
+            }
+        }
+    }
+}
+
+/// Stage banners to display, one at a time.
+///
+/// Banners are queued and timed in the fixed timestep, like the gameplay, so that the
+/// input lock they apply is deterministic and replays stay in sync. Their visuals are
+/// only a reflection of this state.
+#[derive(Default)]
+pub struct StageBanners {
+    queue: VecDeque<Banner>,
+    /// Banner on screen, if any, and time since it appeared, in seconds.
+    current: Option<(Banner, f32)>,
+    /// Number of banners shown so far, to detect a new banner. Not reset between games.
+    serial: u32,
+}
+
+impl StageBanners {
+    /// Is the player input locked by a banner which just appeared?
+    pub fn is_input_locked(&self) -> bool {
+        self.current
+            .is_some_and(|(_, time)| time < INPUT_LOCK_DURATION)
+    }
+}
+
+/// Marker for the entities of the banner on screen.
+#[derive(Component)]
+struct BannerVisual;
+
+/// Text of a stage clear banner, whose sections after the title and subtitle are the
+/// lines of the bonus tally, counted up one after the other.
+#[derive(Component)]
+struct BonusTally {
+    lines: Vec<(String, u32)>,
+    /// Index of the last tick played, counted from the start of the first line.
+    last_tick: Option<u32>,
+}
+
+fn banner_setup(mut banners: ResMut<StageBanners>) {
+    // Keep the serial, which the visuals compare against
+    banners.queue.clear();
+    banners.current = None;
+}
+
+/// Queue a banner for each stage started or cleared, and award the stage clear bonus.
+/// Runs after the enemies, which start and clear the stages.
+pub fn update_stage_banners(
+    mut banners: ResMut<StageBanners>,
+    mut stage_start_events: EventReader<StageStartEvent>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    mut score_award: ScoreAward,
+    q_player: Query<(&PlayerController, &Transform)>,
+    lives: Res<Lives>,
+    stats: Res<RunStats>,
+) {
+    // A boss kill both clears a stage and starts the next one, so show clears first
+    for ev in stage_clear_events.iter() {
+        let tally = ClearTally {
+            lives: lives.remaining,
+            bombs: q_player
+                .get_single()
+                .map_or(0, |(controller, _)| controller.bombs()),
+            stats: stats
+                .cleared
+                .last()
+                .map_or_else(StatCounters::default, |s| s.counters),
+            time: stats.cleared.last().map_or(0., |s| s.time),
+        };
+        let position = q_player
+            .get_single()
+            .map_or(Vec3::ZERO, |(_, transform)| transform.translation);
+        score_award.award(tally.bonus(), position, PopupKind::Bonus);
+        banners.queue.push_back(Banner::Clear {
+            stage: ev.stage,
+            is_last: ev.is_last,
+            tally,
+        });
+    }
+    for ev in stage_start_events.iter() {
+        banners.queue.push_back(Banner::Start { stage: ev.stage });
+    }
+
+    if let Some((banner, time)) = &mut banners.current {
+        *time += FIXED_DELTA_SECONDS;
+        if *time >= banner.duration() {
+            banners.current = None;
+        }
+    }
+    if banners.current.is_none() {
+        if let Some(banner) = banners.queue.pop_front() {
+            debug!(target: "banner", "Banner: {:?}", banner);
+            banners.current = Some((banner, 0.));
+            banners.serial += 1;
+        }
+    }
+}
+
+/// Spawn the visuals of a banner when it appears, and despawn them when it's gone.
+fn update_banner_visuals(
+    mut commands: Commands,
+    banners: Res<StageBanners>,
+    mut shown_serial: Local<Option<u32>>,
+    q_visuals: Query<Entity, With<BannerVisual>>,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let serial = banners.current.map(|_| banners.serial);
+    if serial == *shown_serial {
+        return;
+    }
+    *shown_serial = serial;
+
+    for entity in q_visuals.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    let banner = if let Some((banner, _)) = banners.current {
+        banner
+    } else {
+        return;
+    };
+
+    let hold = Duration::from_secs_f32(banner.duration() - SLIDE_DURATION * 2.);
+    let (band_height, text_top) = banner.layout();
+    let slide = Duration::from_secs_f32(SLIDE_DURATION);
+
+    // Translucent band across the playfield, unfolding vertically
+    let folded = Vec3::new(1., 0., 1.);
+    let band_tween = Tween::new(
+        EaseFunction::QuadraticOut,
+        TweeningType::Once,
+        slide,
+        TransformScaleLens {
+            start: folded,
+            end: Vec3::ONE,
+        },
+    )
+    .then(Delay::new(hold))
+    .then(Tween::new(
+        EaseFunction::QuadraticIn,
+        TweeningType::Once,
+        slide,
+        TransformScaleLens {
+            start: Vec3::ONE,
+            end: folded,
+        },
+    ));
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Quad {
+                size: Vec2::new(20., band_height),
+                flip: false,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: BANNER_BAND_COLOR,
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+            transform: Transform::from_xyz(0., 0., 1.).with_scale(folded),
+            ..Default::default()
+        })
+        .insert(Name::new("StageBanner"))
+        .insert(InGameEntity)
+        .insert(NotShadowCaster)
+        .insert(NotShadowReceiver)
+        .insert(Animator::new(band_tween))
+        .insert(BannerVisual);
+
+    // Title and subtitle, fading in and out with the band
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    let text_tween = |section| {
+        let transparent = Color::NONE;
+        Tween::new(
+            EaseFunction::QuadraticOut,
+            TweeningType::Once,
+            slide,
+            TextColorLens {
+                start: transparent,
+                end: BANNER_TEXT_COLOR,
+                section,
+            },
+        )
+        .then(Delay::new(hold))
+        .then(Tween::new(
+            EaseFunction::QuadraticIn,
+            TweeningType::Once,
+            slide,
+            TextColorLens {
+                start: BANNER_TEXT_COLOR,
+                end: transparent,
+                section,
+            },
+        ))
+    };
+    // Lines of the bonus tally, each fading in when it starts counting up
+    let tally_lines = match banner {
+        Banner::Clear { tally, .. } => tally.lines(),
+        Banner::Start { .. } => vec![],
+    };
+    let tally_tween = |index: usize| {
+        let start = tally_line_start(index);
+        let section = index + 2;
+        let transparent = Color::NONE;
+        Delay::new(Duration::from_secs_f32(start))
+            .then(Tween::new(
+                EaseFunction::QuadraticOut,
+                TweeningType::Once,
+                Duration::from_secs_f32(TALLY_FADE_DURATION),
+                TextColorLens {
+                    start: transparent,
+                    end: BANNER_TEXT_COLOR,
+                    section,
+                },
+            ))
+            .then(Delay::new(Duration::from_secs_f32(
+                banner.duration() - SLIDE_DURATION - start - TALLY_FADE_DURATION,
+            )))
+            .then(Tween::new(
+                EaseFunction::QuadraticIn,
+                TweeningType::Once,
+                slide,
+                TextColorLens {
+                    start: BANNER_TEXT_COLOR,
+                    end: transparent,
+                    section,
+                },
+            ))
+    };
+    let mut sections = vec![
+        TextSection {
+            value: banner.title(),
+            style: TextStyle {
+                font: font.clone(),
+                font_size: 56.,
+                color: Color::NONE,
+            },
+        },
+        TextSection {
+            value: format!("\n{}", banner.subtitle()),
+            style: TextStyle {
+                font: font.clone(),
+                font_size: 24.,
+                color: Color::NONE,
+            },
+        },
+    ];
+    let mut tracks = vec![text_tween(0), text_tween(1)];
+    for (index, (label, _)) in tally_lines.iter().enumerate() {
+        sections.push(TextSection {
+            value: tally_line_text(label, 0),
+            style: TextStyle {
+                font: font.clone(),
+                font_size: 24.,
+                color: Color::NONE,
+            },
+        });
+        tracks.push(tally_tween(index));
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(text_top),
+                    left: Val::Px(0.),
+                    right: Val::Px(0.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("StageBannerText"))
+        .insert(InGameEntity)
+        .insert(BannerVisual)
+        .with_children(|parent| {
+            let mut text = parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections,
+                    alignment: TextAlignment {
+                        horizontal: HorizontalAlign::Center,
+                        ..Default::default()
+                    },
+                },
+                ..Default::default()
+            });
+            text.insert(Animator::new(Tracks::new(tracks)));
+            if !tally_lines.is_empty() {
+                text.insert(BonusTally {
+                    lines: tally_lines,
+                    last_tick: None,
+                });
+            }
+        });
+}
+
+/// Count up the lines of the bonus tally of the stage clear banner one after the
+/// other, ticking while counting.
+fn update_bonus_tally(
+    banners: Res<StageBanners>,
+    mut query: Query<(&mut Text, &mut BonusTally)>,
+    mut sfx_events: EventWriter<SfxEvent>,
+) {
+    let time = if let Some((Banner::Clear { .. }, time)) = banners.current {
+        time
+    } else {
+        return;
+    };
+    for (mut text, mut tally) in query.iter_mut() {
+        let mut is_counting = false;
+        for (index, (label, value)) in tally.lines.iter().enumerate() {
+            let progress = ((time - tally_line_start(index)) / TALLY_COUNT_DURATION).clamp(0., 1.);
+            is_counting |= progress > 0. && progress < 1.;
+            let line = tally_line_text(label, (*value as f32 * progress).round() as u32);
+            let section = &mut text.sections[index + 2];
+            if section.value != line {
+                section.value = line;
+            }
+        }
+        let tick = ((time - tally_line_start(0)).max(0.) / TALLY_TICK_INTERVAL) as u32;
+        if is_counting && tally.last_tick != Some(tick) {
+            tally.last_tick = Some(tick);
+            sfx_events.send(SfxEvent::new(Sfx::Tally));
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::AppState;
+
+/// Benchmark mode, enabled with `--bench`. Measures the frame time of a game for a
+/// fixed duration, then logs some statistics and exits.
+pub struct BenchPlugin;
+
+impl Plugin for BenchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bench>().add_system_set_to_stage(
+            CoreStage::Update,
+            SystemSet::on_update(AppState::InGame).with_system(update_bench),
+        );
+    }
+}
+
+/// Duration of the benchmark, in seconds of real time.
+const BENCH_DURATION: f64 = 60.;
+
+/// Duration ignored at the start of the benchmark while assets finish loading and
+/// shaders compile, in seconds.
+const BENCH_WARMUP: f64 = 2.;
+
+#[derive(Default)]
+struct Bench {
+    /// Time the game started, in seconds since startup.
+    start_time: Option<f64>,
+    /// Frame times measured after the warmup, in seconds.
+    frame_times: Vec<f32>,
+}
+
+fn update_bench(mut bench: ResMut<Bench>, time: Res<Time>, mut exit: EventWriter<AppExit>) {
+    let now = time.seconds_since_startup();
+    let start_time = *bench.start_time.get_or_insert(now);
+    let elapsed = now - start_time;
+    if elapsed < BENCH_WARMUP {
+        return;
+    }
+    if elapsed < BENCH_WARMUP + BENCH_DURATION {
+        bench.frame_times.push(time.delta_seconds());
+        return;
+    }
+
+    let mut frame_times = std::mem::take(&mut bench.frame_times);
+    if frame_times.is_empty() {
+        exit.send(AppExit);
+        return;
+    }
+    frame_times.sort_by(f32::total_cmp);
+    let count = frame_times.len();
+    let average = frame_times.iter().sum::<f32>() / count as f32;
+    let percentile = |p: f32| frame_times[((count - 1) as f32 * p) as usize];
+    info!(
+        target: "bench",
+        "BENCH: frames={} avg={:.2}ms p50={:.2}ms p99={:.2}ms max={:.2}ms",
+        count,
+        average * 1000.,
+        percentile(0.5) * 1000.,
+        percentile(0.99) * 1000.,
+        frame_times[count - 1] * 1000.
+    );
+    exit.send(AppExit);
+}
@@ -0,0 +1,254 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_tweening::{lens::*, *};
+use std::time::Duration;
+
+use crate::{
+    game::{InGameEntity, MainCamera, PlayerController, ScoreEvent},
+    letterbox::PlayfieldNode,
+    rank::GrazeEvent,
+    timestep::FixedUpdateStage,
+    AppState,
+};
+
+/// Score popups rising from where the score of a kill or a bonus is awarded, and the
+/// bonus awarded for each milestone of grazes.
+pub struct BonusPlugin;
+
+impl Plugin for BonusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ScorePopupEvent>()
+            .init_resource::<GrazeMilestones>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(bonus_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_score_popups)
+                    .with_system(despawn_score_popups),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame).with_system(award_graze_milestones),
+            );
+    }
+}
+
+/// Number of grazes between two graze milestone bonuses.
+const GRAZE_MILESTONE: u32 = 50;
+
+/// Score bonus for each graze milestone.
+const GRAZE_MILESTONE_BONUS: u32 = 1_000;
+
+/// Time a popup stays on screen, in seconds.
+const POPUP_DURATION: f32 = 0.9;
+
+/// Distance a popup rises while on screen, in percent of the playfield height.
+const POPUP_RISE: f32 = 5.;
+
+/// Width of the popup node, centered on the popup position, in percent of the
+/// playfield width.
+const POPUP_WIDTH: f32 = 20.;
+
+/// Kind of score popup, which sets its look.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupKind {
+    /// Score of an enemy kill, multiplied by the combo.
+    Kill,
+    /// Graze milestone bonus.
+    Graze,
+    /// Stage clear bonus.
+    Bonus,
+}
+
+impl PopupKind {
+    fn font_size(&self) -> f32 {
+        match self {
+            PopupKind::Kill => 16.,
+            PopupKind::Graze => 20.,
+            PopupKind::Bonus => 32.,
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            PopupKind::Kill => Color::rgb(0.95, 0.95, 0.95),
+            PopupKind::Graze => Color::rgb(0.4, 0.9, 1.),
+            PopupKind::Bonus => Color::rgb(1., 0.85, 0.3),
+        }
+    }
+}
+
+/// Event to show a score popup.
+#[derive(Debug, Clone, Copy)]
+pub struct ScorePopupEvent {
+    /// World position the popup rises from.
+    pub position: Vec3,
+    pub score: u32,
+    pub kind: PopupKind,
+}
+
+/// Award some score to the player, with a popup at the position it was earned.
+#[derive(SystemParam)]
+pub struct ScoreAward<'w, 's> {
+    score_events: EventWriter<'w, 's, ScoreEvent>,
+    popup_events: EventWriter<'w, 's, ScorePopupEvent>,
+}
+
+impl<'w, 's> ScoreAward<'w, 's> {
+    pub fn award(&mut self, score: u32, position: Vec3, kind: PopupKind) {
+        self.score_events.send(ScoreEvent(score));
+        if score > 0 {
+            self.popup_events.send(ScorePopupEvent {
+                position,
+                score,
+                kind,
+            });
+        }
+    }
+}
+
+/// Grazes counted toward the next graze milestone.
+#[derive(Default)]
+struct GrazeMilestones {
+    grazes: u32,
+}
+
+/// Popup on screen, despawned once it expires.
+#[derive(Component)]
+struct ScorePopup {
+    /// Remaining time on screen, in seconds.
+    remaining: f32,
+}
+
+/// Root of the popups, laid out over the playfield.
+#[derive(Component)]
+struct ScorePopupLayer;
+
+fn bonus_setup(mut commands: Commands, mut milestones: ResMut<GrazeMilestones>) {
+    *milestones = GrazeMilestones::default();
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("ScorePopupLayer"))
+        .insert(InGameEntity)
+        .insert(PlayfieldNode)
+        .insert(ScorePopupLayer);
+}
+
+/// Award a bonus each time the player reaches a number of grazes multiple of
+/// [`GRAZE_MILESTONE`].
+fn award_graze_milestones(
+    mut milestones: ResMut<GrazeMilestones>,
+    mut graze_events: EventReader<GrazeEvent>,
+    mut score_award: ScoreAward,
+    q_player: Query<&Transform, With<PlayerController>>,
+) {
+    milestones.grazes += graze_events.iter().count() as u32;
+    if milestones.grazes < GRAZE_MILESTONE {
+        return;
+    }
+    milestones.grazes -= GRAZE_MILESTONE;
+    let position = q_player
+        .get_single()
+        .map_or(Vec3::ZERO, |transform| transform.translation);
+    debug!(target: "bonus", "Graze milestone bonus: {}", GRAZE_MILESTONE_BONUS);
+    score_award.award(GRAZE_MILESTONE_BONUS, position, PopupKind::Graze);
+}
+
+fn spawn_score_popups(
+    mut commands: Commands,
+    mut popup_events: EventReader<ScorePopupEvent>,
+    q_layer: Query<Entity, With<ScorePopupLayer>>,
+    q_camera: Query<&MainCamera>,
+    asset_server: Res<AssetServer>,
+) {
+    let (layer, main_camera) = match (q_layer.get_single(), q_camera.get_single()) {
+        (Ok(layer), Ok(main_camera)) => (layer, main_camera),
+        _ => return,
+    };
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    for ev in popup_events.iter() {
+        let position = main_camera.playfield_percent(ev.position);
+        let start = Rect {
+            left: Val::Percent(position.x - POPUP_WIDTH / 2.),
+            top: Val::Percent(position.y),
+            ..Default::default()
+        };
+        let end = Rect {
+            top: Val::Percent(position.y - POPUP_RISE),
+            ..start
+        };
+        let duration = Duration::from_secs_f32(POPUP_DURATION);
+        let color = ev.kind.color();
+        commands.entity(layer).with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: start,
+                        size: Size::new(Val::Percent(POPUP_WIDTH), Val::Auto),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..Default::default()
+                })
+                .insert(Animator::new(Tween::new(
+                    EaseFunction::QuadraticOut,
+                    TweeningType::Once,
+                    duration,
+                    UiPositionLens { start, end },
+                )))
+                .insert(ScorePopup {
+                    remaining: POPUP_DURATION,
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                ev.score.to_string(),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: ev.kind.font_size(),
+                                    color,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(Animator::new(Tween::new(
+                            EaseFunction::QuadraticIn,
+                            TweeningType::Once,
+                            duration,
+                            TextColorLens {
+                                start: color,
+                                end: Color::NONE,
+                                section: 0,
+                            },
+                        )));
+                });
+        });
+    }
+}
+
+fn despawn_score_popups(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut ScorePopup)>,
+    time: Res<Time>,
+) {
+    for (entity, mut popup) in query.iter_mut() {
+        popup.remaining -= time.delta_seconds();
+        if popup.remaining <= 0. {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
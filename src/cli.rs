@@ -0,0 +1,343 @@
+use bevy::{
+    log::LogSettings,
+    prelude::*,
+    window::{PresentMode, WindowMode},
+};
+
+use crate::settings::{DisplayMode, LogLevel, PresentModeSetting, Settings};
+
+/// Usage printed by `--help` and on invalid arguments.
+const USAGE: &str = "\
+Usage: super-kaizen-overloaded [OPTIONS]
+
+Options:
+  --stage <N>            Skip the menu and start a game at stage N (from 1)
+  --seed <SEED>          Seed of the random number generators of all runs
+  --windowed             Start in a window, even if fullscreen in the settings
+  --fullscreen           Start in borderless fullscreen
+  --width <W>            Width of the window, in logical pixels
+  --height <H>           Height of the window, in logical pixels
+  --vsync                Force vertical sync
+  --no-vsync             Disable vertical sync, presenting frames immediately
+  --msaa <N>             Number of MSAA samples, 1 (disabled) or 4
+  --bench                Skip the menu and run an invincible game for a fixed
+                         duration, then log frame time statistics and exit
+  --replay <FILE>        Skip the menu and start a game with the given replay
+                         as a ghost, using its seed. FILE is a path, or a file
+                         name of the replay directory
+  --max-delta <SECS>     Maximum game time a single frame can advance, in seconds
+                         (default: 0.05). Longer frames slow the game down
+  --headless             Run without any window nor rendering, e.g. with --bench
+  --simulate <N> <SECS>  Run stage N headless and muted for SECS seconds with an
+                         invincible scripted player, or one following --replay,
+                         then log bullet and entity statistics and exit
+  --pattern <ENEMY>      Skip the menu and preview the fire patterns of the given
+                         enemy in an empty arena, with an invincible player. Debug
+                         builds show a window to tweak and restart the patterns
+  --log-level <LEVEL>    Minimum log level: error, warn, info, debug or trace
+  --log-filter <FILTER>  Per-target log levels, like 'timeline=trace'
+  --help                 Print this help and exit";
+
+/// Launch options given on the command line, parsed before the app is built, so that
+/// testers and CI can launch directly into specific content.
+#[derive(Debug, Clone, Default)]
+pub struct LaunchOptions {
+    /// Stage to start the game at, starting from 1.
+    pub stage: Option<usize>,
+    /// Seed forced for all runs. See [`RunSeed::fixed`](crate::game::RunSeed::fixed).
+    pub seed: Option<u64>,
+    pub windowed: bool,
+    pub fullscreen: bool,
+    /// Override of the window size of the [`VideoSettings`](crate::settings::VideoSettings).
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Force vertical sync on or off, overriding the present mode of the settings.
+    pub vsync: Option<bool>,
+    /// Override of the MSAA sample count of the settings.
+    pub msaa: Option<u32>,
+    pub bench: bool,
+    /// Replay to play back as a ghost.
+    pub replay: Option<String>,
+    /// Override of the [`GameTime::max_delta_seconds`](crate::game::GameTime::max_delta_seconds).
+    pub max_delta: Option<f32>,
+    pub headless: bool,
+    /// Duration of the headless simulation of the [`stage`](Self::stage), in seconds of
+    /// game time. See [`SimulatePlugin`](crate::simulate::SimulatePlugin).
+    pub simulate: Option<f32>,
+    /// Enemy whose patterns are previewed, instead of playing the timeline. See
+    /// [`PatternPreview`](crate::enemy::PatternPreview).
+    pub pattern: Option<String>,
+    /// Override of [`LoggingSettings::level`](crate::settings::LoggingSettings::level).
+    pub log_level: Option<LogLevel>,
+    /// Override of [`LoggingSettings::filter`](crate::settings::LoggingSettings::filter).
+    pub log_filter: Option<String>,
+}
+
+impl LaunchOptions {
+    /// Parse the command line arguments of the process, printing the usage and exiting
+    /// if they are invalid or if `--help` is given.
+    pub fn from_env() -> LaunchOptions {
+        match LaunchOptions::parse(std::env::args().skip(1)) {
+            Ok(Some(options)) => options,
+            Ok(None) => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            Err(err) => {
+                eprintln!("error: {}\n\n{}", err, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    /// Parse the given arguments, excluding the program name. Return `None` if the
+    /// help was requested.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<LaunchOptions>, String> {
+        let mut options = LaunchOptions::default();
+        while let Some(arg) = args.next() {
+            // Accept both "--flag value" and "--flag=value"
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", flag))
+            };
+            match flag.as_str() {
+                "--stage" => {
+                    let stage = value()?;
+                    match stage.parse::<usize>() {
+                        Ok(stage) if stage >= 1 => options.stage = Some(stage),
+                        _ => return Err(format!("invalid stage '{}'", stage)),
+                    }
+                }
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(
+                        seed.parse()
+                            .map_err(|_| format!("invalid seed '{}'", seed))?,
+                    );
+                }
+                "--replay" => options.replay = Some(value()?),
+                "--simulate" => {
+                    let stage = value()?;
+                    match stage.parse::<usize>() {
+                        Ok(stage) if stage >= 1 => options.stage = Some(stage),
+                        _ => return Err(format!("invalid stage '{}'", stage)),
+                    }
+                    let duration = args
+                        .next()
+                        .ok_or_else(|| "missing duration for --simulate".to_string())?;
+                    match duration.parse::<f32>() {
+                        Ok(duration) if duration > 0. => options.simulate = Some(duration),
+                        _ => return Err(format!("invalid simulation duration '{}'", duration)),
+                    }
+                    options.headless = true;
+                }
+                "--pattern" => options.pattern = Some(value()?),
+                "--max-delta" => {
+                    let max_delta = value()?;
+                    match max_delta.parse::<f32>() {
+                        Ok(max_delta) if max_delta > 0. => options.max_delta = Some(max_delta),
+                        _ => return Err(format!("invalid maximum delta time '{}'", max_delta)),
+                    }
+                }
+                "--log-level" => {
+                    let level = value()?;
+                    options.log_level = Some(
+                        LogLevel::parse(&level)
+                            .ok_or_else(|| format!("invalid log level '{}'", level))?,
+                    );
+                }
+                "--log-filter" => options.log_filter = Some(value()?),
+                "--width" | "--height" => {
+                    let size = value()?;
+                    let size = match size.parse::<u32>() {
+                        Ok(size) if size > 0 => size,
+                        _ => return Err(format!("invalid window size '{}'", size)),
+                    };
+                    if flag == "--width" {
+                        options.width = Some(size);
+                    } else {
+                        options.height = Some(size);
+                    }
+                }
+                "--msaa" => {
+                    let samples = value()?;
+                    match samples.parse::<u32>() {
+                        Ok(samples) if samples == 1 || samples == 4 => options.msaa = Some(samples),
+                        _ => return Err(format!("invalid MSAA sample count '{}'", samples)),
+                    }
+                }
+                "--windowed" => options.windowed = true,
+                "--fullscreen" => options.fullscreen = true,
+                "--vsync" => options.vsync = Some(true),
+                "--no-vsync" => options.vsync = Some(false),
+                "--bench" => options.bench = true,
+                "--headless" => options.headless = true,
+                "--help" | "-h" => return Ok(None),
+                _ => return Err(format!("unknown argument '{}'", arg)),
+            }
+        }
+        if options.windowed && options.fullscreen {
+            return Err("--windowed and --fullscreen are exclusive".to_string());
+        }
+        Ok(Some(options))
+    }
+
+    /// Should the game start as soon as loading completes, instead of showing the menu?
+    pub fn skip_menu(&self) -> bool {
+        self.stage.is_some() || self.bench || self.replay.is_some() || self.pattern.is_some()
+    }
+
+    /// Is the player invulnerable for the whole game, to benchmark, simulate or preview
+    /// patterns?
+    pub fn invulnerable_player(&self) -> bool {
+        self.bench || self.simulate.is_some() || self.pattern.is_some()
+    }
+
+    /// Index of the stage to start the game at.
+    pub fn start_stage(&self) -> usize {
+        self.stage.map_or(0, |stage| stage - 1)
+    }
+
+    /// Logging configuration from the settings, with the overrides of the command line.
+    pub fn log_settings(&self, settings: &Settings) -> LogSettings {
+        LogSettings {
+            level: self.log_level.unwrap_or(settings.log.level).into(),
+            filter: self
+                .log_filter
+                .clone()
+                .unwrap_or_else(|| settings.log.filter.clone()),
+        }
+    }
+}
+
+/// Title of the game window.
+const WINDOW_TITLE: &str = "Super Kaizen Overloaded";
+
+/// Window and rendering configuration the app is built with, from the video settings
+/// and the overrides of the command line. On the web the canvas follows the browser
+/// window instead, and only vertical sync is supported, so most of it is ignored.
+#[derive(Debug, Clone)]
+pub struct LaunchConfig {
+    pub display_mode: DisplayMode,
+    /// Size of the window, in logical pixels.
+    pub width: u32,
+    pub height: u32,
+    pub present_mode: PresentModeSetting,
+    /// Number of MSAA samples, 1 (disabled) or 4.
+    pub msaa_samples: u32,
+}
+
+impl LaunchConfig {
+    pub fn new(launch: &LaunchOptions, settings: &Settings) -> LaunchConfig {
+        let video = &settings.video;
+        let present_mode = match launch.vsync {
+            Some(true) => PresentModeSetting::Fifo,
+            Some(false) => PresentModeSetting::Immediate,
+            None => video.present_mode,
+        };
+        LaunchConfig {
+            display_mode: if launch.windowed {
+                DisplayMode::Windowed
+            } else if launch.fullscreen {
+                DisplayMode::Borderless
+            } else {
+                video.display_mode
+            },
+            width: launch.width.unwrap_or(video.window_width).max(1),
+            height: launch.height.unwrap_or(video.window_height).max(1),
+            present_mode,
+            // Only 1 and 4 samples are supported everywhere
+            msaa_samples: match launch
+                .msaa
+                .unwrap_or_else(|| video.graphics_quality.msaa_samples())
+            {
+                1 => 1,
+                _ => 4,
+            },
+        }
+    }
+
+    /// Descriptor of the main window.
+    pub fn window(&self) -> WindowDescriptor {
+        if cfg!(target_arch = "wasm32") {
+            return WindowDescriptor {
+                title: WINDOW_TITLE.to_string(),
+                present_mode: PresentMode::Fifo,
+                ..Default::default()
+            };
+        }
+        WindowDescriptor {
+            title: WINDOW_TITLE.to_string(),
+            width: self.width as f32,
+            height: self.height as f32,
+            present_mode: self.present_mode.into(),
+            mode: self.display_mode.into(),
+            ..Default::default()
+        }
+    }
+
+    /// MSAA configuration of the render passes.
+    pub fn msaa(&self) -> Msaa {
+        Msaa {
+            samples: self.msaa_samples,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<LaunchOptions>, String> {
+        LaunchOptions::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_stage() {
+        let options = parse(&["--stage", "3"]).unwrap().unwrap();
+        assert_eq!(options.stage, Some(3));
+        assert_eq!(options.start_stage(), 2);
+        assert!(options.skip_menu());
+
+        let options = parse(&["--stage=1"]).unwrap().unwrap();
+        assert_eq!(options.start_stage(), 0);
+
+        let options = parse(&[]).unwrap().unwrap();
+        assert_eq!(options.start_stage(), 0);
+        assert!(!options.skip_menu());
+
+        // Stages start from 1
+        assert!(parse(&["--stage", "0"]).is_err());
+        assert!(parse(&["--stage", "-1"]).is_err());
+        assert!(parse(&["--stage", "two"]).is_err());
+        assert!(parse(&["--stage"]).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_arguments() {
+        assert_eq!(
+            parse(&["--bench", "--turbo"]).unwrap_err(),
+            "unknown argument '--turbo'"
+        );
+        assert!(parse(&["--turbo=1"]).is_err());
+        assert!(parse(&["stage"]).is_err());
+        assert!(parse(&["--windowed", "--fullscreen"]).is_err());
+        assert!(parse(&["--msaa", "2"]).is_err());
+        assert!(parse(&["--max-delta", "0"]).is_err());
+        assert!(parse(&["--max-delta", "fast"]).is_err());
+    }
+
+    #[test]
+    fn parse_help() {
+        assert!(parse(&["--help"]).unwrap().is_none());
+        assert!(parse(&["-h"]).unwrap().is_none());
+        // The help wins over the arguments after it, even invalid ones
+        assert!(parse(&["--bench", "--help", "--turbo"]).unwrap().is_none());
+    }
+}
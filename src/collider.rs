@@ -0,0 +1,199 @@
+use bevy::{prelude::*, render::primitives::Aabb, utils::HashMap};
+use heron::prelude::*;
+
+pub struct ColliderPlugin;
+
+impl Plugin for ColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ColliderCache>()
+            .add_system(update_model_colliders);
+    }
+}
+
+/// Replace the [`CollisionShape`] of the entity with a simplified proxy derived from
+/// the bounds of a GLTF scene spawned below it, once that scene is loaded.
+///
+/// The entity keeps its initial shape until then. The proxy is a child collider
+/// centered on the bounds, with the same [`CollisionLayers`] as the entity, since the
+/// model origin is not necessarily at its center. The component is removed once the
+/// proxy is applied.
+#[derive(Component, Debug, Clone)]
+pub struct ModelCollider {
+    /// Path of the GLTF scene, used as the key of the [`ColliderCache`].
+    pub model: String,
+    /// Entity the scene is spawned under, which holds the scale and rotation of the
+    /// model. Only the meshes below it are accounted for, so that other children like
+    /// health bars don't inflate the bounds.
+    pub scene_root: Entity,
+}
+
+impl ModelCollider {
+    pub fn new(model: impl Into<String>, scene_root: Entity) -> Self {
+        ModelCollider {
+            model: model.into(),
+            scene_root,
+        }
+    }
+}
+
+/// Bounds (min, max) of the GLTF scenes already measured, per scene path, so that the
+/// bounds of a model are only computed for its first instance. The bounds are in the
+/// local space of the scene root, before the scale and rotation of each instance.
+#[derive(Default)]
+pub struct ColliderCache(HashMap<String, (Vec3, Vec3)>);
+
+/// Ratio of the longest to the shortest side of the bounds above which the proxy is
+/// elongated instead of a sphere.
+const ELONGATED_RATIO: f32 = 1.5;
+
+/// Derive a simplified collision shape from the half extents of some bounds, in the
+/// XY plane of the 2D physics.
+fn proxy_shape(half_extents: Vec3) -> CollisionShape {
+    let (hx, hy) = (half_extents.x, half_extents.y);
+    if hx.max(hy) < hx.min(hy) * ELONGATED_RATIO {
+        CollisionShape::Sphere {
+            radius: (hx + hy) / 2.,
+        }
+    } else if hy > hx {
+        // Capsules are aligned with the Y axis
+        CollisionShape::Capsule {
+            half_segment: hy - hx,
+            radius: hx,
+        }
+    } else {
+        CollisionShape::Cuboid {
+            half_extends: half_extents,
+            border_radius: None,
+        }
+    }
+}
+
+/// Collect all the descendants of an entity which have some bounds.
+fn collect_bounds(
+    entity: Entity,
+    q_children: &Query<&Children>,
+    q_bounds: &Query<(&Aabb, &GlobalTransform)>,
+    bounds: &mut Vec<(Aabb, Mat4)>,
+) {
+    if let Ok((aabb, transform)) = q_bounds.get(entity) {
+        bounds.push((aabb.clone(), transform.compute_matrix()));
+    }
+    if let Ok(children) = q_children.get(entity) {
+        for child in children.iter() {
+            collect_bounds(*child, q_children, q_bounds, bounds);
+        }
+    }
+}
+
+/// Bounds (min, max) of all the meshes below a scene root, in the local space of the
+/// given root entity, or `None` if the scene is not spawned yet.
+pub fn model_bounds(
+    scene_root: Entity,
+    root_transform: &GlobalTransform,
+    q_children: &Query<&Children>,
+    q_bounds: &Query<(&Aabb, &GlobalTransform)>,
+) -> Option<(Vec3, Vec3)> {
+    let mut bounds = vec![];
+    collect_bounds(scene_root, q_children, q_bounds, &mut bounds);
+    if bounds.is_empty() {
+        return None;
+    }
+
+    // Merge the bounds of all meshes, in the local space of the root entity
+    let to_local = root_transform.compute_matrix().inverse();
+    let mut min = Vec3::splat(f32::MAX);
+    let mut max = Vec3::splat(f32::MIN);
+    for (aabb, transform) in &bounds {
+        let to_root = to_local * *transform;
+        let center = Vec3::from(aabb.center);
+        let half_extents = Vec3::from(aabb.half_extents);
+        for corner in 0..8 {
+            let sign = Vec3::new(
+                if corner & 1 == 0 { -1. } else { 1. },
+                if corner & 2 == 0 { -1. } else { 1. },
+                if corner & 4 == 0 { -1. } else { 1. },
+            );
+            let point = to_root.transform_point3(center + half_extents * sign);
+            min = min.min(point);
+            max = max.max(point);
+        }
+    }
+    Some((min, max))
+}
+
+/// Bounds (min, max) of the box (min, max) transformed by the given transform.
+fn transform_bounds(min: Vec3, max: Vec3, transform: &Transform) -> (Vec3, Vec3) {
+    let matrix = transform.compute_matrix();
+    let mut new_min = Vec3::splat(f32::MAX);
+    let mut new_max = Vec3::splat(f32::MIN);
+    for corner in 0..8 {
+        let point = Vec3::new(
+            if corner & 1 == 0 { min.x } else { max.x },
+            if corner & 2 == 0 { min.y } else { max.y },
+            if corner & 4 == 0 { min.z } else { max.z },
+        );
+        let point = matrix.transform_point3(point);
+        new_min = new_min.min(point);
+        new_max = new_max.max(point);
+    }
+    (new_min, new_max)
+}
+
+fn update_model_colliders(
+    mut commands: Commands,
+    mut cache: ResMut<ColliderCache>,
+    q_pending: Query<(Entity, &ModelCollider, Option<&CollisionLayers>)>,
+    q_roots: Query<(&Transform, &GlobalTransform)>,
+    q_children: Query<&Children>,
+    q_bounds: Query<(&Aabb, &GlobalTransform)>,
+) {
+    for (entity, model_collider, layers) in q_pending.iter() {
+        let (root_transform, root_global_transform) =
+            if let Ok(root) = q_roots.get(model_collider.scene_root) {
+                root
+            } else {
+                continue;
+            };
+        let (min, max) = if let Some(bounds) = cache.0.get(&model_collider.model) {
+            *bounds
+        } else {
+            let bounds = if let Some(bounds) = model_bounds(
+                model_collider.scene_root,
+                root_global_transform,
+                &q_children,
+                &q_bounds,
+            ) {
+                bounds
+            } else {
+                // Scene not spawned yet
+                continue;
+            };
+            debug!(
+                target: "collider",
+                "Model bounds for '{}': {:?}",
+                model_collider.model, bounds
+            );
+            cache.0.insert(model_collider.model.clone(), bounds);
+            bounds
+        };
+
+        // Apply the scale and rotation of this instance
+        let (min, max) = transform_bounds(min, max, root_transform);
+        let center = (min + max) / 2.;
+        let shape = proxy_shape((max - min) / 2.);
+        commands
+            .entity(entity)
+            .remove::<CollisionShape>()
+            .remove::<ModelCollider>()
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle((
+                        Transform::from_translation(Vec3::new(center.x, center.y, 0.)),
+                        GlobalTransform::identity(),
+                    ))
+                    .insert(Name::new("ModelCollider"))
+                    .insert(shape)
+                    .insert(layers.copied().unwrap_or_default());
+            });
+    }
+}
@@ -0,0 +1,150 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::PlayerController,
+    overdrive::{Overdrive, OVERDRIVE_SCORE_MULTIPLIER},
+    timestep::{FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+/// Kill combo: each kill soon after the previous one raises a multiplier applied to the
+/// kill scores. The multiplier slowly decays between kills, and the combo breaks when
+/// the player gets hit or doesn't kill anything for too long. An [`Overdrive`] further
+/// multiplies the kill scores while it lasts.
+pub struct ComboPlugin;
+
+impl Plugin for ComboPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Combo>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(combo_setup),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame).with_system(update_combo),
+            );
+    }
+}
+
+/// Time after a kill during which the next kill extends the combo, in seconds.
+const COMBO_WINDOW: f32 = 2.5;
+
+/// Multiplier gained per kill of the combo.
+const MULTIPLIER_STEP: f32 = 0.1;
+
+/// Maximum score multiplier.
+const MAX_MULTIPLIER: f32 = 5.;
+
+/// Multiplier lost per second between two kills of the combo.
+const MULTIPLIER_DECAY: f32 = 0.2;
+
+/// Current kill combo.
+///
+/// Updated in the gameplay steps, since it affects the score, which replays must
+/// reproduce.
+#[derive(Debug)]
+pub struct Combo {
+    multiplier: f32,
+    /// Number of kills in the current combo.
+    kills: u32,
+    /// Number of combos broken so far, to detect a break.
+    breaks: u32,
+    /// Time left to extend the combo, in seconds. Zero when there's no combo.
+    timer: f32,
+    /// Hits taken by the player so far, to detect a new hit.
+    hits_taken: u32,
+    /// Is the player in overdrive?
+    overdrive: bool,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Combo {
+            multiplier: 1.,
+            kills: 0,
+            breaks: 0,
+            timer: 0.,
+            hits_taken: 0,
+            overdrive: false,
+        }
+    }
+}
+
+impl Combo {
+    /// Score multiplier, at least one.
+    pub fn multiplier(&self) -> f32 {
+        self.multiplier
+    }
+
+    /// Number of kills in the current combo.
+    pub fn kills(&self) -> u32 {
+        self.kills
+    }
+
+    /// Number of combos broken so far, which only ever increases during a game.
+    pub fn breaks(&self) -> u32 {
+        self.breaks
+    }
+
+    /// Extend the combo with a kill, and return the kill score scaled by the multiplier
+    /// before the kill, and by the overdrive if any.
+    pub fn add_kill(&mut self, score: u32) -> u32 {
+        let overdrive = if self.overdrive {
+            OVERDRIVE_SCORE_MULTIPLIER
+        } else {
+            1.
+        };
+        let score = (score as f32 * self.multiplier * overdrive).round() as u32;
+        self.kills += 1;
+        self.multiplier = (self.multiplier + MULTIPLIER_STEP).min(MAX_MULTIPLIER);
+        self.timer = COMBO_WINDOW;
+        score
+    }
+
+    fn break_combo(&mut self) {
+        if self.kills > 0 {
+            debug!(
+                target: "combo",
+                "COMBO: break kills={} multiplier={:.1}",
+                self.kills,
+                self.multiplier
+            );
+            self.breaks += 1;
+        }
+        self.multiplier = 1.;
+        self.kills = 0;
+        self.timer = 0.;
+    }
+}
+
+fn combo_setup(mut commands: Commands) {
+    commands.insert_resource(Combo::default());
+}
+
+/// Decay the multiplier, and break the combo on time out or when the player is hit.
+/// Runs before the enemy kills, which extend the combo.
+pub fn update_combo(
+    mut combo: ResMut<Combo>,
+    q_player: Query<(&PlayerController, Option<&Overdrive>)>,
+) {
+    if let Ok((controller, overdrive)) = q_player.get_single() {
+        if combo.overdrive != overdrive.is_some() {
+            combo.overdrive = overdrive.is_some();
+        }
+        let hits_taken = controller.hits_taken();
+        if hits_taken != combo.hits_taken {
+            combo.hits_taken = hits_taken;
+            combo.break_combo();
+        }
+    }
+
+    if combo.timer > 0. {
+        combo.timer -= FIXED_DELTA_SECONDS;
+        if combo.timer <= 0. {
+            combo.break_combo();
+        } else {
+            combo.multiplier = (combo.multiplier - MULTIPLIER_DECAY * FIXED_DELTA_SECONDS).max(1.);
+        }
+    }
+}
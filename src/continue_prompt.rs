@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    game::{ContinueEvent, GameOverEvent, InGameEntity, LastLifeLostEvent},
+    music::AudioFacade,
+    navigation::{NavAction, NavMenuBundle},
+    AppState,
+};
+
+/// Arcade-style "Continue?" countdown, offered when the player loses their last life.
+pub struct ContinuePlugin;
+
+impl Plugin for ContinuePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            CoreStage::Update,
+            SystemSet::on_update(AppState::InGame).with_system(update_continue_prompt),
+        );
+    }
+}
+
+/// Number the countdown starts from. The game ends once it runs past zero.
+const COUNTDOWN_START: u32 = 9;
+
+/// Duration of each number of the countdown, in seconds.
+const COUNTDOWN_STEP: f32 = 1.;
+
+/// Time before the prompt accepts any input, in seconds, so that the player mashing
+/// the fire button while dying doesn't continue by accident.
+const INPUT_DELAY: f32 = 0.5;
+
+/// Root of the continue prompt.
+#[derive(Component)]
+struct ContinuePrompt {
+    /// Time since the prompt appeared, in seconds.
+    time: f32,
+}
+
+impl ContinuePrompt {
+    /// Number currently displayed by the countdown, or `None` once it ran out.
+    fn countdown(&self) -> Option<u32> {
+        let step = (self.time / COUNTDOWN_STEP) as u32;
+        COUNTDOWN_START.checked_sub(step)
+    }
+}
+
+/// Text of the countdown number.
+#[derive(Component)]
+struct ContinueCountdown;
+
+/// Show the prompt when the player loses their last life, then either continue if the
+/// player confirms, or end the game once the countdown runs out. Back skips the rest
+/// of the countdown.
+fn update_continue_prompt(
+    mut commands: Commands,
+    mut q_prompt: Query<(Entity, &mut ContinuePrompt, &ActionState<NavAction>)>,
+    mut q_countdown: Query<&mut Text, With<ContinueCountdown>>,
+    mut last_life_events: EventReader<LastLifeLostEvent>,
+    mut continue_events: EventWriter<ContinueEvent>,
+    mut game_over_events: EventWriter<GameOverEvent>,
+    asset_server: Res<AssetServer>,
+    audio_facade: AudioFacade,
+    time: Res<Time>,
+) {
+    if last_life_events.iter().count() > 0 && q_prompt.is_empty() {
+        spawn_continue_prompt(&mut commands, &asset_server);
+        return;
+    }
+
+    let (entity, mut prompt, action_state) = if let Ok(prompt) = q_prompt.get_single_mut() {
+        prompt
+    } else {
+        return;
+    };
+
+    let prev_countdown = prompt.countdown();
+    prompt.time += time.delta_seconds();
+    let accepts_input = prompt.time >= INPUT_DELAY;
+    if accepts_input && action_state.just_pressed(NavAction::Confirm) {
+        commands.entity(entity).despawn_recursive();
+        continue_events.send(ContinueEvent);
+        audio_facade.play_ui(asset_server.load("sounds/click4.ogg"));
+        return;
+    }
+
+    let countdown = if accepts_input && action_state.just_pressed(NavAction::Back) {
+        None
+    } else {
+        prompt.countdown()
+    };
+    if let Some(countdown) = countdown {
+        if Some(countdown) != prev_countdown {
+            for mut text in q_countdown.iter_mut() {
+                text.sections[0].value = countdown.to_string();
+            }
+            audio_facade.play_ui(asset_server.load("sounds/click4.ogg"));
+        }
+    } else {
+        commands.entity(entity).despawn_recursive();
+        game_over_events.send(GameOverEvent);
+    }
+}
+
+fn spawn_continue_prompt(commands: &mut Commands, asset_server: &AssetServer) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let color = Color::rgb_u8(224, 224, 224);
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color,
+            },
+            Default::default(),
+        )
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(30.),
+                    left: Val::Percent(30.),
+                    right: Val::Percent(30.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.6)),
+            ..Default::default()
+        })
+        .insert(Name::new("ContinuePrompt"))
+        .insert(InGameEntity)
+        .insert(ContinuePrompt { time: 0. })
+        .insert_bundle(NavMenuBundle::new(1))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: text("CONTINUE?".to_string(), 56.),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(TextBundle {
+                    text: text(COUNTDOWN_START.to_string(), 144.),
+                    ..Default::default()
+                })
+                .insert(ContinueCountdown);
+            parent.spawn_bundle(TextBundle {
+                text: text("Confirm to continue, score resets".to_string(), 24.),
+                ..Default::default()
+            });
+        });
+}
@@ -0,0 +1,128 @@
+use bevy::prelude::*;
+use bevy_tweening::{lens::*, *};
+use leafwing_input_manager::prelude::*;
+use std::time::Duration;
+
+use crate::{
+    flow::RequestStateChange,
+    navigation::{NavAction, NavMenuBundle},
+    AppState,
+};
+
+/// Credits page of the main menu, scrolling the text of `assets/credits.txt` up the
+/// screen, then going back to the menu.
+pub struct CreditsPlugin;
+
+impl Plugin for CreditsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Credits).with_system(credits_setup))
+            .add_system_set(SystemSet::on_update(AppState::Credits).with_system(credits_run))
+            .add_system_set(SystemSet::on_exit(AppState::Credits).with_system(credits_cleanup));
+    }
+}
+
+/// Text of the credits, one entry per line.
+const CREDITS: &str = include_str!("../assets/credits.txt");
+
+/// Font size of the credits text.
+const FONT_SIZE: f32 = 32.;
+
+/// Height of a line of the credits text, relative to the font size.
+const LINE_HEIGHT: f32 = 1.25;
+
+/// Scrolling speed of the credits, in pixels per second.
+const SCROLL_SPEED: f32 = 60.;
+
+/// Marker for all entities of the credits page, for cleanup.
+#[derive(Component)]
+struct CreditsScreen;
+
+/// Time left before the credits finished scrolling, in seconds.
+#[derive(Component)]
+struct CreditsTimer(f32);
+
+fn credits_setup(mut commands: Commands, asset_server: Res<AssetServer>, windows: Res<Windows>) {
+    debug!(target: "setup", "credits_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(CreditsScreen);
+
+    let font = asset_server.load("fonts/FiraSans-Regular.ttf");
+    let window_height = windows.get_primary().map_or(720., |window| window.height());
+    let text_height = CREDITS.lines().count() as f32 * FONT_SIZE * LINE_HEIGHT;
+
+    // Scroll from fully below the screen to fully above it. UI nodes are laid out from
+    // the bottom of the screen up.
+    let start = Rect {
+        top: Val::Px(-text_height),
+        left: Val::Px(0.),
+        right: Val::Px(0.),
+        ..Default::default()
+    };
+    let end = Rect {
+        top: Val::Px(window_height),
+        ..start
+    };
+    let duration = (window_height + text_height) / SCROLL_SPEED;
+    let tween = Tween::new(
+        EaseMethod::Linear,
+        TweeningType::Once,
+        Duration::from_secs_f32(duration),
+        UiPositionLens { start, end },
+    );
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: start,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("credits"))
+        .insert(CreditsScreen)
+        .insert(CreditsTimer(duration))
+        .insert(Animator::new(tween))
+        .insert_bundle(NavMenuBundle::new(1))
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    CREDITS,
+                    TextStyle {
+                        font,
+                        font_size: FONT_SIZE,
+                        color: Color::rgb_u8(32, 32, 32),
+                    },
+                    TextAlignment {
+                        vertical: VerticalAlign::Top,
+                        horizontal: HorizontalAlign::Center,
+                    },
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn credits_run(
+    mut q_credits: Query<(&mut CreditsTimer, &ActionState<NavAction>)>,
+    mut state_requests: EventWriter<RequestStateChange>,
+    time: Res<Time>,
+) {
+    let (mut timer, action_state) = q_credits.single_mut();
+    timer.0 -= time.delta_seconds();
+    if timer.0 <= 0.
+        || action_state.just_pressed(NavAction::Back)
+        || action_state.just_pressed(NavAction::Confirm)
+    {
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+    }
+}
+
+fn credits_cleanup(mut commands: Commands, query: Query<Entity, With<CreditsScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
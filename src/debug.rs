@@ -1,13 +1,59 @@
-use bevy::{app::CoreStage, asset::AssetStage, prelude::*};
+use bevy::{
+    app::CoreStage,
+    asset::AssetStage,
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+    transform::TransformSystem,
+};
+use bevy_inspector_egui::{bevy_egui::EguiContext, egui};
+use bevy_prototype_debug_lines::{DebugLines, DebugLinesPlugin};
+use heron::prelude::*;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    enemy::{DebugEnemyCommand, PatternPreview, TimelineView},
+    event_log::EventLog,
+    game::{
+        CameraBaseTransform, DebugCheats, EnemyBullet, MainCamera, PlayerController, TimeScale,
+        ENEMY_BULLET_RADIUS,
+    },
+    logging::LogBuffer,
+    rank::Rank,
+    replay::ReplayRecorder,
+    tuning::Tuning,
+    Layer,
+};
 
 pub struct DebugPlugin;
 
 impl Plugin for DebugPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(fps_counter_setup)
+        app.add_plugin(DebugLinesPlugin::default())
+            .init_resource::<PatternHeatmap>()
+            .init_resource::<DebugConsole>()
+            .init_resource::<TimelineScrubber>()
+            .init_resource::<PhotoMode>()
+            .init_resource::<TuningPanel>()
+            .add_startup_system(fps_counter_setup)
+            .add_startup_system(event_ticker_setup)
+            .add_startup_system(log_viewer_setup)
             .add_system(fps_counter)
-            // Helper to exit with ESC key
-            .add_system(bevy::input::system::exit_on_esc_system);
+            .add_system(update_event_ticker)
+            .add_system(update_log_viewer)
+            .add_system(toggle_event_log_file)
+            .add_system(debug_time_scale)
+            .add_system(debug_console)
+            .add_system(timeline_scrubber)
+            .add_system(pattern_preview_window)
+            .add_system(tuning_panel)
+            .add_system(draw_collision_shapes)
+            .add_system(toggle_cheats)
+            .add_system(record_pattern_heatmap)
+            .add_system(toggle_photo_mode)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_photo_camera.before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -68,3 +114,983 @@ fn fps_counter(mut query: Query<(&mut Text, &mut FpsCounter)>, time: Res<Time>)
         counter.0 = now;
     }
 }
+
+/// Number of lines of the event ticker.
+const TICKER_LINES: usize = 8;
+
+/// Time an event stays on the ticker, in seconds.
+const TICKER_DURATION: f64 = 6.;
+
+/// Scrolling list of the most recent entries of the [`EventLog`].
+#[derive(Component)]
+struct EventTicker;
+
+fn event_ticker_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+        font_size: 14.0,
+        color: Color::rgb_u8(32, 32, 32),
+    };
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(24.0),
+                    right: Val::Px(5.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: (0..TICKER_LINES)
+                    .map(|_| TextSection {
+                        value: "".to_string(),
+                        style: style.clone(),
+                    })
+                    .collect(),
+                alignment: TextAlignment {
+                    horizontal: HorizontalAlign::Right,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .insert(Name::new("EventTicker"))
+        .insert(EventTicker);
+}
+
+fn update_event_ticker(
+    log: Res<EventLog>,
+    mut query: Query<&mut Text, With<EventTicker>>,
+    time: Res<Time>,
+) {
+    let mut text = if let Ok(text) = query.get_single_mut() {
+        text
+    } else {
+        return;
+    };
+    let now = time.seconds_since_startup();
+    let mut entries = log
+        .entries
+        .iter()
+        .rev()
+        .take_while(|entry| now - entry.time < TICKER_DURATION)
+        .take(TICKER_LINES);
+    // Newest event at the bottom, fading out as it ages
+    for section in text.sections.iter_mut().rev() {
+        let (value, alpha) = if let Some(entry) = entries.next() {
+            let age = (now - entry.time) / TICKER_DURATION;
+            (format!("{}\n", entry.event), 1. - age as f32 * age as f32)
+        } else {
+            ("".to_string(), 1.)
+        };
+        if section.value != value {
+            section.value = value;
+        }
+        section.style.color.set_a(alpha);
+    }
+}
+
+/// Number of lines of the log viewer.
+const LOG_VIEWER_LINES: usize = 16;
+
+/// Panel listing the most recent messages of the [`LogBuffer`], toggled with F8.
+#[derive(Component)]
+struct LogViewer {
+    /// Message count of the buffer when the panel was last updated.
+    count: u64,
+}
+
+fn log_viewer_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+        font_size: 12.0,
+        color: Color::WHITE,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(5.0),
+                    left: Val::Px(5.0),
+                    ..Default::default()
+                },
+                max_size: Size::new(Val::Percent(60.), Val::Undefined),
+                padding: Rect::all(Val::Px(4.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.75)),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Name::new("LogViewer"))
+        .insert(LogViewer { count: u64::MAX })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text {
+                    sections: (0..LOG_VIEWER_LINES)
+                        .map(|_| TextSection {
+                            value: "".to_string(),
+                            style: style.clone(),
+                        })
+                        .collect(),
+                    ..Default::default()
+                },
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            });
+        });
+}
+
+fn log_level_color(level: bevy::log::Level) -> Color {
+    match level {
+        bevy::log::Level::ERROR => Color::rgb(1., 0.3, 0.3),
+        bevy::log::Level::WARN => Color::rgb(1., 0.85, 0.3),
+        bevy::log::Level::INFO => Color::WHITE,
+        _ => Color::rgb(0.6, 0.6, 0.6),
+    }
+}
+
+fn update_log_viewer(
+    keyboard_input: Res<Input<KeyCode>>,
+    buffer: Res<LogBuffer>,
+    mut q_viewer: Query<(&mut LogViewer, &mut Visibility, &Children)>,
+    mut q_text: Query<(&mut Text, &mut Visibility), Without<LogViewer>>,
+) {
+    let (mut viewer, mut visibility, children) = if let Ok(viewer) = q_viewer.get_single_mut() {
+        viewer
+    } else {
+        return;
+    };
+    let (mut text, mut text_visibility) = if let Ok(text) = q_text.get_mut(children[0]) {
+        text
+    } else {
+        return;
+    };
+
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        visibility.is_visible = !visibility.is_visible;
+        text_visibility.is_visible = visibility.is_visible;
+        // Force a refresh when shown
+        viewer.count = u64::MAX;
+    }
+    if !visibility.is_visible || buffer.count() == viewer.count {
+        return;
+    }
+    viewer.count = buffer.count();
+
+    // Newest message at the bottom
+    let lines = buffer.last(LOG_VIEWER_LINES);
+    let padding = LOG_VIEWER_LINES - lines.len();
+    for (index, section) in text.sections.iter_mut().enumerate() {
+        if let Some(line) = index.checked_sub(padding).and_then(|i| lines.get(i)) {
+            section.value = format!("{:>5} {}: {}\n", line.level, line.target, line.message);
+            section.style.color = log_level_color(line.level);
+        } else {
+            section.value = "\n".to_string();
+        }
+    }
+}
+
+/// Toggle writing the event log to a file of the `logs/` folder with F10.
+fn toggle_event_log_file(keyboard_input: Res<Input<KeyCode>>, mut log: ResMut<EventLog>) {
+    if !keyboard_input.just_pressed(KeyCode::F10) {
+        return;
+    }
+    if log.is_writing_file() {
+        log.close_file();
+        info!(target: "debug", "Stopped writing the event log");
+        return;
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let path = format!("logs/events_{}.log", timestamp);
+    match log.open_file(&path) {
+        Ok(_) => info!(target: "debug", "Writing the event log to '{}'", path),
+        Err(err) => warn!(target: "debug", "Failed to write the event log to '{}': {}", path, err),
+    }
+}
+
+/// Change the speed of the gameplay with F2 (0.25x), F3 (0.5x), F4 (1x) and F5 (2x),
+/// freeze it with F6, and advance it by a single fixed step with F7 while frozen.
+fn debug_time_scale(keyboard_input: Res<Input<KeyCode>>, mut time_scale: ResMut<TimeScale>) {
+    for (key, scale) in [
+        (KeyCode::F2, 0.25),
+        (KeyCode::F3, 0.5),
+        (KeyCode::F4, 1.),
+        (KeyCode::F5, 2.),
+    ] {
+        if keyboard_input.just_pressed(key) {
+            time_scale.set_debug_scale(scale);
+            info!(target: "debug", "Time scale: {}x", scale);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        let frozen = !time_scale.is_frozen();
+        time_scale.set_frozen(frozen);
+        info!(target: "debug", "Time {}", if frozen { "frozen" } else { "resumed" });
+    }
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        time_scale.step_frame();
+    }
+}
+
+/// Maximum number of lines kept in the history of the debug console.
+const CONSOLE_HISTORY_LINES: usize = 64;
+
+/// Amount of weapon power given by the `give_power` console command by default.
+const CONSOLE_DEFAULT_POWER: u32 = 10;
+
+const CONSOLE_HELP: &[&str] = &[
+    "help                  list the commands",
+    "spawn <enemy> <x> <y> spawn an enemy",
+    "kill_all              kill all enemies",
+    "give_power [amount]   add weapon power",
+    "set_rank <rank>       set the rank, from 0 to 5",
+    "goto_time <t>         move the timeline to <t> seconds",
+    "god                   toggle player invincibility",
+    "infinite_bombs        toggle infinite bombs",
+    "one_hit_kill          toggle one-hit kills on enemies",
+];
+
+/// Debug console, toggled with the backquote key, to spawn enemies and trigger events
+/// without editing the timeline.
+#[derive(Default)]
+struct DebugConsole {
+    open: bool,
+    /// Command being typed.
+    input: String,
+    /// Commands run and their output, oldest first.
+    history: Vec<String>,
+}
+
+impl DebugConsole {
+    fn print(&mut self, line: impl Into<String>) {
+        self.history.push(line.into());
+        if self.history.len() > CONSOLE_HISTORY_LINES {
+            let excess = self.history.len() - CONSOLE_HISTORY_LINES;
+            self.history.drain(..excess);
+        }
+    }
+}
+
+fn debug_console(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut console: ResMut<DebugConsole>,
+    mut enemy_commands: EventWriter<DebugEnemyCommand>,
+    mut rank: ResMut<Rank>,
+    mut cheats: ResMut<DebugCheats>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut q_player: Query<&mut PlayerController>,
+) {
+    let just_opened = keyboard_input.just_pressed(KeyCode::Grave) && !console.open;
+    if keyboard_input.just_pressed(KeyCode::Grave) {
+        console.open = !console.open;
+    }
+    if !console.open {
+        return;
+    }
+
+    let mut submitted = None;
+    egui::Window::new("Console").show(egui_context.ctx_mut(), |ui| {
+        egui::ScrollArea::vertical()
+            .max_height(200.)
+            .stick_to_bottom()
+            .show(ui, |ui| {
+                for line in &console.history {
+                    ui.monospace(line);
+                }
+            });
+        let response = ui.text_edit_singleline(&mut console.input);
+        // Don't type the toggle key into the console
+        console.input.retain(|c| c != '`');
+        if response.lost_focus() && ui.input().key_pressed(egui::Key::Enter) {
+            submitted = Some(std::mem::take(&mut console.input));
+        }
+        if just_opened || submitted.is_some() {
+            response.request_focus();
+        }
+    });
+
+    let line = match submitted {
+        Some(line) if !line.trim().is_empty() => line,
+        _ => return,
+    };
+    console.print(format!("> {}", line.trim()));
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let result = match args.as_slice() {
+        ["help"] => {
+            for help in CONSOLE_HELP {
+                console.print(*help);
+            }
+            Ok(())
+        }
+        ["spawn", enemy, x, y] => match (x.parse::<f32>(), y.parse::<f32>()) {
+            (Ok(x), Ok(y)) => {
+                enemy_commands.send(DebugEnemyCommand::Spawn {
+                    enemy: enemy.to_string(),
+                    position: Vec3::new(x, y, 0.),
+                });
+                Ok(())
+            }
+            _ => Err("invalid position".to_string()),
+        },
+        ["kill_all"] => {
+            enemy_commands.send(DebugEnemyCommand::KillAll);
+            Ok(())
+        }
+        ["give_power", amount @ ..] if amount.len() <= 1 => {
+            match amount
+                .first()
+                .map_or(Ok(CONSOLE_DEFAULT_POWER), |a| a.parse())
+            {
+                Ok(amount) => {
+                    if let Ok(mut controller) = q_player.get_single_mut() {
+                        controller.add_power(amount);
+                        Ok(())
+                    } else {
+                        Err("no player".to_string())
+                    }
+                }
+                Err(_) => Err("invalid amount".to_string()),
+            }
+        }
+        ["set_rank", value] => match value.parse::<f32>() {
+            Ok(value) => {
+                rank.set(value);
+                Ok(())
+            }
+            Err(_) => Err("invalid rank".to_string()),
+        },
+        ["goto_time", time] => match time.parse::<f64>() {
+            Ok(time) => {
+                enemy_commands.send(DebugEnemyCommand::GotoTime(time));
+                Ok(())
+            }
+            Err(_) => Err("invalid time".to_string()),
+        },
+        ["god"] => {
+            cheats.god_mode = !cheats.god_mode;
+            console.print(format!("god mode: {}", on_off(cheats.god_mode)));
+            Ok(())
+        }
+        ["infinite_bombs"] => {
+            cheats.infinite_bombs = !cheats.infinite_bombs;
+            console.print(format!("infinite bombs: {}", on_off(cheats.infinite_bombs)));
+            Ok(())
+        }
+        ["one_hit_kill"] => {
+            cheats.one_hit_kill = !cheats.one_hit_kill;
+            console.print(format!("one-hit kill: {}", on_off(cheats.one_hit_kill)));
+            Ok(())
+        }
+        _ => Err("unknown command, type 'help' for the list".to_string()),
+    };
+    match result {
+        // Any command but the help alters the run, which then isn't ranked
+        Ok(()) if args.as_slice() != ["help"] => recorder.taint(),
+        Ok(()) => {}
+        Err(err) => console.print(format!("error: {}", err)),
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "ON"
+    } else {
+        "OFF"
+    }
+}
+
+/// Toggle the [`DebugCheats`] with Ctrl+G (god mode), Ctrl+B (infinite bombs) and
+/// Ctrl+K (one-hit kill). Toggling any cheat taints the current run.
+fn toggle_cheats(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut cheats: ResMut<DebugCheats>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if !keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
+    }
+    if keyboard_input.any_just_pressed([KeyCode::G, KeyCode::B, KeyCode::K]) {
+        recorder.taint();
+    }
+    if keyboard_input.just_pressed(KeyCode::G) {
+        cheats.god_mode = !cheats.god_mode;
+        info!(target: "debug", "God mode: {}", on_off(cheats.god_mode));
+    }
+    if keyboard_input.just_pressed(KeyCode::B) {
+        cheats.infinite_bombs = !cheats.infinite_bombs;
+        info!(target: "debug", "Infinite bombs: {}", on_off(cheats.infinite_bombs));
+    }
+    if keyboard_input.just_pressed(KeyCode::K) {
+        cheats.one_hit_kill = !cheats.one_hit_kill;
+        info!(target: "debug", "One-hit kill: {}", on_off(cheats.one_hit_kill));
+    }
+}
+
+/// Timeline scrubber, toggled with F11, listing the events of the enemy timeline to
+/// pause it, or seek to any event to iterate on late waves.
+#[derive(Default)]
+struct TimelineScrubber {
+    open: bool,
+}
+
+fn timeline_scrubber(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut scrubber: ResMut<TimelineScrubber>,
+    view: Res<TimelineView>,
+    mut enemy_commands: EventWriter<DebugEnemyCommand>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F11) {
+        scrubber.open = !scrubber.open;
+    }
+    if !scrubber.open {
+        return;
+    }
+
+    egui::Window::new("Timeline").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.monospace(format!(
+                "t={:.2}s  next #{}/{}",
+                view.time,
+                view.index,
+                view.events.len()
+            ));
+            let label = if view.paused { "Resume" } else { "Pause" };
+            if ui.button(label).clicked() {
+                enemy_commands.send(DebugEnemyCommand::SetTimelinePaused(!view.paused));
+            }
+        });
+        ui.separator();
+        let row_height = ui.spacing().interact_size.y;
+        egui::ScrollArea::vertical().max_height(400.).show_rows(
+            ui,
+            row_height,
+            view.events.len(),
+            |ui, rows| {
+                for index in rows {
+                    let (time, description) = &view.events[index];
+                    ui.horizontal(|ui| {
+                        // Jump pauses on the event to inspect it, restart plays from it
+                        if ui.small_button("Jump").clicked() {
+                            enemy_commands.send(DebugEnemyCommand::Seek { index, pause: true });
+                        }
+                        if ui.small_button("Restart").clicked() {
+                            enemy_commands.send(DebugEnemyCommand::Seek {
+                                index,
+                                pause: false,
+                            });
+                        }
+                        let text = format!("#{:<4} {:>7.2}s  {}", index, time, description);
+                        let text = egui::RichText::new(text).monospace();
+                        // Highlight the next event, and dim the ones already run
+                        ui.label(if index == view.index {
+                            text.color(egui::Color32::YELLOW)
+                        } else if index < view.index {
+                            text.weak()
+                        } else {
+                            text
+                        });
+                    });
+                }
+            },
+        );
+    });
+}
+
+/// Window of the pattern preview started with `--pattern`, with a slider for each
+/// parameter of the fire tags of the previewed enemy, applied on restart with Ctrl+R.
+fn pattern_preview_window(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut preview: ResMut<PatternPreview>,
+    mut enemy_commands: EventWriter<DebugEnemyCommand>,
+) {
+    let enemy = match &preview.enemy {
+        Some(enemy) => enemy.clone(),
+        None => return,
+    };
+
+    let mut restart = keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl])
+        && keyboard_input.just_pressed(KeyCode::R);
+    egui::Window::new("Pattern").show(egui_context.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.monospace(&enemy);
+            if ui.button("Restart (Ctrl+R)").clicked() {
+                restart = true;
+            }
+        });
+        ui.separator();
+        if preview.params.is_empty() {
+            ui.label("No tweakable parameter");
+        }
+        egui::ScrollArea::vertical()
+            .max_height(400.)
+            .show(ui, |ui| {
+                for param in preview.params.iter_mut() {
+                    let slider = egui::Slider::new(&mut param.value, param.range.0..=param.range.1)
+                        .text(&param.name);
+                    ui.add(if param.integer {
+                        slider.integer()
+                    } else {
+                        slider
+                    });
+                }
+            });
+    });
+    if restart {
+        enemy_commands.send(DebugEnemyCommand::RestartPreview);
+    }
+}
+
+/// Tuning panel, toggled with Ctrl+T, to edit the [`Tuning`] of the gameplay live and
+/// save it to presets, or load it back from them.
+#[derive(Default)]
+struct TuningPanel {
+    open: bool,
+    /// Name of the preset to save or load.
+    preset: String,
+    /// Names of the saved presets, listed when opening the panel and after saving.
+    presets: Vec<String>,
+    /// Outcome of the last save or load.
+    status: String,
+}
+
+fn tuning_panel(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut panel: ResMut<TuningPanel>,
+    mut tuning: ResMut<Tuning>,
+) {
+    if keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl])
+        && keyboard_input.just_pressed(KeyCode::T)
+    {
+        panel.open = !panel.open;
+        if panel.open {
+            panel.presets = Tuning::list_presets();
+        }
+    }
+    if !panel.open {
+        return;
+    }
+
+    // Edit a copy, to only trigger change detection when a value actually changes
+    let panel = &mut *panel;
+    let mut edited = tuning.clone();
+    egui::Window::new("Tuning").show(egui_context.ctx_mut(), |ui| {
+        for (name, value, range) in edited.params_mut() {
+            ui.add(egui::Slider::new(value, range).text(name));
+        }
+        if ui.button("Reset to defaults").clicked() {
+            edited = Tuning::default();
+        }
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut panel.preset);
+            let name = panel.preset.trim().to_string();
+            if ui.button("Save").clicked() && !name.is_empty() {
+                panel.status = match edited.save_preset(&name) {
+                    Ok(()) => format!("saved preset {}", name),
+                    Err(err) => format!("failed to save preset {}: {}", name, err),
+                };
+                panel.presets = Tuning::list_presets();
+            }
+            if ui.button("Load").clicked() && !name.is_empty() {
+                panel.status = match Tuning::load_preset(&name) {
+                    Ok(preset) => {
+                        edited = preset;
+                        format!("loaded preset {}", name)
+                    }
+                    Err(err) => format!("failed to load preset {}: {}", name, err),
+                };
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            for preset in &panel.presets {
+                if ui.small_button(preset).clicked() {
+                    panel.preset = preset.clone();
+                }
+            }
+        });
+        if !panel.status.is_empty() {
+            ui.label(&panel.status);
+        }
+    });
+    if edited != *tuning {
+        debug!(target: "debug", "Tuning: {:?}", edited);
+        *tuning = edited;
+    }
+}
+
+/// Number of segments of the circles drawn by the collision overlay.
+const CIRCLE_SEGMENTS: usize = 16;
+
+/// Color of a collision shape in the collision overlay, from its collision group.
+fn collision_color(layers: Option<&CollisionLayers>) -> Color {
+    let layers = if let Some(layers) = layers {
+        layers
+    } else {
+        return Color::WHITE;
+    };
+    if layers.contains_group(Layer::Player) {
+        Color::GREEN
+    } else if layers.contains_group(Layer::PlayerCollect) {
+        Color::LIME_GREEN
+    } else if layers.contains_group(Layer::PlayerBullet) {
+        Color::CYAN
+    } else if layers.contains_group(Layer::Enemy) {
+        Color::RED
+    } else if layers.contains_group(Layer::EnemyBullet) {
+        Color::ORANGE
+    } else if layers.contains_group(Layer::Pickup) {
+        Color::YELLOW
+    } else {
+        Color::GRAY
+    }
+}
+
+/// Draw a closed polyline in world space, for a single frame.
+fn draw_polyline(lines: &mut DebugLines, points: impl Iterator<Item = Vec3>, color: Color) {
+    let points: Vec<Vec3> = points.collect();
+    for (index, start) in points.iter().enumerate() {
+        let end = points[(index + 1) % points.len()];
+        lines.line_colored(*start, end, 0., color);
+    }
+}
+
+/// Points of a circle in the XY plane.
+fn circle(center: Vec3, radius: f32) -> impl Iterator<Item = Vec3> {
+    (0..CIRCLE_SEGMENTS).map(move |index| {
+        let angle = index as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+        center + Vec3::new(angle.cos(), angle.sin(), 0.) * radius
+    })
+}
+
+/// Overlay of the collision shapes of all the bodies, toggled with F12. Enemy bullets
+/// are not physics bodies, so their hitbox is drawn from [`ENEMY_BULLET_RADIUS`].
+fn draw_collision_shapes(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut visible: Local<bool>,
+    mut lines: ResMut<DebugLines>,
+    query: Query<(&GlobalTransform, &CollisionShape, Option<&CollisionLayers>)>,
+    q_bullets: Query<&GlobalTransform, (With<EnemyBullet>, Without<CollisionShape>)>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F12) {
+        *visible = !*visible;
+    }
+    if !*visible {
+        return;
+    }
+
+    for (transform, shape, layers) in query.iter() {
+        let color = collision_color(layers);
+        // Physics ignores the scale of the bodies
+        let to_world = |point: Vec3| transform.translation + transform.rotation * point;
+        match shape {
+            CollisionShape::Sphere { radius } => {
+                draw_polyline(&mut lines, circle(Vec3::ZERO, *radius).map(to_world), color);
+            }
+            CollisionShape::Capsule {
+                half_segment,
+                radius,
+            } => {
+                // Two half circles along the local Y axis, joined by straight sides
+                let half = CIRCLE_SEGMENTS / 2;
+                let top = circle(Vec3::Y * *half_segment, *radius).take(half + 1);
+                let bottom = circle(-Vec3::Y * *half_segment, *radius).skip(half);
+                let bottom = bottom.chain(std::iter::once(
+                    -Vec3::Y * *half_segment + Vec3::X * *radius,
+                ));
+                draw_polyline(&mut lines, top.chain(bottom).map(to_world), color);
+            }
+            CollisionShape::Cuboid { half_extends, .. } => {
+                let corners = [
+                    Vec3::new(-half_extends.x, -half_extends.y, 0.),
+                    Vec3::new(half_extends.x, -half_extends.y, 0.),
+                    Vec3::new(half_extends.x, half_extends.y, 0.),
+                    Vec3::new(-half_extends.x, half_extends.y, 0.),
+                ];
+                draw_polyline(&mut lines, corners.into_iter().map(to_world), color);
+            }
+            CollisionShape::ConvexHull { points, .. } => {
+                draw_polyline(&mut lines, points.iter().copied().map(to_world), color);
+            }
+            // Height fields and custom shapes are not used by the game
+            _ => {}
+        }
+    }
+
+    let color = collision_color(Some(
+        &CollisionLayers::none().with_group(Layer::EnemyBullet),
+    ));
+    for transform in q_bullets.iter() {
+        draw_polyline(
+            &mut lines,
+            circle(transform.translation, ENEMY_BULLET_RADIUS),
+            color,
+        );
+    }
+}
+
+/// Density heatmap of enemy bullets accumulated over the duration of a pattern.
+///
+/// Toggle recording with F9; stopping the recording exports the heatmap as a PNG
+/// image into the `heatmaps/` folder, to help verify safe lanes of authored fire tags.
+struct PatternHeatmap {
+    /// Is the heatmap currently accumulating bullet positions?
+    recording: bool,
+    /// World-space area covered by the grid, captured when recording starts.
+    bounds: Rect<f32>,
+    /// Grid resolution, in cells.
+    width: usize,
+    height: usize,
+    /// Number of bullet samples per cell.
+    cells: Vec<u32>,
+    /// Number of frames accumulated.
+    frame_count: u32,
+}
+
+impl Default for PatternHeatmap {
+    fn default() -> Self {
+        PatternHeatmap {
+            recording: false,
+            bounds: Rect::<f32> {
+                left: -3.49,
+                right: 3.49,
+                bottom: -1.96,
+                top: 1.96,
+            },
+            width: 160,
+            height: 90,
+            cells: vec![],
+            frame_count: 0,
+        }
+    }
+}
+
+impl PatternHeatmap {
+    fn start(&mut self, bounds: Rect<f32>) {
+        self.recording = true;
+        self.bounds = bounds;
+        self.cells = vec![0; self.width * self.height];
+        self.frame_count = 0;
+    }
+
+    fn accumulate(&mut self, position: Vec3) {
+        let u = (position.x - self.bounds.left) / (self.bounds.right - self.bounds.left);
+        let v = (self.bounds.top - position.y) / (self.bounds.top - self.bounds.bottom);
+        if !(0. ..1.).contains(&u) || !(0. ..1.).contains(&v) {
+            return;
+        }
+        let x = (u * self.width as f32) as usize;
+        let y = (v * self.height as f32) as usize;
+        self.cells[y * self.width + x] += 1;
+    }
+
+    /// Map a normalized density in [0:1] to a black-blue-red-yellow-white ramp.
+    fn color_ramp(t: f32) -> [u8; 3] {
+        const RAMP: [[f32; 3]; 5] = [
+            [0., 0., 0.],
+            [0., 0., 1.],
+            [1., 0., 0.],
+            [1., 1., 0.],
+            [1., 1., 1.],
+        ];
+        let t = t.clamp(0., 1.) * (RAMP.len() - 1) as f32;
+        let i = (t.floor() as usize).min(RAMP.len() - 2);
+        let f = t - i as f32;
+        let mut rgb = [0; 3];
+        for c in 0..3 {
+            let value = RAMP[i][c] + (RAMP[i + 1][c] - RAMP[i][c]) * f;
+            rgb[c] = (value * 255.) as u8;
+        }
+        rgb
+    }
+
+    fn export(&self, path: &str) -> image::ImageResult<()> {
+        let max = self.cells.iter().copied().max().unwrap_or(0).max(1) as f32;
+        let img = image::RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let count = self.cells[y as usize * self.width + x as usize] as f32;
+            // Square root to keep sparse lanes visible next to dense spiral arms
+            image::Rgb(Self::color_ramp((count / max).sqrt()))
+        });
+        img.save(path)
+    }
+}
+
+fn record_pattern_heatmap(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut heatmap: ResMut<PatternHeatmap>,
+    q_bullets: Query<&Transform, With<EnemyBullet>>,
+    q_camera: Query<&MainCamera>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        if heatmap.recording {
+            heatmap.recording = false;
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = format!("heatmaps/heatmap_{}.png", timestamp);
+            let result = std::fs::create_dir_all("heatmaps")
+                .map_err(image::ImageError::IoError)
+                .and_then(|_| heatmap.export(&path));
+            match result {
+                Ok(_) => info!(
+                    target: "debug",
+                    "Exported pattern heatmap ({} frames) to '{}'",
+                    heatmap.frame_count, path
+                ),
+                Err(err) => {
+                    warn!(
+                        target: "debug",
+                        "Failed to export pattern heatmap to '{}': {}",
+                        path, err
+                    )
+                }
+            }
+        } else {
+            let bounds = if q_camera.is_empty() {
+                heatmap.bounds
+            } else {
+                q_camera.single().screen_bounds
+            };
+            heatmap.start(bounds);
+            info!(target: "debug", "Recording pattern heatmap...");
+        }
+    }
+
+    if !heatmap.recording {
+        return;
+    }
+
+    heatmap.frame_count += 1;
+    for transform in q_bullets.iter() {
+        heatmap.accumulate(transform.translation);
+    }
+}
+
+/// Mouse motion to camera rotation, in radians per pixel.
+const PHOTO_ORBIT_SPEED: f32 = 0.005;
+
+/// Fraction of the camera distance zoomed in per mouse wheel line.
+const PHOTO_ZOOM_SPEED: f32 = 0.1;
+
+/// Photo mode, toggled with Ctrl+P, to capture promotional shots. Freezes the gameplay
+/// and hides the HUD; dragging with the left mouse button orbits the main camera around
+/// the center of the playfield, and the mouse wheel zooms.
+#[derive(Default)]
+struct PhotoMode {
+    active: bool,
+    /// Was the gameplay frozen already when entering photo mode?
+    was_frozen: bool,
+    /// UI nodes hidden by the photo mode, to show again on exit.
+    hidden: Vec<Entity>,
+    yaw: f32,
+    pitch: f32,
+    /// Camera distance from the center of the playfield, and its initial value.
+    distance: f32,
+    base_distance: f32,
+    /// Initial scale of the orthographic projection, if any, to zoom with.
+    base_scale: f32,
+}
+
+fn toggle_photo_mode(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut photo: ResMut<PhotoMode>,
+    mut time_scale: ResMut<TimeScale>,
+    mut q_nodes: Query<(Entity, &mut Visibility), With<Node>>,
+    q_camera: Query<(&CameraBaseTransform, Option<&OrthographicProjection>), With<MainCamera>>,
+) {
+    if !keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl])
+        || !keyboard_input.just_pressed(KeyCode::P)
+    {
+        return;
+    }
+
+    if photo.active {
+        photo.active = false;
+        time_scale.set_frozen(photo.was_frozen);
+        for entity in std::mem::take(&mut photo.hidden) {
+            if let Ok((_, mut visibility)) = q_nodes.get_mut(entity) {
+                visibility.is_visible = true;
+            }
+        }
+        info!(target: "debug", "Photo mode: OFF");
+        return;
+    }
+
+    // Only in game, where there's a main camera to move around
+    let (base_transform, orthographic) = if let Ok(camera) = q_camera.get_single() {
+        camera
+    } else {
+        return;
+    };
+    photo.active = true;
+    photo.was_frozen = time_scale.is_frozen();
+    time_scale.set_frozen(true);
+    photo.hidden.clear();
+    for (entity, mut visibility) in q_nodes.iter_mut() {
+        if visibility.is_visible {
+            visibility.is_visible = false;
+            photo.hidden.push(entity);
+        }
+    }
+    photo.yaw = 0.;
+    photo.pitch = 0.;
+    photo.base_distance = base_transform.0.translation.length();
+    photo.distance = photo.base_distance;
+    photo.base_scale = orthographic.map_or(1., |projection| projection.scale);
+    info!(target: "debug", "Photo mode: ON");
+}
+
+/// Place the main camera of the photo mode. Runs after all the systems moving the
+/// camera, like the screen shake, to override them.
+fn update_photo_camera(
+    mut photo: ResMut<PhotoMode>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut motion_events: EventReader<MouseMotion>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut q_camera: Query<(&mut Transform, Option<&mut OrthographicProjection>), With<MainCamera>>,
+) {
+    if !photo.active {
+        return;
+    }
+
+    if mouse_input.pressed(MouseButton::Left) {
+        for ev in motion_events.iter() {
+            photo.yaw -= ev.delta.x * PHOTO_ORBIT_SPEED;
+            photo.pitch = (photo.pitch - ev.delta.y * PHOTO_ORBIT_SPEED).clamp(-1.4, 1.4);
+        }
+    }
+    for ev in wheel_events.iter() {
+        let lines = match ev.unit {
+            MouseScrollUnit::Line => ev.y,
+            // About the height of a line of text
+            MouseScrollUnit::Pixel => ev.y / 16.,
+        };
+        photo.distance *= 1. - lines * PHOTO_ZOOM_SPEED;
+        photo.distance = photo
+            .distance
+            .clamp(photo.base_distance * 0.1, photo.base_distance * 4.);
+    }
+
+    let (mut transform, orthographic) = if let Ok(camera) = q_camera.get_single_mut() {
+        camera
+    } else {
+        return;
+    };
+    let rotation = Quat::from_euler(EulerRot::YXZ, photo.yaw, photo.pitch, 0.);
+    *transform = Transform::from_translation(rotation * Vec3::Z * photo.distance)
+        .looking_at(Vec3::ZERO, Vec3::Y);
+    if let Some(mut projection) = orthographic {
+        projection.scale = photo.base_scale * photo.distance / photo.base_distance;
+    }
+}
@@ -0,0 +1,345 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    game::{InGameEntity, PlayerAction, PlayerController},
+    music::AudioFacade,
+    AppState,
+};
+
+/// Dialogues between waves, triggered by the enemy timeline, which pauses spawning
+/// until the player dismisses them.
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DialogueState::load())
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(dialogue_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_dialogue),
+            );
+    }
+}
+
+/// Speed at which the text of a line is revealed, in characters per second.
+const CHARS_PER_SECOND: f32 = 40.;
+
+/// Size of the character portraits, in pixels.
+const PORTRAIT_SIZE: f32 = 96.;
+
+const DIALOGUE_BOX_COLOR: Color = Color::rgba(0.02, 0.02, 0.05, 0.8);
+const DIALOGUE_TEXT_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+
+/// Character speaking in dialogues.
+#[derive(Debug, Clone, Deserialize)]
+struct CharacterDescriptor {
+    name: String,
+    /// Path of the portrait image asset, if any. Characters without a portrait are
+    /// shown as a plain square of their color.
+    #[serde(default)]
+    portrait: Option<String>,
+    /// Color of the portrait background and of the character name.
+    color: [u8; 3],
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LineDescriptor {
+    /// Name of the speaking character.
+    character: String,
+    text: String,
+    /// Path of the audio asset played when the line appears, if any.
+    #[serde(default)]
+    sfx: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DialogueDescriptor {
+    /// Name of the dialogue, referenced by the timeline.
+    name: String,
+    lines: Vec<LineDescriptor>,
+}
+
+/// Dialogue scripts, loaded from `assets/dialogue_db.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct DialogueDatabase {
+    characters: Vec<CharacterDescriptor>,
+    dialogues: Vec<DialogueDescriptor>,
+}
+
+impl DialogueDatabase {
+    fn parse() -> serde_json::Result<DialogueDatabase> {
+        serde_json::from_str(include_str!("../assets/dialogue_db.json"))
+    }
+}
+
+/// Error parsing the dialogue database, if any, to report it during boot. The game
+/// still runs without any dialogue in that case.
+pub fn dialogue_database_error() -> Option<String> {
+    DialogueDatabase::parse().err().map(|err| err.to_string())
+}
+
+/// Dialogue being displayed.
+#[derive(Debug, Clone, Copy)]
+struct ActiveDialogue {
+    /// Index of the dialogue in the database.
+    index: usize,
+    /// Index of the line displayed.
+    line: usize,
+    /// Number of characters of the line revealed so far.
+    revealed: f32,
+}
+
+/// Dialogue database, and dialogue being displayed, if any.
+pub struct DialogueState {
+    database: DialogueDatabase,
+    current: Option<ActiveDialogue>,
+}
+
+impl DialogueState {
+    fn load() -> DialogueState {
+        let database = DialogueDatabase::parse().unwrap_or_else(|err| {
+            error!(target: "dialogue", "Failed to parse dialogue database: {}", err);
+            DialogueDatabase::default()
+        });
+        DialogueState {
+            database,
+            current: None,
+        }
+    }
+
+    /// Start the dialogue with the given name. Return `false` if there's no such
+    /// dialogue, in which case there's nothing to wait for.
+    pub fn start(&mut self, name: &str) -> bool {
+        let index = self
+            .database
+            .dialogues
+            .iter()
+            .position(|dialogue| dialogue.name == name && !dialogue.lines.is_empty());
+        if let Some(index) = index {
+            debug!(target: "dialogue", "Start dialogue '{}'", name);
+            self.current = Some(ActiveDialogue {
+                index,
+                line: 0,
+                revealed: 0.,
+            });
+            true
+        } else {
+            debug!(target: "dialogue", "No dialogue '{}', skipping", name);
+            false
+        }
+    }
+
+    /// Is a dialogue being displayed?
+    pub fn is_active(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn line(&self, active: &ActiveDialogue) -> &LineDescriptor {
+        &self.database.dialogues[active.index].lines[active.line]
+    }
+
+    fn character(&self, name: &str) -> Option<&CharacterDescriptor> {
+        self.database
+            .characters
+            .iter()
+            .find(|character| character.name == name)
+    }
+}
+
+/// Root of the dialogue box of the line being displayed.
+#[derive(Component)]
+struct DialogueBox;
+
+/// Text of the line being displayed, revealed progressively.
+#[derive(Component)]
+struct DialogueLine;
+
+fn dialogue_setup(mut dialogue: ResMut<DialogueState>) {
+    dialogue.current = None;
+}
+
+/// Reveal the text of the current line, and advance to the next line when the player
+/// presses the primary fire button. A first press reveals the whole line at once.
+fn update_dialogue(
+    mut commands: Commands,
+    mut dialogue: ResMut<DialogueState>,
+    mut shown_line: Local<Option<(usize, usize)>>,
+    q_player: Query<&ActionState<PlayerAction>, With<PlayerController>>,
+    q_box: Query<Entity, With<DialogueBox>>,
+    mut q_line: Query<&mut Text, With<DialogueLine>>,
+    asset_server: Res<AssetServer>,
+    audio_facade: AudioFacade,
+    time: Res<Time>,
+) {
+    // Input, unless a new line appeared this frame
+    let mut active = dialogue.current;
+    if let Some(active) = &mut active {
+        let line_len = dialogue.line(active).text.chars().count();
+        let is_new_line = *shown_line != Some((active.index, active.line));
+        // Without a player to dismiss it, skip through the dialogue
+        let pressed = q_player.get_single().map_or(true, |action_state| {
+            action_state.just_pressed(PlayerAction::ShootPrimary)
+        });
+        if pressed && !is_new_line {
+            if (active.revealed as usize) < line_len {
+                active.revealed = line_len as f32;
+            } else {
+                active.line += 1;
+                active.revealed = 0.;
+            }
+        } else {
+            active.revealed =
+                (active.revealed + time.delta_seconds() * CHARS_PER_SECOND).min(line_len as f32);
+        }
+    }
+    let active =
+        active.filter(|active| active.line < dialogue.database.dialogues[active.index].lines.len());
+    dialogue.current = active;
+
+    // Respawn the dialogue box on each new line
+    let line_key = active.map(|active| (active.index, active.line));
+    if line_key != *shown_line || (active.is_some() && q_box.is_empty()) {
+        *shown_line = line_key;
+        for entity in q_box.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        if let Some(active) = &active {
+            let line = dialogue.line(active);
+            if let Some(sfx) = &line.sfx {
+                audio_facade.play_voice(asset_server.load(sfx.as_str()));
+            }
+            spawn_dialogue_box(
+                &mut commands,
+                &asset_server,
+                line,
+                dialogue.character(&line.character),
+            );
+        }
+        return;
+    }
+
+    if let Some(active) = &active {
+        let text: String = dialogue
+            .line(active)
+            .text
+            .chars()
+            .take(active.revealed as usize)
+            .collect();
+        for mut line_text in q_line.iter_mut() {
+            if line_text.sections[0].value != text {
+                line_text.sections[0].value = text.clone();
+            }
+        }
+    }
+}
+
+fn spawn_dialogue_box(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    line: &LineDescriptor,
+    character: Option<&CharacterDescriptor>,
+) {
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    let color = character.map_or(DIALOGUE_TEXT_COLOR, |character| {
+        Color::rgb_u8(character.color[0], character.color[1], character.color[2])
+    });
+    let portrait = character.and_then(|character| character.portrait.as_ref());
+    let portrait_size = Size::new(Val::Px(PORTRAIT_SIZE), Val::Px(PORTRAIT_SIZE));
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(24.),
+                    left: Val::Percent(10.),
+                    right: Val::Percent(10.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(12.)),
+                align_items: AlignItems::FlexStart,
+                ..Default::default()
+            },
+            color: UiColor(DIALOGUE_BOX_COLOR),
+            ..Default::default()
+        })
+        .insert(Name::new("DialogueBox"))
+        .insert(InGameEntity)
+        .insert(DialogueBox)
+        .with_children(|parent| {
+            // Portrait
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: portrait_size,
+                    min_size: portrait_size,
+                    margin: Rect {
+                        right: Val::Px(16.),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                color: UiColor(if portrait.is_some() {
+                    Color::WHITE
+                } else {
+                    color
+                }),
+                image: portrait.map_or_else(Default::default, |path| {
+                    UiImage(asset_server.load(path.as_str()))
+                }),
+                ..Default::default()
+            });
+
+            // Character name, then the line being revealed
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        flex_grow: 1.,
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            line.character.clone(),
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 28.,
+                                color,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                margin: Rect {
+                                    top: Val::Px(8.),
+                                    ..Default::default()
+                                },
+                                max_size: Size::new(Val::Px(800.), Val::Undefined),
+                                ..Default::default()
+                            },
+                            text: Text::with_section(
+                                "",
+                                TextStyle {
+                                    font,
+                                    font_size: 24.,
+                                    color: DIALOGUE_TEXT_COLOR,
+                                },
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(DialogueLine);
+                });
+        });
+}
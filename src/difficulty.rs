@@ -0,0 +1,124 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+
+pub struct DifficultyPlugin;
+
+impl Plugin for DifficultyPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Difficulty>();
+    }
+}
+
+/// Difficulty of the game, selected in the main menu.
+///
+/// Unlike the [`Rank`](crate::rank::Rank), which follows the player performance
+/// during a game, the difficulty is fixed for the entire game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+    Lunatic,
+}
+
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
+    }
+}
+
+/// All difficulties, from the easiest to the hardest.
+const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Easy,
+    Difficulty::Normal,
+    Difficulty::Hard,
+    Difficulty::Lunatic,
+];
+
+impl Difficulty {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Difficulty::Easy => "Easy",
+            Difficulty::Normal => "Normal",
+            Difficulty::Hard => "Hard",
+            Difficulty::Lunatic => "Lunatic",
+        }
+    }
+
+    /// Cycle through the difficulties, wrapping around at both ends.
+    pub fn cycle(&mut self, delta: i32) {
+        let index = DIFFICULTIES.iter().position(|d| d == self).unwrap_or(1) as i32;
+        *self = DIFFICULTIES[(index + delta).rem_euclid(DIFFICULTIES.len() as i32) as usize];
+    }
+
+    /// Default multipliers applied to all enemies at this difficulty.
+    pub fn multipliers(&self) -> DifficultyMultipliers {
+        match self {
+            Difficulty::Easy => DifficultyMultipliers {
+                life: 0.75,
+                bullet_speed: 0.8,
+                fire_delay: 1.3,
+                bullet_count: 0.7,
+            },
+            Difficulty::Normal => DifficultyMultipliers::default(),
+            Difficulty::Hard => DifficultyMultipliers {
+                life: 1.2,
+                bullet_speed: 1.15,
+                fire_delay: 0.85,
+                bullet_count: 1.25,
+            },
+            Difficulty::Lunatic => DifficultyMultipliers {
+                life: 1.5,
+                bullet_speed: 1.3,
+                fire_delay: 0.7,
+                bullet_count: 1.6,
+            },
+        }
+    }
+}
+
+/// Multipliers applied to the stats of an enemy depending on the [`Difficulty`].
+#[derive(Debug, Clone, Copy)]
+pub struct DifficultyMultipliers {
+    pub life: f32,
+    pub bullet_speed: f32,
+    /// Multiplier of the delays between shots. Lower values fire faster.
+    pub fire_delay: f32,
+    pub bullet_count: f32,
+}
+
+impl Default for DifficultyMultipliers {
+    fn default() -> Self {
+        DifficultyMultipliers {
+            life: 1.,
+            bullet_speed: 1.,
+            fire_delay: 1.,
+            bullet_count: 1.,
+        }
+    }
+}
+
+/// Per-enemy override of some of the [`DifficultyMultipliers`] of a difficulty. The
+/// multipliers not overridden keep the default value of the difficulty.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct DifficultyOverride {
+    #[serde(default)]
+    pub life: Option<f32>,
+    #[serde(default)]
+    pub bullet_speed: Option<f32>,
+    #[serde(default)]
+    pub fire_delay: Option<f32>,
+    #[serde(default)]
+    pub bullet_count: Option<f32>,
+}
+
+impl DifficultyOverride {
+    pub fn apply(&self, multipliers: DifficultyMultipliers) -> DifficultyMultipliers {
+        DifficultyMultipliers {
+            life: self.life.unwrap_or(multipliers.life),
+            bullet_speed: self.bullet_speed.unwrap_or(multipliers.bullet_speed),
+            fire_delay: self.fire_delay.unwrap_or(multipliers.fire_delay),
+            bullet_count: self.bullet_count.unwrap_or(multipliers.bullet_count),
+        }
+    }
+}
@@ -2,6 +2,8 @@ use bevy::{
     app::CoreStage,
     asset::AssetStage,
     core::FloatOrd,
+    ecs::system::SystemParam,
+    math::const_vec3,
     pbr::{NotShadowCaster, NotShadowReceiver},
     prelude::*,
     utils::HashMap,
@@ -12,14 +14,43 @@ use rand::{distributions::WeightedIndex, prelude::*};
 use serde::Deserialize;
 use std::{
     f32::consts::{PI, TAU},
+    marker::PhantomData,
     time::Duration,
 };
 
 use crate::{
+    backdrop::SkyTransitionEvent,
+    banner::update_stage_banners,
+    bonus::{PopupKind, ScoreAward},
+    cli::LaunchOptions,
+    collider::ModelCollider,
+    combo::{update_combo, Combo},
+    dialogue::DialogueState,
+    difficulty::{Difficulty, DifficultyMultipliers, DifficultyOverride},
+    event_log::LogEvent,
     game::{
-        DamageEvent, InitLifebarsEvent, LifebarHud, LifebarOrientation, PlayerController,
-        ScoreEvent, ShowLifebarsEvent, UpdateLifebarsEvent,
+        move_enemy_bullets, own_scene_material, roll_run_seed, Bounce, BulletAssets, BulletPalette,
+        BulletThreat, CommonAssets, ContinueEvent, CullMargin, Damage, DamageEvent, DamageOverTime,
+        DamageSource, DebugCheats, EnemyBullet, GameRng, GameTime, HideLifebarsEvent, InGameEntity,
+        InitLifebarsEvent, LastLifeLostEvent, LifebarAnchor, LifebarHud, MainCamera,
+        PendingDespawn, PlayerController, RunSeed, ScoreEvent, ShowLifebarsEvent, StageClearEvent,
+        StageStartEvent, TimeScale, UpdateLifebarsEvent, DEFAULT_CULL_MARGIN, DEFAULT_DAMAGE,
+        ENEMY_BULLET_DAMAGE,
     },
+    hud::BarOrientation,
+    music::{AudioManager, BeatGrid, BgmPlaylist, MusicClock},
+    obstacle::SpawnObstacleEvent,
+    pickup::{DropPickupsEvent, PickupKind},
+    rank::Rank,
+    rumble::RumbleRequest,
+    settings::Settings,
+    sfx::{Sfx, SfxEvent},
+    shake::ScreenShakeEvent,
+    stats::update_run_stats,
+    timestep::{AddFixedEvent, FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    tuning::Tuning,
+    vfx::{ExplosionEvent, ExplosionKind, MuzzleFlashEvent},
+    weather::{WeatherEvent, WeatherParams},
     AppState, Bullet, Layer, Quad,
 };
 
@@ -28,26 +59,105 @@ pub struct EnemyPlugin;
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<EnemyManager>()
+            .init_resource::<PatternCaptures>()
+            .add_event::<CalloutEvent>()
+            .add_event::<BulletCancelEvent>()
+            .add_event::<EnemyKilledEvent>()
+            .add_fixed_event::<DebugEnemyCommand>()
+            .init_resource::<TimelineView>()
+            .init_resource::<PatternPreview>()
+            .add_system(update_threat_materials)
             .add_system_set_to_stage(
                 CoreStage::Update,
-                SystemSet::on_enter(AppState::InGame).with_system(setup_enemy),
+                SystemSet::on_enter(AppState::InGame)
+                    .with_system(setup_enemy.after(roll_run_seed))
+                    .with_system(reset_timeline_view)
+                    .with_system(setup_attack_callout),
             )
             .add_system_set_to_stage(
                 CoreStage::Update,
-                SystemSet::on_update(AppState::InGame).with_system(update_enemy),
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(log_enemy_spawns)
+                    .with_system(detonate_enemies)
+                    .with_system(update_laser_beams)
+                    .with_system(cancel_bullets)
+                    .with_system(despawn_enemies_outside_screen)
+                    .with_system(update_mini_health_bars)
+                    .with_system(setup_enemy_materials)
+                    .with_system(setup_model_materials)
+                    .with_system(start_hit_flashes)
+                    .with_system(
+                        update_hit_flashes
+                            .after(start_hit_flashes)
+                            .after(setup_model_materials),
+                    )
+                    .with_system(update_attack_callout)
+                    .with_system(pause_timeline_for_continue)
+                    .with_system(update_timeline_view),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(apply_debug_commands.before(update_enemy))
+                    .with_system(sync_timeline_to_music.before(update_enemy))
+                    .with_system(update_enemy.before(update_stage_banners))
+                    .with_system(
+                        kill_enemies
+                            .after(update_enemy)
+                            .after(update_combo)
+                            .before(update_stage_banners),
+                    )
+                    .with_system(update_enemy_parts.after(update_enemy))
+                    .with_system(update_formations.after(update_enemy))
+                    .with_system(update_bullet_motions.before(move_enemy_bullets))
+                    .with_system(
+                        update_boss_death
+                            .after(kill_enemies)
+                            .before(update_run_stats)
+                            .before(update_stage_banners),
+                    ),
             );
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
-enum BulletKind {
+pub enum BulletKind {
     #[serde(alias = "pink_donut")]
     PinkDonut,
     #[serde(alias = "white_ball")]
     WhiteBall,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+impl BulletKind {
+    pub const ALL: [BulletKind; 2] = [BulletKind::PinkDonut, BulletKind::WhiteBall];
+
+    /// Path of the bullet texture, packed into the [`BulletAtlas`](crate::game::BulletAtlas).
+    pub fn texture_path(&self) -> &'static str {
+        match self {
+            BulletKind::PinkDonut => "textures/bullet2.png",
+            BulletKind::WhiteBall => "textures/bullet3.png",
+        }
+    }
+
+    /// Size of the bullet quad, in world units.
+    pub fn size(&self) -> f32 {
+        match self {
+            BulletKind::PinkDonut => 0.1,
+            BulletKind::WhiteBall => 0.08,
+        }
+    }
+
+    /// Distance beyond the screen bounds past which the bullet is despawned, in world
+    /// units. See [`CullMargin`].
+    pub fn cull_margin(&self) -> f32 {
+        match self {
+            BulletKind::PinkDonut => 0.2,
+            BulletKind::WhiteBall => 0.15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 enum FireTagKind {
     #[serde(alias = "spiral")]
     Spiral,
@@ -55,6 +165,220 @@ enum FireTagKind {
     DoubleSpiral,
     #[serde(alias = "aim_burst")]
     AimBurst,
+    /// Beam aimed near the player, which telegraphs then sweeps through an angular range.
+    #[serde(alias = "laser")]
+    Laser,
+    /// Waves of rings of bullets expanding from the enemy.
+    #[serde(alias = "ring")]
+    Ring(RingParams),
+    /// Run the child fire tags one after the other, looping back to the first one
+    /// after the last one.
+    #[serde(alias = "sequence")]
+    Sequence(Vec<FireTagStep>),
+    /// Run all the child fire tags at the same time.
+    #[serde(alias = "parallel")]
+    Parallel(Vec<FireTagStep>),
+}
+
+/// Parameters of a [`FireTagKind::Ring`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct RingParams {
+    /// Number of bullets per ring.
+    bullet_count: u32,
+    /// Number of rings per volley.
+    waves: u32,
+    /// Delay between two rings of a volley, in seconds.
+    wave_delay: f32,
+    /// Rotation of each ring relative to the previous one, in degrees. Non-zero values
+    /// produce flower patterns.
+    angle_offset: f32,
+    /// Bullet speed of the first ring, in world units per second.
+    speed: f32,
+    /// Bullet speed added to each ring relative to the previous one.
+    speed_ramp: f32,
+    /// Align the first bullet of each volley with the direction of the player.
+    aim: bool,
+    /// Delay between the last ring of a volley and the first ring of the next one,
+    /// in seconds.
+    volley_delay: f32,
+}
+
+impl Default for RingParams {
+    fn default() -> Self {
+        RingParams {
+            bullet_count: 16,
+            waves: 4,
+            wave_delay: 0.25,
+            angle_offset: 0.,
+            speed: 1.5,
+            speed_ramp: 0.,
+            aim: false,
+            volley_delay: 1.5,
+        }
+    }
+}
+
+/// Parameters of the fire tags of an enemy which don't carry their own, shared by all
+/// its phases and parts. See [`EnemyDescriptor::fire_tag_params`].
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct FireTagParams {
+    /// Parameters of the [`FireTagKind::Spiral`] and [`FireTagKind::DoubleSpiral`].
+    spiral: SpiralParams,
+    /// Parameters of the [`FireTagKind::AimBurst`].
+    aim_burst: AimBurstParams,
+}
+
+/// Parameters of a [`FireTagKind::Spiral`], and of each of the two spirals of a
+/// [`FireTagKind::DoubleSpiral`], the second one rotating the other way.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct SpiralParams {
+    /// Number of arms, evenly spread around the enemy.
+    arms_count: u32,
+    /// Bullet speed, in world units per second.
+    bullet_speed: f32,
+    /// Delay between two volleys, in seconds.
+    fire_delay: f32,
+    /// Rotation speed of the arms, in degrees per second, counter-clockwise.
+    rotate_speed: f32,
+    /// Maximum random deviation of the direction of each bullet, in degrees.
+    angle_jitter: f32,
+    /// Maximum random deviation of the speed of each bullet, in world units per second.
+    speed_jitter: f32,
+    /// Number of volleys of a cycle of the safety gap. The arm aiming closest to the
+    /// player stops firing for the first `gap_length` volleys of each cycle, opening a
+    /// gap to pass through. Zero disables the gap.
+    gap_period: u32,
+    /// Number of volleys the safety gap stays open, at the start of each cycle.
+    gap_length: u32,
+}
+
+impl Default for SpiralParams {
+    fn default() -> Self {
+        SpiralParams {
+            arms_count: 6,
+            bullet_speed: 4.3,
+            fire_delay: 0.04,
+            rotate_speed: 35.,
+            angle_jitter: 0.,
+            speed_jitter: 0.,
+            gap_period: 25,
+            gap_length: 5,
+        }
+    }
+}
+
+/// Parameters of a [`FireTagKind::AimBurst`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+struct AimBurstParams {
+    /// Number of bullets of the burst.
+    bullet_count: u32,
+    /// Bullet speed, in world units per second.
+    bullet_speed: f32,
+    /// Delay between two bullets, in seconds.
+    fire_delay: f32,
+    /// Maximum random deviation of the direction of each bullet, in degrees.
+    angle_jitter: f32,
+    /// Maximum random deviation of the speed of each bullet, in world units per second.
+    speed_jitter: f32,
+}
+
+impl Default for AimBurstParams {
+    fn default() -> Self {
+        AimBurstParams {
+            bullet_count: 6,
+            bullet_speed: 2.1,
+            fire_delay: 0.04,
+            angle_jitter: 0.,
+            speed_jitter: 0.,
+        }
+    }
+}
+
+/// Child fire tag of a [`FireTagKind::Sequence`] or [`FireTagKind::Parallel`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct FireTagStep {
+    fire_tag: FireTagKind,
+    /// Delay before the fire tag starts, in seconds. In a sequence, the delay is
+    /// relative to the end of the previous step.
+    #[serde(default)]
+    delay: f32,
+    /// Duration the fire tag runs for, in seconds. If not set, the fire tag runs
+    /// forever, and any step after it in a sequence never runs.
+    #[serde(default)]
+    duration: Option<f32>,
+    /// Kind of bullets fired, if different from the one of the enemy.
+    #[serde(default)]
+    bullet_kind: Option<BulletKind>,
+    /// Motion of the bullets fired, if they don't just fly straight.
+    #[serde(default)]
+    bullet_motion: Option<BulletMotion>,
+    /// Number of times the bullets fired bounce off the screen edges and the obstacles.
+    #[serde(default)]
+    bounce: Option<u8>,
+    /// Sound played with each volley fired.
+    #[serde(default)]
+    sfx: Option<Sfx>,
+    /// Flash around the enemy with each volley fired.
+    #[serde(default)]
+    muzzle_flash: bool,
+    /// Sound played when the step starts, before its delay, to telegraph the volleys to
+    /// come, like a charge-up whine before a ring burst.
+    #[serde(default)]
+    charge_sfx: Option<Sfx>,
+}
+
+/// Motion of an enemy bullet beyond flying straight at a constant speed, updated by
+/// [`update_bullet_motions()`]. Speeds scale with the rank and difficulty like the
+/// initial bullet speed.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+struct BulletMotion {
+    /// Change of speed, in world units per second squared. Negative values slow the
+    /// bullet down.
+    acceleration: f32,
+    /// Minimum speed the bullet slows down to, in world units per second.
+    min_speed: f32,
+    /// Maximum speed the bullet speeds up to, in world units per second.
+    max_speed: f32,
+    /// Rotation of the bullet direction, in degrees per second, counter-clockwise.
+    curve: f32,
+    /// Delay after which the bullet turns toward the player, once, in seconds.
+    aim_delay: Option<f32>,
+    /// Speed of the bullet once turned toward the player, if different from its
+    /// current speed.
+    aim_speed: Option<f32>,
+    /// Time since the bullet was fired, in seconds.
+    #[serde(skip)]
+    time: f32,
+}
+
+impl Default for BulletMotion {
+    fn default() -> Self {
+        BulletMotion {
+            acceleration: 0.,
+            min_speed: 0.,
+            max_speed: 10.,
+            curve: 0.,
+            aim_delay: None,
+            aim_speed: None,
+            time: 0.,
+        }
+    }
+}
+
+impl BulletMotion {
+    /// Scale all the speeds of the motion, like the bullet speed at fire time.
+    fn scaled(mut self, factor: f32) -> Self {
+        self.acceleration *= factor;
+        self.min_speed *= factor;
+        self.max_speed *= factor;
+        self.aim_speed = self.aim_speed.map(|speed| speed * factor);
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
@@ -63,6 +387,137 @@ enum MotionPatternKind {
     EnterStay,
     #[serde(alias = "fly_by")]
     FlyBy,
+    /// Follow the path given by the timeline event which spawned the enemy.
+    #[serde(alias = "path")]
+    Path,
+    /// Steer toward the player, and self-destruct when close enough.
+    #[serde(alias = "homing")]
+    Homing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum PathCurve {
+    /// Straight lines between consecutive points, at constant speed.
+    #[serde(alias = "linear")]
+    Linear,
+    /// Chain of cubic Bézier curves, where each curve uses the last point of the
+    /// previous one and the 3 next points as control points.
+    #[serde(alias = "bezier")]
+    Bezier,
+}
+
+impl Default for PathCurve {
+    fn default() -> Self {
+        PathCurve::Linear
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum PathEasing {
+    #[serde(alias = "linear")]
+    Linear,
+    #[serde(alias = "ease_in")]
+    EaseIn,
+    #[serde(alias = "ease_out")]
+    EaseOut,
+    #[serde(alias = "ease_in_out")]
+    EaseInOut,
+}
+
+impl Default for PathEasing {
+    fn default() -> Self {
+        PathEasing::Linear
+    }
+}
+
+impl PathEasing {
+    fn ease(&self, t: f32) -> f32 {
+        match self {
+            PathEasing::Linear => t,
+            PathEasing::EaseIn => t * t,
+            PathEasing::EaseOut => t * (2. - t),
+            PathEasing::EaseInOut => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - 2. * (1. - t) * (1. - t)
+                }
+            }
+        }
+    }
+}
+
+/// Path followed by an enemy with the [`MotionPatternKind::Path`] motion.
+#[derive(Debug, Clone, Deserialize)]
+struct PathDescriptor {
+    /// Points of the path, relative to the spawn position of the enemy.
+    points: Vec<Vec3>,
+    #[serde(default)]
+    curve: PathCurve,
+    /// Time to travel the entire path, in seconds.
+    duration: f32,
+    #[serde(default)]
+    easing: PathEasing,
+    /// Fraction of the path after which the enemy starts firing.
+    #[serde(default = "PathDescriptor::default_fire_at")]
+    fire_at: f32,
+}
+
+impl PathDescriptor {
+    fn default_fire_at() -> f32 {
+        0.3
+    }
+
+    /// Position along the path at the given fraction `t` in `[0:1]`, before easing.
+    fn position_at(&self, t: f32) -> Vec3 {
+        let points = &self.points;
+        if points.len() < 2 {
+            return points.first().copied().unwrap_or(Vec3::ZERO);
+        }
+        let t = t.clamp(0., 1.);
+        match self.curve {
+            PathCurve::Linear => {
+                let lengths: Vec<f32> = points.windows(2).map(|w| w[0].distance(w[1])).collect();
+                let mut distance = t * lengths.iter().sum::<f32>();
+                for (index, length) in lengths.iter().enumerate() {
+                    if distance <= *length && *length > 0. {
+                        return points[index].lerp(points[index + 1], distance / length);
+                    }
+                    distance -= length;
+                }
+                *points.last().unwrap()
+            }
+            PathCurve::Bezier => {
+                let count = (points.len() - 1) / 3;
+                if count == 0 {
+                    return points[0].lerp(*points.last().unwrap(), t);
+                }
+                let x = t * count as f32;
+                let index = (x.floor() as usize).min(count - 1);
+                let t = x - index as f32;
+                let p = &points[index * 3..index * 3 + 4];
+                let u = 1. - t;
+                p[0] * (u * u * u)
+                    + p[1] * (3. * u * u * t)
+                    + p[2] * (3. * u * t * t)
+                    + p[3] * (t * t * t)
+            }
+        }
+    }
+}
+
+/// Boss phase, mapped to a single lifebar of the boss.
+#[derive(Debug, Clone, Deserialize)]
+struct PhaseDescriptor {
+    /// Color of the lifebar of this phase.
+    color: [f32; 3],
+    /// Name of the attack, announced when the phase starts firing.
+    #[serde(default)]
+    attack_name: Option<String>,
+    /// Fire tags run at the same time during the phase.
+    fire_tags: Vec<FireTagKind>,
+    motion_pattern_kind: MotionPatternKind,
+    bullet_kind: BulletKind,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -72,9 +527,58 @@ struct EnemyDescriptor {
     #[serde(default)]
     is_boss: bool,
     kill_score: u32,
-    fire_tag_kind: FireTagKind,
+    /// Fire tags run at the same time by the enemy.
+    fire_tags: Vec<FireTagKind>,
+    /// Parameters of the fire tags of the enemy, its phases and its parts, which don't
+    /// carry their own, so that variants of the same pattern can be tuned per enemy.
+    #[serde(default)]
+    fire_tag_params: FireTagParams,
     motion_pattern_kind: MotionPatternKind,
     bullet_kind: BulletKind,
+    /// Boss phases, in order of play, each with its own lifebar. If empty, the enemy
+    /// uses the fire tag, motion pattern and bullet kind above for its entire life.
+    #[serde(default)]
+    phases: Vec<PhaseDescriptor>,
+    /// Path of the GLTF scene rendered for the enemy, if any. Otherwise the enemy is
+    /// rendered as a placeholder cube.
+    #[serde(default)]
+    model: Option<String>,
+    /// Uniform scale of the GLTF scene.
+    #[serde(default = "default_model_scale")]
+    model_scale: f32,
+    /// Rotation of the GLTF scene around the vertical axis, in degrees. Models authored
+    /// facing forward like the player ship need a half turn to face the player.
+    #[serde(default)]
+    model_yaw: f32,
+    /// Radius of the collision sphere of the enemy. If not set, the collision shape is
+    /// derived from the bounds of the GLTF scene, if any.
+    #[serde(default)]
+    collision_radius: Option<f32>,
+    /// Damage dealt to the player ramming into the enemy.
+    #[serde(default = "default_contact_damage")]
+    contact_damage: f32,
+    /// Multipliers applied to the bullet speed, bullet count and fire rate of the fire
+    /// tags of the enemy at the lowest and highest [`Rank`], interpolated in between.
+    #[serde(default = "default_rank_multipliers")]
+    rank_multipliers: (f32, f32),
+    /// Overrides of the default [`DifficultyMultipliers`] of some difficulties, for
+    /// enemies which need a different balance.
+    #[serde(default)]
+    difficulty_overrides: HashMap<Difficulty, DifficultyOverride>,
+    /// Destructible parts of a multi-part boss, like turrets or wings. The enemy itself
+    /// is the core, whose destruction destroys all its remaining parts.
+    #[serde(default)]
+    parts: Vec<PartDescriptor>,
+    /// Pickups dropped when killed, each rolled on its own. If empty, the enemy drops
+    /// random pickups instead.
+    #[serde(default)]
+    drops: Vec<DropDescriptor>,
+    /// Always drop at least one of the `drops`, drawn by their probabilities if none
+    /// was rolled. Such carrier enemies glow, so that the player can tell them apart.
+    #[serde(default)]
+    guaranteed_drop: bool,
+    #[serde(skip)]
+    model_scene: Option<Handle<Scene>>,
     #[serde(skip)]
     enemy_mesh: Handle<Mesh>,
     #[serde(skip)]
@@ -85,800 +589,4290 @@ struct EnemyDescriptor {
     bullet_material: Handle<StandardMaterial>,
 }
 
+/// Destructible part of a multi-part enemy, with its own life and fire tags.
 #[derive(Debug, Clone, Deserialize)]
-struct TimelineEvent {
-    time: f64,
-    enemy: String,
-    start_pos: Vec3,
+struct PartDescriptor {
+    name: String,
+    /// Position of the part relative to its owner, in world units.
+    offset: [f32; 2],
+    life: f32,
+    kill_score: u32,
+    /// Fire tags run at the same time by the part, while its owner is firing.
+    #[serde(default)]
+    fire_tags: Vec<FireTagKind>,
+    bullet_kind: BulletKind,
+    /// Radius of the collision sphere of the part.
+    #[serde(default = "default_part_collision_radius")]
+    collision_radius: f32,
 }
 
-#[derive(Default)]
-struct Timeline {
-    start_time: f64,
-    events: Vec<TimelineEvent>,
-    index: usize,
-    time: f64,
+/// Pickup dropped by an enemy when killed. See [`EnemyDescriptor::drops`].
+#[derive(Debug, Clone, Copy, Deserialize)]
+struct DropDescriptor {
+    kind: PickupKind,
+    /// Probability of the drop, in `[0:1]`.
+    #[serde(default = "default_drop_probability")]
+    probability: f32,
 }
 
-#[derive(Debug, Clone, Deserialize)]
-struct EnemyDatabase {
-    enemies: Vec<EnemyDescriptor>,
-    timeline_delay: f64,
-    timeline: Vec<TimelineEvent>,
+fn default_drop_probability() -> f32 {
+    1.
 }
 
-struct BulletAssets {
-    mesh: Handle<Mesh>,
-    material: Handle<StandardMaterial>,
+fn default_model_scale() -> f32 {
+    1.
 }
 
-struct EnemyManager {
-    mesh: Handle<Mesh>,
-    material: Handle<StandardMaterial>,
-    boss_lifebar_entity: Entity,
-    descriptors: HashMap<String, EnemyDescriptor>,
-    bullet_assets: HashMap<BulletKind, BulletAssets>,
-    timeline: Timeline,
+fn default_part_collision_radius() -> f32 {
+    0.06
 }
 
-impl Default for EnemyManager {
-    fn default() -> Self {
-        EnemyManager {
-            mesh: Handle::default(),
-            material: Handle::default(),
-            boss_lifebar_entity: Entity::from_raw(0),
-            descriptors: HashMap::default(),
-            bullet_assets: HashMap::default(),
-            timeline: Timeline::default(),
-        }
-    }
+fn default_contact_damage() -> f32 {
+    DEFAULT_DAMAGE
 }
 
-impl EnemyManager {
-    fn add_descriptor(&mut self, descriptor: EnemyDescriptor) {
-        self.descriptors.insert(descriptor.name.clone(), descriptor);
+fn default_rank_multipliers() -> (f32, f32) {
+    (1., 1.25)
+}
+
+impl EnemyDescriptor {
+    /// Multipliers of the enemy stats at the given difficulty.
+    fn difficulty_multipliers(&self, difficulty: Difficulty) -> DifficultyMultipliers {
+        let multipliers = difficulty.multipliers();
+        self.difficulty_overrides
+            .get(&difficulty)
+            .map_or(multipliers, |overrides| overrides.apply(multipliers))
     }
 
-    fn execute_timeline(
-        &mut self,
-        dt: f32,
-        commands: &mut Commands,
-        init_events: &mut EventWriter<InitLifebarsEvent>,
-        show_events: &mut EventWriter<ShowLifebarsEvent>,
-    ) {
-        self.timeline.time += dt as f64;
-        for index in self.timeline.index..self.timeline.events.len() {
-            let ev = &self.timeline.events[index];
-            if self.timeline.start_time + ev.time > self.timeline.time {
-                self.timeline.index = index;
-                return;
-            }
-            self.spawn(commands, init_events, show_events, &ev.enemy, ev.start_pos);
+    /// Visit the tweakable parameters of all the fire tags of the enemy, its phases and
+    /// its parts.
+    fn visit_pattern_params(&mut self, visitor: &mut dyn FnMut(String, ParamRef, (f32, f32))) {
+        visit_fire_tag_params(&mut self.fire_tags, "fire_tags", visitor);
+        self.fire_tag_params.visit(visitor);
+        for (index, phase) in self.phases.iter_mut().enumerate() {
+            visit_fire_tag_params(&mut phase.fire_tags, &format!("phases[{}]", index), visitor);
+        }
+        for part in self.parts.iter_mut() {
+            visit_fire_tag_params(&mut part.fire_tags, &part.name.clone(), visitor);
         }
-        self.timeline.index = self.timeline.events.len(); // timeline done
     }
 
-    fn spawn(
-        &self,
-        commands: &mut Commands,
-        init_events: &mut EventWriter<InitLifebarsEvent>,
-        show_events: &mut EventWriter<ShowLifebarsEvent>,
-        desc: &str,
-        position: Vec3,
-    ) {
-        if let Some(desc) = self.descriptors.get(&desc.to_owned()) {
-            let motion_pattern: Box<dyn MotionPattern + Send + Sync> =
-                match &desc.motion_pattern_kind {
-                    MotionPatternKind::EnterStay => {
-                        let mut motion = EnterStayMotion::default();
-                        motion.enter_height = position.y;
-                        Box::new(motion)
-                    }
-                    MotionPatternKind::FlyBy => {
-                        let mut motion = FlyByMotion::default();
-                        motion.start = position;
-                        motion.direction = if position.y > 0. {
-                            Vec3::new(-1., 0.25, 0.)
-                        } else {
-                            Vec3::new(-1., -0.25, 0.)
-                        };
-                        Box::new(motion)
-                    }
+    /// Current values of the tweakable parameters of the fire tags of the enemy.
+    fn pattern_params(&self) -> Vec<PatternParam> {
+        let mut params = vec![];
+        self.clone()
+            .visit_pattern_params(&mut |name, value, range| {
+                let (value, integer) = match value {
+                    ParamRef::Float(value) => (*value, false),
+                    ParamRef::Count(value) => (*value as f32, true),
                 };
-            let bullet_assets = self.bullet_assets.get(&desc.bullet_kind).unwrap();
-            let fire_tag: Box<dyn FireTag + Send + Sync> = match &desc.fire_tag_kind {
-                FireTagKind::Spiral => {
-                    let mut fire_tag = FireTagSpiral::default();
-                    fire_tag.bullet_mesh = bullet_assets.mesh.clone();
-                    fire_tag.bullet_material = bullet_assets.material.clone();
-                    Box::new(fire_tag)
-                }
-                FireTagKind::DoubleSpiral => {
-                    let mut fire_tag = FireTagDoubleSpiral::default();
-                    fire_tag.spiral1.bullet_mesh = bullet_assets.mesh.clone();
-                    fire_tag.spiral1.bullet_material = bullet_assets.material.clone();
-                    fire_tag.spiral2.bullet_mesh = bullet_assets.mesh.clone();
-                    fire_tag.spiral2.bullet_material = bullet_assets.material.clone();
-                    Box::new(fire_tag)
-                }
-                FireTagKind::AimBurst => {
-                    let mut fire_tag = FireTagAimBurst::default();
-                    fire_tag.bullet_mesh = bullet_assets.mesh.clone();
-                    fire_tag.bullet_material = bullet_assets.material.clone();
-                    Box::new(fire_tag)
-                }
-            };
+                params.push(PatternParam {
+                    name,
+                    value,
+                    range,
+                    integer,
+                });
+            });
+        params
+    }
 
-            let mut enemy_controller = EnemyController::default();
-            enemy_controller.motion_pattern = Some(motion_pattern);
-            enemy_controller.fire_tag = Some(fire_tag);
-            enemy_controller.life = desc.life;
-            enemy_controller.remain_life = desc.life;
-            enemy_controller.is_boss = desc.is_boss;
-            enemy_controller.kill_score = desc.kill_score;
+    /// Overwrite the tweakable parameters of the fire tags of the enemy with the given
+    /// values, in the order of [`EnemyDescriptor::pattern_params()`].
+    fn apply_pattern_params(&mut self, params: &[PatternParam]) {
+        let mut params = params.iter();
+        self.visit_pattern_params(&mut |_, value, _| {
+            if let Some(param) = params.next() {
+                match value {
+                    ParamRef::Float(value) => *value = param.value,
+                    ParamRef::Count(value) => *value = param.value.round().max(0.) as u32,
+                }
+            }
+        });
+    }
+}
 
-            let entity = commands
-                .spawn_bundle(PbrBundle {
-                    mesh: self.mesh.clone(),
-                    material: self.material.clone(),
-                    transform: Transform::from_translation(position),
-                    ..Default::default()
-                })
-                .insert(Name::new(desc.name.clone()))
-                .insert(enemy_controller)
-                .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
-                // Physics
-                .insert(RigidBody::KinematicPositionBased)
-                .insert(CollisionShape::Sphere { radius: 0.1 })
-                //.insert(Velocity::from_linear(Vec3::X * 5.))
-                //.insert(RotationConstraints::lock())
-                .insert(
-                    CollisionLayers::none()
-                        .with_group(Layer::Enemy)
-                        .with_masks(&[Layer::World, Layer::Player, Layer::PlayerBullet]),
-                )
-                .id();
+/// Wave of enemies of the same type, spawned one after the other.
+#[derive(Debug, Clone, Deserialize)]
+struct WaveDescriptor {
+    enemy: String,
+    /// Number of enemies in the wave.
+    count: u32,
+    /// Spawn position of the first enemy.
+    start_pos: Vec3,
+    /// Offset of the spawn position of each enemy from the previous one.
+    #[serde(default)]
+    offset: Vec3,
+    /// Delay between the spawn of two consecutive enemies, in seconds.
+    #[serde(default)]
+    interval: f64,
+    /// Path followed by each enemy, relative to its own spawn position.
+    #[serde(default)]
+    path: Option<PathDescriptor>,
+}
 
-            if desc.is_boss {
-                init_events.send(InitLifebarsEvent {
-                    entity: self.boss_lifebar_entity,
-                    colors: vec![Color::RED, Color::ORANGE, Color::YELLOW],
-                    life_per_bar: desc.life / 3.,
-                });
-                show_events.send(ShowLifebarsEvent {
-                    entity: self.boss_lifebar_entity,
-                    play_audio: false,
-                });
-            }
+/// Layout of the followers of a formation around its leader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum FormationLayout {
+    /// Followers trailing behind the leader on both sides, in rows of two.
+    #[serde(alias = "v")]
+    V,
+    /// Followers trailing behind the leader in single file.
+    #[serde(alias = "line")]
+    Line,
+    /// Followers evenly spaced on a circle around the leader.
+    #[serde(alias = "circle")]
+    Circle,
+}
 
-            println!("SPAWNED ENEMY {:?} @ {:?}", entity, position);
-        } else {
-            println!("Failed to spawn unknown enemy type '{}'", desc);
-        }
+impl FormationLayout {
+    /// Offsets of the given number of followers from the leader, with the given
+    /// spacing between neighbors.
+    fn offsets(&self, count: u32, spacing: f32) -> Vec<Vec3> {
+        (0..count)
+            .map(|index| match self {
+                FormationLayout::V => {
+                    let row = (index / 2 + 1) as f32;
+                    let side = if index % 2 == 0 { 1. } else { -1. };
+                    Vec3::new(row, side * row, 0.) * spacing
+                }
+                FormationLayout::Line => Vec3::X * (index + 1) as f32 * spacing,
+                FormationLayout::Circle => {
+                    let angle = TAU * index as f32 / count as f32;
+                    Vec3::new(angle.cos(), angle.sin(), 0.) * spacing
+                }
+            })
+            .collect()
     }
 }
 
-struct FireTagContext<'w, 's, 'ctx> {
-    dt: f32,
-    origin: Vec3,
-    player_position: Vec3,
-    commands: &'ctx mut Commands<'w, 's>,
+/// Formation of enemies following a leader, and scattering once the leader is gone.
+/// See [`FormationMember`].
+#[derive(Debug, Clone, Deserialize)]
+struct FormationDescriptor {
+    /// Enemy leading the formation.
+    leader: String,
+    /// Enemy of the followers, the same as the leader if not set.
+    #[serde(default)]
+    follower: Option<String>,
+    /// Spawn position of the leader.
+    start_pos: Vec3,
+    layout: FormationLayout,
+    /// Number of followers.
+    count: u32,
+    /// Distance between neighbor enemies of the formation, in world units.
+    #[serde(default = "default_formation_spacing")]
+    spacing: f32,
+    /// Path followed by the leader, if it uses the [`MotionPatternKind::Path`] motion.
+    #[serde(default)]
+    path: Option<PathDescriptor>,
 }
 
-impl<'w, 's, 'ctx> FireTagContext<'w, 's, 'ctx> {
-    fn new(
-        dt: f32,
-        origin: Vec3,
-        player_position: Vec3,
-        commands: &'ctx mut Commands<'w, 's>,
-    ) -> Self {
-        FireTagContext {
-            dt,
-            origin,
-            player_position,
-            commands,
+fn default_formation_spacing() -> f32 {
+    0.3
+}
+
+/// Transition of the sky to another time of day. See [`SkyTransitionEvent`].
+#[derive(Debug, Clone, Deserialize)]
+struct SkyDescriptor {
+    /// Time of day to reach, from 0 at sunrise to 1 at sunset.
+    time_of_day: f32,
+    /// Duration of the transition, in seconds.
+    duration: f32,
+}
+
+/// Obstacle drifting through the play area. See [`SpawnObstacleEvent`].
+#[derive(Debug, Clone, Deserialize)]
+struct ObstacleDescriptor {
+    /// Vertical position of the obstacle.
+    y: f32,
+    /// Radius of the obstacle.
+    radius: f32,
+}
+
+/// Mid-boss pausing the timeline until killed or retreating. See
+/// [`TimelineEntry::MidBoss`].
+#[derive(Debug, Clone, Deserialize)]
+struct MidBossDescriptor {
+    enemy: String,
+    start_pos: Vec3,
+    /// Time after which the mid-boss retreats if still alive, in seconds.
+    retreat_after: f32,
+}
+
+/// Section of the timeline played several times in a row.
+#[derive(Debug, Clone, Deserialize)]
+struct RepeatDescriptor {
+    /// Number of times the section is played. If omitted, the section repeats until
+    /// the end of the run, that is until the last boss spawns.
+    #[serde(default)]
+    count: Option<u32>,
+    /// Delay between the start of two consecutive repetitions, in seconds.
+    period: f64,
+    /// Entries of the section, with times relative to the start of each repetition.
+    entries: Vec<TimelineEntry>,
+}
+
+/// Time of a timeline entry, in seconds, or in beats or bars of the music of the stage,
+/// like `4.5`, `{ "beat": 18 }` or `{ "bar": 4.5 }`. Musical times are converted into
+/// seconds with the tempo of the music, then the event waits for the next beat or bar
+/// of the music playing before triggering, to stay in sync with the soundtrack even
+/// after the timeline paused.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum TimelineTime {
+    Seconds(f64),
+    Beats { beat: f64 },
+    Bars { bar: f64 },
+}
+
+impl TimelineTime {
+    fn seconds(&self, grid: &BeatGrid) -> f64 {
+        match *self {
+            TimelineTime::Seconds(seconds) => seconds,
+            TimelineTime::Beats { beat } => grid.beats_to_seconds(beat),
+            TimelineTime::Bars { bar } => grid.bars_to_seconds(bar),
         }
     }
 
-    fn fire(
-        &mut self,
-        rot: Quat,
-        speed: f32,
-        mesh: Handle<Mesh>,
-        material: Handle<StandardMaterial>,
-    ) {
-        // println!(
-        //     "FIRE: origin={:?} angle={} speed={}",
-        //     self.origin, angle, speed
-        // );
-        self.commands
-            .spawn_bundle(PbrBundle {
-                mesh,
-                material,
-                transform: Transform::from_rotation(rot).with_translation(self.origin),
-                ..Default::default()
-            })
-            .insert(Bullet(Vec3::X * speed))
-            // Rendering
-            .insert(NotShadowCaster)
-            .insert(NotShadowReceiver)
-            // Physics
-            .insert(RigidBody::Dynamic) // TODO - or Dynamic?
-            .insert(CollisionShape::Sphere { radius: 0.1 })
-            .insert(Velocity::from_linear(rot.mul_vec3(Vec3::X * speed)))
-            .insert(RotationConstraints::lock())
-            .insert(
-                CollisionLayers::none()
-                    .with_group(Layer::EnemyBullet)
-                    .with_masks(&[Layer::World, Layer::Player]),
-            );
+    fn sync(&self) -> Option<BeatSync> {
+        match self {
+            TimelineTime::Seconds(_) => None,
+            TimelineTime::Beats { .. } => Some(BeatSync::Beat),
+            TimelineTime::Bars { .. } => Some(BeatSync::Bar),
+        }
     }
 }
 
-trait FireTag {
-    fn execute(&mut self, context: &mut FireTagContext);
+/// Musical unit a timeline event waits for before triggering. See [`TimelineTime`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BeatSync {
+    Beat,
+    Bar,
 }
 
-struct FireTagSpiral {
-    arms_count: i32,
-    bullet_speed: f32,
-    fire_delay: f32,
-    rotate_speed: f32,
-    bullet_mesh: Handle<Mesh>,
-    bullet_material: Handle<StandardMaterial>,
-    //
-    cur_time: f32,
-    cur_angle: f32,
-    cur_iter: i32,
+impl BeatSync {
+    /// Length of the unit, in beats.
+    fn beats(&self, grid: &BeatGrid) -> f64 {
+        match self {
+            BeatSync::Beat => 1.,
+            BeatSync::Bar => grid.beats_per_bar.max(1) as f64,
+        }
+    }
 }
 
-impl Default for FireTagSpiral {
-    fn default() -> Self {
-        FireTagSpiral {
-            arms_count: 6,
-            bullet_speed: 4.3,
-            fire_delay: 0.04,
-            rotate_speed: 35_f32.to_radians(),
-            bullet_mesh: Handle::default(),
-            bullet_material: Handle::default(),
-            //
-            cur_time: 0.,
-            cur_angle: 0.,
-            cur_iter: 0,
+/// Entry of the timeline, as authored in the enemy database.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum TimelineEntry {
+    Wave {
+        time: TimelineTime,
+        wave: WaveDescriptor,
+    },
+    Repeat {
+        time: TimelineTime,
+        repeat: RepeatDescriptor,
+    },
+    /// Pause the timeline until all enemies are dead or gone. The time of the entries
+    /// which follow is unaffected, so they're delayed by the time spent waiting.
+    WaitClear {
+        time: TimelineTime,
+        wait_clear: bool,
+    },
+    /// Pause the timeline until the dialogue with the given name, from the dialogue
+    /// database, is dismissed by the player.
+    Dialogue {
+        time: TimelineTime,
+        dialogue: String,
+    },
+    /// Spawn an obstacle beyond the right edge of the screen.
+    Obstacle {
+        time: TimelineTime,
+        obstacle: ObstacleDescriptor,
+    },
+    /// Spawn a mid-boss, and pause the timeline until it's killed or retreats. Enemies
+    /// already spawned keep going meanwhile.
+    MidBoss {
+        time: TimelineTime,
+        mid_boss: MidBossDescriptor,
+    },
+    /// Move the sun to another time of day, like a sunset for the boss fight.
+    Sky {
+        time: TimelineTime,
+        sky: SkyDescriptor,
+    },
+    /// Change the weather, like a storm rolling in.
+    Weather {
+        time: TimelineTime,
+        weather: WeatherParams,
+    },
+    /// Spawn a leader and its followers in formation.
+    Formation {
+        time: TimelineTime,
+        formation: FormationDescriptor,
+    },
+    Spawn {
+        time: TimelineTime,
+        enemy: String,
+        start_pos: Vec3,
+        /// Path followed by the enemy, if it uses the [`MotionPatternKind::Path`] motion.
+        #[serde(default)]
+        path: Option<PathDescriptor>,
+    },
+}
+
+impl TimelineEntry {
+    fn time(&self) -> TimelineTime {
+        match self {
+            TimelineEntry::Wave { time, .. }
+            | TimelineEntry::Repeat { time, .. }
+            | TimelineEntry::WaitClear { time, .. }
+            | TimelineEntry::Dialogue { time, .. }
+            | TimelineEntry::Obstacle { time, .. }
+            | TimelineEntry::MidBoss { time, .. }
+            | TimelineEntry::Sky { time, .. }
+            | TimelineEntry::Weather { time, .. }
+            | TimelineEntry::Formation { time, .. }
+            | TimelineEntry::Spawn { time, .. } => *time,
         }
     }
-}
 
-impl FireTag for FireTagSpiral {
-    fn execute(&mut self, mut context: &mut FireTagContext) {
-        let dt = context.dt;
-        // println!(
-        //     "EXEC: dt={} cur_angle={} cur_iter={}",
-        //     dt, self.cur_angle, self.cur_iter
-        // );
-        self.cur_time += dt;
-        let cone_angle = 30_f32.to_radians(); // need to be >= 60 deg for 6 arms, othewise there's a time gap!
-        if self.cur_time >= self.fire_delay {
-            self.cur_time = 0.; // for safety, run at most once per frame
-            let delta_angle = TAU / self.arms_count as f32;
-            let mut angle = self.cur_angle % TAU;
-            // find the arm with a direction aiming closest to the player
-            // we need to stop firing for a bit always on the same arm, otherwise
-            // it's useless if this is distributed across 2 arms (not enough space
-            // on either of them to safely pass through).
-            let player_angle = PI; // TODO
-            let aim_arm_idx = (0..self.arms_count)
-                .map(|idx| (idx, (angle + delta_angle * idx as f32) % TAU))
-                .min_by(|(idx0, angle0), (id1, angle1)| {
-                    // equality cannot happen since arms are evenly spaced out
-                    if (angle0 - player_angle).abs() <= (angle1 - player_angle).abs() {
-                        std::cmp::Ordering::Less
+    /// Expand the entry into individual timeline events, offset by the given time.
+    /// Musical times are converted into seconds with the given tempo. Repeats without
+    /// a count stop before `end_time`.
+    fn flatten(
+        &self,
+        time_offset: f64,
+        end_time: f64,
+        grid: &BeatGrid,
+        events: &mut Vec<TimelineEvent>,
+    ) {
+        let time = time_offset + self.time().seconds(grid);
+        let sync = self.time().sync();
+        match self {
+            TimelineEntry::Wave { wave, .. } => {
+                for index in 0..wave.count {
+                    let event = TimelineEvent::spawn(
+                        time + wave.interval * index as f64,
+                        &wave.enemy,
+                        wave.start_pos + wave.offset * index as f32,
+                    )
+                    .with_path(wave.path.clone());
+                    // Only the first spawn waits for the beat, the others follow it
+                    // at their own interval
+                    events.push(if index == 0 {
+                        event.with_sync(sync)
                     } else {
-                        std::cmp::Ordering::Greater
+                        event
+                    });
+                }
+            }
+            TimelineEntry::Repeat { repeat, .. } => {
+                for index in 0.. {
+                    let time_offset = time + repeat.period * index as f64;
+                    let done = match repeat.count {
+                        Some(count) => index >= count,
+                        // Guard against a zero period repeating forever
+                        None => time_offset >= end_time || (index > 0 && repeat.period <= 0.),
+                    };
+                    if done {
+                        break;
+                    }
+                    for entry in &repeat.entries {
+                        entry.flatten(time_offset, end_time, grid, events);
                     }
-                })
-                .map(|(idx, _)| idx)
-                .unwrap_or(0);
-            //println!("AIM ARM = #{}", aim_arm_idx);
-            self.cur_iter += 1;
-            // repeat
-            for idx in 0..self.arms_count {
-                // println!(
-                //     "ARM #{}: angle={} min={} max={}",
-                //     idx,
-                //     angle,
-                //     PI - cone_angle,
-                //     PI + cone_angle
-                // );
-                if self.cur_iter % 25 >= 5 || idx != aim_arm_idx {
-                    let rot = Quat::from_rotation_z(angle);
-                    context.fire(
-                        rot,
-                        self.bullet_speed,
-                        self.bullet_mesh.clone(),
-                        self.bullet_material.clone(),
-                    );
                 }
-                // sequence
-                angle = (angle + delta_angle) % TAU;
             }
+            TimelineEntry::WaitClear { wait_clear, .. } => {
+                if *wait_clear {
+                    events.push(TimelineEvent::new(time, TimelineAction::WaitClear));
+                }
+            }
+            TimelineEntry::Dialogue { dialogue, .. } => events.push(
+                TimelineEvent::new(time, TimelineAction::Dialogue(dialogue.clone()))
+                    .with_sync(sync),
+            ),
+            TimelineEntry::Obstacle { obstacle, .. } => events.push(
+                TimelineEvent::new(
+                    time,
+                    TimelineAction::Obstacle {
+                        y: obstacle.y,
+                        radius: obstacle.radius,
+                    },
+                )
+                .with_sync(sync),
+            ),
+            TimelineEntry::MidBoss { mid_boss, .. } => events.push(
+                TimelineEvent::new(
+                    time,
+                    TimelineAction::MidBoss {
+                        enemy: mid_boss.enemy.clone(),
+                        start_pos: mid_boss.start_pos,
+                        retreat_after: mid_boss.retreat_after,
+                    },
+                )
+                .with_sync(sync),
+            ),
+            TimelineEntry::Weather { weather, .. } => events
+                .push(TimelineEvent::new(time, TimelineAction::Weather(*weather)).with_sync(sync)),
+            TimelineEntry::Formation { formation, .. } => events.push(
+                TimelineEvent::new(
+                    time,
+                    TimelineAction::Formation {
+                        leader: formation.leader.clone(),
+                        follower: formation
+                            .follower
+                            .clone()
+                            .unwrap_or_else(|| formation.leader.clone()),
+                        start_pos: formation.start_pos,
+                        offsets: formation.layout.offsets(formation.count, formation.spacing),
+                        path: formation.path.clone(),
+                    },
+                )
+                .with_sync(sync),
+            ),
+            TimelineEntry::Sky { sky, .. } => events.push(
+                TimelineEvent::new(
+                    time,
+                    TimelineAction::Sky {
+                        time_of_day: sky.time_of_day,
+                        duration: sky.duration,
+                    },
+                )
+                .with_sync(sync),
+            ),
+            TimelineEntry::Spawn {
+                enemy,
+                start_pos,
+                path,
+                ..
+            } => events.push(
+                TimelineEvent::spawn(time, enemy, *start_pos)
+                    .with_path(path.clone())
+                    .with_sync(sync),
+            ),
         }
-        // sequence
-        self.cur_angle = (self.cur_angle + self.rotate_speed * dt) % TAU;
     }
 }
 
-struct FireTagDoubleSpiral {
-    spiral1: FireTagSpiral,
-    spiral2: FireTagSpiral,
+#[derive(Debug, Clone)]
+enum TimelineAction {
+    Spawn {
+        enemy: String,
+        start_pos: Vec3,
+        path: Option<PathDescriptor>,
+    },
+    /// Wait until all enemies are dead or gone. See [`TimelineEntry::WaitClear`].
+    WaitClear,
+    /// Wait until a dialogue is dismissed. See [`TimelineEntry::Dialogue`].
+    Dialogue(String),
+    /// Spawn an obstacle. See [`TimelineEntry::Obstacle`].
+    Obstacle { y: f32, radius: f32 },
+    /// Spawn a mid-boss and wait for it. See [`TimelineEntry::MidBoss`].
+    MidBoss {
+        enemy: String,
+        start_pos: Vec3,
+        retreat_after: f32,
+    },
+    /// Start a sky transition. See [`TimelineEntry::Sky`].
+    Sky { time_of_day: f32, duration: f32 },
+    /// Change the weather. See [`TimelineEntry::Weather`].
+    Weather(WeatherParams),
+    /// Spawn a formation. See [`TimelineEntry::Formation`].
+    Formation {
+        leader: String,
+        follower: String,
+        start_pos: Vec3,
+        /// Offset of each follower from the leader.
+        offsets: Vec<Vec3>,
+        path: Option<PathDescriptor>,
+    },
 }
 
-impl Default for FireTagDoubleSpiral {
-    fn default() -> Self {
-        FireTagDoubleSpiral {
-            spiral1: FireTagSpiral::default(),
-            spiral2: FireTagSpiral {
-                rotate_speed: -35_f32.to_radians(),
-                ..Default::default()
+#[derive(Debug, Clone)]
+struct TimelineEvent {
+    time: f64,
+    action: TimelineAction,
+    /// Beat or bar of the music the event waits for once its time is reached, if any.
+    sync: Option<BeatSync>,
+}
+
+impl TimelineEvent {
+    fn new(time: f64, action: TimelineAction) -> Self {
+        TimelineEvent {
+            time,
+            action,
+            sync: None,
+        }
+    }
+
+    fn spawn(time: f64, enemy: &str, start_pos: Vec3) -> Self {
+        TimelineEvent::new(
+            time,
+            TimelineAction::Spawn {
+                enemy: enemy.to_string(),
+                start_pos,
+                path: None,
             },
+        )
+    }
+
+    /// Wait for the next beat or bar of the music before triggering this event.
+    fn with_sync(mut self, sync: Option<BeatSync>) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Set the path followed by the enemy spawned by this event.
+    fn with_path(mut self, path: Option<PathDescriptor>) -> Self {
+        if let TimelineAction::Spawn { path: p, .. } = &mut self.action {
+            *p = path;
         }
+        self
     }
-}
 
-impl FireTag for FireTagDoubleSpiral {
-    fn execute(&mut self, mut context: &mut FireTagContext) {
-        self.spiral1.execute(context);
-        self.spiral2.execute(context);
+    /// Short description of the event, for the debug tools.
+    fn describe(&self) -> String {
+        match &self.action {
+            TimelineAction::Spawn { enemy, .. } => format!("spawn {}", enemy),
+            TimelineAction::WaitClear => "wait clear".to_string(),
+            TimelineAction::Dialogue(name) => format!("dialogue {}", name),
+            TimelineAction::Obstacle { .. } => "obstacle".to_string(),
+            TimelineAction::MidBoss { enemy, .. } => format!("mid-boss {}", enemy),
+            TimelineAction::Sky { time_of_day, .. } => format!("sky {:.2}", time_of_day),
+            TimelineAction::Weather(_) => "weather".to_string(),
+            TimelineAction::Formation {
+                leader, offsets, ..
+            } => format!("formation {}+{}", leader, offsets.len()),
+        }
+    }
+
+    /// Name of the enemy spawned by this event, if any.
+    fn enemy(&self) -> Option<&str> {
+        match &self.action {
+            TimelineAction::Spawn { enemy, .. }
+            | TimelineAction::MidBoss { enemy, .. }
+            | TimelineAction::Formation { leader: enemy, .. } => Some(enemy),
+            TimelineAction::WaitClear
+            | TimelineAction::Dialogue(_)
+            | TimelineAction::Obstacle { .. }
+            | TimelineAction::Sky { .. }
+            | TimelineAction::Weather(_) => None,
+        }
     }
 }
 
-struct FireTagAimBurst {
-    bullet_count: i32,
-    bullet_speed: f32,
-    fire_delay: f32,
-    bullet_mesh: Handle<Mesh>,
-    bullet_material: Handle<StandardMaterial>,
-    //
-    cur_time: f32,
-    cur_iter: i32,
+#[derive(Default)]
+struct Timeline {
+    start_time: f64,
+    events: Vec<TimelineEvent>,
+    index: usize,
+    time: f64,
+    /// Is the timeline paused until all enemies are dead or gone?
+    waiting_clear: bool,
+    /// Is the timeline paused until a dialogue is dismissed?
+    waiting_dialogue: bool,
+    /// Is the timeline paused until the mid-boss is killed or retreats?
+    waiting_mid_boss: bool,
+    /// Is the timeline paused until the player continues after a game over?
+    waiting_continue: bool,
+    /// Is the timeline paused from the debug tools?
+    paused: bool,
+    /// Position in the music of the stage at this step, if playing.
+    music_clock: Option<MusicClock>,
+    /// Beat of the music at the previous step, if it was playing.
+    last_music_beat: Option<f64>,
 }
 
-impl Default for FireTagAimBurst {
-    fn default() -> Self {
-        FireTagAimBurst {
-            bullet_count: 6,
-            bullet_speed: 2.1,
-            fire_delay: 0.04,
-            bullet_mesh: Handle::default(),
-            bullet_material: Handle::default(),
-            //
-            cur_time: 0.,
-            cur_iter: 0,
+impl Timeline {
+    /// Flatten the authored timeline entries into a list of events sorted by time,
+    /// converting the musical times with the given tempo. Repeats without a count
+    /// stop before `end_time`, the end of the run.
+    fn flatten(entries: &[TimelineEntry], end_time: f64, grid: &BeatGrid) -> Vec<TimelineEvent> {
+        let mut events = vec![];
+        for entry in entries {
+            entry.flatten(0., end_time, grid, &mut events);
         }
+        // Stable sort, to keep the authoring order of events with the same time
+        events.sort_by_key(|ev| FloatOrd(ev.time as f32));
+        events
     }
-}
 
-impl FireTag for FireTagAimBurst {
-    fn execute(&mut self, mut context: &mut FireTagContext) {
-        if self.cur_iter < self.bullet_count {
-            let dt = context.dt;
-            // println!(
-            //     "EXEC: dt={} cur_angle={} cur_iter={}",
-            //     dt, self.cur_angle, self.cur_iter
-            // );
-            self.cur_time += dt;
-            if self.cur_time >= self.fire_delay {
-                self.cur_time = 0.; // for safety, run at most once per frame
-                let dir = (context.player_position - context.origin)
-                    .try_normalize()
-                    .unwrap_or(Vec3::X);
-                let rot = Quat::from_rotation_arc(Vec3::X, dir);
-                context.fire(
-                    rot,
-                    self.bullet_speed,
-                    self.bullet_mesh.clone(),
-                    self.bullet_material.clone(),
-                );
-                self.cur_iter += 1;
+    /// Did the music cross the beat or bar an event is synced to since the previous
+    /// step? Events trigger right away when not synced, or without music to sync to.
+    fn is_on_beat(&self, sync: Option<BeatSync>) -> bool {
+        match (sync, self.music_clock, self.last_music_beat) {
+            (Some(sync), Some(clock), Some(last_beat)) => {
+                let unit = sync.beats(&clock.grid);
+                (last_beat / unit).floor() < (clock.beat / unit).floor()
             }
+            _ => true,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum MotionResult {
-    DoNothing,
-    StartFireTag,
+#[derive(Debug, Clone, Default, Deserialize)]
+struct EnemyDatabase {
+    enemies: Vec<EnemyDescriptor>,
+    timeline_delay: f64,
+    timeline: Vec<TimelineEntry>,
 }
 
-trait MotionPattern {
-    fn do_motion(
-        &mut self,
-        dt: f32,
-        transform: &mut Transform,
-        animator: &mut Animator<Transform>,
-    ) -> MotionResult;
+impl EnemyDatabase {
+    fn parse() -> serde_json::Result<EnemyDatabase> {
+        serde_json::from_str(include_str!("../assets/enemy_db.json"))
+    }
 }
 
-enum EnterStayPhase {
-    Idle,
-    Enter,
-    Stay,
+/// Error parsing the enemy database, if any, to report it during boot. The game still
+/// runs without any enemy in that case.
+pub fn enemy_database_error() -> Option<String> {
+    EnemyDatabase::parse().err().map(|err| err.to_string())
 }
 
-struct EnterStayMotion {
-    phase: EnterStayPhase,
-    enter_height: f32,
+struct EnemyManager {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+    health_bar_mesh: Handle<Mesh>,
+    health_bar_material: Handle<StandardMaterial>,
+    laser_mesh: Handle<Mesh>,
+    laser_telegraph_material: Handle<StandardMaterial>,
+    laser_material: Handle<StandardMaterial>,
+    /// Material of the glow around the enemies carrying a guaranteed drop.
+    carrier_glow_material: Handle<StandardMaterial>,
+    /// Material of homing enemies, only if color-coding threats.
+    homing_material: Option<Handle<StandardMaterial>>,
+    descriptors: HashMap<String, EnemyDescriptor>,
+    bullet_assets: HashMap<BulletKind, BulletAssets>,
+    timeline: Timeline,
+    /// Number of stages in the timeline, one per boss.
+    stage_count: usize,
+    /// Number of stages already cleared.
+    stages_cleared: usize,
+    /// Difficulty of the game, fixed when the game starts.
+    difficulty: Difficulty,
+    /// Progress of the death sequence of the current boss.
+    boss_death: BossDeath,
 }
 
-impl Default for EnterStayMotion {
+/// Progress of the death sequence of a boss, which delays the stage clear until the
+/// slow motion ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BossDeath {
+    /// No boss is dying.
+    None,
+    /// A boss was just killed; the sequence starts on this step.
+    Killed,
+    /// The slow motion is playing.
+    SlowMotion,
+}
+
+impl Default for EnemyManager {
     fn default() -> Self {
-        EnterStayMotion {
-            phase: EnterStayPhase::Idle,
-            enter_height: 0.,
+        EnemyManager {
+            mesh: Handle::default(),
+            material: Handle::default(),
+            health_bar_mesh: Handle::default(),
+            health_bar_material: Handle::default(),
+            laser_mesh: Handle::default(),
+            laser_telegraph_material: Handle::default(),
+            laser_material: Handle::default(),
+            carrier_glow_material: Handle::default(),
+            homing_material: None,
+            descriptors: HashMap::default(),
+            bullet_assets: HashMap::default(),
+            timeline: Timeline::default(),
+            stage_count: 0,
+            stages_cleared: 0,
+            difficulty: Difficulty::default(),
+            boss_death: BossDeath::None,
         }
     }
 }
 
-impl MotionPattern for EnterStayMotion {
-    fn do_motion(
+impl EnemyManager {
+    fn add_descriptor(&mut self, descriptor: EnemyDescriptor) {
+        self.descriptors.insert(descriptor.name.clone(), descriptor);
+    }
+
+    /// Move the timeline to the event at the given index, as if all the events before
+    /// it had run, without running them.
+    fn seek(&mut self, index: usize, time: f64) {
+        let index = index.min(self.timeline.events.len());
+        // Keep the stage count in sync with the bosses skipped or replayed
+        let stages_cleared = self.timeline.events[..index]
+            .iter()
+            .filter(|ev| {
+                ev.enemy()
+                    .and_then(|enemy| self.descriptors.get(enemy))
+                    .map_or(false, |desc| desc.is_boss)
+            })
+            .count();
+        let timeline = &mut self.timeline;
+        timeline.index = index;
+        timeline.time = timeline.start_time + time;
+        timeline.waiting_clear = false;
+        timeline.waiting_dialogue = false;
+        timeline.waiting_mid_boss = false;
+        self.stages_cleared = stages_cleared;
+        self.boss_death = BossDeath::None;
+    }
+
+    fn execute_timeline(
         &mut self,
         dt: f32,
-        transform: &mut Transform,
-        animator: &mut Animator<Transform>,
-    ) -> MotionResult {
-        match self.phase {
-            EnterStayPhase::Idle => {
-                self.phase = EnterStayPhase::Enter;
-                transform.translation = Vec3::new(5., self.enter_height, 0.);
+        alive_enemies: usize,
+        engaged_mid_bosses: usize,
+        commands: &mut Commands,
+        init_events: &mut EventWriter<InitLifebarsEvent>,
+        show_events: &mut EventWriter<ShowLifebarsEvent>,
+        timeline_events: &mut TimelineEvents,
+        dialogue: &mut DialogueState,
+    ) {
+        if self.timeline.waiting_continue || self.timeline.paused {
+            return;
+        }
+        if self.timeline.waiting_dialogue {
+            if dialogue.is_active() {
+                return;
+            }
+            debug!(target: "timeline", "TIMELINE: dialogue dismissed, resuming");
+            self.timeline.waiting_dialogue = false;
+        }
+        if self.timeline.waiting_clear {
+            if alive_enemies > 0 {
+                return;
+            }
+            debug!(target: "timeline", "TIMELINE: cleared, resuming");
+            self.timeline.waiting_clear = false;
+        }
+        if self.timeline.waiting_mid_boss {
+            if engaged_mid_bosses > 0 {
+                return;
+            }
+            debug!(target: "timeline", "TIMELINE: mid-boss gone, resuming");
+            self.timeline.waiting_mid_boss = false;
+        }
+
+        self.timeline.time += dt as f64;
+        let mut spawned = false;
+        for index in self.timeline.index..self.timeline.events.len() {
+            let ev = &self.timeline.events[index];
+            let time = self.timeline.start_time + ev.time;
+            if time > self.timeline.time || !self.timeline.is_on_beat(ev.sync) {
+                self.timeline.index = index;
+                return;
+            }
+            match &ev.action {
+                TimelineAction::Spawn {
+                    enemy,
+                    start_pos,
+                    path,
+                } => {
+                    self.spawn(
+                        commands,
+                        init_events,
+                        show_events,
+                        enemy,
+                        *start_pos,
+                        path.as_ref(),
+                        None,
+                    );
+                    spawned = true;
+                }
+                TimelineAction::MidBoss {
+                    enemy,
+                    start_pos,
+                    retreat_after,
+                } => {
+                    self.spawn(
+                        commands,
+                        init_events,
+                        show_events,
+                        enemy,
+                        *start_pos,
+                        None,
+                        Some(*retreat_after),
+                    );
+                    debug!(target: "timeline", "TIMELINE: waiting for mid-boss '{}'", enemy);
+                    self.timeline.index = index + 1;
+                    self.timeline.time = time;
+                    self.timeline.waiting_mid_boss = true;
+                    return;
+                }
+                TimelineAction::Obstacle { y, radius } => {
+                    timeline_events.obstacle.send(SpawnObstacleEvent {
+                        y: *y,
+                        radius: *radius,
+                    });
+                }
+                TimelineAction::Sky {
+                    time_of_day,
+                    duration,
+                } => {
+                    timeline_events.sky.send(SkyTransitionEvent {
+                        time_of_day: *time_of_day,
+                        duration: *duration,
+                    });
+                }
+                TimelineAction::Weather(params) => {
+                    timeline_events.weather.send(WeatherEvent(*params));
+                }
+                TimelineAction::Formation {
+                    leader,
+                    follower,
+                    start_pos,
+                    offsets,
+                    path,
+                } => {
+                    let leader = self.spawn(
+                        commands,
+                        init_events,
+                        show_events,
+                        leader,
+                        *start_pos,
+                        path.as_ref(),
+                        None,
+                    );
+                    if let Some(leader) = leader {
+                        for &offset in offsets {
+                            if let Some(entity) = self.spawn(
+                                commands,
+                                init_events,
+                                show_events,
+                                follower,
+                                *start_pos + offset,
+                                None,
+                                None,
+                            ) {
+                                commands
+                                    .entity(entity)
+                                    .insert(FormationMember { leader, offset });
+                            }
+                        }
+                    }
+                    spawned = true;
+                }
+                TimelineAction::WaitClear => {
+                    // Enemies spawned this frame are not in the world yet, so count
+                    // them as alive
+                    if spawned || alive_enemies > 0 {
+                        debug!(target: "timeline", "TIMELINE: waiting for all enemies to clear");
+                        self.timeline.index = index + 1;
+                        self.timeline.time = time;
+                        self.timeline.waiting_clear = true;
+                        return;
+                    }
+                }
+                TimelineAction::Dialogue(name) => {
+                    if dialogue.start(name) {
+                        debug!(target: "timeline", "TIMELINE: waiting for dialogue '{}'", name);
+                        self.timeline.index = index + 1;
+                        self.timeline.time = time;
+                        self.timeline.waiting_dialogue = true;
+                        return;
+                    }
+                }
+            }
+        }
+        self.timeline.index = self.timeline.events.len(); // timeline done
+    }
+
+    /// Spawn an enemy, and return its entity, or `None` if the enemy is unknown.
+    fn spawn(
+        &self,
+        commands: &mut Commands,
+        init_events: &mut EventWriter<InitLifebarsEvent>,
+        show_events: &mut EventWriter<ShowLifebarsEvent>,
+        desc: &str,
+        position: Vec3,
+        path: Option<&PathDescriptor>,
+        retreat_after: Option<f32>,
+    ) -> Option<Entity> {
+        if let Some(desc) = self.descriptors.get(&desc.to_owned()) {
+            let (fire_tags, motion_pattern_kind, bullet_kind) =
+                if let Some(phase) = desc.phases.first() {
+                    (
+                        &phase.fire_tags,
+                        phase.motion_pattern_kind,
+                        phase.bullet_kind,
+                    )
+                } else {
+                    (&desc.fire_tags, desc.motion_pattern_kind, desc.bullet_kind)
+                };
+            let motion_pattern = create_motion_pattern(motion_pattern_kind, position, 5., path);
+            let fire_tag = self.create_fire_tags(fire_tags, bullet_kind, &desc.fire_tag_params);
+            let difficulty = desc.difficulty_multipliers(self.difficulty);
+            let life = desc.life * difficulty.life;
+
+            let mut enemy_controller = EnemyController::default();
+            enemy_controller.motion_pattern = Some(motion_pattern);
+            enemy_controller.fire_tag = Some(fire_tag);
+            enemy_controller.life = life;
+            enemy_controller.remain_life = life;
+            enemy_controller.is_boss = desc.is_boss;
+            enemy_controller.kill_score = desc.kill_score;
+            enemy_controller.rank_multipliers = desc.rank_multipliers;
+            enemy_controller.difficulty = difficulty;
+            enemy_controller.phases = desc.phases.clone();
+            enemy_controller.fire_tag_params = desc.fire_tag_params.clone();
+            enemy_controller.drops = desc.drops.clone();
+            enemy_controller.guaranteed_drop = desc.guaranteed_drop;
+            // Bosses and mid-bosses own a lifebar, so that several of them can show at once
+            let lifebar = if desc.is_boss || retreat_after.is_some() {
+                // Lifebar colors go from undermost to topmost, so reverse the phases
+                let colors: Vec<Color> = if !desc.phases.is_empty() {
+                    desc.phases
+                        .iter()
+                        .rev()
+                        .map(|phase| Color::rgb(phase.color[0], phase.color[1], phase.color[2]))
+                        .collect()
+                } else if desc.is_boss {
+                    vec![Color::RED, Color::ORANGE, Color::YELLOW]
+                } else {
+                    vec![Color::ORANGE, Color::YELLOW]
+                };
+                let life_per_bar = life / colors.len() as f32;
+                let entity = spawn_boss_lifebar(commands, desc.is_boss, life_per_bar, &colors);
+                Some((entity, colors, life_per_bar))
+            } else {
+                None
+            };
+            enemy_controller.lifebar_entity = lifebar.as_ref().map(|(entity, ..)| *entity);
+
+            let entity = commands
+                .spawn_bundle((
+                    Transform::from_translation(position),
+                    GlobalTransform::identity(),
+                    Visibility::default(),
+                    ComputedVisibility::default(),
+                ))
+                .insert(Name::new(desc.name.clone()))
+                .insert(InGameEntity)
+                .insert(enemy_controller)
+                .insert(Damage(desc.contact_damage))
+                .insert(FixedStep::default())
+                .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
+                // Physics
+                .insert(RigidBody::KinematicPositionBased)
+                .insert(CollisionShape::Sphere {
+                    radius: desc.collision_radius.unwrap_or(0.1),
+                })
+                //.insert(Velocity::from_linear(Vec3::X * 5.))
+                //.insert(RotationConstraints::lock())
+                .insert(
+                    CollisionLayers::none()
+                        .with_group(Layer::Enemy)
+                        .with_masks(&[Layer::World, Layer::Player, Layer::PlayerBullet]),
+                )
+                .id();
+
+            if let (Some(model), Some(scene)) = (&desc.model, &desc.model_scene) {
+                let scale = desc.model_scale;
+                let rotation = Quat::from_rotation_y(desc.model_yaw.to_radians());
+                let mut scene_root = None;
+                commands.entity(entity).with_children(|parent| {
+                    let id = parent
+                        .spawn_bundle((
+                            Transform::from_scale(Vec3::splat(scale)).with_rotation(rotation),
+                            GlobalTransform::identity(),
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn_scene(scene.clone());
+                        })
+                        .id();
+                    scene_root = Some(id);
+                });
+                if let Some(scene_root) = scene_root {
+                    commands.entity(entity).insert(EnemyModel {
+                        scene_root,
+                        materials: vec![],
+                    });
+                    if desc.collision_radius.is_none() {
+                        commands
+                            .entity(entity)
+                            .insert(ModelCollider::new(model, scene_root));
+                    }
+                }
+            } else {
+                let material = match (motion_pattern_kind, &self.homing_material) {
+                    (MotionPatternKind::Homing, Some(material)) => material.clone(),
+                    _ => self.material.clone(),
+                };
+                commands
+                    .entity(entity)
+                    .insert(self.mesh.clone())
+                    .insert(material);
+            }
+
+            if desc.guaranteed_drop {
+                let size = desc.collision_radius.unwrap_or(0.1) * CARRIER_GLOW_SCALE;
                 let tween = Tween::new(
-                    EaseFunction::QuadraticOut,
-                    TweeningType::Once,
-                    Duration::from_secs_f32(5.),
-                    TransformPositionLens {
-                        start: transform.translation,
-                        end: Vec3::new(2., self.enter_height, 0.),
+                    EaseFunction::SineInOut,
+                    TweeningType::PingPong,
+                    Duration::from_secs_f32(CARRIER_GLOW_PERIOD / 2.),
+                    TransformScaleLens {
+                        start: Vec3::splat(size * 0.8),
+                        end: Vec3::splat(size),
                     },
                 );
-                animator.set_tweenable(tween);
-                animator.state = AnimatorState::Playing;
-                MotionResult::DoNothing
+                commands.entity(entity).with_children(|parent| {
+                    parent
+                        .spawn_bundle(PbrBundle {
+                            mesh: self.laser_mesh.clone(),
+                            material: self.carrier_glow_material.clone(),
+                            transform: Transform::from_xyz(0., 0., -0.05)
+                                .with_scale(Vec3::splat(size)),
+                            ..Default::default()
+                        })
+                        .insert(Name::new("CarrierGlow"))
+                        .insert(Animator::new(tween))
+                        .insert(NotShadowCaster)
+                        .insert(NotShadowReceiver);
+                });
             }
-            EnterStayPhase::Enter => {
-                if animator.progress() >= 1. {
-                    self.phase = EnterStayPhase::Stay;
-                    let tween = Tween::new(
-                        EaseFunction::QuadraticInOut,
-                        TweeningType::PingPong,
-                        Duration::from_secs_f32(3.),
-                        TransformPositionLens {
-                            start: transform.translation,
-                            end: transform.translation + Vec3::Y * 0.6,
-                        },
-                    );
-                    animator.set_tweenable(tween);
-                    animator.state = AnimatorState::Playing;
-                    MotionResult::StartFireTag
+
+            if let Some(retreat_after) = retreat_after {
+                commands.entity(entity).insert(MidBoss {
+                    retreat_timer: retreat_after,
+                    retreating: false,
+                });
+            }
+
+            // Bosses and mid-bosses display their life in their lifebar instead
+            if lifebar.is_none() {
+                commands.entity(entity).with_children(|parent| {
+                    parent
+                        .spawn_bundle(PbrBundle {
+                            mesh: self.health_bar_mesh.clone(),
+                            material: self.health_bar_material.clone(),
+                            transform: Transform::from_xyz(0., MINI_HEALTH_BAR_OFFSET, 0.01),
+                            visibility: Visibility { is_visible: false },
+                            ..Default::default()
+                        })
+                        .insert(NotShadowCaster)
+                        .insert(NotShadowReceiver)
+                        .insert(MiniHealthBar);
+                });
+            }
+
+            for part in &desc.parts {
+                self.spawn_part(commands, entity, position, part, desc, difficulty);
+            }
+
+            if let Some((lifebar_entity, colors, life_per_bar)) = lifebar {
+                init_events.send(InitLifebarsEvent {
+                    entity: lifebar_entity,
+                    life_per_bar,
+                    colors,
+                });
+                show_events.send(ShowLifebarsEvent {
+                    entity: lifebar_entity,
+                    play_audio: false,
+                });
+            }
+            Some(entity)
+        } else {
+            warn!(target: "timeline", "Failed to spawn unknown enemy type '{}'", desc);
+            None
+        }
+    }
+
+    /// Spawn a destructible part of the given owner enemy. The part is a separate
+    /// enemy moved along with its owner by [`update_enemy_parts()`].
+    fn spawn_part(
+        &self,
+        commands: &mut Commands,
+        owner: Entity,
+        position: Vec3,
+        part: &PartDescriptor,
+        desc: &EnemyDescriptor,
+        difficulty: DifficultyMultipliers,
+    ) {
+        let offset = Vec3::new(part.offset[0], part.offset[1], 0.);
+        let life = part.life * difficulty.life;
+
+        let mut enemy_controller = EnemyController::default();
+        enemy_controller.fire_tag =
+            Some(self.create_fire_tags(&part.fire_tags, part.bullet_kind, &desc.fire_tag_params));
+        enemy_controller.life = life;
+        enemy_controller.remain_life = life;
+        enemy_controller.kill_score = part.kill_score;
+        enemy_controller.rank_multipliers = desc.rank_multipliers;
+        enemy_controller.difficulty = difficulty;
+
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: self.mesh.clone(),
+                material: self.material.clone(),
+                transform: Transform::from_translation(position + offset),
+                ..Default::default()
+            })
+            .insert(Name::new(format!("{}/{}", desc.name, part.name)))
+            .insert(InGameEntity)
+            .insert(enemy_controller)
+            .insert(EnemyPart { owner, offset })
+            .insert(Damage(desc.contact_damage))
+            .insert(FixedStep::default())
+            // Unused, but required by update_enemy()
+            .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
+            // Physics
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(CollisionShape::Sphere {
+                radius: part.collision_radius,
+            })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layer::Enemy)
+                    .with_masks(&[Layer::World, Layer::Player, Layer::PlayerBullet]),
+            )
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh: self.health_bar_mesh.clone(),
+                        material: self.health_bar_material.clone(),
+                        transform: Transform::from_xyz(0., MINI_HEALTH_BAR_OFFSET, 0.01),
+                        visibility: Visibility { is_visible: false },
+                        ..Default::default()
+                    })
+                    .insert(NotShadowCaster)
+                    .insert(NotShadowReceiver)
+                    .insert(MiniHealthBar);
+            });
+    }
+
+    /// Create the fire tag running all the given fire tags at the same time.
+    fn create_fire_tags(
+        &self,
+        kinds: &[FireTagKind],
+        bullet_kind: BulletKind,
+        params: &FireTagParams,
+    ) -> Box<dyn FireTag + Send + Sync> {
+        if kinds.len() == 1 {
+            return self.create_fire_tag(&kinds[0], bullet_kind, params);
+        }
+        let children = kinds
+            .iter()
+            .map(|kind| {
+                FireTagChild::new(self.create_fire_tag(kind, bullet_kind, params), 0., None)
+            })
+            .collect();
+        Box::new(FireTagParallel { children })
+    }
+
+    fn create_fire_tag(
+        &self,
+        kind: &FireTagKind,
+        bullet_kind: BulletKind,
+        params: &FireTagParams,
+    ) -> Box<dyn FireTag + Send + Sync> {
+        let bullet_assets = self.bullet_assets.get(&bullet_kind).unwrap();
+        match kind {
+            FireTagKind::Spiral => {
+                let mut fire_tag = FireTagSpiral::new(params.spiral.clone());
+                fire_tag.bullet_mesh = bullet_assets.mesh.clone();
+                fire_tag.bullet_material = bullet_assets.material(BulletThreat::Pattern);
+                fire_tag.bullet_cull_margin = bullet_assets.cull_margin;
+                Box::new(fire_tag)
+            }
+            FireTagKind::DoubleSpiral => {
+                let mut fire_tag = FireTagDoubleSpiral::new(&params.spiral);
+                let material = bullet_assets.material(BulletThreat::Pattern);
+                fire_tag.spiral1.bullet_mesh = bullet_assets.mesh.clone();
+                fire_tag.spiral1.bullet_material = material.clone();
+                fire_tag.spiral1.bullet_cull_margin = bullet_assets.cull_margin;
+                fire_tag.spiral2.bullet_mesh = bullet_assets.mesh.clone();
+                fire_tag.spiral2.bullet_material = material;
+                fire_tag.spiral2.bullet_cull_margin = bullet_assets.cull_margin;
+                Box::new(fire_tag)
+            }
+            FireTagKind::AimBurst => {
+                let mut fire_tag = FireTagAimBurst::new(params.aim_burst.clone());
+                fire_tag.bullet_mesh = bullet_assets.mesh.clone();
+                fire_tag.bullet_material = bullet_assets.material(BulletThreat::Aimed);
+                fire_tag.bullet_cull_margin = bullet_assets.cull_margin;
+                Box::new(fire_tag)
+            }
+            FireTagKind::Laser => {
+                let mut fire_tag = FireTagLaser::default();
+                fire_tag.mesh = self.laser_mesh.clone();
+                fire_tag.telegraph_material = self.laser_telegraph_material.clone();
+                fire_tag.beam_material = self.laser_material.clone();
+                Box::new(fire_tag)
+            }
+            FireTagKind::Ring(params) => {
+                let threat = if params.aim {
+                    BulletThreat::Aimed
                 } else {
-                    MotionResult::DoNothing
+                    BulletThreat::Pattern
+                };
+                Box::new(FireTagRing {
+                    params: params.clone(),
+                    bullet_mesh: bullet_assets.mesh.clone(),
+                    bullet_material: bullet_assets.material(threat),
+                    bullet_cull_margin: bullet_assets.cull_margin,
+                    cur_time: 0.,
+                    cur_wave: 0,
+                    base_angle: 0.,
+                })
+            }
+            FireTagKind::Sequence(steps) => Box::new(FireTagSequence {
+                children: self.create_fire_tag_children(steps, bullet_kind, params),
+                index: 0,
+            }),
+            FireTagKind::Parallel(steps) => Box::new(FireTagParallel {
+                children: self.create_fire_tag_children(steps, bullet_kind, params),
+            }),
+        }
+    }
+
+    fn create_fire_tag_children(
+        &self,
+        steps: &[FireTagStep],
+        bullet_kind: BulletKind,
+        params: &FireTagParams,
+    ) -> Vec<FireTagChild> {
+        steps
+            .iter()
+            .map(|step| {
+                let bullet_kind = step.bullet_kind.unwrap_or(bullet_kind);
+                let mut child = FireTagChild::new(
+                    self.create_fire_tag(&step.fire_tag, bullet_kind, params),
+                    step.delay,
+                    step.duration,
+                );
+                child.bullet_motion = step.bullet_motion;
+                child.bounce = step.bounce;
+                child.sfx = step.sfx;
+                child.muzzle_flash = step.muzzle_flash;
+                child.charge_sfx = step.charge_sfx;
+                child
+            })
+            .collect()
+    }
+}
+
+/// Create a motion pattern starting at the given position. For motions with an
+/// entering phase, `enter_duration` is the duration of that phase, in seconds. For the
+/// path motion, `path` is the path to follow; without one, the enemy flies straight
+/// across the screen.
+fn create_motion_pattern(
+    kind: MotionPatternKind,
+    start: Vec3,
+    enter_duration: f32,
+    path: Option<&PathDescriptor>,
+) -> Box<dyn MotionPattern + Send + Sync> {
+    match kind {
+        MotionPatternKind::EnterStay => {
+            let mut motion = EnterStayMotion::default();
+            motion.start = start;
+            motion.enter_height = start.y;
+            motion.enter_duration = enter_duration;
+            Box::new(motion)
+        }
+        MotionPatternKind::FlyBy => {
+            let mut motion = FlyByMotion::default();
+            motion.start = start;
+            motion.direction = if start.y > 0. {
+                Vec3::new(-1., 0.25, 0.)
+            } else {
+                Vec3::new(-1., -0.25, 0.)
+            };
+            Box::new(motion)
+        }
+        MotionPatternKind::Path => {
+            let path = path.cloned().unwrap_or_else(|| PathDescriptor {
+                points: vec![Vec3::ZERO, Vec3::new(-10., 0., 0.)],
+                curve: PathCurve::Linear,
+                duration: 5.,
+                easing: PathEasing::Linear,
+                fire_at: PathDescriptor::default_fire_at(),
+            });
+            Box::new(PathMotion {
+                start,
+                path,
+                time: 0.,
+                has_fired: false,
+            })
+        }
+        MotionPatternKind::Homing => Box::new(HomingMotion {
+            start,
+            velocity: Vec3::new(-1.6, 0., 0.),
+            turn_rate: 1.8,
+            time: 0.,
+        }),
+    }
+}
+
+/// Enemy which fired a bullet.
+#[derive(Component)]
+struct BulletOwner(Entity);
+
+/// Event sent when an enemy is killed by the player.
+///
+/// The enemy entity is despawned by [`kill_enemies()`] once all the side effects of
+/// the kill are applied, in the same gameplay step.
+#[derive(Debug, Clone, Copy)]
+pub struct EnemyKilledEvent {
+    pub entity: Entity,
+    pub position: Vec3,
+    pub is_boss: bool,
+}
+
+/// Debug command sent from the debug console, to test enemies and patterns without
+/// editing the timeline.
+#[derive(Debug, Clone)]
+pub enum DebugEnemyCommand {
+    /// Spawn an enemy by name. Its motion pattern takes over from the given position.
+    Spawn { enemy: String, position: Vec3 },
+    /// Kill all the enemies alive, as if shot down by the player.
+    KillAll,
+    /// Move the timeline to the given time, in seconds since its start.
+    GotoTime(f64),
+    /// Clear the enemies and bullets, and move the timeline to the event at the given
+    /// index, optionally pausing it there.
+    Seek { index: usize, pause: bool },
+    /// Pause or resume the timeline.
+    SetTimelinePaused(bool),
+    /// Apply the parameters of the [`PatternPreview`] to the previewed enemy, and
+    /// respawn it on a clean screen.
+    RestartPreview,
+}
+
+/// Tweakable parameter of the fire tags of the enemy of the [`PatternPreview`].
+#[derive(Debug, Clone)]
+pub struct PatternParam {
+    /// Path of the parameter in the enemy descriptor, like `phases[1][0].speed`.
+    pub name: String,
+    pub value: f32,
+    /// Range of the slider editing the value.
+    pub range: (f32, f32),
+    /// Is the value a count, edited as an integer?
+    pub integer: bool,
+}
+
+/// Pattern preview started with `--pattern`: a single enemy in an empty arena, which
+/// the debug tools respawn on demand with its fire tag parameters tweaked, to iterate
+/// on a pattern without replaying the timeline.
+#[derive(Debug, Default)]
+pub struct PatternPreview {
+    /// Name of the previewed enemy, if previewing.
+    pub enemy: Option<String>,
+    /// Parameters of the fire tags of the enemy, applied on restart.
+    pub params: Vec<PatternParam>,
+}
+
+/// Mutable reference to a tweakable fire tag parameter.
+enum ParamRef<'a> {
+    Float(&'a mut f32),
+    Count(&'a mut u32),
+}
+
+/// Visit the tweakable parameters of the given fire tags, with their name and the
+/// range of their slider.
+fn visit_fire_tag_params(
+    kinds: &mut [FireTagKind],
+    prefix: &str,
+    visitor: &mut dyn FnMut(String, ParamRef, (f32, f32)),
+) {
+    for (index, kind) in kinds.iter_mut().enumerate() {
+        let prefix = format!("{}[{}]", prefix, index);
+        match kind {
+            FireTagKind::Ring(params) => {
+                let name = |field| format!("{}.{}", prefix, field);
+                visitor(
+                    name("bullet_count"),
+                    ParamRef::Count(&mut params.bullet_count),
+                    (1., 64.),
+                );
+                visitor(name("waves"), ParamRef::Count(&mut params.waves), (1., 16.));
+                visitor(
+                    name("wave_delay"),
+                    ParamRef::Float(&mut params.wave_delay),
+                    (0., 2.),
+                );
+                visitor(
+                    name("angle_offset"),
+                    ParamRef::Float(&mut params.angle_offset),
+                    (-180., 180.),
+                );
+                visitor(name("speed"), ParamRef::Float(&mut params.speed), (0.1, 6.));
+                visitor(
+                    name("speed_ramp"),
+                    ParamRef::Float(&mut params.speed_ramp),
+                    (-2., 2.),
+                );
+                visitor(
+                    name("volley_delay"),
+                    ParamRef::Float(&mut params.volley_delay),
+                    (0., 5.),
+                );
+            }
+            FireTagKind::Sequence(steps) | FireTagKind::Parallel(steps) => {
+                for (step_index, step) in steps.iter_mut().enumerate() {
+                    let step_prefix = format!("{}[{}]", prefix, step_index);
+                    visitor(
+                        format!("{}.delay", step_prefix),
+                        ParamRef::Float(&mut step.delay),
+                        (0., 10.),
+                    );
+                    if let Some(duration) = &mut step.duration {
+                        visitor(
+                            format!("{}.duration", step_prefix),
+                            ParamRef::Float(duration),
+                            (0.1, 20.),
+                        );
+                    }
+                    visit_fire_tag_params(
+                        std::slice::from_mut(&mut step.fire_tag),
+                        &format!("{}.fire_tag", step_prefix),
+                        visitor,
+                    );
                 }
             }
-            EnterStayPhase::Stay => MotionResult::DoNothing,
+            // Parameters of the enemy, visited by FireTagParams::visit()
+            FireTagKind::Spiral | FireTagKind::DoubleSpiral | FireTagKind::AimBurst => {}
+            // Hard-coded pattern, without any parameter
+            FireTagKind::Laser => {}
+        }
+    }
+}
+
+impl FireTagParams {
+    fn visit(&mut self, visitor: &mut dyn FnMut(String, ParamRef, (f32, f32))) {
+        let spiral = &mut self.spiral;
+        let name = |field| format!("fire_tag_params.spiral.{}", field);
+        visitor(
+            name("arms_count"),
+            ParamRef::Count(&mut spiral.arms_count),
+            (1., 16.),
+        );
+        visitor(
+            name("bullet_speed"),
+            ParamRef::Float(&mut spiral.bullet_speed),
+            (0.1, 8.),
+        );
+        visitor(
+            name("fire_delay"),
+            ParamRef::Float(&mut spiral.fire_delay),
+            (0.01, 1.),
+        );
+        visitor(
+            name("rotate_speed"),
+            ParamRef::Float(&mut spiral.rotate_speed),
+            (-180., 180.),
+        );
+        visitor(
+            name("angle_jitter"),
+            ParamRef::Float(&mut spiral.angle_jitter),
+            (0., 45.),
+        );
+        visitor(
+            name("speed_jitter"),
+            ParamRef::Float(&mut spiral.speed_jitter),
+            (0., 2.),
+        );
+        visitor(
+            name("gap_period"),
+            ParamRef::Count(&mut spiral.gap_period),
+            (0., 100.),
+        );
+        visitor(
+            name("gap_length"),
+            ParamRef::Count(&mut spiral.gap_length),
+            (0., 50.),
+        );
+
+        let aim_burst = &mut self.aim_burst;
+        let name = |field| format!("fire_tag_params.aim_burst.{}", field);
+        visitor(
+            name("bullet_count"),
+            ParamRef::Count(&mut aim_burst.bullet_count),
+            (1., 32.),
+        );
+        visitor(
+            name("bullet_speed"),
+            ParamRef::Float(&mut aim_burst.bullet_speed),
+            (0.1, 8.),
+        );
+        visitor(
+            name("fire_delay"),
+            ParamRef::Float(&mut aim_burst.fire_delay),
+            (0.01, 1.),
+        );
+        visitor(
+            name("angle_jitter"),
+            ParamRef::Float(&mut aim_burst.angle_jitter),
+            (0., 45.),
+        );
+        visitor(
+            name("speed_jitter"),
+            ParamRef::Float(&mut aim_burst.speed_jitter),
+            (0., 2.),
+        );
+    }
+}
+
+/// Read-only view of the enemy timeline, for the debug tools.
+#[derive(Debug, Default)]
+pub struct TimelineView {
+    /// Current time, in seconds since the start of the timeline.
+    pub time: f64,
+    /// Index of the next event to run.
+    pub index: usize,
+    /// Is the timeline paused from the debug tools?
+    pub paused: bool,
+    /// Time and description of all the events, in order.
+    pub events: Vec<(f64, String)>,
+}
+
+/// Event to cancel all the bullets fired by an enemy, sent when the enemy is killed or
+/// a boss phase ends. Cancelled bullets turn into stars flying toward the player.
+#[derive(Debug, Clone, Copy)]
+struct BulletCancelEvent {
+    owner: Entity,
+}
+
+struct FireTagContext<'w, 's, 'ctx> {
+    dt: f32,
+    owner: Entity,
+    origin: Vec3,
+    player_position: Vec3,
+    /// Difficulty multiplier derived from the [`Rank`], scaling the bullet speed, the
+    /// bullet count and the fire rate.
+    rank_multiplier: f32,
+    /// Multipliers of the [`Difficulty`] of the game, on top of the rank one.
+    difficulty: DifficultyMultipliers,
+    /// Motion of the bullets fired by the fire tag being executed, if any. See
+    /// [`FireTagStep::bullet_motion`].
+    bullet_motion: Option<BulletMotion>,
+    /// Bounces of the bullets fired by the fire tag being executed. See
+    /// [`FireTagStep::bounce`].
+    bounce: u8,
+    /// Number of bullets and beams fired since the start of the update.
+    fired: u32,
+    /// Effects of the volleys fired since the start of the update.
+    fx: FireTagFx,
+    /// Seeded generator of the random jitter of the bullets, see [`GameRng`].
+    rng: &'ctx mut StdRng,
+    commands: &'ctx mut Commands<'w, 's>,
+}
+
+/// Sound and visual effects of the volleys fired by the fire tags of an enemy, see
+/// [`FireTagStep::sfx`].
+#[derive(Debug, Default)]
+struct FireTagFx {
+    /// Sounds telegraphing the volleys of the steps which started.
+    charge_sfx: Vec<Sfx>,
+    /// Sounds of the volleys fired.
+    volley_sfx: Vec<Sfx>,
+    /// Flash around the enemy for the volleys fired.
+    muzzle_flash: bool,
+}
+
+impl<'w, 's, 'ctx> FireTagContext<'w, 's, 'ctx> {
+    fn new(
+        dt: f32,
+        owner: Entity,
+        origin: Vec3,
+        player_position: Vec3,
+        rank_multiplier: f32,
+        difficulty: DifficultyMultipliers,
+        rng: &'ctx mut StdRng,
+        commands: &'ctx mut Commands<'w, 's>,
+    ) -> Self {
+        FireTagContext {
+            dt,
+            owner,
+            origin,
+            player_position,
+            rank_multiplier,
+            difficulty,
+            bullet_motion: None,
+            bounce: 0,
+            fired: 0,
+            fx: FireTagFx::default(),
+            rng,
+            commands,
+        }
+    }
+
+    /// Random deviation in `[-range:range]`. Doesn't draw from the generator when the
+    /// range is zero, so that patterns without jitter leave the random sequence intact.
+    fn jitter(&mut self, range: f32) -> f32 {
+        if range > 0. {
+            self.rng.gen_range(-range..=range)
+        } else {
+            0.
+        }
+    }
+
+    /// Scale a fire delay by the rank and difficulty, firing faster at higher rank.
+    fn delay(&self, delay: f32) -> f32 {
+        delay * self.difficulty.fire_delay / self.rank_multiplier
+    }
+
+    /// Scale a bullet count by the rank and difficulty, firing denser patterns at
+    /// higher rank.
+    fn count(&self, count: u32) -> u32 {
+        (count as f32 * self.rank_multiplier * self.difficulty.bullet_count).round() as u32
+    }
+
+    fn fire(
+        &mut self,
+        rot: Quat,
+        speed: f32,
+        mesh: Handle<Mesh>,
+        material: Handle<StandardMaterial>,
+        cull_margin: f32,
+    ) {
+        // println!(
+        //     "FIRE: origin={:?} angle={} speed={}",
+        //     self.origin, angle, speed
+        // );
+        self.fired += 1;
+        let speed_factor = self.rank_multiplier * self.difficulty.bullet_speed;
+        let speed = speed * speed_factor;
+        let mut bullet = self.commands.spawn_bundle(PbrBundle {
+            mesh,
+            material,
+            transform: Transform::from_rotation(rot).with_translation(self.origin),
+            ..Default::default()
+        });
+        bullet
+            .insert(Bullet(Vec3::X * speed))
+            .insert(EnemyBullet)
+            .insert(Damage(ENEMY_BULLET_DAMAGE))
+            .insert(InGameEntity)
+            .insert(CullMargin(cull_margin))
+            .insert(BulletOwner(self.owner))
+            .insert(FixedStep::default())
+            // Rendering
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver);
+        if let Some(motion) = self.bullet_motion {
+            bullet.insert(motion.scaled(speed_factor));
+        }
+        if self.bounce > 0 {
+            bullet.insert(Bounce(self.bounce));
+        }
+    }
+}
+
+trait FireTag {
+    fn execute(&mut self, context: &mut FireTagContext);
+
+    /// Reset the progress of the fire tag, to play it again from its start.
+    fn reset(&mut self);
+}
+
+/// Width of the preview of a laser beam while it telegraphs, in world units.
+const LASER_TELEGRAPH_WIDTH: f32 = 0.02;
+
+/// Laser beam fired by an enemy, which follows its owner and plays on its own once
+/// fired: it telegraphs as a thin translucent preview, then activates and sweeps from
+/// `start_angle` to `end_angle`, and finally despawns.
+///
+/// The beam entity holds the physics, centered on the beam; the visual is a child
+/// quad stretched to the beam size.
+#[derive(Component)]
+struct LaserBeam {
+    start_angle: f32,
+    end_angle: f32,
+    telegraph_duration: f32,
+    sweep_duration: f32,
+    length: f32,
+    width: f32,
+    damage_per_second: f32,
+    beam_material: Handle<StandardMaterial>,
+    visual: Entity,
+    time: f32,
+    active: bool,
+}
+
+impl LaserBeam {
+    fn angle(&self) -> f32 {
+        let t = ((self.time - self.telegraph_duration) / self.sweep_duration).clamp(0., 1.);
+        self.start_angle + (self.end_angle - self.start_angle) * t
+    }
+
+    /// Transform of the beam entity for the given owner position.
+    fn transform(&self, origin: Vec3) -> Transform {
+        let angle = self.angle();
+        let dir = Vec3::new(angle.cos(), angle.sin(), 0.);
+        Transform::from_translation(origin + dir * self.length / 2.)
+            .with_rotation(Quat::from_rotation_z(angle))
+    }
+}
+
+struct FireTagLaser {
+    telegraph_duration: f32,
+    sweep_duration: f32,
+    /// Delay after a beam despawns before the next one is fired, in seconds.
+    cooldown: f32,
+    /// Total angle swept by a beam, centered on the player, in radians.
+    sweep_angle: f32,
+    length: f32,
+    width: f32,
+    damage_per_second: f32,
+    mesh: Handle<Mesh>,
+    telegraph_material: Handle<StandardMaterial>,
+    beam_material: Handle<StandardMaterial>,
+    //
+    cur_time: f32,
+    /// Sweep direction of the next beam, alternating each beam.
+    sweep_dir: f32,
+}
+
+impl Default for FireTagLaser {
+    fn default() -> Self {
+        FireTagLaser {
+            telegraph_duration: 1.,
+            sweep_duration: 2.,
+            cooldown: 1.5,
+            sweep_angle: PI / 3.,
+            length: 8.,
+            width: 0.12,
+            damage_per_second: 12.,
+            mesh: Handle::default(),
+            telegraph_material: Handle::default(),
+            beam_material: Handle::default(),
+            //
+            cur_time: 0.,
+            sweep_dir: 1.,
+        }
+    }
+}
+
+impl FireTag for FireTagLaser {
+    fn execute(&mut self, context: &mut FireTagContext) {
+        self.cur_time -= context.dt;
+        if self.cur_time > 0. {
+            return;
+        }
+        self.cur_time +=
+            self.telegraph_duration + self.sweep_duration + context.delay(self.cooldown);
+
+        let to_player = context.player_position - context.origin;
+        let aim = to_player.y.atan2(to_player.x);
+        let half_sweep = self.sweep_angle / 2. * self.sweep_dir;
+        self.sweep_dir = -self.sweep_dir;
+        context.fired += 1;
+
+        let visual = context
+            .commands
+            .spawn_bundle(PbrBundle {
+                mesh: self.mesh.clone(),
+                material: self.telegraph_material.clone(),
+                transform: Transform::from_scale(Vec3::new(self.length, LASER_TELEGRAPH_WIDTH, 1.)),
+                ..Default::default()
+            })
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver)
+            .id();
+        let beam = LaserBeam {
+            start_angle: aim - half_sweep,
+            end_angle: aim + half_sweep,
+            telegraph_duration: self.telegraph_duration,
+            sweep_duration: self.sweep_duration,
+            length: self.length,
+            width: self.width,
+            damage_per_second: self.damage_per_second,
+            beam_material: self.beam_material.clone(),
+            visual,
+            time: 0.,
+            active: false,
+        };
+        context
+            .commands
+            .spawn_bundle((beam.transform(context.origin), GlobalTransform::identity()))
+            .insert(Name::new("LaserBeam"))
+            .insert(InGameEntity)
+            .insert(BulletOwner(context.owner))
+            // Centered on the beam, so culled only once the entire beam is off-screen
+            .insert(CullMargin(self.length))
+            .push_children(&[visual])
+            .insert(beam);
+    }
+
+    fn reset(&mut self) {
+        self.cur_time = 0.;
+        self.sweep_dir = 1.;
+    }
+}
+
+/// Child fire tag of a composite fire tag, with its own timing.
+struct FireTagChild {
+    fire_tag: Box<dyn FireTag + Send + Sync>,
+    delay: f32,
+    duration: Option<f32>,
+    /// Motion of the bullets fired by the child, overriding the one of its parent.
+    bullet_motion: Option<BulletMotion>,
+    /// Bounces of the bullets fired by the child, overriding the ones of its parent.
+    bounce: Option<u8>,
+    /// See [`FireTagStep::sfx`].
+    sfx: Option<Sfx>,
+    /// See [`FireTagStep::muzzle_flash`].
+    muzzle_flash: bool,
+    /// See [`FireTagStep::charge_sfx`].
+    charge_sfx: Option<Sfx>,
+    /// Time since the child was (re)started, including the delay, in seconds.
+    time: f32,
+}
+
+impl FireTagChild {
+    fn new(fire_tag: Box<dyn FireTag + Send + Sync>, delay: f32, duration: Option<f32>) -> Self {
+        FireTagChild {
+            fire_tag,
+            delay,
+            duration,
+            bullet_motion: None,
+            bounce: None,
+            sfx: None,
+            muzzle_flash: false,
+            charge_sfx: None,
+            time: 0.,
+        }
+    }
+
+    /// Advance the child, executing its fire tag if past its delay and not done.
+    fn execute(&mut self, context: &mut FireTagContext) {
+        if self.time <= 0. {
+            if let Some(sfx) = self.charge_sfx {
+                context.fx.charge_sfx.push(sfx);
+            }
+        }
+        self.time += context.dt;
+        if self.time >= self.delay && !self.is_done() {
+            let parent = (context.bullet_motion, context.bounce);
+            if self.bullet_motion.is_some() {
+                context.bullet_motion = self.bullet_motion;
+            }
+            if let Some(bounce) = self.bounce {
+                context.bounce = bounce;
+            }
+            let fired = context.fired;
+            self.fire_tag.execute(context);
+            context.bullet_motion = parent.0;
+            context.bounce = parent.1;
+            if context.fired > fired {
+                if let Some(sfx) = self.sfx {
+                    context.fx.volley_sfx.push(sfx);
+                }
+                context.fx.muzzle_flash |= self.muzzle_flash;
+            }
+        }
+    }
+
+    /// Restart the child from its delay.
+    fn reset(&mut self) {
+        self.time = 0.;
+        self.fire_tag.reset();
+    }
+
+    fn is_done(&self) -> bool {
+        if let Some(duration) = self.duration {
+            self.time >= self.delay + duration
+        } else {
+            false
+        }
+    }
+}
+
+/// Run child fire tags one after the other, in a loop.
+struct FireTagSequence {
+    children: Vec<FireTagChild>,
+    index: usize,
+}
+
+impl FireTag for FireTagSequence {
+    fn execute(&mut self, context: &mut FireTagContext) {
+        if self.children.is_empty() {
+            return;
+        }
+        let child = &mut self.children[self.index];
+        child.execute(context);
+        if child.is_done() {
+            child.reset();
+            self.index = (self.index + 1) % self.children.len();
+        }
+    }
+
+    fn reset(&mut self) {
+        for child in self.children.iter_mut() {
+            child.reset();
+        }
+        self.index = 0;
+    }
+}
+
+/// Run child fire tags all at the same time.
+struct FireTagParallel {
+    children: Vec<FireTagChild>,
+}
+
+impl FireTag for FireTagParallel {
+    fn execute(&mut self, context: &mut FireTagContext) {
+        for child in self.children.iter_mut() {
+            child.execute(context);
+        }
+    }
+
+    fn reset(&mut self) {
+        for child in self.children.iter_mut() {
+            child.reset();
+        }
+    }
+}
+
+struct FireTagSpiral {
+    params: SpiralParams,
+    bullet_mesh: Handle<Mesh>,
+    bullet_material: Handle<StandardMaterial>,
+    bullet_cull_margin: f32,
+    //
+    cur_time: f32,
+    cur_angle: f32,
+    cur_iter: i32,
+    /// Arm leaving the safety gap, kept until another arm aims clearly closer to the
+    /// player, see [`SPIRAL_GAP_HYSTERESIS`].
+    gap_arm: Option<i32>,
+}
+
+impl FireTagSpiral {
+    fn new(params: SpiralParams) -> Self {
+        FireTagSpiral {
+            params,
+            bullet_mesh: Handle::default(),
+            bullet_material: Handle::default(),
+            bullet_cull_margin: DEFAULT_CULL_MARGIN,
+            //
+            cur_time: 0.,
+            cur_angle: 0.,
+            cur_iter: 0,
+            gap_arm: None,
+        }
+    }
+
+    /// Is the safety gap open for the current volley?
+    fn is_gap_open(&self) -> bool {
+        let params = &self.params;
+        params.gap_period > 0 && (self.cur_iter as u32 % params.gap_period) < params.gap_length
+    }
+}
+
+/// Angle by which another arm of a spiral needs to aim closer to the player than the
+/// current gap arm to move the safety gap to it, in degrees. Prevents the gap from
+/// flickering between two arms when the player is halfway between them.
+const SPIRAL_GAP_HYSTERESIS: f32 = 8.;
+
+/// Absolute difference between two angles, in `[0:PI]` radians.
+fn angle_distance(angle0: f32, angle1: f32) -> f32 {
+    let delta = (angle0 - angle1).rem_euclid(TAU);
+    delta.min(TAU - delta)
+}
+
+impl FireTag for FireTagSpiral {
+    fn execute(&mut self, mut context: &mut FireTagContext) {
+        let dt = context.dt;
+        // println!(
+        //     "EXEC: dt={} cur_angle={} cur_iter={}",
+        //     dt, self.cur_angle, self.cur_iter
+        // );
+        self.cur_time += dt;
+        let cone_angle = 30_f32.to_radians(); // need to be >= 60 deg for 6 arms, othewise there's a time gap!
+        let arms_count = self.params.arms_count.max(1) as i32;
+        if self.cur_time >= context.delay(self.params.fire_delay) {
+            self.cur_time = 0.; // for safety, run at most once per frame
+            let delta_angle = TAU / arms_count as f32;
+            let mut angle = self.cur_angle % TAU;
+            // find the arm with a direction aiming closest to the player
+            // we need to stop firing for a bit always on the same arm, otherwise
+            // it's useless if this is distributed across 2 arms (not enough space
+            // on either of them to safely pass through).
+            let to_player = context.player_position - context.origin;
+            let player_angle = to_player.y.atan2(to_player.x);
+            let arm_distance =
+                |idx: i32| angle_distance(angle + delta_angle * idx as f32, player_angle);
+            let closest_arm_idx = (0..arms_count)
+                .min_by_key(|&idx| FloatOrd(arm_distance(idx)))
+                .unwrap_or(0);
+            let aim_arm_idx = match self.gap_arm {
+                Some(idx)
+                    if idx < arms_count
+                        && arm_distance(idx)
+                            <= arm_distance(closest_arm_idx)
+                                + SPIRAL_GAP_HYSTERESIS.to_radians() =>
+                {
+                    idx
+                }
+                _ => closest_arm_idx,
+            };
+            self.gap_arm = Some(aim_arm_idx);
+            //println!("AIM ARM = #{}", aim_arm_idx);
+            self.cur_iter += 1;
+            // repeat
+            for idx in 0..arms_count {
+                // println!(
+                //     "ARM #{}: angle={} min={} max={}",
+                //     idx,
+                //     angle,
+                //     PI - cone_angle,
+                //     PI + cone_angle
+                // );
+                if !self.is_gap_open() || idx != aim_arm_idx {
+                    let jitter = context.jitter(self.params.angle_jitter.to_radians());
+                    let rot = Quat::from_rotation_z(angle + jitter);
+                    let speed = self.params.bullet_speed + context.jitter(self.params.speed_jitter);
+                    context.fire(
+                        rot,
+                        speed,
+                        self.bullet_mesh.clone(),
+                        self.bullet_material.clone(),
+                        self.bullet_cull_margin,
+                    );
+                }
+                // sequence
+                angle = (angle + delta_angle) % TAU;
+            }
+        }
+        // sequence
+        self.cur_angle = (self.cur_angle + self.params.rotate_speed.to_radians() * dt) % TAU;
+    }
+
+    fn reset(&mut self) {
+        self.cur_time = 0.;
+        self.cur_angle = 0.;
+        self.cur_iter = 0;
+        self.gap_arm = None;
+    }
+}
+
+struct FireTagDoubleSpiral {
+    spiral1: FireTagSpiral,
+    spiral2: FireTagSpiral,
+}
+
+impl FireTagDoubleSpiral {
+    fn new(params: &SpiralParams) -> Self {
+        FireTagDoubleSpiral {
+            spiral1: FireTagSpiral::new(params.clone()),
+            spiral2: FireTagSpiral::new(SpiralParams {
+                rotate_speed: -params.rotate_speed,
+                ..params.clone()
+            }),
+        }
+    }
+}
+
+impl FireTag for FireTagDoubleSpiral {
+    fn execute(&mut self, mut context: &mut FireTagContext) {
+        self.spiral1.execute(context);
+        self.spiral2.execute(context);
+    }
+
+    fn reset(&mut self) {
+        self.spiral1.reset();
+        self.spiral2.reset();
+    }
+}
+
+struct FireTagAimBurst {
+    params: AimBurstParams,
+    bullet_mesh: Handle<Mesh>,
+    bullet_material: Handle<StandardMaterial>,
+    bullet_cull_margin: f32,
+    //
+    cur_time: f32,
+    cur_iter: i32,
+}
+
+impl FireTagAimBurst {
+    fn new(params: AimBurstParams) -> Self {
+        FireTagAimBurst {
+            params,
+            bullet_mesh: Handle::default(),
+            bullet_material: Handle::default(),
+            bullet_cull_margin: DEFAULT_CULL_MARGIN,
+            //
+            cur_time: 0.,
+            cur_iter: 0,
+        }
+    }
+}
+
+impl FireTag for FireTagAimBurst {
+    fn execute(&mut self, mut context: &mut FireTagContext) {
+        if self.cur_iter < context.count(self.params.bullet_count) as i32 {
+            let dt = context.dt;
+            // println!(
+            //     "EXEC: dt={} cur_angle={} cur_iter={}",
+            //     dt, self.cur_angle, self.cur_iter
+            // );
+            self.cur_time += dt;
+            if self.cur_time >= context.delay(self.params.fire_delay) {
+                self.cur_time = 0.; // for safety, run at most once per frame
+                let dir = (context.player_position - context.origin)
+                    .try_normalize()
+                    .unwrap_or(Vec3::X);
+                let jitter = context.jitter(self.params.angle_jitter.to_radians());
+                let rot = Quat::from_rotation_z(jitter) * Quat::from_rotation_arc(Vec3::X, dir);
+                let speed = self.params.bullet_speed + context.jitter(self.params.speed_jitter);
+                context.fire(
+                    rot,
+                    speed,
+                    self.bullet_mesh.clone(),
+                    self.bullet_material.clone(),
+                    self.bullet_cull_margin,
+                );
+                self.cur_iter += 1;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cur_time = 0.;
+        self.cur_iter = 0;
+    }
+}
+
+struct FireTagRing {
+    params: RingParams,
+    bullet_mesh: Handle<Mesh>,
+    bullet_material: Handle<StandardMaterial>,
+    bullet_cull_margin: f32,
+    //
+    cur_time: f32,
+    cur_wave: u32,
+    /// Angle of the first bullet of the first ring of the current volley, in radians.
+    base_angle: f32,
+}
+
+impl FireTag for FireTagRing {
+    fn execute(&mut self, context: &mut FireTagContext) {
+        self.cur_time -= context.dt;
+        if self.cur_time > 0. {
+            return;
+        }
+
+        let params = &self.params;
+        if self.cur_wave == 0 {
+            self.base_angle = if params.aim {
+                let to_player = context.player_position - context.origin;
+                to_player.y.atan2(to_player.x)
+            } else {
+                0.
+            };
+        }
+
+        let wave = self.cur_wave as f32;
+        let ring_angle = self.base_angle + params.angle_offset.to_radians() * wave;
+        let speed = params.speed + params.speed_ramp * wave;
+        let bullet_count = context.count(params.bullet_count).max(1);
+        let delta_angle = TAU / bullet_count as f32;
+        for index in 0..bullet_count {
+            let angle = ring_angle + delta_angle * index as f32;
+            context.fire(
+                Quat::from_rotation_z(angle),
+                speed,
+                self.bullet_mesh.clone(),
+                self.bullet_material.clone(),
+                self.bullet_cull_margin,
+            );
+        }
+
+        self.cur_wave += 1;
+        if self.cur_wave >= params.waves {
+            self.cur_wave = 0;
+            self.cur_time += context.delay(params.volley_delay);
+        } else {
+            self.cur_time += context.delay(params.wave_delay);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.cur_time = 0.;
+        self.cur_wave = 0;
+        self.base_angle = 0.;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MotionResult {
+    DoNothing,
+    StartFireTag,
+    /// The enemy blows itself up, damaging the player if close enough.
+    SelfDestruct,
+}
+
+trait MotionPattern {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult;
+}
+
+enum EnterStayPhase {
+    Idle,
+    Enter,
+    Stay,
+}
+
+struct EnterStayMotion {
+    phase: EnterStayPhase,
+    start: Vec3,
+    enter_height: f32,
+    enter_duration: f32,
+}
+
+impl Default for EnterStayMotion {
+    fn default() -> Self {
+        EnterStayMotion {
+            phase: EnterStayPhase::Idle,
+            start: Vec3::new(5., 0., 0.),
+            enter_height: 0.,
+            enter_duration: 5.,
+        }
+    }
+}
+
+impl MotionPattern for EnterStayMotion {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        match self.phase {
+            EnterStayPhase::Idle => {
+                self.phase = EnterStayPhase::Enter;
+                transform.translation = self.start;
+                let tween = Tween::new(
+                    EaseFunction::QuadraticOut,
+                    TweeningType::Once,
+                    Duration::from_secs_f32(self.enter_duration),
+                    TransformPositionLens {
+                        start: transform.translation,
+                        end: Vec3::new(2., self.enter_height, 0.),
+                    },
+                );
+                animator.set_tweenable(tween);
+                animator.state = AnimatorState::Playing;
+                MotionResult::DoNothing
+            }
+            EnterStayPhase::Enter => {
+                if animator.progress() >= 1. {
+                    self.phase = EnterStayPhase::Stay;
+                    let tween = Tween::new(
+                        EaseFunction::QuadraticInOut,
+                        TweeningType::PingPong,
+                        Duration::from_secs_f32(3.),
+                        TransformPositionLens {
+                            start: transform.translation,
+                            end: transform.translation + Vec3::Y * 0.6,
+                        },
+                    );
+                    animator.set_tweenable(tween);
+                    animator.state = AnimatorState::Playing;
+                    MotionResult::StartFireTag
+                } else {
+                    MotionResult::DoNothing
+                }
+            }
+            EnterStayPhase::Stay => MotionResult::DoNothing,
+        }
+    }
+}
+
+/// Acceleration of a fly-by enemy leaving the screen once done flying by, in world units
+/// per second squared.
+const FLY_BY_EXIT_ACCELERATION: f32 = 2.;
+
+struct FlyByMotion {
+    start: Vec3,
+    direction: Vec3,
+    has_fired: bool,
+    /// Speed of the enemy leaving the screen after the fly-by, in world units per second.
+    exit_speed: f32,
+}
+
+impl Default for FlyByMotion {
+    fn default() -> Self {
+        FlyByMotion {
+            start: Vec3::ZERO,
+            direction: Vec3::ZERO,
+            has_fired: false,
+            exit_speed: 0.,
+        }
+    }
+}
+
+impl MotionPattern for FlyByMotion {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        match &animator.state {
+            AnimatorState::Paused => {
+                let tween = Tween::new(
+                    EaseFunction::QuadraticOut,
+                    TweeningType::Once,
+                    Duration::from_secs_f32(5.),
+                    TransformPositionLens {
+                        start: self.start,
+                        end: self.start + self.direction * 6.,
+                    },
+                );
+                animator.set_tweenable(tween);
+                animator.state = AnimatorState::Playing;
+                MotionResult::DoNothing
+            }
+            AnimatorState::Playing => {
+                if animator.progress() >= 1. {
+                    // Keep going until culled off-screen, instead of hovering forever
+                    // where the fly-by ends
+                    self.exit_speed += FLY_BY_EXIT_ACCELERATION * dt;
+                    transform.translation += self.direction * self.exit_speed * dt;
+                }
+                if !self.has_fired && animator.progress() >= 0.3 {
+                    self.has_fired = true;
+                    MotionResult::StartFireTag
+                } else {
+                    MotionResult::DoNothing
+                }
+            }
+        }
+    }
+}
+
+struct PathMotion {
+    start: Vec3,
+    path: PathDescriptor,
+    /// Time elapsed since the start of the path, in seconds.
+    time: f32,
+    has_fired: bool,
+}
+
+impl MotionPattern for PathMotion {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        self.time += dt;
+        let progress = (self.time / self.path.duration.max(1e-3)).min(1.);
+        let t = self.path.easing.ease(progress);
+        transform.translation = self.start + self.path.position_at(t);
+        if !self.has_fired && progress >= self.path.fire_at {
+            self.has_fired = true;
+            MotionResult::StartFireTag
+        } else {
+            MotionResult::DoNothing
+        }
+    }
+}
+
+/// Distance to the player under which a homing enemy self-destructs.
+const DETONATE_RADIUS: f32 = 0.3;
+
+/// Radius of the blast of a self-destructing enemy.
+const DETONATE_BLAST_RADIUS: f32 = 0.45;
+
+/// Damage dealt to the player caught in the blast of a self-destructing enemy.
+const DETONATE_DAMAGE: f32 = 5.;
+
+/// Duration after which a homing enemy stops steering and flies straight, so that it
+/// eventually leaves the screen if it missed the player, in seconds.
+const HOMING_DURATION: f32 = 6.;
+
+struct HomingMotion {
+    start: Vec3,
+    velocity: Vec3,
+    /// Maximum turn rate, in radians per second.
+    turn_rate: f32,
+    /// Time elapsed since the enemy spawned, in seconds.
+    time: f32,
+}
+
+impl MotionPattern for HomingMotion {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        if self.time == 0. {
+            transform.translation = self.start;
+        }
+        self.time += dt;
+
+        let to_player = (player_position - transform.translation).truncate();
+        if to_player.length() < DETONATE_RADIUS {
+            return MotionResult::SelfDestruct;
+        }
+
+        // Rotate the velocity toward the player, up to the max turn rate
+        if self.time < HOMING_DURATION {
+            let current = self.velocity.y.atan2(self.velocity.x);
+            let target = to_player.y.atan2(to_player.x);
+            let delta = (target - current + PI).rem_euclid(TAU) - PI;
+            let max_delta = self.turn_rate * dt;
+            let angle = current + delta.clamp(-max_delta, max_delta);
+            self.velocity = Vec3::new(angle.cos(), angle.sin(), 0.) * self.velocity.length();
+        }
+        transform.translation += self.velocity * dt;
+        MotionResult::DoNothing
+    }
+}
+
+/// Acceleration of a retreating mid-boss, in world units per second squared.
+const RETREAT_ACCELERATION: f32 = 3.;
+
+/// Mid-boss flying away up and backward, until culled beyond the top edge.
+struct RetreatMotion {
+    speed: f32,
+}
+
+impl MotionPattern for RetreatMotion {
+    fn do_motion(
+        &mut self,
+        dt: f32,
+        player_position: Vec3,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        self.speed += RETREAT_ACCELERATION * dt;
+        transform.translation += Vec3::new(0.5, 1., 0.).normalize() * self.speed * dt;
+        MotionResult::DoNothing
+    }
+}
+
+#[derive(Component)]
+pub struct EnemyController {
+    motion_pattern: Option<Box<dyn MotionPattern + Send + Sync>>,
+    fire_tag: Option<Box<dyn FireTag + Send + Sync>>,
+    fire_tag_started: bool,
+    life: f32,
+    remain_life: f32,
+    is_boss: bool,
+    kill_score: u32,
+    /// Boss phases, if any. See [`EnemyDescriptor::phases`].
+    phases: Vec<PhaseDescriptor>,
+    /// See [`EnemyDescriptor::fire_tag_params`].
+    fire_tag_params: FireTagParams,
+    /// Index of the current phase into `phases`.
+    phase_index: usize,
+    /// Remaining time of the phase transition animation, in seconds.
+    phase_transition: f32,
+    /// Entity holding the LifebarHud displaying the life of this enemy, if any.
+    lifebar_entity: Option<Entity>,
+    /// Player misses when the current boss phase started firing, while its pattern
+    /// capture is in progress.
+    capture_misses: Option<u32>,
+    /// See [`EnemyDescriptor::rank_multipliers`].
+    rank_multipliers: (f32, f32),
+    /// Multipliers of the game difficulty, fixed at spawn.
+    difficulty: DifficultyMultipliers,
+    /// See [`EnemyDescriptor::drops`].
+    drops: Vec<DropDescriptor>,
+    /// See [`EnemyDescriptor::guaranteed_drop`].
+    guaranteed_drop: bool,
+    /// Time left before the volleys fired play their effects again, in seconds.
+    fx_cooldown: f32,
+    /// Effects of the volleys fired during the last update, to play.
+    fx: FireTagFx,
+}
+
+impl Default for EnemyController {
+    fn default() -> Self {
+        EnemyController {
+            motion_pattern: None,
+            fire_tag: None,
+            fire_tag_started: false,
+            life: 0.,
+            remain_life: 0.,
+            is_boss: false,
+            kill_score: 1,
+            phases: vec![],
+            fire_tag_params: FireTagParams::default(),
+            phase_index: 0,
+            phase_transition: 0.,
+            lifebar_entity: None,
+            capture_misses: None,
+            rank_multipliers: (1., 1.),
+            difficulty: DifficultyMultipliers::default(),
+            drops: vec![],
+            guaranteed_drop: false,
+            fx_cooldown: 0.,
+            fx: FireTagFx::default(),
+        }
+    }
+}
+
+/// Minimum delay between the effects of two volleys of the same enemy, in seconds, so
+/// that fast patterns like spirals don't play a sound for each of their volleys.
+const VOLLEY_FX_INTERVAL: f32 = 0.12;
+
+/// Size of the glow around the enemies carrying a guaranteed drop, relative to their
+/// collision radius.
+const CARRIER_GLOW_SCALE: f32 = 4.;
+
+/// Period of the pulse of the glow around the enemies carrying a guaranteed drop, in
+/// seconds.
+const CARRIER_GLOW_PERIOD: f32 = 0.8;
+
+/// Duration of the transition animation between two boss phases, in seconds.
+const PHASE_TRANSITION_DURATION: f32 = 0.6;
+
+/// Mid-boss spawned by the timeline, which pauses the timeline until killed or
+/// retreating. See [`TimelineEntry::MidBoss`].
+#[derive(Component)]
+struct MidBoss {
+    /// Time left before retreating, in seconds.
+    retreat_timer: f32,
+    /// Is the mid-boss flying away? It doesn't fire anymore, and the timeline resumes.
+    retreating: bool,
+}
+
+impl EnemyController {
+    pub fn is_boss(&self) -> bool {
+        self.is_boss
+    }
+
+    /// Roll the pickups dropped when killed, or return `None` for random drops. See
+    /// [`EnemyDescriptor::drops`].
+    fn roll_drops(&self, rng: &mut impl Rng) -> Option<Vec<PickupKind>> {
+        if self.drops.is_empty() {
+            return None;
+        }
+        let mut kinds: Vec<PickupKind> = self
+            .drops
+            .iter()
+            .filter(|drop| rng.gen::<f32>() < drop.probability)
+            .map(|drop| drop.kind)
+            .collect();
+        if kinds.is_empty() && self.guaranteed_drop {
+            let index = WeightedIndex::new(self.drops.iter().map(|drop| drop.probability.max(0.)))
+                .map_or(0, |weights| weights.sample(rng));
+            kinds.push(self.drops[index].kind);
+        }
+        Some(kinds)
+    }
+
+    /// Index of the phase matching the current remaining life, with each phase
+    /// spanning an equal share of the total life like the boss lifebars.
+    fn phase_from_life(&self) -> usize {
+        if self.phases.is_empty() {
+            return 0;
+        }
+        let life_per_phase = self.life / self.phases.len() as f32;
+        let index = ((self.life - self.remain_life) / life_per_phase).floor() as usize;
+        index.min(self.phases.len() - 1)
+    }
+
+    /// Life left before the end of the current phase, or before dying for the last one.
+    fn phase_remain_life(&self) -> f32 {
+        let phase_count = self.phases.len().max(1);
+        let life_per_phase = self.life / phase_count as f32;
+        let phases_left = phase_count - 1 - self.phase_index.min(phase_count - 1);
+        self.remain_life - life_per_phase * phases_left as f32
+    }
+
+    /// End the capture of the current boss pattern, if any, and return `true` if the
+    /// player didn't miss since the pattern started.
+    fn end_capture(&mut self, misses: Option<u32>) -> bool {
+        match (self.capture_misses.take(), misses) {
+            (Some(start_misses), Some(misses)) => start_misses == misses,
+            _ => false,
+        }
+    }
+
+    fn update(
+        &mut self,
+        dt: f32,
+        entity: Entity,
+        origin: Vec3,
+        player_position: Vec3,
+        rank_factor: f32,
+        bullet_speed: f32,
+        rng: &mut StdRng,
+        commands: &mut Commands,
+        transform: &mut Transform,
+        animator: &mut Animator<Transform>,
+    ) -> MotionResult {
+        // Move
+        let mut result = MotionResult::DoNothing;
+        if let Some(motion_pattern) = &mut self.motion_pattern {
+            result = motion_pattern.do_motion(dt, player_position, transform, animator);
+            if result == MotionResult::StartFireTag {
+                self.fire_tag_started = true;
+            }
+        }
+
+        // Fire
+        if self.fire_tag_started {
+            //println!("ENEMY_UPDATE: dt={} origin={:?}", dt, origin);
+            let (min_multiplier, max_multiplier) = self.rank_multipliers;
+            let rank_multiplier = min_multiplier + (max_multiplier - min_multiplier) * rank_factor;
+            let difficulty = DifficultyMultipliers {
+                bullet_speed: self.difficulty.bullet_speed * bullet_speed,
+                ..self.difficulty
+            };
+            let mut context = FireTagContext::new(
+                dt,
+                entity,
+                origin,
+                player_position,
+                rank_multiplier,
+                difficulty,
+                rng,
+                commands,
+            );
+            if let Some(fire_tag) = &mut self.fire_tag {
+                fire_tag.execute(&mut context);
+            }
+
+            // Charge-ups telegraph the volleys to come, so are never dropped
+            let fx = std::mem::take(&mut context.fx);
+            self.fx.charge_sfx.extend(fx.charge_sfx);
+            self.fx_cooldown -= dt;
+            if self.fx_cooldown <= 0. && (!fx.volley_sfx.is_empty() || fx.muzzle_flash) {
+                self.fx_cooldown = VOLLEY_FX_INTERVAL;
+                self.fx.volley_sfx.extend(fx.volley_sfx);
+                self.fx.muzzle_flash |= fx.muzzle_flash;
+            }
+        }
+
+        result
+    }
+}
+
+/// Recolor the threat materials of the bullets and homing enemies when the palette
+/// changes, so that a new palette applies to the bullets already on screen.
+fn update_threat_materials(
+    manager: Res<EnemyManager>,
+    common_assets: Res<CommonAssets>,
+    palette: Res<BulletPalette>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if !palette.is_changed() {
+        return;
+    }
+    for bullet_assets in common_assets.enemy_bullets.values() {
+        for (threat, handle) in &bullet_assets.threat_materials {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color = palette.color(*threat);
+            }
+        }
+    }
+    if let Some(material) = manager
+        .homing_material
+        .as_ref()
+        .and_then(|handle| materials.get_mut(handle))
+    {
+        material.base_color = palette.color(BulletThreat::Homing);
+    }
+}
+
+fn setup_enemy(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut manager: ResMut<EnemyManager>,
+    common_assets: Res<CommonAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<Settings>,
+    palette: Res<BulletPalette>,
+    run_seed: Res<RunSeed>,
+    launch: Res<LaunchOptions>,
+    difficulty: Res<Difficulty>,
+    playlist: Res<BgmPlaylist>,
+    mut stage_start_events: EventWriter<StageStartEvent>,
+) {
+    manager.difficulty = *difficulty;
+    info!(target: "timeline", "Difficulty: {}", difficulty.label());
+    let color_code_threats = settings.gameplay.color_code_threats;
+    manager.bullet_assets = common_assets.enemy_bullets.clone();
+    if !color_code_threats {
+        for bullet_assets in manager.bullet_assets.values_mut() {
+            bullet_assets.threat_materials.clear();
+        }
+    }
+
+    manager.mesh = meshes.add(Mesh::from(shape::Cube { size: 0.1 }));
+    manager.material = materials.add(Color::rgb(0.8, 0.7, 0.6).into());
+    manager.homing_material = if color_code_threats {
+        Some(materials.add(palette.color(BulletThreat::Homing).into()))
+    } else {
+        None
+    };
+    manager.health_bar_mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::new(MINI_HEALTH_BAR_WIDTH, 0.015),
+        flip: false,
+    }));
+    manager.health_bar_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(1., 0.25, 0.15),
+        unlit: true,
+        ..Default::default()
+    });
+    manager.laser_mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::ONE,
+        flip: false,
+    }));
+    manager.laser_telegraph_material = materials.add(StandardMaterial {
+        base_color: Color::rgba(1., 0.3, 0.6, 0.4),
+        unlit: true,
+        alpha_mode: AlphaMode::Blend,
+        ..Default::default()
+    });
+    manager.laser_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(1., 0.75, 0.9),
+        unlit: true,
+        ..Default::default()
+    });
+    manager.carrier_glow_material = materials.add(StandardMaterial {
+        base_color: Color::rgba(1., 0.9, 0.3, 0.35),
+        unlit: true,
+        alpha_mode: AlphaMode::Blend,
+        ..Default::default()
+    });
+
+    let mut database = EnemyDatabase::parse().unwrap_or_else(|err| {
+        error!(target: "timeline", "Failed to parse enemy database: {}", err);
+        EnemyDatabase::default()
+    });
+    for mut descriptor in database.enemies.drain(..) {
+        descriptor.model_scene = descriptor
+            .model
+            .as_ref()
+            .map(|model| asset_server.load(model.as_str()));
+        manager.add_descriptor(descriptor);
+    }
+
+    manager.timeline.start_time = database.timeline_delay;
+    manager.timeline.waiting_continue = false;
+    manager.timeline.paused = false;
+    manager.timeline.music_clock = None;
+    manager.timeline.last_music_beat = None;
+
+    manager.timeline.events.clear();
+
+    let mut rng = run_seed.rng(TIMELINE_STREAM);
+    let enemies = ["fly_by", "6_arm_spiral", "6_arm_double_spiral_boss"];
+
+    // fly_by = often
+    {
+        let mut time = 0.;
+        let min_time = 0.15;
+        for i in 0..100 {
+            time += rng.gen_range(min_time..min_time * 1.5);
+            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
+            manager
+                .timeline
+                .events
+                .push(TimelineEvent::spawn(time, "fly_by", start_pos));
+        }
+    }
+
+    // 6_arm_spiral = sometimes
+    {
+        let mut time = 0.;
+        let min_time = 6.;
+        for i in 0..20 {
+            time += rng.gen_range(min_time..min_time * 1.5);
+            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
+            manager
+                .timeline
+                .events
+                .push(TimelineEvent::spawn(time, "6_arm_spiral", start_pos));
+        }
+    }
+
+    // asteroids = now and then
+    {
+        let mut time = 0.;
+        let min_time = 4.;
+        for i in 0..40 {
+            time += rng.gen_range(min_time..min_time * 1.5);
+            manager.timeline.events.push(TimelineEvent::new(
+                time,
+                TimelineAction::Obstacle {
+                    y: rng.gen_range(-1.7..1.7),
+                    radius: rng.gen_range(0.1..0.25),
+                },
+            ));
+        }
+    }
+
+    // 6_arm_double_spiral_boss = rarely, with a flower_mid_boss halfway through each
+    // stage, both entering on a bar of the music
+    let run_end = {
+        let mut time = 0.;
+        let min_time = 30.;
+        for i in 0..3 {
+            let stage_start = time;
+            time += rng.gen_range(min_time..min_time * 1.5);
+            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
+            manager.timeline.events.push(
+                TimelineEvent::spawn(time, "6_arm_double_spiral_boss", start_pos)
+                    .with_sync(Some(BeatSync::Bar)),
+            );
+            manager.timeline.events.push(
+                TimelineEvent::new(
+                    (stage_start + time) / 2.,
+                    TimelineAction::MidBoss {
+                        enemy: "flower_mid_boss".to_string(),
+                        start_pos: Vec3::new(5., 0.5, 0.),
+                        retreat_after: MID_BOSS_RETREAT_AFTER,
+                    },
+                )
+                .with_sync(Some(BeatSync::Bar)),
+            );
+        }
+        time
+    };
+
+    // power_carrier = regularly, so that the weapon power keeps up with the stages
+    {
+        let mut time = 0.;
+        let min_time = 12.;
+        for i in 0..15 {
+            time += rng.gen_range(min_time..min_time * 1.25);
+            let start_pos = Vec3::new(5., rng.gen_range(-1.2..1.2), 0.);
+            manager
+                .timeline
+                .events
+                .push(TimelineEvent::spawn(time, "power_carrier", start_pos));
+        }
+    }
+
+    // Authored waves, played on top of the procedural ones above and repeated up to
+    // the last boss if they don't have a count. The bosses and mid-bosses ending each
+    // stage are all procedural.
+    let mut events = Timeline::flatten(&database.timeline, run_end, &playlist.stage_grid(0));
+    events.append(&mut manager.timeline.events);
+    manager.timeline.events = events;
+
+    // Sort by time, keeping the authored events first on ties
+    manager
+        .timeline
+        .events
+        .sort_by_key(|ev| FloatOrd(ev.time as f32));
+
+    // Wait for the field to clear before each boss, for its dialogue, and set the sun
+    // for the fight
+    let mut stage = 0;
+    let mut index = 0;
+    while index < manager.timeline.events.len() {
+        let ev = &manager.timeline.events[index];
+        let is_boss = ev
+            .enemy()
+            .and_then(|enemy| manager.descriptors.get(enemy))
+            .map_or(false, |desc| desc.is_boss);
+        if is_boss {
+            stage += 1;
+            let time = ev.time;
+            let dialogue = TimelineAction::Dialogue(format!("stage{}_boss", stage));
+            manager.timeline.events.splice(
+                index..index,
+                [
+                    TimelineEvent::new(time, TimelineAction::WaitClear),
+                    TimelineEvent::new(time, dialogue),
+                    TimelineEvent::new(
+                        time,
+                        TimelineAction::Sky {
+                            time_of_day: BOSS_TIME_OF_DAY,
+                            duration: BOSS_SKY_TRANSITION_DURATION,
+                        },
+                    ),
+                ],
+            );
+            index += 3;
+        }
+        index += 1;
+    }
+
+    // The pattern preview replaces the timeline with a single spawn of the previewed
+    // enemy, at the position and along the path of its first spawn in the timeline
+    let mut preview = PatternPreview::default();
+    if let Some(enemy) = &launch.pattern {
+        if let Some(descriptor) = manager.descriptors.get(enemy) {
+            let spawn = manager
+                .timeline
+                .events
+                .iter()
+                .find_map(|ev| match &ev.action {
+                    TimelineAction::Spawn {
+                        enemy: name,
+                        start_pos,
+                        path,
+                    } if name == enemy => {
+                        Some(TimelineEvent::spawn(0., enemy, *start_pos).with_path(path.clone()))
+                    }
+                    TimelineAction::MidBoss {
+                        enemy: name,
+                        start_pos,
+                        ..
+                    } if name == enemy => Some(TimelineEvent::spawn(0., enemy, *start_pos)),
+                    _ => None,
+                })
+                .unwrap_or_else(|| TimelineEvent::spawn(0., enemy, PREVIEW_START_POS));
+            info!(target: "timeline", "Previewing the patterns of '{}'", enemy);
+            preview.params = descriptor.pattern_params();
+            preview.enemy = Some(enemy.clone());
+            manager.timeline.events = vec![spawn];
+        } else {
+            warn!(target: "timeline", "Unknown enemy '{}' to preview", enemy);
+            manager.timeline.events.clear();
+        }
+    }
+    commands.insert_resource(preview);
+
+    for (i, ev) in manager.timeline.events.iter().enumerate() {
+        trace!(target: "timeline", "[{}] t={} {:?}", i, ev.time, ev.action);
+    }
+
+    // Each boss ends a stage
+    manager.stage_count = manager
+        .timeline
+        .events
+        .iter()
+        .filter(|ev| {
+            ev.enemy()
+                .and_then(|enemy| manager.descriptors.get(enemy))
+                .map_or(false, |desc| desc.is_boss)
+        })
+        .count();
+    manager.stages_cleared = 0;
+    manager.boss_death = BossDeath::None;
+
+    // Skip the stages before the start one, resuming right after the boss ending
+    // the previous stage
+    let start_stage = launch
+        .start_stage()
+        .min(manager.stage_count.saturating_sub(1));
+    if start_stage > 0 {
+        let is_boss = |ev: &TimelineEvent| {
+            ev.enemy()
+                .and_then(|enemy| manager.descriptors.get(enemy))
+                .map_or(false, |desc| desc.is_boss)
+        };
+        let index = manager
+            .timeline
+            .events
+            .iter()
+            .enumerate()
+            .filter(|(_, ev)| is_boss(ev))
+            .nth(start_stage - 1)
+            .map(|(index, _)| index)
+            .unwrap();
+        let time = manager.timeline.events[index].time;
+        manager.timeline.index = index + 1;
+        manager.timeline.time = manager.timeline.start_time + time;
+        manager.stages_cleared = start_stage;
+        info!(target: "timeline", "Starting at stage {}", start_stage + 1);
+    }
+    if manager.stage_count > 0 {
+        stage_start_events.send(StageStartEvent {
+            stage: manager.stages_cleared,
+        });
+    }
+
+    commands.insert_resource(PatternCaptures::default());
+}
+
+/// Start position of the enemy of the pattern preview, if not spawned by the timeline.
+const PREVIEW_START_POS: Vec3 = const_vec3!([3.8, 0., 0.]);
+
+/// Time after which the mid-boss of each stage retreats if still alive, in seconds.
+const MID_BOSS_RETREAT_AFTER: f32 = 20.;
+
+/// Time of day of the boss fights, a sunset. See [`SkyTransitionEvent`].
+const BOSS_TIME_OF_DAY: f32 = 0.95;
+
+/// Duration of the sky transition at the start of a boss fight, in seconds.
+const BOSS_SKY_TRANSITION_DURATION: f32 = 8.;
+
+/// Stream of the [`RunSeed`] generator of the timeline.
+const TIMELINE_STREAM: u64 = 0x2000;
+
+/// Score bonus for capturing a boss pattern.
+const CAPTURE_BONUS: u32 = 3_000;
+
+/// Number of score items showered when capturing a boss pattern.
+const CAPTURE_MEDAL_COUNT: u32 = 20;
+
+/// Boss pattern capture statistics of the current game.
+///
+/// A pattern is captured when the player survives an entire boss phase, from the
+/// moment it starts firing, without getting hit or using a bomb.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PatternCaptures {
+    /// Number of boss patterns captured.
+    pub captured: u32,
+    /// Number of boss patterns attempted.
+    pub attempted: u32,
+}
+
+/// Event writers for the lifebars of enemies.
+#[derive(SystemParam)]
+struct LifebarEvents<'w, 's> {
+    init: EventWriter<'w, 's, InitLifebarsEvent>,
+    show: EventWriter<'w, 's, ShowLifebarsEvent>,
+    hide: EventWriter<'w, 's, HideLifebarsEvent>,
+    update: EventWriter<'w, 's, UpdateLifebarsEvent>,
+}
+
+#[derive(SystemParam)]
+struct EnemyEvents<'w, 's> {
+    killed: EventWriter<'w, 's, EnemyKilledEvent>,
+    cancel: EventWriter<'w, 's, BulletCancelEvent>,
+    log: EventWriter<'w, 's, LogEvent>,
+    sfx: EventWriter<'w, 's, SfxEvent>,
+    muzzle: EventWriter<'w, 's, MuzzleFlashEvent>,
+}
+
+/// Resources scaling and randomizing the fire tags of the enemies.
+#[derive(SystemParam)]
+struct FireTagResources<'w, 's> {
+    rank: Res<'w, Rank>,
+    game_rng: ResMut<'w, GameRng>,
+    tuning: Res<'w, Tuning>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+/// Event writers for the timeline actions handled by other modules.
+#[derive(SystemParam)]
+struct TimelineEvents<'w, 's> {
+    obstacle: EventWriter<'w, 's, SpawnObstacleEvent>,
+    sky: EventWriter<'w, 's, SkyTransitionEvent>,
+    weather: EventWriter<'w, 's, WeatherEvent>,
+}
+
+/// Feed the position of the stage music to the timeline, for the events synced to it.
+fn sync_timeline_to_music(audio_manager: Res<AudioManager>, mut manager: ResMut<EnemyManager>) {
+    let timeline = &mut manager.timeline;
+    timeline.last_music_beat = timeline.music_clock.map(|clock| clock.beat);
+    timeline.music_clock = audio_manager.music_clock();
+}
+
+/// Pause the timeline while the player decides whether to continue after losing their
+/// last life, so that a continue resumes the timeline where the player left it.
+fn pause_timeline_for_continue(
+    mut manager: ResMut<EnemyManager>,
+    mut last_life_events: EventReader<LastLifeLostEvent>,
+    mut continue_events: EventReader<ContinueEvent>,
+) {
+    if last_life_events.iter().count() > 0 {
+        debug!(target: "timeline", "TIMELINE: paused until continue");
+        manager.timeline.waiting_continue = true;
+    }
+    if continue_events.iter().count() > 0 {
+        debug!(target: "timeline", "TIMELINE: continue, resuming");
+        manager.timeline.waiting_continue = false;
+    }
+}
+
+fn update_enemy(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Name,
+            &mut EnemyController,
+            &mut Transform,
+            &mut Animator<Transform>,
+            Option<&mut MidBoss>,
+        ),
+        (Without<PlayerController>, Without<Detonate>),
+    >,
+    q_player: Query<(&Transform, &PlayerController)>,
+    mut enemy_events: EnemyEvents,
+    mut timeline_events: TimelineEvents,
+    mut fire_tag_resources: FireTagResources,
+    mut manager: ResMut<EnemyManager>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut lifebar_events: LifebarEvents,
+    mut score_events: EventWriter<ScoreEvent>,
+    mut drop_events: EventWriter<DropPickupsEvent>,
+    mut callout_events: EventWriter<CalloutEvent>,
+    mut shake_events: EventWriter<ScreenShakeEvent>,
+    mut captures: ResMut<PatternCaptures>,
+    mut dialogue: ResMut<DialogueState>,
+    cheats: Res<DebugCheats>,
+) {
+    let dt = FIXED_DELTA_SECONDS;
+
+    let player = q_player.get_single().ok();
+    let target_pos = player.map_or(Vec3::ZERO, |(transform, _)| transform.translation);
+    let misses = player.map(|(_, controller)| controller.misses());
+
+    // Execute timeline
+    let alive_enemies = query.iter().count();
+    let engaged_mid_bosses = query
+        .iter()
+        .filter(|(.., mid_boss)| {
+            mid_boss
+                .as_ref()
+                .map_or(false, |mid_boss| !mid_boss.retreating)
+        })
+        .count();
+    manager.execute_timeline(
+        dt,
+        alive_enemies,
+        engaged_mid_bosses,
+        &mut commands,
+        &mut lifebar_events.init,
+        &mut lifebar_events.show,
+        &mut timeline_events,
+        &mut dialogue,
+    );
+
+    // need to loop once per enemy, so collect all now
+    let damage_events = damage_events.iter().collect::<Vec<_>>();
+
+    for (entity, name, mut controller, mut transform, mut animator, mid_boss) in query.iter_mut() {
+        // Apply damage to enemy
+        let damage: f32 = damage_events
+            .iter()
+            .filter_map(|ev| {
+                if ev.entity == entity {
+                    Some(ev.damage)
+                } else {
+                    None
+                }
+            })
+            .sum();
+        if damage > 0. {
+            // Only down to the next phase, to still play all the phases of a boss, with
+            // a small margin to cross the phase threshold despite rounding
+            let damage = if cheats.one_hit_kill {
+                damage.max(controller.phase_remain_life() + 0.01)
+            } else {
+                damage
+            };
+            controller.remain_life -= damage;
+
+            // Update the lifebar of this enemy, if any
+            if let Some(lifebar_entity) = controller.lifebar_entity {
+                lifebar_events.update.send(UpdateLifebarsEvent {
+                    entity: lifebar_entity,
+                    remain_life: controller.remain_life,
+                });
+            }
+        }
+
+        // Award the pattern capture bonus if the player survived the whole boss phase
+        // without getting hit or bombing
+        let phase_index = controller.phase_from_life();
+        let phase_ended = controller.remain_life <= 0. || phase_index != controller.phase_index;
+        if phase_ended && controller.end_capture(misses) {
+            captures.captured += 1;
+            enemy_events.log.send(LogEvent::Capture {
+                bonus: CAPTURE_BONUS,
+            });
+            score_events.send(ScoreEvent(CAPTURE_BONUS));
+            drop_events.send(DropPickupsEvent {
+                position: transform.translation,
+                count: CAPTURE_MEDAL_COUNT,
+                kind: Some(PickupKind::Score),
+            });
+            callout_events.send(CalloutEvent::Capture(CAPTURE_BONUS));
+        }
+
+        // Dead enemies are despawned by kill_enemies()
+        if controller.remain_life <= 0. {
+            enemy_events.killed.send(EnemyKilledEvent {
+                entity,
+                position: transform.translation,
+                is_boss: controller.is_boss,
+            });
+            continue;
+        }
+
+        // Retreat a mid-boss which survived for too long, and let the timeline resume
+        if let Some(mut mid_boss) = mid_boss {
+            if !mid_boss.retreating {
+                mid_boss.retreat_timer -= dt;
+                if mid_boss.retreat_timer <= 0. {
+                    debug!(target: "timeline", "TIMELINE: mid-boss '{}' retreats", name.as_str());
+                    mid_boss.retreating = true;
+                    enemy_events
+                        .cancel
+                        .send(BulletCancelEvent { owner: entity });
+                    if let Some(lifebar_entity) = controller.lifebar_entity.take() {
+                        lifebar_events.hide.send(HideLifebarsEvent {
+                            entity: lifebar_entity,
+                        });
+                    }
+                    // No more phases nor fire, just fly away
+                    controller.phases.clear();
+                    controller.phase_index = 0;
+                    controller.phase_transition = 0.;
+                    transform.scale = Vec3::ONE;
+                    controller.fire_tag = None;
+                    controller.fire_tag_started = false;
+                    controller.motion_pattern = Some(Box::new(RetreatMotion { speed: 0. }));
+                    animator.stop();
+                    animator.state = AnimatorState::Paused;
+                    continue;
+                }
+            }
+        }
+
+        // Switch boss phase when its current lifebar is depleted
+        if phase_index != controller.phase_index {
+            enemy_events.log.send(LogEvent::Phase {
+                name: name.as_str().to_string(),
+                index: phase_index,
+            });
+            controller.phase_index = phase_index;
+            shake_events.send(ScreenShakeEvent { trauma: 0.4 });
+            let phase = controller.phases[phase_index].clone();
+
+            // Cancel all bullets of the previous phase
+            enemy_events
+                .cancel
+                .send(BulletCancelEvent { owner: entity });
+
+            controller.fire_tag = Some(manager.create_fire_tags(
+                &phase.fire_tags,
+                phase.bullet_kind,
+                &controller.fire_tag_params,
+            ));
+            controller.fire_tag_started = false;
+            controller.motion_pattern = Some(create_motion_pattern(
+                phase.motion_pattern_kind,
+                transform.translation,
+                1.5,
+                None,
+            ));
+            animator.stop();
+            animator.state = AnimatorState::Paused;
+            controller.phase_transition = PHASE_TRANSITION_DURATION;
+        }
+
+        // Play the phase transition animation, pausing motion and fire meanwhile
+        if controller.phase_transition > 0. {
+            controller.phase_transition = (controller.phase_transition - dt).max(0.);
+            let t = 1. - controller.phase_transition / PHASE_TRANSITION_DURATION;
+            transform.scale = Vec3::splat(1. + 0.8 * (t * PI).sin());
+            continue;
+        }
+
+        //println!("enemy xform={:?}", transform);
+        let was_firing = controller.fire_tag_started;
+        let result = controller.update(
+            dt,
+            entity,
+            transform.translation,
+            target_pos,
+            fire_tag_resources.rank.factor(),
+            fire_tag_resources.tuning.enemy_bullet_speed,
+            &mut fire_tag_resources.game_rng.0,
+            &mut commands,
+            &mut *transform,
+            &mut *animator,
+        );
+        let fx = std::mem::take(&mut controller.fx);
+        for sfx in fx.charge_sfx.into_iter().chain(fx.volley_sfx) {
+            enemy_events.sfx.send(SfxEvent::from_entity(sfx, entity));
+        }
+        if fx.muzzle_flash {
+            enemy_events.muzzle.send(MuzzleFlashEvent {
+                position: transform.translation,
+                direction: None,
+            });
+        }
+        if result == MotionResult::SelfDestruct {
+            enemy_events.log.send(LogEvent::SelfDestruct {
+                name: name.as_str().to_string(),
+            });
+            commands.entity(entity).insert(Detonate);
+            continue;
+        }
+
+        // Announce the attack of the current boss phase when it starts firing, and
+        // start tracking its capture
+        if !was_firing && controller.fire_tag_started {
+            if let Some(name) = controller
+                .phases
+                .get(controller.phase_index)
+                .and_then(|phase| phase.attack_name.clone())
+            {
+                callout_events.send(CalloutEvent::Attack(name));
+            }
+            if controller.is_boss && misses.is_some() {
+                controller.capture_misses = misses;
+                captures.attempted += 1;
+            }
+        }
+    }
+}
+
+/// Destructible part of a multi-part enemy, following its owner at a fixed offset and
+/// firing only while its owner fires. See [`PartDescriptor`].
+#[derive(Component)]
+struct EnemyPart {
+    /// Enemy owning the part, whose death destroys the part too.
+    owner: Entity,
+    /// Position of the part relative to its owner.
+    offset: Vec3,
+}
+
+/// Follower of a formation, moving along with its leader at a fixed offset and firing
+/// only while its leader fires, instead of following its own motion pattern. See
+/// [`FormationDescriptor`].
+#[derive(Component)]
+struct FormationMember {
+    /// Enemy leading the formation.
+    leader: Entity,
+    /// Position of the follower relative to its leader.
+    offset: Vec3,
+}
+
+/// Move the followers of formations with their leader. Once the leader is gone, the
+/// followers scatter, each flying by on its own.
+fn update_formations(
+    mut commands: Commands,
+    mut q_members: Query<(
+        Entity,
+        &FormationMember,
+        &mut Transform,
+        &mut EnemyController,
+        &mut Animator<Transform>,
+    )>,
+    q_leaders: Query<(&Transform, &EnemyController), Without<FormationMember>>,
+) {
+    for (entity, member, mut transform, mut controller, mut animator) in q_members.iter_mut() {
+        match q_leaders.get(member.leader) {
+            Ok((leader_transform, leader_controller)) if leader_controller.remain_life > 0. => {
+                transform.translation = leader_transform.translation + member.offset;
+                if controller.motion_pattern.is_some() {
+                    // Drop the motion started on spawn, before joining the formation
+                    controller.motion_pattern = None;
+                    animator.stop();
+                    animator.state = AnimatorState::Paused;
+                }
+                controller.fire_tag_started = leader_controller.fire_tag_started;
+            }
+            _ => {
+                controller.motion_pattern = Some(create_motion_pattern(
+                    MotionPatternKind::FlyBy,
+                    transform.translation,
+                    5.,
+                    None,
+                ));
+                controller.fire_tag_started = true;
+                animator.stop();
+                animator.state = AnimatorState::Paused;
+                commands.entity(entity).remove::<FormationMember>();
+            }
+        }
+    }
+}
+
+/// Move the parts of multi-part enemies with their owner, and start or stop firing
+/// along with it.
+fn update_enemy_parts(
+    mut commands: Commands,
+    mut q_parts: Query<(Entity, &EnemyPart, &mut Transform, &mut EnemyController)>,
+    q_owners: Query<(&Transform, &EnemyController), Without<EnemyPart>>,
+) {
+    for (entity, part, mut transform, mut controller) in q_parts.iter_mut() {
+        if let Ok((owner_transform, owner_controller)) = q_owners.get(part.owner) {
+            transform.translation = owner_transform.translation + part.offset;
+            controller.fire_tag_started =
+                owner_controller.fire_tag_started && owner_controller.phase_transition <= 0.;
+        } else {
+            // Owner left without being killed, like when culled
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+/// Apply all the side effects of the enemies killed during this step, like the score,
+/// the drops and the explosion, start the death sequence when a boss dies, then
+/// despawn them along with their remaining parts.
+fn kill_enemies(
+    mut commands: Commands,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    query: Query<(&Name, &EnemyController)>,
+    q_parts: Query<(Entity, &EnemyPart, &Transform)>,
+    mut manager: ResMut<EnemyManager>,
+    mut combo: ResMut<Combo>,
+    mut log_events: EventWriter<LogEvent>,
+    mut cancel_events: EventWriter<BulletCancelEvent>,
+    mut score_award: ScoreAward,
+    mut drop_events: EventWriter<DropPickupsEvent>,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+    mut sfx_events: EventWriter<SfxEvent>,
+    mut hide_events: EventWriter<HideLifebarsEvent>,
+    mut shake_events: EventWriter<ScreenShakeEvent>,
+    mut rumble_events: EventWriter<RumbleRequest>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    for ev in killed_events.iter() {
+        let (name, controller) = if let Ok(enemy) = query.get(ev.entity) {
+            enemy
+        } else {
+            continue;
+        };
+        log_events.send(LogEvent::Kill {
+            name: name.as_str().to_string(),
+            is_boss: controller.is_boss,
+        });
+        cancel_events.send(BulletCancelEvent { owner: ev.entity });
+        score_award.award(
+            combo.add_kill(controller.kill_score),
+            ev.position,
+            PopupKind::Kill,
+        );
+        if let Some(kinds) = controller.roll_drops(&mut game_rng.0) {
+            for kind in kinds {
+                drop_events.send(DropPickupsEvent {
+                    position: ev.position,
+                    count: 1,
+                    kind: Some(kind),
+                });
+            }
+        } else {
+            drop_events.send(DropPickupsEvent {
+                position: ev.position,
+                count: if controller.is_boss { 12 } else { 2 },
+                kind: None,
+            });
+        }
+        explosion_events.send(ExplosionEvent {
+            position: ev.position,
+            kind: if controller.is_boss {
+                ExplosionKind::Boss
+            } else {
+                ExplosionKind::Enemy
+            },
+        });
+        let sfx = if controller.is_boss {
+            Sfx::BossExplosion
+        } else {
+            Sfx::Explosion
+        };
+        sfx_events.send(SfxEvent::from_entity(sfx, ev.entity));
+        if let Some(lifebar_entity) = controller.lifebar_entity {
+            hide_events.send(HideLifebarsEvent {
+                entity: lifebar_entity,
+            });
+        }
+        if controller.is_boss {
+            shake_events.send(ScreenShakeEvent { trauma: 1. });
+            rumble_events.send(RumbleRequest {
+                strong: 1.,
+                weak: 1.,
+                duration: 1.2,
+            });
+            manager.boss_death = BossDeath::Killed;
+        }
+        // Remaining parts go down with the core, without awarding their score
+        for (part_entity, part, transform) in q_parts.iter() {
+            if part.owner == ev.entity {
+                cancel_events.send(BulletCancelEvent { owner: part_entity });
+                explosion_events.send(ExplosionEvent {
+                    position: transform.translation,
+                    kind: ExplosionKind::Enemy,
+                });
+                commands.entity(part_entity).despawn_recursive();
+            }
+        }
+        commands.entity(ev.entity).despawn_recursive();
+    }
+}
+
+/// Distance of the boss lifebars from the top edge of the screen while visible, in
+/// percent of the screen height. See [`LifebarHud::spawn()`].
+const BOSS_LIFEBAR_MARGIN: f32 = 2.2;
+
+/// Spawn the hidden lifebar HUD of a boss, or the shorter one of a mid-boss, stacked
+/// below the other ones if shown at the same time. The lifebar is despawned once
+/// hidden again, after its owner died or retreated.
+fn spawn_boss_lifebar(
+    commands: &mut Commands,
+    is_boss: bool,
+    life_per_bar: f32,
+    colors: &[Color],
+) -> Entity {
+    let mut lifebars = LifebarHud::default();
+    lifebars.orientation = BarOrientation::Horizontal;
+    lifebars.size = if is_boss {
+        Vec2::new(72., 1.6)
+    } else {
+        Vec2::new(40., 1.2)
+    };
+    lifebars.bar_size = Vec2::new(99.75, 80.);
+    lifebars.despawn_when_hidden = true;
+    lifebars.set_lifebars(life_per_bar, colors.iter().copied());
+    LifebarHud::spawn(
+        lifebars,
+        if is_boss {
+            "BossLifebar"
+        } else {
+            "MidBossLifebar"
+        },
+        LifebarAnchor::TopCenter,
+        BOSS_LIFEBAR_MARGIN,
+        commands,
+    )
+}
+
+/// Speed of the gameplay during the death of a boss, relative to real time.
+const BOSS_DEATH_TIME_SCALE: f32 = 0.3;
+
+/// Duration of the slow motion when a boss dies, in seconds of real time.
+const BOSS_DEATH_SLOW_MOTION_DURATION: f32 = 1.;
+
+/// Play the death sequence of a boss: slow the game down and cancel all the enemy
+/// bullets while the boss blows up, then clear the stage once back to full speed.
+fn update_boss_death(
+    mut manager: ResMut<EnemyManager>,
+    mut time_scale: ResMut<TimeScale>,
+    q_owners: Query<&BulletOwner, Without<PendingDespawn>>,
+    mut cancel_events: EventWriter<BulletCancelEvent>,
+    mut stage_clear_events: EventWriter<StageClearEvent>,
+    mut stage_start_events: EventWriter<StageStartEvent>,
+) {
+    match manager.boss_death {
+        BossDeath::None => {}
+        BossDeath::Killed => {
+            time_scale.start_slow_motion(BOSS_DEATH_TIME_SCALE, BOSS_DEATH_SLOW_MOTION_DURATION);
+            let mut owners: Vec<Entity> = vec![];
+            for owner in q_owners.iter() {
+                if !owners.contains(&owner.0) {
+                    owners.push(owner.0);
+                }
+            }
+            for owner in owners {
+                cancel_events.send(BulletCancelEvent { owner });
+            }
+            manager.boss_death = BossDeath::SlowMotion;
+        }
+        BossDeath::SlowMotion => {
+            if time_scale.is_slow_motion() {
+                return;
+            }
+            manager.boss_death = BossDeath::None;
+            stage_clear_events.send(StageClearEvent {
+                stage: manager.stages_cleared,
+                is_last: manager.stages_cleared + 1 >= manager.stage_count,
+            });
+            manager.stages_cleared += 1;
+            if manager.stages_cleared < manager.stage_count {
+                stage_start_events.send(StageStartEvent {
+                    stage: manager.stages_cleared,
+                });
+            }
+        }
+    }
+}
+
+/// Apply the commands of the debug console.
+fn apply_debug_commands(
+    mut commands: Commands,
+    mut debug_commands: EventReader<DebugEnemyCommand>,
+    mut manager: ResMut<EnemyManager>,
+    query: Query<(Entity, &Transform, &EnemyController)>,
+    q_clear: Query<
+        (Entity, Option<&BulletOwner>),
+        (
+            Or<(With<EnemyController>, With<EnemyPart>, With<BulletOwner>)>,
+            Without<PendingDespawn>,
+        ),
+    >,
+    mut lifebar_events: LifebarEvents,
+    mut damage_events: EventWriter<DamageEvent>,
+    preview: Res<PatternPreview>,
+) {
+    // Start over from a clean screen
+    let mut clear_screen = |commands: &mut Commands| {
+        for (_, _, controller) in query.iter() {
+            if let Some(lifebar_entity) = controller.lifebar_entity {
+                lifebar_events.hide.send(HideLifebarsEvent {
+                    entity: lifebar_entity,
+                });
+            }
+        }
+        for (entity, owner) in q_clear.iter() {
+            if owner.is_some() {
+                commands.entity(entity).insert(PendingDespawn);
+            } else {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    };
+
+    for command in debug_commands.iter() {
+        match command {
+            DebugEnemyCommand::Spawn { enemy, position } => {
+                if !manager.descriptors.contains_key(enemy) {
+                    warn!(target: "debug", "Unknown enemy '{}'", enemy);
+                    continue;
+                }
+                info!(target: "debug", "Spawning '{}' at {}", enemy, position);
+                manager.spawn(
+                    &mut commands,
+                    &mut lifebar_events.init,
+                    &mut lifebar_events.show,
+                    enemy,
+                    *position,
+                    None,
+                    None,
+                );
+            }
+            DebugEnemyCommand::KillAll => {
+                info!(target: "debug", "Killing {} enemies", query.iter().count());
+                for (entity, transform, controller) in query.iter() {
+                    damage_events.send(DamageEvent {
+                        entity,
+                        damage: controller.remain_life,
+                        source: DamageSource::PlayerBullet,
+                        position: transform.translation,
+                    });
+                }
+            }
+            DebugEnemyCommand::GotoTime(time) => {
+                let time = time.max(0.);
+                let index = manager
+                    .timeline
+                    .events
+                    .iter()
+                    .position(|ev| ev.time >= time)
+                    .unwrap_or(manager.timeline.events.len());
+                manager.seek(index, time);
+                info!(target: "debug", "Timeline moved to t={} (event #{})", time, index);
+            }
+            DebugEnemyCommand::Seek { index, pause } => {
+                let time = manager.timeline.events.get(*index).map_or_else(
+                    || manager.timeline.time - manager.timeline.start_time,
+                    |ev| ev.time,
+                );
+                manager.seek(*index, time);
+                manager.timeline.paused = *pause;
+                clear_screen(&mut commands);
+                info!(target: "debug", "Timeline seeked to event #{} (t={})", index, time);
+            }
+            DebugEnemyCommand::SetTimelinePaused(paused) => {
+                manager.timeline.paused = *paused;
+            }
+            DebugEnemyCommand::RestartPreview => {
+                let enemy = match &preview.enemy {
+                    Some(enemy) => enemy,
+                    None => continue,
+                };
+                if let Some(descriptor) = manager.descriptors.get_mut(enemy) {
+                    descriptor.apply_pattern_params(&preview.params);
+                }
+                // Replay the single spawn of the preview timeline
+                manager.seek(0, 0.);
+                manager.timeline.paused = false;
+                clear_screen(&mut commands);
+                info!(target: "debug", "Restarting the preview of '{}'", enemy);
+            }
+        }
+    }
+}
+
+/// Rebuild the timeline view of the new game, whose events depend on the run seed.
+fn reset_timeline_view(mut view: ResMut<TimelineView>) {
+    view.events.clear();
+}
+
+fn update_timeline_view(manager: Res<EnemyManager>, mut view: ResMut<TimelineView>) {
+    let timeline = &manager.timeline;
+    view.time = timeline.time - timeline.start_time;
+    view.index = timeline.index;
+    view.paused = timeline.paused;
+    if view.events.len() != timeline.events.len() {
+        view.events = timeline
+            .events
+            .iter()
+            .map(|ev| (ev.time, ev.describe()))
+            .collect();
+    }
+}
+
+fn log_enemy_spawns(
+    query: Query<&Name, Added<EnemyController>>,
+    mut log_events: EventWriter<LogEvent>,
+) {
+    for name in query.iter() {
+        log_events.send(LogEvent::Spawn {
+            name: name.as_str().to_string(),
+        });
+    }
+}
+
+/// Move the laser beams with their owner, activate them once done telegraphing, and
+/// despawn them once done sweeping or if their owner died.
+fn update_laser_beams(
+    mut commands: Commands,
+    mut q_beams: Query<
+        (Entity, &mut LaserBeam, &BulletOwner, &mut Transform),
+        Without<PendingDespawn>,
+    >,
+    q_owners: Query<&Transform, (With<EnemyController>, Without<LaserBeam>)>,
+    mut q_visuals: Query<
+        (&mut Transform, &mut Handle<StandardMaterial>),
+        (Without<LaserBeam>, Without<EnemyController>),
+    >,
+    game_time: Res<GameTime>,
+) {
+    let dt = game_time.delta_seconds();
+    for (entity, mut beam, owner, mut transform) in q_beams.iter_mut() {
+        beam.time += dt;
+        let origin = if let Ok(owner_transform) = q_owners.get(owner.0) {
+            owner_transform.translation
+        } else {
+            commands.entity(entity).insert(PendingDespawn);
+            continue;
+        };
+        if beam.time >= beam.telegraph_duration + beam.sweep_duration {
+            commands.entity(entity).insert(PendingDespawn);
+            continue;
+        }
+
+        if !beam.active && beam.time >= beam.telegraph_duration {
+            beam.active = true;
+            if let Ok((mut visual_transform, mut material)) = q_visuals.get_mut(beam.visual) {
+                visual_transform.scale.y = beam.width;
+                *material = beam.beam_material.clone();
+            }
+            commands
+                .entity(entity)
+                .insert(RigidBody::Sensor)
+                .insert(CollisionShape::Cuboid {
+                    half_extends: Vec3::new(beam.length / 2., beam.width / 2., 0.),
+                    border_radius: None,
+                })
+                .insert(
+                    CollisionLayers::none()
+                        .with_group(Layer::EnemyBullet)
+                        .with_masks(&[Layer::Player]),
+                )
+                .insert(DamageOverTime::new(beam.damage_per_second));
+        }
+
+        *transform = beam.transform(origin);
+    }
+}
+
+/// Turn the bullets of the enemies listed in the [`BulletCancelEvent`]s into stars,
+/// and despawn any other projectile they own, like laser beams.
+fn cancel_bullets(
+    mut commands: Commands,
+    mut cancel_events: EventReader<BulletCancelEvent>,
+    q_owned: Query<(Entity, &BulletOwner, &Transform, Option<&Bullet>), Without<PendingDespawn>>,
+    mut drop_events: EventWriter<DropPickupsEvent>,
+) {
+    let owners: Vec<Entity> = cancel_events.iter().map(|ev| ev.owner).collect();
+    if owners.is_empty() {
+        return;
+    }
+    for (entity, owner, transform, bullet) in q_owned.iter() {
+        if !owners.contains(&owner.0) {
+            continue;
+        }
+        if bullet.is_some() {
+            drop_events.send(DropPickupsEvent {
+                position: transform.translation,
+                count: 1,
+                kind: Some(PickupKind::Star),
+            });
+        }
+        commands.entity(entity).insert(PendingDespawn);
+    }
+}
+
+/// Apply the [`BulletMotion`] of the enemy bullets to their velocity and direction,
+/// before they move.
+fn update_bullet_motions(
+    mut query: Query<(&mut Transform, &mut Bullet, &mut BulletMotion), With<EnemyBullet>>,
+    q_player: Query<&Transform, (With<PlayerController>, Without<EnemyBullet>)>,
+) {
+    let dt = FIXED_DELTA_SECONDS;
+    let player_position = q_player
+        .get_single()
+        .ok()
+        .map(|transform| transform.translation);
+    for (mut transform, mut bullet, mut motion) in query.iter_mut() {
+        motion.time += dt;
+
+        // Velocity is in the local space of the bullet, along its X axis
+        let mut speed = bullet.0.length();
+        if motion.acceleration != 0. {
+            speed = (speed + motion.acceleration * dt).clamp(motion.min_speed, motion.max_speed);
+        }
+        if motion.curve != 0. {
+            transform.rotation *= Quat::from_rotation_z(motion.curve.to_radians() * dt);
+        }
+        if let Some(aim_delay) = motion.aim_delay {
+            if motion.time >= aim_delay {
+                motion.aim_delay = None;
+                if let Some(player_position) = player_position {
+                    let to_player = (player_position - transform.translation).truncate();
+                    transform.rotation = Quat::from_rotation_z(to_player.y.atan2(to_player.x));
+                }
+                if let Some(aim_speed) = motion.aim_speed {
+                    speed = aim_speed;
+                }
+            }
+        }
+        bullet.0 = Vec3::X * speed;
+    }
+}
+
+/// Marker for an enemy which self-destructed, to be blown up by [`detonate_enemies()`].
+#[derive(Component)]
+struct Detonate;
+
+/// Blow up the enemies which self-destructed, damaging the player if caught in the blast.
+fn detonate_enemies(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform), (With<Detonate>, Without<PendingDespawn>)>,
+    q_player: Query<(Entity, &Transform, &PlayerController)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut explosion_events: EventWriter<ExplosionEvent>,
+    mut sfx_events: EventWriter<SfxEvent>,
+) {
+    for (entity, transform) in query.iter() {
+        let position = transform.translation;
+        explosion_events.send(ExplosionEvent {
+            position,
+            kind: ExplosionKind::Enemy,
+        });
+        sfx_events.send(SfxEvent::from_entity(Sfx::Explosion, entity));
+        if let Ok((player_entity, player_transform, controller)) = q_player.get_single() {
+            if controller.is_vulnerable()
+                && player_transform.translation.distance(position) < DETONATE_BLAST_RADIUS
+            {
+                damage_events.send(DamageEvent {
+                    entity: player_entity,
+                    damage: DETONATE_DAMAGE,
+                    source: DamageSource::Explosion,
+                    position,
+                });
+            }
         }
+        commands.entity(entity).insert(PendingDespawn);
     }
 }
 
-struct FlyByMotion {
-    start: Vec3,
-    direction: Vec3,
-    has_fired: bool,
-}
+/// Distance beyond the screen bounds past which a non-boss enemy is despawned, in world
+/// units.
+const ENEMY_CULL_MARGIN: f32 = 0.5;
 
-impl Default for FlyByMotion {
-    fn default() -> Self {
-        FlyByMotion {
-            start: Vec3::ZERO,
-            direction: Vec3::ZERO,
-            has_fired: false,
+/// Despawn regular enemies which left the screen, so that they don't block the timeline
+/// when it waits for all enemies to clear.
+fn despawn_enemies_outside_screen(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, &EnemyController), Without<PendingDespawn>>,
+    q_camera: Query<&MainCamera>,
+) {
+    let bounds = if let Ok(camera) = q_camera.get_single() {
+        camera.screen_bounds
+    } else {
+        return;
+    };
+    // Enemies spawn beyond the right edge, so only check the other edges
+    for (entity, transform, controller) in query.iter() {
+        let pos = transform.translation;
+        if !controller.is_boss
+            && (pos.x < bounds.left - ENEMY_CULL_MARGIN
+                || pos.y < bounds.bottom - ENEMY_CULL_MARGIN
+                || pos.y > bounds.top + ENEMY_CULL_MARGIN)
+        {
+            commands.entity(entity).insert(PendingDespawn);
         }
     }
 }
 
-impl MotionPattern for FlyByMotion {
-    fn do_motion(
-        &mut self,
-        dt: f32,
-        transform: &mut Transform,
-        animator: &mut Animator<Transform>,
-    ) -> MotionResult {
-        match &animator.state {
-            AnimatorState::Paused => {
-                let tween = Tween::new(
-                    EaseFunction::QuadraticOut,
-                    TweeningType::Once,
-                    Duration::from_secs_f32(5.),
-                    TransformPositionLens {
-                        start: self.start,
-                        end: self.start + self.direction * 6.,
-                    },
-                );
-                animator.set_tweenable(tween);
-                animator.state = AnimatorState::Playing;
-                MotionResult::DoNothing
-            }
-            AnimatorState::Playing => {
-                if !self.has_fired && animator.progress() >= 0.3 {
-                    self.has_fired = true;
-                    MotionResult::StartFireTag
-                } else {
-                    MotionResult::DoNothing
-                }
-            }
+/// Small health bar floating above a non-boss enemy, as a child of the enemy entity.
+#[derive(Component)]
+struct MiniHealthBar;
+
+/// Width of a [`MiniHealthBar`] at full health, in world units.
+const MINI_HEALTH_BAR_WIDTH: f32 = 0.16;
+
+/// Vertical offset of a [`MiniHealthBar`] above its enemy, in world units.
+const MINI_HEALTH_BAR_OFFSET: f32 = 0.12;
+
+fn update_mini_health_bars(
+    mut q_bars: Query<(&Parent, &mut Transform, &mut Visibility), With<MiniHealthBar>>,
+    q_enemies: Query<&EnemyController>,
+) {
+    for (parent, mut transform, mut visibility) in q_bars.iter_mut() {
+        if let Ok(controller) = q_enemies.get(parent.0) {
+            let ratio = (controller.remain_life / controller.life.max(1e-3)).clamp(0., 1.);
+            // Hide at full health to reduce clutter
+            visibility.is_visible = ratio < 1.;
+            // Scale and keep left-aligned
+            transform.scale.x = ratio.max(1e-3);
+            transform.translation.x = -(1. - ratio) * MINI_HEALTH_BAR_WIDTH / 2.;
         }
     }
 }
 
+/// Duration of the flash of an enemy taking a hit, in seconds.
+const HIT_FLASH_DURATION: f32 = 0.1;
+
+/// Emissive color of an enemy at the start of a hit flash.
+const HIT_FLASH_COLOR: Color = Color::rgb(1., 0.55, 0.45);
+
+/// Scale of an enemy at the start of a hit flash.
+const HIT_FLASH_SCALE: f32 = 1.12;
+
+/// Flash of an enemy which just took a hit, fading out over [`HIT_FLASH_DURATION`].
 #[derive(Component)]
-struct EnemyController {
-    motion_pattern: Option<Box<dyn MotionPattern + Send + Sync>>,
-    fire_tag: Option<Box<dyn FireTag + Send + Sync>>,
-    fire_tag_started: bool,
-    life: f32,
-    remain_life: f32,
-    is_boss: bool,
-    kill_score: u32,
+struct HitFlash {
+    /// Remaining time of the flash, in seconds.
+    remaining: f32,
 }
 
-impl Default for EnemyController {
-    fn default() -> Self {
-        EnemyController {
-            motion_pattern: None,
-            fire_tag: None,
-            fire_tag_started: false,
-            life: 0.,
-            remain_life: 0.,
-            is_boss: false,
-            kill_score: 1,
+/// GLTF scene rendered for an enemy, spawned below the entity `scene_root` child of
+/// the enemy. The enemy entity itself has no material.
+#[derive(Component)]
+struct EnemyModel {
+    scene_root: Entity,
+    /// Own copies of the materials of the scene meshes, see [`setup_model_materials()`].
+    materials: Vec<Handle<StandardMaterial>>,
+}
+
+/// Give each newly spawned enemy its own copy of its material, so that it can flash
+/// independently of the other enemies sharing the same material.
+fn setup_enemy_materials(
+    mut query: Query<&mut Handle<StandardMaterial>, Added<EnemyController>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for mut handle in query.iter_mut() {
+        if let Some(material) = materials.get(&*handle).cloned() {
+            *handle = materials.add(material);
         }
     }
 }
 
-impl EnemyController {
-    fn update(
-        &mut self,
-        dt: f32,
-        origin: Vec3,
-        player_position: Vec3,
-        commands: &mut Commands,
-        transform: &mut Transform,
-        animator: &mut Animator<Transform>,
-    ) {
-        // Move
-        if let Some(motion_pattern) = &mut self.motion_pattern {
-            if motion_pattern.do_motion(dt, transform, animator) == MotionResult::StartFireTag {
-                self.fire_tag_started = true;
+/// Give the meshes of the GLTF scene of each enemy their own copy of their material,
+/// once the scene is spawned, for the same reason as [`setup_enemy_materials()`].
+fn setup_model_materials(
+    mut q_materials: Query<
+        (Entity, &mut Handle<StandardMaterial>),
+        Added<Handle<StandardMaterial>>,
+    >,
+    q_parents: Query<&Parent>,
+    mut q_models: Query<&mut EnemyModel>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, mut handle) in q_materials.iter_mut() {
+        // The scene root is a direct child of the enemy
+        let is_scene_root = |ancestor: Entity| {
+            q_parents.get(ancestor).map_or(false, |parent| {
+                q_models
+                    .get(parent.0)
+                    .map_or(false, |model| model.scene_root == ancestor)
+            })
+        };
+        let scene_root = own_scene_material(
+            entity,
+            &mut handle,
+            &q_parents,
+            is_scene_root,
+            &mut materials,
+        );
+        if let Some(parent) = scene_root.and_then(|scene_root| q_parents.get(scene_root).ok()) {
+            if let Ok(mut model) = q_models.get_mut(parent.0) {
+                model.materials.push(handle.clone());
             }
         }
+    }
+}
 
-        // Fire
-        if self.fire_tag_started {
-            //println!("ENEMY_UPDATE: dt={} origin={:?}", dt, origin);
-            let mut context = FireTagContext::new(dt, origin, player_position, commands);
-            if let Some(fire_tag) = &mut self.fire_tag {
-                fire_tag.execute(&mut context);
-            }
+/// Start or restart the hit flash of all enemies damaged this frame.
+fn start_hit_flashes(
+    mut commands: Commands,
+    mut damage_events: EventReader<DamageEvent>,
+    q_enemies: Query<(), (With<EnemyController>, Without<PendingDespawn>)>,
+) {
+    for ev in damage_events.iter() {
+        if ev.damage > 0. && q_enemies.get(ev.entity).is_ok() {
+            commands.entity(ev.entity).insert(HitFlash {
+                remaining: HIT_FLASH_DURATION,
+            });
         }
     }
 }
 
-fn setup_enemy(
+/// Fade out the hit flash of enemies, tinting their material and nudging their scale.
+fn update_hit_flashes(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut manager: ResMut<EnemyManager>,
-    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(
+        Entity,
+        &mut HitFlash,
+        &EnemyController,
+        &mut Transform,
+        Option<&Handle<StandardMaterial>>,
+        Option<&EnemyModel>,
+    )>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    game_time: Res<GameTime>,
+    settings: Res<Settings>,
 ) {
-    manager.bullet_assets.insert(
-        BulletKind::PinkDonut,
-        BulletAssets {
-            mesh: meshes.add(Mesh::from(Quad { size: 0.1 })),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("textures/bullet2.png")),
-                //emissive: Color::RED,
-                unlit: true,
-                alpha_mode: AlphaMode::Blend,
-                ..Default::default()
-            }),
-        },
-    );
-    manager.bullet_assets.insert(
-        BulletKind::WhiteBall,
-        BulletAssets {
-            mesh: meshes.add(Mesh::from(Quad { size: 0.08 })),
-            material: materials.add(StandardMaterial {
-                base_color_texture: Some(asset_server.load("textures/bullet3.png")),
-                //emissive: Color::WHITE,
-                unlit: true,
-                alpha_mode: AlphaMode::Blend,
-                ..Default::default()
-            }),
-        },
-    );
-
-    // FIXME - Copied from game.rs :(
-    let hud_mat_black = materials.add(StandardMaterial {
-        base_color: Color::BLACK,
-        unlit: true,
-        alpha_mode: AlphaMode::Blend,
-        ..Default::default()
-    });
+    let dt = game_time.delta_seconds();
+    // Sustained fire retriggers the flash many times per second, so only keep the
+    // scale nudge when flashing is reduced
+    let flash_color = if settings.accessibility.reduce_flashing {
+        Color::BLACK
+    } else {
+        HIT_FLASH_COLOR
+    };
+    for (entity, mut flash, controller, mut transform, material, model) in query.iter_mut() {
+        flash.remaining = (flash.remaining - dt).max(0.);
+        let ratio = flash.remaining / HIT_FLASH_DURATION;
 
-    // Boss lifebars
-    let mut boss_lifebars = LifebarHud::default();
-    boss_lifebars.orientation = LifebarOrientation::Horizontal;
-    //boss_lifebars.visible_pos = Vec2::new(0., screen_bounds.top + lifebar_margin_v);
-    //boss_lifebars.hidden_pos = Vec2::new(0., screen_bounds.top - lifebar_margin_v);
-    boss_lifebars.visible_pos = Vec2::new(0., 1.5); // TODO
-    boss_lifebars.hidden_pos = Vec2::new(0., 2.0); // TODO
-    boss_lifebars.set_lifebars(40.0, [Color::RED, Color::ORANGE, Color::YELLOW]);
-    let boss_lifebar_entity = LifebarHud::spawn(
-        boss_lifebars,
-        "BossLifebar",
-        Vec2::new(4.01, 0.05),
-        hud_mat_black.clone(),
-        Vec2::new(4., 0.04),
-        &mut commands,
-        &mut *meshes,
-        &mut *materials,
-    );
+        // Enemies with a GLTF model have their materials on the meshes of their scene
+        let scene_materials = model.map_or(&[][..], |model| &model.materials[..]);
+        for handle in material.into_iter().chain(scene_materials) {
+            if let Some(material) = materials.get_mut(handle) {
+                material.emissive = flash_color * ratio;
+            }
+        }
 
-    manager.mesh = meshes.add(Mesh::from(shape::Cube { size: 0.1 }));
-    manager.material = materials.add(Color::rgb(0.8, 0.7, 0.6).into());
-    manager.boss_lifebar_entity = boss_lifebar_entity;
+        // Don't fight with the boss phase transition animation, which scales too
+        if controller.phase_transition <= 0. {
+            transform.scale = Vec3::splat(1. + (HIT_FLASH_SCALE - 1.) * ratio);
+        }
 
-    let mut database: EnemyDatabase =
-        serde_json::from_str(&include_str!("../assets/enemy_db.json")).unwrap();
-    for descriptor in database.enemies.drain(..) {
-        manager.add_descriptor(descriptor);
+        if flash.remaining <= 0. {
+            commands.entity(entity).remove::<HitFlash>();
+        }
     }
+}
 
-    manager.timeline.start_time = database.timeline_delay;
-    //manager.timeline.events = database.timeline;
-
-    let mut rng = thread_rng();
-    let enemies = ["fly_by", "6_arm_spiral", "6_arm_double_spiral_boss"];
+/// Event to display a banner announcing a boss attack or a pattern capture.
+enum CalloutEvent {
+    /// A boss phase started firing the attack with the given name.
+    Attack(String),
+    /// The player captured a boss pattern, and was awarded the given bonus.
+    Capture(u32),
+}
 
-    // fly_by = often
-    {
-        let mut time = 0.;
-        let min_time = 0.15;
-        for i in 0..100 {
-            time += rng.gen_range(min_time..min_time * 1.5);
-            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
-            manager.timeline.events.push(TimelineEvent {
-                time,
-                enemy: "fly_by".into(),
-                start_pos,
-            });
-        }
-    }
+#[derive(Component)]
+struct AttackCallout;
 
-    // 6_arm_spiral = sometimes
-    {
-        let mut time = 0.;
-        let min_time = 6.;
-        for i in 0..20 {
-            time += rng.gen_range(min_time..min_time * 1.5);
-            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
-            manager.timeline.events.push(TimelineEvent {
-                time,
-                enemy: "6_arm_spiral".into(),
-                start_pos,
-            });
-        }
-    }
+#[derive(Component)]
+struct CaptureBanner;
 
-    // 6_arm_double_spiral_boss = rarely
-    {
-        let mut time = 0.;
-        let min_time = 30.;
-        for i in 0..3 {
-            time += rng.gen_range(min_time..min_time * 1.5);
-            let start_pos = Vec3::new(5., rng.gen_range(-1.5..1.5), 0.);
-            manager.timeline.events.push(TimelineEvent {
-                time,
-                enemy: "6_arm_double_spiral_boss".into(),
-                start_pos,
-            });
-        }
-    }
+const ATTACK_CALLOUT_COLOR: Color = Color::rgb(0.85, 0.1, 0.45);
+const CAPTURE_BANNER_COLOR: Color = Color::rgb(0.95, 0.7, 0.1);
 
-    // Sort by time
-    manager
-        .timeline
-        .events
-        .sort_by_key(|ev| FloatOrd(ev.time as f32));
-    for (i, ev) in manager.timeline.events.iter().enumerate() {
-        println!(
-            "[{}] t={} enemy={} start_pos={:?}",
-            i, ev.time, ev.enemy, ev.start_pos
-        );
-    }
+fn setup_attack_callout(mut commands: Commands, common_assets: Res<CommonAssets>) {
+    let font = common_assets.hud_font.clone();
+    spawn_banner(
+        &mut commands,
+        font.clone(),
+        90.,
+        "AttackCallout",
+        AttackCallout,
+    );
+    spawn_banner(&mut commands, font, 140., "CaptureBanner", CaptureBanner);
 }
 
-fn update_enemy(
-    mut commands: Commands,
-    mut query: Query<
-        (
-            Entity,
-            &mut EnemyController,
-            &mut Transform,
-            &mut Animator<Transform>,
-        ),
-        Without<PlayerController>,
-    >,
-    q_player: Query<&Transform, With<PlayerController>>,
-    time: Res<Time>,
-    mut manager: ResMut<EnemyManager>,
-    mut damage_events: EventReader<DamageEvent>,
-    mut init_events: EventWriter<InitLifebarsEvent>,
-    mut show_events: EventWriter<ShowLifebarsEvent>,
-    mut lifebar_events: EventWriter<UpdateLifebarsEvent>,
-    mut score_events: EventWriter<ScoreEvent>,
+/// Spawn an horizontally centered banner text, initially transparent.
+fn spawn_banner(
+    commands: &mut Commands,
+    font: Handle<Font>,
+    top: f32,
+    name: &str,
+    marker: impl Component,
 ) {
-    //println!("update_enemy() t={}", time.seconds_since_startup());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(top),
+                    left: Val::Px(0.0),
+                    right: Val::Px(0.0),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new(name.to_string()))
+        .insert(InGameEntity)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font,
+                            font_size: 40.0,
+                            color: Color::NONE,
+                        },
+                        TextAlignment {
+                            horizontal: HorizontalAlign::Center,
+                            ..Default::default()
+                        },
+                    ),
+                    ..Default::default()
+                })
+                .insert(Animator::<Text>::default().with_state(AnimatorState::Paused))
+                .insert(marker);
+        });
+}
 
-    let dt = time.delta_seconds();
+/// Show a banner with the given text, fading in then out.
+fn show_banner(text: &mut Text, animator: &mut Animator<Text>, value: String, color: Color) {
+    text.sections[0].value = value;
+    let mut transparent = color;
+    transparent.set_a(0.);
+    let tween = Tween::new(
+        EaseFunction::QuadraticOut,
+        TweeningType::Once,
+        Duration::from_secs_f32(0.4),
+        TextColorLens {
+            start: transparent,
+            end: color,
+            section: 0,
+        },
+    )
+    .then(Delay::new(Duration::from_secs_f32(2.)))
+    .then(Tween::new(
+        EaseFunction::QuadraticIn,
+        TweeningType::Once,
+        Duration::from_secs_f32(1.5),
+        TextColorLens {
+            start: color,
+            end: transparent,
+            section: 0,
+        },
+    ));
+    animator.set_tweenable(tween);
+    animator.rewind();
+    animator.state = AnimatorState::Playing;
+}
 
-    // Execute timeline
-    manager.execute_timeline(dt, &mut commands, &mut init_events, &mut show_events);
+fn update_attack_callout(
+    mut callout_events: EventReader<CalloutEvent>,
+    mut q_callout: Query<(&mut Text, &mut Animator<Text>), With<AttackCallout>>,
+    mut q_capture: Query<
+        (&mut Text, &mut Animator<Text>),
+        (With<CaptureBanner>, Without<AttackCallout>),
+    >,
+) {
+    for ev in callout_events.iter() {
+        match ev {
+            CalloutEvent::Attack(name) => {
+                for (mut text, mut animator) in q_callout.iter_mut() {
+                    show_banner(
+                        &mut text,
+                        &mut animator,
+                        format!("~ {} ~", name),
+                        ATTACK_CALLOUT_COLOR,
+                    );
+                }
+            }
+            CalloutEvent::Capture(bonus) => {
+                for (mut text, mut animator) in q_capture.iter_mut() {
+                    show_banner(
+                        &mut text,
+                        &mut animator,
+                        format!("Pattern captured! +{}", bonus),
+                        CAPTURE_BANNER_COLOR,
+                    );
+                }
+            }
+        }
+    }
+}
 
-    // need to loop once per enemy, so collect all now
-    let damage_events = damage_events.iter().collect::<Vec<_>>();
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::CommandQueue;
 
-    for (entity, mut controller, mut transform, mut animator) in query.iter_mut() {
-        // Apply damage to enemy
-        let damage: f32 = damage_events
-            .iter()
-            .filter_map(|ev| {
-                if ev.entity == entity {
-                    Some(ev.damage)
-                } else {
-                    None
-                }
-            })
-            .sum();
-        if damage > 0. {
-            controller.remain_life -= damage;
+    use super::*;
 
-            // Update boss lifebar if this enemy is a boss
-            if controller.is_boss {
-                lifebar_events.send(UpdateLifebarsEvent {
-                    entity: manager.boss_lifebar_entity,
-                    remain_life: controller.remain_life,
-                });
+    /// Rotations of the bullets fired on each frame of each cycle of a sequence.
+    fn fire_cycles(sequence: &mut FireTagSequence, cycles: usize) -> Vec<Vec<Vec<Quat>>> {
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut q_bullets = world.query_filtered::<(Entity, &Transform), With<EnemyBullet>>();
+        let mut frames = vec![vec![]];
+        while frames.len() <= cycles {
+            let index = sequence.index;
+            {
+                let mut commands = Commands::new(&mut queue, &world);
+                let mut context = FireTagContext::new(
+                    1. / 64.,
+                    Entity::from_raw(0),
+                    Vec3::ZERO,
+                    Vec3::new(-2., 0.5, 0.),
+                    1.,
+                    DifficultyMultipliers::default(),
+                    &mut rng,
+                    &mut commands,
+                );
+                sequence.execute(&mut context);
+            }
+            queue.apply(&mut world);
+            let fired: Vec<_> = q_bullets
+                .iter(&world)
+                .map(|(entity, transform)| (entity, transform.rotation))
+                .collect();
+            frames
+                .last_mut()
+                .unwrap()
+                .push(fired.iter().map(|(_, rotation)| *rotation).collect());
+            for (entity, _) in fired {
+                world.despawn(entity);
+            }
+            // The sequence wraps around after its last child
+            if sequence.index < index {
+                frames.push(vec![]);
             }
         }
-        if controller.remain_life <= 0. {
-            println!("ENEMY {:?} KILLED", entity);
-            score_events.send(ScoreEvent(controller.kill_score));
-            commands.entity(entity).despawn_recursive();
-            return;
-        }
+        frames.truncate(cycles);
+        frames
+    }
 
-        //println!("enemy xform={:?}", transform);
-        let target_pos = if q_player.is_empty() {
-            Vec3::ZERO
-        } else {
-            q_player.single().translation
+    #[test]
+    fn sequence_restarts_identically() {
+        let mut sequence = FireTagSequence {
+            children: vec![
+                FireTagChild::new(
+                    Box::new(FireTagAimBurst::new(AimBurstParams::default())),
+                    0.1,
+                    Some(0.5),
+                ),
+                FireTagChild::new(
+                    Box::new(FireTagSpiral::new(SpiralParams::default())),
+                    0.,
+                    Some(1.),
+                ),
+            ],
+            index: 0,
         };
-        controller.update(
-            dt,
-            transform.translation,
-            target_pos,
-            &mut commands,
-            &mut *transform,
-            &mut *animator,
-        );
+        let cycles = fire_cycles(&mut sequence, 2);
+        assert!(cycles[0].iter().any(|frame| !frame.is_empty()));
+        assert_eq!(cycles[0], cycles[1]);
     }
 }
@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use std::{collections::VecDeque, fmt, io::Write};
+
+use crate::{
+    game::{DamageEvent, PlayerController},
+    AppState,
+};
+
+pub struct EventLogPlugin;
+
+impl Plugin for EventLogPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LogEvent>()
+            .init_resource::<EventLog>()
+            .add_system(log_state_transitions)
+            .add_system(log_damage_totals)
+            .add_system_to_stage(CoreStage::PostUpdate, collect_log_events);
+    }
+}
+
+/// Gameplay event recorded in the [`EventLog`].
+#[derive(Debug, Clone)]
+pub enum LogEvent {
+    /// Enemy spawned, with the name of its descriptor.
+    Spawn {
+        name: String,
+    },
+    /// Enemy killed by the player.
+    Kill {
+        name: String,
+        is_boss: bool,
+    },
+    /// Boss switched to a new phase.
+    Phase {
+        name: String,
+        index: usize,
+    },
+    /// Enemy blew itself up.
+    SelfDestruct {
+        name: String,
+    },
+    /// Boss pattern captured without a miss.
+    Capture {
+        bonus: u32,
+    },
+    /// Player lost a life, with the number of lives left.
+    PlayerKilled {
+        lives: u32,
+    },
+    PlayerRespawned,
+    /// Player continued after losing their last life.
+    Continue,
+    /// Player used a bomb, with the number of bombs left.
+    Bomb {
+        remaining: u32,
+    },
+    /// Player burned the full overdrive meter.
+    Overdrive,
+    /// Player weapon upgraded to a new power tier.
+    PowerUp {
+        tier: usize,
+    },
+    /// Achievement unlocked, with its identifier.
+    Achievement {
+        id: &'static str,
+    },
+    /// Damage dealt to enemies and taken by the player over the last second.
+    Damage {
+        dealt: f32,
+        taken: f32,
+    },
+    /// App state changed.
+    State(AppState),
+}
+
+impl fmt::Display for LogEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogEvent::Spawn { name } => write!(f, "SPAWN {}", name),
+            LogEvent::Kill { name, is_boss } => {
+                write!(f, "KILL {}{}", name, if *is_boss { " (boss)" } else { "" })
+            }
+            LogEvent::Phase { name, index } => write!(f, "PHASE {} #{}", name, index + 1),
+            LogEvent::SelfDestruct { name } => write!(f, "SELF-DESTRUCT {}", name),
+            LogEvent::Capture { bonus } => write!(f, "CAPTURE bonus={}", bonus),
+            LogEvent::PlayerKilled { lives } => write!(f, "PLAYER KILLED lives={}", lives),
+            LogEvent::PlayerRespawned => write!(f, "PLAYER RESPAWNED"),
+            LogEvent::Continue => write!(f, "CONTINUE"),
+            LogEvent::Bomb { remaining } => write!(f, "BOMB remaining={}", remaining),
+            LogEvent::Overdrive => write!(f, "OVERDRIVE"),
+            LogEvent::PowerUp { tier } => write!(f, "POWER UP tier={}", tier),
+            LogEvent::Achievement { id } => write!(f, "ACHIEVEMENT {}", id),
+            LogEvent::Damage { dealt, taken } => {
+                write!(f, "DAMAGE dealt={:.0}/s taken={:.0}/s", dealt, taken)
+            }
+            LogEvent::State(state) => write!(f, "STATE {:?}", state),
+        }
+    }
+}
+
+/// Entry of the [`EventLog`].
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    /// Time since startup when the event occurred, in seconds.
+    pub time: f64,
+    pub event: LogEvent,
+}
+
+/// Maximum number of entries kept in memory by the [`EventLog`].
+const MAX_LOG_ENTRIES: usize = 256;
+
+/// Structured log of the recent gameplay events, collected from the [`LogEvent`]s
+/// sent by gameplay systems. Each entry is also printed to the console, and
+/// optionally appended to a file.
+#[derive(Default)]
+pub struct EventLog {
+    /// Most recent entries, oldest first.
+    pub entries: VecDeque<LogEntry>,
+    /// File the entries are appended to, if any.
+    file: Option<std::fs::File>,
+}
+
+impl EventLog {
+    /// Start appending the entries to the given file, creating its parent directory
+    /// if needed.
+    pub fn open_file(&mut self, path: &str) -> std::io::Result<()> {
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        self.file = Some(
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?,
+        );
+        Ok(())
+    }
+
+    /// Stop writing the entries to file.
+    pub fn close_file(&mut self) {
+        self.file = None;
+    }
+
+    pub fn is_writing_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        let line = format!("[{:8.2}] {}", entry.time, entry.event);
+        info!(target: "events", "{}", line);
+        if let Some(file) = &mut self.file {
+            if let Err(err) = writeln!(file, "{}", line) {
+                warn!(target: "events", "Failed to write event log: {}", err);
+                self.file = None;
+            }
+        }
+        if self.entries.len() >= MAX_LOG_ENTRIES {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+}
+
+fn collect_log_events(
+    mut log: ResMut<EventLog>,
+    mut log_events: EventReader<LogEvent>,
+    time: Res<Time>,
+) {
+    let now = time.seconds_since_startup();
+    for event in log_events.iter() {
+        log.push(LogEntry {
+            time: now,
+            event: event.clone(),
+        });
+    }
+}
+
+fn log_state_transitions(state: Res<State<AppState>>, mut log_events: EventWriter<LogEvent>) {
+    if state.is_changed() {
+        log_events.send(LogEvent::State(*state.current()));
+    }
+}
+
+/// Damage accumulated since the last [`LogEvent::Damage`].
+#[derive(Default)]
+struct DamageTotals {
+    dealt: f32,
+    taken: f32,
+    /// Time since the totals were last logged, in seconds.
+    time: f32,
+}
+
+/// Log the damage dealt and taken, once per second while any is dealt.
+fn log_damage_totals(
+    mut totals: Local<DamageTotals>,
+    mut damage_events: EventReader<DamageEvent>,
+    q_player: Query<(), With<PlayerController>>,
+    mut log_events: EventWriter<LogEvent>,
+    time: Res<Time>,
+) {
+    for ev in damage_events.iter() {
+        if q_player.get(ev.entity).is_ok() {
+            totals.taken += ev.damage;
+        } else {
+            totals.dealt += ev.damage;
+        }
+    }
+
+    totals.time += time.delta_seconds();
+    if totals.time < 1. {
+        return;
+    }
+    if totals.dealt > 0. || totals.taken > 0. {
+        log_events.send(LogEvent::Damage {
+            dealt: totals.dealt / totals.time,
+            taken: totals.taken / totals.time,
+        });
+    }
+    *totals = DamageTotals::default();
+}
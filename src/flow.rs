@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use bevy_tweening::*;
+use std::time::Duration;
+
+use crate::AppState;
+
+/// Transitions between the states of the app. Systems never set the [`AppState`]
+/// directly, but send a [`RequestStateChange`] event instead; the transition then
+/// fades the screen to black, changes the state at the end of the frame, so that all
+/// stages see the new state from the start of the next frame, and fades back in.
+pub struct GameFlowPlugin;
+
+impl Plugin for GameFlowPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RequestStateChange>()
+            .init_resource::<GameFlow>()
+            .add_startup_system(flow_setup)
+            .add_system(component_animator_system::<UiColor>)
+            .add_system_to_stage(CoreStage::Last, apply_state_changes)
+            .add_system_to_stage(CoreStage::Last, keep_fade_on_top);
+    }
+}
+
+/// Duration of the fade to black before a state change, and of the fade back in
+/// after it, in seconds.
+const FADE_DURATION: f32 = 0.25;
+
+/// Depth of the fade screen, in front of all the other UI nodes.
+const FADE_Z: f32 = 100.;
+
+/// User data of the completion event of the fade to black.
+const FADE_OUT_COMPLETED: u64 = 1;
+
+/// Request to change the [`AppState`], applied by the [`GameFlowPlugin`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequestStateChange {
+    /// State to change to.
+    pub state: AppState,
+    /// Fade to black and back around the change, instead of changing immediately.
+    pub fade: bool,
+}
+
+impl RequestStateChange {
+    /// Change to the given state with a fade to black.
+    pub fn fade_to(state: AppState) -> Self {
+        RequestStateChange { state, fade: true }
+    }
+
+    /// Change to the given state immediately.
+    pub fn immediate(state: AppState) -> Self {
+        RequestStateChange { state, fade: false }
+    }
+}
+
+/// State change in progress, if any.
+#[derive(Default)]
+struct GameFlow {
+    /// State to change to once the screen is black.
+    pending: Option<AppState>,
+}
+
+/// Full-screen quad faded in and out around the state changes.
+#[derive(Component)]
+struct FadeScreen;
+
+/// Lens fading the alpha of a [`UiColor`].
+struct UiAlphaLens {
+    start: f32,
+    end: f32,
+}
+
+impl Lens<UiColor> for UiAlphaLens {
+    fn lerp(&mut self, target: &mut UiColor, ratio: f32) {
+        target.0.set_a(self.start + (self.end - self.start) * ratio);
+    }
+}
+
+fn fade_tween(start: f32, end: f32) -> Tween<UiColor> {
+    Tween::new(
+        EaseMethod::Linear,
+        TweeningType::Once,
+        Duration::from_secs_f32(FADE_DURATION),
+        UiAlphaLens { start, end },
+    )
+}
+
+fn flow_setup(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.)),
+            ..Default::default()
+        })
+        .insert(Name::new("FadeScreen"))
+        .insert(FadeScreen)
+        .insert(Animator::<UiColor>::default());
+}
+
+/// Apply the requested state changes, and change the state once the fade to black
+/// completes. Runs last in the frame, so the state driver of every stage picks the new
+/// state up at the same time next frame.
+fn apply_state_changes(
+    mut flow: ResMut<GameFlow>,
+    mut requests: EventReader<RequestStateChange>,
+    mut completed_events: EventReader<TweenCompleted>,
+    mut state: ResMut<State<AppState>>,
+    mut query: Query<&mut Animator<UiColor>, With<FadeScreen>>,
+) {
+    for ev in completed_events.iter() {
+        if ev.user_data != FADE_OUT_COMPLETED {
+            continue;
+        }
+        if let Some(new_state) = flow.pending.take() {
+            set_state(&mut state, new_state);
+            if let Ok(mut animator) = query.get_mut(ev.entity) {
+                animator.set_tweenable(fade_tween(1., 0.));
+            }
+        }
+    }
+
+    // Only apply the first request of the frame
+    let request = match requests.iter().fold(None, |first, ev| first.or(Some(*ev))) {
+        Some(request) => request,
+        None => return,
+    };
+    if let Some(pending) = flow.pending {
+        trace!(
+            target: "flow",
+            "Ignored request to change state to {:?} while changing to {:?}",
+            request.state,
+            pending
+        );
+        return;
+    }
+    match (request.fade, query.get_single_mut()) {
+        (true, Ok(mut animator)) => {
+            animator
+                .set_tweenable(fade_tween(0., 1.).with_completed_event(true, FADE_OUT_COMPLETED));
+            flow.pending = Some(request.state);
+        }
+        _ => set_state(&mut state, request.state),
+    }
+}
+
+fn set_state(state: &mut State<AppState>, new_state: AppState) {
+    debug!(target: "flow", "State change: {:?} -> {:?}", state.current(), new_state);
+    if let Err(err) = state.set(new_state) {
+        warn!(target: "flow", "Failed to change state to {:?}: {:?}", new_state, err);
+    }
+}
+
+/// Move the fade screen in front of the other UI nodes. The UI only orders the root
+/// nodes by their spawn order, so this overrides the depth after the layout.
+fn keep_fade_on_top(mut query: Query<&mut GlobalTransform, With<FadeScreen>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.z = FADE_Z;
+    }
+}
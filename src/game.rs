@@ -1,42 +1,104 @@
 use bevy::{
     app::CoreStage,
     asset::{AssetStage, LoadState},
+    ecs::system::SystemParam,
     gltf::{Gltf, GltfMesh},
     input::gamepad::GamepadButtonType,
-    math::const_vec2,
+    math::{const_vec2, const_vec3},
     pbr::{NotShadowCaster, NotShadowReceiver},
     prelude::*,
+    render::{camera::CameraProjection, primitives::Aabb},
+    transform::TransformSystem,
+    utils::HashMap,
     window::WindowId,
 };
 use bevy_atmosphere::*;
-use bevy_kira_audio::{
-    Audio as KiraAudio, AudioChannel as KiraAudioChannel, AudioPlugin as KiraAudioPlugin,
-    AudioSource as KiraAudioSource, PlaybackState,
-};
+use bevy_kira_audio::{AudioSource as KiraAudioSource, PlaybackState};
 use bevy_tweening::{lens::*, *};
 use heron::prelude::*;
 use leafwing_input_manager::prelude::*;
 use rand::prelude::*;
-use std::{f32::consts::PI, time::Duration};
+use std::{
+    f32::consts::{PI, TAU},
+    marker::PhantomData,
+    time::Duration,
+};
 
 pub struct GamePlugin;
 
-use crate::{menu::AudioManager, AppState, Layer};
+use crate::{
+    banner::{update_stage_banners, StageBanners},
+    cli::LaunchOptions,
+    collider::model_bounds,
+    enemy::BulletKind,
+    event_log::LogEvent,
+    gamepad::ActiveGamepad,
+    hud::{
+        spawn_hud_widget, update_hud_slides, BarOrientation, HudAnchor, HudSlide, HudWidget,
+        ScoreDisplay, ScreenEdge, SlideState,
+    },
+    letterbox::{playfield_fit, PlayfieldFit, PLAYFIELD_ASPECT_RATIO},
+    music::AudioFacade,
+    obstacle::block_player,
+    overdrive::{Overdrive, OverdriveMeter, OVERDRIVE_AURA_RADIUS, OVERDRIVE_FIRE_DELAY_FACTOR},
+    rumble::RumbleRequest,
+    settings::{PaletteKind, Settings},
+    sfx::{Sfx, SfxEvent},
+    shake::ScreenShakeEvent,
+    shield::{Shield, SHIELD_RADIUS},
+    ship::{Hangar, PowerTier, ShipDescriptor},
+    timestep::{AddFixedEvent, FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    touch::apply_touch_input,
+    tuning::Tuning,
+    vfx::{MuzzleFlashEvent, SparkEvent},
+    AppState, Layer,
+};
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<PlayerController>()
-            .add_event::<DamageEvent>()
+            .add_fixed_event::<DamageEvent>()
+            .add_fixed_event::<StageStartEvent>()
+            .add_fixed_event::<ShotEvent>()
             .add_event::<InitLifebarsEvent>()
             .add_event::<ShowLifebarsEvent>()
+            .add_event::<HideLifebarsEvent>()
             .add_event::<UpdateLifebarsEvent>()
             .add_event::<ScoreEvent>()
-            .init_resource::<AudioRes>()
+            .add_event::<StageClearEvent>()
+            .add_event::<PlayerKilledEvent>()
+            .add_event::<PlayerHitEvent>()
+            .add_event::<LastLifeLostEvent>()
+            .add_event::<ContinueEvent>()
+            .add_event::<GameOverEvent>()
+            .add_event::<BombEvent>()
+            .init_resource::<CommonAssets>()
+            .init_resource::<GameTime>()
+            .init_resource::<TimeScale>()
+            .init_resource::<RunSeed>()
+            .init_resource::<GameRng>()
+            .init_resource::<BulletAtlas>()
+            .init_resource::<BulletPalette>()
+            .init_resource::<Lives>()
+            .init_resource::<DebugCheats>()
             .add_plugin(bevy_atmosphere::AtmospherePlugin {
                 dynamic: true,
                 ..default()
             })
             .add_plugin(InputManagerPlugin::<PlayerAction>::default())
+            .add_system_to_stage(CoreStage::First, update_game_time)
+            .add_system_to_stage(CoreStage::Last, despawn_pending)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                order_lifebars.after(TransformSystem::TransformPropagate),
+            )
+            .add_system(update_bullet_palette)
+            .add_system_set(
+                // Bullet textures are preloaded during boot
+                SystemSet::on_exit(AppState::Boot)
+                    .with_system(setup_bullet_atlas)
+                    .with_system(setup_common_assets.after(setup_bullet_atlas)),
+            )
             .add_system_set_to_stage(
                 CoreStage::PreUpdate,
                 SystemSet::on_update(AppState::InGame).with_system(update_screen_bounds),
@@ -44,87 +106,1029 @@ impl Plugin for GamePlugin {
             .add_system_set_to_stage(
                 CoreStage::Update,
                 SystemSet::on_enter(AppState::InGame)
+                    .with_system(roll_run_seed)
                     .with_system(game_setup)
-                    .with_system(lifebar_text_setup),
+                    .with_system(lifebar_text_setup)
+                    .with_system(hit_overlay_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_exit(AppState::InGame).with_system(game_cleanup),
             )
             .add_system_set_to_stage(
                 CoreStage::Update,
                 SystemSet::on_update(AppState::InGame)
-                    .with_system(update_player)
+                    .with_system(latch_player_input.after(apply_touch_input))
+                    .with_system(update_auto_fire)
+                    .with_system(apply_debug_cheats)
+                    .with_system(update_hit_feedback)
+                    .with_system(setup_ship_materials)
+                    .with_system(update_player_blink.after(setup_ship_materials))
+                    .with_system(update_ship_model)
+                    .with_system(update_lives_counter)
+                    .with_system(update_charge_glow)
+                    .with_system(update_player_hitboxes)
+                    .with_system(update_charge_meter)
                     .with_system(despawn_bullets_outside_screen)
                     .with_system(detect_collisions)
-                    .with_system(update_sky_from_sun)
-                    .with_system(update_hud),
+                    .with_system(apply_damage_over_time)
+                    .with_system(continue_game)
+                    .with_system(update_hud.after(update_hud_slides)),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(move_player_bullets)
+                    .with_system(move_enemy_bullets)
+                    .with_system(bounce_enemy_bullets.after(move_enemy_bullets))
+                    .with_system(detect_enemy_bullet_hits.after(bounce_enemy_bullets))
+                    .with_system(
+                        update_player
+                            .after(update_stage_banners)
+                            .after(detect_enemy_bullet_hits)
+                            .before(block_player),
+                    )
+                    .with_system(kill_player.after(update_player)),
             );
     }
 }
 
-#[derive(Component)]
-struct Sun;
+/// Default maximum delta time of the [`GameTime`], in seconds. Can be changed with
+/// `--max-delta`.
+pub const DEFAULT_MAX_DELTA_SECONDS: f32 = 1. / 20.;
+
+/// Gameplay clock.
+///
+/// Wraps the frame delta time with a maximum clamp, so that a long hitch (asset load,
+/// window drag, ...) results in a gameplay slowdown instead of bullets and the enemy
+/// timeline jumping forward unfairly. Gameplay systems should read their delta time
+/// from this resource instead of [`Time`].
+///
+/// The clamped time is also accumulated into the fixed gameplay steps run by the
+/// [`FixedUpdateStage`]; systems there advance by [`FIXED_DELTA_SECONDS`] instead.
+pub struct GameTime {
+    /// Maximum delta time applied to gameplay in a single frame, in seconds.
+    pub max_delta_seconds: f32,
+    /// Clamped delta time of the current frame, in seconds.
+    delta_seconds: f32,
+    /// Game time not yet consumed by a fixed step, in seconds.
+    accumulator: f32,
+    /// Number of fixed steps of the current frame.
+    steps: u32,
+    /// Number of fixed steps of the current frame not run yet.
+    pending_steps: u32,
+    /// Is the gameplay paused? The clock doesn't advance while paused.
+    paused: bool,
+    /// Remaining real time of the current hit-stop, in seconds. The clock doesn't
+    /// advance during a hit-stop either.
+    hit_stop: f32,
+}
+
+impl Default for GameTime {
+    fn default() -> Self {
+        GameTime::new(DEFAULT_MAX_DELTA_SECONDS)
+    }
+}
+
+impl GameTime {
+    /// Create a clock clamping the delta time of each frame to the given maximum, in
+    /// seconds.
+    pub fn new(max_delta_seconds: f32) -> GameTime {
+        GameTime {
+            max_delta_seconds,
+            delta_seconds: 0.,
+            accumulator: 0.,
+            steps: 0,
+            pending_steps: 0,
+            paused: false,
+            hit_stop: 0.,
+        }
+    }
+
+    /// Clamped delta time of the current frame, in seconds.
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta_seconds
+    }
+
+    /// Number of fixed steps run during the current frame.
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Fraction of a fixed step the game time is past the last step, to interpolate
+    /// the rendering between the last two steps.
+    pub fn overstep_fraction(&self) -> f32 {
+        self.accumulator / FIXED_DELTA_SECONDS
+    }
+
+    /// Is the gameplay paused?
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the gameplay.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// Freeze the gameplay for a short while, to emphasize an impact.
+    pub fn start_hit_stop(&mut self, duration: f32) {
+        self.hit_stop = self.hit_stop.max(duration);
+    }
+
+    /// Take the next fixed step of the current frame, if any is left.
+    pub fn consume_step(&mut self) -> bool {
+        if self.pending_steps == 0 {
+            return false;
+        }
+        self.pending_steps -= 1;
+        true
+    }
+
+    /// Advance the clock by the scaled delta time of the frame. The hit-stop runs on
+    /// the real delta time instead, so that it doesn't drag on during a slow motion.
+    fn advance(&mut self, real_delta_seconds: f32, delta_seconds: f32) {
+        let was_hit_stop = self.hit_stop > 0.;
+        self.hit_stop = (self.hit_stop - real_delta_seconds).max(0.);
+        self.delta_seconds = if self.paused || was_hit_stop {
+            0.
+        } else {
+            delta_seconds.min(self.max_delta_seconds)
+        };
+        self.accumulator += self.delta_seconds;
+        self.steps = (self.accumulator / FIXED_DELTA_SECONDS) as u32;
+        self.accumulator = (self.accumulator - self.steps as f32 * FIXED_DELTA_SECONDS).max(0.);
+        self.pending_steps = self.steps;
+    }
+}
+
+/// Global speed of the gameplay clock.
+///
+/// Scales the real delta time before it is clamped into the [`GameTime`], so that all
+/// gameplay systems, fixed steps and physics alike, slow down together. On top of the
+/// gameplay slow motions, the debug hotkeys can change the speed, or freeze the game
+/// and advance it one fixed step at a time.
+pub struct TimeScale {
+    /// Speed of the gameplay relative to real time.
+    scale: f32,
+    /// Remaining real time of the current slow motion, in seconds.
+    slow_motion: f32,
+    /// Speed multiplier set from the debug hotkeys.
+    debug_scale: f32,
+    /// Is the gameplay frozen, only advancing on request?
+    frozen: bool,
+    /// Advance a single fixed step on the next frame while frozen.
+    step: bool,
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale {
+            scale: 1.,
+            slow_motion: 0.,
+            debug_scale: 1.,
+            frozen: false,
+            step: false,
+        }
+    }
+}
 
-fn update_sky_from_sun(
-    mut sky_mat: ResMut<AtmosphereMat>,
-    mut query: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+impl TimeScale {
+    /// Speed of the gameplay relative to real time.
+    pub fn scale(&self) -> f32 {
+        self.scale * self.debug_scale
+    }
+
+    /// Set the debug speed multiplier, applied on top of any slow motion.
+    pub fn set_debug_scale(&mut self, scale: f32) {
+        self.debug_scale = scale;
+    }
+
+    /// Is the gameplay frozen?
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Freeze or unfreeze the gameplay.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        self.step = false;
+    }
+
+    /// Advance the frozen gameplay by a single fixed step on the next frame.
+    pub fn step_frame(&mut self) {
+        self.step = self.frozen;
+    }
+
+    /// Is a slow motion playing?
+    pub fn is_slow_motion(&self) -> bool {
+        self.slow_motion > 0.
+    }
+
+    /// Slow the gameplay down to the given speed for a duration of real time, in
+    /// seconds. Replaces any slow motion already playing.
+    pub fn start_slow_motion(&mut self, scale: f32, duration: f32) {
+        self.scale = scale;
+        self.slow_motion = duration;
+    }
+
+    /// Scaled delta time of the frame, in seconds.
+    fn delta_seconds(&mut self, real_delta_seconds: f32) -> f32 {
+        if !self.frozen {
+            real_delta_seconds * self.scale()
+        } else if std::mem::take(&mut self.step) {
+            FIXED_DELTA_SECONDS
+        } else {
+            0.
+        }
+    }
+
+    fn advance(&mut self, delta_seconds: f32) {
+        if self.slow_motion > 0. && !self.frozen {
+            self.slow_motion = (self.slow_motion - delta_seconds).max(0.);
+            if self.slow_motion == 0. {
+                self.scale = 1.;
+            }
+        }
+    }
+}
+
+/// Seed of the random number generators of the current run.
+///
+/// Systems which need to be reproducible from one run to the other, like the backdrop,
+/// draw from generators derived from this seed instead of the thread RNG. The seed is
+/// rolled when a game starts, and recorded in the replay of the run.
+#[derive(Default)]
+pub struct RunSeed {
+    /// Seed forced for all runs instead of a random one, for reproducible tests.
+    pub fixed: Option<u64>,
+    /// Is the next run a daily run? Its seed is the date, so that all players get the
+    /// same run on a given day.
+    pub daily: bool,
+    /// Seed of the current run.
+    pub seed: u64,
+}
+
+impl RunSeed {
+    /// Random number generator for the given stream of the current run. Each stream
+    /// yields an independent sequence, so that the systems drawing from different
+    /// streams don't influence each other.
+    pub fn rng(&self, stream: u64) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    }
+}
+
+/// Stream of the [`RunSeed`] generator of the [`GameRng`].
+const GAMEPLAY_STREAM: u64 = 0x4000;
+
+/// Random number generator of the gameplay, like the pickup drops, reset from the
+/// [`RunSeed`] at the start of each run.
+///
+/// Cosmetic effects drawn on each frame, like the screen shake, keep using the thread
+/// RNG instead, so that the frame rate doesn't change the sequence of the gameplay.
+pub struct GameRng(pub StdRng);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng(StdRng::seed_from_u64(0))
+    }
+}
+
+/// Seed of the daily run of today, which is the UTC date as `YYYYMMDD`.
+pub fn daily_seed() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    #[cfg(target_arch = "wasm32")]
+    let millis = js_sys::Date::now() as u64;
+
+    // Civil date from the number of days since 1970-01-01, in the proleptic Gregorian
+    // calendar (see http://howardhinnant.github.io/date_algorithms.html)
+    let days = (millis / 86_400_000) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year * 10_000 + month * 100 + day) as u64
+}
+
+/// Roll the seed of a new run, and reset the [`GameRng`] from it. Systems drawing from
+/// the [`RunSeed`] when a game starts must run after this one.
+pub fn roll_run_seed(mut run_seed: ResMut<RunSeed>, mut game_rng: ResMut<GameRng>) {
+    run_seed.seed = if let Some(seed) = run_seed.fixed {
+        seed
+    } else if run_seed.daily {
+        daily_seed()
+    } else {
+        thread_rng().gen()
+    };
+    *game_rng = GameRng(run_seed.rng(GAMEPLAY_STREAM));
+    info!(
+        target: "game",
+        "RUN SEED: {}{}",
+        run_seed.seed,
+        if run_seed.daily { " (daily)" } else { "" }
+    );
+}
+
+pub fn update_game_time(
     time: Res<Time>,
+    mut game_time: ResMut<GameTime>,
+    mut time_scale: ResMut<TimeScale>,
+    mut physics_steps: ResMut<PhysicsSteps>,
+    mut physics_time: ResMut<PhysicsTime>,
+) {
+    let delta_seconds = time_scale.delta_seconds(time.delta_seconds());
+    game_time.advance(time.delta_seconds(), delta_seconds);
+    if !game_time.is_paused() {
+        time_scale.advance(time.delta_seconds());
+    }
+
+    // Step physics by the same amount of game time as the fixed steps, so that bullets
+    // stay in sync with the gameplay, and don't move at all on frames without a step
+    let steps = game_time.steps();
+    if steps == 0 {
+        physics_time.pause();
+    } else {
+        physics_time.resume();
+        *physics_steps =
+            PhysicsSteps::every_frame(Duration::from_secs_f32(steps as f32 * FIXED_DELTA_SECONDS));
+    }
+}
+
+/// Paths of all the bullet textures packed into the [`BulletAtlas`].
+pub const BULLET_TEXTURES: &[&str] = &[
+    "textures/bullet1.png",
+    "textures/bullet2.png",
+    "textures/bullet3.png",
+];
+
+/// Texture atlas packing all bullet textures.
+///
+/// All bullets share a single material sampling the atlas, and each bullet texture
+/// uses a quad mesh whose UVs map to its sub-rectangle inside the atlas. This avoids
+/// switching bind groups between bullet kinds during heavy patterns.
+#[derive(Default)]
+pub struct BulletAtlas {
+    /// Material shared by all bullets, if the atlas was built successfully.
+    material: Option<Handle<StandardMaterial>>,
+    /// UV sub-rectangle (min, max) of each bullet texture inside the atlas.
+    uv_rects: HashMap<&'static str, (Vec2, Vec2)>,
+    /// Per-texture fallback materials, if the atlas failed to build.
+    fallback_materials: HashMap<&'static str, Handle<StandardMaterial>>,
+}
+
+impl BulletAtlas {
+    /// Material to render bullets using the given texture.
+    pub fn material(&self, texture: &str) -> Handle<StandardMaterial> {
+        if let Some(material) = &self.material {
+            material.clone()
+        } else {
+            self.fallback_materials
+                .get(texture)
+                .cloned()
+                .unwrap_or_default()
+        }
+    }
+
+    /// Create a bullet quad mesh of the given size sampling the given texture.
+    pub fn mesh(&self, texture: &str, size: f32) -> Mesh {
+        let (uv_min, uv_max) = self
+            .uv_rects
+            .get(texture)
+            .copied()
+            .unwrap_or((Vec2::ZERO, Vec2::ONE));
+        Quad { size }.to_mesh_with_uvs(uv_min, uv_max)
+    }
+
+    /// Create a new material to render bullets using the given texture, tinted with the
+    /// given color. Unlike [`BulletAtlas::material()`] this adds a material each call,
+    /// so should be called once per texture and color.
+    pub fn tinted_material(
+        &self,
+        texture: &str,
+        tint: Color,
+        materials: &mut Assets<StandardMaterial>,
+    ) -> Handle<StandardMaterial> {
+        let mut material = materials
+            .get(&self.material(texture))
+            .cloned()
+            .unwrap_or_default();
+        material.base_color = tint;
+        materials.add(material)
+    }
+}
+
+/// Threat class of a bullet, to color-code bullets when the assist option is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BulletThreat {
+    /// Fired at the player.
+    Aimed,
+    /// Part of a static pattern, regardless of the player position.
+    Pattern,
+    /// Steering toward the player.
+    Homing,
+}
+
+/// Colors used to color-code bullets by threat class, shared by all bullet emitters, and
+/// to draw the lifebars. Follows the palette of the accessibility settings.
+#[derive(Debug, Clone)]
+pub struct BulletPalette {
+    pub kind: PaletteKind,
+    pub aimed: Color,
+    pub pattern: Color,
+    pub homing: Color,
+}
+
+impl Default for BulletPalette {
+    fn default() -> Self {
+        BulletPalette::new(PaletteKind::Default)
+    }
+}
+
+impl BulletPalette {
+    pub fn new(kind: PaletteKind) -> Self {
+        match kind {
+            PaletteKind::Default => BulletPalette {
+                kind,
+                aimed: Color::rgb(1., 0.35, 0.3),
+                pattern: Color::rgb(0.45, 0.8, 1.),
+                homing: Color::rgb(1., 0.8, 0.2),
+            },
+            // Tell the threats apart by the orange-blue axis and by lightness
+            PaletteKind::RedGreen => BulletPalette {
+                kind,
+                aimed: Color::rgb(1., 0.6, 0.),
+                pattern: Color::rgb(0.35, 0.7, 1.),
+                homing: Color::rgb(0.95, 0.95, 0.95),
+            },
+            // Tell the threats apart by the red-cyan axis and by lightness
+            PaletteKind::BlueYellow => BulletPalette {
+                kind,
+                aimed: Color::rgb(1., 0.25, 0.3),
+                pattern: Color::rgb(0.2, 0.8, 0.8),
+                homing: Color::rgb(0.95, 0.95, 0.95),
+            },
+        }
+    }
+
+    /// Color of a lifebar drawn with this palette. The lifebars go from red to green
+    /// through yellow, so the red-green safe palette maps them onto a vermillion to sky
+    /// blue ramp; the other palettes keep them as is.
+    pub fn lifebar_color(&self, color: Color) -> Color {
+        if self.kind != PaletteKind::RedGreen {
+            return color;
+        }
+        let sum = color.r() + color.g();
+        if sum <= 0. {
+            return color;
+        }
+        // Ratio of green, from 0 for red to 1 for green
+        let t = color.g() / sum;
+        let (from, to, t) = if t < 0.5 {
+            (LIFEBAR_RAMP[0], LIFEBAR_RAMP[1], t * 2.)
+        } else {
+            (LIFEBAR_RAMP[1], LIFEBAR_RAMP[2], t * 2. - 1.)
+        };
+        let rgb = from.lerp(to, t);
+        Color::rgba(rgb.x, rgb.y, rgb.z, color.a())
+    }
+
+    pub fn color(&self, threat: BulletThreat) -> Color {
+        match threat {
+            BulletThreat::Aimed => self.aimed,
+            BulletThreat::Pattern => self.pattern,
+            BulletThreat::Homing => self.homing,
+        }
+    }
+}
+
+/// Colors of the red-green safe lifebars, from the lowest to the highest life.
+const LIFEBAR_RAMP: [Vec3; 3] = [
+    const_vec3!([0.85, 0.35, 0.]),
+    const_vec3!([0.95, 0.9, 0.75]),
+    const_vec3!([0.35, 0.7, 1.]),
+];
+
+/// Switch the palette to the one of the accessibility settings.
+fn update_bullet_palette(settings: Res<Settings>, mut palette: ResMut<BulletPalette>) {
+    if settings.is_changed() && palette.kind != settings.accessibility.palette {
+        *palette = BulletPalette::new(settings.accessibility.palette);
+    }
+}
+
+fn bullet_material(texture: Handle<Image>) -> StandardMaterial {
+    StandardMaterial {
+        base_color_texture: Some(texture),
+        //emissive: Color::RED,
+        unlit: true,
+        alpha_mode: AlphaMode::Blend,
+        ..Default::default()
+    }
+}
+
+fn setup_bullet_atlas(
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut bullet_atlas: ResMut<BulletAtlas>,
 ) {
-    if let Some((mut light_trans, mut directional)) = query.single_mut().into() {
-        // -PI to 0 and back
-        let ratio = (time.seconds_since_startup() as f32 / 60.).fract();
-        let ratio = ((ratio * PI * 2.).sin() + 1.) / 2.;
-        light_trans.rotation = Quat::from_rotation_x(-PI + PI * ratio);
+    let mut builder = TextureAtlasBuilder::default();
+    for path in BULLET_TEXTURES {
+        let handle: Handle<Image> = asset_server.get_handle(*path);
+        if let Some(image) = images.get(&handle) {
+            builder.add_texture(handle.clone(), image);
+        }
+    }
+
+    match builder.finish(&mut *images) {
+        Ok(atlas) => {
+            for path in BULLET_TEXTURES {
+                let handle: Handle<Image> = asset_server.get_handle(*path);
+                if let Some(index) = atlas.get_texture_index(&handle) {
+                    let rect = &atlas.textures[index];
+                    bullet_atlas
+                        .uv_rects
+                        .insert(*path, (rect.min / atlas.size, rect.max / atlas.size));
+                }
+            }
+            bullet_atlas.material = Some(materials.add(bullet_material(atlas.texture)));
+            info!(
+                target: "assets",
+                "Bullet atlas: {} textures packed into {}x{}",
+                bullet_atlas.uv_rects.len(),
+                atlas.size.x,
+                atlas.size.y
+            );
+        }
+        Err(err) => {
+            warn!(
+                target: "assets",
+                "Failed to build bullet atlas, using one material per texture: {:?}",
+                err
+            );
+            for path in BULLET_TEXTURES {
+                let material = materials.add(bullet_material(asset_server.get_handle(*path)));
+                bullet_atlas.fallback_materials.insert(*path, material);
+            }
+        }
+    }
+}
+
+/// Texture of the player bullets, packed into the [`BulletAtlas`].
+const PLAYER_BULLET_TEXTURE: &str = "textures/bullet1.png";
+
+/// Mesh and materials of a kind of enemy bullet.
+#[derive(Clone)]
+pub struct BulletAssets {
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+    /// Distance beyond the screen bounds past which the bullet is despawned, in world
+    /// units. See [`CullMargin`].
+    pub cull_margin: f32,
+    /// Materials per threat class, to color-code threats.
+    pub threat_materials: HashMap<BulletThreat, Handle<StandardMaterial>>,
+}
+
+impl BulletAssets {
+    /// Material of a bullet of the given threat class, tinted if any material is
+    /// available for that threat.
+    pub fn material(&self, threat: BulletThreat) -> Handle<StandardMaterial> {
+        self.threat_materials
+            .get(&threat)
+            .unwrap_or(&self.material)
+            .clone()
+    }
+}
+
+/// Assets shared by the game and enemy plugins, built once when leaving the boot
+/// screen, so that each game doesn't add its own copy of the same meshes and materials.
+#[derive(Default)]
+pub struct CommonAssets {
+    /// Font of the in-game HUD.
+    pub hud_font: Handle<Font>,
+    /// Sweep playing while the lifebars fill up.
+    pub sound_fill_lifebars: Handle<KiraAudioSource>,
+    pub player_bullet_texture: Handle<Image>,
+    pub player_bullet_mesh: Handle<Mesh>,
+    pub player_bullet_material: Handle<StandardMaterial>,
+    /// Material of the charge shot, tinted with [`CHARGE_COLOR`].
+    pub charge_shot_material: Handle<StandardMaterial>,
+    /// Assets of each kind of enemy bullet. The threat materials are always built, and
+    /// follow the [`BulletPalette`], but are only used when color-coding threats.
+    pub enemy_bullets: HashMap<BulletKind, BulletAssets>,
+}
 
-        // Update sky from sun direction
-        let pos = light_trans.rotation.mul_vec3(Vec3::Z);
-        sky_mat.sun_position = pos;
-        //directional.illuminance = t.sin().max(0.0).powf(2.0) * 100000.0;
+fn setup_common_assets(
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    bullet_atlas: Res<BulletAtlas>,
+    palette: Res<BulletPalette>,
+    mut common_assets: ResMut<CommonAssets>,
+) {
+    common_assets.hud_font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    common_assets.sound_fill_lifebars = asset_server.load("sounds/sweep_fill2.ogg");
+
+    common_assets.player_bullet_texture = asset_server.load(PLAYER_BULLET_TEXTURE);
+    common_assets.player_bullet_mesh = meshes.add(bullet_atlas.mesh(PLAYER_BULLET_TEXTURE, 0.1));
+    common_assets.player_bullet_material = bullet_atlas.material(PLAYER_BULLET_TEXTURE);
+    common_assets.charge_shot_material =
+        bullet_atlas.tinted_material(PLAYER_BULLET_TEXTURE, CHARGE_COLOR, &mut *materials);
+
+    for kind in BulletKind::ALL {
+        let texture = kind.texture_path();
+        let threat_materials = [BulletThreat::Aimed, BulletThreat::Pattern]
+            .into_iter()
+            .map(|threat| {
+                let material =
+                    bullet_atlas.tinted_material(texture, palette.color(threat), &mut *materials);
+                (threat, material)
+            })
+            .collect();
+        common_assets.enemy_bullets.insert(
+            kind,
+            BulletAssets {
+                mesh: meshes.add(bullet_atlas.mesh(texture, kind.size())),
+                material: bullet_atlas.material(texture),
+                cull_margin: kind.cull_margin(),
+                threat_materials,
+            },
+        );
     }
 }
 
 #[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
-enum PlayerAction {
+pub enum PlayerAction {
     MoveUp,
     MoveDown,
     MoveLeft,
     MoveRight,
     ShootPrimary,
+    /// Charge while held, and fire a piercing shot on release.
+    ShootSecondary,
+    Bomb,
+    /// Toggle the auto-fire of the primary weapon.
+    ToggleAutoFire,
+    /// Burn the full overdrive meter.
+    Overdrive,
     //
     DebugSpawnBoss,
 }
 
+/// Cooloff between two volleys of a weapon. The leftover time of the previous cooloff
+/// carries over while the trigger stays held, so that the fire rate doesn't depend on
+/// how the cooloff lines up with the steps.
+#[derive(Debug, Default, Clone, Copy, Reflect)]
+pub struct FireTimer {
+    cooloff: f32,
+}
+
+impl FireTimer {
+    /// Advance the timer by `dt` seconds, and return `true` if the weapon fires, in
+    /// which case the next volley is delayed by `delay` seconds.
+    pub fn tick(&mut self, dt: f32, trigger: bool, delay: f32) -> bool {
+        let was_cooling = self.cooloff > 0.;
+        self.cooloff -= dt;
+        if !trigger || self.cooloff > 0. {
+            return false;
+        }
+        // Don't carry over the time the weapon was idle
+        if !was_cooling {
+            self.cooloff = 0.;
+        }
+        self.cooloff += delay;
+        true
+    }
+
+    /// Make the weapon ready to fire immediately.
+    pub fn reset(&mut self) {
+        self.cooloff = 0.;
+    }
+}
+
+/// Left analog stick of the active gamepad, read from the raw gamepad axes since the
+/// input manager only maps buttons to actions.
+#[derive(SystemParam)]
+struct GamepadStick<'w, 's> {
+    active: Res<'w, ActiveGamepad>,
+    axes: Res<'w, Axis<GamepadAxis>>,
+    settings: Res<'w, Settings>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> GamepadStick<'w, 's> {
+    /// Movement direction from the left stick of the active gamepad, of length in
+    /// `[0:1]` after the deadzone and response curve of the settings.
+    fn direction(&self) -> Vec2 {
+        let gamepad = if let Some(gamepad) = self.active.0 {
+            gamepad
+        } else {
+            return Vec2::ZERO;
+        };
+        let axis = |axis_type| self.axes.get(GamepadAxis(gamepad, axis_type)).unwrap_or(0.);
+        let stick = Vec2::new(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        self.settings.controls.stick_to_direction(stick)
+    }
+}
+
+/// Cheats toggled from the debug tools, to survey late-game patterns without dying.
+#[derive(Debug, Default)]
+pub struct DebugCheats {
+    /// The player ignores all hits.
+    pub god_mode: bool,
+    /// The bomb stock stays full.
+    pub infinite_bombs: bool,
+    /// Any damage kills an enemy, or ends the current phase of a boss.
+    pub one_hit_kill: bool,
+}
+
+impl DebugCheats {
+    /// Is any cheat on?
+    pub fn is_active(&self) -> bool {
+        self.god_mode || self.infinite_bombs || self.one_hit_kill
+    }
+}
+
+/// Distance from the player hurt box below which an enemy bullet counts as grazed, in
+/// world units, until the ship model is loaded. Larger than the hurt box so that
+/// grazing doesn't mean dying.
+const DEFAULT_GRAZE_RADIUS: f32 = 0.35;
+
+/// Radius of the pickup collection box, in world units, until the ship model is loaded.
+const DEFAULT_COLLECT_RADIUS: f32 = 0.2;
+
+/// Position of the cockpit along the ship length, as a fraction of its half length
+/// forward of the center of the model bounds. The hurt box is centered on it.
+const COCKPIT_OFFSET: f32 = 0.25;
+
+/// Graze distance beyond the model bounds, in world units. Grazing means passing near
+/// the ship, not through its wings, which aren't part of the hurt box.
+const GRAZE_MARGIN: f32 = 0.15;
+
+/// Radius of the pickup collection box, relative to the half size of the model bounds.
+const COLLECT_SCALE: f32 = 1.25;
+
+/// Hitboxes of the player ship, placed from the bounds of its model once loaded.
+///
+/// The player only takes damage from a small hurt box at the cockpit, while pickups
+/// are collected by a larger box around the whole ship. Both are child colliders of
+/// the player, [`HurtBox`] and [`CollectBox`]. Enemy bullets aren't physics bodies, so
+/// they're tested against the hurt box and graze radius by distance instead.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PlayerHitboxes {
+    /// Offset of the hurt box from the player origin, in world units.
+    pub hurt_offset: Vec3,
+    /// Radius of the hurt box, from [`ShipDescriptor::hitbox_radius`].
+    pub hurt_radius: f32,
+    /// Distance from the hurt box below which an enemy bullet counts as grazed.
+    pub graze_radius: f32,
+    /// Entity the ship model is spawned under, until the hitboxes are placed from its
+    /// bounds.
+    model_root: Option<Entity>,
+}
+
+impl PlayerHitboxes {
+    /// Position of the hurt box, for a player at the given position.
+    pub fn hurt_position(&self, player_position: Vec3) -> Vec3 {
+        player_position + self.hurt_offset
+    }
+}
+
+/// Hurt box of the player, child of the player entity.
+#[derive(Component)]
+struct HurtBox;
+
+/// Pickup collection box of the player, child of the player entity.
+#[derive(Component)]
+struct CollectBox;
+
 #[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct PlayerController {
     input_dir: Vec2,
-    primary_cooloff: f32,
+    primary_timer: FireTimer,
     bullet_texture: Handle<Image>,
     bullet_mesh: Handle<Mesh>,
     bullet_material: Handle<StandardMaterial>,
+    charge_shot_material: Handle<StandardMaterial>,
     primary_fire_delay: f32,
+    /// Factor applied to the primary fire delay by the auto-fire assist.
+    fire_delay_factor: f32,
+    /// Fire the primary weapon continuously, without holding the button.
+    auto_fire: bool,
     primary_fire_offset: Vec3,
+    /// Movement speed, in world units per second.
+    speed: f32,
+    /// Half size of the ship on screen, to keep it inside the screen bounds.
+    half_size: Vec2,
+    /// Weapon power tiers of the ship, by increasing power.
+    #[reflect(ignore)]
+    power_tiers: Vec<PowerTier>,
     life: f32,
     remain_life: f32,
     lifebar_entity: Entity,
+    /// Total number of power items collected.
+    power: u32,
+    /// Number of bombs in stock.
+    bombs: u32,
+    /// Remaining delay before respawning after a death, in seconds, or zero if alive.
+    respawn_delay: f32,
+    /// Remaining invincibility time after respawning, in seconds.
+    invincible_time: f32,
+    /// Number of times the player got hit this game.
+    hits_taken: u32,
+    /// Number of bombs used this game.
+    bombs_used: u32,
+    /// Charge of the secondary weapon, in `[0:1]`.
+    charge: f32,
+    /// Was the bomb button pressed since the last fixed step?
+    bomb_pressed: bool,
+    /// Was the overdrive button pressed since the last fixed step?
+    overdrive_pressed: bool,
+    /// Ignore all hits, for benchmarks or from the [`DebugCheats`].
+    invulnerable: bool,
+    /// Remaining time before an enemy body can damage the player again, in seconds.
+    contact_cooldown: f32,
+    /// Velocity pushing the player away from the last enemy body it bumped into, in
+    /// world units per second.
+    knockback: Vec2,
+    /// Remaining time of the ram power-up, in seconds, or zero if inactive.
+    ram_time: f32,
 }
 
 impl Default for PlayerController {
     fn default() -> Self {
         PlayerController {
             input_dir: Vec2::ZERO,
-            primary_cooloff: 0.,
+            primary_timer: FireTimer::default(),
             bullet_texture: Handle::default(),
             bullet_mesh: Handle::default(),
             bullet_material: Handle::default(),
-            primary_fire_delay: 0.084,
-            primary_fire_offset: Vec3::new(0.58, 0., -0.22),
+            charge_shot_material: Handle::default(),
+            primary_fire_delay: 0.,
+            fire_delay_factor: 1.,
+            auto_fire: false,
+            primary_fire_offset: Vec3::ZERO,
+            speed: 0.,
+            half_size: Vec2::ZERO,
+            power_tiers: vec![],
             life: 100.,
             remain_life: 100.,
             lifebar_entity: Entity::from_raw(0),
+            power: 0,
+            bombs: 2,
+            respawn_delay: 0.,
+            invincible_time: 0.,
+            hits_taken: 0,
+            bombs_used: 0,
+            charge: 0.,
+            bomb_pressed: false,
+            overdrive_pressed: false,
+            invulnerable: false,
+            contact_cooldown: 0.,
+            knockback: Vec2::ZERO,
+            ram_time: 0.,
         }
     }
 }
 
+/// Maximum number of bombs the player can stock.
+pub const MAX_BOMBS: u32 = 5;
+
+/// Time to fully charge the secondary weapon, in seconds.
+const CHARGE_DURATION: f32 = 1.2;
+
+/// Minimum charge of the secondary weapon to fire on release. Below it, releasing
+/// just cancels the charge.
+const MIN_CHARGE: f32 = 0.3;
+
+/// Damage dealt by a regular bullet of the player.
+const PLAYER_BULLET_DAMAGE: f32 = 1.;
+
+/// Damage dealt by a charge shot to each enemy it goes through, at minimum and full
+/// charge.
+const CHARGE_SHOT_DAMAGE: (f32, f32) = (6., 24.);
+
+/// Tint of the charge shot and the charge glow.
+const CHARGE_COLOR: Color = Color::rgb(0.45, 0.95, 1.);
+
+/// Delay after taking contact damage from an enemy body before the next contact can
+/// damage the player again, in seconds. Longer than [`HIT_INVINCIBLE_DURATION`], so
+/// that bouncing off a large enemy doesn't drain the life.
+const CONTACT_COOLDOWN: f32 = 1.5;
+
+/// Speed at which the player is pushed away from an enemy body it bumps into, in world
+/// units per second.
+const KNOCKBACK_SPEED: f32 = 3.;
+
+/// Rate at which the knockback velocity decays, per second.
+const KNOCKBACK_DAMPING: f32 = 8.;
+
+/// Duration of the ram power-up granted by a ram pickup, in seconds.
+pub const RAM_DURATION: f32 = 10.;
+
+/// Damage dealt to an enemy rammed by the player while the ram power-up is active.
+const RAM_DAMAGE: f32 = 10.;
+
 impl PlayerController {
-    fn spawn_bullet(&self, commands: &mut Commands, transform: &Transform) {
+    /// Apply the stats of a ship, selected before the game.
+    pub fn equip(&mut self, ship: &ShipDescriptor) {
+        self.speed = ship.speed;
+        self.half_size = Vec2::from(ship.half_size);
+        self.primary_fire_offset = Vec3::from(ship.fire_offset);
+        self.power_tiers = ship.power_tiers.clone();
+        self.primary_fire_delay = self.power_tiers[self.power_tier()].fire_delay;
+    }
+
+    /// Index into the power tiers of the ship of the current weapon power tier.
+    pub fn power_tier(&self) -> usize {
+        self.power_tiers
+            .iter()
+            .rposition(|tier| self.power >= tier.power)
+            .unwrap_or(0)
+    }
+
+    /// Add some weapon power, and return `true` if this upgraded the power tier.
+    pub fn add_power(&mut self, amount: u32) -> bool {
+        let prev_tier = self.power_tier();
+        self.power += amount;
+        let tier = self.power_tier();
+        self.primary_fire_delay = self.power_tiers[tier].fire_delay;
+        tier != prev_tier
+    }
+
+    /// Add a bomb to the stock, up to [`MAX_BOMBS`].
+    pub fn add_bomb(&mut self) {
+        self.bombs = (self.bombs + 1).min(MAX_BOMBS);
+    }
+
+    pub fn bombs(&self) -> u32 {
+        self.bombs
+    }
+
+    /// Number of misses this game, counting both hits taken and bombs used. Boss
+    /// patterns are only captured if this doesn't change during the pattern.
+    pub fn misses(&self) -> u32 {
+        self.hits_taken + self.bombs_used
+    }
+
+    /// Number of hits taken this game.
+    pub fn hits_taken(&self) -> u32 {
+        self.hits_taken
+    }
+
+    /// Number of bombs used this game.
+    pub fn bombs_used(&self) -> u32 {
+        self.bombs_used
+    }
+
+    /// Does the primary weapon fire continuously, without holding the button?
+    pub fn is_auto_firing(&self) -> bool {
+        self.auto_fire
+    }
+
+    /// Activate the ram power-up, or restart its timer if already active.
+    pub fn add_ram(&mut self) {
+        self.ram_time = RAM_DURATION;
+    }
+
+    /// Does ramming an enemy damage it? The player then also takes no contact damage.
+    pub fn is_ramming(&self) -> bool {
+        self.ram_time > 0.
+    }
+
+    /// Charge of the secondary weapon, in `[0:1]`.
+    pub fn charge(&self) -> f32 {
+        self.charge
+    }
+
+    /// Is the player dead and waiting to respawn?
+    pub fn is_respawning(&self) -> bool {
+        self.respawn_delay > 0.
+    }
+
+    /// Can the player currently be hit? This is `false` while respawning and during
+    /// the invincibility window which follows.
+    pub fn is_vulnerable(&self) -> bool {
+        !self.is_respawning() && self.invincible_time <= 0. && !self.invulnerable
+    }
+
+    fn spawn_bullet(&self, commands: &mut Commands, transform: &Transform, velocity: Vec3) {
         commands
             .spawn_bundle(PbrBundle {
                 mesh: self.bullet_mesh.clone(),
@@ -132,15 +1136,48 @@ impl PlayerController {
                 transform: *transform,
                 ..Default::default()
             })
-            .insert(Bullet(Vec3::X * 5.))
+            .insert(Bullet(velocity))
+            .insert(Damage(PLAYER_BULLET_DAMAGE))
+            .insert(InGameEntity)
+            .insert(FixedStep::default())
             // Rendering
             .insert(NotShadowCaster)
             .insert(NotShadowReceiver)
             // Physics
-            .insert(RigidBody::Dynamic) // TODO - or Dynamic?
+            .insert(RigidBody::KinematicPositionBased)
             .insert(CollisionShape::Sphere { radius: 0.1 })
-            .insert(Velocity::from_linear(Vec3::X * 5.))
-            .insert(RotationConstraints::lock())
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layer::PlayerBullet)
+                    .with_masks(&[Layer::World, Layer::Enemy]),
+            );
+    }
+
+    /// Fire a charge shot at the given speed, piercing through enemies, with a damage
+    /// and a size which grow with the current charge.
+    fn spawn_charge_shot(&self, commands: &mut Commands, transform: &Transform, speed: f32) {
+        let t = (self.charge - MIN_CHARGE) / (1. - MIN_CHARGE);
+        let damage = CHARGE_SHOT_DAMAGE.0 + (CHARGE_SHOT_DAMAGE.1 - CHARGE_SHOT_DAMAGE.0) * t;
+        let size = 1.5 + 1.5 * t;
+        let velocity = Vec3::X * speed;
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: self.bullet_mesh.clone(),
+                material: self.charge_shot_material.clone(),
+                transform: transform.with_scale(Vec3::new(size * 1.5, size, 1.)),
+                ..Default::default()
+            })
+            .insert(Bullet(velocity))
+            .insert(Damage(damage))
+            .insert(Piercing)
+            .insert(InGameEntity)
+            .insert(FixedStep::default())
+            // Rendering
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver)
+            // Physics
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(CollisionShape::Sphere { radius: 0.1 * size })
             .insert(
                 CollisionLayers::none()
                     .with_group(Layer::PlayerBullet)
@@ -152,43 +1189,295 @@ impl PlayerController {
 #[derive(Component)]
 struct Player;
 
+/// Player bullet going through enemies instead of despawning on the first hit. Its
+/// [`Damage`] is dealt to each enemy it goes through.
+#[derive(Component)]
+pub struct Piercing;
+
+/// Glow at the nose of the player ship while the secondary weapon charges.
+#[derive(Component)]
+struct ChargeGlow;
+
+/// Fill of the charge meter of the secondary weapon on the HUD.
+#[derive(Component)]
+struct ChargeMeter;
+
+/// Velocity of a bullet, in world units per second. Player bullets move in world
+/// space in [`move_player_bullets()`], and enemy bullets along their own rotation in
+/// [`move_enemy_bullets()`].
 #[derive(Component)]
 pub struct Bullet(pub Vec3);
 
+/// Marker for a bullet fired by an enemy.
+///
+/// Unlike the player bullets, enemy bullets are not physics bodies: heron is overkill
+/// for the hundreds of point-vs-circle tests of a heavy pattern. They move in
+/// [`move_enemy_bullets()`] and hit the player in [`detect_enemy_bullet_hits()`]
+/// instead. Laser beams are still physics sensors.
+#[derive(Component)]
+pub struct EnemyBullet;
+
+/// Number of times an enemy bullet still bounces off the screen edges and the
+/// obstacles, before leaving the screen or being blocked like any other bullet.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Bounce(pub u8);
+
+impl Bounce {
+    /// Reflect the direction of a bullet hitting a surface of the given normal, and use
+    /// up a bounce. A bullet already moving away from the surface keeps going without
+    /// using any bounce. Returns `false` if the bullet has no bounce left.
+    pub fn reflect(&mut self, transform: &mut Transform, normal: Vec2) -> bool {
+        if self.0 == 0 {
+            return false;
+        }
+        // Bullets move along their local X axis, see move_enemy_bullets()
+        let direction = transform.rotation.mul_vec3(Vec3::X).truncate();
+        let dot = direction.dot(normal);
+        if dot < 0. {
+            let reflected = direction - 2. * dot * normal;
+            transform.rotation = Quat::from_rotation_z(reflected.y.atan2(reflected.x));
+            self.0 -= 1;
+        }
+        true
+    }
+}
+
+/// Radius of the hitbox of the enemy bullets, in world units.
+pub const ENEMY_BULLET_RADIUS: f32 = 0.1;
+
+/// Damage dealt by an enemy bullet hitting the player.
+pub const ENEMY_BULLET_DAMAGE: f32 = 1.;
+
+/// Distance beyond the screen bounds past which a bullet without a [`CullMargin`] is
+/// despawned, in world units.
+pub const DEFAULT_CULL_MARGIN: f32 = 0.5;
+
+/// Distance beyond the screen bounds past which a projectile is despawned, in world
+/// units. Large projectiles need a larger margin, so that they don't vanish while still
+/// partly visible.
+///
+/// Bullets without this component use [`DEFAULT_CULL_MARGIN`]. Other projectiles are
+/// only culled if they have it.
+#[derive(Component, Clone, Copy)]
+pub struct CullMargin(pub f32);
+
+/// Marker for an entity to despawn at the end of the frame.
+///
+/// Systems mark entities instead of despawning them directly, so that several systems
+/// (or several contacts in the same system) can request the removal of the same entity
+/// within a frame without despawning it twice.
+#[derive(Component)]
+pub struct PendingDespawn;
+
+/// Marker for all root entities spawned during a game, despawned when leaving
+/// [`AppState::InGame`] so that the next game starts clean.
+#[derive(Component)]
+pub struct InGameEntity;
+
+fn game_cleanup(mut commands: Commands, query: Query<Entity, With<InGameEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Despawn all entities marked with [`PendingDespawn`], once per frame.
+fn despawn_pending(mut commands: Commands, query: Query<Entity, With<PendingDespawn>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Cosmetic animation of the ship model, following the player input: roll on vertical
+/// movement, yaw on horizontal movement, and a slow bobbing when idle. Doesn't affect
+/// the gameplay, so it's animated per frame and not in the gameplay steps.
 #[derive(Component, Default)]
 struct ShipController {
+    /// Roll around the forward axis, in degrees.
     roll: f32,
+    /// Yaw around the vertical axis, in degrees.
+    yaw: f32,
+    /// Weight of the idle bobbing, in `[0:1]`, fading in while there's no input.
+    bob_weight: f32,
+    /// Phase of the idle bobbing, in radians.
+    bob_phase: f32,
+    /// Length of the engine exhaust, relative to its cruise length.
+    exhaust_length: f32,
 }
 
+/// Engine exhaust flame behind the ship, stretched by [`update_ship_model()`].
+#[derive(Component)]
+struct EngineExhaust;
+
+/// Maximum roll of the ship model on vertical movement, in degrees.
+const SHIP_MAX_ROLL: f32 = 40.;
+
+/// Maximum yaw of the ship model on horizontal movement, in degrees.
+const SHIP_MAX_YAW: f32 = 12.;
+
+/// Amplitude of the idle bobbing of the ship model, in world units.
+const SHIP_BOB_AMPLITUDE: f32 = 0.015;
+
+/// Frequency of the idle bobbing of the ship model, in cycles per second.
+const SHIP_BOB_FREQUENCY: f32 = 0.6;
+
+/// Size of the engine exhaust at cruise length, in world units.
+const EXHAUST_SIZE: Vec2 = const_vec2!([0.12, 0.035]);
+
+/// Length of the engine exhaust when moving backward and forward, relative to the
+/// cruise length.
+const EXHAUST_LENGTH_RANGE: (f32, f32) = (0.4, 1.8);
+
+const EXHAUST_COLOR: Color = Color::rgba(1., 0.6, 0.2, 0.8);
+const EXHAUST_CORE_COLOR: Color = Color::rgba(1., 0.95, 0.8, 0.9);
+
 #[derive(Component, Default)]
-struct MainCamera {
-    screen_bounds: Rect<f32>,
+pub struct MainCamera {
+    pub screen_bounds: Rect<f32>,
 }
 
+/// Transform of the main camera without any screen shake applied.
+///
+/// Systems depending on the camera placement, like the screen bounds, use this instead
+/// of the actual camera [`Transform`], which is offset while the screen shakes.
+#[derive(Component, Default)]
+pub struct CameraBaseTransform(pub Transform);
+
 impl MainCamera {
+    /// Half size of the area visible by the camera on the playfield plane (Z=0), in
+    /// world units, for either a perspective or an orthographic projection.
+    pub fn half_size(
+        perspective: Option<&PerspectiveProjection>,
+        orthographic: Option<&OrthographicProjection>,
+        transform: &Transform,
+    ) -> Vec2 {
+        if let Some(projection) = orthographic {
+            Vec2::new(
+                (projection.right - projection.left) * projection.scale * 0.5,
+                (projection.top - projection.bottom) * projection.scale * 0.5,
+            )
+            .abs()
+        } else if let Some(projection) = perspective {
+            let camera_half_height = (projection.fov * transform.translation.z * 0.5).abs();
+            let camera_half_width = (camera_half_height * projection.aspect_ratio).abs();
+            Vec2::new(camera_half_width, camera_half_height)
+        } else {
+            Vec2::ZERO
+        }
+    }
+
+    /// Position of a point of the playfield plane on screen, in percent of the screen
+    /// size from its top left corner, to lay out UI nodes over the playfield.
+    pub fn playfield_percent(&self, position: Vec3) -> Vec2 {
+        let bounds = &self.screen_bounds;
+        let width = (bounds.right - bounds.left).max(f32::EPSILON);
+        let height = (bounds.top - bounds.bottom).max(f32::EPSILON);
+        Vec2::new(
+            (position.x - bounds.left) / width * 100.,
+            (bounds.top - position.y) / height * 100.,
+        )
+    }
+
+    /// Is the position farther than `margin` outside the screen bounds?
+    pub fn is_outside(&self, position: Vec3, margin: f32) -> bool {
+        position.x < self.screen_bounds.left - margin
+            || position.x > self.screen_bounds.right + margin
+            || position.y < self.screen_bounds.bottom - margin
+            || position.y > self.screen_bounds.top + margin
+    }
+
     pub fn update_screen_bounds(
         &mut self,
-        projection: &PerspectiveProjection,
+        perspective: Option<&PerspectiveProjection>,
+        orthographic: Option<&OrthographicProjection>,
         transform: &Transform,
     ) {
-        let camera_half_height = (projection.fov * transform.translation.z * 0.5).abs();
-        let camera_half_width = (camera_half_height * projection.aspect_ratio).abs();
+        // Only the playfield counts as the screen; the rest of the window is covered by
+        // the letterbox bars
+        let mut half_size = MainCamera::half_size(perspective, orthographic, transform);
+        if half_size.y > 0. {
+            if half_size.x / half_size.y > PLAYFIELD_ASPECT_RATIO {
+                half_size.x = half_size.y * PLAYFIELD_ASPECT_RATIO;
+            } else {
+                half_size.y = half_size.x / PLAYFIELD_ASPECT_RATIO;
+            }
+        }
+        let camera_half_width = half_size.x;
+        let camera_half_height = half_size.y;
         self.screen_bounds.left = -camera_half_width;
         self.screen_bounds.right = camera_half_width;
         self.screen_bounds.bottom = -camera_half_height;
         self.screen_bounds.top = camera_half_height;
-        println!(
+        debug!(
+            target: "camera",
             "Screen bounds changed: cw/2={} ch/2={} bounds={:?}",
             camera_half_width, camera_half_height, self.screen_bounds
         );
     }
 }
 
+/// Kind of source of a [`DamageEvent`], to allow resistances or shields against some
+/// kinds only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageSource {
+    /// Bullet or missile fired by the player.
+    PlayerBullet,
+    /// Bullet fired by an enemy.
+    EnemyBullet,
+    /// Collision between the bodies of the player and an enemy.
+    Contact,
+    /// Laser beam, or any other [`DamageOverTime`].
+    Laser,
+    /// Blast of a self-destructing enemy.
+    Explosion,
+}
+
 /// Event to damage a player or enemy.
 #[derive(Debug)]
 pub struct DamageEvent {
     pub entity: Entity,
     pub damage: f32,
+    pub source: DamageSource,
+    /// World-space position where the damage was dealt, for impact effects.
+    pub position: Vec3,
+}
+
+/// Damage dealt by a projectile on hit, or by a body on contact.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Damage(pub f32);
+
+/// Damage dealt on contact by a body without any [`Damage`].
+pub const DEFAULT_DAMAGE: f32 = 1.;
+
+/// Interval between two damage ticks of a [`DamageOverTime`], in seconds.
+const DAMAGE_TICK_INTERVAL: f32 = 0.25;
+
+/// Deal damage continuously to the entities overlapping this one, like a laser beam,
+/// instead of a single hit on contact. The entity is not despawned on contact.
+#[derive(Component, Debug, Default)]
+pub struct DamageOverTime {
+    pub damage_per_second: f32,
+    /// Entities currently overlapping, with the time until their next damage tick.
+    overlaps: Vec<(Entity, f32)>,
+}
+
+impl DamageOverTime {
+    pub fn new(damage_per_second: f32) -> Self {
+        DamageOverTime {
+            damage_per_second,
+            overlaps: vec![],
+        }
+    }
+
+    fn start_overlap(&mut self, entity: Entity) {
+        if !self.overlaps.iter().any(|(e, _)| *e == entity) {
+            // Damage immediately on contact
+            self.overlaps.push((entity, 0.));
+        }
+    }
+
+    fn stop_overlap(&mut self, entity: Entity) {
+        self.overlaps.retain(|(e, _)| *e != entity);
+    }
 }
 
 pub struct Lifebar {
@@ -219,6 +1508,12 @@ pub struct ShowLifebarsEvent {
     pub play_audio: bool,
 }
 
+#[derive(Debug, Clone)]
+pub struct HideLifebarsEvent {
+    /// Entity holding the LifebarHud component of the lifebars to hide.
+    pub entity: Entity,
+}
+
 #[derive(Debug, Clone)]
 pub struct UpdateLifebarsEvent {
     /// Entity holding the LifebarHud component to update.
@@ -227,12 +1522,6 @@ pub struct UpdateLifebarsEvent {
     pub remain_life: f32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum LifebarOrientation {
-    Horizontal,
-    Vertical,
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LifebarFillSeqPhase {
     /// Off-screen, waiting.
@@ -247,12 +1536,64 @@ pub enum LifebarFillSeqPhase {
     SlideOut,
 }
 
+/// Screen location of a lifebar HUD.
+///
+/// Lifebars sharing the same anchor stack inward from its edge in the order they're
+/// shown, so that they never overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LifebarAnchor {
+    TopCenter,
+    TopLeft,
+    TopRight,
+    BottomCenter,
+    BottomLeft,
+    BottomRight,
+    LeftCenter,
+    RightCenter,
+}
+
+impl LifebarAnchor {
+    /// Screen edge the lifebars stack inward from.
+    pub fn edge(&self) -> ScreenEdge {
+        match self {
+            LifebarAnchor::TopCenter | LifebarAnchor::TopLeft | LifebarAnchor::TopRight => {
+                ScreenEdge::Top
+            }
+            LifebarAnchor::BottomCenter
+            | LifebarAnchor::BottomLeft
+            | LifebarAnchor::BottomRight => ScreenEdge::Bottom,
+            LifebarAnchor::LeftCenter => ScreenEdge::Left,
+            LifebarAnchor::RightCenter => ScreenEdge::Right,
+        }
+    }
+
+    /// Perpendicular edge the lifebars are aligned to, if any.
+    fn align_edge(&self) -> Option<ScreenEdge> {
+        match self {
+            LifebarAnchor::TopLeft | LifebarAnchor::BottomLeft => Some(ScreenEdge::Left),
+            LifebarAnchor::TopRight | LifebarAnchor::BottomRight => Some(ScreenEdge::Right),
+            _ => None,
+        }
+    }
+}
+
+/// Spacing between two lifebars stacked on the same anchor, in percent of the screen
+/// size along the edge normal.
+const LIFEBAR_STACK_SPACING: f32 = 0.8;
+
+/// Distance past the screen edge of a hidden lifebar, in percent of the screen size
+/// along the edge normal.
+const LIFEBAR_HIDDEN_GAP: f32 = 1.;
+
+/// Lifebars drawn in screen space, as a HUD widget.
 #[derive(Component)]
 pub struct LifebarHud {
     ///
-    pub orientation: LifebarOrientation,
-    pub visible_pos: Vec2,
-    pub hidden_pos: Vec2,
+    pub orientation: BarOrientation,
+    /// Size of the background, in percent of the screen size.
+    pub size: Vec2,
+    /// Size of the bars, in percent of the background size.
+    pub bar_size: Vec2,
     /// Descriptions of all lifebars.
     pub lifebars: Vec<Lifebar>,
     /// Index of current lifebar.
@@ -263,104 +1604,121 @@ pub struct LifebarHud {
     pub remain_life: f32,
     /// Force an update of the lifebar state (including colors).
     pub force_update: bool,
-    /// Material for the next lifebar under the current one, if any.
-    pub under_mat: Handle<StandardMaterial>,
-    /// Material for the current lifebar.
-    pub over_mat: Handle<StandardMaterial>,
+    /// Node of the next lifebar under the current one, if any.
     pub underbar_entity: Entity,
+    /// Node of the current lifebar, child of the underbar node.
     pub overbar_entity: Entity,
     pub fill_seq: LifebarFillSeqPhase,
+    /// Remaining life of the last update received, applied once the lifebar is ready,
+    /// so that the updates received while it slides in or fills up aren't lost.
+    pub pending_life: Option<f32>,
+    /// Despawn the widget once slid out, for the lifebars owned by a single enemy.
+    pub despawn_when_hidden: bool,
+    /// Screen location, set on spawn.
+    anchor: LifebarAnchor,
+    /// Distance from the anchor edge of the first lifebar of the stack, in percent of
+    /// the screen size along the edge normal.
+    margin: f32,
+    /// Distance from the anchor edge while visible, after stacking.
+    visible_inset: f32,
+    /// Order in which the lifebar was last shown, the most recent drawing on top.
+    layer: u32,
 }
 
 impl Default for LifebarHud {
     fn default() -> Self {
         LifebarHud {
-            orientation: LifebarOrientation::Horizontal,
-            visible_pos: Vec2::ZERO,
-            hidden_pos: Vec2::ZERO,
+            orientation: BarOrientation::Horizontal,
+            size: Vec2::ZERO,
+            bar_size: Vec2::ZERO,
             lifebars: vec![],
             index: 0,
             life: 0.,
             remain_life: 0.,
             force_update: false,
-            under_mat: Handle::default(),
-            over_mat: Handle::default(),
             underbar_entity: Entity::from_raw(0),
             overbar_entity: Entity::from_raw(0),
             fill_seq: LifebarFillSeqPhase::Idle,
+            pending_life: None,
+            despawn_when_hidden: false,
+            anchor: LifebarAnchor::TopCenter,
+            margin: 0.,
+            visible_inset: 0.,
+            layer: 0,
         }
     }
 }
 
-impl LifebarHud {
-    pub fn spawn<'w, 's>(
-        mut this: LifebarHud,
-        name: impl Into<std::borrow::Cow<'static, str>>,
-        size_background: Vec2,
-        mat_background: Handle<StandardMaterial>,
-        size: Vec2,
-        commands: &mut Commands<'w, 's>,
-        meshes: &mut Assets<Mesh>,
-        materials: &mut Assets<StandardMaterial>,
-    ) -> Entity {
-        // Bars mesh
-        let mesh = meshes.add(Mesh::from(shape::Quad { size, flip: false }));
-
-        // Underbar material
-        this.under_mat = materials.add(StandardMaterial {
-            base_color: this.lifebars[0].color,
-            unlit: true,
-            alpha_mode: AlphaMode::Blend,
-            ..Default::default()
-        });
-
-        // Overbar material
-        this.over_mat = materials.add(StandardMaterial {
-            base_color: this.lifebars[this.lifebars.len() - 1].color,
-            unlit: true,
-            alpha_mode: AlphaMode::Blend,
-            ..Default::default()
-        });
+impl HudWidget for LifebarHud {
+    fn size(&self) -> Size<Val> {
+        Size::new(Val::Percent(self.size.x), Val::Percent(self.size.y))
+    }
 
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Quad {
-                    size: size_background,
-                    flip: false,
-                })),
-                material: mat_background,
-                transform: Transform::from_translation(Vec3::new(
-                    this.hidden_pos.x,
-                    this.hidden_pos.y,
-                    1.,
-                )),
+    fn spawn_content(&mut self, parent: &mut ChildBuilder) {
+        self.underbar_entity = parent
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(self.bar_size.x), Val::Percent(self.bar_size.y)),
+                    ..Default::default()
+                },
+                color: UiColor(self.lifebars[0].color),
                 ..Default::default()
             })
-            .insert(Name::new(name))
-            .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
+            .insert(LifebarUnder)
             .with_children(|parent| {
-                this.underbar_entity = parent
-                    .spawn_bundle(PbrBundle {
-                        mesh: mesh.clone(),
-                        material: this.under_mat.clone(),
-                        transform: Transform::from_xyz(0.0, 0.0, 0.001),
-                        ..Default::default()
-                    })
-                    .insert(LifebarUnder)
-                    .id();
-                this.overbar_entity = parent
-                    .spawn_bundle(PbrBundle {
-                        mesh,
-                        material: this.over_mat.clone(),
-                        transform: Transform::from_xyz(0.0, 0.0, 0.002),
+                // Scaled about its center to fill up and drain
+                self.overbar_entity = parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                            ..Default::default()
+                        },
+                        color: UiColor(self.lifebars[self.lifebars.len() - 1].color),
                         ..Default::default()
                     })
                     .insert(LifebarOver)
                     .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
                     .id();
             })
-            .insert(this)
-            .id()
+            .id();
+    }
+}
+
+impl LifebarHud {
+    /// Spawn the lifebar widget, hidden, at the given anchor and distance from its
+    /// edge. The lifebar is stacked past the other visible lifebars of the same anchor
+    /// when shown.
+    pub fn spawn<'w, 's>(
+        mut this: LifebarHud,
+        name: impl Into<std::borrow::Cow<'static, str>>,
+        anchor: LifebarAnchor,
+        margin: f32,
+        commands: &mut Commands<'w, 's>,
+    ) -> Entity {
+        this.anchor = anchor;
+        this.margin = margin;
+        this.visible_inset = margin;
+        let hud_anchor = HudAnchor {
+            edge: anchor.edge(),
+            visible_inset: margin,
+            hidden_inset: -(this.thickness() + LIFEBAR_HIDDEN_GAP),
+            align: anchor.align_edge().map(|edge| (edge, margin)),
+        };
+        spawn_hud_widget(commands, name, hud_anchor, this)
+    }
+
+    /// Size of the background along the normal of the anchor edge, in percent of the
+    /// screen size.
+    fn thickness(&self) -> f32 {
+        match self.anchor.edge() {
+            ScreenEdge::Top | ScreenEdge::Bottom => self.size.y,
+            ScreenEdge::Left | ScreenEdge::Right => self.size.x,
+        }
+    }
+
+    /// Is the lifebar on screen, or about to be?
+    fn is_shown(&self) -> bool {
+        self.fill_seq != LifebarFillSeqPhase::Idle && self.fill_seq != LifebarFillSeqPhase::SlideOut
     }
 
     pub fn set_lifebars(&mut self, life: f32, colors: impl IntoIterator<Item = Color>) {
@@ -380,18 +1738,167 @@ impl LifebarHud {
 #[derive(Component)]
 struct LifebarCounter;
 
-#[derive(Component)]
-struct ScoreCounter(u32);
-
 pub struct ScoreEvent(pub u32);
 
+/// Event sent when a stage is cleared by defeating its boss.
+#[derive(Debug, Clone, Copy)]
+pub struct StageClearEvent {
+    /// Index of the cleared stage, starting from zero.
+    pub stage: usize,
+    /// Is this the last stage of the run?
+    pub is_last: bool,
+}
+
+/// Event sent when a stage starts, either at the start of the game or after clearing
+/// the previous stage.
+#[derive(Debug, Clone, Copy)]
+pub struct StageStartEvent {
+    /// Index of the started stage, starting from zero.
+    pub stage: usize,
+}
+
+/// Event sent when the player uses a bomb.
+#[derive(Debug, Clone, Copy)]
+pub struct BombEvent;
+
+/// Event sent when the player fires a volley or a charge shot.
+#[derive(Debug, Clone, Copy)]
+pub struct ShotEvent {
+    /// Number of bullets fired.
+    pub bullets: u32,
+}
+
+/// Event sent when the player runs out of life.
+///
+/// The life loss, the respawn or the game over are applied by [`kill_player()`] in the
+/// same gameplay step.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerKilledEvent {
+    pub entity: Entity,
+    pub position: Vec3,
+}
+
+/// Event sent when the player takes damage.
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerHitEvent;
+
+/// Event sent when the player lost their last life, to offer a continue.
+#[derive(Debug, Clone, Copy)]
+pub struct LastLifeLostEvent;
+
+/// Event sent when the player continues after losing their last life.
+#[derive(Debug, Clone, Copy)]
+pub struct ContinueEvent;
+
+/// Event sent when the game ends, after the player lost their last life and declined
+/// to continue.
+#[derive(Debug, Clone, Copy)]
+pub struct GameOverEvent;
+
 #[derive(Component)]
 struct GameOverText;
 
-fn lifebar_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    commands.spawn_bundle(UiCameraBundle::default());
+/// Lifebar HUD of the player, which outlives the player entity after a game over.
+#[derive(Component)]
+struct PlayerLifebar {
+    /// Full life of the player, to spawn it again on continue.
+    life: f32,
+}
+
+#[derive(Component)]
+struct LivesCounter;
+
+/// Number of extra lives of the player.
+pub struct Lives {
+    /// Remaining extra lives, not counting the current one.
+    pub remaining: u32,
+}
+
+impl Default for Lives {
+    fn default() -> Self {
+        Lives { remaining: 2 }
+    }
+}
+
+/// Delay between the player death and its respawn, in seconds.
+const RESPAWN_DELAY: f32 = 1.5;
+
+/// Duration of the invincibility window after respawning, in seconds.
+const INVINCIBLE_DURATION: f32 = 3.;
+
+/// Duration of the invincibility window after taking damage, in seconds.
+const HIT_INVINCIBLE_DURATION: f32 = 1.;
+
+/// Duration of the gameplay freeze when the player takes damage, in seconds.
+const HIT_STOP_DURATION: f32 = 0.08;
 
-    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+/// Color of the screen overlay flashing when the player takes damage, at its peak.
+const HIT_OVERLAY_COLOR: Color = Color::rgba(0.9, 0.05, 0.05, 0.35);
+
+/// Duration of the fade out of the hit overlay, in seconds.
+const HIT_OVERLAY_DURATION: f32 = 0.35;
+
+/// Red overlay covering the screen when the player takes damage.
+#[derive(Component, Default)]
+struct HitOverlay {
+    /// Remaining time of the fade out, in seconds.
+    remaining: f32,
+}
+
+fn hit_overlay_setup(mut commands: Commands) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("HitOverlay"))
+        .insert(HitOverlay::default())
+        .insert(InGameEntity);
+}
+
+/// Freeze the gameplay for a moment and flash the hit overlay when the player takes
+/// damage. The overlay doesn't flash when flashing is reduced.
+fn update_hit_feedback(
+    mut hit_events: EventReader<PlayerHitEvent>,
+    mut game_time: ResMut<GameTime>,
+    mut query: Query<(&mut HitOverlay, &mut UiColor)>,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let (mut overlay, mut color) = if let Ok(overlay) = query.get_single_mut() {
+        overlay
+    } else {
+        return;
+    };
+    if hit_events.iter().count() > 0 {
+        game_time.start_hit_stop(HIT_STOP_DURATION);
+        if !settings.accessibility.reduce_flashing {
+            overlay.remaining = HIT_OVERLAY_DURATION;
+        }
+    }
+    // Real time, since the game time stands still during the hit-stop
+    overlay.remaining = (overlay.remaining - time.delta_seconds()).max(0.);
+    let mut overlay_color = HIT_OVERLAY_COLOR;
+    overlay_color.set_a(HIT_OVERLAY_COLOR.a() * overlay.remaining / HIT_OVERLAY_DURATION);
+    if color.0 != overlay_color {
+        color.0 = overlay_color;
+    }
+}
+
+/// Position where the player spawns and respawns, on the left side of the screen.
+const PLAYER_SPAWN_POSITION: Vec3 = const_vec3!([-1.5, 0., 0.]);
+
+fn lifebar_text_setup(mut commands: Commands, common_assets: Res<CommonAssets>) {
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(InGameEntity);
+
+    let font = common_assets.hud_font.clone();
 
     commands
         .spawn_bundle(NodeBundle {
@@ -405,6 +1912,7 @@ fn lifebar_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             ..Default::default()
         })
         .insert(Name::new("LifeBarText"))
+        .insert(InGameEntity)
         .with_children(|parent| {
             parent
                 .spawn_bundle(TextBundle {
@@ -440,17 +1948,17 @@ fn lifebar_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                         align_self: AlignSelf::FlexStart,
                         position_type: PositionType::Absolute,
                         position: Rect {
-                            top: Val::Px(50.0),
+                            top: Val::Px(104.0),
                             right: Val::Px(50.0),
                             ..Default::default()
                         },
                         ..Default::default()
                     },
                     text: Text::with_section(
-                        "00000000",
+                        "",
                         TextStyle {
                             font: font.clone(),
-                            font_size: 48.0,
+                            font_size: 26.0,
                             color: Color::rgb_u8(32, 32, 32),
                         },
                         TextAlignment {
@@ -460,7 +1968,7 @@ fn lifebar_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ),
                     ..Default::default()
                 })
-                .insert(ScoreCounter(0));
+                .insert(LivesCounter);
 
             parent
                 .spawn_bundle(TextBundle {
@@ -492,14 +2000,76 @@ fn lifebar_text_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ..Default::default()
                 })
                 .insert(GameOverText);
+
+            // Charge meter of the secondary weapon
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            bottom: Val::Px(20.0),
+                            left: Val::Px(50.0),
+                            ..Default::default()
+                        },
+                        size: Size::new(Val::Px(160.), Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::rgba_u8(32, 32, 32, 160)),
+                    ..Default::default()
+                })
+                .insert(Name::new("ChargeMeter"))
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                ..Default::default()
+                            },
+                            ..Default::default()
+                        })
+                        .insert(ChargeMeter);
+                });
         });
 }
 
 #[derive(Component, Default)]
 struct HudManager {}
 
-// FIXME
-const SHIP1_SCALE: f32 = 0.3;
+/// Clamp a position inside some bounds shrunk by the given margin on each side. If the
+/// bounds are smaller than twice the margin, the position is centered instead.
+fn clamp_to_bounds(position: Vec3, bounds: &Rect<f32>, margin: Vec2) -> Vec3 {
+    let clamp_axis = |value: f32, min: f32, max: f32| {
+        if min <= max {
+            value.clamp(min, max)
+        } else {
+            (min + max) / 2.
+        }
+    };
+    Vec3::new(
+        clamp_axis(position.x, bounds.left + margin.x, bounds.right - margin.x),
+        clamp_axis(position.y, bounds.bottom + margin.y, bounds.top - margin.y),
+        position.z,
+    )
+}
+
+/// Event writers for the feedback of the player actions.
+#[derive(SystemParam)]
+struct PlayerFeedback<'w, 's> {
+    shake: EventWriter<'w, 's, ScreenShakeEvent>,
+    rumble: EventWriter<'w, 's, RumbleRequest>,
+    sfx: EventWriter<'w, 's, SfxEvent>,
+    muzzle: EventWriter<'w, 's, MuzzleFlashEvent>,
+    shot: EventWriter<'w, 's, ShotEvent>,
+    hit: EventWriter<'w, 's, PlayerHitEvent>,
+}
+
+/// Event writers for the player lifebar.
+#[derive(SystemParam)]
+struct PlayerLifebarEvents<'w, 's> {
+    update: EventWriter<'w, 's, UpdateLifebarsEvent>,
+    show: EventWriter<'w, 's, ShowLifebarsEvent>,
+    hide: EventWriter<'w, 's, HideLifebarsEvent>,
+}
 
 fn update_player(
     mut commands: Commands,
@@ -508,13 +2078,20 @@ fn update_player(
         &mut PlayerController,
         &ActionState<PlayerAction>,
         &mut Transform,
+        Option<&Overdrive>,
     )>,
-    mut q_ship: Query<(&mut Transform, &mut ShipController), Without<PlayerController>>,
-    time: Res<Time>,
     mut damage_events: EventReader<DamageEvent>,
-    mut lifebar_events: EventWriter<UpdateLifebarsEvent>,
+    mut lifebar_events: PlayerLifebarEvents,
     q_camera: Query<&MainCamera>,
-    mut q_gameover: Query<&mut Visibility, With<GameOverText>>,
+    mut killed_events: EventWriter<PlayerKilledEvent>,
+    q_bullets: Query<Entity, (With<EnemyBullet>, Without<PendingDespawn>)>,
+    mut bomb_events: EventWriter<BombEvent>,
+    mut log_events: EventWriter<LogEvent>,
+    banners: Res<StageBanners>,
+    mut overdrive_meter: ResMut<OverdriveMeter>,
+    stick: GamepadStick,
+    mut feedback: PlayerFeedback,
+    tuning: Res<Tuning>,
     // DEBUG
     //mut init_events: EventWriter<InitLifebarsEvent>,
     //mut show_events: EventWriter<ShowLifebarsEvent>,
@@ -526,8 +2103,9 @@ fn update_player(
         return;
     }
 
-    let (player_entity, mut controller, action_state, mut transform) = query.single_mut();
-    let dt = time.delta_seconds();
+    let (player_entity, mut controller, action_state, mut transform, overdrive) =
+        query.single_mut();
+    let dt = FIXED_DELTA_SECONDS;
 
     // Apply damage to player
     let player_damage: f32 = damage_events
@@ -542,26 +2120,63 @@ fn update_player(
         .sum();
     if player_damage > 0. {
         controller.remain_life -= player_damage;
+        controller.hits_taken += 1;
+        feedback.shake.send(ScreenShakeEvent { trauma: 0.5 });
+        feedback.rumble.send(RumbleRequest {
+            strong: 0.8,
+            weak: 0.4,
+            duration: 0.2,
+        });
+        feedback.sfx.send(SfxEvent::new(Sfx::PlayerHit));
+        feedback.hit.send(PlayerHitEvent);
+        // Brief invulnerability, so that a single contact doesn't drain the life on
+        // every collision
+        controller.invincible_time = controller.invincible_time.max(HIT_INVINCIBLE_DURATION);
         // println!(
         //     "Player damaged: damage={} remain_life={} target_hud={:?}",
         //     player_damage, controller.remain_life, controller.lifebar_entity
         // );
-        lifebar_events.send(UpdateLifebarsEvent {
+        lifebar_events.update.send(UpdateLifebarsEvent {
             entity: controller.lifebar_entity,
             remain_life: controller.remain_life,
         });
     }
-    if controller.remain_life <= 0. {
-        if !q_gameover.is_empty() {
-            let mut vis = q_gameover.single_mut();
-            vis.is_visible = true;
-        }
-        commands.entity(player_entity).despawn_recursive();
-        // GAME ENDS
-        println!("PLAYER KILLED");
+    // The death is applied by kill_player(); don't move nor shoot meanwhile
+    if controller.remain_life <= 0. && !controller.is_respawning() {
+        killed_events.send(PlayerKilledEvent {
+            entity: player_entity,
+            position: transform.translation,
+        });
         return;
     }
 
+    // Wait for respawn; the ship is hidden and cannot move nor shoot
+    if controller.is_respawning() {
+        controller.respawn_delay -= dt;
+        if controller.is_respawning() {
+            return;
+        }
+        controller.respawn_delay = 0.;
+        controller.invincible_time = INVINCIBLE_DURATION;
+        controller.knockback = Vec2::ZERO;
+        controller.ram_time = 0.;
+        controller.remain_life = controller.life;
+        controller.primary_timer.reset();
+        transform.translation = PLAYER_SPAWN_POSITION;
+        lifebar_events.show.send(ShowLifebarsEvent {
+            entity: controller.lifebar_entity,
+            play_audio: false,
+        });
+        lifebar_events.update.send(UpdateLifebarsEvent {
+            entity: controller.lifebar_entity,
+            remain_life: controller.remain_life,
+        });
+        log_events.send(LogEvent::PlayerRespawned);
+    }
+    controller.invincible_time = (controller.invincible_time - dt).max(0.);
+    controller.contact_cooldown = (controller.contact_cooldown - dt).max(0.);
+    controller.ram_time = (controller.ram_time - dt).max(0.);
+
     // Move player
     controller.input_dir = Vec2::ZERO;
     if action_state.pressed(PlayerAction::MoveUp) {
@@ -576,62 +2191,130 @@ fn update_player(
     if action_state.pressed(PlayerAction::MoveRight) {
         controller.input_dir.x += 1.;
     }
-    let dv = if let Some(input_dir) = controller.input_dir.try_normalize() {
+    let stick_dir = stick.direction();
+    if stick_dir != Vec2::ZERO {
+        // The stick takes precedence over the buttons, with an analog speed
+        controller.input_dir = stick_dir;
+    } else if let Some(input_dir) = controller.input_dir.try_normalize() {
         controller.input_dir = input_dir;
-        const SPEED: f32 = 1.6;
-        let dv = input_dir * SPEED * dt;
-        transform.translation += Vec3::new(dv.x, dv.y, 0.);
-        let screen_bounds = if q_camera.is_empty() {
-            Rect::<f32> {
-                left: -3.49,
-                right: 3.49,
-                bottom: -1.96,
-                top: 1.96,
-            }
-        } else {
-            q_camera.single().screen_bounds
-        };
-        transform.translation.x = transform
-            .translation
-            .x
-            .clamp(screen_bounds.left, screen_bounds.right);
-        transform.translation.y = transform
-            .translation
-            .y
-            .clamp(screen_bounds.bottom, screen_bounds.top);
-        dv
+    }
+    let dv = controller.input_dir * controller.speed * tuning.player_speed * dt;
+    transform.translation += Vec3::new(dv.x, dv.y, 0.);
+
+    // Bump back from enemy bodies, on top of the player input
+    if controller.knockback != Vec2::ZERO {
+        let knockback = controller.knockback * dt;
+        transform.translation += Vec3::new(knockback.x, knockback.y, 0.);
+        controller.knockback *= (-KNOCKBACK_DAMPING * dt).exp();
+        if controller.knockback.length_squared() < 1e-4 {
+            controller.knockback = Vec2::ZERO;
+        }
+    }
+
+    // Keep the whole ship on screen, even when not moving, in case the screen bounds
+    // shrank after the window got resized
+    let screen_bounds = if q_camera.is_empty() {
+        Rect::<f32> {
+            left: -3.49,
+            right: 3.49,
+            bottom: -1.96,
+            top: 1.96,
+        }
     } else {
-        Vec2::ZERO
+        q_camera.single().screen_bounds
     };
+    let clamped = clamp_to_bounds(transform.translation, &screen_bounds, controller.half_size);
+    if clamped != transform.translation {
+        transform.translation = clamped;
+    }
 
-    let (mut ship_transform, mut ship_controller) = q_ship.single_mut();
-    let target_roll = if dv.y > 0. {
-        -40.
-    } else {
-        if dv.y < 0. {
-            40.
-        } else {
-            0.
+    // Banners briefly lock the weapons, but not the movement
+    let input_locked = banners.is_input_locked();
+
+    let trigger =
+        !input_locked && (controller.auto_fire || action_state.pressed(PlayerAction::ShootPrimary));
+    let mut delay =
+        controller.primary_fire_delay * controller.fire_delay_factor * tuning.primary_fire_delay;
+    if overdrive.is_some() {
+        delay *= OVERDRIVE_FIRE_DELAY_FACTOR;
+    }
+    if controller.primary_timer.tick(dt, trigger, delay) {
+        let mut origin = transform.clone();
+        origin.translation += controller.primary_fire_offset;
+        let volley = &controller.power_tiers[controller.power_tier()].volley;
+        for &(offset, angle) in volley {
+            let mut transform = origin;
+            transform.translation.y += offset;
+            let velocity = Quat::from_rotation_z(angle.to_radians())
+                .mul_vec3(Vec3::X * tuning.player_bullet_speed);
+            controller.spawn_bullet(&mut commands, &transform, velocity);
         }
-    };
-    let roll = ship_controller.roll.lerp(&target_roll, &(dt * 5.));
-    ship_controller.roll = roll;
-    ship_transform.rotation = Quat::from_rotation_x(roll.to_radians());
+        feedback.shot.send(ShotEvent {
+            bullets: volley.len() as u32,
+        });
+        feedback.muzzle.send(MuzzleFlashEvent {
+            position: origin.translation,
+            direction: Some(Vec3::X),
+        });
+        feedback.sfx.send(SfxEvent::new(Sfx::Shoot));
+    }
 
-    let was_cooling = controller.primary_cooloff > 0.;
-    controller.primary_cooloff -= dt;
-    if action_state.pressed(PlayerAction::ShootPrimary) && controller.primary_cooloff <= 0. {
-        if !was_cooling {
-            controller.primary_cooloff = 0.;
+    // Charge the secondary weapon while held, and fire on release
+    if input_locked {
+        controller.charge = 0.;
+    } else if action_state.pressed(PlayerAction::ShootSecondary) {
+        controller.charge = (controller.charge + dt / CHARGE_DURATION).min(1.);
+        // Light buzz growing with the charge, renewed on each step while held
+        feedback.rumble.send(RumbleRequest {
+            strong: 0.,
+            weak: 0.1 + 0.2 * controller.charge,
+            duration: 0.1,
+        });
+    } else if controller.charge > 0. {
+        if controller.charge >= MIN_CHARGE {
+            let mut origin = transform.clone();
+            origin.translation += controller.primary_fire_offset;
+            controller.spawn_charge_shot(&mut commands, &origin, tuning.charge_shot_speed);
+            feedback.shot.send(ShotEvent { bullets: 1 });
+            feedback.shake.send(ScreenShakeEvent {
+                trauma: 0.2 * controller.charge,
+            });
         }
-        controller.primary_cooloff += controller.primary_fire_delay;
-        let mut transform = transform.clone();
-        transform.translation += controller.primary_fire_offset * SHIP1_SCALE / 2.; // FIXME - fire origin
-        controller.spawn_bullet(&mut commands, &transform);
-        transform.translation.y += 0.1;
-        controller.spawn_bullet(&mut commands, &transform);
-        transform.translation.y -= 0.2;
-        controller.spawn_bullet(&mut commands, &transform);
+        controller.charge = 0.;
+    }
+
+    // Bomb clears all enemy bullets
+    let bomb_pressed = std::mem::take(&mut controller.bomb_pressed);
+    if bomb_pressed && !input_locked && controller.bombs > 0 {
+        controller.bombs -= 1;
+        controller.bombs_used += 1;
+        feedback.shake.send(ScreenShakeEvent { trauma: 0.6 });
+        feedback.rumble.send(RumbleRequest {
+            strong: 1.,
+            weak: 0.6,
+            duration: 0.35,
+        });
+        bomb_events.send(BombEvent);
+        log_events.send(LogEvent::Bomb {
+            remaining: controller.bombs,
+        });
+        for entity in q_bullets.iter() {
+            commands.entity(entity).insert(PendingDespawn);
+        }
+    }
+
+    // Overdrive burns the full meter
+    let overdrive_pressed = std::mem::take(&mut controller.overdrive_pressed);
+    if overdrive_pressed && !input_locked && overdrive.is_none() && overdrive_meter.burn() {
+        commands.entity(player_entity).insert(Overdrive::default());
+        feedback.shake.send(ScreenShakeEvent { trauma: 0.3 });
+        feedback.rumble.send(RumbleRequest {
+            strong: 0.5,
+            weak: 0.8,
+            duration: 0.3,
+        });
+        feedback.sfx.send(SfxEvent::new(Sfx::ChargeUp));
+        log_events.send(LogEvent::Overdrive);
     }
 
     // DEBUG
@@ -648,163 +2331,518 @@ fn update_player(
     // }
 }
 
+/// Lose a life and wait for the respawn when the player is killed, or end the game
+/// unless the player continues if that was the last life.
+fn kill_player(
+    mut commands: Commands,
+    mut killed_events: EventReader<PlayerKilledEvent>,
+    mut query: Query<&mut PlayerController>,
+    mut lives: ResMut<Lives>,
+    mut last_life_events: EventWriter<LastLifeLostEvent>,
+    mut log_events: EventWriter<LogEvent>,
+    mut hide_events: EventWriter<HideLifebarsEvent>,
+    mut sfx_events: EventWriter<SfxEvent>,
+) {
+    for ev in killed_events.iter() {
+        let mut controller = if let Ok(controller) = query.get_mut(ev.entity) {
+            controller
+        } else {
+            continue;
+        };
+        // The lifebar slides back in on respawn or continue
+        hide_events.send(HideLifebarsEvent {
+            entity: controller.lifebar_entity,
+        });
+        sfx_events.send(SfxEvent::new(Sfx::Explosion));
+        if lives.remaining == 0 {
+            // The game ends unless the player continues
+            commands.entity(ev.entity).despawn_recursive();
+            last_life_events.send(LastLifeLostEvent);
+            log_events.send(LogEvent::PlayerKilled { lives: 0 });
+            continue;
+        }
+        lives.remaining -= 1;
+        controller.respawn_delay = RESPAWN_DELAY;
+        controller.charge = 0.;
+        log_events.send(LogEvent::PlayerKilled {
+            lives: lives.remaining,
+        });
+    }
+}
+
+/// Toggle the auto-fire from its quick key, and follow the setting.
+fn update_auto_fire(
+    mut query: Query<(&mut PlayerController, &ActionState<PlayerAction>)>,
+    mut settings: ResMut<Settings>,
+) {
+    for (mut controller, action_state) in query.iter_mut() {
+        if action_state.just_pressed(PlayerAction::ToggleAutoFire) {
+            settings.gameplay.auto_fire = !settings.gameplay.auto_fire;
+            settings.save();
+        }
+        if controller.auto_fire != settings.gameplay.auto_fire {
+            controller.auto_fire = settings.gameplay.auto_fire;
+        }
+    }
+}
+
+/// Apply the [`DebugCheats`] to the player.
+fn apply_debug_cheats(
+    mut query: Query<&mut PlayerController>,
+    cheats: Res<DebugCheats>,
+    launch: Res<LaunchOptions>,
+) {
+    for mut controller in query.iter_mut() {
+        let invulnerable = cheats.god_mode || launch.invulnerable_player();
+        if controller.invulnerable != invulnerable {
+            controller.invulnerable = invulnerable;
+        }
+        if cheats.infinite_bombs && controller.bombs < MAX_BOMBS {
+            controller.bombs = MAX_BOMBS;
+        }
+    }
+}
+
+/// Remember the button presses until the next fixed step, which may not run during
+/// the frame of the press on monitors refreshing faster than the steps.
+fn latch_player_input(mut query: Query<(&mut PlayerController, &ActionState<PlayerAction>)>) {
+    for (mut controller, action_state) in query.iter_mut() {
+        if action_state.just_pressed(PlayerAction::Bomb) {
+            controller.bomb_pressed = true;
+        }
+        if action_state.just_pressed(PlayerAction::Overdrive) {
+            controller.overdrive_pressed = true;
+        }
+    }
+}
+
+/// Give a mesh newly spawned below the root of a GLTF scene its own copy of its
+/// material, so that it can be edited without affecting the other instances of the
+/// model, which share the materials of the loaded GLTF asset. The scene root is the
+/// first ancestor of the mesh for which `is_root` returns true, returned if found.
+pub fn own_scene_material(
+    entity: Entity,
+    handle: &mut Handle<StandardMaterial>,
+    q_parents: &Query<&Parent>,
+    is_root: impl Fn(Entity) -> bool,
+    materials: &mut Assets<StandardMaterial>,
+) -> Option<Entity> {
+    // The scene meshes are spawned some levels below the scene root
+    let mut ancestor = entity;
+    while let Ok(parent) = q_parents.get(ancestor) {
+        ancestor = parent.0;
+        if is_root(ancestor) {
+            if let Some(material) = materials.get(&*handle).cloned() {
+                *handle = materials.add(material);
+            }
+            return Some(ancestor);
+        }
+    }
+    None
+}
+
+/// Give the player ship its own copy of the materials of its scene, once spawned, so
+/// that [`update_player_blink()`] doesn't fade the other instances of the ship model,
+/// and tint them with the color of the ship.
+fn setup_ship_materials(
+    mut q_materials: Query<
+        (Entity, &mut Handle<StandardMaterial>),
+        Added<Handle<StandardMaterial>>,
+    >,
+    q_parents: Query<&Parent>,
+    q_ships: Query<(), With<ShipController>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    hangar: Res<Hangar>,
+) {
+    let tint = hangar.ship().tint;
+    for (entity, mut handle) in q_materials.iter_mut() {
+        let ship = own_scene_material(
+            entity,
+            &mut handle,
+            &q_parents,
+            |ancestor| q_ships.contains(ancestor),
+            &mut materials,
+        );
+        if let (Some(_), Some([r, g, b])) = (ship, tint) {
+            if let Some(material) = materials.get_mut(&*handle) {
+                let color = material.base_color;
+                material.base_color =
+                    Color::rgba(color.r() * r, color.g() * g, color.b() * b, color.a());
+            }
+        }
+    }
+}
+
+/// Blink the player ship while invincible, and hide it while waiting to respawn, by
+/// animating the alpha of its own copy of the ship materials.
+fn update_player_blink(
+    q_player: Query<(Entity, &PlayerController)>,
+    q_children: Query<&Children>,
+    q_materials: Query<&Handle<StandardMaterial>>,
+    q_effects: Query<(), Or<(With<ChargeGlow>, With<EngineExhaust>)>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut was_blinking: Local<bool>,
+    settings: Res<Settings>,
+) {
+    let (player_entity, controller) = if let Ok(player) = q_player.get_single() {
+        player
+    } else {
+        return;
+    };
+    let alpha = if controller.is_respawning() {
+        0.
+    } else if controller.invincible_time > 0. {
+        if settings.accessibility.reduce_flashing {
+            // Stay see-through instead of blinking
+            0.55
+        } else {
+            // Blink 5 times per second
+            0.55 + 0.35 * (controller.invincible_time * 5. * 2. * PI).cos()
+        }
+    } else {
+        1.
+    };
+    let is_blinking = alpha < 1.;
+    if !is_blinking && !*was_blinking {
+        return;
+    }
+    *was_blinking = is_blinking;
+
+    // The materials are on the meshes of the ship scene, deep below the player entity.
+    // The effects attached to the ship are translucent already, and keep their alpha.
+    let mut entities = vec![player_entity];
+    while let Some(entity) = entities.pop() {
+        if q_effects.contains(entity) {
+            continue;
+        }
+        if let Ok(handle) = q_materials.get(entity) {
+            if let Some(material) = materials.get_mut(handle) {
+                material.base_color.set_a(alpha);
+                material.alpha_mode = if is_blinking {
+                    AlphaMode::Blend
+                } else {
+                    AlphaMode::Opaque
+                };
+            }
+        }
+        if let Ok(children) = q_children.get(entity) {
+            entities.extend(children.iter().copied());
+        }
+    }
+}
+
+/// Bank the ship model and stretch its engine exhaust with the player input, and bob it
+/// when idle.
+fn update_ship_model(
+    q_player: Query<&PlayerController>,
+    mut q_ship: Query<(&mut Transform, &mut ShipController)>,
+    mut q_exhaust: Query<&mut Transform, (With<EngineExhaust>, Without<ShipController>)>,
+    game_time: Res<GameTime>,
+    time: Res<Time>,
+) {
+    let (input_dir, is_respawning) = if let Ok(controller) = q_player.get_single() {
+        (controller.input_dir, controller.is_respawning())
+    } else {
+        return;
+    };
+    let dt = game_time.delta_seconds();
+    let (mut transform, mut ship) = if let Ok(ship) = q_ship.get_single_mut() {
+        ship
+    } else {
+        return;
+    };
+    let smooth = (dt * 5.).min(1.);
+    ship.roll = ship.roll.lerp(&(-input_dir.y * SHIP_MAX_ROLL), &smooth);
+    ship.yaw = ship.yaw.lerp(&(input_dir.x * SHIP_MAX_YAW), &smooth);
+    let target_bob = if input_dir == Vec2::ZERO { 1. } else { 0. };
+    ship.bob_weight = ship.bob_weight.lerp(&target_bob, &(dt * 2.).min(1.));
+    ship.bob_phase = (ship.bob_phase + dt * SHIP_BOB_FREQUENCY * TAU) % TAU;
+    let (backward, forward) = EXHAUST_LENGTH_RANGE;
+    let target_length = if input_dir.x > 0. {
+        1. + (forward - 1.) * input_dir.x
+    } else {
+        1. + (1. - backward) * input_dir.x
+    };
+    ship.exhaust_length = ship.exhaust_length.lerp(&target_length, &(dt * 8.).min(1.));
+
+    transform.rotation = Quat::from_rotation_y(ship.yaw.to_radians())
+        * Quat::from_rotation_x(ship.roll.to_radians());
+    transform.translation.y = ship.bob_phase.sin() * SHIP_BOB_AMPLITUDE * ship.bob_weight;
+
+    // The ship is hidden while respawning
+    let flicker = 1. + 0.12 * (time.seconds_since_startup() as f32 * 37.).sin();
+    for mut exhaust_transform in q_exhaust.iter_mut() {
+        exhaust_transform.scale = if is_respawning {
+            Vec3::ZERO
+        } else {
+            Vec3::new(ship.exhaust_length * flicker, 1., 1.)
+        };
+        exhaust_transform.translation.y = transform.translation.y;
+    }
+}
+
+/// Place the player hitboxes from the bounds of the ship model, once it's spawned.
+fn update_player_hitboxes(
+    mut q_player: Query<&mut PlayerHitboxes>,
+    q_model: Query<(&Transform, &GlobalTransform), With<ShipController>>,
+    q_children: Query<&Children>,
+    q_bounds: Query<(&Aabb, &GlobalTransform)>,
+    mut q_hurt_box: Query<&mut Transform, (With<HurtBox>, Without<ShipController>)>,
+    mut q_collect_box: Query<
+        (&mut Transform, &mut CollisionShape),
+        (With<CollectBox>, Without<HurtBox>, Without<ShipController>),
+    >,
+) {
+    let mut hitboxes = if let Ok(hitboxes) = q_player.get_single_mut() {
+        hitboxes
+    } else {
+        return;
+    };
+    let model_root = if let Some(model_root) = hitboxes.model_root {
+        model_root
+    } else {
+        return;
+    };
+    let (model_transform, model_global_transform) = if let Ok(model) = q_model.get(model_root) {
+        model
+    } else {
+        return;
+    };
+    // In the local space of the model, so that the roll of the ship doesn't matter
+    let (min, max) = if let Some(bounds) =
+        model_bounds(model_root, model_global_transform, &q_children, &q_bounds)
+    {
+        bounds
+    } else {
+        // Scene not spawned yet
+        return;
+    };
+    let center = (min + max) / 2. * model_transform.scale;
+    let half_size = (max - min) / 2. * model_transform.scale;
+    let ship_radius = half_size.x.max(half_size.y);
+
+    // Ships face right
+    hitboxes.hurt_offset = Vec3::new(center.x + half_size.x * COCKPIT_OFFSET, center.y, 0.);
+    hitboxes.graze_radius = ship_radius + GRAZE_MARGIN;
+    hitboxes.model_root = None;
+    if let Ok(mut transform) = q_hurt_box.get_single_mut() {
+        transform.translation = hitboxes.hurt_offset;
+    }
+    let collect_radius = ship_radius * COLLECT_SCALE;
+    if let Ok((mut transform, mut shape)) = q_collect_box.get_single_mut() {
+        transform.translation = Vec3::new(center.x, center.y, 0.);
+        *shape = CollisionShape::Sphere {
+            radius: collect_radius,
+        };
+    }
+    debug!(
+        target: "game",
+        "Player hitboxes: hurt_offset={:?} hurt_radius={} graze_radius={} collect_radius={}",
+        hitboxes.hurt_offset,
+        hitboxes.hurt_radius,
+        hitboxes.graze_radius,
+        collect_radius
+    );
+}
+
+/// Grow the charge glow with the charge of the secondary weapon, pulsing once full.
+fn update_charge_glow(
+    q_player: Query<&PlayerController>,
+    mut q_glow: Query<(&mut Transform, &mut Visibility), With<ChargeGlow>>,
+    time: Res<Time>,
+) {
+    let charge = q_player
+        .get_single()
+        .map_or(0., |controller| controller.charge());
+    for (mut transform, mut visibility) in q_glow.iter_mut() {
+        if charge <= 0. {
+            if visibility.is_visible {
+                visibility.is_visible = false;
+            }
+            continue;
+        }
+        visibility.is_visible = true;
+        let pulse = if charge >= 1. {
+            1. + 0.15 * (time.seconds_since_startup() as f32 * 20.).sin()
+        } else {
+            1.
+        };
+        transform.scale = Vec3::splat((0.04 + 0.12 * charge) * pulse);
+    }
+}
+
+fn update_charge_meter(
+    q_player: Query<&PlayerController>,
+    mut q_meter: Query<(&mut Style, &mut UiColor), With<ChargeMeter>>,
+) {
+    let charge = q_player
+        .get_single()
+        .map_or(0., |controller| controller.charge());
+    for (mut style, mut color) in q_meter.iter_mut() {
+        let width = Val::Percent(charge * 100.);
+        if style.size.width != width {
+            style.size.width = width;
+        }
+        // Gray until the charge is enough to fire, white once full
+        let target = if charge >= 1. {
+            Color::WHITE
+        } else if charge >= MIN_CHARGE {
+            CHARGE_COLOR
+        } else {
+            Color::rgb_u8(96, 96, 96)
+        };
+        if color.0 != target {
+            color.0 = target;
+        }
+    }
+}
+
+fn update_lives_counter(lives: Res<Lives>, mut query: Query<&mut Text, With<LivesCounter>>) {
+    if !lives.is_changed() {
+        return;
+    }
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!("Lives: {}", lives.remaining);
+    }
+}
+
+/// Distance of the player lifebars from the left edge of the screen while visible, in
+/// percent of the screen width. See [`LifebarHud::spawn()`].
+const PLAYER_LIFEBAR_MARGIN: f32 = 0.5;
+
 /// Calculate screen bounds based on camera projection.
 fn update_screen_bounds(
-    mut query: Query<(
-        &mut MainCamera,
-        ChangeTrackers<PerspectiveProjection>,
-        &PerspectiveProjection,
-        ChangeTrackers<Transform>,
-        &Transform,
-    )>,
+    mut query: Query<
+        (
+            &mut MainCamera,
+            Option<&PerspectiveProjection>,
+            Option<&OrthographicProjection>,
+            &CameraBaseTransform,
+        ),
+        Or<(
+            Changed<PerspectiveProjection>,
+            Changed<OrthographicProjection>,
+            Changed<CameraBaseTransform>,
+        )>,
+    >,
 ) {
-    let (
-        mut main_camera,
-        camera_projection_tracker,
-        camera_projection,
-        camera_transform_tracker,
-        camera_transform,
-    ) = query.single_mut();
-    if camera_projection_tracker.is_changed() || camera_transform_tracker.is_changed() {
-        main_camera.update_screen_bounds(camera_projection, camera_transform);
+    if let Ok((mut main_camera, perspective, orthographic, camera_transform)) =
+        query.get_single_mut()
+    {
+        main_camera.update_screen_bounds(perspective, orthographic, &camera_transform.0);
     }
 }
 
+/// Despawn the projectiles which left the screen by more than their [`CullMargin`].
+///
+/// The screen bounds of the [`MainCamera`] ignore any screen shake.
 fn despawn_bullets_outside_screen(
     mut commands: Commands,
-    mut query: Query<(Entity, &mut Transform, &Bullet), Without<MainCamera>>,
-    q_camera: Query<(&PerspectiveProjection, &Transform), With<MainCamera>>,
+    query: Query<
+        (Entity, &Transform, Option<&CullMargin>, Option<&Bounce>),
+        (
+            Or<(With<Bullet>, With<CullMargin>)>,
+            Without<PendingDespawn>,
+        ),
+    >,
+    q_camera: Query<&MainCamera>,
 ) {
-    // Calculate screen bounds based on camera
-    let (camera_projection, camera_transform) = q_camera.single();
-    // TODO - Dynamic margin in world units, to make it constant-size in screen space
-    const MARGIN: f32 = 1.5; // in world units, so actually quite big if camera.x ~= 5 units
-    let mut camera_half_height =
-        (camera_projection.fov * camera_transform.translation.z * 0.5).abs();
-    let camera_half_width = MARGIN + (camera_half_height * camera_projection.aspect_ratio).abs();
-    camera_half_height += MARGIN;
-    // println!(
-    //     "Camera: w/2={} h/2={}",
-    //     camera_half_width, camera_half_height
-    // );
-
-    for (entity, mut transform, bullet) in query.iter_mut() {
-        if transform.translation.x.abs() > camera_half_width
-            || transform.translation.y.abs() > camera_half_height
-        {
-            commands.entity(entity).despawn();
+    let camera = if let Ok(camera) = q_camera.get_single() {
+        camera
+    } else {
+        return;
+    };
+    for (entity, transform, cull_margin, bounce) in query.iter() {
+        // Bouncing bullets come back on screen, see bounce_enemy_bullets()
+        if bounce.map_or(false, |bounce| bounce.0 > 0) {
+            continue;
+        }
+        let margin = cull_margin.map_or(DEFAULT_CULL_MARGIN, |cull_margin| cull_margin.0);
+        if camera.is_outside(transform.translation, margin) {
+            commands.entity(entity).insert(PendingDespawn);
         }
     }
 }
 
-#[derive(Default)]
-pub(crate) struct SfxAudio;
-
-#[derive(Default)]
-struct AudioRes {
-    sound_hit: Handle<KiraAudioSource>,
-    sound_fill_lifebars: Handle<KiraAudioSource>,
-}
-
 fn game_setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    audio: Res<KiraAudio>,
-    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
     windows: Res<Windows>,
-    mut audio_res: ResMut<AudioRes>,
+    common_assets: Res<CommonAssets>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut init_events: EventWriter<InitLifebarsEvent>,
     mut show_events: EventWriter<ShowLifebarsEvent>,
+    settings: Res<Settings>,
+    launch: Res<LaunchOptions>,
+    hangar: Res<Hangar>,
 ) {
-    println!("game_setup");
+    debug!(target: "setup", "game_setup");
 
-    let ship_mesh: Handle<Scene> = asset_server.load("ship1.glb#Scene0");
-
-    sfx_audio.set_volume(0.5);
-
-    audio_res.sound_hit = asset_server.load("sounds/hit.ogg");
-    audio_res.sound_fill_lifebars = asset_server.load("sounds/sweep_fill2.ogg");
+    commands.insert_resource(Lives::default());
 
     // Main camera
     let camera_depth = 5.0;
-    let mut camera_bundle = PerspectiveCameraBundle {
-        transform: Transform::from_xyz(0.0, 0.0, camera_depth).looking_at(Vec3::ZERO, Vec3::Y),
-        ..Default::default()
-    };
-    // FIXME - aspect ratio will be fixed-up later based on window size, but we need it now
-    let window = windows.get(WindowId::primary()).unwrap();
-    let aspect_ratio = window.width() / window.height();
-    camera_bundle.perspective_projection.aspect_ratio = aspect_ratio;
+    let camera_transform =
+        Transform::from_xyz(0.0, 0.0, camera_depth).looking_at(Vec3::ZERO, Vec3::Y);
+    // No window when running headless
+    let (window_width, window_height) = windows
+        .get(WindowId::primary())
+        .map_or((1280., 720.), |window| (window.width(), window.height()));
+    let aspect_ratio = window_width / window_height;
+    // Enlarge the view of windows taller than the playfield to show all of it
+    let fit = playfield_fit(aspect_ratio);
     let mut main_camera = MainCamera::default();
-    main_camera.update_screen_bounds(
-        &camera_bundle.perspective_projection,
-        &camera_bundle.transform,
-    );
+    let camera_entity = if settings.video.orthographic_camera {
+        // Flat look, with the same world-unit scaling as the perspective camera on the
+        // playfield plane, so the playfield keeps the same size
+        let mut camera_bundle = OrthographicCameraBundle::new_3d();
+        camera_bundle.transform = camera_transform;
+        camera_bundle.orthographic_projection.scale =
+            (PerspectiveProjection::default().fov * camera_depth * 0.5).abs() * fit;
+        // FIXME - projection will be fixed-up later based on window size, but we need it now
+        camera_bundle
+            .orthographic_projection
+            .update(window_width, window_height);
+        main_camera.update_screen_bounds(
+            None,
+            Some(&camera_bundle.orthographic_projection),
+            &camera_transform,
+        );
+        commands.spawn_bundle(camera_bundle).id()
+    } else {
+        let mut camera_bundle = PerspectiveCameraBundle {
+            transform: camera_transform,
+            ..Default::default()
+        };
+        // FIXME - aspect ratio will be fixed-up later based on window size, but we need it now
+        camera_bundle.perspective_projection.aspect_ratio = aspect_ratio;
+        camera_bundle.perspective_projection.fov *= fit;
+        main_camera.update_screen_bounds(
+            Some(&camera_bundle.perspective_projection),
+            None,
+            &camera_transform,
+        );
+        commands.spawn_bundle(camera_bundle).id()
+    };
     let screen_bounds = main_camera.screen_bounds;
-    println!("Initial screen bounds: {:?}", screen_bounds);
-    commands.spawn_bundle(camera_bundle).insert(main_camera);
-
-    // Debug camera for Heron/Rapier 2D collision shapes
-    // FIXME - doesn't work
-    // commands.spawn_bundle(OrthographicCameraBundle::new_2d());
-    // commands.spawn_bundle(SpriteBundle{
-    //     sprite: Sprite {
-    //         color: Color::RED,
-    //         custom_size: Some(Vec2::new(500., 10.)),
-    //         ..Default::default()
-    //     },
-    //     //texture: asset_server.load("textures/bullet_dev_32.png"),
-    //     transform: Transform::identity(),
-    //     ..Default::default()
-    // });
-
-    // light
+    debug!(target: "camera", "Initial screen bounds: {:?}", screen_bounds);
     commands
-        .spawn_bundle(DirectionalLightBundle {
-            directional_light: DirectionalLight {
-                color: Color::WHITE,
-                illuminance: 8000.0,
-                shadows_enabled: true,
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(Name::new("Sun"))
-        .insert(Sun);
-
-    //let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+        .entity(camera_entity)
+        .insert(main_camera)
+        .insert(CameraBaseTransform(camera_transform))
+        .insert(PlayfieldFit(fit))
+        .insert(InGameEntity);
 
-    // FIXME - Copied in enemy.rs :(
-    let hud_mat_black = materials.add(StandardMaterial {
-        base_color: Color::BLACK,
-        unlit: true,
-        alpha_mode: AlphaMode::Blend,
-        ..Default::default()
-    });
+    // Collision shapes are drawn in world space by the debug overlay of DebugPlugin
 
-    // let z_hud = 1.;
-    // let perspective_correction = camera_depth / (camera_depth - z_hud);
-    // let screen_to_world = (screen_bounds.bottom - screen_bounds.top).abs()
-    //     / window.physical_height().max(1) as f32
-    //     * perspective_correction;
-    // let lifebar_margin = 64. * screen_to_world;
-    // println!(
-    //     "w={} h={} screen_to_world={} lifebar_margin={}",
-    //     window.physical_width(),
-    //     window.physical_height(),
-    //     screen_to_world,
-    //     lifebar_margin
-    // );
-    let lifebar_margin_v = 0.4;
-    let lifebar_margin_h = lifebar_margin_v * aspect_ratio;
+    //let font = asset_server.load("fonts/FiraMono-Regular.ttf");
 
     // Player lifebars
     let mut player_lifebars = LifebarHud::default();
-    player_lifebars.orientation = LifebarOrientation::Vertical;
-    player_lifebars.visible_pos = Vec2::new(screen_bounds.left + lifebar_margin_h, 0.);
-    player_lifebars.hidden_pos = Vec2::new(screen_bounds.left - lifebar_margin_h, 0.);
+    player_lifebars.orientation = BarOrientation::Vertical;
+    player_lifebars.size = Vec2::new(0.9, 96.);
+    player_lifebars.bar_size = Vec2::new(80., 99.7);
     let player_lifebar_colors = (0..12)
         .map(|i| {
             if i < 6 {
@@ -818,17 +2856,18 @@ fn game_setup(
     let player_lifebars_entity = LifebarHud::spawn(
         player_lifebars,
         "PlayerLifebar",
-        Vec2::new(0.05, 3.01),
-        hud_mat_black.clone(),
-        Vec2::new(0.04, 3.),
+        LifebarAnchor::LeftCenter,
+        PLAYER_LIFEBAR_MARGIN,
         &mut commands,
-        &mut *meshes,
-        &mut *materials,
     );
 
     // Show player lifebars
     let player_lifebars_count = player_lifebar_colors.len();
     let player_life_per_lifebar = 100.;
+    let player_life = player_lifebars_count as f32 * player_life_per_lifebar;
+    commands
+        .entity(player_lifebars_entity)
+        .insert(PlayerLifebar { life: player_life });
     init_events.send(InitLifebarsEvent {
         entity: player_lifebars_entity,
         colors: player_lifebar_colors.into(),
@@ -839,21 +2878,59 @@ fn game_setup(
         play_audio: true,
     });
 
-    let bullet_texture = asset_server.load("textures/bullet1.png");
-    //let bullet_texture = asset_server.load("textures/dev_uv.png");
+    spawn_player(
+        &mut commands,
+        &asset_server,
+        &mut *meshes,
+        &mut *materials,
+        &common_assets,
+        hangar.ship(),
+        &settings,
+        &launch,
+        player_lifebars_entity,
+        player_life,
+        0.,
+    );
+
+    // // HudManager
+    // let mut hud = HudManager::default();
+    // commands.spawn().insert(Name::new("HudManager")).insert(hud);
+}
+
+/// Spawn the player ship, when the game starts and again when continuing after a game
+/// over. The lifebar HUD of the player is spawned only once, by [`game_setup()`].
+fn spawn_player(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    common_assets: &CommonAssets,
+    ship: &ShipDescriptor,
+    settings: &Settings,
+    launch: &LaunchOptions,
+    lifebar_entity: Entity,
+    life: f32,
+    invincible_time: f32,
+) -> Entity {
+    let ship_mesh: Handle<Scene> = asset_server.load(ship.model.as_str());
+    // Reported during boot already; keep the game playable with a placeholder
+    let ship_failed = asset_server.get_load_state(&ship_mesh) == LoadState::Failed;
+
     let mut player_controller = PlayerController::default();
-    player_controller.bullet_texture = bullet_texture.clone();
-    player_controller.bullet_mesh = meshes.add(Mesh::from(Quad { size: 0.1 }));
-    player_controller.bullet_material = materials.add(StandardMaterial {
-        base_color_texture: Some(bullet_texture),
-        //emissive: Color::RED,
-        unlit: true,
-        alpha_mode: AlphaMode::Blend,
-        ..Default::default()
-    });
-    player_controller.life = player_lifebars_count as f32 * player_life_per_lifebar;
-    player_controller.remain_life = player_controller.life;
-    player_controller.lifebar_entity = player_lifebars_entity;
+    player_controller.bullet_texture = common_assets.player_bullet_texture.clone();
+    player_controller.bullet_mesh = common_assets.player_bullet_mesh.clone();
+    player_controller.bullet_material = common_assets.player_bullet_material.clone();
+    player_controller.charge_shot_material = common_assets.charge_shot_material.clone();
+    player_controller.equip(ship);
+    let nose_offset = player_controller.primary_fire_offset;
+    let tail_offset = -player_controller.half_size.x;
+    player_controller.life = life;
+    player_controller.remain_life = life;
+    player_controller.lifebar_entity = lifebar_entity;
+    player_controller.invincible_time = invincible_time;
+    player_controller.fire_delay_factor = settings.gameplay.fire_delay_factor();
+    player_controller.auto_fire = settings.gameplay.auto_fire;
+    player_controller.invulnerable = launch.invulnerable_player();
 
     let mut input_map = InputMap::default();
     input_map.insert(PlayerAction::MoveUp, KeyCode::Up);
@@ -864,18 +2941,30 @@ fn game_setup(
     input_map.insert(PlayerAction::MoveDown, GamepadButtonType::DPadDown);
     input_map.insert(PlayerAction::MoveLeft, KeyCode::Left);
     input_map.insert(PlayerAction::MoveLeft, KeyCode::A);
-    input_map.insert(PlayerAction::MoveLeft, GamepadButtonType::DPadDown);
+    input_map.insert(PlayerAction::MoveLeft, GamepadButtonType::DPadLeft);
     input_map.insert(PlayerAction::MoveRight, KeyCode::Right);
     input_map.insert(PlayerAction::MoveRight, KeyCode::D);
-    input_map.insert(PlayerAction::MoveRight, GamepadButtonType::DPadDown);
+    input_map.insert(PlayerAction::MoveRight, GamepadButtonType::DPadRight);
     input_map.insert(PlayerAction::ShootPrimary, KeyCode::Space);
     input_map.insert(PlayerAction::ShootPrimary, KeyCode::LControl);
+    input_map.insert(PlayerAction::ShootPrimary, GamepadButtonType::South);
+    input_map.insert(PlayerAction::ShootSecondary, KeyCode::C);
+    input_map.insert(PlayerAction::ShootSecondary, KeyCode::LAlt);
+    input_map.insert(PlayerAction::ShootSecondary, GamepadButtonType::North);
+    input_map.insert(PlayerAction::Bomb, KeyCode::X);
+    input_map.insert(PlayerAction::Bomb, KeyCode::LShift);
+    input_map.insert(PlayerAction::Bomb, GamepadButtonType::West);
+    input_map.insert(PlayerAction::ToggleAutoFire, KeyCode::V);
+    input_map.insert(PlayerAction::ToggleAutoFire, GamepadButtonType::Select);
+    input_map.insert(PlayerAction::Overdrive, KeyCode::Z);
+    input_map.insert(PlayerAction::Overdrive, GamepadButtonType::East);
     #[cfg(not(debug_assertions))] // only in release, otherwise annoying with egui inspector
     input_map.insert(PlayerAction::ShootPrimary, MouseButton::Left);
     #[cfg(debug_assertions)] // debug feature
     input_map.insert(PlayerAction::DebugSpawnBoss, KeyCode::F1);
 
     // Player entity
+    let mut model_root = None;
     commands
         // .spawn_bundle(PbrBundle {
         //     mesh: meshes.add(Mesh::from(shape::Cube { size: 0.1 })),
@@ -884,76 +2973,355 @@ fn game_setup(
         //     ..Default::default()
         // })
         .spawn()
-        .insert(Transform::from_translation(Vec3::X * -1.5)) // start on left side
+        .insert(Transform::from_translation(PLAYER_SPAWN_POSITION)) // start on left side
         .insert(GlobalTransform::identity())
         .insert(Name::new("Player"))
         .insert(Player)
+        .insert(InGameEntity)
+        .insert(FixedStep::default())
         .insert(player_controller)
         .insert_bundle(InputManagerBundle::<PlayerAction> {
             action_state: ActionState::default(),
             input_map,
         })
-        // Physics
+        // Physics; the colliders are children, see PlayerHitboxes
         .insert(RigidBody::KinematicPositionBased)
-        .insert(CollisionShape::Sphere { radius: 0.1 })
-        .insert(
-            CollisionLayers::none()
-                .with_group(Layer::Player)
-                .with_masks(&[Layer::World, Layer::Enemy, Layer::EnemyBullet]),
-        )
+        .with_children(|parent| {
+            parent
+                .spawn_bundle((Transform::identity(), GlobalTransform::identity()))
+                .insert(Name::new("HurtBox"))
+                .insert(HurtBox)
+                .insert(CollisionShape::Sphere {
+                    radius: ship.hitbox_radius,
+                })
+                .insert(
+                    CollisionLayers::none()
+                        .with_group(Layer::Player)
+                        .with_masks(&[Layer::World, Layer::Enemy, Layer::EnemyBullet]),
+                );
+
+            parent
+                .spawn_bundle((Transform::identity(), GlobalTransform::identity()))
+                .insert(Name::new("CollectBox"))
+                .insert(CollectBox)
+                .insert(CollisionShape::Sphere {
+                    radius: DEFAULT_COLLECT_RADIUS,
+                })
+                .insert(
+                    CollisionLayers::none()
+                        .with_group(Layer::PlayerCollect)
+                        .with_masks(&[Layer::Pickup]),
+                );
+        })
         // Rendering
         .with_children(|parent| {
+            model_root = Some(
+                parent
+                    .spawn_bundle((
+                        Transform::from_scale(Vec3::splat(ship.scale)),
+                        GlobalTransform::identity(),
+                    ))
+                    .insert(ShipController {
+                        exhaust_length: 1.,
+                        ..Default::default()
+                    })
+                    .with_children(|parent| {
+                        if ship_failed {
+                            parent.spawn_bundle(PbrBundle {
+                                mesh: meshes.add(Mesh::from(shape::Box::new(0.8, 0.3, 0.5))),
+                                material: materials.add(Color::rgb(1., 0., 1.).into()),
+                                ..Default::default()
+                            });
+                        } else {
+                            parent.spawn_scene(ship_mesh);
+                        }
+                    })
+                    .id(),
+            );
+
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Icosphere {
+                        radius: 0.5,
+                        subdivisions: 2,
+                    })),
+                    material: materials.add(StandardMaterial {
+                        base_color: *CHARGE_COLOR.as_rgba().set_a(0.6),
+                        unlit: true,
+                        alpha_mode: AlphaMode::Blend,
+                        ..Default::default()
+                    }),
+                    transform: Transform::from_translation(nose_offset),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(Name::new("ChargeGlow"))
+                .insert(NotShadowCaster)
+                .insert(NotShadowReceiver)
+                .insert(ChargeGlow);
+
+            // Pivot at the back of the ship, so that the flame stretches backward
+            let exhaust_mesh = meshes.add(Mesh::from(shape::Quad {
+                size: EXHAUST_SIZE,
+                flip: false,
+            }));
             parent
                 .spawn_bundle((
-                    Transform::from_scale(Vec3::splat(SHIP1_SCALE)),
+                    Transform::from_xyz(tail_offset, 0., 0.),
                     GlobalTransform::identity(),
                 ))
-                .insert(ShipController::default())
+                .insert(Name::new("EngineExhaust"))
+                .insert(EngineExhaust)
                 .with_children(|parent| {
-                    parent.spawn_scene(ship_mesh);
+                    for (color, scale) in [(EXHAUST_COLOR, 1.), (EXHAUST_CORE_COLOR, 0.5)] {
+                        parent
+                            .spawn_bundle(PbrBundle {
+                                mesh: exhaust_mesh.clone(),
+                                material: materials.add(StandardMaterial {
+                                    base_color: color,
+                                    unlit: true,
+                                    alpha_mode: AlphaMode::Blend,
+                                    ..Default::default()
+                                }),
+                                transform: Transform::from_xyz(
+                                    -EXHAUST_SIZE.x * scale / 2.,
+                                    0.,
+                                    0.001 * scale,
+                                )
+                                .with_scale(Vec3::new(scale, scale, 1.)),
+                                ..Default::default()
+                            })
+                            .insert(NotShadowCaster)
+                            .insert(NotShadowReceiver);
+                    }
                 });
-        });
+        })
+        .insert(PlayerHitboxes {
+            hurt_offset: Vec3::ZERO,
+            hurt_radius: ship.hitbox_radius,
+            graze_radius: DEFAULT_GRAZE_RADIUS,
+            model_root,
+        })
+        .id()
+}
 
-    // // HudManager
-    // let mut hud = HudManager::default();
-    // commands.spawn().insert(Name::new("HudManager")).insert(hud);
+/// Spawn the player again when continuing after a game over, with all lives and the
+/// score reset, like arcade cabinets do. The screen is cleared of enemy bullets, and
+/// the enemy timeline resumes where it paused.
+fn continue_game(
+    mut commands: Commands,
+    mut continue_events: EventReader<ContinueEvent>,
+    mut game_over_events: EventReader<GameOverEvent>,
+    mut q_gameover: Query<&mut Visibility, With<GameOverText>>,
+    q_lifebar: Query<(Entity, &PlayerLifebar)>,
+    mut q_score: Query<&mut ScoreDisplay>,
+    q_bullets: Query<Entity, (With<EnemyBullet>, Without<PendingDespawn>)>,
+    mut lifebar_events: PlayerLifebarEvents,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    common_assets: Res<CommonAssets>,
+    hangar: Res<Hangar>,
+    settings: Res<Settings>,
+    launch: Res<LaunchOptions>,
+    mut log_events: EventWriter<LogEvent>,
+) {
+    if game_over_events.iter().count() > 0 {
+        for mut visibility in q_gameover.iter_mut() {
+            visibility.is_visible = true;
+        }
+    }
 
-    let clouds_texture = asset_server.load("textures/clouds2.png");
-    let mut rng = rand::thread_rng();
-    for _ in 0..10 {
-        let h = rng.gen::<f32>() * 3. - 1.5;
-        let delay = rng.gen::<f32>() * 2.457;
-        let duration = 0.7 + rng.gen::<f32>() * 1.3;
-        let x = 0.8 + rng.gen::<f32>() * 0.4;
-        let y = 0.8 + rng.gen::<f32>() * 0.4;
-        let s = 0.3 + rng.gen::<f32>() * 1.4;
-        let clouds_tween = Delay::new(Duration::from_secs_f32(delay)).then(Tween::new(
-            EaseMethod::Linear,
-            TweeningType::Loop,
-            Duration::from_secs_f32(duration),
-            TransformPositionLens {
-                end: Vec3::new(-5., h, 0.),
-                start: Vec3::new(5., h, 0.),
-            },
-        ));
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Quad {
-                    size: Vec2::new(2., 0.3),
-                    flip: false,
-                })),
-                material: materials.add(StandardMaterial {
-                    base_color_texture: Some(clouds_texture.clone()),
-                    unlit: true,
-                    alpha_mode: AlphaMode::Blend,
-                    ..Default::default()
-                }),
-                transform: Transform::from_translation(Vec3::X * 10.) // out of screen
-                    .with_scale(Vec3::new(x * s, y * s, 1.)),
-                ..Default::default()
-            })
-            .insert(Name::new("clouds"))
-            .insert(Animator::new(clouds_tween));
+    if continue_events.iter().count() == 0 {
+        return;
+    }
+    let (lifebar_entity, lifebar) = if let Ok(lifebar) = q_lifebar.get_single() {
+        lifebar
+    } else {
+        return;
+    };
+    log_events.send(LogEvent::Continue);
+
+    commands.insert_resource(Lives::default());
+    for mut score in q_score.iter_mut() {
+        score.reset();
+    }
+    for entity in q_bullets.iter() {
+        commands.entity(entity).insert(PendingDespawn);
+    }
+
+    spawn_player(
+        &mut commands,
+        &asset_server,
+        &mut *meshes,
+        &mut *materials,
+        &common_assets,
+        hangar.ship(),
+        &settings,
+        &launch,
+        lifebar_entity,
+        lifebar.life,
+        INVINCIBLE_DURATION,
+    );
+    lifebar_events.show.send(ShowLifebarsEvent {
+        entity: lifebar_entity,
+        play_audio: false,
+    });
+    lifebar_events.update.send(UpdateLifebarsEvent {
+        entity: lifebar_entity,
+        remain_life: lifebar.life,
+    });
+}
+
+/// Move the player bullets by their velocity. They're kinematic physics bodies, so
+/// that they're interpolated like the rest of the simulation instead of stepped by
+/// heron at the frame rate.
+fn move_player_bullets(mut query: Query<(&mut Transform, &Bullet), Without<EnemyBullet>>) {
+    for (mut transform, bullet) in query.iter_mut() {
+        transform.translation += bullet.0 * FIXED_DELTA_SECONDS;
+    }
+}
+
+/// Move the enemy bullets by their velocity, in the local space of their fixed
+/// rotation.
+pub fn move_enemy_bullets(mut query: Query<(&mut Transform, &Bullet), With<EnemyBullet>>) {
+    for (mut transform, bullet) in query.iter_mut() {
+        let velocity = transform.rotation.mul_vec3(bullet.0);
+        transform.translation += velocity * FIXED_DELTA_SECONDS;
+    }
+}
+
+/// Bounce the enemy bullets off the screen edges, while they have any [`Bounce`] left.
+pub fn bounce_enemy_bullets(
+    mut query: Query<(&mut Transform, &mut Bounce), With<EnemyBullet>>,
+    q_camera: Query<&MainCamera>,
+) {
+    let bounds = if let Ok(camera) = q_camera.get_single() {
+        camera.screen_bounds
+    } else {
+        return;
+    };
+    for (mut transform, mut bounce) in query.iter_mut() {
+        let pos = transform.translation;
+        let normal = if pos.x < bounds.left {
+            Vec2::X
+        } else if pos.x > bounds.right {
+            -Vec2::X
+        } else if pos.y < bounds.bottom {
+            Vec2::Y
+        } else if pos.y > bounds.top {
+            -Vec2::Y
+        } else {
+            continue;
+        };
+        if bounce.reflect(&mut transform, normal) {
+            transform.translation.x = pos.x.clamp(bounds.left, bounds.right);
+            transform.translation.y = pos.y.clamp(bounds.bottom, bounds.top);
+        }
+    }
+}
+
+/// Test the enemy bullets against the player hitbox, and damage the player on contact.
+///
+/// There's only one player, so a distance check per bullet is all the broad phase
+/// needed. Bullets go through the player while respawning or invincible, and are
+/// destroyed without damage on contact with the bubble of a [`Shield`] or the aura of
+/// an [`Overdrive`].
+fn detect_enemy_bullet_hits(
+    mut commands: Commands,
+    q_player: Query<(
+        Entity,
+        &Transform,
+        &PlayerHitboxes,
+        &PlayerController,
+        Option<&Shield>,
+        Option<&Overdrive>,
+    )>,
+    q_bullets: Query<
+        (Entity, &Transform, Option<&Damage>),
+        (With<EnemyBullet>, Without<PendingDespawn>),
+    >,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut spark_events: EventWriter<SparkEvent>,
+) {
+    let (player_entity, player_transform, hitboxes, controller, shield, overdrive) =
+        if let Ok(player) = q_player.get_single() {
+            player
+        } else {
+            return;
+        };
+    if controller.is_respawning() {
+        return;
+    }
+
+    // The overdrive aura is larger than the shield bubble
+    let barrier_radius = if overdrive.is_some() {
+        Some(OVERDRIVE_AURA_RADIUS)
+    } else {
+        shield.map(|_| SHIELD_RADIUS)
+    };
+    if let Some(barrier_radius) = barrier_radius {
+        let hit_radius = barrier_radius + ENEMY_BULLET_RADIUS;
+        let player_position = player_transform.translation;
+        for (entity, transform, _) in q_bullets.iter() {
+            if transform.translation.distance_squared(player_position) < hit_radius * hit_radius {
+                let position =
+                    player_position.lerp(transform.translation, barrier_radius / hit_radius);
+                spark_events.send(SparkEvent { position });
+                commands.entity(entity).insert(PendingDespawn);
+            }
+        }
+        return;
+    }
+
+    if !controller.is_vulnerable() {
+        return;
+    }
+
+    let player_radius = hitboxes.hurt_radius;
+    let hit_radius = player_radius + ENEMY_BULLET_RADIUS;
+    let player_position = hitboxes.hurt_position(player_transform.translation);
+    for (entity, transform, damage) in q_bullets.iter() {
+        if transform.translation.distance_squared(player_position) >= hit_radius * hit_radius {
+            continue;
+        }
+        // Same contact point as contact_point() for two spheres
+        let position = player_position.lerp(transform.translation, player_radius / hit_radius);
+        spark_events.send(SparkEvent { position });
+        damage_events.send(DamageEvent {
+            entity: player_entity,
+            damage: damage.map_or(ENEMY_BULLET_DAMAGE, |damage| damage.0),
+            source: DamageSource::EnemyBullet,
+            position,
+        });
+        commands.entity(entity).insert(PendingDespawn);
+    }
+}
+
+/// Approximate the contact point between two colliders.
+///
+/// Heron only reports the colliding entities, so interpolate between the collider
+/// centers proportionally to their radius, which for two overlapping spheres gives
+/// a point on the surface of the first one facing the second one.
+fn contact_point(
+    q_shapes: &Query<(&GlobalTransform, &CollisionShape)>,
+    entity1: Entity,
+    entity2: Entity,
+) -> Vec3 {
+    let radius = |shape: &CollisionShape| match shape {
+        CollisionShape::Sphere { radius } => *radius,
+        CollisionShape::Capsule { radius, .. } => *radius,
+        CollisionShape::Cuboid { half_extends, .. } => half_extends.min_element(),
+        _ => 0.,
+    };
+    match (q_shapes.get(entity1), q_shapes.get(entity2)) {
+        (Ok((transform1, shape1)), Ok((transform2, shape2))) => {
+            let (r1, r2) = (radius(shape1), radius(shape2));
+            let t = if r1 + r2 > 0. { r1 / (r1 + r2) } else { 0.5 };
+            transform1.translation.lerp(transform2.translation, t)
+        }
+        (Ok((transform, _)), _) | (_, Ok((transform, _))) => transform.translation,
+        _ => Vec3::ZERO,
     }
 }
 
@@ -961,10 +3329,13 @@ fn detect_collisions(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     mut damage_events: EventWriter<DamageEvent>,
-    query_player: Query<&mut PlayerController>,
-    audio: Res<KiraAudio>,
-    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
-    audio_res: Res<AudioRes>,
+    q_shapes: Query<(&GlobalTransform, &CollisionShape)>,
+    mut query_player: Query<&mut PlayerController>,
+    mut sfx_events: EventWriter<SfxEvent>,
+    mut spark_events: EventWriter<SparkEvent>,
+    mut q_damage_over_time: Query<&mut DamageOverTime>,
+    q_damage: Query<&Damage>,
+    q_piercing: Query<&Piercing>,
 ) {
     for event in collision_events.iter() {
         match event {
@@ -975,48 +3346,164 @@ fn detect_collisions(
                 //     data2.rigid_body_entity()
                 // );
 
-                // Damage player
-                if data1.collision_layers().contains_group(Layer::Player) {
-                    damage_events.send(DamageEvent {
-                        entity: data1.rigid_body_entity(),
-                        damage: 1.,
-                    });
-                }
-                if data2.collision_layers().contains_group(Layer::Player) {
-                    damage_events.send(DamageEvent {
-                        entity: data2.rigid_body_entity(),
-                        damage: 1.,
-                    });
+                // Pickups don't deal damage, they're collected in collect_pickups()
+                if data1.collision_layers().contains_group(Layer::Pickup)
+                    || data2.collision_layers().contains_group(Layer::Pickup)
+                {
+                    continue;
                 }
 
-                // Damage enemy
-                if data1.collision_layers().contains_group(Layer::Enemy) {
-                    damage_events.send(DamageEvent {
-                        entity: data1.rigid_body_entity(),
-                        damage: 1.,
-                    });
-                    sfx_audio.play(audio_res.sound_hit.clone());
+                // Damage over time is applied in apply_damage_over_time() while overlapping
+                let mut is_damage_over_time = false;
+                for (source, target) in [(data1, data2), (data2, data1)] {
+                    if let Ok(mut dot) = q_damage_over_time.get_mut(source.rigid_body_entity()) {
+                        dot.start_overlap(target.rigid_body_entity());
+                        is_damage_over_time = true;
+                    }
                 }
-                if data2.collision_layers().contains_group(Layer::Enemy) {
-                    damage_events.send(DamageEvent {
-                        entity: data2.rigid_body_entity(),
-                        damage: 1.,
-                    });
-                    sfx_audio.play(audio_res.sound_hit.clone());
+                if is_damage_over_time {
+                    continue;
+                }
+
+                // Obstacles only block, see the obstacle module; they stop player bullets
+                // but don't deal nor take damage
+                if data1.collision_layers().contains_group(Layer::World)
+                    || data2.collision_layers().contains_group(Layer::World)
+                {
+                    for data in [data1, data2] {
+                        if data.collision_layers().contains_group(Layer::PlayerBullet)
+                            && !q_piercing.contains(data.rigid_body_entity())
+                        {
+                            commands
+                                .entity(data.rigid_body_entity())
+                                .insert(PendingDespawn);
+                        }
+                    }
+                    continue;
                 }
 
-                // Despawn bullet
-                if data1.collision_layers().contains_group(Layer::PlayerBullet) {
-                    commands.entity(data1.rigid_body_entity()).despawn();
+                // Body contact between the player and an enemy
+                let player_enemy = if data1.collision_layers().contains_group(Layer::Player)
+                    && data2.collision_layers().contains_group(Layer::Enemy)
+                {
+                    Some((data1, data2))
+                } else if data2.collision_layers().contains_group(Layer::Player)
+                    && data1.collision_layers().contains_group(Layer::Enemy)
+                {
+                    Some((data2, data1))
+                } else {
+                    None
+                };
+                if let Some((player, enemy)) = player_enemy {
+                    let player_entity = player.rigid_body_entity();
+                    let enemy_entity = enemy.rigid_body_entity();
+                    let position = contact_point(
+                        &q_shapes,
+                        player.collision_shape_entity(),
+                        enemy.collision_shape_entity(),
+                    );
+                    let damage = q_damage
+                        .get(enemy_entity)
+                        .map_or(DEFAULT_DAMAGE, |damage| damage.0);
+                    let away = match (
+                        q_shapes.get(player.collision_shape_entity()),
+                        q_shapes.get(enemy.collision_shape_entity()),
+                    ) {
+                        (Ok((player_transform, _)), Ok((enemy_transform, _))) => {
+                            (player_transform.translation - enemy_transform.translation)
+                                .truncate()
+                                .normalize_or_zero()
+                        }
+                        _ => Vec2::ZERO,
+                    };
+                    if let Ok(mut controller) = query_player.get_mut(player_entity) {
+                        if controller.is_respawning() {
+                            continue;
+                        }
+                        // Always bump back, even while invincible or on cooldown
+                        controller.knockback = if away != Vec2::ZERO {
+                            away * KNOCKBACK_SPEED
+                        } else {
+                            Vec2::new(-KNOCKBACK_SPEED, 0.)
+                        };
+                        spark_events.send(SparkEvent { position });
+                        if controller.is_ramming() {
+                            damage_events.send(DamageEvent {
+                                entity: enemy_entity,
+                                damage: RAM_DAMAGE,
+                                source: DamageSource::Contact,
+                                position,
+                            });
+                            sfx_events.send(SfxEvent::from_entity(Sfx::Hit, enemy_entity));
+                        } else if controller.is_vulnerable() && controller.contact_cooldown <= 0. {
+                            controller.contact_cooldown = CONTACT_COOLDOWN;
+                            damage_events.send(DamageEvent {
+                                entity: player_entity,
+                                damage,
+                                source: DamageSource::Contact,
+                                position,
+                            });
+                        }
+                    }
+                    continue;
                 }
-                if data2.collision_layers().contains_group(Layer::PlayerBullet) {
-                    commands.entity(data2.rigid_body_entity()).despawn();
+
+                // Ignore all hits on the player while respawning or invincible
+                let player_invincible = [data1, data2].iter().any(|data| {
+                    data.collision_layers().contains_group(Layer::Player)
+                        && query_player
+                            .get(data.rigid_body_entity())
+                            .map_or(false, |controller| !controller.is_vulnerable())
+                });
+                if player_invincible {
+                    continue;
                 }
-                if data1.collision_layers().contains_group(Layer::EnemyBullet) {
-                    commands.entity(data1.rigid_body_entity()).despawn();
+
+                let position = contact_point(
+                    &q_shapes,
+                    data1.collision_shape_entity(),
+                    data2.collision_shape_entity(),
+                );
+                spark_events.send(SparkEvent { position });
+
+                // Each body takes the damage of the other one
+                let damage = |data: &CollisionData| {
+                    q_damage
+                        .get(data.rigid_body_entity())
+                        .map_or(DEFAULT_DAMAGE, |damage| damage.0)
+                };
+                for (target, other) in [(data1, data2), (data2, data1)] {
+                    let layers = target.collision_layers();
+                    if !layers.contains_group(Layer::Player) && !layers.contains_group(Layer::Enemy)
+                    {
+                        continue;
+                    }
+                    let source = if other.collision_layers().contains_group(Layer::PlayerBullet) {
+                        DamageSource::PlayerBullet
+                    } else {
+                        DamageSource::Contact
+                    };
+                    damage_events.send(DamageEvent {
+                        entity: target.rigid_body_entity(),
+                        damage: damage(other),
+                        source,
+                        position,
+                    });
+                    if layers.contains_group(Layer::Enemy) {
+                        sfx_events
+                            .send(SfxEvent::from_entity(Sfx::Hit, target.rigid_body_entity()));
+                    }
                 }
-                if data2.collision_layers().contains_group(Layer::EnemyBullet) {
-                    commands.entity(data2.rigid_body_entity()).despawn();
+
+                // Despawn bullet, unless piercing
+                for data in [data1, data2] {
+                    if data.collision_layers().contains_group(Layer::PlayerBullet)
+                        && !q_piercing.contains(data.rigid_body_entity())
+                    {
+                        commands
+                            .entity(data.rigid_body_entity())
+                            .insert(PendingDespawn);
+                    }
                 }
             }
             CollisionEvent::Stopped(data1, data2) => {
@@ -1025,48 +3512,88 @@ fn detect_collisions(
                 //     data1.rigid_body_entity(),
                 //     data2.rigid_body_entity()
                 // )
+                for (source, target) in [(data1, data2), (data2, data1)] {
+                    if let Ok(mut dot) = q_damage_over_time.get_mut(source.rigid_body_entity()) {
+                        dot.stop_overlap(target.rigid_body_entity());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Apply the damage ticks of all [`DamageOverTime`] entities to the entities they
+/// overlap. The player doesn't take damage while invincible.
+fn apply_damage_over_time(
+    mut query: Query<&mut DamageOverTime>,
+    q_targets: Query<(&GlobalTransform, Option<&PlayerController>)>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut spark_events: EventWriter<SparkEvent>,
+    game_time: Res<GameTime>,
+) {
+    let dt = game_time.delta_seconds();
+    for mut dot in query.iter_mut() {
+        let damage = dot.damage_per_second * DAMAGE_TICK_INTERVAL;
+        dot.overlaps
+            .retain(|(entity, _)| q_targets.get(*entity).is_ok());
+        for (entity, next_tick) in dot.overlaps.iter_mut() {
+            *next_tick -= dt;
+            if *next_tick > 0. {
+                continue;
+            }
+            let (transform, controller) = q_targets.get(*entity).unwrap();
+            if controller.map_or(true, |controller| controller.is_vulnerable()) {
+                let position = transform.translation;
+                damage_events.send(DamageEvent {
+                    entity: *entity,
+                    damage,
+                    source: DamageSource::Laser,
+                    position,
+                });
+                spark_events.send(SparkEvent { position });
+                *next_tick = DAMAGE_TICK_INTERVAL;
+            } else {
+                *next_tick = 0.;
             }
         }
     }
 }
 
 fn update_hud(
-    mut hud_query: Query<
-        (
-            Entity,
-            &mut LifebarHud,
-            &mut Transform,
-            &mut Animator<Transform>,
-        ),
-        Without<LifebarOver>,
-    >,
+    mut commands: Commands,
+    mut hud_query: Query<(
+        Entity,
+        &mut LifebarHud,
+        &mut HudSlide,
+        &Style,
+        &mut Animator<Style>,
+    )>,
     mut over_query: Query<
-        (&mut LifebarOver, &mut Transform, &mut Animator<Transform>),
-        Without<LifebarHud>,
+        (&mut Transform, &mut Animator<Transform>, &mut UiColor),
+        With<LifebarOver>,
     >,
-    mut text_query: Query<(&mut Text, &mut LifebarCounter), Without<ScoreCounter>>,
-    mut q_score: Query<(&mut Text, &mut ScoreCounter), Without<LifebarCounter>>,
+    mut under_query: Query<&mut UiColor, (With<LifebarUnder>, Without<LifebarOver>)>,
+    mut text_query: Query<(&mut Text, &mut LifebarCounter)>,
+    q_parents: Query<&Parent>,
     player_controller: Query<&PlayerController>, // FIXME - bad design
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut init_events: EventReader<InitLifebarsEvent>,
     mut show_events: EventReader<ShowLifebarsEvent>,
+    mut hide_events: EventReader<HideLifebarsEvent>,
     mut update_events: EventReader<UpdateLifebarsEvent>,
-    mut score_events: EventReader<ScoreEvent>,
-    audio: Res<KiraAudio>,
-    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
-    audio_res: Res<AudioRes>,
-    //
-    //asset_server: Res<AssetServer>,
-    //audio: Res<KiraAudio>,
+    audio_facade: AudioFacade,
+    common_assets: Res<CommonAssets>,
+    palette: Res<BulletPalette>,
+    mut next_layer: Local<u32>,
 ) {
     // #4083 - EventReader::iter() is draining, cannot loop
     let update_events = update_events.iter().collect::<Vec<_>>();
 
     // Initialize any lifebar HUD if needed
     for ev in init_events.iter() {
-        if let Ok((_, mut hud, _, _)) = hud_query.get_mut(ev.entity) {
+        if let Ok((_, mut hud, _, _, _)) = hud_query.get_mut(ev.entity) {
             let mut colors = ev.colors.clone();
-            println!(
+            debug!(
+                target: "lifebar",
                 "INIT LIFEBAR: entity={:?} life_per_bar={} colors_count={}",
                 ev.entity,
                 ev.life_per_bar,
@@ -1076,168 +3603,158 @@ fn update_hud(
         }
     }
 
+    // Hide any lifebar HUD if needed
+    for ev in hide_events.iter() {
+        if let Ok((_, mut hud, mut slide, style, mut animator)) = hud_query.get_mut(ev.entity) {
+            debug!(
+                target: "lifebar",
+                "HIDE LIFEBAR: entity={:?} prev_state={:?}",
+                ev.entity, hud.fill_seq
+            );
+            if hud.is_shown() {
+                if let Ok((_, mut over_animator, _)) = over_query.get_mut(hud.overbar_entity) {
+                    over_animator.stop();
+                }
+                slide.slide_out(style, &mut animator, 1.5);
+                hud.fill_seq = LifebarFillSeqPhase::SlideOut;
+            }
+        }
+    }
+
+    // Outer extent of the lifebars on screen, to stack the ones shown next past them
+    let mut stacks = hud_query
+        .iter()
+        .filter(|(_, hud, ..)| hud.is_shown())
+        .map(|(entity, hud, ..)| (entity, hud.anchor, hud.visible_inset + hud.thickness()))
+        .collect::<Vec<_>>();
+
     // Show any lifebar HUD if needed
     for ev in show_events.iter() {
-        if let Ok((_, mut hud, mut transform, mut animator)) = hud_query.get_mut(ev.entity) {
-            println!(
+        if let Ok((_, mut hud, mut slide, style, mut animator)) = hud_query.get_mut(ev.entity) {
+            debug!(
+                target: "lifebar",
                 "SHOW LIFEBAR: entity={:?} prev_state={:?}",
                 ev.entity, hud.fill_seq
             );
-            if hud.fill_seq == LifebarFillSeqPhase::Idle {
-                animator.set_tweenable(Tween::new(
-                    EaseMethod::Linear,
-                    TweeningType::Once,
-                    Duration::from_secs_f32(2.5),
-                    TransformPositionLens {
-                        start: Vec3::new(
-                            hud.hidden_pos.x,
-                            hud.hidden_pos.y,
-                            transform.translation.z,
-                        ),
-                        end: Vec3::new(
-                            hud.visible_pos.x,
-                            hud.visible_pos.y,
-                            transform.translation.z,
-                        ),
-                    },
-                ));
-                animator.rewind();
-                animator.state = AnimatorState::Playing;
+            // A lifebar still sliding out, like the player one when respawning right
+            // after dying, slides back in
+            if !hud.is_shown() {
+                let inset = stacks
+                    .iter()
+                    .filter(|(entity, anchor, _)| *entity != ev.entity && *anchor == hud.anchor)
+                    .map(|(_, _, extent)| extent + LIFEBAR_STACK_SPACING)
+                    .fold(hud.margin, f32::max);
+                hud.visible_inset = inset;
+                stacks.push((ev.entity, hud.anchor, inset + hud.thickness()));
+                hud.layer = *next_layer;
+                *next_layer += 1;
+                slide.set_visible_inset(inset);
+                slide.slide_in(style, &mut animator, 2.5);
                 hud.fill_seq = LifebarFillSeqPhase::SlideIn(ev.play_audio);
                 hud.index = 0; // start from bottom-most bar
+                hud.remain_life = hud.life;
+                hud.pending_life = None;
             }
         }
     }
 
     // Update all HUDs
-    for (hud_entity, mut hud, mut transform, mut animator) in hud_query.iter_mut() {
-        let mut need_color_update = hud.force_update;
+    for (hud_entity, mut hud, slide, _, _) in hud_query.iter_mut() {
+        let mut need_color_update = hud.force_update || palette.is_changed();
         hud.force_update = false;
 
-        if let Ok((mut overbar, mut over_transform, mut over_animator)) =
+        if let Ok((mut over_transform, mut over_animator, _)) =
             over_query.get_mut(hud.overbar_entity)
         {
             // Transition fill sequence if needed
-            if animator.progress() >= 1. || over_animator.progress() >= 1. {
-                // TODO - auto-stop on completed
-                animator.stop();
-                over_animator.stop();
-
-                match hud.fill_seq {
-                    LifebarFillSeqPhase::SlideIn(play_audio) => {
-                        hud.fill_seq = LifebarFillSeqPhase::FillUp(0);
-                        if play_audio {
-                            sfx_audio.play(audio_res.sound_fill_lifebars.clone());
-                        }
-                        need_color_update = true;
-                        let start = match hud.orientation {
-                            LifebarOrientation::Horizontal => Vec3::new(0., 1., 1.),
-                            LifebarOrientation::Vertical => Vec3::new(1., 0., 1.),
-                        };
-                        over_animator.set_tweenable(Tween::new(
-                            EaseMethod::Linear,
-                            TweeningType::Once,
-                            Duration::from_secs_f32(1.1917), // 14.3s audio sweep <-> 12 bars
-                            TransformScaleLens {
-                                start,
-                                end: Vec3::ONE,
-                            },
-                        ));
+            match hud.fill_seq {
+                LifebarFillSeqPhase::SlideIn(play_audio)
+                    if slide.state() == SlideState::Visible =>
+                {
+                    hud.fill_seq = LifebarFillSeqPhase::FillUp(0);
+                    if play_audio {
+                        audio_facade.play_sfx(common_assets.sound_fill_lifebars.clone());
+                    }
+                    need_color_update = true;
+                    // 14.3s audio sweep <-> 12 bars
+                    over_animator.set_tweenable(hud.orientation.fill_tween(1.1917));
+                    over_animator.state = AnimatorState::Playing;
+                }
+                LifebarFillSeqPhase::FillUp(mut bar_index) if over_animator.progress() >= 1. => {
+                    // TODO - auto-stop on completed
+                    over_animator.stop();
+                    bar_index += 1;
+                    if bar_index < hud.lifebars.len() {
+                        hud.index = bar_index;
+                        hud.fill_seq = LifebarFillSeqPhase::FillUp(bar_index);
                         over_animator.state = AnimatorState::Playing;
+                        need_color_update = true;
+                    } else {
+                        hud.fill_seq = LifebarFillSeqPhase::Ready;
                     }
-                    LifebarFillSeqPhase::FillUp(mut bar_index) => {
-                        bar_index += 1;
-                        if bar_index < hud.lifebars.len() {
-                            hud.index = bar_index;
-                            hud.fill_seq = LifebarFillSeqPhase::FillUp(bar_index);
-                            over_animator.state = AnimatorState::Playing;
-                            need_color_update = true;
-                        } else {
-                            hud.fill_seq = LifebarFillSeqPhase::Ready;
+                }
+                LifebarFillSeqPhase::SlideOut if slide.state() == SlideState::Hidden => {
+                    hud.fill_seq = LifebarFillSeqPhase::Idle;
+                    // The widget is below a playfield-sized root, see spawn_hud_widget()
+                    if hud.despawn_when_hidden {
+                        if let Ok(parent) = q_parents.get(hud_entity) {
+                            commands.entity(parent.0).despawn_recursive();
                         }
                     }
-                    LifebarFillSeqPhase::SlideOut => {
-                        hud.fill_seq = LifebarFillSeqPhase::Idle;
-                    }
-                    _ => (),
                 }
+                _ => (),
             }
 
-            // Update lifetime bars from damage events
+            // Update lifetime bars from damage events, once ready
+            if let Some(ev) = update_events
+                .iter()
+                .filter(|ev| ev.entity == hud_entity)
+                .last()
+            {
+                hud.pending_life = Some(ev.remain_life);
+            }
             if hud.fill_seq == LifebarFillSeqPhase::Ready {
-                if let Some(ev) = update_events
-                    .iter()
-                    .filter(|ev| ev.entity == hud_entity)
-                    .last()
-                {
+                if let Some(remain_life) = hud.pending_life.take() {
                     //println!("update_events: ")
                     let total_life = (hud.life * hud.lifebars.len() as f32).max(1.);
-                    let new_index = ev.remain_life / hud.life;
-                    let over_progress = new_index.fract();
-                    let new_index = new_index.floor() as usize;
+                    let new_index = remain_life / hud.life;
+                    let (new_index, over_progress) = if new_index >= hud.lifebars.len() as f32 {
+                        // Full life, e.g. after a respawn
+                        (hud.lifebars.len() - 1, 1.)
+                    } else {
+                        (new_index.floor() as usize, new_index.fract())
+                    };
                     hud.remain_life = over_progress * hud.life;
                     // println!(
                     //     "hud: life_per_bar={} lifebar_count={} total_life={} remain_life={} bar_index={} bar_remain_life={}",
-                    //     hud.life, hud.lifebars.len(), total_life, ev.remain_life, new_index, hud.remain_life
+                    //     hud.life, hud.lifebars.len(), total_life, remain_life, new_index, hud.remain_life
                     // );
                     if hud.index != new_index {
-                        // Change bars
+                        // Change bars. Deaths slide the lifebar out through a
+                        // HideLifebarsEvent, see kill_player() and kill_enemies().
                         hud.index = new_index;
                         need_color_update = true;
-                        // if hud.index == 0 && hud.remain_life <= 0. {
-                        //     // killed
-                        //     println!("ENTITY KILLED");
-                        //     // {
-                        //     //     let sound_channel_sfx = KiraAudioChannel::new("sfx".to_string());
-                        //     //     audio.set_volume_in_channel(0.7, &sound_channel_sfx);
-                        //     //     let sound_click = asset_server.load("sounds/explosion.ogg");
-                        //     //     audio.play_in_channel(sound_click.clone(), &sound_channel_sfx);
-                        //     // }
-                        //     hud.fill_seq = LifebarFillSeqPhase::SlideOut;
-                        //     animator.set_tweenable(Tween::new(
-                        //         EaseMethod::Linear,
-                        //         TweeningType::Once,
-                        //         Duration::from_secs_f32(2.5),
-                        //         TransformPositionLens {
-                        //             start: transform.translation,
-                        //             end: Vec3::new(
-                        //                 hud.hidden_pos.x,
-                        //                 hud.hidden_pos.y,
-                        //                 transform.translation.z,
-                        //             ),
-                        //         },
-                        //     ));
-                        //     animator.rewind();
-                        //     animator.state = AnimatorState::Playing;
-                        // }
                     }
 
                     // Scale overbar by progress
-                    match hud.orientation {
-                        LifebarOrientation::Horizontal => {
-                            over_transform.scale = Vec3::new(over_progress, 1., 1.)
-                        }
-                        LifebarOrientation::Vertical => {
-                            over_transform.scale = Vec3::new(1., over_progress, 1.)
-                        }
-                    }
+                    over_transform.scale = hud.orientation.fill_scale(over_progress);
                 }
             }
         }
 
         // Update bars color
         if need_color_update {
-            let over_color = hud.lifebars[hud.index].color;
+            let over_color = palette.lifebar_color(hud.lifebars[hud.index].color);
             let under_color = if hud.index > 0 {
-                hud.lifebars[hud.index - 1].color
+                palette.lifebar_color(hud.lifebars[hud.index - 1].color)
             } else {
                 Color::NONE
             };
-            if let Some(under_mat) = materials.get_mut(hud.under_mat.clone()) {
-                under_mat.base_color = under_color;
+            if let Ok(mut color) = under_query.get_mut(hud.underbar_entity) {
+                color.0 = under_color;
             }
-            if let Some(over_mat) = materials.get_mut(hud.over_mat.clone()) {
-                over_mat.base_color = over_color;
+            if let Ok((_, _, mut color)) = over_query.get_mut(hud.overbar_entity) {
+                color.0 = over_color;
             }
         }
 
@@ -1252,14 +3769,51 @@ fn update_hud(
         //         }
         //     }
         // }
+    }
+}
 
-        // Update the score text
-        if !q_score.is_empty() {
-            let (mut text, mut counter) = q_score.single_mut();
-            for ev in score_events.iter() {
-                counter.0 += ev.0;
+/// Draw the lifebars most recently shown on top of the other ones.
+///
+/// The UI assigns a depth to each node in hierarchy order, so lifebars sliding over one
+/// another would draw in spawn order. Instead their depths are redistributed in the
+/// order they were shown once propagated, keeping the same set of depths so the other
+/// nodes still draw in the same order relative to the lifebars.
+fn order_lifebars(
+    hud_query: Query<(Entity, &LifebarHud, &Parent)>,
+    mut node_query: Query<(&Transform, &mut GlobalTransform), With<Node>>,
+) {
+    // Background, underbar and overbar nodes of each lifebar, with the local depth of
+    // the last two relative to their parent
+    let mut lifebars = vec![];
+    for (entity, hud, parent) in hud_query.iter() {
+        let depth = |entity: Entity| {
+            node_query
+                .get(entity)
+                .map(|(transform, _)| transform.translation.z)
+                .unwrap_or(0.)
+        };
+        let root_depth = if let Ok((_, global_transform)) = node_query.get(parent.0) {
+            global_transform.translation.z
+        } else {
+            continue;
+        };
+        let base = root_depth + depth(entity);
+        let under_depth = depth(hud.underbar_entity);
+        let over_depth = under_depth + depth(hud.overbar_entity);
+        lifebars.push((hud.layer, base, entity, hud, under_depth, over_depth));
+    }
+    let mut bases = lifebars.iter().map(|lifebar| lifebar.1).collect::<Vec<_>>();
+    bases.sort_by(|a, b| a.total_cmp(b));
+    lifebars.sort_by_key(|lifebar| lifebar.0);
+    for ((_, _, entity, hud, under_depth, over_depth), base) in lifebars.into_iter().zip(bases) {
+        for (entity, depth) in [
+            (entity, base),
+            (hud.underbar_entity, base + under_depth),
+            (hud.overbar_entity, base + over_depth),
+        ] {
+            if let Ok((_, mut global_transform)) = node_query.get_mut(entity) {
+                global_transform.translation.z = depth;
             }
-            text.sections[0].value = format!("{}", counter.0).into();
         }
     }
 }
@@ -1277,15 +3831,28 @@ impl Default for Quad {
     }
 }
 
-impl From<Quad> for Mesh {
-    fn from(quad: Quad) -> Self {
-        let extent = quad.size / 2.0;
+impl Quad {
+    /// Build a mesh whose UVs map to the given sub-rectangle of a texture.
+    pub fn to_mesh_with_uvs(self, uv_min: Vec2, uv_max: Vec2) -> Mesh {
+        let extent = self.size / 2.0;
 
         let vertices = [
-            ([-extent, -extent, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0]),
-            ([-extent, extent, 0.0], [0.0, 0.0, 1.0], [0.0, 0.0]),
-            ([extent, -extent, 0.0], [0.0, 0.0, 1.0], [1.0, 1.0]),
-            ([extent, extent, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0]),
+            (
+                [-extent, -extent, 0.0],
+                [0.0, 0.0, 1.0],
+                [uv_min.x, uv_max.y],
+            ),
+            (
+                [-extent, extent, 0.0],
+                [0.0, 0.0, 1.0],
+                [uv_min.x, uv_min.y],
+            ),
+            (
+                [extent, -extent, 0.0],
+                [0.0, 0.0, 1.0],
+                [uv_max.x, uv_max.y],
+            ),
+            ([extent, extent, 0.0], [0.0, 0.0, 1.0], [uv_max.x, uv_min.y]),
         ];
 
         let indices = bevy::render::mesh::Indices::U16(vec![0, 2, 1, 1, 2, 3]);
@@ -1307,3 +3874,9 @@ impl From<Quad> for Mesh {
         mesh
     }
 }
+
+impl From<Quad> for Mesh {
+    fn from(quad: Quad) -> Self {
+        quad.to_mesh_with_uvs(Vec2::ZERO, Vec2::ONE)
+    }
+}
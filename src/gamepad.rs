@@ -0,0 +1,220 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    game::{GameTime, PlayerAction, PlayerController},
+    AppState,
+};
+
+/// Gamepad hot-plug: track the gamepad driving the player, show a toast when a
+/// gamepad is connected or disconnected, and pause the game when the active one is
+/// disconnected.
+pub struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ActiveGamepad>()
+            .add_startup_system(toast_setup)
+            .add_system(track_gamepads)
+            .add_system(update_toast.after(track_gamepads))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(assign_player_gamepad.after(track_gamepads))
+                    .with_system(resume_after_disconnect.after(track_gamepads)),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(clear_pause));
+    }
+}
+
+/// Time a toast stays fully visible, in seconds.
+const TOAST_DURATION: f32 = 2.5;
+
+/// Duration of the fade out of a toast, in seconds.
+const TOAST_FADE_DURATION: f32 = 0.5;
+
+const TOAST_TEXT_COLOR: Color = Color::rgb(0.95, 0.95, 0.95);
+
+/// Minimum value of a gamepad button for a press to make its gamepad the active one.
+const BUTTON_PRESS_THRESHOLD: f32 = 0.5;
+
+/// Gamepad driving the player, if any: the first one connected, or the last one a
+/// button was pressed on.
+#[derive(Debug, Default)]
+pub struct ActiveGamepad(pub Option<Gamepad>);
+
+/// Short message at the bottom of the screen, fading out after a while.
+#[derive(Component, Default)]
+struct Toast {
+    /// Remaining time before the toast is hidden, in seconds. A pinned toast stays
+    /// visible until replaced.
+    remaining: f32,
+    /// Keep the toast visible until replaced, instead of fading it out.
+    pinned: bool,
+}
+
+impl Toast {
+    fn show(&mut self, text: &mut Text, message: &str, pinned: bool) {
+        text.sections[0].value = message.to_string();
+        self.remaining = TOAST_DURATION + TOAST_FADE_DURATION;
+        self.pinned = pinned;
+    }
+}
+
+fn toast_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    // Not an in-game entity, since gamepads can come and go on any screen
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                align_self: AlignSelf::Center,
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(24.),
+                    ..Default::default()
+                },
+                margin: Rect {
+                    left: Val::Auto,
+                    right: Val::Auto,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                "",
+                TextStyle {
+                    font,
+                    font_size: 28.0,
+                    color: TOAST_TEXT_COLOR,
+                },
+                TextAlignment {
+                    horizontal: HorizontalAlign::Center,
+                    ..Default::default()
+                },
+            ),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Name::new("Toast"))
+        .insert(Toast::default());
+}
+
+/// Follow the gamepad connections and button presses to select the active gamepad,
+/// and pause the game when the active gamepad is disconnected mid-game.
+fn track_gamepads(
+    mut active: ResMut<ActiveGamepad>,
+    gamepads: Res<Gamepads>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+    mut q_toast: Query<(&mut Toast, &mut Text)>,
+    mut game_time: ResMut<GameTime>,
+    app_state: Res<State<AppState>>,
+) {
+    let (mut toast, mut text) = q_toast.single_mut();
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                debug!(target: "gamepad", "GAMEPAD: connected {:?}", gamepad);
+                if active.0.is_none() {
+                    active.0 = Some(*gamepad);
+                }
+                toast.show(&mut *text, "Controller connected", false);
+            }
+            GamepadEventType::Disconnected => {
+                debug!(target: "gamepad", "GAMEPAD: disconnected {:?}", gamepad);
+                if active.0 != Some(*gamepad) {
+                    toast.show(&mut *text, "Controller disconnected", false);
+                    continue;
+                }
+                // Fall back to any other connected gamepad
+                active.0 = gamepads.iter().find(|other| *other != gamepad).copied();
+                if active.0.is_none() && *app_state.current() == AppState::InGame {
+                    game_time.set_paused(true);
+                    toast.show(
+                        &mut *text,
+                        "Controller disconnected - press any key or button to resume",
+                        true,
+                    );
+                } else {
+                    toast.show(&mut *text, "Controller disconnected", false);
+                }
+            }
+            GamepadEventType::ButtonChanged(_, value) => {
+                if *value >= BUTTON_PRESS_THRESHOLD && active.0 != Some(*gamepad) {
+                    debug!(target: "gamepad", "GAMEPAD: switched to {:?}", gamepad);
+                    active.0 = Some(*gamepad);
+                }
+            }
+            GamepadEventType::AxisChanged(..) => {}
+        }
+    }
+}
+
+/// Bind the active gamepad to the input map of the player.
+fn assign_player_gamepad(
+    active: Res<ActiveGamepad>,
+    mut query: Query<&mut InputMap<PlayerAction>, With<PlayerController>>,
+) {
+    for mut input_map in query.iter_mut() {
+        if input_map.gamepad() == active.0 {
+            continue;
+        }
+        if let Some(gamepad) = active.0 {
+            input_map.set_gamepad(gamepad);
+        } else {
+            input_map.clear_gamepad();
+        }
+    }
+}
+
+/// Resume the game paused by a gamepad disconnection on the next key or button press.
+fn resume_after_disconnect(
+    mut game_time: ResMut<GameTime>,
+    keyboard: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut q_toast: Query<&mut Toast>,
+) {
+    if !game_time.is_paused() {
+        return;
+    }
+    if keyboard.get_just_pressed().next().is_some()
+        || gamepad_buttons.get_just_pressed().next().is_some()
+    {
+        game_time.set_paused(false);
+        if let Ok(mut toast) = q_toast.get_single_mut() {
+            if toast.pinned {
+                toast.pinned = false;
+                toast.remaining = 0.;
+            }
+        }
+    }
+}
+
+/// Don't carry a pause over to the next game.
+fn clear_pause(mut game_time: ResMut<GameTime>, mut q_toast: Query<&mut Toast>) {
+    game_time.set_paused(false);
+    if let Ok(mut toast) = q_toast.get_single_mut() {
+        toast.pinned = false;
+        toast.remaining = 0.;
+    }
+}
+
+/// Fade out and hide the toast once its time is up.
+fn update_toast(mut query: Query<(&mut Toast, &mut Text, &mut Visibility)>, time: Res<Time>) {
+    let (mut toast, mut text, mut visibility) = query.single_mut();
+    if !toast.pinned {
+        toast.remaining = (toast.remaining - time.delta_seconds()).max(0.);
+    }
+    let is_visible = toast.pinned || toast.remaining > 0.;
+    if visibility.is_visible != is_visible {
+        visibility.is_visible = is_visible;
+    }
+    let alpha = if toast.pinned {
+        1.
+    } else {
+        (toast.remaining / TOAST_FADE_DURATION).min(1.)
+    };
+    let mut color = TOAST_TEXT_COLOR;
+    color.set_a(alpha);
+    if text.sections[0].style.color != color {
+        text.sections[0].style.color = color;
+    }
+}
@@ -0,0 +1,545 @@
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+use bevy_tweening::{lens::*, *};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::{
+    flow::RequestStateChange,
+    music::AudioFacade,
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    replay::{format_date, ReplayRecorder},
+    save::{self, SaveFormat},
+    AppState,
+};
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load())
+            .add_system_set(
+                SystemSet::on_enter(AppState::HighScores).with_system(high_scores_setup),
+            )
+            .add_system_set(SystemSet::on_update(AppState::HighScores).with_system(high_scores_run))
+            .add_system_set(
+                SystemSet::on_exit(AppState::HighScores).with_system(high_scores_cleanup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_name_entry),
+            );
+    }
+}
+
+/// Path of the high score file, relative to the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const HIGH_SCORES_PATH: &str = "highscores.json";
+
+/// Key of the high scores in the browser local storage.
+#[cfg(target_arch = "wasm32")]
+const HIGH_SCORES_STORAGE_KEY: &str = "super-kaizen-overloaded/highscores";
+
+/// Format of the high score file.
+const HIGH_SCORES_FORMAT: SaveFormat = SaveFormat {
+    version: 1,
+    migrate: migrate_high_scores,
+};
+
+fn migrate_high_scores(version: u32, _json: &mut serde_json::Value) -> Result<(), String> {
+    Err(format!("no migration from version {}", version))
+}
+
+/// Number of entries of the high score table.
+const MAX_HIGH_SCORES: usize = 10;
+
+/// Number of characters of a player name.
+const NAME_LENGTH: usize = 3;
+
+/// Characters a player name is made of, in the order they are cycled through.
+const NAME_CHARSET: &[char] = &[
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
+    'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', '.', '-',
+    ' ',
+];
+
+/// Delay before the name entry accepts any input, in seconds, so that the player still
+/// mashing the fire button doesn't validate it by accident.
+const NAME_INPUT_DELAY: f32 = 0.6;
+
+/// Distance the entries of the high score page slide in from, in pixels.
+const ENTRY_SLIDE_DISTANCE: f32 = 1200.;
+
+/// Duration of the slide animation of each entry of the high score page, in seconds.
+const ENTRY_SLIDE_DURATION: f32 = 0.5;
+
+/// Delay between the slide animations of two consecutive entries, in seconds.
+const ENTRY_SLIDE_DELAY: f32 = 0.08;
+
+/// Entry of the high score table.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScoreEntry {
+    pub name: String,
+    pub score: u32,
+    /// Last stage reached, starting from 1.
+    pub stage: u32,
+    /// End date of the run, in seconds since the Unix epoch, or zero if unknown.
+    pub date: u64,
+}
+
+/// Best scores of all runs, persisted to disk, or to the local storage on the web.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HighScores {
+    /// Entries sorted by decreasing score, at most [`MAX_HIGH_SCORES`].
+    pub entries: Vec<HighScoreEntry>,
+    /// Name last entered, to prefill the next name entry.
+    pub last_name: String,
+}
+
+impl HighScores {
+    /// Load the high scores, or return an empty table if there's none or they cannot
+    /// be parsed.
+    pub fn load() -> HighScores {
+        #[cfg(not(target_arch = "wasm32"))]
+        let loaded = save::load(HIGH_SCORES_PATH, &HIGH_SCORES_FORMAT);
+        #[cfg(target_arch = "wasm32")]
+        let loaded = match local_storage()
+            .and_then(|storage| storage.get_item(HIGH_SCORES_STORAGE_KEY).ok().flatten())
+        {
+            Some(json) => save::from_json(&json, &HIGH_SCORES_FORMAT),
+            None => return HighScores::default(),
+        };
+
+        match loaded {
+            Ok(loaded) => {
+                if let Some(version) = loaded.migrated_from {
+                    info!(
+                        target: "highscore",
+                        "Migrated high scores from version {} (backup: {:?})",
+                        version,
+                        loaded.backup
+                    );
+                }
+                loaded.value
+            }
+            Err(save::LoadError::Read(err)) => {
+                info!(target: "highscore", "No high scores loaded: {}", err);
+                HighScores::default()
+            }
+            Err(err) => {
+                warn!(target: "highscore", "Failed to load high scores: {}", err);
+                HighScores::default()
+            }
+        }
+    }
+
+    /// Save the high scores.
+    pub fn save(&self) {
+        let result = save::to_json(self, &HIGH_SCORES_FORMAT, true).and_then(write_high_scores);
+        if let Err(err) = result {
+            error!(target: "highscore", "Failed to save high scores: {}", err);
+        }
+    }
+
+    /// Does the given score enter the table?
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < MAX_HIGH_SCORES
+                || self.entries.last().is_some_and(|last| score > last.score))
+    }
+
+    /// Insert an entry at its rank, dropping the lowest one if the table is full.
+    /// Return the index of the new entry, or `None` if it didn't qualify.
+    pub fn insert(&mut self, entry: HighScoreEntry) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        // Ties rank below the existing entries
+        let index = self
+            .entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(index, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(index)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_high_scores(json: String) -> Result<(), String> {
+    std::fs::write(HIGH_SCORES_PATH, json).map_err(|err| err.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_high_scores(json: String) -> Result<(), String> {
+    local_storage()
+        .ok_or_else(|| "no local storage".to_string())?
+        .set_item(HIGH_SCORES_STORAGE_KEY, &json)
+        .map_err(|err| format!("{:?}", err))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Prompt shown at the end of a run whose score enters the high score table, to enter
+/// the name of the player arcade-style.
+#[derive(Component)]
+pub struct NameEntry {
+    /// Index into [`NAME_CHARSET`] of each character of the name.
+    chars: [usize; NAME_LENGTH],
+    /// Index of the character being edited.
+    cursor: usize,
+    /// Time since the prompt appeared, in seconds.
+    time: f32,
+}
+
+impl NameEntry {
+    fn name(&self) -> String {
+        let name: String = self
+            .chars
+            .iter()
+            .map(|&index| NAME_CHARSET[index])
+            .collect();
+        name.trim().to_string()
+    }
+}
+
+/// Text of the [`NameEntry`] showing the name.
+#[derive(Component)]
+struct NameText;
+
+/// Color of the characters of the name entry.
+const NAME_COLOR: Color = Color::rgb(0.88, 0.88, 0.88);
+
+/// Color of the character of the name entry being edited.
+const NAME_CURSOR_COLOR: Color = Color::rgb(0.22, 0.76, 0.75);
+
+/// Spawn the [`NameEntry`] prompt, prefilled with the last name entered.
+pub fn spawn_name_entry(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    high_scores: &HighScores,
+) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: NAME_COLOR,
+            },
+            Default::default(),
+        )
+    };
+
+    let mut entry = NameEntry {
+        chars: [0; NAME_LENGTH],
+        cursor: 0,
+        time: 0.,
+    };
+    for (index, c) in high_scores.last_name.chars().take(NAME_LENGTH).enumerate() {
+        entry.chars[index] = NAME_CHARSET.iter().position(|&x| x == c).unwrap_or(0);
+    }
+    let name_sections = (0..NAME_LENGTH)
+        .map(|_| TextSection {
+            value: String::new(),
+            style: TextStyle {
+                font: font.clone(),
+                font_size: 64.,
+                color: NAME_COLOR,
+            },
+        })
+        .collect();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(30.),
+                    left: Val::Percent(30.),
+                    right: Val::Percent(30.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.6)),
+            ..Default::default()
+        })
+        .insert(Name::new("NameEntry"))
+        .insert(entry)
+        .insert_bundle(InputManagerBundle::<NavAction> {
+            action_state: ActionState::default(),
+            input_map: NavAction::input_map(),
+        })
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: text("New high score!".to_string(), 40.),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(12.)),
+                        ..Default::default()
+                    },
+                    text: Text {
+                        sections: name_sections,
+                        alignment: Default::default(),
+                    },
+                    ..Default::default()
+                })
+                .insert(NameText);
+            parent.spawn_bundle(TextBundle {
+                text: text(
+                    "Up/Down: letter  Left/Right: move  Confirm: done".to_string(),
+                    18.,
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+fn update_name_entry(
+    mut commands: Commands,
+    mut q_entry: Query<(Entity, &mut NameEntry, &ActionState<NavAction>)>,
+    mut q_text: Query<&mut Text, With<NameText>>,
+    recorder: Res<ReplayRecorder>,
+    mut high_scores: ResMut<HighScores>,
+    time: Res<Time>,
+) {
+    let (entity, mut entry, action_state) = if let Ok(entry) = q_entry.get_single_mut() {
+        entry
+    } else {
+        return;
+    };
+
+    entry.time += time.delta_seconds();
+    if entry.time >= NAME_INPUT_DELAY {
+        let cursor = entry.cursor;
+        let charset_len = NAME_CHARSET.len();
+        if action_state.just_pressed(NavAction::SelectPrev) {
+            entry.chars[cursor] = (entry.chars[cursor] + 1) % charset_len;
+        }
+        if action_state.just_pressed(NavAction::SelectNext) {
+            entry.chars[cursor] = (entry.chars[cursor] + charset_len - 1) % charset_len;
+        }
+        if action_state.just_pressed(NavAction::Increase) {
+            entry.cursor = (cursor + 1).min(NAME_LENGTH - 1);
+        }
+        if action_state.just_pressed(NavAction::Decrease)
+            || action_state.just_pressed(NavAction::Back)
+        {
+            entry.cursor = cursor.saturating_sub(1);
+        }
+
+        if action_state.just_pressed(NavAction::Confirm) {
+            let name = entry.name();
+            let replay = &recorder.replay;
+            let rank = high_scores.insert(HighScoreEntry {
+                name: if name.is_empty() {
+                    "???".to_string()
+                } else {
+                    name.clone()
+                },
+                score: replay.score,
+                stage: replay.stage,
+                date: replay.date,
+            });
+            info!(
+                target: "highscore",
+                "New high score: name={} score={} rank={:?}",
+                name,
+                replay.score,
+                rank.map(|rank| rank + 1)
+            );
+            high_scores.last_name = name;
+            high_scores.save();
+            commands.entity(entity).despawn_recursive();
+            return;
+        }
+    }
+
+    if let Ok(mut text) = q_text.get_single_mut() {
+        for (index, section) in text.sections.iter_mut().enumerate() {
+            let c = NAME_CHARSET[entry.chars[index]];
+            // Show spaces as underscores, otherwise they're invisible
+            section.value = if c == ' ' { '_' } else { c }.to_string();
+            section.style.color = if index == entry.cursor {
+                NAME_CURSOR_COLOR
+            } else {
+                NAME_COLOR
+            };
+        }
+    }
+}
+
+#[derive(Component, Default)]
+struct HighScoreMenu {
+    sound_click: Handle<KiraAudioSource>,
+}
+
+/// Marker for all entities of the high score screen, for cleanup.
+#[derive(Component)]
+struct HighScoreScreen;
+
+fn high_scores_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    high_scores: Res<HighScores>,
+) {
+    debug!(target: "setup", "high_scores_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(HighScoreScreen);
+
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    let text_color = Color::rgb_u8(32, 32, 32);
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: text_color,
+            },
+            Default::default(),
+        )
+    };
+
+    let mut menu = HighScoreMenu::default();
+    menu.sound_click = asset_server.load("sounds/click4.ogg");
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect::all(Val::Px(0.)),
+                margin: Rect::all(Val::Px(16.)),
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("high_scores"))
+        .insert(HighScoreScreen)
+        .insert(menu)
+        .insert_bundle(NavMenuBundle::new(1))
+        .with_children(|parent| {
+            let menu_entity = parent.parent_entity();
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(24.)),
+                    ..Default::default()
+                },
+                text: text("High Scores".to_string(), 64.),
+                ..Default::default()
+            });
+
+            if high_scores.entries.is_empty() {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    text: text("No high score yet".to_string(), 28.),
+                    ..Default::default()
+                });
+            }
+            for (index, entry) in high_scores.entries.iter().enumerate() {
+                let date = if entry.date > 0 {
+                    format_date(entry.date, "-", " ", ":")
+                } else {
+                    "----------------".to_string()
+                };
+                // Slide the entries in one after the other
+                let start = Rect {
+                    left: Val::Px(ENTRY_SLIDE_DISTANCE),
+                    ..Default::default()
+                };
+                let slide = Delay::new(Duration::from_secs_f32(index as f32 * ENTRY_SLIDE_DELAY))
+                    .then(Tween::new(
+                        EaseFunction::QuadraticOut,
+                        TweeningType::Once,
+                        Duration::from_secs_f32(ENTRY_SLIDE_DURATION),
+                        UiPositionLens {
+                            start,
+                            end: Rect {
+                                left: Val::Px(0.),
+                                ..Default::default()
+                            },
+                        },
+                    ));
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            position: start,
+                            ..Default::default()
+                        },
+                        text: text(
+                            format!(
+                                "{:>2}. {:<width$}  {:>8}  Stage {}  {}",
+                                index + 1,
+                                entry.name,
+                                entry.score,
+                                entry.stage,
+                                date,
+                                width = NAME_LENGTH
+                            ),
+                            28.,
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(Animator::new(slide));
+            }
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(24.)),
+                        ..Default::default()
+                    },
+                    text: text("Back".to_string(), 36.),
+                    ..Default::default()
+                })
+                .insert(FocusHighlight {
+                    menu: menu_entity,
+                    index: 0,
+                    color: text_color,
+                    focus_color: Color::rgb_u8(57, 194, 190),
+                });
+        });
+}
+
+fn high_scores_run(
+    q_menu: Query<(&HighScoreMenu, &ActionState<NavAction>), With<NavMenu>>,
+    audio_facade: AudioFacade,
+    mut state_requests: EventWriter<RequestStateChange>,
+) {
+    let (menu, action_state) = q_menu.single();
+    if action_state.just_pressed(NavAction::Confirm) || action_state.just_pressed(NavAction::Back) {
+        audio_facade.play_ui(menu.sound_click.clone());
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+    }
+}
+
+fn high_scores_cleanup(mut commands: Commands, query: Query<Entity, With<HighScoreScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
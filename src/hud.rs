@@ -0,0 +1,704 @@
+use bevy::prelude::*;
+use bevy_tweening::{lens::*, *};
+use std::time::Duration;
+
+use crate::{
+    combo::Combo,
+    game::{CommonAssets, InGameEntity, PlayerController, ScoreEvent, MAX_BOMBS},
+    letterbox::PlayfieldNode,
+    overdrive::{Overdrive, OverdriveMeter, OVERDRIVE_DURATION},
+    AppState,
+};
+
+/// Screen-space HUD widgets: UI nodes anchored to an edge of the screen, which slide in
+/// and out of view. The score, the bomb stock, the combo multiplier and the overdrive
+/// gauge are displayed with them, as well as the lifebars of the game plugin.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::InGame).with_system(hud_setup))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(show_displays)
+                    .with_system(update_hud_slides.after(show_displays))
+                    .with_system(update_score_display)
+                    .with_system(update_bomb_stock)
+                    .with_system(update_combo_display)
+                    .with_system(update_overdrive_gauge),
+            );
+    }
+}
+
+/// Default color of the background of the widgets.
+pub const HUD_BACKGROUND_COLOR: Color = Color::BLACK;
+
+/// Duration of the slide in of the score, bomb stock, combo and overdrive displays, in
+/// seconds.
+const DISPLAY_SLIDE_IN_DURATION: f32 = 0.8;
+
+/// Distance of the score, bomb stock, combo and overdrive displays from the right edge
+/// of the screen, in percent of the screen width.
+const DISPLAY_INSET: f32 = 3.9;
+
+/// Distance of the score display from the top edge of the screen, in percent of the
+/// screen height.
+const SCORE_DISPLAY_TOP: f32 = 7.;
+
+/// Distance of the bomb stock display from the top edge of the screen, in percent of
+/// the screen height. Below the lives counter.
+const BOMB_STOCK_TOP: f32 = 19.;
+
+/// Distance of the combo display from the top edge of the screen, in percent of the
+/// screen height.
+const COMBO_DISPLAY_TOP: f32 = 24.;
+
+/// Distance of the overdrive gauge from the top edge of the screen, in percent of the
+/// screen height.
+const OVERDRIVE_GAUGE_TOP: f32 = 30.;
+
+/// Width of the bar of the overdrive gauge, in logical pixels.
+const OVERDRIVE_BAR_WIDTH: f32 = 120.;
+
+/// Height of the bar of the overdrive gauge, in logical pixels.
+const OVERDRIVE_BAR_HEIGHT: f32 = 10.;
+
+const SCORE_TEXT_COLOR: Color = Color::rgb(32. / 255., 32. / 255., 32. / 255.);
+
+/// Size of an icon of the bomb stock, in logical pixels.
+const BOMB_ICON_SIZE: f32 = 14.;
+
+const BOMB_ICON_COLOR: Color = Color::rgb(1., 0.55, 0.1);
+const BOMB_ICON_EMPTY_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+
+const COMBO_IDLE_COLOR: Color = Color::rgb(0.4, 0.4, 0.4);
+const COMBO_ACTIVE_COLOR: Color = Color::rgb(1., 0.55, 0.1);
+const COMBO_BREAK_COLOR: Color = Color::rgb(0.85, 0.1, 0.1);
+
+/// Duration of the pulse of the combo display on each kill, in seconds.
+const COMBO_PULSE_DURATION: f32 = 0.25;
+
+/// Duration of the crack of the combo display when the combo breaks, in seconds.
+const COMBO_CRACK_DURATION: f32 = 0.8;
+
+const OVERDRIVE_FILL_COLOR: Color = Color::rgb(0.6, 0.35, 0.15);
+const OVERDRIVE_FULL_COLOR: Color = Color::rgb(1., 0.45, 0.1);
+const OVERDRIVE_ACTIVE_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+
+/// Edge of the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenEdge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// Screen edge a widget is anchored to, and its distances from that edge.
+///
+/// The distances are inward from the edge, in percent of the screen size along the
+/// axis of the edge normal, so that the widget follows the edge when the window is
+/// resized. The widget is centered along the edge, unless aligned to a corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HudAnchor {
+    pub edge: ScreenEdge,
+    /// Distance from the edge when the widget is visible.
+    pub visible_inset: f32,
+    /// Distance from the edge when the widget is hidden, usually negative.
+    pub hidden_inset: f32,
+    /// Perpendicular edge the widget is aligned to, and its distance from it in
+    /// percent of the screen size, if any.
+    pub align: Option<(ScreenEdge, f32)>,
+}
+
+impl Default for HudAnchor {
+    fn default() -> Self {
+        HudAnchor {
+            edge: ScreenEdge::Top,
+            visible_inset: 0.,
+            hidden_inset: 0.,
+            align: None,
+        }
+    }
+}
+
+impl HudAnchor {
+    /// Visible and hidden positions of the widget node.
+    pub fn positions(&self) -> (Rect<Val>, Rect<Val>) {
+        fn set(rect: &mut Rect<Val>, edge: ScreenEdge, offset: f32) {
+            let offset = Val::Percent(offset);
+            match edge {
+                ScreenEdge::Left => rect.left = offset,
+                ScreenEdge::Right => rect.right = offset,
+                ScreenEdge::Top => rect.top = offset,
+                ScreenEdge::Bottom => rect.bottom = offset,
+            }
+        }
+        let position = |inset: f32| {
+            let mut rect = Rect::<Val>::default();
+            set(&mut rect, self.edge, inset);
+            if let Some((edge, offset)) = self.align {
+                set(&mut rect, edge, offset);
+            }
+            rect
+        };
+        (position(self.visible_inset), position(self.hidden_inset))
+    }
+}
+
+/// Orientation of a bar, along which it fills up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarOrientation {
+    Horizontal,
+    Vertical,
+}
+
+impl BarOrientation {
+    /// Scale of a bar node filled up to the given fraction, scaling about its center.
+    pub fn fill_scale(&self, progress: f32) -> Vec3 {
+        match self {
+            BarOrientation::Horizontal => Vec3::new(progress, 1., 1.),
+            BarOrientation::Vertical => Vec3::new(1., progress, 1.),
+        }
+    }
+
+    /// Tween filling up a bar node from empty to full.
+    pub fn fill_tween(&self, duration: f32) -> Tween<Transform> {
+        Tween::new(
+            EaseMethod::Linear,
+            TweeningType::Once,
+            Duration::from_secs_f32(duration),
+            TransformScaleLens {
+                start: self.fill_scale(0.),
+                end: Vec3::ONE,
+            },
+        )
+    }
+}
+
+/// Widget of the HUD, spawned with [`spawn_hud_widget()`] inside a background node
+/// anchored to a screen edge.
+pub trait HudWidget: Component + Sized {
+    /// Size of the background node.
+    fn size(&self) -> Size<Val>;
+
+    /// Color of the background node.
+    fn background(&self) -> Color {
+        HUD_BACKGROUND_COLOR
+    }
+
+    /// Spawn the content nodes of the widget, children of the background node.
+    fn spawn_content(&mut self, parent: &mut ChildBuilder);
+}
+
+/// Slide state of a widget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlideState {
+    /// Off-screen.
+    Hidden,
+    /// Sliding from the hidden to the visible position.
+    SlidingIn,
+    /// On screen.
+    Visible,
+    /// Sliding from its current position to the hidden position.
+    SlidingOut,
+}
+
+/// Slide in and out animation of a widget between the positions of its anchor,
+/// playing on the [`Animator<Style>`] of the widget.
+#[derive(Component, Debug)]
+pub struct HudSlide {
+    anchor: HudAnchor,
+    state: SlideState,
+}
+
+impl HudSlide {
+    pub fn state(&self) -> SlideState {
+        self.state
+    }
+
+    /// Start sliding into the screen, from the hidden position if hidden, or from the
+    /// current position if sliding out.
+    pub fn slide_in(&mut self, style: &Style, animator: &mut Animator<Style>, duration: f32) {
+        let (visible_pos, hidden_pos) = self.anchor.positions();
+        let start = match self.state {
+            SlideState::Hidden => hidden_pos,
+            SlideState::SlidingOut => style.position,
+            _ => return,
+        };
+        self.play(animator, start, visible_pos, duration);
+        self.state = SlideState::SlidingIn;
+    }
+
+    /// Start sliding out of the screen from the current position, unless hidden or
+    /// sliding out already.
+    pub fn slide_out(&mut self, style: &Style, animator: &mut Animator<Style>, duration: f32) {
+        if self.state == SlideState::Hidden || self.state == SlideState::SlidingOut {
+            return;
+        }
+        let (_, hidden_pos) = self.anchor.positions();
+        self.play(animator, style.position, hidden_pos, duration);
+        self.state = SlideState::SlidingOut;
+    }
+
+    /// Change the distance of the widget from its edge when visible, taking effect on
+    /// the next slide in.
+    pub fn set_visible_inset(&mut self, inset: f32) {
+        self.anchor.visible_inset = inset;
+    }
+
+    fn play(
+        &self,
+        animator: &mut Animator<Style>,
+        start: Rect<Val>,
+        end: Rect<Val>,
+        duration: f32,
+    ) {
+        animator.set_tweenable(Tween::new(
+            EaseMethod::Linear,
+            TweeningType::Once,
+            Duration::from_secs_f32(duration),
+            UiPositionLens { start, end },
+        ));
+        animator.rewind();
+        animator.state = AnimatorState::Playing;
+    }
+}
+
+/// Spawn the nodes of a widget, hidden, and return the entity of its background node,
+/// which holds the widget component.
+pub fn spawn_hud_widget<W: HudWidget>(
+    commands: &mut Commands,
+    name: impl Into<std::borrow::Cow<'static, str>>,
+    anchor: HudAnchor,
+    mut widget: W,
+) -> Entity {
+    let (_, hidden_pos) = anchor.positions();
+    let mut entity = Entity::from_raw(0);
+    commands
+        .spawn_bundle(NodeBundle {
+            // Playfield-sized root, to center the widget along its edge
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new(name))
+        .insert(InGameEntity)
+        .insert(PlayfieldNode)
+        .with_children(|parent| {
+            entity = parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: hidden_pos,
+                        align_self: AlignSelf::Center,
+                        size: widget.size(),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..Default::default()
+                    },
+                    color: UiColor(widget.background()),
+                    ..Default::default()
+                })
+                .insert(HudSlide {
+                    anchor,
+                    state: SlideState::Hidden,
+                })
+                .insert(Animator::<Style>::default().with_state(AnimatorState::Paused))
+                .with_children(|parent| widget.spawn_content(parent))
+                .insert(widget)
+                .id();
+        });
+    entity
+}
+
+/// Settle the slides which finished playing.
+pub fn update_hud_slides(mut query: Query<(&mut HudSlide, &mut Animator<Style>)>) {
+    for (mut slide, mut animator) in query.iter_mut() {
+        if animator.progress() < 1. {
+            continue;
+        }
+        let state = match slide.state {
+            SlideState::SlidingIn => SlideState::Visible,
+            SlideState::SlidingOut => SlideState::Hidden,
+            _ => continue,
+        };
+        animator.stop();
+        slide.state = state;
+    }
+}
+
+/// Score of the current game, accumulated from the [`ScoreEvent`]s.
+#[derive(Component)]
+pub struct ScoreDisplay {
+    score: u32,
+    font: Handle<Font>,
+    text_entity: Entity,
+}
+
+impl ScoreDisplay {
+    /// Reset the score to zero, like on continue.
+    pub fn reset(&mut self) {
+        self.score = 0;
+    }
+}
+
+impl HudWidget for ScoreDisplay {
+    fn size(&self) -> Size<Val> {
+        Size::default()
+    }
+
+    fn background(&self) -> Color {
+        Color::NONE
+    }
+
+    fn spawn_content(&mut self, parent: &mut ChildBuilder) {
+        self.text_entity = parent
+            .spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "0",
+                    TextStyle {
+                        font: self.font.clone(),
+                        font_size: 48.0,
+                        color: SCORE_TEXT_COLOR,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Right,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            })
+            .id();
+    }
+}
+
+/// Stock of bombs of the player, one icon per bomb it can hold.
+#[derive(Component, Default)]
+struct BombStock {
+    icons: Vec<Entity>,
+}
+
+impl HudWidget for BombStock {
+    fn size(&self) -> Size<Val> {
+        Size::default()
+    }
+
+    fn spawn_content(&mut self, parent: &mut ChildBuilder) {
+        self.icons = (0..MAX_BOMBS)
+            .map(|_| {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(BOMB_ICON_SIZE), Val::Px(BOMB_ICON_SIZE)),
+                            margin: Rect::all(Val::Px(3.)),
+                            ..Default::default()
+                        },
+                        color: UiColor(BOMB_ICON_EMPTY_COLOR),
+                        ..Default::default()
+                    })
+                    .id()
+            })
+            .collect();
+    }
+}
+
+/// Score multiplier of the kill [`Combo`].
+#[derive(Component)]
+struct ComboDisplay {
+    font: Handle<Font>,
+    text_entity: Entity,
+    /// Kills and breaks of the combo last displayed.
+    kills: u32,
+    breaks: u32,
+}
+
+impl HudWidget for ComboDisplay {
+    fn size(&self) -> Size<Val> {
+        Size::default()
+    }
+
+    fn background(&self) -> Color {
+        Color::NONE
+    }
+
+    fn spawn_content(&mut self, parent: &mut ChildBuilder) {
+        self.text_entity = parent
+            .spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "x1.0",
+                    TextStyle {
+                        font: self.font.clone(),
+                        font_size: 32.0,
+                        color: COMBO_IDLE_COLOR,
+                    },
+                    TextAlignment {
+                        horizontal: HorizontalAlign::Right,
+                        ..Default::default()
+                    },
+                ),
+                ..Default::default()
+            })
+            .insert(Animator::<Transform>::default().with_state(AnimatorState::Paused))
+            .insert(Animator::<Text>::default().with_state(AnimatorState::Paused))
+            .id();
+    }
+}
+
+/// Fill level of the [`OverdriveMeter`], or time left of the [`Overdrive`] while
+/// active.
+#[derive(Component)]
+struct OverdriveGauge {
+    fill_entity: Entity,
+}
+
+impl HudWidget for OverdriveGauge {
+    fn size(&self) -> Size<Val> {
+        Size::new(
+            Val::Px(OVERDRIVE_BAR_WIDTH + 4.),
+            Val::Px(OVERDRIVE_BAR_HEIGHT + 4.),
+        )
+    }
+
+    fn spawn_content(&mut self, parent: &mut ChildBuilder) {
+        self.fill_entity = parent
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(0.), Val::Px(OVERDRIVE_BAR_HEIGHT)),
+                    margin: Rect::all(Val::Px(2.)),
+                    ..Default::default()
+                },
+                color: UiColor(OVERDRIVE_FILL_COLOR),
+                ..Default::default()
+            })
+            .id();
+    }
+}
+
+fn hud_setup(mut commands: Commands, common_assets: Res<CommonAssets>) {
+    let font = common_assets.hud_font.clone();
+    spawn_hud_widget(
+        &mut commands,
+        "ScoreDisplay",
+        HudAnchor {
+            edge: ScreenEdge::Right,
+            visible_inset: DISPLAY_INSET,
+            hidden_inset: -30.,
+            align: Some((ScreenEdge::Top, SCORE_DISPLAY_TOP)),
+        },
+        ScoreDisplay {
+            score: 0,
+            font: font.clone(),
+            text_entity: Entity::from_raw(0),
+        },
+    );
+    spawn_hud_widget(
+        &mut commands,
+        "BombStock",
+        HudAnchor {
+            edge: ScreenEdge::Right,
+            visible_inset: DISPLAY_INSET,
+            hidden_inset: -20.,
+            align: Some((ScreenEdge::Top, BOMB_STOCK_TOP)),
+        },
+        BombStock::default(),
+    );
+    spawn_hud_widget(
+        &mut commands,
+        "ComboDisplay",
+        HudAnchor {
+            edge: ScreenEdge::Right,
+            visible_inset: DISPLAY_INSET,
+            hidden_inset: -20.,
+            align: Some((ScreenEdge::Top, COMBO_DISPLAY_TOP)),
+        },
+        ComboDisplay {
+            font,
+            text_entity: Entity::from_raw(0),
+            kills: 0,
+            breaks: 0,
+        },
+    );
+    spawn_hud_widget(
+        &mut commands,
+        "OverdriveGauge",
+        HudAnchor {
+            edge: ScreenEdge::Right,
+            visible_inset: DISPLAY_INSET,
+            hidden_inset: -20.,
+            align: Some((ScreenEdge::Top, OVERDRIVE_GAUGE_TOP)),
+        },
+        OverdriveGauge {
+            fill_entity: Entity::from_raw(0),
+        },
+    );
+}
+
+/// Slide the score, bomb stock, combo and overdrive displays in as soon as they're
+/// spawned.
+fn show_displays(
+    mut query: Query<
+        (&mut HudSlide, &Style, &mut Animator<Style>),
+        Or<(
+            Added<ScoreDisplay>,
+            Added<BombStock>,
+            Added<ComboDisplay>,
+            Added<OverdriveGauge>,
+        )>,
+    >,
+) {
+    for (mut slide, style, mut animator) in query.iter_mut() {
+        slide.slide_in(style, &mut animator, DISPLAY_SLIDE_IN_DURATION);
+    }
+}
+
+fn update_score_display(
+    mut score_events: EventReader<ScoreEvent>,
+    mut query: Query<&mut ScoreDisplay>,
+    mut q_text: Query<&mut Text>,
+) {
+    let mut display = if let Ok(display) = query.get_single_mut() {
+        display
+    } else {
+        return;
+    };
+    for ev in score_events.iter() {
+        display.score += ev.0;
+    }
+    if !display.is_changed() {
+        return;
+    }
+    if let Ok(mut text) = q_text.get_mut(display.text_entity) {
+        text.sections[0].value = format!("{}", display.score);
+    }
+}
+
+fn update_bomb_stock(
+    q_player: Query<&PlayerController>,
+    q_stock: Query<&BombStock>,
+    mut q_icons: Query<&mut UiColor>,
+) {
+    let bombs = if let Ok(controller) = q_player.get_single() {
+        controller.bombs()
+    } else {
+        return;
+    };
+    for stock in q_stock.iter() {
+        for (index, icon) in stock.icons.iter().enumerate() {
+            let color = if (index as u32) < bombs {
+                BOMB_ICON_COLOR
+            } else {
+                BOMB_ICON_EMPTY_COLOR
+            };
+            if let Ok(mut icon_color) = q_icons.get_mut(*icon) {
+                if icon_color.0 != color {
+                    icon_color.0 = color;
+                }
+            }
+        }
+    }
+}
+
+/// Display the combo multiplier, pulsing on each kill and cracking when the combo
+/// breaks.
+fn update_combo_display(
+    combo: Res<Combo>,
+    mut query: Query<&mut ComboDisplay>,
+    mut q_text: Query<(&mut Text, &mut Animator<Transform>, &mut Animator<Text>)>,
+) {
+    if !combo.is_changed() {
+        return;
+    }
+    for mut display in query.iter_mut() {
+        let (mut text, mut scale_animator, mut color_animator) =
+            if let Ok(text) = q_text.get_mut(display.text_entity) {
+                text
+            } else {
+                continue;
+            };
+        text.sections[0].value = format!("x{:.1}", combo.multiplier());
+
+        let (scale_tween, color) = if combo.breaks() != display.breaks {
+            // Squash in red, then fade back to idle
+            color_animator.set_tweenable(Tween::new(
+                EaseFunction::QuadraticIn,
+                TweeningType::Once,
+                Duration::from_secs_f32(COMBO_CRACK_DURATION),
+                TextColorLens {
+                    start: COMBO_BREAK_COLOR,
+                    end: COMBO_IDLE_COLOR,
+                    section: 0,
+                },
+            ));
+            color_animator.rewind();
+            color_animator.state = AnimatorState::Playing;
+            let tween = Tween::new(
+                EaseFunction::BounceOut,
+                TweeningType::Once,
+                Duration::from_secs_f32(COMBO_CRACK_DURATION),
+                TransformScaleLens {
+                    start: Vec3::new(1.3, 0.6, 1.),
+                    end: Vec3::ONE,
+                },
+            );
+            (tween, COMBO_BREAK_COLOR)
+        } else if combo.kills() != display.kills && combo.kills() > 0 {
+            color_animator.stop();
+            let tween = Tween::new(
+                EaseFunction::QuadraticOut,
+                TweeningType::Once,
+                Duration::from_secs_f32(COMBO_PULSE_DURATION),
+                TransformScaleLens {
+                    start: Vec3::splat(1.4),
+                    end: Vec3::ONE,
+                },
+            );
+            (tween, COMBO_ACTIVE_COLOR)
+        } else {
+            continue;
+        };
+        display.kills = combo.kills();
+        display.breaks = combo.breaks();
+        text.sections[0].style.color = color;
+        scale_animator.set_tweenable(scale_tween);
+        scale_animator.rewind();
+        scale_animator.state = AnimatorState::Playing;
+    }
+}
+
+/// Fill the overdrive gauge with the meter, brightening once full, then drain it with
+/// the time left of the overdrive.
+fn update_overdrive_gauge(
+    meter: Res<OverdriveMeter>,
+    q_player: Query<Option<&Overdrive>, With<PlayerController>>,
+    q_gauge: Query<&OverdriveGauge>,
+    mut q_fill: Query<(&mut Style, &mut UiColor)>,
+) {
+    let overdrive = q_player.get_single().ok().flatten();
+    let (fill, color) = if let Some(overdrive) = overdrive {
+        (
+            overdrive.remaining.max(0.) / OVERDRIVE_DURATION,
+            OVERDRIVE_ACTIVE_COLOR,
+        )
+    } else if meter.is_full() {
+        (1., OVERDRIVE_FULL_COLOR)
+    } else {
+        (meter.value(), OVERDRIVE_FILL_COLOR)
+    };
+    for gauge in q_gauge.iter() {
+        if let Ok((mut style, mut fill_color)) = q_fill.get_mut(gauge.fill_entity) {
+            let width = Val::Percent(fill * 100.);
+            if style.size.width != width {
+                style.size.width = width;
+            }
+            if fill_color.0 != color {
+                fill_color.0 = color;
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use bevy::{prelude::*, window::WindowId};
+
+use crate::{game::InGameEntity, AppState};
+
+/// Fixed 16:9 playfield: whatever the shape of the window, like a browser canvas, the
+/// camera is fitted to show the whole playfield, and the rest of the window is covered
+/// by black bars, so the gameplay area and the HUD anchors don't depend on the window.
+pub struct LetterboxPlugin;
+
+impl Plugin for LetterboxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Playfield>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_playfield)
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(letterbox_setup))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(fit_camera_to_playfield)
+                    .with_system(fit_playfield_nodes)
+                    .with_system(update_letterbox_bars),
+            );
+    }
+}
+
+/// Aspect ratio (width / height) of the playfield.
+pub const PLAYFIELD_ASPECT_RATIO: f32 = 16. / 9.;
+
+const BAR_COLOR: Color = Color::BLACK;
+
+/// Area of the primary window showing the playfield, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Playfield {
+    /// Size of the window.
+    pub window_size: Vec2,
+    /// Size of the playfield, at most the window size.
+    pub size: Vec2,
+}
+
+impl Default for Playfield {
+    fn default() -> Self {
+        Playfield::from_window_size(Vec2::new(1280., 720.))
+    }
+}
+
+impl Playfield {
+    pub fn from_window_size(window_size: Vec2) -> Self {
+        let window_size = window_size.max(Vec2::ONE);
+        let size = if window_size.x / window_size.y > PLAYFIELD_ASPECT_RATIO {
+            // Wider than the playfield; pillarbox
+            Vec2::new(window_size.y * PLAYFIELD_ASPECT_RATIO, window_size.y)
+        } else {
+            // Taller than the playfield; letterbox
+            Vec2::new(window_size.x, window_size.x / PLAYFIELD_ASPECT_RATIO)
+        };
+        Playfield { window_size, size }
+    }
+
+    /// Size of each of the two bars on the sides of the playfield, horizontally and
+    /// vertically. At least one of the two is zero.
+    pub fn bar_size(&self) -> Vec2 {
+        (self.window_size - self.size) * 0.5
+    }
+}
+
+/// Factor to apply to the vertical extent seen by a camera so that a window of the
+/// given aspect ratio shows the whole playfield. Windows wider than the playfield show
+/// its full height already, and need no fitting.
+pub fn playfield_fit(aspect_ratio: f32) -> f32 {
+    (PLAYFIELD_ASPECT_RATIO / aspect_ratio).max(1.)
+}
+
+/// Fitting currently applied to the projection of a camera, as returned by
+/// [`playfield_fit()`].
+#[derive(Component, Debug, Clone, Copy)]
+pub struct PlayfieldFit(pub f32);
+
+/// Marker for the root UI nodes laid out over the playfield instead of the whole window.
+/// Their position and size are overwritten to match the [`Playfield`].
+#[derive(Component, Default)]
+pub struct PlayfieldNode;
+
+/// Black bar covering one side of the window outside of the playfield.
+#[derive(Component)]
+enum LetterboxBar {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+fn update_playfield(windows: Res<Windows>, mut playfield: ResMut<Playfield>) {
+    // No window when running headless
+    if let Some(window) = windows.get(WindowId::primary()) {
+        let new_playfield = Playfield::from_window_size(Vec2::new(window.width(), window.height()));
+        if *playfield != new_playfield {
+            debug!(target: "camera", "Playfield changed: {:?}", new_playfield);
+            *playfield = new_playfield;
+        }
+    }
+}
+
+fn letterbox_setup(mut commands: Commands) {
+    for bar in [
+        LetterboxBar::Left,
+        LetterboxBar::Right,
+        LetterboxBar::Top,
+        LetterboxBar::Bottom,
+    ] {
+        commands
+            .spawn_bundle(NodeBundle {
+                // Sized by update_letterbox_bars()
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    ..Default::default()
+                },
+                color: UiColor(BAR_COLOR),
+                ..Default::default()
+            })
+            .insert(Name::new("LetterboxBar"))
+            .insert(InGameEntity)
+            .insert(bar);
+    }
+}
+
+/// Rescale the camera projections when the window aspect ratio changes, so the whole
+/// playfield stays visible. The projections are scaled relative to the fitting already
+/// applied, to preserve any other change like the photo mode zoom.
+fn fit_camera_to_playfield(
+    playfield: Res<Playfield>,
+    mut query: Query<(
+        &mut PlayfieldFit,
+        Option<&mut PerspectiveProjection>,
+        Option<&mut OrthographicProjection>,
+    )>,
+) {
+    let fit = playfield_fit(playfield.window_size.x / playfield.window_size.y);
+    for (mut current_fit, perspective, orthographic) in query.iter_mut() {
+        if (current_fit.0 - fit).abs() < 1e-4 {
+            continue;
+        }
+        let factor = fit / current_fit.0;
+        if let Some(mut projection) = perspective {
+            projection.fov *= factor;
+        }
+        if let Some(mut projection) = orthographic {
+            projection.scale *= factor;
+        }
+        current_fit.0 = fit;
+    }
+}
+
+fn fit_playfield_nodes(
+    playfield: Res<Playfield>,
+    mut query: Query<&mut Style, With<PlayfieldNode>>,
+    q_added: Query<(), Added<PlayfieldNode>>,
+) {
+    if !playfield.is_changed() && q_added.is_empty() {
+        return;
+    }
+    let bar_size = playfield.bar_size();
+    for mut style in query.iter_mut() {
+        style.position_type = PositionType::Absolute;
+        style.position = Rect {
+            left: Val::Px(bar_size.x),
+            top: Val::Px(bar_size.y),
+            ..Default::default()
+        };
+        style.size = Size::new(Val::Px(playfield.size.x), Val::Px(playfield.size.y));
+    }
+}
+
+fn update_letterbox_bars(
+    playfield: Res<Playfield>,
+    mut query: Query<(&LetterboxBar, &mut Style)>,
+    q_added: Query<(), Added<LetterboxBar>>,
+) {
+    if !playfield.is_changed() && q_added.is_empty() {
+        return;
+    }
+    let bar_size = playfield.bar_size();
+    let window_size = playfield.window_size;
+    for (bar, mut style) in query.iter_mut() {
+        let (position, size) = match bar {
+            LetterboxBar::Left => (
+                Rect {
+                    left: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                Vec2::new(bar_size.x, window_size.y),
+            ),
+            LetterboxBar::Right => (
+                Rect {
+                    right: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                Vec2::new(bar_size.x, window_size.y),
+            ),
+            LetterboxBar::Top => (
+                Rect {
+                    left: Val::Px(0.),
+                    top: Val::Px(0.),
+                    ..Default::default()
+                },
+                Vec2::new(window_size.x, bar_size.y),
+            ),
+            LetterboxBar::Bottom => (
+                Rect {
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    ..Default::default()
+                },
+                Vec2::new(window_size.x, bar_size.y),
+            ),
+        };
+        style.position = position;
+        style.size = Size::new(Val::Px(size.x), Val::Px(size.y));
+    }
+}
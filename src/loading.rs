@@ -0,0 +1,449 @@
+use bevy::{
+    asset::{HandleId, LoadState},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use serde::Deserialize;
+
+use crate::{
+    cli::LaunchOptions, dialogue::dialogue_database_error, enemy::enemy_database_error,
+    flow::RequestStateChange, game::StageStartEvent, menu::menu_layout_error,
+    music::bgm_database_error, ship::ship_database_error, tutorial::tutorial_database_error,
+    AppState,
+};
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingAssets>()
+            .init_resource::<StageAssets>()
+            .insert_resource(AssetManifest::parse().unwrap_or_default())
+            // on_enter() doesn't work on the initial state, so setup at startup instead
+            .add_startup_system(loading_setup)
+            .add_system_set(SystemSet::on_update(AppState::Boot).with_system(loading_run))
+            .add_system_set(SystemSet::on_exit(AppState::Boot).with_system(loading_cleanup))
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(load_stage_assets))
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(unload_stage_assets));
+    }
+}
+
+/// Group of assets used by a single stage.
+#[derive(Debug, Clone, Deserialize)]
+struct StageAssetGroup {
+    /// Name of the stage, for logging.
+    name: String,
+    /// Paths of the assets.
+    assets: Vec<String>,
+}
+
+/// Assets to preload, loaded from `assets/asset_manifest.json`.
+///
+/// The common assets are loaded during boot and kept for the whole session. The assets
+/// of a stage are only kept while that stage or the one before it is played, so the
+/// memory doesn't grow along a campaign while the next stage is always ready.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct AssetManifest {
+    common: Vec<String>,
+    /// Asset group of each stage, indexed by stage. Stages past the end use the last
+    /// group.
+    stages: Vec<StageAssetGroup>,
+}
+
+impl AssetManifest {
+    fn parse() -> serde_json::Result<AssetManifest> {
+        serde_json::from_str(include_str!("../assets/asset_manifest.json"))
+    }
+
+    /// Index of the asset group of a stage, if any.
+    fn group_index(&self, stage: usize) -> Option<usize> {
+        if self.stages.is_empty() {
+            None
+        } else {
+            Some(stage.min(self.stages.len() - 1))
+        }
+    }
+}
+
+/// Error parsing the asset manifest, if any, to report it during boot. The game still
+/// runs without it, loading each asset on first use.
+fn asset_manifest_error() -> Option<String> {
+    AssetManifest::parse().err().map(|err| err.to_string())
+}
+
+/// Handles to all the assets preloaded during [`AppState::Boot`].
+///
+/// Holding the strong handles here keeps the assets alive, so that later calls to
+/// `AssetServer::load()` with the same path return the already-loaded asset instead
+/// of streaming it in during gameplay.
+#[derive(Default)]
+pub struct LoadingAssets {
+    /// Handles to the common assets of the [`AssetManifest`].
+    pub handles: Vec<HandleUntyped>,
+    /// Paths of all the assets loaded during boot, including the asset group of the
+    /// start stage.
+    paths: Vec<String>,
+    /// Description of each asset which failed to load, once all assets are done loading.
+    pub failures: Vec<String>,
+    /// Time the failure report has been shown for, in seconds, if any.
+    report_time: Option<f32>,
+}
+
+impl LoadingAssets {
+    /// Fraction in [0:1] of the assets fully loaded.
+    pub fn progress(&self, asset_server: &AssetServer) -> f32 {
+        if self.paths.is_empty() {
+            return 1.;
+        }
+        let loaded_count = self
+            .paths
+            .iter()
+            .filter(|path| asset_server.get_load_state(path.as_str()) == LoadState::Loaded)
+            .count();
+        loaded_count as f32 / self.paths.len() as f32
+    }
+
+    /// Combined load state of all preloaded assets.
+    pub fn load_state(&self, asset_server: &AssetServer) -> LoadState {
+        asset_server
+            .get_group_load_state(self.paths.iter().map(|path| HandleId::from(path.as_str())))
+    }
+
+    /// Are all the preloaded assets either loaded or failed? Unlike
+    /// [`LoadingAssets::load_state()`] this doesn't stop at the first failure.
+    fn is_done(&self, asset_server: &AssetServer) -> bool {
+        self.paths.iter().all(|path| {
+            matches!(
+                asset_server.get_load_state(path.as_str()),
+                LoadState::Loaded | LoadState::Failed
+            )
+        })
+    }
+}
+
+/// Handles to the asset groups of the stages currently kept loaded, by group index.
+///
+/// Dropping the handles of a group lets the asset server free the assets which aren't
+/// used anywhere else.
+#[derive(Default)]
+struct StageAssets {
+    groups: Vec<(usize, Vec<HandleUntyped>)>,
+}
+
+impl StageAssets {
+    /// Keep the asset groups of the given stages loaded, starting to load any missing
+    /// one, and drop all the other groups.
+    fn retain(&mut self, stages: &[usize], manifest: &AssetManifest, asset_server: &AssetServer) {
+        let indices: Vec<usize> = stages
+            .iter()
+            .filter_map(|stage| manifest.group_index(*stage))
+            .collect();
+        self.groups.retain(|(index, _)| {
+            let keep = indices.contains(index);
+            if !keep {
+                debug!(
+                    target: "assets",
+                    "Unloading the assets of {}",
+                    manifest.stages[*index].name
+                );
+            }
+            keep
+        });
+        for index in indices {
+            if self.groups.iter().any(|(loaded, _)| *loaded == index) {
+                continue;
+            }
+            let group = &manifest.stages[index];
+            debug!(
+                target: "assets",
+                "Loading the {} assets of {}",
+                group.assets.len(),
+                group.name
+            );
+            let handles = group
+                .assets
+                .iter()
+                .map(|path| asset_server.load_untyped(path.as_str()))
+                .collect();
+            self.groups.push((index, handles));
+        }
+    }
+}
+
+/// Time the failure report stays on screen if no key is pressed, in seconds, so that
+/// unattended runs (`--bench`, CI) still proceed.
+const REPORT_DURATION: f32 = 10.;
+
+/// Placeholder for an image which failed to load. Bullet textures are replaced with a
+/// plain disc, so that the patterns stay readable, and other images with a magenta
+/// checkerboard which stands out.
+fn placeholder_image(path: &str) -> Image {
+    const SIZE: u32 = 32;
+    let is_bullet = path.starts_with("textures/bullet");
+    let mut data = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            let rgba = if is_bullet {
+                let d = Vec2::new(x as f32 + 0.5, y as f32 + 0.5).distance(Vec2::splat(16.));
+                let alpha = ((15. - d).clamp(0., 1.) * 255.) as u8;
+                [255, 255, 255, alpha]
+            } else if (x / 8 + y / 8) % 2 == 0 {
+                [255, 0, 255, 255]
+            } else {
+                [0, 0, 0, 255]
+            };
+            data.extend_from_slice(&rgba);
+        }
+    }
+    Image::new(
+        Extent3d {
+            width: SIZE,
+            height: SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingBar;
+
+/// Text reporting the assets which failed to load.
+#[derive(Component)]
+struct FailureReport;
+
+fn loading_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading_assets: ResMut<LoadingAssets>,
+    mut stage_assets: ResMut<StageAssets>,
+    manifest: Res<AssetManifest>,
+    launch: Res<LaunchOptions>,
+) {
+    debug!(target: "setup", "loading_setup");
+
+    loading_assets.handles = manifest
+        .common
+        .iter()
+        .map(|path| asset_server.load_untyped(path.as_str()))
+        .collect();
+    // Also load the first stage played, so the game starts without any hitch
+    let start_stage = launch.start_stage();
+    stage_assets.retain(&[start_stage], &manifest, &asset_server);
+    let mut paths = manifest.common.clone();
+    if let Some(index) = manifest.group_index(start_stage) {
+        paths.extend(manifest.stages[index].assets.iter().cloned());
+    }
+    loading_assets.paths = paths;
+
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(LoadingScreen);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("loading"))
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            // Bar background
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(400.), Val::Px(16.)),
+                        padding: Rect::all(Val::Px(2.)),
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::rgb_u8(32, 32, 32)),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    // Bar fill
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                ..Default::default()
+                            },
+                            color: UiColor(Color::rgb_u8(57, 194, 190)),
+                            ..Default::default()
+                        })
+                        .insert(LoadingBar);
+                });
+        });
+}
+
+fn loading_run(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut loading_assets: ResMut<LoadingAssets>,
+    mut images: ResMut<Assets<Image>>,
+    mut state_requests: EventWriter<RequestStateChange>,
+    mut q_bar: Query<&mut Style, With<LoadingBar>>,
+    launch: Res<LaunchOptions>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    time: Res<Time>,
+) {
+    let progress = loading_assets.progress(&asset_server);
+    if let Ok(mut style) = q_bar.get_single_mut() {
+        style.size.width = Val::Percent(progress * 100.);
+    }
+
+    // Launch options can start a game directly
+    let next_state = if launch.skip_menu() {
+        AppState::InGame
+    } else {
+        AppState::Splash
+    };
+
+    // Once the failures are reported, continue with the placeholders on any input
+    if let Some(report_time) = &mut loading_assets.report_time {
+        *report_time += time.delta_seconds();
+        let any_input = keyboard_input.get_just_pressed().next().is_some()
+            || gamepad_input.get_just_pressed().next().is_some();
+        if any_input || *report_time >= REPORT_DURATION {
+            state_requests.send(RequestStateChange::immediate(next_state));
+        }
+        return;
+    }
+
+    if !loading_assets.is_done(&asset_server) {
+        return;
+    }
+
+    // Replace the images which failed to load with placeholders, before the bullet
+    // atlas is built from them on exit
+    let mut failures = vec![];
+    for path in &loading_assets.paths {
+        if asset_server.get_load_state(path.as_str()) != LoadState::Failed {
+            continue;
+        }
+        if path.ends_with(".png") {
+            let handle: Handle<Image> = asset_server.get_handle(path.as_str());
+            images.set_untracked(handle, placeholder_image(path));
+            failures.push(format!("{} (replaced with a placeholder)", path));
+        } else {
+            failures.push(path.clone());
+        }
+    }
+    if let Some(err) = asset_manifest_error() {
+        failures.push(format!(
+            "asset_manifest.json: {} (assets load on first use)",
+            err
+        ));
+    }
+    if let Some(err) = enemy_database_error() {
+        failures.push(format!("enemy_db.json: {} (no enemy will spawn)", err));
+    }
+    if let Some(err) = dialogue_database_error() {
+        failures.push(format!("dialogue_db.json: {} (no dialogue will show)", err));
+    }
+    if let Some(err) = ship_database_error() {
+        failures.push(format!(
+            "ship_db.json: {} (only the stock ship is available)",
+            err
+        ));
+    }
+    if let Some(err) = bgm_database_error() {
+        failures.push(format!("bgm_db.json: {} (no music will play)", err));
+    }
+    if let Some(err) = tutorial_database_error() {
+        failures.push(format!("tutorial_db.json: {} (no tutorial will show)", err));
+    }
+    if let Some(err) = menu_layout_error() {
+        failures.push(format!(
+            "menu_layout.json: {} (using the built-in layout)",
+            err
+        ));
+    }
+
+    if failures.is_empty() {
+        info!(target: "assets", "All {} assets loaded.", loading_assets.paths.len());
+        state_requests.send(RequestStateChange::immediate(next_state));
+        return;
+    }
+
+    for failure in &failures {
+        error!(target: "assets", "Failed to load {}", failure);
+    }
+    let report = format!(
+        "Failed to load {} asset(s):\n\n{}\n\nPress any key to continue anyway.",
+        failures.len(),
+        failures
+            .iter()
+            .map(|failure| format!("- {}", failure))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(32.),
+                    left: Val::Px(32.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                report,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraMono-Regular.ttf"),
+                    font_size: 20.,
+                    color: Color::rgb(0.9, 0.2, 0.2),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(Name::new("FailureReport"))
+        .insert(LoadingScreen)
+        .insert(FailureReport);
+    loading_assets.failures = failures;
+    loading_assets.report_time = Some(0.);
+}
+
+fn loading_cleanup(mut commands: Commands, query: Query<Entity, With<LoadingScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Keep the assets of the stage just started and of the next one loaded, and unload
+/// those of the previous stages.
+fn load_stage_assets(
+    mut stage_start_events: EventReader<StageStartEvent>,
+    mut stage_assets: ResMut<StageAssets>,
+    manifest: Res<AssetManifest>,
+    asset_server: Res<AssetServer>,
+) {
+    if let Some(ev) = stage_start_events.iter().last() {
+        stage_assets.retain(&[ev.stage, ev.stage + 1], &manifest, &asset_server);
+    }
+}
+
+/// Unload the assets of the stages played, except those of the first stage, which the
+/// next game starts with.
+fn unload_stage_assets(
+    mut stage_assets: ResMut<StageAssets>,
+    manifest: Res<AssetManifest>,
+    asset_server: Res<AssetServer>,
+    launch: Res<LaunchOptions>,
+) {
+    stage_assets.retain(&[launch.start_stage()], &manifest, &asset_server);
+}
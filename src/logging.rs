@@ -0,0 +1,129 @@
+use bevy::{log::LogSettings, prelude::*};
+use parking_lot::Mutex;
+use std::{collections::VecDeque, sync::Arc};
+
+#[cfg(not(target_arch = "wasm32"))]
+use bevy::utils::tracing::{
+    field::{Field, Visit},
+    Event, Subscriber,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing_subscriber::{layer::Context, prelude::*, registry::Registry, EnvFilter, Layer};
+
+/// Replacement for the Bevy `LogPlugin`, which must be disabled from the
+/// `DefaultPlugins`.
+///
+/// Configures logging from the [`LogSettings`] resource like the Bevy plugin does, and
+/// on native builds also captures the most recent messages into the [`LogBuffer`] for
+/// the in-game log viewer.
+pub struct LoggingPlugin;
+
+impl Plugin for LoggingPlugin {
+    fn build(&self, app: &mut App) {
+        let buffer = LogBuffer::default();
+
+        // The browser console is the only sink on the web
+        #[cfg(target_arch = "wasm32")]
+        app.add_plugin(bevy::log::LogPlugin);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let default_filter = {
+                let settings = app.world.get_resource_or_insert_with(LogSettings::default);
+                format!("{},{}", settings.level, settings.filter)
+            };
+            tracing_log::LogTracer::init().unwrap();
+            // RUST_LOG takes precedence over the settings, like with the Bevy plugin
+            let filter_layer = EnvFilter::try_from_default_env()
+                .or_else(|_| EnvFilter::try_new(&default_filter))
+                .unwrap();
+            let subscriber = Registry::default()
+                .with(filter_layer)
+                .with(tracing_subscriber::fmt::Layer::default())
+                .with(CaptureLayer(buffer.clone()));
+            bevy::utils::tracing::subscriber::set_global_default(subscriber)
+                .expect("Could not set global default tracing subscriber");
+        }
+
+        app.insert_resource(buffer);
+    }
+}
+
+/// Maximum number of messages kept by the [`LogBuffer`].
+const MAX_LOG_LINES: usize = 200;
+
+/// Log message captured by the [`LogBuffer`].
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: bevy::log::Level,
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct LogBufferInner {
+    /// Most recent messages, oldest first.
+    lines: VecDeque<LogLine>,
+    /// Total number of messages captured since startup, to detect new ones.
+    count: u64,
+}
+
+/// Most recent log messages, shared with the logging backend which fills it.
+#[derive(Default, Clone)]
+pub struct LogBuffer(Arc<Mutex<LogBufferInner>>);
+
+impl LogBuffer {
+    fn push(&self, line: LogLine) {
+        let mut inner = self.0.lock();
+        if inner.lines.len() >= MAX_LOG_LINES {
+            inner.lines.pop_front();
+        }
+        inner.lines.push_back(line);
+        inner.count += 1;
+    }
+
+    /// Total number of messages captured since startup.
+    pub fn count(&self) -> u64 {
+        self.0.lock().count
+    }
+
+    /// Clone of the last `n` messages, oldest first.
+    pub fn last(&self, n: usize) -> Vec<LogLine> {
+        let inner = self.0.lock();
+        let skip = inner.lines.len().saturating_sub(n);
+        inner.lines.iter().skip(skip).cloned().collect()
+    }
+}
+
+/// Tracing layer copying the events which pass the filter into a [`LogBuffer`].
+#[cfg(not(target_arch = "wasm32"))]
+struct CaptureLayer(LogBuffer);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<S: Subscriber> Layer<S> for CaptureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor(String::new());
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        self.0.push(LogLine {
+            level: *metadata.level(),
+            target: metadata.target().to_string(),
+            message: visitor.0,
+        });
+    }
+}
+
+/// Format the fields of an event into a single line, the message first.
+#[cfg(not(target_arch = "wasm32"))]
+struct MessageVisitor(String);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0.insert_str(0, &format!("{:?}", value));
+        } else {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+}
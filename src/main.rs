@@ -1,31 +1,139 @@
 #![allow(dead_code, unused_imports, unused_variables, unused_mut)]
 
 use bevy::{
+    app::{ScheduleRunnerPlugin, ScheduleRunnerSettings},
     diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin},
+    log::LogPlugin,
     prelude::*,
-    window::PresentMode,
+    render::settings::WgpuSettings,
+    window::{PresentMode, WindowMode},
+    winit::WinitPlugin,
 };
-use bevy_kira_audio::{Audio, AudioApp, AudioPlugin};
+use bevy_kira_audio::{Audio, AudioPlugin};
 use bevy_tweening::*;
 use heron::prelude::*;
+use std::time::Duration;
 
 #[cfg(debug_assertions)]
 use bevy_inspector_egui::WorldInspectorPlugin;
 
+mod backdrop;
+mod banner;
+mod bench;
+mod bonus;
+mod cli;
+mod collider;
+mod combo;
+mod continue_prompt;
+mod credits;
 mod debug;
+mod dialogue;
+mod difficulty;
 mod enemy;
+mod event_log;
+mod flow;
 mod game;
+mod gamepad;
+mod highscore;
+mod hud;
+mod letterbox;
+mod loading;
+mod logging;
 mod menu;
+mod music;
+mod navigation;
+mod obstacle;
+mod options;
+mod overdrive;
+mod pickup;
+mod pod;
+mod profile;
+mod rank;
+mod replay;
+mod replay_menu;
+mod rumble;
+mod save;
+mod screenshot;
+mod settings;
+mod sfx;
+mod shake;
+mod shield;
+mod ship;
+mod simulate;
+mod speedrun;
+mod splash;
+mod stats;
+mod timestep;
+mod touch;
+mod tuning;
+mod tutorial;
+mod vfx;
+mod weather;
+#[cfg(target_arch = "wasm32")]
+mod web;
 
+use backdrop::BackdropPlugin;
+use banner::BannerPlugin;
+use bench::BenchPlugin;
+use bonus::BonusPlugin;
+use cli::{LaunchConfig, LaunchOptions};
+use collider::ColliderPlugin;
+use combo::ComboPlugin;
+use continue_prompt::ContinuePlugin;
+use credits::CreditsPlugin;
 use debug::DebugPlugin;
+use dialogue::DialoguePlugin;
+use difficulty::DifficultyPlugin;
 use enemy::EnemyPlugin;
-use game::{Bullet, GamePlugin, Quad, SfxAudio};
+use event_log::EventLogPlugin;
+use flow::GameFlowPlugin;
+use game::{Bullet, EnemyBullet, GamePlugin, GameTime, Quad, RunSeed};
+use gamepad::GamepadPlugin;
+use highscore::HighScorePlugin;
+use hud::HudPlugin;
+use letterbox::LetterboxPlugin;
+use loading::LoadingPlugin;
+use logging::LoggingPlugin;
 use menu::MenuPlugin;
+use music::MusicPlugin;
+use navigation::NavigationPlugin;
+use obstacle::ObstaclePlugin;
+use options::OptionsPlugin;
+use overdrive::OverdrivePlugin;
+use pickup::PickupPlugin;
+use pod::PodPlugin;
+use profile::{Profile, ProfilePlugin};
+use rank::RankPlugin;
+use replay::{Replay, ReplayPlugin};
+use replay_menu::ReplayMenuPlugin;
+use rumble::RumblePlugin;
+use screenshot::ScreenshotPlugin;
+use settings::{FrameRateCap, PresentModeSetting, Settings, SettingsPlugin};
+use sfx::SfxPlugin;
+use shake::ShakePlugin;
+use shield::ShieldPlugin;
+use ship::ShipPlugin;
+use simulate::SimulatePlugin;
+use speedrun::SpeedrunPlugin;
+use splash::SplashPlugin;
+use stats::StatsPlugin;
+use timestep::{FixedTimestepPlugin, FixedUpdateStage};
+use touch::TouchPlugin;
+use tuning::TuningPlugin;
+use tutorial::TutorialPlugin;
+use vfx::{Particle, VfxPlugin};
+use weather::WeatherPlugin;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AppState {
     Boot,
+    Splash,
     Menu,
+    ShipSelect,
+    Options,
+    Replays,
+    HighScores,
+    Credits,
     InGame,
 }
 
@@ -36,53 +144,161 @@ pub enum Layer {
     PlayerBullet,
     Enemy,
     EnemyBullet,
+    Pickup,
+    /// Collection box of the player, larger than its hurt box.
+    PlayerCollect,
 }
 
+/// Entities hidden from the world inspector, spawned by the hundreds and too
+/// short-lived to inspect anyway.
+type InspectorFilter = (Without<Bullet>, Without<EnemyBullet>, Without<Particle>);
+
 fn main() {
+    let launch = LaunchOptions::from_env();
+    let mut settings = Settings::load();
+    if launch.bench {
+        // Measure the actual frame time, not the pacing. Not saved, unless changed in
+        // the options menu.
+        settings.video.frame_rate_cap = FrameRateCap::Uncapped;
+        settings.video.present_mode = PresentModeSetting::Immediate;
+    }
+    if launch.simulate.is_some() {
+        // Nothing to listen to in a simulation. Not saved either.
+        settings.audio.bgm_volume = 0;
+        settings.audio.sfx_volume = 0;
+    }
+
+    let config = LaunchConfig::new(&launch, &settings);
+
     let mut app = App::new();
-    app.insert_resource(WindowDescriptor {
-        title: "Super Kaizen Overloaded".to_string(),
-        // width: 1200.,
-        // height: 600.,
-        present_mode: PresentMode::Fifo, // vsync
-        ..Default::default()
-    })
-    .insert_resource(ClearColor(Color::rgba(0., 0., 0., 0.)))
-    .insert_resource(bevy_atmosphere::AtmosphereMat::default())
-    .add_plugins(DefaultPlugins)
-    //.add_plugin(LogDiagnosticsPlugin::default())
-    .add_plugin(FrameTimeDiagnosticsPlugin::default());
+    app.insert_resource(config.window())
+        .insert_resource(launch.log_settings(&settings))
+        .insert_resource(settings)
+        .insert_resource(ClearColor(Color::rgba(0., 0., 0., 0.)))
+        .insert_resource(bevy_atmosphere::AtmosphereMat::default())
+        // Replaces the Bevy log plugin, to capture messages for the in-game viewer
+        .add_plugin(LoggingPlugin);
+
+    if launch.headless {
+        // No window nor GPU device; the app loop runs at a fixed rate instead
+        app.insert_resource(WgpuSettings {
+            backends: None,
+            ..Default::default()
+        })
+        .add_plugins_with(DefaultPlugins, |group| {
+            group.disable::<LogPlugin>().disable::<WinitPlugin>()
+        })
+        .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
+            1. / 60.,
+        )))
+        .add_plugin(ScheduleRunnerPlugin);
+    } else {
+        app.add_plugins_with(DefaultPlugins, |group| group.disable::<LogPlugin>());
+    }
+
+    // Loaded after logging is set up, to report any error
+    app.insert_resource(Profile::load())
+        //.add_plugin(LogDiagnosticsPlugin::default())
+        .add_plugin(FrameTimeDiagnosticsPlugin::default());
 
     #[cfg(debug_assertions)]
-    app.add_plugin(DebugPlugin)
-        .add_plugin(WorldInspectorPlugin::new().filter::<Without<Bullet>>());
+    if !launch.headless {
+        app.add_plugin(DebugPlugin)
+            .add_plugin(WorldInspectorPlugin::new().filter::<InspectorFilter>());
+    }
 
-    app.add_plugin(TweeningPlugin)
+    app.add_plugin(FixedTimestepPlugin)
         .add_plugin(AudioPlugin)
-        .add_audio_channel::<SfxAudio>()
         .add_plugin(PhysicsPlugin::default());
 
     let initial_state = AppState::Boot;
     app.add_state(initial_state)
         .add_state_to_stage(CoreStage::First, initial_state) // BUG #1671
         .add_state_to_stage(CoreStage::PreUpdate, initial_state) // BUG #1671
+        .add_state_to_stage(FixedUpdateStage, initial_state) // BUG #1671
         .add_state_to_stage(CoreStage::PostUpdate, initial_state) // BUG #1671
         .add_state_to_stage(CoreStage::Last, initial_state); // BUG #1671
 
-    app.add_plugin(MenuPlugin)
+    app.add_plugin(SettingsPlugin)
+        .add_plugin(GameFlowPlugin)
+        .add_plugin(EventLogPlugin)
+        .add_plugin(LoadingPlugin)
+        .add_plugin(SplashPlugin)
+        .add_plugin(NavigationPlugin)
+        .add_plugin(GamepadPlugin)
+        .add_plugin(RumblePlugin)
+        .add_plugin(TouchPlugin)
+        .add_plugin(DifficultyPlugin)
+        .add_plugin(MenuPlugin)
+        .add_plugin(MusicPlugin)
+        .add_plugin(SfxPlugin)
+        .add_plugin(ShipPlugin)
+        .add_plugin(OptionsPlugin)
+        .add_plugin(ReplayMenuPlugin)
+        .add_plugin(HighScorePlugin)
+        .add_plugin(CreditsPlugin)
+        .add_plugin(ColliderPlugin)
         .add_plugin(GamePlugin)
-        .add_plugin(EnemyPlugin);
+        .add_plugin(ContinuePlugin)
+        .add_plugin(BackdropPlugin)
+        .add_plugin(WeatherPlugin)
+        .add_plugin(EnemyPlugin)
+        .add_plugin(BannerPlugin)
+        .add_plugin(BonusPlugin)
+        .add_plugin(HudPlugin)
+        .add_plugin(LetterboxPlugin)
+        .add_plugin(DialoguePlugin)
+        .add_plugin(TutorialPlugin)
+        .add_plugin(PickupPlugin)
+        .add_plugin(ObstaclePlugin)
+        .add_plugin(PodPlugin)
+        .add_plugin(ShieldPlugin)
+        .add_plugin(OverdrivePlugin)
+        .add_plugin(TuningPlugin)
+        .add_plugin(RankPlugin)
+        .add_plugin(ComboPlugin)
+        .add_plugin(SpeedrunPlugin)
+        .add_plugin(StatsPlugin)
+        .add_plugin(ProfilePlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(VfxPlugin)
+        .add_plugin(ShakePlugin)
+        .add_plugin(ScreenshotPlugin);
 
-    // Only enable MSAA on non-web platforms
+    if launch.bench {
+        app.add_plugin(BenchPlugin);
+    }
+    if launch.simulate.is_some() {
+        app.add_plugin(SimulatePlugin);
+    }
+
+    // Play back a replay with the seed it was recorded with, unless forced
+    let fixed_seed = launch.seed.or_else(|| {
+        let replay = Replay::load_file(launch.replay.as_deref()?);
+        if replay.is_none() {
+            error!(
+                "Failed to load replay '{}'",
+                launch.replay.as_ref().unwrap()
+            );
+        }
+        replay.map(|replay| replay.seed)
+    });
+    if let Some(max_delta) = launch.max_delta {
+        app.insert_resource(GameTime::new(max_delta));
+    }
+    app.insert_resource(RunSeed {
+        fixed: fixed_seed,
+        ..Default::default()
+    })
+    .insert_resource(launch);
+
+    // Only configure MSAA on non-web platforms
     #[cfg(not(target_arch = "wasm32"))]
-    app.insert_resource(Msaa { samples: 4 });
+    app.insert_resource(config.msaa());
 
-    app.add_system_set(SystemSet::on_update(AppState::Boot).with_system(boot));
+    // On the web, track the browser window size and pixel density
+    #[cfg(target_arch = "wasm32")]
+    app.add_plugin(web::WebPlugin);
 
     app.run();
 }
-
-fn boot(mut state: ResMut<State<AppState>>) {
-    // workaround for on_enter() not working on initial state; use a dummy initial state instead
-    state.set(AppState::Menu).unwrap();
-}
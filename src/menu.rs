@@ -1,88 +1,222 @@
 use bevy::{
     app::{AppExit, CoreStage},
     asset::AssetStage,
-    input::gamepad::GamepadButtonType,
     prelude::*,
+    window::WindowMode,
 };
-use bevy_kira_audio::{
-    Audio as KiraAudio, AudioChannel as KiraAudioChannel, AudioPlugin as KiraAudioPlugin,
-    AudioSource as KiraAudioSource, InstanceHandle,
-};
+use bevy_kira_audio::AudioSource as KiraAudioSource;
 use bevy_tweening::{lens::*, *};
 use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
 use std::time::Duration;
 
 pub struct MenuPlugin;
 
-use crate::{AppState, SfxAudio};
+use crate::{
+    cli::LaunchOptions,
+    difficulty::Difficulty,
+    flow::RequestStateChange,
+    game::RunSeed,
+    music::AudioFacade,
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    profile::Profile,
+    AppState,
+};
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugin(InputManagerPlugin::<MenuAction>::default())
-            .add_plugin(KiraAudioPlugin)
-            .init_resource::<AudioManager>()
-            .add_system_set(
-                SystemSet::on_enter(AppState::Menu)
-                    .with_system(menu_setup)
-                    .with_system(start_background_audio),
-            )
+        app.insert_resource(MenuLayout::load())
+            .add_system_set(SystemSet::on_enter(AppState::Menu).with_system(menu_setup))
             .add_system_set(SystemSet::on_update(AppState::Menu).with_system(menu_run))
             .add_system_set(SystemSet::on_exit(AppState::Menu).with_system(menu_cleanup));
     }
 }
 
-#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
-enum MenuAction {
-    SelectNext,
-    SelectPrev,
-    ClickButton,
+/// Action triggered by a button of the main menu.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum MenuEntry {
+    #[serde(alias = "new_game")]
+    NewGame,
+    /// New game seeded with the date, the same for all players on a given day.
+    #[serde(alias = "daily_run")]
+    DailyRun,
+    /// Cycle through the difficulties. The button shows the current difficulty
+    /// instead of its label.
+    #[serde(alias = "difficulty")]
+    Difficulty,
+    /// Cycle through the stages unlocked in the profile to start the game at. The
+    /// button shows the current stage instead of its label.
+    #[serde(alias = "stage")]
+    Stage,
+    #[serde(alias = "options")]
+    Options,
+    #[serde(alias = "replays")]
+    Replays,
+    #[serde(alias = "high_scores")]
+    HighScores,
+    #[serde(alias = "credits")]
+    Credits,
+    #[serde(alias = "quit")]
+    Quit,
+    /// Toggle the fullscreen mode. Replaces [`MenuEntry::Quit`] on the web, where
+    /// there's nothing to quit to.
+    #[serde(alias = "fullscreen")]
+    Fullscreen,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ButtonDescriptor {
+    entry: MenuEntry,
+    label: String,
+}
+
+/// Layout of the main menu, loaded from `assets/menu_layout.json`.
+#[derive(Debug, Clone, Deserialize)]
+struct MenuLayout {
+    /// Path of the title image asset.
+    title_image: String,
+    /// Size of the title image, in pixels.
+    title_size: Vec2,
+    /// Path of the font asset of the button labels.
+    font: String,
+    font_size: f32,
+    /// Size of each button, in pixels.
+    button_size: Vec2,
+    /// Margin around each button, in pixels.
+    button_margin: f32,
+    button_color: [u8; 3],
+    /// Color of the button which has the focus.
+    button_focus_color: [u8; 3],
+    text_color: [u8; 3],
+    /// Scale of the selected button.
+    selected_scale: f32,
+    /// Duration of the scale animation when the selection changes, in seconds.
+    select_duration: f32,
+    /// Duration of the appear animation of each button, in seconds.
+    appear_duration: f32,
+    /// Delay between the appear animations of two consecutive buttons, in seconds.
+    appear_delay: f32,
+    /// Buttons of the menu, in display order.
+    buttons: Vec<ButtonDescriptor>,
+}
+
+/// Copy of the menu layout embedded in the executable, used on the web, and if the
+/// layout file of the assets can't be parsed.
+const EMBEDDED_MENU_LAYOUT: &str = include_str!("../assets/menu_layout.json");
+
+impl MenuLayout {
+    /// Parse the layout from the assets, read at runtime so that it can be tweaked
+    /// without rebuilding, or from the embedded copy if the file can't be read.
+    fn parse() -> serde_json::Result<MenuLayout> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = bevy::asset::FileAssetIo::get_root_path()
+                .join("assets")
+                .join("menu_layout.json");
+            if let Ok(json) = std::fs::read_to_string(path) {
+                return serde_json::from_str(&json);
+            }
+        }
+        serde_json::from_str(EMBEDDED_MENU_LAYOUT)
+    }
+
+    fn load() -> MenuLayout {
+        // Errors are reported during boot, see menu_layout_error()
+        let layout = MenuLayout::parse()
+            .or_else(|_| serde_json::from_str(EMBEDDED_MENU_LAYOUT))
+            .unwrap_or_else(|_| MenuLayout::fallback());
+        // On the web, exiting the app just freezes the canvas
+        #[cfg(target_arch = "wasm32")]
+        let layout = {
+            let mut layout = layout;
+            for button in layout.buttons.iter_mut() {
+                if button.entry == MenuEntry::Quit {
+                    button.entry = MenuEntry::Fullscreen;
+                    button.label = "Fullscreen".to_string();
+                }
+            }
+            layout
+        };
+        layout
+    }
+
+    /// Bare layout used if even the embedded copy fails to parse, so that the game
+    /// can still be started and quit.
+    fn fallback() -> MenuLayout {
+        let button = |entry, label: &str| ButtonDescriptor {
+            entry,
+            label: label.to_string(),
+        };
+        MenuLayout {
+            title_image: "title.png".to_string(),
+            title_size: Vec2::new(800., 380.),
+            font: "fonts/FiraMono-Regular.ttf".to_string(),
+            font_size: 48.,
+            button_size: Vec2::new(300., 80.),
+            button_margin: 8.,
+            button_color: [57, 194, 190],
+            button_focus_color: [112, 224, 220],
+            text_color: [32, 32, 32],
+            selected_scale: 1.1,
+            select_duration: 0.4,
+            appear_duration: 1.2,
+            appear_delay: 0.2,
+            buttons: vec![
+                button(MenuEntry::NewGame, "New Game"),
+                button(MenuEntry::Options, "Options"),
+                button(MenuEntry::Quit, "Quit"),
+            ],
+        }
+    }
+}
+
+/// Error parsing the menu layout, if any, to report it during boot. The game still
+/// runs with the embedded layout, or a bare one, in that case.
+pub fn menu_layout_error() -> Option<String> {
+    MenuLayout::parse().err().map(|err| err.to_string())
 }
 
 #[derive(Component, Default)]
 struct Menu {
-    selected_index: i32,
     sound_click: Handle<KiraAudioSource>,
 }
 
 #[derive(Component, Default)]
 struct Button(pub i32);
 
-pub struct AudioManager {
-    pub menu_bgm: Handle<KiraAudioSource>,
-    pub menu_instance: Option<InstanceHandle>,
-    pub game_bgm: Handle<KiraAudioSource>,
-    pub game_instance: Option<InstanceHandle>,
-}
-
-impl Default for AudioManager {
-    fn default() -> Self {
-        AudioManager {
-            menu_bgm: Handle::default(),
-            menu_instance: None,
-            game_bgm: Handle::default(),
-            game_instance: None,
-        }
-    }
-}
+/// Text of a button showing a value instead of its label, like the difficulty.
+#[derive(Component)]
+struct ValueText(MenuEntry);
 
 fn menu_run(
-    mut q_menu: Query<(&mut Menu, &ActionState<MenuAction>)>,
+    layout: Res<MenuLayout>,
+    mut q_menu: Query<(&Menu, &mut NavMenu, &ActionState<NavAction>)>,
     mut q_animators: Query<(&Button, &mut Animator<Transform>)>,
     q_buttons: Query<(&Button, &Node, &GlobalTransform)>,
     mut exit: EventWriter<AppExit>,
-    audio: Res<KiraAudio>,
-    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
-    mut app_state: ResMut<State<AppState>>,
+    audio_facade: AudioFacade,
+    mut state_requests: EventWriter<RequestStateChange>,
     mut cursor_moved_events: EventReader<CursorMoved>,
     mouse_button_input: Res<Input<MouseButton>>,
+    mut windows: ResMut<Windows>,
+    mut difficulty: ResMut<Difficulty>,
+    mut q_value_texts: Query<(&mut Text, &ValueText)>,
+    mut run_seed: ResMut<RunSeed>,
+    mut launch: ResMut<LaunchOptions>,
+    profile: Res<Profile>,
 ) {
-    let (mut menu, action_state) = q_menu.single_mut();
-    let prev_sel = menu.selected_index;
-    if action_state.just_pressed(MenuAction::SelectNext) {
-        menu.selected_index = (menu.selected_index + 1).min(1);
-    }
-    if action_state.just_pressed(MenuAction::SelectPrev) {
-        menu.selected_index = (menu.selected_index - 1).max(0);
+    let (menu, mut nav, action_state) = q_menu.single_mut();
+    let prev_sel = nav.focused as i32;
+    nav.navigate(action_state);
+    // Back moves the focus to the Quit button, so that pressing Back twice quits
+    if action_state.just_pressed(NavAction::Back) {
+        if let Some(index) = layout
+            .buttons
+            .iter()
+            .position(|button| button.entry == MenuEntry::Quit)
+        {
+            nav.focus(index);
+        }
     }
     for ev in cursor_moved_events.iter() {
         for (button, node, transform) in q_buttons.iter() {
@@ -91,34 +225,37 @@ fn menu_run(
             if (origin.x - ev.position.x).abs() < half_size.x
                 && (origin.y - ev.position.y).abs() < half_size.y
             {
-                menu.selected_index = button.0;
+                nav.focus(button.0 as usize);
             }
         }
     }
 
-    if prev_sel != menu.selected_index {
-        sfx_audio.play(menu.sound_click.clone());
+    let selected_index = nav.focused as i32;
+    if prev_sel != selected_index {
+        audio_facade.play_ui(menu.sound_click.clone());
+        let selected_scale = Vec3::splat(layout.selected_scale);
+        let select_duration = Duration::from_secs_f32(layout.select_duration);
         for (button, mut animator) in q_animators.iter_mut() {
             if button.0 == prev_sel {
                 let tween_out = Tween::new(
                     EaseFunction::QuadraticInOut,
                     TweeningType::Once,
-                    Duration::from_secs_f32(0.4),
+                    select_duration,
                     TransformScaleLens {
-                        start: Vec3::new(1.1, 1.1, 1.1),
+                        start: selected_scale,
                         end: Vec3::ONE,
                     },
                 );
                 animator.set_tweenable(tween_out);
                 animator.state = AnimatorState::Playing;
-            } else if button.0 == menu.selected_index {
+            } else if button.0 == selected_index {
                 let tween_in = Tween::new(
                     EaseFunction::QuadraticInOut,
                     TweeningType::Once,
-                    Duration::from_secs_f32(0.4),
+                    select_duration,
                     TransformScaleLens {
                         start: Vec3::ONE,
-                        end: Vec3::new(1.1, 1.1, 1.1),
+                        end: selected_scale,
                     },
                 );
                 animator.set_tweenable(tween_in);
@@ -127,38 +264,122 @@ fn menu_run(
         }
     }
 
-    if action_state.just_pressed(MenuAction::ClickButton) {
-        match menu.selected_index {
-            0 => app_state.set(AppState::InGame).unwrap(),
-            1 => exit.send(AppExit),
-            _ => unreachable!(),
+    // Left and right also change the difficulty or stage, backward and forward
+    let entry = layout.buttons[nav.focused].entry;
+    let mut delta = 0;
+    if action_state.just_pressed(NavAction::Increase) {
+        delta += 1;
+    }
+    if action_state.just_pressed(NavAction::Decrease) {
+        delta -= 1;
+    }
+    if action_state.just_pressed(NavAction::Confirm) {
+        delta = 1;
+    }
+    if delta != 0 && matches!(entry, MenuEntry::Difficulty | MenuEntry::Stage) {
+        if entry == MenuEntry::Difficulty {
+            difficulty.cycle(delta);
+            debug!(target: "menu", "Difficulty: {}", difficulty.label());
+        } else {
+            // Stages are numbered from 1, the first one being the default
+            let count = profile.stages_unlocked() as i32;
+            let stage = (launch.start_stage() as i32 + delta).rem_euclid(count) + 1;
+            launch.stage = (stage > 1).then_some(stage as usize);
+            debug!(target: "menu", "Start stage: {}", stage);
+        }
+        for (mut text, value_text) in q_value_texts.iter_mut() {
+            if let Some(value) = value_text_of(value_text.0, *difficulty, &*launch) {
+                text.sections[0].value = value;
+            }
+        }
+        audio_facade.play_ui(menu.sound_click.clone());
+    }
+
+    if action_state.just_pressed(NavAction::Confirm) {
+        match layout.buttons[nav.focused].entry {
+            MenuEntry::NewGame => {
+                run_seed.daily = false;
+                state_requests.send(RequestStateChange::fade_to(AppState::ShipSelect));
+            }
+            MenuEntry::DailyRun => {
+                // Same run for all players, from the start
+                run_seed.daily = true;
+                launch.stage = None;
+                state_requests.send(RequestStateChange::fade_to(AppState::ShipSelect));
+            }
+            // Changed above
+            MenuEntry::Difficulty | MenuEntry::Stage => (),
+            MenuEntry::Options => {
+                state_requests.send(RequestStateChange::fade_to(AppState::Options))
+            }
+            MenuEntry::Replays => {
+                state_requests.send(RequestStateChange::fade_to(AppState::Replays))
+            }
+            MenuEntry::HighScores => {
+                state_requests.send(RequestStateChange::fade_to(AppState::HighScores))
+            }
+            MenuEntry::Credits => {
+                state_requests.send(RequestStateChange::fade_to(AppState::Credits))
+            }
+            MenuEntry::Quit => exit.send(AppExit),
+            MenuEntry::Fullscreen => {
+                if let Some(window) = windows.get_primary_mut() {
+                    window.set_mode(if window.mode() == WindowMode::Windowed {
+                        WindowMode::BorderlessFullscreen
+                    } else {
+                        WindowMode::Windowed
+                    });
+                }
+            }
         }
     }
 }
 
-fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    println!("menu_setup");
-    commands.spawn_bundle(UiCameraBundle::default());
+/// Text of the buttons showing a value, with arrows hinting that it can be changed, or
+/// `None` for the buttons showing their label.
+fn value_text_of(
+    entry: MenuEntry,
+    difficulty: Difficulty,
+    launch: &LaunchOptions,
+) -> Option<String> {
+    match entry {
+        MenuEntry::Difficulty => Some(format!("< {} >", difficulty.label())),
+        MenuEntry::Stage => Some(format!("< Stage {} >", launch.start_stage() + 1)),
+        _ => None,
+    }
+}
 
-    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+fn menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    layout: Res<MenuLayout>,
+    difficulty: Res<Difficulty>,
+    mut launch: ResMut<LaunchOptions>,
+    profile: Res<Profile>,
+) {
+    debug!(target: "setup", "menu_setup");
 
-    let title_image = asset_server.load("title.png");
+    // The stage may have been locked again by a progress reset
+    if launch.start_stage() >= profile.stages_unlocked() {
+        launch.stage = None;
+    }
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(MenuCamera);
+
+    let font = asset_server.load(&layout.font);
+
+    let title_image = asset_server.load(&layout.title_image);
 
     let mut menu = Menu::default();
     menu.sound_click = asset_server.load("sounds/click4.ogg");
 
-    let mut input_map = InputMap::default();
-    input_map.insert(MenuAction::SelectNext, KeyCode::Down);
-    input_map.insert(MenuAction::SelectNext, KeyCode::S);
-    input_map.insert(MenuAction::SelectNext, GamepadButtonType::DPadDown);
-    input_map.insert(MenuAction::SelectPrev, KeyCode::Up);
-    input_map.insert(MenuAction::SelectPrev, KeyCode::W);
-    input_map.insert(MenuAction::SelectPrev, GamepadButtonType::DPadUp);
-    input_map.insert(MenuAction::ClickButton, KeyCode::Return);
-    input_map.insert(MenuAction::ClickButton, KeyCode::Space);
-    input_map.insert(MenuAction::ClickButton, GamepadButtonType::South);
+    let mut nav_bundle = NavMenuBundle::new(layout.buttons.len());
     #[cfg(not(debug_assertions))] // only in release, otherwise annoying with egui inspector
-    input_map.insert(MenuAction::ClickButton, MouseButton::Left);
+    nav_bundle
+        .input
+        .input_map
+        .insert(NavAction::Confirm, MouseButton::Left);
 
     let container = commands
         .spawn_bundle(NodeBundle {
@@ -179,21 +400,19 @@ fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         })
         .insert(Name::new("menu"))
         .insert(menu)
-        .insert_bundle(InputManagerBundle::<MenuAction> {
-            action_state: ActionState::default(),
-            input_map,
-        })
+        .insert_bundle(nav_bundle)
         .id();
 
     // Title
+    let title_size = Size::new(Val::Px(layout.title_size.x), Val::Px(layout.title_size.y));
     commands
         .spawn_bundle(NodeBundle {
             node: Node {
-                size: Vec2::new(800., 380.),
+                size: layout.title_size,
             },
             style: Style {
-                size: Size::new(Val::Px(800.), Val::Px(380.)),
-                min_size: Size::new(Val::Px(800.), Val::Px(380.)),
+                size: title_size,
+                min_size: title_size,
                 margin: Rect::all(Val::Px(0.)),
                 padding: Rect::all(Val::Px(0.)),
                 align_content: AlignContent::Center,
@@ -208,21 +427,24 @@ fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(Name::new("title"))
         .insert(Parent(container));
 
-    const DURATION_SEC: f32 = 1.2;
-    const DELAY_MS: u64 = 200;
-
-    let mut start_time_ms = 0;
-    for (index, text) in ["New Game", "Quit"].iter().enumerate() {
-        let delay = Delay::new(Duration::from_millis(start_time_ms));
-        start_time_ms += DELAY_MS;
+    let button_color = layout.button_color;
+    let button_focus_color = layout.button_focus_color;
+    let text_color = layout.text_color;
+    let mut start_time = 0.;
+    for (index, button) in layout.buttons.iter().enumerate() {
+        let text = &button.label;
+        let value_text = value_text_of(button.entry, *difficulty, &*launch);
+        let entry = button.entry;
+        let delay = Delay::new(Duration::from_secs_f32(start_time));
+        start_time += layout.appear_delay;
         let tween_scale = Tween::new(
             EaseFunction::BounceOut,
             TweeningType::Once,
-            Duration::from_secs_f32(DURATION_SEC),
+            Duration::from_secs_f32(layout.appear_duration),
             TransformScaleLens {
                 start: Vec3::ZERO,
                 end: if index == 0 {
-                    Vec3::new(1.1, 1.1, 1.1)
+                    Vec3::splat(layout.selected_scale)
                 } else {
                     Vec3::ONE
                 },
@@ -232,11 +454,14 @@ fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         commands
             .spawn_bundle(NodeBundle {
                 node: Node {
-                    size: Vec2::new(300., 80.),
+                    size: layout.button_size,
                 },
                 style: Style {
-                    min_size: Size::new(Val::Px(300.), Val::Px(80.)),
-                    margin: Rect::all(Val::Px(8.)),
+                    min_size: Size::new(
+                        Val::Px(layout.button_size.x),
+                        Val::Px(layout.button_size.y),
+                    ),
+                    margin: Rect::all(Val::Px(layout.button_margin)),
                     padding: Rect::all(Val::Px(8.)),
                     align_content: AlignContent::Center,
                     align_items: AlignItems::Center,
@@ -244,22 +469,36 @@ fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     justify_content: JustifyContent::Center,
                     ..Default::default()
                 },
-                color: UiColor(Color::rgb_u8(57, 194, 190)),
+                color: UiColor(Color::rgb_u8(
+                    button_color[0],
+                    button_color[1],
+                    button_color[2],
+                )),
                 transform: Transform::from_scale(Vec3::splat(0.01)),
                 ..Default::default()
             })
             .insert(Name::new(format!("button:{}", text)))
             .insert(Button(index as i32))
+            .insert(FocusHighlight {
+                menu: container,
+                index,
+                color: Color::rgb_u8(button_color[0], button_color[1], button_color[2]),
+                focus_color: Color::rgb_u8(
+                    button_focus_color[0],
+                    button_focus_color[1],
+                    button_focus_color[2],
+                ),
+            })
             .insert(Parent(container))
             .insert(Animator::new(seq))
             .with_children(|parent| {
-                parent.spawn_bundle(TextBundle {
+                let mut text_commands = parent.spawn_bundle(TextBundle {
                     text: Text::with_section(
-                        text.to_string(),
+                        value_text.clone().unwrap_or_else(|| text.clone()),
                         TextStyle {
                             font: font.clone(),
-                            font_size: 48.0,
-                            color: Color::rgb_u8(32, 32, 32),
+                            font_size: layout.font_size,
+                            color: Color::rgb_u8(text_color[0], text_color[1], text_color[2]),
                         },
                         TextAlignment {
                             vertical: VerticalAlign::Center,
@@ -268,22 +507,21 @@ fn menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     ),
                     ..Default::default()
                 });
+                if value_text.is_some() {
+                    text_commands.insert(ValueText(entry));
+                }
             });
     }
 }
 
-fn menu_cleanup(mut commands: Commands, query: Query<Entity, With<Menu>>) {
-    commands.entity(query.single()).despawn_recursive();
-}
+#[derive(Component)]
+struct MenuCamera;
 
-fn start_background_audio(
-    asset_server: Res<AssetServer>,
-    audio: Res<KiraAudio>,
-    mut audio_manager: ResMut<AudioManager>,
+fn menu_cleanup(
+    mut commands: Commands,
+    query: Query<Entity, With<Menu>>,
+    q_camera: Query<Entity, With<MenuCamera>>,
 ) {
-    //if config.sound.enabled {
-    audio_manager.menu_bgm = asset_server.load("bgm/436507__doctor-dreamchip__2018-08-02.ogg");
-    audio.set_volume(1.); //config.sound.volume);
-    audio_manager.menu_instance = Some(audio.play_looped(audio_manager.menu_bgm.clone()));
-    //}
+    commands.entity(query.single()).despawn_recursive();
+    commands.entity(q_camera.single()).despawn_recursive();
 }
@@ -0,0 +1,825 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_kira_audio::{
+    AudioApp, AudioChannel as KiraAudioChannel, AudioSource as KiraAudioSource, InstanceHandle,
+};
+use kira::LoopBehavior;
+use serde::{de::Error as _, Deserialize};
+use std::marker::PhantomData;
+
+use crate::{
+    enemy::EnemyController,
+    game::{BombEvent, GameTime, StageStartEvent},
+    settings::Settings,
+    vfx::ExplosionEvent,
+    AppState,
+};
+
+/// Background music, crossfading between the menu, game and boss themes depending on
+/// the app state, and ducking under the loudest sound effects. The game theme follows
+/// the playlist of the stages, and its playback position drives the music clock the
+/// timeline syncs to. Also applies the
+/// volume settings and the mute hotkey to all audio channels, and stops, pauses and
+/// resumes them as the game ends, pauses and resumes.
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<MenuBgmAudio>()
+            .add_audio_channel::<GameBgmAudio>()
+            .add_audio_channel::<BossBgmAudio>()
+            .add_audio_channel::<SfxAudio>()
+            .add_audio_channel::<UiAudio>()
+            .add_audio_channel::<VoiceAudio>()
+            .init_resource::<AudioManager>()
+            .init_resource::<BgmPlaylist>()
+            .add_startup_system(music_setup)
+            .add_system(apply_loop_points)
+            .add_system(apply_audio_settings.before(update_music))
+            .add_system(toggle_mute.before(update_music))
+            .add_system(pause_audio_with_game)
+            .add_system(update_music)
+            .add_system(update_music_clock.after(update_music))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(swap_stage_track.before(update_music)),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(stop_game_audio))
+            .add_system_to_stage(CoreStage::PostUpdate, apply_channel_commands)
+            .add_system_to_stage(CoreStage::Last, clear_channel_commands);
+    }
+}
+
+/// Key toggling the mute of all audio, anywhere in the game.
+const MUTE_KEY: KeyCode = KeyCode::M;
+
+/// Volume of the [`SfxAudio`], [`UiAudio`] and [`VoiceAudio`] channels.
+const SFX_CHANNEL_VOLUME: f32 = 0.5;
+
+/// Duration of the crossfade between two tracks, in seconds.
+const FADE_DURATION: f32 = 1.5;
+
+/// Volume factor of the music while ducked.
+const DUCK_VOLUME: f32 = 0.4;
+
+/// Time the music stays ducked after a bomb or an explosion, in seconds.
+const DUCK_DURATION: f32 = 0.6;
+
+/// Speed at which the music ducks and recovers, in volume factor per second.
+const DUCK_SPEED: f32 = 4.;
+
+/// Playback rate of the boss theme. The boss theme reuses the game track sped up,
+/// until it gets its own track.
+const BOSS_PLAYBACK_RATE: f32 = 1.12;
+
+#[derive(Default)]
+struct MenuBgmAudio;
+
+#[derive(Default)]
+struct GameBgmAudio;
+
+#[derive(Default)]
+struct BossBgmAudio;
+
+/// Channel of the gameplay sound effects played outside of the [`Sfx`] voices, like
+/// the lifebar fill sound.
+///
+/// [`Sfx`]: crate::sfx::Sfx
+#[derive(Default)]
+pub(crate) struct SfxAudio;
+
+/// Channel of the menu sounds.
+#[derive(Default)]
+pub(crate) struct UiAudio;
+
+/// Channel of the dialogue sounds.
+#[derive(Default)]
+pub(crate) struct VoiceAudio;
+
+/// Kind of audio channel. All the channels of a kind are stopped, paused and resumed
+/// together, see [`AudioManager::stop()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioChannelKind {
+    /// Background music tracks.
+    Bgm,
+    /// Gameplay sound effects, including the [`Sfx`](crate::sfx::Sfx) voices.
+    Sfx,
+    /// Menu sounds.
+    Ui,
+    /// Dialogue sounds.
+    Voice,
+}
+
+impl AudioChannelKind {
+    const ALL: [AudioChannelKind; 4] = [
+        AudioChannelKind::Bgm,
+        AudioChannelKind::Sfx,
+        AudioChannelKind::Ui,
+        AudioChannelKind::Voice,
+    ];
+}
+
+/// Command applied to all the audio channels of a kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelCommand {
+    /// Stop all the sounds of the channels.
+    Stop,
+    /// Pause all the sounds of the channels.
+    Pause,
+    /// Resume all the sounds paused.
+    Resume,
+}
+
+/// Tempo of a background music track, to convert musical times into seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeatGrid {
+    /// Tempo, in beats per minute.
+    pub bpm: f32,
+    /// Number of beats per bar.
+    pub beats_per_bar: u32,
+}
+
+impl Default for BeatGrid {
+    fn default() -> Self {
+        BeatGrid {
+            bpm: 120.,
+            beats_per_bar: 4,
+        }
+    }
+}
+
+impl BeatGrid {
+    /// Duration of the given number of beats, in seconds.
+    pub fn beats_to_seconds(&self, beats: f64) -> f64 {
+        beats * 60. / self.bpm.max(1.) as f64
+    }
+
+    /// Duration of the given number of bars, in seconds.
+    pub fn bars_to_seconds(&self, bars: f64) -> f64 {
+        self.beats_to_seconds(bars * self.beats_per_bar as f64)
+    }
+}
+
+/// Position in the music of the current stage, see [`AudioManager::music_clock()`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MusicClock {
+    /// Beats played since the end of the intro of the track, negative during the intro,
+    /// and counting on across the loops of the track.
+    pub beat: f64,
+    /// Tempo of the track.
+    pub grid: BeatGrid,
+}
+
+/// Background music track of the playlist, with the metadata to sync the gameplay to it.
+#[derive(Debug, Clone, Deserialize)]
+struct BgmTrack {
+    name: String,
+    /// Path of the audio file.
+    path: String,
+    /// Tempo, in beats per minute.
+    bpm: f32,
+    /// Number of beats per bar.
+    #[serde(default = "default_beats_per_bar")]
+    beats_per_bar: u32,
+    /// Length of the intro before the first bar, in seconds. The beats are counted from
+    /// the end of the intro.
+    #[serde(default)]
+    intro: f64,
+    /// Position the track loops back to once it reaches its end, in seconds.
+    #[serde(default)]
+    loop_start: f64,
+}
+
+fn default_beats_per_bar() -> u32 {
+    4
+}
+
+impl BgmTrack {
+    fn grid(&self) -> BeatGrid {
+        BeatGrid {
+            bpm: self.bpm,
+            beats_per_bar: self.beats_per_bar,
+        }
+    }
+}
+
+/// Background music tracks, loaded from `assets/bgm_db.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct BgmDatabase {
+    tracks: Vec<BgmTrack>,
+    /// Name of the track of the menus.
+    menu: String,
+    /// Name of the track of each stage, indexed by stage. Stages past the end use the
+    /// last track.
+    stages: Vec<String>,
+}
+
+impl BgmDatabase {
+    fn parse() -> serde_json::Result<BgmDatabase> {
+        let database: BgmDatabase = serde_json::from_str(include_str!("../assets/bgm_db.json"))?;
+        if database.stages.is_empty() {
+            return Err(serde_json::Error::custom("no stage track"));
+        }
+        if let Some(name) = std::iter::once(&database.menu)
+            .chain(&database.stages)
+            .find(|name| database.track_index(name).is_none())
+        {
+            return Err(serde_json::Error::custom(format!(
+                "unknown track '{}'",
+                name
+            )));
+        }
+        Ok(database)
+    }
+
+    fn track_index(&self, name: &str) -> Option<usize> {
+        self.tracks.iter().position(|track| track.name == name)
+    }
+}
+
+/// Error parsing the music database, if any, to report it during boot. The game still
+/// runs without music in that case.
+pub fn bgm_database_error() -> Option<String> {
+    BgmDatabase::parse().err().map(|err| err.to_string())
+}
+
+/// Playlist of the background music of the menus and of each stage.
+pub struct BgmPlaylist {
+    database: BgmDatabase,
+    /// Handle of each track, indexed like the tracks of the database.
+    handles: Vec<Handle<KiraAudioSource>>,
+    /// Tracks whose loop point still needs to be applied once loaded.
+    pending_loop_points: Vec<usize>,
+}
+
+impl Default for BgmPlaylist {
+    fn default() -> Self {
+        BgmPlaylist {
+            database: BgmDatabase::parse().unwrap_or_default(),
+            handles: vec![],
+            pending_loop_points: vec![],
+        }
+    }
+}
+
+impl BgmPlaylist {
+    /// Index of the track of the menus, if any.
+    fn menu_track(&self) -> Option<usize> {
+        self.database.track_index(&self.database.menu)
+    }
+
+    /// Index of the track of the given stage, if any.
+    fn stage_track(&self, stage: usize) -> Option<usize> {
+        let stages = &self.database.stages;
+        stages
+            .get(stage)
+            .or_else(|| stages.last())
+            .and_then(|name| self.database.track_index(name))
+    }
+
+    /// Tempo of the track of the given stage.
+    pub fn stage_grid(&self, stage: usize) -> BeatGrid {
+        self.stage_track(stage)
+            .map_or_else(BeatGrid::default, |index| {
+                self.database.tracks[index].grid()
+            })
+    }
+
+    fn handle(&self, index: Option<usize>) -> Handle<KiraAudioSource> {
+        index
+            .and_then(|index| self.handles.get(index).cloned())
+            .unwrap_or_default()
+    }
+}
+
+/// Playback time of a track, read from its instance. See [`AudioManager::music_clock()`].
+#[derive(Default)]
+struct TrackClock {
+    /// Instance the time is read from.
+    instance: Option<InstanceHandle>,
+    /// Playback position last read from the instance, in seconds.
+    position: Option<f64>,
+    /// Time played since the instance started, counting the loops, in seconds.
+    time: Option<f64>,
+}
+
+/// Background music track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MusicTrack {
+    Menu,
+    Game,
+    Boss,
+}
+
+impl MusicTrack {
+    /// Track to play in the given app state, if any.
+    fn for_state(state: AppState, is_boss_alive: bool) -> Option<MusicTrack> {
+        match state {
+            AppState::Boot | AppState::Splash => None,
+            AppState::InGame if is_boss_alive => Some(MusicTrack::Boss),
+            AppState::InGame => Some(MusicTrack::Game),
+            _ => Some(MusicTrack::Menu),
+        }
+    }
+}
+
+/// State of the background music. Each track plays on its own channel, whose volume
+/// fades toward 1 for the current track and toward 0 for the others. A track starts
+/// over once it faded out completely.
+pub struct AudioManager {
+    pub menu_bgm: Handle<KiraAudioSource>,
+    pub game_bgm: Handle<KiraAudioSource>,
+    pub boss_bgm: Handle<KiraAudioSource>,
+    /// Track fading in or playing, if any.
+    pub current: Option<MusicTrack>,
+    /// Fade volume of each track, in `[0:1]`, indexed by track.
+    volumes: [f32; 3],
+    /// Is each track playing?
+    playing: [bool; 3],
+    /// Instance of each track playing, indexed by track.
+    instances: [Option<InstanceHandle>; 3],
+    /// Playlist track of the current stage, played by the game theme.
+    game_track: Option<BgmTrack>,
+    /// Restart the game and boss themes, to play a new stage track.
+    restart_game_track: bool,
+    /// Playback time of the game theme.
+    game_clock: TrackClock,
+    /// Channel volume last applied to each track.
+    applied_volumes: [f32; 3],
+    /// Volume factor of all tracks, lowered while ducking.
+    duck: f32,
+    /// Remaining time to keep the music ducked, in seconds.
+    duck_time: f32,
+    /// Volume of the music, in `[0:1]`, from the settings.
+    pub bgm_volume: f32,
+    /// Volume of the sound effects, in `[0:1]`, from the settings.
+    pub sfx_volume: f32,
+    /// Mute all audio. Toggled with [`MUTE_KEY`], and not saved.
+    pub muted: bool,
+    /// Volume last applied to the [`SfxAudio`], [`UiAudio`] and [`VoiceAudio`] channels.
+    applied_sfx_volume: f32,
+    /// Commands issued this frame to the channels, in order, applied at the end of the
+    /// frame.
+    channel_commands: Vec<(AudioChannelKind, ChannelCommand)>,
+}
+
+impl Default for AudioManager {
+    fn default() -> Self {
+        AudioManager {
+            menu_bgm: Handle::default(),
+            game_bgm: Handle::default(),
+            boss_bgm: Handle::default(),
+            current: None,
+            volumes: [0.; 3],
+            playing: [false; 3],
+            instances: [None, None, None],
+            game_track: None,
+            restart_game_track: false,
+            game_clock: TrackClock::default(),
+            applied_volumes: [-1.; 3],
+            duck: 1.,
+            duck_time: 0.,
+            bgm_volume: 1.,
+            sfx_volume: 1.,
+            muted: false,
+            applied_sfx_volume: -1.,
+            channel_commands: vec![],
+        }
+    }
+}
+
+impl AudioManager {
+    /// Duck the music for a short while, under a loud sound effect.
+    pub fn duck(&mut self) {
+        self.duck_time = DUCK_DURATION;
+    }
+
+    /// Volume factor of the music, taking into account the mute.
+    pub fn bgm_gain(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.bgm_volume
+        }
+    }
+
+    /// Volume factor of the sound effects, taking into account the mute.
+    pub fn sfx_gain(&self) -> f32 {
+        if self.muted {
+            0.
+        } else {
+            self.sfx_volume
+        }
+    }
+
+    /// Stop all the sounds of the channels of the given kind. Stopped music tracks
+    /// fade in again from silence.
+    pub fn stop(&mut self, kind: AudioChannelKind) {
+        self.channel_commands.push((kind, ChannelCommand::Stop));
+    }
+
+    /// Pause all the sounds of the channels of the given kind.
+    pub fn pause(&mut self, kind: AudioChannelKind) {
+        self.channel_commands.push((kind, ChannelCommand::Pause));
+    }
+
+    /// Resume all the sounds paused of the channels of the given kind.
+    pub fn resume(&mut self, kind: AudioChannelKind) {
+        self.channel_commands.push((kind, ChannelCommand::Resume));
+    }
+
+    /// Stop all the sounds of all the channels.
+    pub fn stop_all(&mut self) {
+        for kind in AudioChannelKind::ALL {
+            self.stop(kind);
+        }
+    }
+
+    /// Pause all the sounds of all the channels.
+    pub fn pause_all(&mut self) {
+        for kind in AudioChannelKind::ALL {
+            self.pause(kind);
+        }
+    }
+
+    /// Resume all the sounds paused of all the channels.
+    pub fn resume_all(&mut self) {
+        for kind in AudioChannelKind::ALL {
+            self.resume(kind);
+        }
+    }
+
+    /// Commands issued this frame to the channels of the given kind, in order.
+    pub fn channel_commands(
+        &self,
+        kind: AudioChannelKind,
+    ) -> impl Iterator<Item = ChannelCommand> + '_ {
+        self.channel_commands
+            .iter()
+            .filter(move |(command_kind, _)| *command_kind == kind)
+            .map(|(_, command)| *command)
+    }
+
+    /// Position in the game theme, while it plays and the playlist knows its tempo. The
+    /// boss theme, a sped-up copy of it, has no clock.
+    pub fn music_clock(&self) -> Option<MusicClock> {
+        let track = self.game_track.as_ref()?;
+        let time = self.game_clock.time?;
+        let grid = track.grid();
+        Some(MusicClock {
+            beat: (time - track.intro) * grid.bpm as f64 / 60.,
+            grid,
+        })
+    }
+
+    fn source(&self, track: MusicTrack) -> Handle<KiraAudioSource> {
+        match track {
+            MusicTrack::Menu => self.menu_bgm.clone(),
+            MusicTrack::Game => self.game_bgm.clone(),
+            MusicTrack::Boss => self.boss_bgm.clone(),
+        }
+    }
+}
+
+fn music_setup(
+    asset_server: Res<AssetServer>,
+    mut audio_manager: ResMut<AudioManager>,
+    mut playlist: ResMut<BgmPlaylist>,
+    boss_audio: Res<KiraAudioChannel<BossBgmAudio>>,
+) {
+    let playlist = &mut *playlist;
+    for (index, track) in playlist.database.tracks.iter().enumerate() {
+        playlist
+            .handles
+            .push(asset_server.load(track.path.as_str()));
+        if track.loop_start > 0. {
+            playlist.pending_loop_points.push(index);
+        }
+    }
+    audio_manager.menu_bgm = playlist.handle(playlist.menu_track());
+    let stage_track = playlist.stage_track(0);
+    audio_manager.game_bgm = playlist.handle(stage_track);
+    audio_manager.game_track = stage_track.map(|index| playlist.database.tracks[index].clone());
+    audio_manager.boss_bgm = audio_manager.game_bgm.clone();
+    boss_audio.set_playback_rate(BOSS_PLAYBACK_RATE);
+}
+
+/// Set the loop points of the tracks once loaded. Kira always loops at the end of a
+/// sound, so only the loop start is configurable.
+fn apply_loop_points(
+    mut playlist: ResMut<BgmPlaylist>,
+    mut sources: ResMut<Assets<KiraAudioSource>>,
+) {
+    if playlist.pending_loop_points.is_empty() {
+        return;
+    }
+    let BgmPlaylist {
+        database,
+        handles,
+        pending_loop_points,
+    } = &mut *playlist;
+    pending_loop_points.retain(|&index| match sources.get_mut(&handles[index]) {
+        Some(source) => {
+            let track = &database.tracks[index];
+            debug!(target: "music", "Track '{}' loops at {}s", track.name, track.loop_start);
+            source.sound.settings.loop_behavior = Some(LoopBehavior {
+                start_position: track.loop_start,
+            });
+            false
+        }
+        None => true,
+    });
+}
+
+/// Play the playlist track of each stage started as the game theme, restarting the
+/// game theme if the track changes.
+fn swap_stage_track(
+    mut stage_start_events: EventReader<StageStartEvent>,
+    playlist: Res<BgmPlaylist>,
+    mut audio_manager: ResMut<AudioManager>,
+) {
+    let ev = match stage_start_events.iter().last() {
+        Some(ev) => ev,
+        None => return,
+    };
+    let stage_track = playlist.stage_track(ev.stage);
+    let source = playlist.handle(stage_track);
+    if source == audio_manager.game_bgm {
+        return;
+    }
+    let track = stage_track.map(|index| playlist.database.tracks[index].clone());
+    debug!(
+        target: "music",
+        "Stage {} track: {:?}",
+        ev.stage + 1,
+        track.as_ref().map(|track| track.name.as_str())
+    );
+    audio_manager.game_bgm = source.clone();
+    audio_manager.boss_bgm = source;
+    audio_manager.game_track = track;
+    audio_manager.restart_game_track = true;
+}
+
+/// Play sounds through this rather than through the audio channels directly, so that
+/// the volume settings and the mute apply to them.
+#[derive(SystemParam)]
+pub(crate) struct AudioFacade<'w, 's> {
+    audio_manager: Res<'w, AudioManager>,
+    sfx_audio: Res<'w, KiraAudioChannel<SfxAudio>>,
+    ui_audio: Res<'w, KiraAudioChannel<UiAudio>>,
+    voice_audio: Res<'w, KiraAudioChannel<VoiceAudio>>,
+    #[system_param(ignore)]
+    _marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> AudioFacade<'w, 's> {
+    /// Play a one-shot sound on the [`SfxAudio`] channel, unless muted.
+    pub fn play_sfx(&self, source: Handle<KiraAudioSource>) {
+        if self.audio_manager.sfx_gain() > 0. {
+            self.sfx_audio.play(source);
+        }
+    }
+
+    /// Play a one-shot sound on the [`UiAudio`] channel, unless muted.
+    pub fn play_ui(&self, source: Handle<KiraAudioSource>) {
+        if self.audio_manager.sfx_gain() > 0. {
+            self.ui_audio.play(source);
+        }
+    }
+
+    /// Play a one-shot sound on the [`VoiceAudio`] channel, unless muted.
+    pub fn play_voice(&self, source: Handle<KiraAudioSource>) {
+        if self.audio_manager.sfx_gain() > 0. {
+            self.voice_audio.play(source);
+        }
+    }
+}
+
+fn apply_audio_settings(settings: Res<Settings>, mut audio_manager: ResMut<AudioManager>) {
+    if !settings.is_changed() {
+        return;
+    }
+    audio_manager.bgm_volume = settings.audio.bgm_volume as f32 / 100.;
+    audio_manager.sfx_volume = settings.audio.sfx_volume as f32 / 100.;
+}
+
+fn toggle_mute(keyboard_input: Res<Input<KeyCode>>, mut audio_manager: ResMut<AudioManager>) {
+    if keyboard_input.just_pressed(MUTE_KEY) {
+        audio_manager.muted = !audio_manager.muted;
+        info!(
+            target: "audio",
+            "Audio {}",
+            if audio_manager.muted { "muted" } else { "unmuted" }
+        );
+    }
+}
+
+/// Pause all the audio while the gameplay is paused.
+fn pause_audio_with_game(
+    game_time: Res<GameTime>,
+    mut audio_manager: ResMut<AudioManager>,
+    mut was_paused: Local<bool>,
+) {
+    let is_paused = game_time.is_paused();
+    if is_paused == *was_paused {
+        return;
+    }
+    *was_paused = is_paused;
+    if is_paused {
+        audio_manager.pause_all();
+    } else {
+        audio_manager.resume_all();
+    }
+}
+
+/// Stop all the audio when leaving the game, so that no sound effect keeps playing
+/// over the menus, and the game music doesn't linger over the menu music.
+fn stop_game_audio(mut audio_manager: ResMut<AudioManager>) {
+    audio_manager.stop_all();
+}
+
+fn update_music(
+    mut audio_manager: ResMut<AudioManager>,
+    state: Res<State<AppState>>,
+    q_enemies: Query<&EnemyController>,
+    menu_audio: Res<KiraAudioChannel<MenuBgmAudio>>,
+    game_audio: Res<KiraAudioChannel<GameBgmAudio>>,
+    boss_audio: Res<KiraAudioChannel<BossBgmAudio>>,
+    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
+    ui_audio: Res<KiraAudioChannel<UiAudio>>,
+    voice_audio: Res<KiraAudioChannel<VoiceAudio>>,
+    mut bomb_events: EventReader<BombEvent>,
+    mut explosion_events: EventReader<ExplosionEvent>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+
+    let is_boss_alive = q_enemies.iter().any(|controller| controller.is_boss());
+    let target = MusicTrack::for_state(*state.current(), is_boss_alive);
+    if target != audio_manager.current {
+        debug!(target: "music", "Music track: {:?} -> {:?}", audio_manager.current, target);
+        audio_manager.current = target;
+    }
+
+    if bomb_events.iter().count() + explosion_events.iter().count() > 0 {
+        audio_manager.duck();
+    }
+    audio_manager.duck_time = (audio_manager.duck_time - dt).max(0.);
+    let duck_target = if audio_manager.duck_time > 0. {
+        DUCK_VOLUME
+    } else {
+        1.
+    };
+    let max_delta = DUCK_SPEED * dt;
+    audio_manager.duck += (duck_target - audio_manager.duck).clamp(-max_delta, max_delta);
+
+    if std::mem::take(&mut audio_manager.restart_game_track) {
+        for track in [MusicTrack::Game, MusicTrack::Boss] {
+            let index = track as usize;
+            if audio_manager.playing[index] {
+                // Start over right away with the new track
+                audio_manager.playing[index] = false;
+                audio_manager.instances[index] = None;
+            }
+        }
+        game_audio.stop();
+        boss_audio.stop();
+    }
+
+    update_track(&mut audio_manager, MusicTrack::Menu, &menu_audio, dt);
+    update_track(&mut audio_manager, MusicTrack::Game, &game_audio, dt);
+    update_track(&mut audio_manager, MusicTrack::Boss, &boss_audio, dt);
+
+    // Also applies to the sounds already playing
+    let sfx_volume = SFX_CHANNEL_VOLUME * audio_manager.sfx_gain();
+    if sfx_volume != audio_manager.applied_sfx_volume {
+        sfx_audio.set_volume(sfx_volume);
+        ui_audio.set_volume(sfx_volume);
+        voice_audio.set_volume(sfx_volume);
+        audio_manager.applied_sfx_volume = sfx_volume;
+    }
+}
+
+/// Apply the commands issued this frame to the channels, see [`AudioManager::stop()`].
+/// The [`Sfx`](crate::sfx::Sfx) voices apply them on their own.
+fn apply_channel_commands(
+    mut audio_manager: ResMut<AudioManager>,
+    menu_audio: Res<KiraAudioChannel<MenuBgmAudio>>,
+    game_audio: Res<KiraAudioChannel<GameBgmAudio>>,
+    boss_audio: Res<KiraAudioChannel<BossBgmAudio>>,
+    sfx_audio: Res<KiraAudioChannel<SfxAudio>>,
+    ui_audio: Res<KiraAudioChannel<UiAudio>>,
+    voice_audio: Res<KiraAudioChannel<VoiceAudio>>,
+) {
+    let commands = std::mem::take(&mut audio_manager.channel_commands);
+    for &(kind, command) in &commands {
+        match kind {
+            AudioChannelKind::Bgm => {
+                apply_channel_command(&*menu_audio, command);
+                apply_channel_command(&*game_audio, command);
+                apply_channel_command(&*boss_audio, command);
+                if command == ChannelCommand::Stop {
+                    // Start the tracks over next time they fade in
+                    audio_manager.volumes = [0.; 3];
+                    audio_manager.playing = [false; 3];
+                    audio_manager.instances = [None, None, None];
+                }
+            }
+            AudioChannelKind::Sfx => apply_channel_command(&*sfx_audio, command),
+            AudioChannelKind::Ui => apply_channel_command(&*ui_audio, command),
+            AudioChannelKind::Voice => apply_channel_command(&*voice_audio, command),
+        }
+    }
+    // Keep the commands until the end of the frame for the sound effect voices
+    audio_manager.channel_commands = commands;
+}
+
+/// Apply a command to a single channel.
+pub(crate) fn apply_channel_command<T>(channel: &KiraAudioChannel<T>, command: ChannelCommand) {
+    match command {
+        ChannelCommand::Stop => channel.stop(),
+        ChannelCommand::Pause => channel.pause(),
+        ChannelCommand::Resume => channel.resume(),
+    }
+}
+
+fn clear_channel_commands(mut audio_manager: ResMut<AudioManager>) {
+    audio_manager.channel_commands.clear();
+}
+
+/// Fade the given track toward its target volume, starting it when it fades in and
+/// stopping it once faded out.
+fn update_track<T>(
+    audio_manager: &mut AudioManager,
+    track: MusicTrack,
+    channel: &KiraAudioChannel<T>,
+    dt: f32,
+) {
+    let index = track as usize;
+    let target = if audio_manager.current == Some(track) {
+        1.
+    } else {
+        0.
+    };
+    let max_delta = dt / FADE_DURATION;
+    let volume = audio_manager.volumes[index];
+    let volume = volume + (target - volume).clamp(-max_delta, max_delta);
+    audio_manager.volumes[index] = volume;
+
+    if volume > 0. && !audio_manager.playing[index] {
+        let instance = channel.play_looped(audio_manager.source(track));
+        audio_manager.instances[index] = Some(instance);
+        audio_manager.playing[index] = true;
+    } else if volume <= 0. && audio_manager.playing[index] {
+        channel.stop();
+        audio_manager.instances[index] = None;
+        audio_manager.playing[index] = false;
+    }
+
+    let channel_volume = volume * audio_manager.duck * audio_manager.bgm_gain();
+    if channel_volume != audio_manager.applied_volumes[index] {
+        channel.set_volume(channel_volume);
+        audio_manager.applied_volumes[index] = channel_volume;
+    }
+}
+
+/// Track the playback time of the game theme from the position of its instance, adding
+/// the length of the looped section each time the track loops.
+fn update_music_clock(
+    mut audio_manager: ResMut<AudioManager>,
+    game_audio: Res<KiraAudioChannel<GameBgmAudio>>,
+    sources: Res<Assets<KiraAudioSource>>,
+) {
+    let instance = audio_manager.instances[MusicTrack::Game as usize].clone();
+    if instance != audio_manager.game_clock.instance {
+        audio_manager.game_clock = TrackClock {
+            instance: instance.clone(),
+            ..Default::default()
+        };
+    }
+
+    // Queued instances have no position yet
+    let position = match instance.and_then(|instance| game_audio.state(instance).position()) {
+        Some(position) => position,
+        None => return,
+    };
+    let loop_start = audio_manager
+        .game_track
+        .as_ref()
+        .map_or(0., |track| track.loop_start);
+    let duration = sources
+        .get(&audio_manager.game_bgm)
+        .map(|source| source.sound.duration().as_secs_f64());
+    let clock = &mut audio_manager.game_clock;
+    clock.time = Some(match (clock.time, clock.position) {
+        (Some(time), Some(last_position)) => {
+            let mut delta = position - last_position;
+            if delta < 0. {
+                // Looped back to the loop start
+                delta += duration.unwrap_or(last_position) - loop_start;
+            }
+            time + delta.max(0.)
+        }
+        _ => position,
+    });
+    clock.position = Some(position);
+}
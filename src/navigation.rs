@@ -0,0 +1,140 @@
+use bevy::{input::gamepad::GamepadButtonType, prelude::*};
+use leafwing_input_manager::prelude::*;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(InputManagerPlugin::<NavAction>::default())
+            .add_system_to_stage(CoreStage::PostUpdate, update_focus_highlights);
+    }
+}
+
+/// Actions shared by all menu screens, so that all of them can be operated with the
+/// keyboard or a gamepad alone.
+#[derive(Actionlike, PartialEq, Eq, Clone, Copy, Hash, Debug)]
+pub enum NavAction {
+    SelectNext,
+    SelectPrev,
+    Increase,
+    Decrease,
+    Confirm,
+    Back,
+}
+
+impl NavAction {
+    /// Input map with the bindings of all navigation actions.
+    pub fn input_map() -> InputMap<NavAction> {
+        let mut input_map = InputMap::default();
+        input_map.insert(NavAction::SelectNext, KeyCode::Down);
+        input_map.insert(NavAction::SelectNext, KeyCode::S);
+        input_map.insert(NavAction::SelectNext, GamepadButtonType::DPadDown);
+        input_map.insert(NavAction::SelectPrev, KeyCode::Up);
+        input_map.insert(NavAction::SelectPrev, KeyCode::W);
+        input_map.insert(NavAction::SelectPrev, GamepadButtonType::DPadUp);
+        input_map.insert(NavAction::Increase, KeyCode::Right);
+        input_map.insert(NavAction::Increase, KeyCode::D);
+        input_map.insert(NavAction::Increase, GamepadButtonType::DPadRight);
+        input_map.insert(NavAction::Decrease, KeyCode::Left);
+        input_map.insert(NavAction::Decrease, KeyCode::A);
+        input_map.insert(NavAction::Decrease, GamepadButtonType::DPadLeft);
+        input_map.insert(NavAction::Confirm, KeyCode::Return);
+        input_map.insert(NavAction::Confirm, KeyCode::Space);
+        input_map.insert(NavAction::Confirm, GamepadButtonType::South);
+        input_map.insert(NavAction::Back, KeyCode::Escape);
+        input_map.insert(NavAction::Back, KeyCode::Back);
+        input_map.insert(NavAction::Back, GamepadButtonType::East);
+        input_map
+    }
+}
+
+/// Bundle for the root entity of a menu screen.
+#[derive(Bundle)]
+pub struct NavMenuBundle {
+    pub menu: NavMenu,
+    #[bundle]
+    pub input: InputManagerBundle<NavAction>,
+}
+
+impl NavMenuBundle {
+    pub fn new(len: usize) -> Self {
+        NavMenuBundle {
+            menu: NavMenu { focused: 0, len },
+            input: InputManagerBundle::<NavAction> {
+                action_state: ActionState::default(),
+                input_map: NavAction::input_map(),
+            },
+        }
+    }
+}
+
+/// Vertical list of focusable items of a menu screen.
+#[derive(Component, Default)]
+pub struct NavMenu {
+    /// Index of the item which has the focus.
+    pub focused: usize,
+    /// Number of items.
+    pub len: usize,
+}
+
+impl NavMenu {
+    /// Move the focus to the next or previous item depending on the actions pressed,
+    /// wrapping around at both ends of the list. Return `true` if the focus moved.
+    pub fn navigate(&mut self, action_state: &ActionState<NavAction>) -> bool {
+        let mut delta = 0;
+        if action_state.just_pressed(NavAction::SelectNext) {
+            delta += 1;
+        }
+        if action_state.just_pressed(NavAction::SelectPrev) {
+            delta -= 1;
+        }
+        self.focus((self.focused as i32 + delta).rem_euclid(self.len.max(1) as i32) as usize)
+    }
+
+    /// Give the focus to the item at the given index. Return `true` if the focus moved.
+    pub fn focus(&mut self, index: usize) -> bool {
+        let index = index.min(self.len.saturating_sub(1));
+        let changed = index != self.focused;
+        self.focused = index;
+        changed
+    }
+}
+
+/// Color highlight of a focusable item of a [`NavMenu`].
+///
+/// The color is applied to the [`Text`] of the entity if any, or else to its [`UiColor`].
+#[derive(Component)]
+pub struct FocusHighlight {
+    /// Entity holding the [`NavMenu`] the item belongs to.
+    pub menu: Entity,
+    /// Index of the item in the menu.
+    pub index: usize,
+    pub color: Color,
+    /// Color when the item has the focus.
+    pub focus_color: Color,
+}
+
+fn update_focus_highlights(
+    q_menus: Query<&NavMenu, Changed<NavMenu>>,
+    mut q_highlights: Query<(&FocusHighlight, Option<&mut Text>, Option<&mut UiColor>)>,
+) {
+    for (highlight, text, ui_color) in q_highlights.iter_mut() {
+        let menu = if let Ok(menu) = q_menus.get(highlight.menu) {
+            menu
+        } else {
+            continue;
+        };
+        let color = if menu.focused == highlight.index {
+            highlight.focus_color
+        } else {
+            highlight.color
+        };
+        if let Some(mut text) = text {
+            for section in text.sections.iter_mut() {
+                section.style.color = color;
+            }
+        } else if let Some(mut ui_color) = ui_color {
+            ui_color.0 = color;
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use bevy::prelude::*;
+use heron::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    game::{
+        bounce_enemy_bullets, move_enemy_bullets, Bounce, EnemyBullet, GameRng, InGameEntity,
+        MainCamera, PendingDespawn, PlayerController, PlayerHitboxes, ENEMY_BULLET_RADIUS,
+    },
+    timestep::{FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    vfx::SparkEvent,
+    AppState, Layer,
+};
+
+pub struct ObstaclePlugin;
+
+impl Plugin for ObstaclePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SpawnObstacleEvent>()
+            .init_resource::<ObstacleAssets>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(setup_obstacles),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spin_obstacles)
+                    .with_system(despawn_obstacles_outside_screen),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_obstacles)
+                    .with_system(move_obstacles)
+                    .with_system(block_player.after(move_obstacles))
+                    .with_system(
+                        block_enemy_bullets
+                            .after(move_enemy_bullets)
+                            .after(bounce_enemy_bullets)
+                            .after(move_obstacles),
+                    ),
+            );
+    }
+}
+
+/// Scrolling speed of the obstacles toward the left of the screen, in world units per
+/// second.
+const OBSTACLE_SPEED: f32 = 0.6;
+
+/// Distance beyond the screen bounds past which an obstacle is despawned, in world units,
+/// in addition to its radius.
+const OBSTACLE_CULL_MARGIN: f32 = 0.1;
+
+/// Event to spawn an obstacle beyond the right edge of the screen, typically sent by the
+/// enemy timeline.
+#[derive(Debug, Clone, Copy)]
+pub struct SpawnObstacleEvent {
+    /// Vertical position of the obstacle, in world units.
+    pub y: f32,
+    /// Radius of the obstacle, in world units.
+    pub radius: f32,
+}
+
+/// Asteroid drifting through the play area on the world layer. Obstacles take no damage
+/// and deal none, but block the player, and destroy the bullets hitting them.
+#[derive(Component, Debug)]
+pub struct Obstacle {
+    /// Radius of the obstacle, in world units.
+    pub radius: f32,
+    /// Angular speed of the spin of the obstacle visual, in radians per second.
+    spin: f32,
+    /// Visual child entity, spinning and scaled to the obstacle radius.
+    visual: Entity,
+}
+
+#[derive(Default)]
+struct ObstacleAssets {
+    mesh: Handle<Mesh>,
+    material: Handle<StandardMaterial>,
+}
+
+fn setup_obstacles(
+    mut obstacle_assets: ResMut<ObstacleAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // Unit sphere scaled by the radius of each obstacle; a low subdivision count gives
+    // it a rocky, faceted look
+    obstacle_assets.mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: 1.,
+        subdivisions: 1,
+    }));
+    obstacle_assets.material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.45, 0.38, 0.33),
+        perceptual_roughness: 0.9,
+        ..Default::default()
+    });
+}
+
+fn spawn_obstacles(
+    mut commands: Commands,
+    mut spawn_events: EventReader<SpawnObstacleEvent>,
+    obstacle_assets: Res<ObstacleAssets>,
+    q_camera: Query<&MainCamera>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let right = q_camera
+        .get_single()
+        .map(|camera| camera.screen_bounds.right)
+        .unwrap_or(3.49);
+    let rng = &mut game_rng.0;
+    for ev in spawn_events.iter() {
+        let visual = commands
+            .spawn_bundle(PbrBundle {
+                mesh: obstacle_assets.mesh.clone(),
+                material: obstacle_assets.material.clone(),
+                transform: Transform::from_scale(Vec3::splat(ev.radius)),
+                ..Default::default()
+            })
+            .id();
+        // The collision shape is not scaled, so only the visual child is
+        commands
+            .spawn_bundle((
+                Transform::from_xyz(right + ev.radius, ev.y, 0.),
+                GlobalTransform::identity(),
+                Visibility::default(),
+                ComputedVisibility::default(),
+            ))
+            .insert(Name::new("Obstacle"))
+            .insert(InGameEntity)
+            .insert(Obstacle {
+                radius: ev.radius,
+                spin: rng.gen_range(-1.5..1.5),
+                visual,
+            })
+            .insert(FixedStep::default())
+            // Physics
+            .insert(RigidBody::KinematicPositionBased)
+            .insert(CollisionShape::Sphere { radius: ev.radius })
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layer::World)
+                    .with_masks(&[Layer::Player, Layer::PlayerBullet, Layer::Enemy]),
+            )
+            .add_child(visual);
+    }
+}
+
+fn move_obstacles(mut query: Query<&mut Transform, With<Obstacle>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.x -= OBSTACLE_SPEED * FIXED_DELTA_SECONDS;
+    }
+}
+
+fn spin_obstacles(query: Query<&Obstacle>, mut q_visuals: Query<&mut Transform>, time: Res<Time>) {
+    let dt = time.delta_seconds();
+    for obstacle in query.iter() {
+        if let Ok(mut transform) = q_visuals.get_mut(obstacle.visual) {
+            transform.rotate(Quat::from_rotation_z(obstacle.spin * dt));
+        }
+    }
+}
+
+/// Push the player hurt box out of the obstacles it moved into, or got hit by.
+pub fn block_player(
+    mut q_player: Query<(&mut Transform, &PlayerHitboxes), With<PlayerController>>,
+    query: Query<(&Transform, &Obstacle), Without<PlayerController>>,
+) {
+    let (mut player_transform, hitboxes) = if let Ok(player) = q_player.get_single_mut() {
+        player
+    } else {
+        return;
+    };
+    for (transform, obstacle) in query.iter() {
+        let min_distance = obstacle.radius + hitboxes.hurt_radius;
+        let delta = hitboxes.hurt_position(player_transform.translation) - transform.translation;
+        if delta.length_squared() < min_distance * min_distance {
+            // Obstacles scroll left, so push out to the left if exactly at the center
+            let dir = delta.try_normalize().unwrap_or(-Vec3::X);
+            player_transform.translation =
+                transform.translation + dir * min_distance - hitboxes.hurt_offset;
+        }
+    }
+}
+
+/// Destroy the enemy bullets hitting an obstacle, or bounce them off it while they have
+/// any [`Bounce`] left. Player bullets are destroyed by heron collisions, like when
+/// hitting an enemy.
+fn block_enemy_bullets(
+    mut commands: Commands,
+    mut q_bullets: Query<
+        (Entity, &mut Transform, Option<&mut Bounce>),
+        (With<EnemyBullet>, Without<PendingDespawn>),
+    >,
+    query: Query<(&Transform, &Obstacle), Without<EnemyBullet>>,
+    mut spark_events: EventWriter<SparkEvent>,
+) {
+    if query.is_empty() {
+        return;
+    }
+    for (entity, mut bullet_transform, bounce) in q_bullets.iter_mut() {
+        for (transform, obstacle) in query.iter() {
+            let hit_radius = obstacle.radius + ENEMY_BULLET_RADIUS;
+            let offset = (bullet_transform.translation - transform.translation).truncate();
+            if offset.length_squared() >= hit_radius * hit_radius {
+                continue;
+            }
+            spark_events.send(SparkEvent {
+                position: bullet_transform.translation,
+            });
+            // Bounce off the surface of the obstacle if possible, else get blocked
+            let normal = offset.normalize_or_zero();
+            if let Some(mut bounce) = bounce {
+                if normal != Vec2::ZERO && bounce.reflect(&mut bullet_transform, normal) {
+                    let surface = transform.translation.truncate() + normal * hit_radius;
+                    bullet_transform.translation.x = surface.x;
+                    bullet_transform.translation.y = surface.y;
+                    break;
+                }
+            }
+            commands.entity(entity).insert(PendingDespawn);
+            break;
+        }
+    }
+}
+
+/// Despawn the obstacles which scrolled past the left edge of the screen.
+fn despawn_obstacles_outside_screen(
+    mut commands: Commands,
+    query: Query<(Entity, &Transform, &Obstacle), Without<PendingDespawn>>,
+    q_camera: Query<&MainCamera>,
+) {
+    let left = if let Ok(camera) = q_camera.get_single() {
+        camera.screen_bounds.left
+    } else {
+        return;
+    };
+    for (entity, transform, obstacle) in query.iter() {
+        if transform.translation.x < left - obstacle.radius - OBSTACLE_CULL_MARGIN {
+            commands.entity(entity).insert(PendingDespawn);
+        }
+    }
+}
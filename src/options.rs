@@ -0,0 +1,447 @@
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    flow::RequestStateChange,
+    music::AudioFacade,
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    profile::Profile,
+    settings::{
+        AutoFireDelay, DisplayMode, FrameRateCap, GraphicsQuality, PaletteKind, PresentModeSetting,
+        Settings, StickResponse, RESOLUTIONS,
+    },
+    AppState,
+};
+
+pub struct OptionsPlugin;
+
+impl Plugin for OptionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Options).with_system(options_setup))
+            .add_system_set(SystemSet::on_update(AppState::Options).with_system(options_run))
+            .add_system_set(SystemSet::on_exit(AppState::Options).with_system(options_cleanup));
+    }
+}
+
+/// Entries of the options screen, in display order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionItem {
+    DisplayMode,
+    Resolution,
+    /// Not listed on the web.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    FrameRateCap,
+    PresentMode,
+    Projection,
+    GraphicsQuality,
+    ShowRank,
+    ShowRunTimer,
+    ShowGhost,
+    DimBackground,
+    ColorCodeThreats,
+    AutoFireDelay,
+    AutoFire,
+    StickDeadzone,
+    StickResponse,
+    Rumble,
+    TouchControls,
+    MusicVolume,
+    SfxVolume,
+    Palette,
+    ReduceFlashing,
+    ResetProgress,
+    Back,
+}
+
+const OPTION_ITEMS: &[OptionItem] = &[
+    OptionItem::DisplayMode,
+    OptionItem::Resolution,
+    // The browser paces frames itself, see `limit_frame_rate()`
+    #[cfg(not(target_arch = "wasm32"))]
+    OptionItem::FrameRateCap,
+    OptionItem::PresentMode,
+    OptionItem::Projection,
+    OptionItem::GraphicsQuality,
+    OptionItem::ShowRank,
+    OptionItem::ShowRunTimer,
+    OptionItem::ShowGhost,
+    OptionItem::DimBackground,
+    OptionItem::ColorCodeThreats,
+    OptionItem::AutoFireDelay,
+    OptionItem::AutoFire,
+    OptionItem::StickDeadzone,
+    OptionItem::StickResponse,
+    OptionItem::Rumble,
+    OptionItem::TouchControls,
+    OptionItem::MusicVolume,
+    OptionItem::SfxVolume,
+    OptionItem::Palette,
+    OptionItem::ReduceFlashing,
+    OptionItem::ResetProgress,
+    OptionItem::Back,
+];
+
+impl OptionItem {
+    fn label(&self) -> &'static str {
+        match self {
+            OptionItem::DisplayMode => "Display",
+            OptionItem::Resolution => "Resolution",
+            OptionItem::FrameRateCap => "Frame rate cap",
+            OptionItem::PresentMode => "Present mode",
+            OptionItem::Projection => "Projection",
+            OptionItem::GraphicsQuality => "Graphics",
+            OptionItem::ShowRank => "Show rank",
+            OptionItem::ShowRunTimer => "Run timer",
+            OptionItem::ShowGhost => "Best run ghost",
+            OptionItem::DimBackground => "Background dim",
+            OptionItem::ColorCodeThreats => "Threat colors",
+            OptionItem::AutoFireDelay => "Auto-fire delay",
+            OptionItem::AutoFire => "Auto-fire",
+            OptionItem::StickDeadzone => "Stick deadzone",
+            OptionItem::StickResponse => "Stick response",
+            OptionItem::Rumble => "Rumble",
+            OptionItem::TouchControls => "Touch controls",
+            OptionItem::MusicVolume => "Music volume",
+            OptionItem::SfxVolume => "SFX volume",
+            OptionItem::Palette => "Palette",
+            OptionItem::ReduceFlashing => "Reduce flashing",
+            OptionItem::ResetProgress => "Reset progress",
+            OptionItem::Back => "Back",
+        }
+    }
+
+    fn value(&self, settings: &Settings) -> Option<&'static str> {
+        match self {
+            OptionItem::DisplayMode => Some(settings.video.display_mode.label()),
+            OptionItem::Resolution => {
+                Some(resolution_index(settings).map_or("Custom", |index| RESOLUTIONS[index].label))
+            }
+            OptionItem::FrameRateCap => Some(settings.video.frame_rate_cap.label()),
+            OptionItem::PresentMode => Some(settings.video.present_mode.label()),
+            OptionItem::Projection => Some(if settings.video.orthographic_camera {
+                "Flat"
+            } else {
+                "Perspective"
+            }),
+            OptionItem::GraphicsQuality => Some(settings.video.graphics_quality.label()),
+            OptionItem::ShowRank => Some(on_off(settings.gameplay.show_rank)),
+            OptionItem::ShowRunTimer => Some(on_off(settings.gameplay.show_run_timer)),
+            OptionItem::ShowGhost => Some(on_off(settings.gameplay.show_ghost)),
+            OptionItem::DimBackground => Some(on_off(settings.gameplay.dim_background)),
+            OptionItem::ColorCodeThreats => Some(on_off(settings.gameplay.color_code_threats)),
+            OptionItem::AutoFireDelay => Some(settings.gameplay.auto_fire_delay.label()),
+            OptionItem::AutoFire => Some(on_off(settings.gameplay.auto_fire)),
+            OptionItem::StickDeadzone => Some(deadzone_label(settings.controls.stick_deadzone)),
+            OptionItem::StickResponse => Some(settings.controls.stick_response.label()),
+            OptionItem::Rumble => Some(on_off(settings.controls.rumble)),
+            OptionItem::TouchControls => Some(on_off(settings.controls.touch_controls)),
+            OptionItem::MusicVolume => Some(volume_bar(settings.audio.bgm_volume)),
+            OptionItem::SfxVolume => Some(volume_bar(settings.audio.sfx_volume)),
+            OptionItem::Palette => Some(settings.accessibility.palette.label()),
+            OptionItem::ReduceFlashing => Some(on_off(settings.accessibility.reduce_flashing)),
+            OptionItem::ResetProgress | OptionItem::Back => None,
+        }
+    }
+
+    fn change(&self, settings: &mut Settings, delta: i32) {
+        match self {
+            OptionItem::DisplayMode => {
+                settings.video.display_mode =
+                    cycle(&DisplayMode::ALL, settings.video.display_mode, delta)
+            }
+            OptionItem::Resolution => {
+                let current = RESOLUTIONS[resolution_index(settings).unwrap_or(0)];
+                let resolution = cycle(&RESOLUTIONS, current, delta);
+                settings.video.window_width = resolution.width;
+                settings.video.window_height = resolution.height;
+            }
+            OptionItem::FrameRateCap => {
+                settings.video.frame_rate_cap =
+                    cycle(&FrameRateCap::ALL, settings.video.frame_rate_cap, delta)
+            }
+            OptionItem::PresentMode => {
+                settings.video.present_mode =
+                    cycle(&PresentModeSetting::ALL, settings.video.present_mode, delta)
+            }
+            OptionItem::Projection => {
+                settings.video.orthographic_camera = !settings.video.orthographic_camera
+            }
+            OptionItem::GraphicsQuality => {
+                settings.video.graphics_quality = cycle(
+                    &GraphicsQuality::ALL,
+                    settings.video.graphics_quality,
+                    delta,
+                )
+            }
+            OptionItem::ShowRank => settings.gameplay.show_rank = !settings.gameplay.show_rank,
+            OptionItem::ShowRunTimer => {
+                settings.gameplay.show_run_timer = !settings.gameplay.show_run_timer
+            }
+            OptionItem::ShowGhost => settings.gameplay.show_ghost = !settings.gameplay.show_ghost,
+            OptionItem::DimBackground => {
+                settings.gameplay.dim_background = !settings.gameplay.dim_background
+            }
+            OptionItem::ColorCodeThreats => {
+                settings.gameplay.color_code_threats = !settings.gameplay.color_code_threats
+            }
+            OptionItem::AutoFireDelay => {
+                settings.gameplay.auto_fire_delay = cycle(
+                    &AutoFireDelay::ALL,
+                    settings.gameplay.auto_fire_delay,
+                    delta,
+                )
+            }
+            OptionItem::AutoFire => settings.gameplay.auto_fire = !settings.gameplay.auto_fire,
+            OptionItem::StickDeadzone => {
+                settings.controls.stick_deadzone =
+                    step_deadzone(settings.controls.stick_deadzone, delta)
+            }
+            OptionItem::StickResponse => {
+                settings.controls.stick_response =
+                    cycle(&StickResponse::ALL, settings.controls.stick_response, delta)
+            }
+            OptionItem::Rumble => settings.controls.rumble = !settings.controls.rumble,
+            OptionItem::TouchControls => {
+                settings.controls.touch_controls = !settings.controls.touch_controls
+            }
+            OptionItem::MusicVolume => {
+                settings.audio.bgm_volume = step_volume(settings.audio.bgm_volume, delta)
+            }
+            OptionItem::SfxVolume => {
+                settings.audio.sfx_volume = step_volume(settings.audio.sfx_volume, delta)
+            }
+            OptionItem::Palette => {
+                settings.accessibility.palette =
+                    cycle(&PaletteKind::ALL, settings.accessibility.palette, delta)
+            }
+            OptionItem::ReduceFlashing => {
+                settings.accessibility.reduce_flashing = !settings.accessibility.reduce_flashing
+            }
+            // Not a setting, see options_run()
+            OptionItem::ResetProgress | OptionItem::Back => (),
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+/// Index into [`RESOLUTIONS`] of the window resolution, if not a custom one.
+fn resolution_index(settings: &Settings) -> Option<usize> {
+    RESOLUTIONS.iter().position(|resolution| {
+        resolution.width == settings.video.window_width
+            && resolution.height == settings.video.window_height
+    })
+}
+
+/// Return the value `delta` steps after `current` in `values`, wrapping around.
+fn cycle<T: Copy + PartialEq>(values: &[T], current: T, delta: i32) -> T {
+    let index = values.iter().position(|v| *v == current).unwrap_or(0) as i32;
+    let len = values.len() as i32;
+    values[(index + delta).rem_euclid(len) as usize]
+}
+
+/// Step of the volume sliders, in percent.
+const VOLUME_STEP: i32 = 10;
+
+/// Return the volume `delta` steps away from `volume`, clamped to `[0:100]`.
+fn step_volume(volume: u8, delta: i32) -> u8 {
+    (volume as i32 + delta * VOLUME_STEP).clamp(0, 100) as u8
+}
+
+/// Slider showing a volume in percent, with one mark per volume step.
+fn volume_bar(volume: u8) -> &'static str {
+    const BAR: &str = "||||||||||..........";
+    let marks = (volume as i32 / VOLUME_STEP).min(10) as usize;
+    &BAR[10 - marks..20 - marks]
+}
+
+/// Step of the stick deadzone, in percent.
+const DEADZONE_STEP: i32 = 5;
+
+/// Largest stick deadzone, in percent.
+const MAX_DEADZONE: i32 = 50;
+
+/// Return the deadzone `delta` steps away from `deadzone`, clamped to
+/// `[0:MAX_DEADZONE]`.
+fn step_deadzone(deadzone: u8, delta: i32) -> u8 {
+    (deadzone as i32 + delta * DEADZONE_STEP).clamp(0, MAX_DEADZONE) as u8
+}
+
+/// Label of a deadzone, in percent. Only multiples of [`DEADZONE_STEP`] are selectable.
+fn deadzone_label(deadzone: u8) -> &'static str {
+    const LABELS: [&str; 11] = [
+        "0%", "5%", "10%", "15%", "20%", "25%", "30%", "35%", "40%", "45%", "50%",
+    ];
+    LABELS[(deadzone as i32 / DEADZONE_STEP).min(10) as usize]
+}
+
+#[derive(Component, Default)]
+struct OptionsMenu {
+    sound_click: Handle<KiraAudioSource>,
+    /// Was reset progress confirmed once already? It takes a second confirmation,
+    /// since it cannot be undone.
+    reset_armed: bool,
+}
+
+/// Marker for all entities of the options screen, for cleanup.
+#[derive(Component)]
+struct OptionsScreen;
+
+#[derive(Component)]
+struct OptionRow(usize);
+
+fn options_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    debug!(target: "setup", "options_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(OptionsScreen);
+
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+
+    let mut menu = OptionsMenu::default();
+    menu.sound_click = asset_server.load("sounds/click4.ogg");
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect::all(Val::Px(0.)),
+                margin: Rect::all(Val::Px(16.)),
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("options"))
+        .insert(OptionsScreen)
+        .insert(menu)
+        .insert_bundle(NavMenuBundle::new(OPTION_ITEMS.len()))
+        .with_children(|parent| {
+            let menu_entity = parent.parent_entity();
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(24.)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Options",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 64.0,
+                        color: Color::rgb_u8(32, 32, 32),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+
+            for index in 0..OPTION_ITEMS.len() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(8.)),
+                            ..Default::default()
+                        },
+                        text: Text::with_section(
+                            "",
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 36.0,
+                                color: Color::rgb_u8(32, 32, 32),
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    })
+                    .insert(OptionRow(index))
+                    .insert(FocusHighlight {
+                        menu: menu_entity,
+                        index,
+                        color: Color::rgb_u8(32, 32, 32),
+                        focus_color: Color::rgb_u8(57, 194, 190),
+                    });
+            }
+        });
+}
+
+fn options_run(
+    mut q_menu: Query<(&mut OptionsMenu, &mut NavMenu, &ActionState<NavAction>)>,
+    mut q_rows: Query<(&OptionRow, &mut Text)>,
+    mut settings: ResMut<Settings>,
+    mut profile: ResMut<Profile>,
+    audio_facade: AudioFacade,
+    mut state_requests: EventWriter<RequestStateChange>,
+) {
+    let (mut menu, mut nav, action_state) = q_menu.single_mut();
+    if nav.navigate(action_state) {
+        audio_facade.play_ui(menu.sound_click.clone());
+        menu.reset_armed = false;
+    }
+
+    let item = OPTION_ITEMS[nav.focused];
+    let mut delta = 0;
+    if action_state.just_pressed(NavAction::Increase)
+        || action_state.just_pressed(NavAction::Confirm)
+    {
+        delta += 1;
+    }
+    if action_state.just_pressed(NavAction::Decrease) {
+        delta -= 1;
+    }
+    if item == OptionItem::ResetProgress {
+        if action_state.just_pressed(NavAction::Confirm) {
+            if menu.reset_armed {
+                profile.reset();
+            }
+            menu.reset_armed = !menu.reset_armed;
+            audio_facade.play_ui(menu.sound_click.clone());
+        }
+    } else if delta != 0 && item != OptionItem::Back {
+        item.change(&mut *settings, delta);
+        audio_facade.play_ui(menu.sound_click.clone());
+    }
+
+    if action_state.just_pressed(NavAction::Back)
+        || (item == OptionItem::Back && action_state.just_pressed(NavAction::Confirm))
+    {
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+        return;
+    }
+
+    for (row, mut text) in q_rows.iter_mut() {
+        let item = OPTION_ITEMS[row.0];
+        let value = if let Some(value) = item.value(&*settings) {
+            format!("{}: < {} >", item.label(), value)
+        } else if item == OptionItem::ResetProgress && menu.reset_armed {
+            "Reset progress? Confirm again".to_string()
+        } else {
+            item.label().to_string()
+        };
+        let section = &mut text.sections[0];
+        if section.value != value {
+            section.value = value;
+        }
+    }
+}
+
+fn options_cleanup(
+    mut commands: Commands,
+    query: Query<Entity, With<OptionsScreen>>,
+    settings: Res<Settings>,
+) {
+    settings.save();
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
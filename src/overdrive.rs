@@ -0,0 +1,232 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+
+use crate::{
+    enemy::EnemyKilledEvent,
+    game::{InGameEntity, PlayerController},
+    rank::GrazeEvent,
+    settings::Settings,
+    timestep::{FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+/// Overdrive meter: grazing enemy bullets and killing enemies fill the meter, and once
+/// full the player can burn it for a short [`Overdrive`], firing twice as fast, scoring
+/// more and destroying the enemy bullets around the ship.
+pub struct OverdrivePlugin;
+
+impl Plugin for OverdrivePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<OverdriveMeter>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(overdrive_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_aura_visuals),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(fill_overdrive_meter)
+                    .with_system(tick_overdrives)
+                    .with_system(follow_player.after(tick_overdrives)),
+            );
+    }
+}
+
+/// Duration of an overdrive, in seconds.
+pub const OVERDRIVE_DURATION: f32 = 6.;
+
+/// Factor applied to the fire delay of the primary weapon during an overdrive.
+pub const OVERDRIVE_FIRE_DELAY_FACTOR: f32 = 0.5;
+
+/// Factor applied to the kill scores during an overdrive, on top of the combo.
+pub const OVERDRIVE_SCORE_MULTIPLIER: f32 = 2.;
+
+/// Radius of the aura around the player during an overdrive, in world units. Enemy
+/// bullets touching the aura are destroyed.
+pub const OVERDRIVE_AURA_RADIUS: f32 = 0.35;
+
+/// Fraction of the meter filled by each graze.
+const GRAZE_GAIN: f32 = 0.01;
+
+/// Fraction of the meter filled by each kill.
+const KILL_GAIN: f32 = 0.02;
+
+/// Fraction of the meter filled by a boss kill.
+const BOSS_KILL_GAIN: f32 = 0.25;
+
+/// Remaining time below which the aura flickers, in seconds.
+const AURA_WARNING_TIME: f32 = 1.5;
+
+/// Pulses of the aura per second.
+const AURA_PULSE_RATE: f32 = 3.;
+
+const AURA_COLOR: Color = Color::rgba(1., 0.45, 0.1, 0.2);
+
+/// Fill level of the overdrive meter, in `[0:1]`.
+///
+/// Updated in the gameplay steps, since it affects the score, which replays must
+/// reproduce.
+#[derive(Debug, Default)]
+pub struct OverdriveMeter {
+    value: f32,
+}
+
+impl OverdriveMeter {
+    pub fn value(&self) -> f32 {
+        self.value
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.value >= 1.
+    }
+
+    fn add(&mut self, amount: f32) {
+        self.value = (self.value + amount).min(1.);
+    }
+
+    /// Empty the meter if full, and return `true` in that case.
+    pub fn burn(&mut self) -> bool {
+        if !self.is_full() {
+            return false;
+        }
+        self.value = 0.;
+        true
+    }
+}
+
+/// Timed overdrive of the player, started by burning a full [`OverdriveMeter`]. The
+/// overdrive is lost if the player dies.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Overdrive {
+    /// Remaining time of the overdrive, in seconds.
+    pub remaining: f32,
+}
+
+impl Default for Overdrive {
+    fn default() -> Self {
+        Overdrive {
+            remaining: OVERDRIVE_DURATION,
+        }
+    }
+}
+
+/// Translucent aura displayed around the player ship during an overdrive.
+#[derive(Component)]
+struct OverdriveAura;
+
+fn overdrive_setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(OverdriveMeter::default());
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Icosphere {
+                radius: OVERDRIVE_AURA_RADIUS,
+                subdivisions: 3,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: AURA_COLOR,
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+            // Placed on the player by follow_player()
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Name::new("OverdriveAura"))
+        .insert(InGameEntity)
+        .insert(OverdriveAura)
+        .insert(FixedStep::default())
+        .insert(NotShadowCaster)
+        .insert(NotShadowReceiver);
+}
+
+/// Fill the meter from the grazes and kills of this step. The meter doesn't fill
+/// during an overdrive.
+fn fill_overdrive_meter(
+    mut meter: ResMut<OverdriveMeter>,
+    mut graze_events: EventReader<GrazeEvent>,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    q_player: Query<(), (With<PlayerController>, With<Overdrive>)>,
+) {
+    let gain = graze_events.iter().count() as f32 * GRAZE_GAIN
+        + killed_events
+            .iter()
+            .map(|ev| {
+                if ev.is_boss {
+                    BOSS_KILL_GAIN
+                } else {
+                    KILL_GAIN
+                }
+            })
+            .sum::<f32>();
+    if gain > 0. && q_player.is_empty() && !meter.is_full() {
+        meter.add(gain);
+        if meter.is_full() {
+            debug!(target: "overdrive", "OVERDRIVE: meter full");
+        }
+    }
+}
+
+/// Run the overdrives down, removing them once expired or if the player died.
+fn tick_overdrives(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Overdrive, &PlayerController)>,
+) {
+    for (entity, mut overdrive, controller) in query.iter_mut() {
+        overdrive.remaining -= FIXED_DELTA_SECONDS;
+        if overdrive.remaining <= 0. || controller.is_respawning() {
+            commands.entity(entity).remove::<Overdrive>();
+        }
+    }
+}
+
+fn follow_player(
+    mut q_aura: Query<&mut Transform, With<OverdriveAura>>,
+    q_player: Query<&Transform, (With<Overdrive>, Without<OverdriveAura>)>,
+) {
+    if let (Ok(mut transform), Ok(player_transform)) =
+        (q_aura.get_single_mut(), q_player.get_single())
+    {
+        transform.translation = player_transform.translation;
+    }
+}
+
+/// Show the aura during an overdrive, pulsing, and flickering when about to run out.
+fn update_aura_visuals(
+    q_player: Query<&Overdrive>,
+    mut q_aura: Query<(&mut Transform, &mut Visibility), With<OverdriveAura>>,
+    settings: Res<Settings>,
+) {
+    let remaining = q_player
+        .get_single()
+        .map_or(0., |overdrive| overdrive.remaining.max(0.));
+
+    if let Ok((mut transform, mut visibility)) = q_aura.get_single_mut() {
+        let is_visible = if remaining <= 0. {
+            false
+        } else if remaining < AURA_WARNING_TIME && !settings.accessibility.reduce_flashing {
+            // Flicker 6 times per second
+            (remaining * 12.).fract() < 0.5
+        } else {
+            true
+        };
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+        if is_visible && !settings.accessibility.reduce_flashing {
+            let pulse = (remaining * AURA_PULSE_RATE * std::f32::consts::TAU).sin();
+            transform.scale = Vec3::splat(1. + 0.06 * pulse);
+        }
+    }
+}
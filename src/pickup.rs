@@ -0,0 +1,371 @@
+use bevy::{
+    math::const_vec3,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use heron::prelude::*;
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    event_log::LogEvent,
+    game::{GameRng, InGameEntity, MainCamera, PendingDespawn, PlayerController, ScoreEvent},
+    sfx::{Sfx, SfxEvent},
+    shield::Shield,
+    timestep::{FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState, Layer,
+};
+
+pub struct PickupPlugin;
+
+impl Plugin for PickupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<DropPickupsEvent>()
+            .init_resource::<PickupAssets>()
+            .add_startup_system(setup_pickups)
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_pickups)
+                    .with_system(collect_pickups),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_pickups)
+                    .with_system(move_pickups.after(update_pickups)),
+            );
+    }
+}
+
+/// Score granted by a single score item.
+const PICKUP_SCORE: u32 = 10;
+
+/// Score granted by a single star from a cancelled bullet.
+const STAR_SCORE: u32 = 2;
+
+/// Speed at which stars fly toward the player, in world units per second.
+const STAR_SPEED: f32 = 4.;
+
+/// Velocity toward which all pickups converge after popping out of an enemy.
+const PICKUP_DRIFT_VELOCITY: Vec3 = const_vec3!([-0.8, 0., 0.]);
+
+/// Distance from the player within which pickups are pulled toward the ship, in world
+/// units.
+const MAGNET_RADIUS: f32 = 0.7;
+
+/// Speed at which the pickups at the edge of the magnet radius fly toward the player,
+/// in world units per second. Closer pickups fly faster.
+const MAGNET_SPEED: f32 = 2.5;
+
+/// Point of collection, as a fraction of the screen width from the right edge. While
+/// the player is past that line, toward the enemies, all the pickups on screen are
+/// collected.
+const COLLECT_LINE: f32 = 0.25;
+
+/// Speed at which the auto-collected pickups fly toward the player, in world units per
+/// second.
+const AUTO_COLLECT_SPEED: f32 = 6.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum PickupKind {
+    /// Increase the player weapon power.
+    #[serde(alias = "power")]
+    Power,
+    /// Grant some score.
+    #[serde(alias = "score")]
+    Score,
+    /// Add a bomb to the player stock.
+    #[serde(alias = "bomb")]
+    Bomb,
+    /// Grant a little score. Enemy bullets turn into stars when cancelled, which fly
+    /// toward the player on their own.
+    #[serde(alias = "star")]
+    Star,
+    /// Protect the player from enemy bullets for a while. See [`Shield`].
+    #[serde(alias = "shield")]
+    Shield,
+    /// Let the player damage enemies by ramming them for a while.
+    #[serde(alias = "ram")]
+    Ram,
+}
+
+impl PickupKind {
+    /// Pick a random kind, mostly score items with some power, and rare bombs, shields
+    /// and rams.
+    fn random(rng: &mut impl Rng) -> PickupKind {
+        let roll = rng.gen::<f32>();
+        if roll < 0.01 {
+            PickupKind::Ram
+        } else if roll < 0.03 {
+            PickupKind::Shield
+        } else if roll < 0.06 {
+            PickupKind::Bomb
+        } else if roll < 0.35 {
+            PickupKind::Power
+        } else {
+            PickupKind::Score
+        }
+    }
+
+    fn color(&self) -> Color {
+        match self {
+            PickupKind::Power => Color::rgb(1., 0.2, 0.1),
+            PickupKind::Score => Color::rgb(0.2, 0.5, 1.),
+            PickupKind::Bomb => Color::rgb(0.2, 1., 0.3),
+            PickupKind::Star => Color::rgb(1., 0.85, 0.2),
+            PickupKind::Shield => Color::rgb(0.3, 0.9, 1.),
+            PickupKind::Ram => Color::rgb(1., 0.5, 0.1),
+        }
+    }
+}
+
+/// Event to drop some pickups, typically when an enemy is killed.
+#[derive(Debug, Clone, Copy)]
+pub struct DropPickupsEvent {
+    /// World-space position to drop the pickups at.
+    pub position: Vec3,
+    /// Number of pickups to drop.
+    pub count: u32,
+    /// Kind of all dropped pickups, or `None` to pick a random kind for each.
+    pub kind: Option<PickupKind>,
+}
+
+#[derive(Component)]
+pub struct Pickup(pub PickupKind);
+
+/// Velocity of a pickup, in world units per second, steered by [`update_pickups()`].
+#[derive(Component)]
+struct PickupVelocity(Vec3);
+
+/// Marker for a pickup auto-collected from the point of collection, flying toward the
+/// player until collected.
+#[derive(Component)]
+struct AutoCollected;
+
+#[derive(Default)]
+struct PickupAssets {
+    mesh: Handle<Mesh>,
+    power_material: Handle<StandardMaterial>,
+    score_material: Handle<StandardMaterial>,
+    bomb_material: Handle<StandardMaterial>,
+    star_material: Handle<StandardMaterial>,
+    shield_material: Handle<StandardMaterial>,
+    ram_material: Handle<StandardMaterial>,
+}
+
+impl PickupAssets {
+    fn material(&self, kind: PickupKind) -> Handle<StandardMaterial> {
+        match kind {
+            PickupKind::Power => self.power_material.clone(),
+            PickupKind::Score => self.score_material.clone(),
+            PickupKind::Bomb => self.bomb_material.clone(),
+            PickupKind::Star => self.star_material.clone(),
+            PickupKind::Shield => self.shield_material.clone(),
+            PickupKind::Ram => self.ram_material.clone(),
+        }
+    }
+}
+
+/// Create the mesh and materials shared by all the pickups, once for all games.
+fn setup_pickups(
+    mut pickup_assets: ResMut<PickupAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |kind: PickupKind| {
+        materials.add(StandardMaterial {
+            base_color: kind.color(),
+            unlit: true,
+            ..Default::default()
+        })
+    };
+    pickup_assets.power_material = material(PickupKind::Power);
+    pickup_assets.score_material = material(PickupKind::Score);
+    pickup_assets.bomb_material = material(PickupKind::Bomb);
+    pickup_assets.star_material = material(PickupKind::Star);
+    pickup_assets.shield_material = material(PickupKind::Shield);
+    pickup_assets.ram_material = material(PickupKind::Ram);
+    pickup_assets.mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::splat(0.08),
+        flip: false,
+    }));
+}
+
+fn spawn_pickups(
+    mut commands: Commands,
+    mut drop_events: EventReader<DropPickupsEvent>,
+    pickup_assets: Res<PickupAssets>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let rng = &mut game_rng.0;
+    for ev in drop_events.iter() {
+        for _ in 0..ev.count {
+            let kind = ev.kind.unwrap_or_else(|| PickupKind::random(rng));
+            // Pop out in a random direction, then drift left (see update_pickups())
+            let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+            let speed = 0.5 + rng.gen::<f32>() * 1.0;
+            let velocity = Quat::from_rotation_z(angle).mul_vec3(Vec3::X * speed);
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: pickup_assets.mesh.clone(),
+                    material: pickup_assets.material(kind),
+                    transform: Transform::from_translation(ev.position),
+                    ..Default::default()
+                })
+                .insert(Name::new("Pickup"))
+                .insert(InGameEntity)
+                .insert(Pickup(kind))
+                .insert(PickupVelocity(velocity))
+                .insert(FixedStep::default())
+                // Rendering
+                .insert(NotShadowCaster)
+                .insert(NotShadowReceiver)
+                // Physics
+                .insert(RigidBody::KinematicPositionBased)
+                .insert(CollisionShape::Sphere { radius: 0.06 })
+                .insert(
+                    CollisionLayers::none()
+                        .with_group(Layer::Pickup)
+                        .with_masks(&[Layer::PlayerCollect]),
+                );
+        }
+    }
+}
+
+/// Move the pickups: drift left, or fly toward the player when within its magnet
+/// radius, when auto-collected, or for stars. Crossing the point of collection
+/// auto-collects all the pickups on screen.
+fn update_pickups(
+    mut commands: Commands,
+    mut query: Query<
+        (
+            Entity,
+            &Pickup,
+            &Transform,
+            &mut PickupVelocity,
+            Option<&AutoCollected>,
+        ),
+        Without<PendingDespawn>,
+    >,
+    q_player: Query<(&Transform, &PlayerController), Without<Pickup>>,
+    q_camera: Query<&MainCamera>,
+) {
+    let dt = FIXED_DELTA_SECONDS;
+    let screen_bounds = q_camera.get_single().map_or(
+        Rect::<f32> {
+            left: -3.49,
+            right: 3.49,
+            bottom: -1.96,
+            top: 1.96,
+        },
+        |camera| camera.screen_bounds,
+    );
+    let player_position = match q_player.get_single() {
+        Ok((transform, controller)) if !controller.is_respawning() => Some(transform.translation),
+        _ => None,
+    };
+    let collect_line =
+        screen_bounds.right - (screen_bounds.right - screen_bounds.left) * COLLECT_LINE;
+    let is_past_collect_line = player_position.map_or(false, |position| position.x >= collect_line);
+    const MARGIN: f32 = 0.5;
+    for (entity, pickup, transform, mut velocity, auto_collected) in query.iter_mut() {
+        let mut auto_collected = auto_collected.is_some();
+        if !auto_collected && is_past_collect_line && is_on_screen(transform, &screen_bounds) {
+            commands.entity(entity).insert(AutoCollected);
+            auto_collected = true;
+        }
+        let to_player = player_position.map(|position| position - transform.translation);
+        let (target_velocity, rate) = match (pickup.0, to_player) {
+            (_, Some(to_player)) if auto_collected => {
+                (to_player.normalize_or_zero() * AUTO_COLLECT_SPEED, 8.)
+            }
+            (PickupKind::Star, Some(to_player)) => (to_player.normalize_or_zero() * STAR_SPEED, 2.),
+            (_, Some(to_player)) if to_player.length_squared() < MAGNET_RADIUS * MAGNET_RADIUS => {
+                // Accelerate as the pickup gets closer, so it doesn't orbit the ship
+                let pull = 2. - to_player.length() / MAGNET_RADIUS;
+                (to_player.normalize_or_zero() * MAGNET_SPEED * pull, 6.)
+            }
+            _ => (PICKUP_DRIFT_VELOCITY, 2.),
+        };
+        velocity.0 = velocity.0.lerp(target_velocity, (dt * rate).min(1.));
+        if transform.translation.x < screen_bounds.left - MARGIN {
+            commands.entity(entity).insert(PendingDespawn);
+        }
+    }
+}
+
+fn move_pickups(mut query: Query<(&mut Transform, &PickupVelocity)>) {
+    for (mut transform, velocity) in query.iter_mut() {
+        transform.translation += velocity.0 * FIXED_DELTA_SECONDS;
+    }
+}
+
+fn is_on_screen(transform: &Transform, screen_bounds: &Rect<f32>) -> bool {
+    let position = transform.translation;
+    position.x >= screen_bounds.left
+        && position.x <= screen_bounds.right
+        && position.y >= screen_bounds.bottom
+        && position.y <= screen_bounds.top
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut collision_events: EventReader<CollisionEvent>,
+    q_pickups: Query<&Pickup, Without<PendingDespawn>>,
+    mut q_player: Query<(Entity, &mut PlayerController)>,
+    mut score_events: EventWriter<ScoreEvent>,
+    mut log_events: EventWriter<LogEvent>,
+    mut sfx_events: EventWriter<SfxEvent>,
+) {
+    for event in collision_events.iter() {
+        if let CollisionEvent::Started(data1, data2) = event {
+            let (pickup_data, player_data) =
+                if data1.collision_layers().contains_group(Layer::Pickup) {
+                    (data1, data2)
+                } else if data2.collision_layers().contains_group(Layer::Pickup) {
+                    (data2, data1)
+                } else {
+                    continue;
+                };
+            if !player_data
+                .collision_layers()
+                .contains_group(Layer::PlayerCollect)
+            {
+                continue;
+            }
+            let pickup_entity = pickup_data.rigid_body_entity();
+            let kind = if let Ok(pickup) = q_pickups.get(pickup_entity) {
+                pickup.0
+            } else {
+                continue;
+            };
+            if let Ok((player_entity, mut controller)) =
+                q_player.get_mut(player_data.rigid_body_entity())
+            {
+                if controller.is_respawning() {
+                    continue;
+                }
+                match kind {
+                    PickupKind::Power => {
+                        if controller.add_power(1) {
+                            log_events.send(LogEvent::PowerUp {
+                                tier: controller.power_tier(),
+                            });
+                        }
+                    }
+                    PickupKind::Score => score_events.send(ScoreEvent(PICKUP_SCORE)),
+                    PickupKind::Bomb => controller.add_bomb(),
+                    PickupKind::Star => score_events.send(ScoreEvent(STAR_SCORE)),
+                    // Picking another shield restarts the timer
+                    PickupKind::Shield => {
+                        commands.entity(player_entity).insert(Shield::default());
+                    }
+                    PickupKind::Ram => controller.add_ram(),
+                }
+                sfx_events.send(SfxEvent::new(Sfx::Pickup));
+            }
+            commands.entity(pickup_entity).insert(PendingDespawn);
+        }
+    }
+}
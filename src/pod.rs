@@ -0,0 +1,264 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use heron::prelude::*;
+use std::f32::consts::{PI, TAU};
+
+use crate::{
+    enemy::EnemyController,
+    game::{BulletAtlas, Damage, InGameEntity, MainCamera, PendingDespawn, PlayerController},
+    timestep::{FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState, Layer,
+};
+
+pub struct PodPlugin;
+
+impl Plugin for PodPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MissileAssets>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(pods_setup),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_pods)
+                    .with_system(fire_missiles.after(update_pods))
+                    .with_system(steer_missiles),
+            );
+    }
+}
+
+/// Number of option pods orbiting the player ship.
+const POD_COUNT: usize = 2;
+
+/// Radius of the orbit of the pods around the player ship, in world units.
+const POD_ORBIT_RADIUS: f32 = 0.35;
+
+/// Angular speed of the pods around the player ship, in radians per second.
+const POD_ORBIT_SPEED: f32 = 2.5;
+
+/// Delay between two missiles fired by the same pod, in seconds.
+const MISSILE_FIRE_DELAY: f32 = 0.9;
+
+/// Speed of the missiles, in world units per second.
+const MISSILE_SPEED: f32 = 4.5;
+
+/// Maximum turn rate of the missiles, in radians per second.
+const MISSILE_TURN_RATE: f32 = 5.;
+
+/// Damage dealt by a missile to the enemy it hits.
+const MISSILE_DAMAGE: f32 = 1.;
+
+/// Time after which a missile is despawned if it didn't hit anything, in seconds.
+const MISSILE_LIFETIME: f32 = 3.;
+
+/// Path of the bullet texture used by the missiles. Must be packed in the bullet atlas.
+const MISSILE_TEXTURE: &str = "textures/bullet3.png";
+
+/// Tint of the missiles.
+const MISSILE_COLOR: Color = Color::rgb(1., 0.6, 0.2);
+
+/// Small drone orbiting the player ship, periodically firing homing missiles at the
+/// nearest enemy.
+#[derive(Component)]
+struct OptionPod {
+    /// Offset of the pod along its orbit, in radians.
+    phase: f32,
+    /// Remaining delay before the pod can fire its next missile, in seconds.
+    cooloff: f32,
+}
+
+/// Player missile steering toward the nearest enemy. Unlike a straight
+/// [`Bullet`](crate::game::Bullet), the velocity is updated each step.
+#[derive(Component)]
+struct Missile {
+    velocity: Vec3,
+    /// Time since the missile was fired, in seconds.
+    time: f32,
+}
+
+#[derive(Default)]
+struct MissileAssets {
+    pod_mesh: Handle<Mesh>,
+    pod_material: Handle<StandardMaterial>,
+    missile_mesh: Handle<Mesh>,
+    missile_material: Handle<StandardMaterial>,
+}
+
+fn pods_setup(
+    mut commands: Commands,
+    mut assets: ResMut<MissileAssets>,
+    bullet_atlas: Res<BulletAtlas>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    assets.pod_mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: 0.04,
+        subdivisions: 1,
+    }));
+    assets.pod_material = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.8, 0.85, 0.9),
+        emissive: MISSILE_COLOR * 0.5,
+        ..Default::default()
+    });
+    assets.missile_mesh = meshes.add(bullet_atlas.mesh(MISSILE_TEXTURE, 0.12));
+    assets.missile_material =
+        bullet_atlas.tinted_material(MISSILE_TEXTURE, MISSILE_COLOR, &mut *materials);
+
+    for index in 0..POD_COUNT {
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: assets.pod_mesh.clone(),
+                material: assets.pod_material.clone(),
+                // Placed on the player by update_pods()
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(Name::new(format!("OptionPod#{}", index)))
+            .insert(InGameEntity)
+            .insert(NotShadowReceiver)
+            .insert(FixedStep::default())
+            .insert(OptionPod {
+                phase: index as f32 * TAU / POD_COUNT as f32,
+                // Stagger the pods so they don't fire at the same time
+                cooloff: MISSILE_FIRE_DELAY * (1. + index as f32 / POD_COUNT as f32),
+            });
+    }
+}
+
+/// Move the pods along their orbit around the player ship, and hide them while the
+/// player respawns.
+fn update_pods(
+    mut q_pods: Query<(&mut OptionPod, &mut Transform, &mut Visibility)>,
+    q_player: Query<(&PlayerController, &Transform), Without<OptionPod>>,
+) {
+    let dt = FIXED_DELTA_SECONDS;
+    let player = q_player.get_single().ok();
+    for (mut pod, mut transform, mut visibility) in q_pods.iter_mut() {
+        pod.phase = (pod.phase + POD_ORBIT_SPEED * dt) % TAU;
+        let is_visible = match player {
+            Some((controller, player_transform)) if !controller.is_respawning() => {
+                // Orbit in the XY plane, squashed vertically to stay clear of the ship
+                let offset = Vec3::new(pod.phase.cos(), pod.phase.sin() * 0.6, 0.);
+                transform.translation = player_transform.translation + offset * POD_ORBIT_RADIUS;
+                true
+            }
+            _ => false,
+        };
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+    }
+}
+
+/// Position of the on-screen enemy nearest to the given position, if any. Enemies still
+/// entering or already leaving the screen are ignored, to not waste missiles on them.
+fn nearest_enemy(
+    position: Vec3,
+    q_enemies: &Query<&Transform, (With<EnemyController>, Without<PendingDespawn>)>,
+    camera: &MainCamera,
+) -> Option<Vec3> {
+    q_enemies
+        .iter()
+        .map(|transform| transform.translation)
+        .filter(|translation| !camera.is_outside(*translation, 0.))
+        .min_by(|a, b| {
+            let da = a.distance_squared(position);
+            let db = b.distance_squared(position);
+            da.total_cmp(&db)
+        })
+}
+
+fn fire_missiles(
+    mut commands: Commands,
+    assets: Res<MissileAssets>,
+    mut q_pods: Query<(&mut OptionPod, &Transform, &Visibility)>,
+    q_enemies: Query<&Transform, (With<EnemyController>, Without<PendingDespawn>)>,
+    q_camera: Query<&MainCamera>,
+) {
+    let camera = if let Ok(camera) = q_camera.get_single() {
+        camera
+    } else {
+        return;
+    };
+    let dt = FIXED_DELTA_SECONDS;
+    for (mut pod, transform, visibility) in q_pods.iter_mut() {
+        pod.cooloff = (pod.cooloff - dt).max(0.);
+        if pod.cooloff > 0. || !visibility.is_visible {
+            continue;
+        }
+        if nearest_enemy(transform.translation, &q_enemies, camera).is_none() {
+            continue;
+        }
+        pod.cooloff = MISSILE_FIRE_DELAY;
+
+        // Launch away from the ship, and let the steering curve it toward the target
+        let launch = Vec3::new(1., pod.phase.sin() * 0.8, 0.).normalize();
+        let velocity = launch * MISSILE_SPEED;
+        let angle = velocity.y.atan2(velocity.x);
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: assets.missile_mesh.clone(),
+                material: assets.missile_material.clone(),
+                transform: Transform::from_translation(transform.translation)
+                    .with_rotation(Quat::from_rotation_z(angle)),
+                ..Default::default()
+            })
+            .insert(Missile { velocity, time: 0. })
+            .insert(Damage(MISSILE_DAMAGE))
+            .insert(InGameEntity)
+            // Rendering
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver)
+            // Physics
+            .insert(RigidBody::Dynamic)
+            .insert(CollisionShape::Sphere { radius: 0.05 })
+            .insert(Velocity::from_linear(velocity))
+            .insert(RotationConstraints::lock())
+            .insert(
+                CollisionLayers::none()
+                    .with_group(Layer::PlayerBullet)
+                    .with_masks(&[Layer::World, Layer::Enemy]),
+            );
+    }
+}
+
+/// Rotate the velocity of each missile toward the nearest enemy, up to the max turn
+/// rate, and despawn the missiles which ran out of time.
+fn steer_missiles(
+    mut commands: Commands,
+    mut q_missiles: Query<
+        (Entity, &mut Missile, &mut Transform, &mut Velocity),
+        Without<PendingDespawn>,
+    >,
+    q_enemies: Query<&Transform, (With<EnemyController>, Without<PendingDespawn>)>,
+    q_camera: Query<&MainCamera>,
+) {
+    let dt = FIXED_DELTA_SECONDS;
+    let camera = q_camera.get_single().ok();
+    for (entity, mut missile, mut transform, mut velocity) in q_missiles.iter_mut() {
+        missile.time += dt;
+        if missile.time >= MISSILE_LIFETIME {
+            commands.entity(entity).insert(PendingDespawn);
+            continue;
+        }
+
+        // Without any target left, keep flying straight
+        let target =
+            camera.and_then(|camera| nearest_enemy(transform.translation, &q_enemies, camera));
+        if let Some(target) = target {
+            let to_target = (target - transform.translation).truncate();
+            let current = missile.velocity.y.atan2(missile.velocity.x);
+            let target_angle = to_target.y.atan2(to_target.x);
+            let delta = (target_angle - current + PI).rem_euclid(TAU) - PI;
+            let max_delta = MISSILE_TURN_RATE * dt;
+            let angle = current + delta.clamp(-max_delta, max_delta);
+            missile.velocity = Vec3::new(angle.cos(), angle.sin(), 0.) * MISSILE_SPEED;
+            transform.rotation = Quat::from_rotation_z(angle);
+        }
+        *velocity = Velocity::from_linear(missile.velocity);
+    }
+}
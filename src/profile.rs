@@ -0,0 +1,365 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::LaunchOptions,
+    event_log::LogEvent,
+    game::{ContinueEvent, PlayerController, ScoreEvent, StageClearEvent},
+    save::{self, SaveFormat},
+    ship::Hangar,
+    AppState,
+};
+
+/// Campaign progress recorded into the [`Profile`] as stages are cleared: stages and
+/// ships unlocked, best stage scores and achievements.
+pub struct ProfilePlugin;
+
+impl Plugin for ProfilePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StageProgress>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(progress_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_progress),
+            );
+    }
+}
+
+/// Path of the profile file, relative to the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const PROFILE_PATH: &str = "profile.json";
+
+/// Key of the profile in the browser local storage.
+#[cfg(target_arch = "wasm32")]
+const PROFILE_STORAGE_KEY: &str = "super-kaizen-overloaded/profile";
+
+/// Format of the profile file.
+const PROFILE_FORMAT: SaveFormat = SaveFormat {
+    version: 1,
+    migrate: migrate_profile,
+};
+
+fn migrate_profile(version: u32, _json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // Unversioned files have the same layout as version 1
+        0 => Ok(()),
+        _ => Err(format!("no migration from version {}", version)),
+    }
+}
+
+/// Achievement unlocked by the player, recorded in the [`Profile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Achievement {
+    /// Clear any stage.
+    FirstClear,
+    /// Clear the last stage.
+    AllClear,
+    /// Clear a stage without getting hit.
+    NoMiss,
+    /// Clear a stage without using a bomb.
+    NoBomb,
+}
+
+impl Achievement {
+    /// Identifier of the achievement in the profile file.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Achievement::FirstClear => "first_clear",
+            Achievement::AllClear => "all_clear",
+            Achievement::NoMiss => "no_miss",
+            Achievement::NoBomb => "no_bomb",
+        }
+    }
+}
+
+/// Player profile, persisted to disk, or to the local storage on the web.
+///
+/// Unlike [`Settings`](crate::settings::Settings), which holds user preferences, the
+/// profile holds the player's progress and records. All the unlock checks of the menus
+/// read it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    /// Best run time at the end of each stage, in seconds, indexed by stage, or `None`
+    /// if the stage was never cleared.
+    pub best_splits: Vec<Option<f32>>,
+    /// Best score made within each stage, indexed by stage, or zero if the stage was
+    /// never cleared.
+    pub best_stage_scores: Vec<u32>,
+    /// Number of stages a new game can start at, from the first one. Profiles saved
+    /// before stages were unlocked have zero, which counts as one.
+    stages_unlocked: usize,
+    /// Names of the ships unlocked, beside the ones available from the start.
+    ships_unlocked: Vec<String>,
+    /// Identifiers of the achievements unlocked, see [`Achievement::id()`].
+    achievements: Vec<String>,
+    /// Has the player gone through all the tutorial prompts? They show during each game
+    /// until then.
+    tutorial_completed: bool,
+}
+
+impl Profile {
+    /// Load the profile, or return an empty profile if there's none. A profile which
+    /// cannot be parsed as a whole keeps its valid fields, so that a single bad value
+    /// doesn't wipe all the progress.
+    pub fn load() -> Profile {
+        #[cfg(not(target_arch = "wasm32"))]
+        let loaded = save::load(PROFILE_PATH, &PROFILE_FORMAT);
+        #[cfg(target_arch = "wasm32")]
+        let loaded = match read_profile() {
+            Some(json) => save::from_json(&json, &PROFILE_FORMAT),
+            None => return Profile::default(),
+        };
+
+        match loaded {
+            Ok(loaded) => {
+                if let Some(version) = loaded.migrated_from {
+                    info!(
+                        target: "profile",
+                        "Migrated profile from version {} (backup: {:?})",
+                        version,
+                        loaded.backup
+                    );
+                }
+                loaded.value
+            }
+            Err(save::LoadError::Read(err)) => {
+                info!(target: "profile", "No profile loaded, using new one: {}", err);
+                Profile::default()
+            }
+            Err(err @ save::LoadError::Invalid { .. }) => {
+                match read_profile().and_then(|json| save::salvage::<Profile>(&json)) {
+                    Some((profile, reset)) => {
+                        warn!(
+                            target: "profile",
+                            "Failed to load profile, recovered it with {:?} reset: {}",
+                            reset,
+                            err
+                        );
+                        profile
+                    }
+                    None => {
+                        warn!(target: "profile", "Failed to load profile, using new one: {}", err);
+                        Profile::default()
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(target: "profile", "Failed to load profile, using new one: {}", err);
+                Profile::default()
+            }
+        }
+    }
+
+    /// Save the profile.
+    pub fn save(&self) {
+        let result = save::to_json(self, &PROFILE_FORMAT, true).and_then(write_profile);
+        if let Err(err) = result {
+            error!(target: "profile", "Failed to save profile: {}", err);
+        }
+    }
+
+    /// Erase all the progress and records, and save the empty profile.
+    pub fn reset(&mut self) {
+        info!(target: "profile", "Reset progress");
+        *self = Profile::default();
+        self.save();
+    }
+
+    /// Record the run time at the end of a stage, and return the difference with the
+    /// previous best time for that stage, if any. The best time is updated if beaten.
+    pub fn record_split(&mut self, stage: usize, time: f32) -> Option<f32> {
+        if self.best_splits.len() <= stage {
+            self.best_splits.resize(stage + 1, None);
+        }
+        let best = self.best_splits[stage];
+        if best.map_or(true, |best| time < best) {
+            self.best_splits[stage] = Some(time);
+        }
+        best.map(|best| time - best)
+    }
+
+    /// Record the clear of a stage with the score made within it, unlocking the next
+    /// stage. Return `true` if the score is a new best for that stage.
+    pub fn record_stage_clear(&mut self, stage: usize, is_last: bool, score: u32) -> bool {
+        if !is_last {
+            self.stages_unlocked = self.stages_unlocked.max(stage + 2);
+        }
+        if self.best_stage_scores.len() <= stage {
+            self.best_stage_scores.resize(stage + 1, 0);
+        }
+        let is_best = score > self.best_stage_scores[stage];
+        if is_best {
+            self.best_stage_scores[stage] = score;
+        }
+        is_best
+    }
+
+    /// Number of stages a new game can start at, at least one.
+    pub fn stages_unlocked(&self) -> usize {
+        self.stages_unlocked.max(1)
+    }
+
+    /// Has the ship with the given name been unlocked? Ships available from the start
+    /// are not recorded, see [`ShipDescriptor::unlock_stage`].
+    ///
+    /// [`ShipDescriptor::unlock_stage`]: crate::ship::ShipDescriptor::unlock_stage
+    pub fn is_ship_unlocked(&self, name: &str) -> bool {
+        self.ships_unlocked.iter().any(|ship| ship == name)
+    }
+
+    /// Unlock the ship with the given name. Return `true` if it was locked.
+    pub fn unlock_ship(&mut self, name: &str) -> bool {
+        if self.is_ship_unlocked(name) {
+            return false;
+        }
+        self.ships_unlocked.push(name.to_string());
+        true
+    }
+
+    pub fn is_tutorial_completed(&self) -> bool {
+        self.tutorial_completed
+    }
+
+    /// Record that the player went through all the tutorial prompts.
+    pub fn complete_tutorial(&mut self) {
+        self.tutorial_completed = true;
+    }
+
+    pub fn has_achievement(&self, achievement: Achievement) -> bool {
+        self.achievements.iter().any(|id| id == achievement.id())
+    }
+
+    /// Unlock an achievement. Return `true` if it was locked.
+    pub fn unlock_achievement(&mut self, achievement: Achievement) -> bool {
+        if self.has_achievement(achievement) {
+            return false;
+        }
+        self.achievements.push(achievement.id().to_string());
+        true
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_profile() -> Option<String> {
+    std::fs::read_to_string(PROFILE_PATH).ok()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_profile() -> Option<String> {
+    local_storage()?
+        .get_item(PROFILE_STORAGE_KEY)
+        .ok()
+        .flatten()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_profile(json: String) -> Result<(), String> {
+    save::write(PROFILE_PATH, &json).map_err(|err| err.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_profile(json: String) -> Result<(), String> {
+    local_storage()
+        .ok_or_else(|| "no local storage".to_string())?
+        .set_item(PROFILE_STORAGE_KEY, &json)
+        .map_err(|err| format!("{:?}", err))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Progress within the current stage, to record into the [`Profile`] once cleared.
+#[derive(Debug, Default)]
+struct StageProgress {
+    /// Score made since the start of the stage.
+    score: u32,
+    /// Hits taken by the player before the start of the stage.
+    hits_taken: u32,
+    /// Bombs used by the player before the start of the stage.
+    bombs_used: u32,
+}
+
+fn progress_setup(mut commands: Commands) {
+    commands.insert_resource(StageProgress::default());
+}
+
+/// Record each stage cleared into the profile, unlocking the next stage, ships and
+/// achievements, and save it. Benchmarks and simulations don't make any progress.
+fn update_progress(
+    mut progress: ResMut<StageProgress>,
+    mut profile: ResMut<Profile>,
+    hangar: Res<Hangar>,
+    launch: Res<LaunchOptions>,
+    q_player: Query<&PlayerController>,
+    mut score_events: EventReader<ScoreEvent>,
+    mut continue_events: EventReader<ContinueEvent>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    mut log_events: EventWriter<LogEvent>,
+) {
+    if continue_events.iter().count() > 0 {
+        // Like the score display, the score restarts from zero on continue
+        progress.score = 0;
+    }
+    for ev in score_events.iter() {
+        progress.score += ev.0;
+    }
+
+    for ev in stage_clear_events.iter() {
+        let (hits_taken, bombs_used) = q_player
+            .get_single()
+            .map_or((progress.hits_taken, progress.bombs_used), |controller| {
+                (controller.hits_taken(), controller.bombs_used())
+            });
+        if !launch.bench && launch.simulate.is_none() {
+            if profile.record_stage_clear(ev.stage, ev.is_last, progress.score) {
+                info!(
+                    target: "profile",
+                    "New best score for stage {}: {}",
+                    ev.stage + 1,
+                    progress.score
+                );
+            }
+
+            let stages_unlocked = profile.stages_unlocked();
+            for ship in hangar.ships() {
+                if ship
+                    .unlock_stage
+                    .map_or(false, |stage| stage <= stages_unlocked)
+                    && profile.unlock_ship(&ship.name)
+                {
+                    info!(target: "profile", "Unlocked ship '{}'", ship.name);
+                }
+            }
+
+            let mut achievements = vec![Achievement::FirstClear];
+            if ev.is_last {
+                achievements.push(Achievement::AllClear);
+            }
+            if hits_taken == progress.hits_taken {
+                achievements.push(Achievement::NoMiss);
+            }
+            if bombs_used == progress.bombs_used {
+                achievements.push(Achievement::NoBomb);
+            }
+            for achievement in achievements {
+                if profile.unlock_achievement(achievement) {
+                    log_events.send(LogEvent::Achievement {
+                        id: achievement.id(),
+                    });
+                }
+            }
+
+            profile.save();
+        }
+
+        progress.score = 0;
+        progress.hits_taken = hits_taken;
+        progress.bombs_used = bombs_used;
+    }
+}
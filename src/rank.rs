@@ -0,0 +1,326 @@
+use bevy::prelude::*;
+
+use crate::{
+    enemy::EnemyKilledEvent,
+    game::{EnemyBullet, GameTime, InGameEntity, PlayerController, PlayerHitboxes},
+    settings::Settings,
+    timestep::AddFixedEvent,
+    tuning::{RankTuning, Tuning},
+    AppState,
+};
+
+pub struct RankPlugin;
+
+impl Plugin for RankPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_fixed_event::<GrazeEvent>()
+            .init_resource::<Rank>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(rank_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(detect_graze)
+                    .with_system(update_rank.after(detect_graze))
+                    .with_system(update_rank_hud.after(update_rank)),
+            );
+    }
+}
+
+/// A single rank level, displayed as a medal with a letter.
+struct RankLevel {
+    letter: &'static str,
+    /// Medal color.
+    color: Color,
+}
+
+const RANK_LEVELS: &[RankLevel] = &[
+    RankLevel {
+        letter: "E",
+        color: Color::rgb(0.45, 0.45, 0.45),
+    },
+    RankLevel {
+        letter: "D",
+        color: Color::rgb(0.55, 0.35, 0.2),
+    },
+    RankLevel {
+        letter: "C",
+        color: Color::rgb(0.8, 0.5, 0.2),
+    },
+    RankLevel {
+        letter: "B",
+        color: Color::rgb(0.75, 0.75, 0.8),
+    },
+    RankLevel {
+        letter: "A",
+        color: Color::rgb(0.95, 0.8, 0.2),
+    },
+    RankLevel {
+        letter: "S",
+        color: Color::rgb(0.22, 0.76, 0.75),
+    },
+];
+
+/// Dynamic difficulty rank of the current game.
+///
+/// The rank rises as the player performs well, by killing enemies, grazing bullets
+/// and surviving, and falls when they die or bomb, by the amounts of the
+/// [`RankTuning`]. Enemies scale their fire tags with
+/// [`Rank::factor()`].
+pub struct Rank {
+    /// Current rank, from 0 (lowest) to the number of rank levels minus one (highest).
+    /// The fractional part is the progress toward the next level.
+    pub value: f32,
+    /// Value displayed on the HUD, smoothly following the actual value.
+    display_value: f32,
+    /// Was the player respawning last frame? Used to detect deaths.
+    was_respawning: bool,
+    /// Number of bombs used by the player at last frame. Used to detect bombs.
+    bombs_used: u32,
+    /// Number of bullets grazed since last frame.
+    grazes: u32,
+}
+
+impl Default for Rank {
+    fn default() -> Self {
+        Rank {
+            value: 0.,
+            display_value: 0.,
+            was_respawning: false,
+            bombs_used: 0,
+            grazes: 0,
+        }
+    }
+}
+
+impl Rank {
+    /// Index of the current rank level.
+    pub fn level(&self) -> usize {
+        (self.value.floor() as usize).min(RANK_LEVELS.len() - 1)
+    }
+
+    /// Rank normalized to `[0:1]`, where 1 is the highest rank.
+    pub fn factor(&self) -> f32 {
+        self.value / (RANK_LEVELS.len() - 1) as f32
+    }
+
+    /// Set the rank, clamped to the valid range.
+    pub fn set(&mut self, value: f32) {
+        self.value = value.clamp(0., (RANK_LEVELS.len() - 1) as f32);
+    }
+
+    fn add(&mut self, amount: f32) {
+        self.value = (self.value + amount).clamp(0., (RANK_LEVELS.len() - 1) as f32);
+    }
+}
+
+/// Event sent when the player grazes an enemy bullet.
+#[derive(Debug, Clone, Copy)]
+pub struct GrazeEvent;
+
+/// Marker for an enemy bullet already grazed by the player, to count it only once.
+#[derive(Component)]
+struct Grazed;
+
+/// Marker for all entities of the rank HUD, to toggle their visibility together.
+#[derive(Component)]
+struct RankHud;
+
+#[derive(Component)]
+struct RankMedal;
+
+#[derive(Component)]
+struct RankLetter;
+
+/// Bar showing the progress toward the next rank level.
+#[derive(Component)]
+struct RankProgress;
+
+fn rank_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(Rank::default());
+
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(140.0),
+                    right: Val::Px(50.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(40.), Val::Px(46.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(RANK_LEVELS[0].color),
+            ..Default::default()
+        })
+        .insert(Name::new("RankHud"))
+        .insert(InGameEntity)
+        .insert(RankHud)
+        .insert(RankMedal)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        RANK_LEVELS[0].letter,
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 36.0,
+                            color: Color::rgb_u8(32, 32, 32),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(RankHud)
+                .insert(RankLetter);
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: Rect {
+                            bottom: Val::Px(0.),
+                            left: Val::Px(0.),
+                            ..Default::default()
+                        },
+                        size: Size::new(Val::Percent(0.), Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::rgb_u8(32, 32, 32)),
+                    ..Default::default()
+                })
+                .insert(RankHud)
+                .insert(RankProgress);
+        });
+}
+
+/// Count the enemy bullets passing close to the vulnerable player.
+fn detect_graze(
+    mut commands: Commands,
+    mut rank: ResMut<Rank>,
+    mut graze_events: EventWriter<GrazeEvent>,
+    q_player: Query<(&Transform, &PlayerHitboxes, &PlayerController)>,
+    q_bullets: Query<(Entity, &Transform), (With<EnemyBullet>, Without<Grazed>)>,
+) {
+    let (player_position, graze_radius) = match q_player.get_single() {
+        Ok((transform, hitboxes, controller)) if controller.is_vulnerable() => (
+            hitboxes.hurt_position(transform.translation),
+            hitboxes.graze_radius,
+        ),
+        _ => return,
+    };
+    for (entity, transform) in q_bullets.iter() {
+        if transform.translation.distance_squared(player_position) < graze_radius * graze_radius {
+            commands.entity(entity).insert(Grazed);
+            rank.grazes += 1;
+            graze_events.send(GrazeEvent);
+        }
+    }
+}
+
+fn update_rank(
+    mut rank: ResMut<Rank>,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    q_player: Query<&PlayerController>,
+    game_time: Res<GameTime>,
+    tuning: Res<Tuning>,
+) {
+    let dt = game_time.delta_seconds();
+    let RankTuning {
+        kill_gain,
+        boss_kill_gain,
+        graze_gain,
+        survival_gain,
+        death_penalty,
+        bomb_penalty,
+    } = tuning.rank;
+
+    let mut gain = 0.;
+    for ev in killed_events.iter() {
+        gain += if ev.is_boss {
+            boss_kill_gain
+        } else {
+            kill_gain
+        };
+    }
+    gain += rank.grazes as f32 * graze_gain;
+    rank.grazes = 0;
+
+    if let Ok(controller) = q_player.get_single() {
+        let is_respawning = controller.is_respawning();
+        if is_respawning && !rank.was_respawning {
+            debug!(target: "rank", "RANK: death penalty");
+            gain -= death_penalty;
+        } else if !is_respawning {
+            gain += survival_gain * dt;
+        }
+        rank.was_respawning = is_respawning;
+
+        let bombs_used = controller.bombs_used();
+        if bombs_used > rank.bombs_used {
+            gain -= bomb_penalty * (bombs_used - rank.bombs_used) as f32;
+            rank.bombs_used = bombs_used;
+        }
+    }
+    if gain != 0. {
+        rank.add(gain);
+    }
+
+    // Smoothly follow the actual value
+    let display_value = rank.display_value;
+    let target = rank.value;
+    if (target - display_value).abs() > 1e-3 {
+        rank.display_value = display_value + (target - display_value) * (dt * 3.).min(1.);
+    } else if display_value != target {
+        rank.display_value = target;
+    }
+}
+
+fn update_rank_hud(
+    rank: Res<Rank>,
+    settings: Res<Settings>,
+    mut q_visibility: Query<&mut Visibility, With<RankHud>>,
+    mut q_medal: Query<&mut UiColor, With<RankMedal>>,
+    mut q_letter: Query<&mut Text, With<RankLetter>>,
+    mut q_progress: Query<&mut Style, With<RankProgress>>,
+) {
+    let show_rank = settings.gameplay.show_rank;
+    for mut visibility in q_visibility.iter_mut() {
+        if visibility.is_visible != show_rank {
+            visibility.is_visible = show_rank;
+        }
+    }
+    if !show_rank || !rank.is_changed() {
+        return;
+    }
+
+    let value = rank.display_value;
+    let index = (value.floor() as usize).min(RANK_LEVELS.len() - 1);
+    let next_index = (index + 1).min(RANK_LEVELS.len() - 1);
+    let progress = if index == next_index {
+        1.
+    } else {
+        value.fract()
+    };
+
+    // Blend the medal color toward the next level as the rank progresses
+    let color = Vec4::from(RANK_LEVELS[index].color)
+        .lerp(Vec4::from(RANK_LEVELS[next_index].color), progress);
+    for mut ui_color in q_medal.iter_mut() {
+        ui_color.0 = color.into();
+    }
+    for mut text in q_letter.iter_mut() {
+        if text.sections[0].value != RANK_LEVELS[index].letter {
+            text.sections[0].value = RANK_LEVELS[index].letter.to_string();
+        }
+    }
+    for mut style in q_progress.iter_mut() {
+        style.size.width = Val::Percent(progress * 100.);
+    }
+}
@@ -0,0 +1,646 @@
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use leafwing_input_manager::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::LaunchOptions,
+    game::{
+        own_scene_material, ContinueEvent, DebugCheats, GameOverEvent, GameTime, InGameEntity,
+        PlayerController, RunSeed, ScoreEvent, StageClearEvent,
+    },
+    highscore::{spawn_name_entry, HighScores, NameEntry},
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    save::{self, SaveFormat},
+    settings::Settings,
+    ship::Hangar,
+    AppState,
+};
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>()
+            .init_resource::<Ghost>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(replay_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(record_replay)
+                    .with_system(show_pending_save_prompt.before(record_replay))
+                    .with_system(update_ghost.after(record_replay))
+                    .with_system(setup_ghost_materials)
+                    .with_system(update_save_prompt.after(record_replay)),
+            );
+    }
+}
+
+/// Directory where replays are saved, relative to the working directory.
+const REPLAY_DIR: &str = "replays";
+
+/// File name of the best replay, which drives the ghost.
+const BEST_REPLAY_FILE: &str = "best.json";
+
+/// Prefix of the file names of the replays saved from the end-of-run prompt.
+const AUTO_SAVE_PREFIX: &str = "auto_";
+
+/// Number of auto-saved replays kept. Older ones are deleted when saving a new one.
+const MAX_AUTO_SAVES: usize = 10;
+
+/// Delay before the save prompt accepts any input, in seconds, so that the player
+/// still mashing the fire button doesn't answer it by accident.
+const PROMPT_INPUT_DELAY: f32 = 0.6;
+
+/// Format of the replay files.
+const REPLAY_FORMAT: SaveFormat = SaveFormat {
+    version: 1,
+    migrate: migrate_replay,
+};
+
+fn migrate_replay(version: u32, _json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // Unversioned files have the same layout as version 1
+        0 => Ok(()),
+        _ => Err(format!("no migration from version {}", version)),
+    }
+}
+
+/// Color of the ghost, multiplied with the colors of the ship model.
+const GHOST_COLOR: Color = Color::rgba(0.4, 0.9, 1.0, 0.35);
+
+/// Interval between two recorded frames, in seconds.
+const RECORD_INTERVAL: f32 = 1. / 30.;
+
+/// Single recorded frame of a replay.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    /// Run time of the frame, in seconds.
+    pub time: f32,
+    /// Player position.
+    pub position: Vec2,
+}
+
+/// Recording of a run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Replay {
+    /// Final score of the run.
+    pub score: u32,
+    /// Last stage reached, starting from 1.
+    pub stage: u32,
+    /// Stage the run started at, starting from 1, or zero if unknown.
+    pub start_stage: u32,
+    /// Name of the ship of the run, or empty if unknown.
+    pub ship: String,
+    /// End date of the run, in seconds since the Unix epoch, or zero if unknown.
+    pub date: u64,
+    /// Seed of the run, to reproduce its random decorations.
+    pub seed: u64,
+    /// Was the run played with assists? See [`GameplaySettings::is_assisted()`].
+    ///
+    /// [`GameplaySettings::is_assisted()`]: crate::settings::GameplaySettings::is_assisted
+    pub assisted: bool,
+    /// Were debug cheats or console commands used during the run?
+    pub tainted: bool,
+    /// Recorded frames, in increasing time order.
+    pub frames: Vec<ReplayFrame>,
+}
+
+impl Replay {
+    /// Load a replay from the given file of the replay directory.
+    pub fn load(file_name: &str) -> Option<Replay> {
+        Replay::load_path(&std::path::Path::new(REPLAY_DIR).join(file_name))
+    }
+
+    /// Load a replay from the given file, or from the file of the replay directory with
+    /// that name if there is no such file, as given on the command line.
+    pub fn load_file(file: &str) -> Option<Replay> {
+        let path = std::path::Path::new(file);
+        if path.is_file() {
+            Replay::load_path(path)
+        } else {
+            Replay::load(file)
+        }
+    }
+
+    fn load_path(path: &std::path::Path) -> Option<Replay> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            match save::load(path, &REPLAY_FORMAT) {
+                Ok(loaded) => {
+                    if let Some(version) = loaded.migrated_from {
+                        info!(
+                            target: "replay",
+                            "Migrated replay {:?} from version {} (backup: {:?})",
+                            path,
+                            version,
+                            loaded.backup
+                        );
+                    }
+                    return Some(loaded.value);
+                }
+                Err(save::LoadError::Read(err)) => {
+                    info!(target: "replay", "No replay loaded from {:?}: {}", path, err)
+                }
+                Err(err) => warn!(target: "replay", "Failed to load replay {:?}: {}", path, err),
+            }
+        }
+        None
+    }
+
+    /// Save the replay to the given file of the replay directory.
+    pub fn save(&self, file_name: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = std::path::Path::new(REPLAY_DIR).join(file_name);
+            let result = std::fs::create_dir_all(REPLAY_DIR)
+                .map_err(|err| err.to_string())
+                .and_then(|_| save::to_json(self, &REPLAY_FORMAT, false))
+                .and_then(|json| std::fs::write(&path, json).map_err(|err| err.to_string()));
+            if let Err(err) = result {
+                error!(target: "replay", "Failed to save replay {:?}: {}", path, err);
+            }
+        }
+    }
+
+    /// Save the replay under a name generated from its date, stage and score, then
+    /// delete the oldest auto-saves beyond [`MAX_AUTO_SAVES`]. Return the file name.
+    pub fn auto_save(&self) -> String {
+        let file_name = format!(
+            "{}{}_stage{}_{}.json",
+            AUTO_SAVE_PREFIX,
+            format_date(self.date, "", "-", ""),
+            self.stage,
+            self.score
+        );
+        self.save(&file_name);
+
+        let mut auto_saves: Vec<String> = Replay::list()
+            .into_iter()
+            .filter(|file_name| file_name.starts_with(AUTO_SAVE_PREFIX))
+            .collect();
+        // Names start with the date, so the newest replays come first
+        if auto_saves.len() > MAX_AUTO_SAVES {
+            for old_file in auto_saves.drain(MAX_AUTO_SAVES..) {
+                info!(target: "replay", "Deleting old replay {}", old_file);
+                #[cfg(not(target_arch = "wasm32"))]
+                if let Err(err) =
+                    std::fs::remove_file(std::path::Path::new(REPLAY_DIR).join(&old_file))
+                {
+                    warn!(target: "replay", "Failed to delete replay {}: {}", old_file, err);
+                }
+            }
+        }
+
+        file_name
+    }
+
+    /// List the file names of the saved replays, excluding the best replay, in reverse
+    /// alphabetical order so that auto-saves are sorted from the newest one.
+    pub fn list() -> Vec<String> {
+        let mut file_names = vec![];
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(entries) = std::fs::read_dir(REPLAY_DIR) {
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if file_name.ends_with(".json") && file_name != BEST_REPLAY_FILE {
+                    file_names.push(file_name);
+                }
+            }
+        }
+        file_names.sort_unstable_by(|a, b| b.cmp(a));
+        file_names
+    }
+
+    /// Player position at the given run time, interpolated between the recorded frames,
+    /// or `None` if the time is outside of the recording.
+    pub fn position_at(&self, time: f32) -> Option<Vec2> {
+        let index = self.frames.partition_point(|frame| frame.time <= time);
+        if index == 0 || index >= self.frames.len() {
+            return None;
+        }
+        let prev = &self.frames[index - 1];
+        let next = &self.frames[index];
+        let t = (time - prev.time) / (next.time - prev.time).max(1e-6);
+        Some(prev.position.lerp(next.position, t))
+    }
+
+    /// Does the run compete for the high scores and the best replay?
+    pub fn is_ranked(&self) -> bool {
+        !self.assisted && !self.tainted
+    }
+
+    /// Was the run played with the given ship from the given stage, starting from 1?
+    /// Only such a run makes a meaningful ghost to race against.
+    pub fn is_comparable(&self, ship: &str, start_stage: u32) -> bool {
+        self.ship == ship && self.start_stage == start_stage
+    }
+}
+
+/// Current date, in seconds since the Unix epoch. Always zero on the web, where
+/// replays are not saved anyway.
+fn now() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Ok(duration) = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH) {
+        return duration.as_secs();
+    }
+    0
+}
+
+/// Format a date in seconds since the Unix epoch as UTC year-month-day and
+/// hour-minute, with the given separators between the fields, the date and the time,
+/// and the hours and minutes.
+pub fn format_date(date: u64, date_sep: &str, sep: &str, time_sep: &str) -> String {
+    let days = (date / 86400) as i64;
+    let seconds = date % 86400;
+    // Civil calendar from days since the epoch, after Howard Hinnant's algorithm
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}{}{:02}{}{:02}{}{:02}{}{:02}",
+        year,
+        date_sep,
+        month,
+        date_sep,
+        day,
+        sep,
+        seconds / 3600,
+        time_sep,
+        seconds % 3600 / 60
+    )
+}
+
+/// Records the current run.
+#[derive(Default)]
+pub struct ReplayRecorder {
+    /// Replay being recorded.
+    pub replay: Replay,
+    /// Run time, in seconds.
+    time: f32,
+    /// Run time of the next frame to record.
+    next_frame_time: f32,
+    recording: bool,
+    /// Show the save prompt once the name of the new high score is entered.
+    prompt_pending: bool,
+}
+
+impl ReplayRecorder {
+    /// Mark the run being recorded as tainted by a debug cheat or console command, so
+    /// that it's not ranked. See [`Replay::tainted`].
+    pub fn taint(&mut self) {
+        if self.recording && !self.replay.tainted {
+            info!(target: "replay", "Run tainted by a debug cheat, it won't be ranked");
+            self.replay.tainted = true;
+        }
+    }
+}
+
+/// Best replay, played back as a translucent ghost of the player ship.
+#[derive(Default)]
+struct Ghost {
+    replay: Option<Replay>,
+    /// Score of the best replay, which may differ from the ghost one if a replay was
+    /// given on the command line.
+    best_score: u32,
+}
+
+/// Root of the model of the ghost, whose meshes are made translucent once spawned, see
+/// [`setup_ghost_materials()`].
+#[derive(Component)]
+struct GhostShip;
+
+/// Prompt shown at the end of a run, asking whether to save the recorded replay.
+#[derive(Component)]
+struct SaveReplayPrompt {
+    /// Time since the prompt appeared, in seconds.
+    time: f32,
+}
+
+fn replay_setup(
+    mut commands: Commands,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut ghost: ResMut<Ghost>,
+    settings: Res<Settings>,
+    launch: Res<LaunchOptions>,
+    cheats: Res<DebugCheats>,
+    hangar: Res<Hangar>,
+    asset_server: Res<AssetServer>,
+) {
+    *recorder = ReplayRecorder {
+        recording: true,
+        ..Default::default()
+    };
+    let ship = hangar.ship();
+    let start_stage = launch.start_stage() as u32 + 1;
+    recorder.replay.stage = start_stage;
+    recorder.replay.start_stage = start_stage;
+    recorder.replay.ship = ship.name.clone();
+    recorder.replay.assisted = settings.gameplay.is_assisted();
+    // Cheats stay on from one game to the next
+    recorder.replay.tainted = cheats.is_active();
+
+    // A replay given on the command line is always shown, instead of the best one
+    let best = Replay::load(BEST_REPLAY_FILE);
+    ghost.best_score = best.as_ref().map_or(0, |replay| replay.score);
+    let show_ghost = if let Some(file) = &launch.replay {
+        ghost.replay = Replay::load_file(file);
+        true
+    } else {
+        ghost.replay = best;
+        settings.gameplay.show_ghost
+    };
+    let replay = match &ghost.replay {
+        Some(replay) if show_ghost => replay,
+        _ => return,
+    };
+    if !replay.is_comparable(&ship.name, start_stage) {
+        info!(
+            target: "replay",
+            "Ghost hidden: replay of ship '{}' from stage {}, playing ship '{}' from stage {}",
+            replay.ship,
+            replay.start_stage,
+            ship.name,
+            start_stage
+        );
+        return;
+    }
+
+    commands
+        .spawn_bundle((
+            Transform::from_scale(Vec3::splat(ship.scale)),
+            GlobalTransform::identity(),
+        ))
+        .insert(Name::new("Ghost"))
+        .insert(InGameEntity)
+        .insert(GhostShip)
+        .with_children(|parent| {
+            parent.spawn_scene(asset_server.load(&ship.model));
+        });
+}
+
+/// Give the meshes of the ghost model their own translucent copy of their material,
+/// once spawned.
+fn setup_ghost_materials(
+    mut commands: Commands,
+    mut q_materials: Query<
+        (Entity, &mut Handle<StandardMaterial>),
+        Added<Handle<StandardMaterial>>,
+    >,
+    q_parents: Query<&Parent>,
+    q_ghosts: Query<(), With<GhostShip>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, mut handle) in q_materials.iter_mut() {
+        let ghost = own_scene_material(
+            entity,
+            &mut handle,
+            &q_parents,
+            |ancestor| q_ghosts.contains(ancestor),
+            &mut materials,
+        );
+        if ghost.is_none() {
+            continue;
+        }
+        if let Some(material) = materials.get_mut(&*handle) {
+            material.base_color = GHOST_COLOR;
+            material.unlit = true;
+            material.alpha_mode = AlphaMode::Blend;
+        }
+        commands.entity(entity).insert(NotShadowCaster);
+    }
+}
+
+fn record_replay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut recorder: ResMut<ReplayRecorder>,
+    mut ghost: ResMut<Ghost>,
+    run_seed: Res<RunSeed>,
+    high_scores: Res<HighScores>,
+    game_time: Res<GameTime>,
+    q_player: Query<&Transform, With<PlayerController>>,
+    mut score_events: EventReader<ScoreEvent>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    mut game_over_events: EventReader<GameOverEvent>,
+    mut continue_events: EventReader<ContinueEvent>,
+) {
+    if !recorder.recording {
+        return;
+    }
+
+    recorder.time += game_time.delta_seconds();
+    if continue_events.iter().count() > 0 {
+        // Like the score display, the score restarts from zero on continue
+        recorder.replay.score = 0;
+    }
+    for ev in score_events.iter() {
+        recorder.replay.score += ev.0;
+    }
+
+    if recorder.time >= recorder.next_frame_time {
+        if let Ok(transform) = q_player.get_single() {
+            let time = recorder.time;
+            recorder.replay.frames.push(ReplayFrame {
+                time,
+                position: transform.translation.truncate(),
+            });
+        }
+        recorder.next_frame_time += RECORD_INTERVAL;
+    }
+
+    let mut finished = game_over_events.iter().count() > 0;
+    for ev in stage_clear_events.iter() {
+        if ev.is_last {
+            finished = true;
+        } else {
+            recorder.replay.stage = ev.stage as u32 + 2;
+        }
+    }
+    if finished {
+        recorder.recording = false;
+        recorder.replay.date = now();
+        recorder.replay.seed = run_seed.seed;
+        // Unranked runs can still be saved from the prompt, but don't compete
+        let is_ranked = recorder.replay.is_ranked();
+        if is_ranked && high_scores.qualifies(recorder.replay.score) {
+            spawn_name_entry(&mut commands, &asset_server, &high_scores);
+            recorder.prompt_pending = true;
+        } else {
+            spawn_save_prompt(&mut commands, &asset_server, &recorder.replay);
+        }
+        if is_ranked && recorder.replay.score > ghost.best_score {
+            info!(target: "replay", "New best replay: score={}", recorder.replay.score);
+            recorder.replay.save(BEST_REPLAY_FILE);
+            ghost.best_score = recorder.replay.score;
+            ghost.replay = Some(recorder.replay.clone());
+        }
+    }
+}
+
+fn update_ghost(
+    recorder: Res<ReplayRecorder>,
+    ghost: Res<Ghost>,
+    mut q_ghosts: Query<(Entity, &mut Transform), With<GhostShip>>,
+    q_children: Query<&Children>,
+    mut q_visibility: Query<&mut Visibility>,
+) {
+    let position = ghost
+        .replay
+        .as_ref()
+        .filter(|_| recorder.recording)
+        .and_then(|replay| replay.position_at(recorder.time));
+    for (ghost_entity, mut transform) in q_ghosts.iter_mut() {
+        if let Some(position) = position {
+            transform.translation = position.extend(0.);
+        }
+        // The visibility doesn't propagate, so apply it to the meshes deep below
+        let is_visible = position.is_some();
+        let mut entities = vec![ghost_entity];
+        while let Some(entity) = entities.pop() {
+            if let Ok(mut visibility) = q_visibility.get_mut(entity) {
+                if visibility.is_visible != is_visible {
+                    visibility.is_visible = is_visible;
+                }
+            }
+            if let Ok(children) = q_children.get(entity) {
+                entities.extend(children.iter().copied());
+            }
+        }
+    }
+}
+
+fn show_pending_save_prompt(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut recorder: ResMut<ReplayRecorder>,
+    q_name_entry: Query<(), With<NameEntry>>,
+) {
+    if recorder.prompt_pending && q_name_entry.is_empty() {
+        recorder.prompt_pending = false;
+        spawn_save_prompt(&mut commands, &asset_server, &recorder.replay);
+    }
+}
+
+fn spawn_save_prompt(commands: &mut Commands, asset_server: &AssetServer, replay: &Replay) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let color = Color::rgb_u8(224, 224, 224);
+    let focus_color = Color::rgb_u8(57, 194, 190);
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color,
+            },
+            Default::default(),
+        )
+    };
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Percent(35.),
+                    left: Val::Percent(30.),
+                    right: Val::Percent(30.),
+                    ..Default::default()
+                },
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::rgba(0., 0., 0., 0.6)),
+            ..Default::default()
+        })
+        .insert(Name::new("SaveReplayPrompt"))
+        .insert(InGameEntity)
+        .insert(SaveReplayPrompt { time: 0. })
+        .insert_bundle(NavMenuBundle::new(2))
+        .with_children(|parent| {
+            let menu_entity = parent.parent_entity();
+            parent.spawn_bundle(TextBundle {
+                text: text("Save replay?".to_string(), 40.),
+                ..Default::default()
+            });
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(8.)),
+                    ..Default::default()
+                },
+                text: text(
+                    format!(
+                        "Stage {}  {}  {}",
+                        replay.stage,
+                        replay.score,
+                        format_date(replay.date, "-", " ", ":")
+                    ),
+                    24.,
+                ),
+                ..Default::default()
+            });
+            for (index, label) in ["Yes", "No"].iter().enumerate() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            ..Default::default()
+                        },
+                        text: text(label.to_string(), 32.),
+                        ..Default::default()
+                    })
+                    .insert(FocusHighlight {
+                        menu: menu_entity,
+                        index,
+                        color,
+                        focus_color,
+                    });
+            }
+        });
+}
+
+fn update_save_prompt(
+    mut commands: Commands,
+    mut q_prompt: Query<(
+        Entity,
+        &mut SaveReplayPrompt,
+        &mut NavMenu,
+        &ActionState<NavAction>,
+    )>,
+    recorder: Res<ReplayRecorder>,
+    time: Res<Time>,
+) {
+    let (entity, mut prompt, mut nav, action_state) = if let Ok(prompt) = q_prompt.get_single_mut()
+    {
+        prompt
+    } else {
+        return;
+    };
+
+    prompt.time += time.delta_seconds();
+    if prompt.time < PROMPT_INPUT_DELAY {
+        return;
+    }
+
+    nav.navigate(action_state);
+    let save = action_state.just_pressed(NavAction::Confirm) && nav.focused == 0;
+    if save {
+        let file_name = recorder.replay.auto_save();
+        info!(target: "replay", "Saved replay {}", file_name);
+    }
+    if action_state.just_pressed(NavAction::Confirm) || action_state.just_pressed(NavAction::Back) {
+        commands.entity(entity).despawn_recursive();
+    }
+}
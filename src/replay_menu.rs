@@ -0,0 +1,158 @@
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    flow::RequestStateChange,
+    music::AudioFacade,
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    replay::{format_date, Replay},
+    AppState,
+};
+
+pub struct ReplayMenuPlugin;
+
+impl Plugin for ReplayMenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Replays).with_system(replay_menu_setup))
+            .add_system_set(SystemSet::on_update(AppState::Replays).with_system(replay_menu_run))
+            .add_system_set(SystemSet::on_exit(AppState::Replays).with_system(replay_menu_cleanup));
+    }
+}
+
+/// Maximum number of replays listed.
+const MAX_LISTED_REPLAYS: usize = 10;
+
+#[derive(Component, Default)]
+struct ReplayMenu {
+    sound_click: Handle<KiraAudioSource>,
+}
+
+/// Marker for all entities of the replays screen, for cleanup.
+#[derive(Component)]
+struct ReplayMenuScreen;
+
+fn replay_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    debug!(target: "setup", "replay_menu_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(ReplayMenuScreen);
+
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    let text_color = Color::rgb_u8(32, 32, 32);
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: text_color,
+            },
+            Default::default(),
+        )
+    };
+
+    let mut menu = ReplayMenu::default();
+    menu.sound_click = asset_server.load("sounds/click4.ogg");
+
+    // Newest replays first; entries which fail to load are skipped
+    let replays: Vec<Replay> = Replay::list()
+        .iter()
+        .filter_map(|file_name| Replay::load(file_name))
+        .take(MAX_LISTED_REPLAYS)
+        .collect();
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect::all(Val::Px(0.)),
+                margin: Rect::all(Val::Px(16.)),
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("replays"))
+        .insert(ReplayMenuScreen)
+        .insert(menu)
+        .insert_bundle(NavMenuBundle::new(1))
+        .with_children(|parent| {
+            let menu_entity = parent.parent_entity();
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(24.)),
+                    ..Default::default()
+                },
+                text: text("Replays".to_string(), 64.),
+                ..Default::default()
+            });
+
+            if replays.is_empty() {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    text: text("No saved replay".to_string(), 28.),
+                    ..Default::default()
+                });
+            }
+            for replay in &replays {
+                let date = if replay.date > 0 {
+                    format_date(replay.date, "-", " ", ":")
+                } else {
+                    "----------------".to_string()
+                };
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    text: text(
+                        format!("{}  Stage {}  {:>8}", date, replay.stage, replay.score),
+                        28.,
+                    ),
+                    ..Default::default()
+                });
+            }
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(24.)),
+                        ..Default::default()
+                    },
+                    text: text("Back".to_string(), 36.),
+                    ..Default::default()
+                })
+                .insert(FocusHighlight {
+                    menu: menu_entity,
+                    index: 0,
+                    color: text_color,
+                    focus_color: Color::rgb_u8(57, 194, 190),
+                });
+        });
+}
+
+fn replay_menu_run(
+    q_menu: Query<(&ReplayMenu, &ActionState<NavAction>), With<NavMenu>>,
+    audio_facade: AudioFacade,
+    mut state_requests: EventWriter<RequestStateChange>,
+) {
+    let (menu, action_state) = q_menu.single();
+    if action_state.just_pressed(NavAction::Confirm) || action_state.just_pressed(NavAction::Back) {
+        audio_facade.play_ui(menu.sound_click.clone());
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+    }
+}
+
+fn replay_menu_cleanup(mut commands: Commands, query: Query<Entity, With<ReplayMenuScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
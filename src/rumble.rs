@@ -0,0 +1,166 @@
+use bevy::prelude::*;
+#[cfg(not(target_arch = "wasm32"))]
+use gilrs::{
+    ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat, Replay, Ticks},
+    Gilrs,
+};
+
+use crate::{game::GameTime, gamepad::ActiveGamepad, settings::Settings, AppState};
+
+/// Gamepad vibrations requested by the gameplay. The requests are merged into a single
+/// effect played on the active gamepad, if any supports force feedback. On the web
+/// the requests are simply dropped.
+pub struct RumblePlugin;
+
+impl Plugin for RumblePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RumbleRequest>()
+            .init_resource::<Rumble>()
+            .add_system(update_rumble)
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(stop_rumble));
+        #[cfg(not(target_arch = "wasm32"))]
+        app.init_non_send_resource::<RumbleEffect>()
+            .add_system(play_rumble.after(update_rumble));
+    }
+}
+
+/// Event to rumble the active gamepad. Overlapping requests don't add up; the
+/// strongest of them applies.
+#[derive(Debug, Clone, Copy)]
+pub struct RumbleRequest {
+    /// Intensity of the strong (low frequency) motor, in `[0:1]`.
+    pub strong: f32,
+    /// Intensity of the weak (high frequency) motor, in `[0:1]`.
+    pub weak: f32,
+    /// Duration of the rumble, in seconds.
+    pub duration: f32,
+}
+
+/// Rumble requests still running.
+#[derive(Default)]
+struct Rumble {
+    requests: Vec<RumbleRequest>,
+    /// Gamepad and motor magnitudes to play, if any.
+    target: Option<(Gamepad, u16, u16)>,
+}
+
+/// Number of distinct motor magnitudes. The intensities are rounded to limit how often
+/// the effect is replaced, like while the charge shot charges.
+const MAGNITUDE_LEVELS: f32 = 8.;
+
+/// Convert an intensity in `[0:1]` to a motor magnitude.
+fn magnitude(intensity: f32) -> u16 {
+    let level = (intensity.clamp(0., 1.) * MAGNITUDE_LEVELS).round() / MAGNITUDE_LEVELS;
+    (level * u16::MAX as f32) as u16
+}
+
+/// Expire the rumble requests and merge those left into the magnitudes to play.
+fn update_rumble(
+    mut rumble: ResMut<Rumble>,
+    mut requests: EventReader<RumbleRequest>,
+    active: Res<ActiveGamepad>,
+    settings: Res<Settings>,
+    game_time: Res<GameTime>,
+    time: Res<Time>,
+) {
+    // Real time, so that a rumble doesn't last through a pause
+    let dt = time.delta_seconds();
+    for request in rumble.requests.iter_mut() {
+        request.duration -= dt;
+    }
+    rumble.requests.retain(|request| request.duration > 0.);
+    rumble.requests.extend(requests.iter().copied());
+
+    let gamepad = match active.0 {
+        Some(gamepad) if settings.controls.rumble && !game_time.is_paused() => gamepad,
+        _ => {
+            rumble.target = None;
+            return;
+        }
+    };
+    let (strong, weak) = rumble
+        .requests
+        .iter()
+        .fold((0_f32, 0_f32), |(strong, weak), request| {
+            (strong.max(request.strong), weak.max(request.weak))
+        });
+    let (strong, weak) = (magnitude(strong), magnitude(weak));
+    rumble.target = if strong > 0 || weak > 0 {
+        Some((gamepad, strong, weak))
+    } else {
+        None
+    };
+}
+
+fn stop_rumble(mut rumble: ResMut<Rumble>) {
+    rumble.requests.clear();
+    rumble.target = None;
+}
+
+/// Force feedback effect playing, kept alive as long as it should play.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct RumbleEffect {
+    /// Gamepad and motor magnitudes of the effect.
+    playing: Option<(Gamepad, u16, u16)>,
+    effect: Option<Effect>,
+}
+
+/// Play the merged rumble on the gamepad, replacing the effect whenever the magnitudes
+/// change. Without gilrs, or on gamepads without force feedback, this does nothing.
+#[cfg(not(target_arch = "wasm32"))]
+fn play_rumble(
+    rumble: Res<Rumble>,
+    mut rumble_effect: NonSendMut<RumbleEffect>,
+    gilrs: Option<NonSendMut<Gilrs>>,
+) {
+    if rumble_effect.playing == rumble.target {
+        return;
+    }
+    // Dropping the effect stops it
+    rumble_effect.effect = None;
+    rumble_effect.playing = rumble.target;
+    let (gamepad, strong, weak) = if let Some(target) = rumble.target {
+        target
+    } else {
+        return;
+    };
+    let mut gilrs = if let Some(gilrs) = gilrs {
+        gilrs
+    } else {
+        return;
+    };
+    let id = if let Some((id, _)) = gilrs
+        .gamepads()
+        .find(|(id, pad)| usize::from(*id) == gamepad.0 && pad.is_ff_supported())
+    {
+        id
+    } else {
+        return;
+    };
+
+    // Repeat a short effect until dropped
+    let scheduling = Replay {
+        play_for: Ticks::from_ms(50),
+        ..Default::default()
+    };
+    let result = EffectBuilder::new()
+        .add_effect(BaseEffect {
+            kind: BaseEffectType::Strong { magnitude: strong },
+            scheduling,
+            envelope: Default::default(),
+        })
+        .add_effect(BaseEffect {
+            kind: BaseEffectType::Weak { magnitude: weak },
+            scheduling,
+            envelope: Default::default(),
+        })
+        .gamepads(&[id])
+        .repeat(Repeat::Infinitely)
+        .finish(&mut *gilrs)
+        .and_then(|effect| effect.play().map(|_| effect));
+    match result {
+        Ok(effect) => rumble_effect.effect = Some(effect),
+        Err(err) => debug!(target: "rumble", "RUMBLE: failed to play effect: {:?}", err),
+    }
+}
@@ -0,0 +1,331 @@
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// Serialized format of a file saved to disk, like the settings or a replay.
+///
+/// Each file is saved with a `version` field. Files saved with an older version of the
+/// format are backed up, then upgraded one version at a time by the `migrate`
+/// function before being deserialized, so that changing a serialized struct doesn't
+/// silently discard the player data.
+pub struct SaveFormat {
+    /// Current version of the format, written to all saved files. Bump it and add a
+    /// migration step from the previous version whenever the layout changes in a way
+    /// `#[serde(default)]` cannot absorb.
+    pub version: u32,
+    /// Upgrade the JSON of a file from the given version to the next one. Files saved
+    /// before formats were versioned have no `version` field, and are version 0.
+    pub migrate: fn(version: u32, json: &mut Value) -> Result<(), String>,
+}
+
+/// Name of the version field of the saved files.
+const VERSION_FIELD: &str = "version";
+
+/// Content of a file successfully loaded with [`load()`].
+pub struct Loaded<T> {
+    pub value: T,
+    /// Version the file was migrated from, if older than the current format.
+    pub migrated_from: Option<u32>,
+    /// Copy of the file made before migrating it, if any.
+    pub backup: Option<PathBuf>,
+}
+
+/// Error loading a file with [`load()`].
+#[derive(Debug)]
+pub enum LoadError {
+    /// The file cannot be read, usually because it doesn't exist.
+    Read(std::io::Error),
+    /// The file is not valid JSON, or doesn't match the format once migrated.
+    Invalid {
+        error: String,
+        backup: Option<PathBuf>,
+    },
+    /// The file was saved by a more recent version of the game.
+    TooRecent {
+        version: u32,
+        backup: Option<PathBuf>,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Read(err) => write!(f, "{}", err),
+            LoadError::Invalid { error, .. } => write!(f, "{}", error),
+            LoadError::TooRecent { version, .. } => {
+                write!(f, "saved with unsupported version {}", version)
+            }
+        }?;
+        match self {
+            LoadError::Invalid {
+                backup: Some(backup),
+                ..
+            }
+            | LoadError::TooRecent {
+                backup: Some(backup),
+                ..
+            } => write!(f, " (backed up to {:?})", backup),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Load a file saved with the given format, migrating it first if saved with an older
+/// version.
+///
+/// The original file is copied next to it before being migrated, and also when it
+/// cannot be loaded at all, since the caller will likely overwrite it with defaults.
+pub fn load<T: DeserializeOwned>(
+    path: impl AsRef<Path>,
+    format: &SaveFormat,
+) -> Result<Loaded<T>, LoadError> {
+    let path = path.as_ref();
+    let json = std::fs::read_to_string(path).map_err(LoadError::Read)?;
+    parse(&json, format, Some(path))
+}
+
+/// Like [`load()`], but from JSON which doesn't come from a file, like the browser
+/// local storage. Nothing is backed up.
+pub fn from_json<T: DeserializeOwned>(
+    json: &str,
+    format: &SaveFormat,
+) -> Result<Loaded<T>, LoadError> {
+    parse(json, format, None)
+}
+
+/// Migrate and deserialize the JSON content of a file, backing up the file at the
+/// given path if any.
+fn parse<T: DeserializeOwned>(
+    json: &str,
+    format: &SaveFormat,
+    path: Option<&Path>,
+) -> Result<Loaded<T>, LoadError> {
+    let backup = |tag: &str| path.and_then(|path| backup(path, tag));
+    let mut value: Value = serde_json::from_str(json).map_err(|err| LoadError::Invalid {
+        error: err.to_string(),
+        backup: backup("invalid"),
+    })?;
+
+    let version = value
+        .get(VERSION_FIELD)
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32;
+    if version > format.version {
+        return Err(LoadError::TooRecent {
+            version,
+            backup: backup(&format!("v{}", version)),
+        });
+    }
+
+    let mut loaded_backup = None;
+    if version < format.version {
+        loaded_backup = backup(&format!("v{}", version));
+        for from in version..format.version {
+            (format.migrate)(from, &mut value).map_err(|err| LoadError::Invalid {
+                error: format!("failed to migrate from version {}: {}", from, err),
+                backup: loaded_backup.clone(),
+            })?;
+        }
+    }
+
+    let value = serde_json::from_value(value).map_err(|err| LoadError::Invalid {
+        error: err.to_string(),
+        backup: loaded_backup.clone().or_else(|| backup("invalid")),
+    })?;
+    Ok(Loaded {
+        value,
+        migrated_from: (version < format.version).then_some(version),
+        backup: loaded_backup,
+    })
+}
+
+/// Recover what can be from JSON which doesn't match a format as a whole, like a file
+/// edited by hand or truncated: each top-level field is kept if valid, and left to its
+/// default otherwise or if cut off. Return the value and the names of the fields reset,
+/// or `None` if the JSON itself cannot be parsed.
+pub fn salvage<T: Serialize + DeserializeOwned + Default>(json: &str) -> Option<(T, Vec<String>)> {
+    let fields = match parse_truncated(json)? {
+        Value::Object(fields) => fields,
+        _ => return None,
+    };
+    let mut value = serde_json::to_value(T::default()).ok()?;
+    let mut reset = vec![];
+    for (name, field) in fields {
+        if name == VERSION_FIELD {
+            continue;
+        }
+        let mut candidate = value.clone();
+        if let Value::Object(object) = &mut candidate {
+            object.insert(name.clone(), field);
+        }
+        if serde_json::from_value::<T>(candidate.clone()).is_ok() {
+            value = candidate;
+        } else {
+            reset.push(name);
+        }
+    }
+    serde_json::from_value(value)
+        .ok()
+        .map(|value| (value, reset))
+}
+
+/// Parse JSON, or if it ends abruptly, the longest list of complete top-level fields
+/// of its object.
+fn parse_truncated(json: &str) -> Option<Value> {
+    let err = match serde_json::from_str(json) {
+        Ok(value) => return Some(value),
+        Err(err) => err,
+    };
+    if !err.is_eof() || !json.trim_start().starts_with('{') {
+        return None;
+    }
+    // Cut after the last field which still parses, commas inside nested values or
+    // strings failing to parse once closed
+    json.rmatch_indices(',')
+        .find_map(|(index, _)| serde_json::from_str(&format!("{}}}", &json[..index])).ok())
+}
+
+/// Write a file through a temporary file next to it, so that a crash while writing
+/// doesn't leave a truncated file behind.
+pub fn write(path: impl AsRef<Path>, json: &str) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let mut temp_path = path.as_os_str().to_os_string();
+    temp_path.push(".tmp");
+    std::fs::write(&temp_path, json)?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// Serialize a value to JSON with the current version of its format.
+pub fn to_json<T: Serialize>(
+    value: &T,
+    format: &SaveFormat,
+    pretty: bool,
+) -> Result<String, String> {
+    #[derive(Serialize)]
+    struct Versioned<'a, T> {
+        version: u32,
+        #[serde(flatten)]
+        value: &'a T,
+    }
+
+    let versioned = Versioned {
+        version: format.version,
+        value,
+    };
+    let json = if pretty {
+        serde_json::to_string_pretty(&versioned)
+    } else {
+        serde_json::to_string(&versioned)
+    };
+    json.map_err(|err| err.to_string())
+}
+
+/// Copy a file to a backup next to it, tagged with the given suffix. An existing backup
+/// is kept as is, since files which are never saved again, like replays, would
+/// otherwise be copied on each load. Return the path of the backup, or `None` if the
+/// copy failed.
+fn backup(path: &Path, tag: &str) -> Option<PathBuf> {
+    let mut file_name = path.file_name()?.to_os_string();
+    file_name.push(format!(".{}.bak", tag));
+    let backup_path = path.with_file_name(file_name);
+    if !backup_path.exists() {
+        std::fs::copy(path, &backup_path).ok()?;
+    }
+    Some(backup_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    struct TestData {
+        name: String,
+        score: u32,
+        stages: Vec<u32>,
+    }
+
+    /// Version 1 renamed `points` into `score`.
+    const TEST_FORMAT: SaveFormat = SaveFormat {
+        version: 1,
+        migrate: |version, json| match version {
+            0 => {
+                if let Some(object) = json.as_object_mut() {
+                    if let Some(points) = object.remove("points") {
+                        object.insert("score".to_string(), points);
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(format!("no migration from version {}", version)),
+        },
+    };
+
+    /// Empty directory for the files of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("save_test_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migration_backs_up_the_file() {
+        let path = test_dir("migration").join("data.json");
+        let json = r#"{"name":"AAA","points":42}"#;
+        std::fs::write(&path, json).unwrap();
+
+        let loaded = load::<TestData>(&path, &TEST_FORMAT).unwrap();
+        assert_eq!(loaded.value.score, 42);
+        assert_eq!(loaded.migrated_from, Some(0));
+        let backup = loaded.backup.unwrap();
+        assert_eq!(backup, path.with_file_name("data.json.v0.bak"));
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), json);
+    }
+
+    #[test]
+    fn too_recent_file_is_rejected_untouched() {
+        let path = test_dir("too_recent").join("data.json");
+        let json = r#"{"version":99,"name":"AAA","score":42,"new_field":true}"#;
+        std::fs::write(&path, json).unwrap();
+
+        match load::<TestData>(&path, &TEST_FORMAT) {
+            Err(LoadError::TooRecent {
+                version: 99,
+                backup: Some(backup),
+            }) => assert_eq!(std::fs::read_to_string(backup).unwrap(), json),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("file loaded"),
+        }
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), json);
+    }
+
+    #[test]
+    fn salvage_truncated_file() {
+        let json = r#"{"version":1,"name":"AAA","score":42,"stages":[1,2"#;
+        let (value, reset) = salvage::<TestData>(json).unwrap();
+        assert_eq!(
+            value,
+            TestData {
+                name: "AAA".to_string(),
+                score: 42,
+                stages: vec![],
+            }
+        );
+        assert!(reset.is_empty());
+
+        // Invalid fields are reset, and cut-off strings don't end the object early
+        let json = r#"{"score":"lots","name":"A,B","stages":[1,2],"extra":"#;
+        let (value, reset) = salvage::<TestData>(json).unwrap();
+        assert_eq!(value.name, "A,B");
+        assert_eq!(value.score, 0);
+        assert_eq!(value.stages, vec![1, 2]);
+        assert_eq!(reset, vec!["score".to_string()]);
+    }
+}
@@ -0,0 +1,293 @@
+use bevy::{
+    prelude::*,
+    render::{
+        camera::{ActiveCamera, Camera3d, RenderTarget},
+        render_asset::RenderAssets,
+        render_resource::{
+            BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
+            ImageDataLayout, MapMode, TextureDimension, TextureFormat, TextureUsages,
+        },
+        renderer::{RenderDevice, RenderQueue},
+        texture::BevyDefault,
+        RenderApp, RenderStage,
+    },
+    ui::entity::CameraUi,
+};
+use image::{codecs::png::PngEncoder, ColorType};
+use parking_lot::Mutex;
+use std::{num::NonZeroU32, sync::Arc};
+
+/// Screenshots of the current frame, HUD included, taken with F1. Saved as PNG to the
+/// `screenshots/` folder, or downloaded by the browser on the web.
+///
+/// Bevy can't read back the window surface, so the frame of the screenshot is rendered
+/// into an image instead, which is copied back from the GPU once rendered. The window
+/// itself misses that frame, which makes for a short flash.
+pub struct ScreenshotPlugin;
+
+impl Plugin for ScreenshotPlugin {
+    fn build(&self, app: &mut App) {
+        let readback = ScreenshotReadback::default();
+        app.init_resource::<Screenshot>()
+            .insert_resource(readback.clone())
+            .add_system_to_stage(CoreStage::Last, update_screenshot);
+        // No render app when running headless
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .insert_resource(readback)
+                .add_system_to_stage(RenderStage::Extract, extract_screenshot)
+                .add_system_to_stage(RenderStage::Cleanup, read_screenshot);
+        }
+    }
+}
+
+/// Pixels of a captured frame, as 8-bit RGBA rows from top to bottom.
+struct Capture {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Captured frame, shared with the render world which fills it.
+#[derive(Default, Clone)]
+struct ScreenshotReadback(Arc<Mutex<Option<Capture>>>);
+
+/// Progress of a screenshot. Each step takes a frame, so that the image exists on the
+/// GPU by the time the cameras render into it.
+enum Screenshot {
+    Idle,
+    /// Image created, waiting for the render world to upload it.
+    Preparing(Handle<Image>),
+    /// Cameras rendering into the image, waiting for its pixels. Keeps the previous
+    /// target of each camera, to restore it after the capture.
+    Capturing {
+        image: Handle<Image>,
+        targets: Vec<(Entity, RenderTarget)>,
+    },
+}
+
+impl Default for Screenshot {
+    fn default() -> Self {
+        Screenshot::Idle
+    }
+}
+
+/// Image to read back in the render world, for the frame being rendered.
+struct ExtractedScreenshot(Handle<Image>);
+
+fn update_screenshot(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut screenshot: ResMut<Screenshot>,
+    readback: Res<ScreenshotReadback>,
+    windows: Res<Windows>,
+    mut images: ResMut<Assets<Image>>,
+    active_3d: Res<ActiveCamera<Camera3d>>,
+    active_ui: Res<ActiveCamera<CameraUi>>,
+    mut q_cameras: Query<&mut Camera>,
+) {
+    let next = match &mut *screenshot {
+        Screenshot::Idle => {
+            if !keyboard_input.just_pressed(KeyCode::F1) {
+                return;
+            }
+            let window = if let Some(window) = windows.get_primary() {
+                window
+            } else {
+                return;
+            };
+            let size = Extent3d {
+                width: window.physical_width(),
+                height: window.physical_height(),
+                depth_or_array_layers: 1,
+            };
+            // Same format as the window, which the render pipelines are specialized for
+            let mut image = Image::new_fill(
+                size,
+                TextureDimension::D2,
+                &[0; 4],
+                TextureFormat::bevy_default(),
+            );
+            image.texture_descriptor.usage = TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_SRC
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT;
+            Screenshot::Preparing(images.add(image))
+        }
+        Screenshot::Preparing(image) => {
+            let mut targets = vec![];
+            for entity in [active_3d.get(), active_ui.get()].into_iter().flatten() {
+                if let Ok(mut camera) = q_cameras.get_mut(entity) {
+                    let target = RenderTarget::Image(image.clone());
+                    targets.push((entity, std::mem::replace(&mut camera.target, target)));
+                }
+            }
+            Screenshot::Capturing {
+                image: image.clone(),
+                targets,
+            }
+        }
+        Screenshot::Capturing { image, targets } => {
+            let capture = if let Some(capture) = readback.0.lock().take() {
+                capture
+            } else {
+                return;
+            };
+            for (entity, target) in targets.drain(..) {
+                if let Ok(mut camera) = q_cameras.get_mut(entity) {
+                    camera.target = target;
+                }
+            }
+            images.remove(&*image);
+            save_screenshot(&capture);
+            Screenshot::Idle
+        }
+    };
+    *screenshot = next;
+}
+
+fn extract_screenshot(mut commands: Commands, screenshot: Res<Screenshot>) {
+    if let Screenshot::Capturing { image, .. } = &*screenshot {
+        commands.insert_resource(ExtractedScreenshot(image.clone()));
+    }
+}
+
+/// Copy the image of the screenshot back from the GPU, once the frame is rendered.
+fn read_screenshot(
+    mut commands: Commands,
+    extracted: Option<Res<ExtractedScreenshot>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    readback: Res<ScreenshotReadback>,
+) {
+    let extracted = if let Some(extracted) = extracted {
+        extracted
+    } else {
+        return;
+    };
+    commands.remove_resource::<ExtractedScreenshot>();
+    let gpu_image = if let Some(gpu_image) = gpu_images.get(&extracted.0) {
+        gpu_image
+    } else {
+        return;
+    };
+
+    // Rows of a texture copy are aligned
+    let width = gpu_image.size.width as u32;
+    let height = gpu_image.size.height as u32;
+    let row_bytes = width as usize * 4;
+    let padded_row_bytes = RenderDevice::align_copy_bytes_per_row(row_bytes);
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("screenshot_buffer"),
+        size: (padded_row_bytes * height as usize) as u64,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("screenshot_encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        gpu_image.texture.as_image_copy(),
+        ImageCopyBuffer {
+            buffer: &buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_row_bytes as u32),
+                rows_per_image: None,
+            },
+        },
+        Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    render_queue.submit([encoder.finish()]);
+
+    // Blocks until the copy is done, which is fine for a one-off
+    let slice = buffer.slice(..);
+    render_device.map_buffer(&slice, MapMode::Read);
+    let mut data = Vec::with_capacity(row_bytes * height as usize);
+    for row in slice.get_mapped_range().chunks(padded_row_bytes) {
+        data.extend_from_slice(&row[..row_bytes]);
+    }
+    buffer.unmap();
+
+    let is_bgra = matches!(
+        gpu_image.texture_format,
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb
+    );
+    for pixel in data.chunks_mut(4) {
+        if is_bgra {
+            pixel.swap(0, 2);
+        }
+        // The clear color is transparent, but the screenshot shouldn't be
+        pixel[3] = 255;
+    }
+    *readback.0.lock() = Some(Capture {
+        width,
+        height,
+        data,
+    });
+}
+
+fn save_screenshot(capture: &Capture) {
+    let mut png = vec![];
+    if let Err(err) = PngEncoder::new(&mut png).encode(
+        &capture.data,
+        capture.width,
+        capture.height,
+        ColorType::Rgba8,
+    ) {
+        warn!(target: "screenshot", "Failed to encode the screenshot: {}", err);
+        return;
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        use std::time::{SystemTime, UNIX_EPOCH};
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let path = format!("screenshots/screenshot_{}.png", timestamp);
+        match std::fs::create_dir_all("screenshots").and_then(|_| std::fs::write(&path, &png)) {
+            Ok(_) => info!(target: "screenshot", "Saved screenshot to '{}'", path),
+            Err(err) => {
+                warn!(target: "screenshot", "Failed to save screenshot to '{}': {}", path, err)
+            }
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        let name = format!("screenshot_{}.png", js_sys::Date::now() as u64);
+        match download(&name, &png) {
+            Ok(_) => info!(target: "screenshot", "Downloaded screenshot '{}'", name),
+            Err(err) => warn!(target: "screenshot", "Failed to download screenshot: {:?}", err),
+        }
+    }
+}
+
+/// Have the browser download a PNG file, through a temporary link to a blob.
+#[cfg(target_arch = "wasm32")]
+fn download(name: &str, png: &[u8]) -> Result<(), wasm_bindgen::JsValue> {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(png));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_("image/png");
+    let blob = web_sys::Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| JsValue::from_str("no document"))?;
+    let link = document
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(JsValue::from)?;
+    link.set_href(&url);
+    link.set_download(name);
+    link.click();
+    web_sys::Url::revoke_object_url(&url)
+}
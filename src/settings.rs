@@ -0,0 +1,806 @@
+use bevy::{
+    pbr::DirectionalLightShadowMap,
+    prelude::*,
+    utils::Instant,
+    window::{PresentMode, WindowMode},
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::save::{self, SaveFormat};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Settings>()
+            .init_resource::<FrameLimiter>()
+            .add_system(apply_video_settings)
+            .add_system_to_stage(CoreStage::Last, limit_frame_rate);
+    }
+}
+
+/// Path of the settings file, relative to the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const SETTINGS_PATH: &str = "settings.json";
+
+/// Key of the settings in the browser local storage.
+#[cfg(target_arch = "wasm32")]
+const SETTINGS_STORAGE_KEY: &str = "super-kaizen-overloaded/settings";
+
+/// Format of the settings file.
+const SETTINGS_FORMAT: SaveFormat = SaveFormat {
+    version: 2,
+    migrate: migrate_settings,
+};
+
+fn migrate_settings(version: u32, json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // Unversioned files have the same layout as version 1
+        0 => Ok(()),
+        // The effects quality became the graphics quality, which also sets the MSAA
+        1 => {
+            if let Some(video) = json
+                .get_mut("video")
+                .and_then(|video| video.as_object_mut())
+            {
+                video.remove("msaa_samples");
+                if let Some(quality) = video.remove("effects_quality") {
+                    video.insert("graphics_quality".to_string(), quality);
+                }
+            }
+            Ok(())
+        }
+        _ => Err(format!("no migration from version {}", version)),
+    }
+}
+
+/// Maximum frame rate of the game loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FrameRateCap {
+    Fps30,
+    Fps60,
+    Fps120,
+    Uncapped,
+}
+
+impl FrameRateCap {
+    pub const ALL: [FrameRateCap; 4] = [
+        FrameRateCap::Fps30,
+        FrameRateCap::Fps60,
+        FrameRateCap::Fps120,
+        FrameRateCap::Uncapped,
+    ];
+
+    /// Target frame rate in frames per second, or `None` if uncapped.
+    pub fn frame_rate(&self) -> Option<f64> {
+        match self {
+            FrameRateCap::Fps30 => Some(30.),
+            FrameRateCap::Fps60 => Some(60.),
+            FrameRateCap::Fps120 => Some(120.),
+            FrameRateCap::Uncapped => None,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            FrameRateCap::Fps30 => "30",
+            FrameRateCap::Fps60 => "60",
+            FrameRateCap::Fps120 => "120",
+            FrameRateCap::Uncapped => "Uncapped",
+        }
+    }
+}
+
+/// Serializable mirror of [`PresentMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PresentModeSetting {
+    /// Vertical sync; the only mode supported on the web.
+    Fifo,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentModeSetting {
+    pub const ALL: [PresentModeSetting; 3] = [
+        PresentModeSetting::Fifo,
+        PresentModeSetting::Mailbox,
+        PresentModeSetting::Immediate,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PresentModeSetting::Fifo => "VSync",
+            PresentModeSetting::Mailbox => "Mailbox",
+            PresentModeSetting::Immediate => "Immediate",
+        }
+    }
+}
+
+impl From<PresentModeSetting> for PresentMode {
+    fn from(mode: PresentModeSetting) -> Self {
+        match mode {
+            PresentModeSetting::Fifo => PresentMode::Fifo,
+            PresentModeSetting::Mailbox => PresentMode::Mailbox,
+            PresentModeSetting::Immediate => PresentMode::Immediate,
+        }
+    }
+}
+
+/// How the game window is displayed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayMode {
+    Windowed,
+    /// Borderless window covering the whole screen, at the desktop resolution.
+    Borderless,
+    /// Exclusive fullscreen, switching the screen to the window resolution.
+    Exclusive,
+}
+
+impl DisplayMode {
+    pub const ALL: [DisplayMode; 3] = [
+        DisplayMode::Windowed,
+        DisplayMode::Borderless,
+        DisplayMode::Exclusive,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            DisplayMode::Windowed => "Windowed",
+            DisplayMode::Borderless => "Borderless",
+            DisplayMode::Exclusive => "Fullscreen",
+        }
+    }
+}
+
+impl From<DisplayMode> for WindowMode {
+    fn from(mode: DisplayMode) -> Self {
+        match mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Exclusive => WindowMode::SizedFullscreen,
+        }
+    }
+}
+
+/// Window resolution selectable in the options, in logical pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+    pub label: &'static str,
+}
+
+/// Window resolutions selectable in the options, by increasing size.
+pub const RESOLUTIONS: [Resolution; 6] = [
+    Resolution {
+        width: 1280,
+        height: 720,
+        label: "1280x720",
+    },
+    Resolution {
+        width: 1366,
+        height: 768,
+        label: "1366x768",
+    },
+    Resolution {
+        width: 1600,
+        height: 900,
+        label: "1600x900",
+    },
+    Resolution {
+        width: 1920,
+        height: 1080,
+        label: "1920x1080",
+    },
+    Resolution {
+        width: 2560,
+        height: 1440,
+        label: "2560x1440",
+    },
+    Resolution {
+        width: 3840,
+        height: 2160,
+        label: "3840x2160",
+    },
+];
+
+/// Assist scaling the delay between two auto-fire volleys of the primary weapon, as a
+/// percentage of the delay of the current weapon power tier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutoFireDelay {
+    Percent50,
+    Percent60,
+    Percent70,
+    Percent80,
+    Percent90,
+    Percent100,
+}
+
+impl AutoFireDelay {
+    pub const ALL: [AutoFireDelay; 6] = [
+        AutoFireDelay::Percent50,
+        AutoFireDelay::Percent60,
+        AutoFireDelay::Percent70,
+        AutoFireDelay::Percent80,
+        AutoFireDelay::Percent90,
+        AutoFireDelay::Percent100,
+    ];
+
+    /// Factor applied to the primary fire delay.
+    pub fn factor(&self) -> f32 {
+        match self {
+            AutoFireDelay::Percent50 => 0.5,
+            AutoFireDelay::Percent60 => 0.6,
+            AutoFireDelay::Percent70 => 0.7,
+            AutoFireDelay::Percent80 => 0.8,
+            AutoFireDelay::Percent90 => 0.9,
+            AutoFireDelay::Percent100 => 1.,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            AutoFireDelay::Percent50 => "50%",
+            AutoFireDelay::Percent60 => "60%",
+            AutoFireDelay::Percent70 => "70%",
+            AutoFireDelay::Percent80 => "80%",
+            AutoFireDelay::Percent90 => "90%",
+            AutoFireDelay::Percent100 => "100%",
+        }
+    }
+}
+
+/// Rendering quality preset, trading the shadows, the anti-aliasing and the amount of
+/// cosmetic effects for speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GraphicsQuality {
+    /// No shadows, no MSAA, sparse particles and clouds, and no muzzle flashes nor
+    /// bullet trails.
+    Low,
+    /// Low resolution shadows, MSAA, and no bullet trails.
+    Medium,
+    High,
+}
+
+impl GraphicsQuality {
+    pub const ALL: [GraphicsQuality; 3] = [
+        GraphicsQuality::Low,
+        GraphicsQuality::Medium,
+        GraphicsQuality::High,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GraphicsQuality::Low => "Low",
+            GraphicsQuality::Medium => "Medium",
+            GraphicsQuality::High => "High",
+        }
+    }
+
+    /// Size of the shadow map of the sun, in texels, or `None` if the sun doesn't cast
+    /// shadows.
+    pub fn shadow_map_size(&self) -> Option<usize> {
+        match self {
+            GraphicsQuality::Low => None,
+            GraphicsQuality::Medium => Some(1024),
+            GraphicsQuality::High => Some(2048),
+        }
+    }
+
+    /// Number of MSAA samples, 1 (disabled) or 4.
+    pub fn msaa_samples(&self) -> u32 {
+        match self {
+            GraphicsQuality::Low => 1,
+            GraphicsQuality::Medium | GraphicsQuality::High => 4,
+        }
+    }
+
+    /// Fraction of the particles of the bursts spawned, like explosions and sparks.
+    pub fn particle_density(&self) -> f32 {
+        match self {
+            GraphicsQuality::Low => 0.4,
+            GraphicsQuality::Medium => 0.7,
+            GraphicsQuality::High => 1.,
+        }
+    }
+
+    /// Fraction of the clouds spawned in the scattered layers of the backdrop.
+    pub fn cloud_density(&self) -> f32 {
+        match self {
+            GraphicsQuality::Low => 0.5,
+            GraphicsQuality::Medium => 0.75,
+            GraphicsQuality::High => 1.,
+        }
+    }
+
+    /// Spawn a flash at the muzzle on each volley?
+    pub fn has_muzzle_flashes(&self) -> bool {
+        *self != GraphicsQuality::Low
+    }
+
+    /// Leave a trail behind the fast bullets?
+    pub fn has_bullet_trails(&self) -> bool {
+        *self == GraphicsQuality::High
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    pub frame_rate_cap: FrameRateCap,
+    pub present_mode: PresentModeSetting,
+    /// Render the playfield with an orthographic projection instead of a perspective
+    /// one, for a flat look. Applied when a game starts.
+    pub orthographic_camera: bool,
+    /// Rendering quality. Low by default on the web, where every quad counts and
+    /// WebGL shadows are slow.
+    pub graphics_quality: GraphicsQuality,
+    /// Display mode of the window. Ignored on the web.
+    pub display_mode: DisplayMode,
+    /// Size of the window, in logical pixels, also the screen resolution in exclusive
+    /// fullscreen. Ignored on the web.
+    pub window_width: u32,
+    pub window_height: u32,
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        VideoSettings {
+            frame_rate_cap: FrameRateCap::Uncapped,
+            present_mode: PresentModeSetting::Fifo,
+            orthographic_camera: false,
+            graphics_quality: if cfg!(target_arch = "wasm32") {
+                GraphicsQuality::Low
+            } else {
+                GraphicsQuality::High
+            },
+            display_mode: DisplayMode::Windowed,
+            window_width: 1280,
+            window_height: 720,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameplaySettings {
+    /// Show the dynamic difficulty rank on the HUD.
+    pub show_rank: bool,
+    /// Show the run timer and stage splits on the HUD and at the end of the run.
+    pub show_run_timer: bool,
+    /// Show a ghost of the best recorded run alongside the player ship.
+    pub show_ghost: bool,
+    /// Dim the background when many enemy bullets are on screen.
+    pub dim_background: bool,
+    /// Assist tinting enemy bullets and homing enemies by threat class. Applied when
+    /// a game starts.
+    pub color_code_threats: bool,
+    /// Assist shortening the delay between auto-fire volleys. Applied when a game
+    /// starts, and ignored in timed runs whose splits are recorded as records.
+    pub auto_fire_delay: AutoFireDelay,
+    /// Fire the primary weapon continuously, without holding the button. Also toggled
+    /// in game with a quick key.
+    pub auto_fire: bool,
+}
+
+impl GameplaySettings {
+    /// Factor applied to the primary fire delay for a new game, taking into account
+    /// that assists don't apply to timed runs.
+    pub fn fire_delay_factor(&self) -> f32 {
+        if self.show_run_timer {
+            1.
+        } else {
+            self.auto_fire_delay.factor()
+        }
+    }
+
+    /// Does a new game start with assists making it easier? Such runs don't enter the
+    /// high scores nor replace the best replay. Visual assists like the threat
+    /// color-coding don't count.
+    pub fn is_assisted(&self) -> bool {
+        self.fire_delay_factor() < 1.
+    }
+}
+
+impl Default for GameplaySettings {
+    fn default() -> Self {
+        GameplaySettings {
+            show_rank: true,
+            show_run_timer: false,
+            show_ghost: false,
+            dim_background: true,
+            color_code_threats: false,
+            auto_fire_delay: AutoFireDelay::Percent100,
+            auto_fire: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AudioSettings {
+    /// Volume of the background music, in percent.
+    pub bgm_volume: u8,
+    /// Volume of the sound effects, in percent.
+    pub sfx_volume: u8,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            bgm_volume: 100,
+            sfx_volume: 100,
+        }
+    }
+}
+
+/// Response curve of the analog stick, mapping the stick deflection past the deadzone to
+/// the ship speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StickResponse {
+    Linear,
+    /// Finer control of slow movements, at the expense of the fast ones.
+    Quadratic,
+    Cubic,
+}
+
+impl StickResponse {
+    pub const ALL: [StickResponse; 3] = [
+        StickResponse::Linear,
+        StickResponse::Quadratic,
+        StickResponse::Cubic,
+    ];
+
+    /// Apply the curve to a deflection in `[0:1]`.
+    pub fn apply(&self, deflection: f32) -> f32 {
+        match self {
+            StickResponse::Linear => deflection,
+            StickResponse::Quadratic => deflection * deflection,
+            StickResponse::Cubic => deflection * deflection * deflection,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            StickResponse::Linear => "Linear",
+            StickResponse::Quadratic => "Quadratic",
+            StickResponse::Cubic => "Cubic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlsSettings {
+    /// Radius of the deadzone of the analog stick, in percent of its full deflection.
+    pub stick_deadzone: u8,
+    /// Response curve of the analog stick.
+    pub stick_response: StickResponse,
+    /// Rumble the gamepad on hits, bombs and explosions.
+    pub rumble: bool,
+    /// Show the on-screen touch controls. On the web they also show up by themselves
+    /// once the screen is touched.
+    pub touch_controls: bool,
+}
+
+impl ControlsSettings {
+    /// Map a raw analog stick position to a movement direction of length in `[0:1]`,
+    /// applying the radial deadzone and the response curve.
+    pub fn stick_to_direction(&self, stick: Vec2) -> Vec2 {
+        let deadzone = self.stick_deadzone as f32 / 100.;
+        let length = stick.length();
+        if length <= deadzone {
+            return Vec2::ZERO;
+        }
+        // Rescale so that the deflection starts from zero at the edge of the deadzone
+        let deflection = ((length - deadzone) / (1. - deadzone)).min(1.);
+        stick / length * self.stick_response.apply(deflection)
+    }
+}
+
+impl Default for ControlsSettings {
+    fn default() -> Self {
+        ControlsSettings {
+            stick_deadzone: 20,
+            stick_response: StickResponse::Linear,
+            rumble: true,
+            touch_controls: false,
+        }
+    }
+}
+
+/// Palette of the bullet threat colors and of the lifebars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PaletteKind {
+    Default,
+    /// Avoid the red-green axis, for protanopia and deuteranopia.
+    RedGreen,
+    /// Avoid the blue-yellow axis, for tritanopia.
+    BlueYellow,
+}
+
+impl PaletteKind {
+    pub const ALL: [PaletteKind; 3] = [
+        PaletteKind::Default,
+        PaletteKind::RedGreen,
+        PaletteKind::BlueYellow,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PaletteKind::Default => "Default",
+            PaletteKind::RedGreen => "Red-green safe",
+            PaletteKind::BlueYellow => "Blue-yellow safe",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessibilitySettings {
+    /// Palette of the bullet threat colors and of the lifebars.
+    pub palette: PaletteKind,
+    /// Disable the screen shake, the white flashes and the fast blinking.
+    pub reduce_flashing: bool,
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        AccessibilitySettings {
+            palette: PaletteKind::Default,
+            reduce_flashing: false,
+        }
+    }
+}
+
+/// Minimum level of the log messages, mirroring [`Level`](bevy::log::Level).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    /// Parse a level name, case-insensitive, as accepted on the command line.
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name.to_ascii_lowercase().as_str() {
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        }
+    }
+}
+
+impl From<LogLevel> for bevy::log::Level {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => bevy::log::Level::ERROR,
+            LogLevel::Warn => bevy::log::Level::WARN,
+            LogLevel::Info => bevy::log::Level::INFO,
+            LogLevel::Debug => bevy::log::Level::DEBUG,
+            LogLevel::Trace => bevy::log::Level::TRACE,
+        }
+    }
+}
+
+/// Logging configuration, applied on startup. Can be overridden on the command line
+/// with `--log-level <level>` and `--log-filter <filter>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoggingSettings {
+    /// Minimum level of the messages logged, for all targets not listed in `filter`.
+    pub level: LogLevel,
+    /// Per-target levels, in the `RUST_LOG` format. The game logs to short targets
+    /// like `timeline`, `lifebar`, `camera` or `replay`, so for example
+    /// `"timeline=trace,lifebar=debug"`.
+    pub filter: String,
+}
+
+impl Default for LoggingSettings {
+    fn default() -> Self {
+        LoggingSettings {
+            level: LogLevel::Info,
+            filter: "wgpu=error".to_string(),
+        }
+    }
+}
+
+/// User settings, persisted to disk.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub gameplay: GameplaySettings,
+    pub controls: ControlsSettings,
+    pub audio: AudioSettings,
+    pub accessibility: AccessibilitySettings,
+    pub log: LoggingSettings,
+}
+
+impl Settings {
+    /// Load the settings from disk, or from the browser local storage on the web, or
+    /// return the default settings if there's none or they cannot be parsed.
+    pub fn load() -> Settings {
+        #[cfg(not(target_arch = "wasm32"))]
+        let loaded = save::load(SETTINGS_PATH, &SETTINGS_FORMAT);
+        #[cfg(target_arch = "wasm32")]
+        let loaded = match local_storage()
+            .and_then(|storage| storage.get_item(SETTINGS_STORAGE_KEY).ok().flatten())
+        {
+            Some(json) => save::from_json(&json, &SETTINGS_FORMAT),
+            None => return Settings::default(),
+        };
+
+        // Logging is configured from the settings, so is not set up yet
+        match loaded {
+            Ok(loaded) => {
+                if let Some(version) = loaded.migrated_from {
+                    println!(
+                        "Migrated settings from version {} (backup: {:?})",
+                        version, loaded.backup
+                    );
+                }
+                loaded.value
+            }
+            Err(save::LoadError::Read(err)) => {
+                println!("No settings loaded, using defaults: {}", err);
+                Settings::default()
+            }
+            Err(err) => {
+                println!("Failed to load settings, using defaults: {}", err);
+                Settings::default()
+            }
+        }
+    }
+
+    /// Save the settings to disk, or to the browser local storage on the web.
+    pub fn save(&self) {
+        let result = save::to_json(self, &SETTINGS_FORMAT, true).and_then(write_settings);
+        if let Err(err) = result {
+            error!(target: "settings", "Failed to save settings: {}", err);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_settings(json: String) -> Result<(), String> {
+    std::fs::write(SETTINGS_PATH, json).map_err(|err| err.to_string())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_settings(json: String) -> Result<(), String> {
+    local_storage()
+        .ok_or_else(|| "no local storage".to_string())?
+        .set_item(SETTINGS_STORAGE_KEY, &json)
+        .map_err(|err| format!("{:?}", err))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok().flatten()
+}
+
+/// Apply the video settings to the window and the renderer when they change.
+///
+/// The display mode, resolution and MSAA the app started with may come from the
+/// command line instead, so they're only applied once changed from the ones the app
+/// started with, like from the options. The shadows of the sun follow the graphics
+/// quality when the game starts, see the backdrop.
+fn apply_video_settings(
+    settings: Res<Settings>,
+    mut windows: ResMut<Windows>,
+    mut last_display: Local<Option<(DisplayMode, u32, u32)>>,
+    mut last_quality: Local<Option<GraphicsQuality>>,
+    msaa: Option<ResMut<Msaa>>,
+    shadow_map: Option<ResMut<DirectionalLightShadowMap>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let quality = settings.video.graphics_quality;
+    if let (Some(mut shadow_map), Some(size)) = (shadow_map, quality.shadow_map_size()) {
+        if shadow_map.size != size {
+            shadow_map.size = size;
+        }
+    }
+    let last_quality = last_quality.replace(quality);
+    if last_quality.is_some() && last_quality != Some(quality) {
+        debug!(target: "settings", "Graphics quality changed: {:?}", quality);
+        // MSAA isn't configured on the web
+        if let Some(mut msaa) = msaa.filter(|_| !cfg!(target_arch = "wasm32")) {
+            msaa.samples = quality.msaa_samples();
+        }
+    }
+    let window = if let Some(window) = windows.get_primary_mut() {
+        window
+    } else {
+        return;
+    };
+    let present_mode = settings.video.present_mode.into();
+    if window.present_mode() != present_mode {
+        window.set_present_mode(present_mode);
+    }
+
+    // The canvas follows the browser window instead on the web
+    if cfg!(target_arch = "wasm32") {
+        return;
+    }
+    let video = &settings.video;
+    let display = (video.display_mode, video.window_width, video.window_height);
+    let last_display = last_display.replace(display);
+    if last_display.is_none() || last_display == Some(display) {
+        return;
+    }
+    debug!(
+        target: "settings",
+        "Display changed: mode={:?} resolution={}x{}",
+        video.display_mode,
+        video.window_width,
+        video.window_height
+    );
+    // Resize first, so that the exclusive fullscreen picks the new resolution
+    window.set_resolution(video.window_width as f32, video.window_height as f32);
+    window.set_mode(video.display_mode.into());
+}
+
+/// Frame limiter state.
+struct FrameLimiter {
+    /// Time at which the previous frame ended.
+    last_frame_end: Instant,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        FrameLimiter {
+            last_frame_end: Instant::now(),
+        }
+    }
+}
+
+/// Sleep at the end of the frame to honor the frame rate cap.
+///
+/// On the web the browser paces frames itself, and the main thread cannot sleep,
+/// so this only applies to native builds.
+fn limit_frame_rate(settings: Res<Settings>, mut limiter: ResMut<FrameLimiter>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(frame_rate) = settings.video.frame_rate_cap.frame_rate() {
+        let target = Duration::from_secs_f64(1. / frame_rate);
+        let elapsed = limiter.last_frame_end.elapsed();
+        if elapsed < target {
+            std::thread::sleep(target - elapsed);
+        }
+    }
+    limiter.last_frame_end = Instant::now();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrate_effects_quality_to_graphics_quality() {
+        // Unversioned file, from before the graphics quality replaced the effects
+        // quality and the MSAA samples
+        let json = r#"{
+            "video": {
+                "effects_quality": "Low",
+                "msaa_samples": 4,
+                "window_width": 1600
+            },
+            "gameplay": { "show_rank": false }
+        }"#;
+        let loaded = save::from_json::<Settings>(json, &SETTINGS_FORMAT).unwrap();
+        assert_eq!(loaded.migrated_from, Some(0));
+        let settings = loaded.value;
+        assert_eq!(settings.video.graphics_quality, GraphicsQuality::Low);
+        assert_eq!(settings.video.window_width, 1600);
+        assert!(!settings.gameplay.show_rank);
+    }
+}
@@ -0,0 +1,298 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_kira_audio::{AudioApp, AudioChannel as KiraAudioChannel, AudioSource as KiraAudioSource};
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::music::{apply_channel_command, AudioChannelKind, AudioManager};
+
+/// Gameplay sound effects, played with a small random pitch and volume variation so
+/// that repeated sounds like the player shots don't drone, and rate-limited so that a
+/// burst of identical events doesn't stack dozens of copies of the same sound. A sound
+/// emitted by an entity plays at most once per frame for that entity.
+pub struct SfxPlugin;
+
+impl Plugin for SfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SfxEvent>()
+            .init_resource::<SfxVoices>()
+            .add_startup_system(sfx_setup)
+            // After the fixed timestep stage, which sends most of the events
+            .add_system_to_stage(CoreStage::PostUpdate, dispatch_sfx);
+        add_voice::<0>(app);
+        add_voice::<1>(app);
+        add_voice::<2>(app);
+        add_voice::<3>(app);
+        add_voice::<4>(app);
+        add_voice::<5>(app);
+        add_voice::<6>(app);
+        add_voice::<7>(app);
+        add_voice::<8>(app);
+        add_voice::<9>(app);
+        add_voice::<10>(app);
+        add_voice::<11>(app);
+        add_voice::<12>(app);
+        add_voice::<13>(app);
+        add_voice::<14>(app);
+        add_voice::<15>(app);
+        add_voice::<16>(app);
+        add_voice::<17>(app);
+    }
+}
+
+/// Maximum number of times the same sound effect plays in a single frame. Events
+/// beyond that are dropped, since the extra copies would only add up to a louder
+/// version of the same sound.
+const MAX_PLAYS_PER_FRAME: usize = 2;
+
+/// Number of audio channels sound effects are spread over. The volume and playback
+/// rate of a channel apply to all the sounds playing on it, so each sound plays on
+/// its own channel. There are enough channels for all sound effects to play the
+/// maximum number of times in a single frame.
+const VOICE_COUNT: usize = Sfx::COUNT * MAX_PLAYS_PER_FRAME;
+
+/// Base volume of all sound effects, scaled by the volume setting.
+const SFX_VOLUME: f32 = 0.5;
+
+/// Gameplay sound effect. Enemy fire tags name the sounds of their volleys in the
+/// enemy database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Sfx {
+    /// Player primary weapon volley.
+    #[serde(alias = "shoot")]
+    Shoot,
+    /// Player bullet hitting an enemy.
+    #[serde(alias = "hit")]
+    Hit,
+    /// Enemy killed.
+    #[serde(alias = "explosion")]
+    Explosion,
+    /// Boss killed.
+    #[serde(alias = "boss_explosion")]
+    BossExplosion,
+    /// Pickup collected by the player.
+    #[serde(alias = "pickup")]
+    Pickup,
+    /// Player taking damage.
+    #[serde(alias = "player_hit")]
+    PlayerHit,
+    /// Enemy volley.
+    #[serde(alias = "enemy_shot")]
+    EnemyShot,
+    /// Enemy charging up before a large volley.
+    #[serde(alias = "charge_up")]
+    ChargeUp,
+    /// Tick of the stage clear bonus tally counting up.
+    #[serde(alias = "tally")]
+    Tally,
+}
+
+impl Sfx {
+    const COUNT: usize = 9;
+}
+
+/// Description of a sound effect.
+struct SfxDesc {
+    /// Path of the audio asset.
+    path: &'static str,
+    /// Volume, relative to [`SFX_VOLUME`].
+    volume: f32,
+    /// Playback rate, which also shifts the pitch.
+    rate: f32,
+    /// Maximum random variation of the volume, as a fraction of the volume.
+    volume_jitter: f32,
+    /// Maximum random variation of the playback rate, as a fraction of the rate.
+    rate_jitter: f32,
+}
+
+/// Description of each sound effect, indexed by [`Sfx`]. Until dedicated samples are
+/// recorded, all sounds but the hit reuse the existing samples at a different pitch.
+const SFX_TABLE: [SfxDesc; Sfx::COUNT] = [
+    // Shoot
+    SfxDesc {
+        path: "sounds/click4.ogg",
+        volume: 0.3,
+        rate: 0.6,
+        volume_jitter: 0.2,
+        rate_jitter: 0.08,
+    },
+    // Hit
+    SfxDesc {
+        path: "sounds/hit.ogg",
+        volume: 1.,
+        rate: 1.,
+        volume_jitter: 0.15,
+        rate_jitter: 0.1,
+    },
+    // Explosion, low and loud enough to stand out of the hits on the same enemy
+    SfxDesc {
+        path: "sounds/hit.ogg",
+        volume: 1.3,
+        rate: 0.32,
+        volume_jitter: 0.1,
+        rate_jitter: 0.12,
+    },
+    // BossExplosion
+    SfxDesc {
+        path: "sounds/hit.ogg",
+        volume: 1.8,
+        rate: 0.2,
+        volume_jitter: 0.,
+        rate_jitter: 0.05,
+    },
+    // Pickup
+    SfxDesc {
+        path: "sounds/click4.ogg",
+        volume: 0.6,
+        rate: 1.6,
+        volume_jitter: 0.1,
+        rate_jitter: 0.05,
+    },
+    // PlayerHit
+    SfxDesc {
+        path: "sounds/hit.ogg",
+        volume: 1.4,
+        rate: 0.7,
+        volume_jitter: 0.05,
+        rate_jitter: 0.05,
+    },
+    // EnemyShot
+    SfxDesc {
+        path: "sounds/click4.ogg",
+        volume: 0.25,
+        rate: 0.4,
+        volume_jitter: 0.2,
+        rate_jitter: 0.1,
+    },
+    // ChargeUp
+    SfxDesc {
+        path: "sounds/sweep_fill2.ogg",
+        volume: 0.8,
+        rate: 1.3,
+        volume_jitter: 0.05,
+        rate_jitter: 0.03,
+    },
+    // Tally
+    SfxDesc {
+        path: "sounds/click4.ogg",
+        volume: 0.4,
+        rate: 2.2,
+        volume_jitter: 0.05,
+        rate_jitter: 0.04,
+    },
+];
+
+/// Event to play a sound effect.
+#[derive(Debug, Clone, Copy)]
+pub struct SfxEvent {
+    pub sfx: Sfx,
+    /// Entity emitting the sound, if any. Each entity emits a given sound at most once
+    /// per frame, like the hits of all the bullets of a volley on the same enemy.
+    pub source: Option<Entity>,
+}
+
+impl SfxEvent {
+    /// Sound effect not emitted by any entity in particular.
+    pub fn new(sfx: Sfx) -> SfxEvent {
+        SfxEvent { sfx, source: None }
+    }
+
+    /// Sound effect emitted by an entity.
+    pub fn from_entity(sfx: Sfx, entity: Entity) -> SfxEvent {
+        SfxEvent {
+            sfx,
+            source: Some(entity),
+        }
+    }
+}
+
+/// Audio channel of a single voice.
+#[derive(Default)]
+struct SfxVoice<const I: usize>;
+
+/// Request to play a sound on a voice.
+#[derive(Debug, Clone)]
+struct SfxPlay {
+    source: Handle<KiraAudioSource>,
+    volume: f32,
+    rate: f32,
+}
+
+/// Sound effect voices, and the sound assigned to each voice this frame, if any.
+#[derive(Default)]
+struct SfxVoices {
+    /// Handle of each sound effect, indexed by [`Sfx`].
+    sources: Vec<Handle<KiraAudioSource>>,
+    /// Sound to play on each voice this frame, if any.
+    pending: [Option<SfxPlay>; VOICE_COUNT],
+    /// Voice to assign the next sound to.
+    next_voice: usize,
+}
+
+fn add_voice<const I: usize>(app: &mut App) {
+    app.add_audio_channel::<SfxVoice<I>>()
+        .add_system_to_stage(CoreStage::PostUpdate, play_voice::<I>.after(dispatch_sfx));
+}
+
+fn sfx_setup(asset_server: Res<AssetServer>, mut voices: ResMut<SfxVoices>) {
+    voices.sources = SFX_TABLE
+        .iter()
+        .map(|desc| asset_server.load(desc.path))
+        .collect();
+}
+
+/// Assign the sound effects requested this frame to the voices, up to
+/// [`MAX_PLAYS_PER_FRAME`] per sound effect and once per source entity, with some
+/// random variation.
+fn dispatch_sfx(
+    mut voices: ResMut<SfxVoices>,
+    mut sfx_events: EventReader<SfxEvent>,
+    audio_manager: Res<AudioManager>,
+) {
+    let mut rng = thread_rng();
+    let mut play_counts = [0; Sfx::COUNT];
+    let mut sources = HashSet::default();
+    let gain = audio_manager.sfx_gain();
+    for ev in sfx_events.iter() {
+        let index = ev.sfx as usize;
+        if gain <= 0. || play_counts[index] >= MAX_PLAYS_PER_FRAME {
+            continue;
+        }
+        if let Some(source) = ev.source {
+            if !sources.insert((ev.sfx, source)) {
+                continue;
+            }
+        }
+        play_counts[index] += 1;
+
+        let desc = &SFX_TABLE[index];
+        let volume_jitter = rng.gen_range(-desc.volume_jitter..=desc.volume_jitter);
+        let rate_jitter = rng.gen_range(-desc.rate_jitter..=desc.rate_jitter);
+        let play = SfxPlay {
+            source: voices.sources[index].clone(),
+            volume: SFX_VOLUME * gain * desc.volume * (1. + volume_jitter),
+            rate: desc.rate * (1. + rate_jitter),
+        };
+        let voice = voices.next_voice;
+        voices.pending[voice] = Some(play);
+        voices.next_voice = (voice + 1) % VOICE_COUNT;
+    }
+}
+
+/// Apply the commands issued this frame to the sound effect channels, then play the
+/// sound assigned to a voice this frame, if any.
+fn play_voice<const I: usize>(
+    mut voices: ResMut<SfxVoices>,
+    channel: Res<KiraAudioChannel<SfxVoice<I>>>,
+    audio_manager: Res<AudioManager>,
+) {
+    for command in audio_manager.channel_commands(AudioChannelKind::Sfx) {
+        apply_channel_command(&*channel, command);
+    }
+    if let Some(play) = voices.pending[I].take() {
+        // This also retunes the previous sound played on this voice, but short sound
+        // effects have usually ended by the time the voice comes around again
+        channel.set_volume(play.volume);
+        channel.set_playback_rate(play.rate);
+        channel.play(play.source);
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+
+use crate::{
+    game::{CameraBaseTransform, GameTime, MainCamera},
+    settings::Settings,
+    AppState,
+};
+
+pub struct ShakePlugin;
+
+impl Plugin for ShakePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ScreenShakeEvent>()
+            .init_resource::<ScreenShake>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(shake_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_screen_shake),
+            );
+    }
+}
+
+/// Event to shake the screen, adding some trauma to the [`ScreenShake`].
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenShakeEvent {
+    /// Amount of trauma to add, where 1 is the strongest shake.
+    pub trauma: f32,
+}
+
+/// Trauma removed per second.
+const TRAUMA_DECAY: f32 = 1.2;
+
+/// Camera offset at full trauma, in world units.
+const MAX_OFFSET: f32 = 0.12;
+
+/// Camera roll at full trauma, in radians.
+const MAX_ROLL: f32 = 0.02;
+
+/// Screen shake state of the main camera.
+///
+/// The shake is driven by a trauma value in `[0:1]` which decays over time. The camera
+/// offset is proportional to the square of the trauma, so that small hits barely shake
+/// the screen while big events do.
+#[derive(Default)]
+pub struct ScreenShake {
+    pub trauma: f32,
+}
+
+fn shake_setup(mut commands: Commands) {
+    commands.insert_resource(ScreenShake::default());
+}
+
+/// Apply a decaying random offset to the main camera, on top of its base transform. The
+/// screen doesn't shake at all when flashing is reduced.
+fn update_screen_shake(
+    mut shake: ResMut<ScreenShake>,
+    mut shake_events: EventReader<ScreenShakeEvent>,
+    mut q_camera: Query<(&mut Transform, &CameraBaseTransform), With<MainCamera>>,
+    game_time: Res<GameTime>,
+    settings: Res<Settings>,
+) {
+    for ev in shake_events.iter() {
+        shake.trauma = (shake.trauma + ev.trauma).min(1.);
+    }
+    if settings.accessibility.reduce_flashing {
+        shake.trauma = 0.;
+    }
+
+    let (mut transform, base_transform) = if let Ok(camera) = q_camera.get_single_mut() {
+        camera
+    } else {
+        return;
+    };
+
+    if shake.trauma <= 0. {
+        if *transform != base_transform.0 {
+            *transform = base_transform.0;
+        }
+        return;
+    }
+
+    let mut rng = thread_rng();
+    let amount = shake.trauma * shake.trauma;
+    let offset = Vec3::new(
+        rng.gen_range(-1.0..1.0) * MAX_OFFSET * amount,
+        rng.gen_range(-1.0..1.0) * MAX_OFFSET * amount,
+        0.,
+    );
+    let roll = rng.gen_range(-1.0..1.0) * MAX_ROLL * amount;
+    *transform = base_transform.0;
+    transform.translation += offset;
+    transform.rotate(Quat::from_rotation_z(roll));
+
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * game_time.delta_seconds()).max(0.);
+}
@@ -0,0 +1,192 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use std::f32::consts::{PI, TAU};
+
+use crate::{
+    game::{InGameEntity, PlayerController},
+    settings::Settings,
+    timestep::{FixedStep, FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+pub struct ShieldPlugin;
+
+impl Plugin for ShieldPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set_to_stage(
+            CoreStage::Update,
+            SystemSet::on_enter(AppState::InGame).with_system(shield_setup),
+        )
+        .add_system_set_to_stage(
+            CoreStage::Update,
+            SystemSet::on_update(AppState::InGame).with_system(update_shield_visuals),
+        )
+        .add_system_set_to_stage(
+            FixedUpdateStage,
+            SystemSet::on_update(AppState::InGame)
+                .with_system(tick_shields)
+                .with_system(follow_player.after(tick_shields)),
+        );
+    }
+}
+
+/// Duration of the shield granted by a shield pickup, in seconds.
+pub const SHIELD_DURATION: f32 = 8.;
+
+/// Radius of the shield bubble, in world units. Enemy bullets touching the bubble are
+/// destroyed.
+pub const SHIELD_RADIUS: f32 = 0.22;
+
+/// Number of segments of the timer ring around the bubble.
+const RING_SEGMENTS: usize = 24;
+
+/// Remaining time below which the bubble blinks, in seconds.
+const SHIELD_WARNING_TIME: f32 = 2.;
+
+const SHIELD_COLOR: Color = Color::rgba(0.3, 0.9, 1., 0.25);
+
+const RING_COLOR: Color = Color::rgb(0.5, 1., 1.);
+
+/// Timed shield protecting the player from enemy bullets, granted by a shield pickup.
+/// Enemy bullets are destroyed on contact with the bubble instead of damaging the
+/// player. The shield is lost if the player dies.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Shield {
+    /// Remaining time of the shield, in seconds.
+    pub remaining: f32,
+}
+
+impl Default for Shield {
+    fn default() -> Self {
+        Shield {
+            remaining: SHIELD_DURATION,
+        }
+    }
+}
+
+/// Translucent bubble displayed around the player ship while shielded.
+#[derive(Component)]
+struct ShieldBubble;
+
+/// Segment of the timer ring around the bubble, which turns off as the shield runs out.
+#[derive(Component)]
+struct ShieldRingSegment(usize);
+
+fn shield_setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let segment_mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::new(0.035, 0.012),
+        flip: false,
+    }));
+    let segment_material = materials.add(StandardMaterial {
+        base_color: RING_COLOR,
+        unlit: true,
+        ..Default::default()
+    });
+
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Icosphere {
+                radius: SHIELD_RADIUS,
+                subdivisions: 3,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: SHIELD_COLOR,
+                unlit: true,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+            // Placed on the player by follow_player()
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Name::new("ShieldBubble"))
+        .insert(InGameEntity)
+        .insert(ShieldBubble)
+        .insert(FixedStep::default())
+        .insert(NotShadowCaster)
+        .insert(NotShadowReceiver)
+        .with_children(|parent| {
+            // Clockwise from the top, so the ring empties like a clock
+            for index in 0..RING_SEGMENTS {
+                let angle = PI / 2. - TAU * index as f32 / RING_SEGMENTS as f32;
+                let offset = Vec3::new(angle.cos(), angle.sin(), 0.) * (SHIELD_RADIUS + 0.04);
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh: segment_mesh.clone(),
+                        material: segment_material.clone(),
+                        transform: Transform::from_translation(offset)
+                            .with_rotation(Quat::from_rotation_z(angle)),
+                        visibility: Visibility { is_visible: false },
+                        ..Default::default()
+                    })
+                    .insert(ShieldRingSegment(index))
+                    .insert(NotShadowCaster)
+                    .insert(NotShadowReceiver);
+            }
+        });
+}
+
+/// Run the shields down, removing them once expired or if the player died.
+fn tick_shields(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Shield, &PlayerController)>,
+) {
+    for (entity, mut shield, controller) in query.iter_mut() {
+        shield.remaining -= FIXED_DELTA_SECONDS;
+        if shield.remaining <= 0. || controller.is_respawning() {
+            commands.entity(entity).remove::<Shield>();
+        }
+    }
+}
+
+fn follow_player(
+    mut q_bubble: Query<&mut Transform, With<ShieldBubble>>,
+    q_player: Query<&Transform, (With<Shield>, Without<ShieldBubble>)>,
+) {
+    if let (Ok(mut transform), Ok(player_transform)) =
+        (q_bubble.get_single_mut(), q_player.get_single())
+    {
+        transform.translation = player_transform.translation;
+    }
+}
+
+/// Show the bubble while the player is shielded, blinking when about to run out, and
+/// turn off the segments of the timer ring as time passes.
+fn update_shield_visuals(
+    q_player: Query<&Shield>,
+    mut q_bubble: Query<&mut Visibility, With<ShieldBubble>>,
+    mut q_segments: Query<(&ShieldRingSegment, &mut Visibility), Without<ShieldBubble>>,
+    settings: Res<Settings>,
+) {
+    let remaining = q_player
+        .get_single()
+        .map_or(0., |shield| shield.remaining.max(0.));
+
+    if let Ok(mut visibility) = q_bubble.get_single_mut() {
+        let is_visible = if remaining <= 0. {
+            false
+        } else if remaining < SHIELD_WARNING_TIME && !settings.accessibility.reduce_flashing {
+            // Blink 4 times per second
+            (remaining * 8.).fract() < 0.5
+        } else {
+            true
+        };
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+    }
+
+    let lit_segments = (remaining / SHIELD_DURATION * RING_SEGMENTS as f32).ceil() as usize;
+    for (segment, mut visibility) in q_segments.iter_mut() {
+        let is_visible = segment.0 < lit_segments;
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use bevy::prelude::*;
+use bevy_kira_audio::AudioSource as KiraAudioSource;
+use leafwing_input_manager::prelude::*;
+use serde::{de::Error as _, Deserialize};
+
+use crate::{
+    flow::RequestStateChange,
+    music::AudioFacade,
+    navigation::{FocusHighlight, NavAction, NavMenu, NavMenuBundle},
+    profile::Profile,
+    AppState,
+};
+
+/// Player ships, and the screen to select one before starting a new game.
+pub struct ShipPlugin;
+
+impl Plugin for ShipPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Hangar::load())
+            .add_system_set(
+                SystemSet::on_enter(AppState::ShipSelect).with_system(ship_select_setup),
+            )
+            .add_system_set(SystemSet::on_update(AppState::ShipSelect).with_system(ship_select_run))
+            .add_system_set(
+                SystemSet::on_exit(AppState::ShipSelect).with_system(ship_select_cleanup),
+            );
+    }
+}
+
+/// Number of characters of the stat bars of the ship select screen.
+const STAT_BAR_LEN: usize = 10;
+
+/// Weapon power tier of a ship, upgraded by collecting power items.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PowerTier {
+    /// Total number of power items to collect to reach this tier.
+    pub power: u32,
+    /// Delay between two consecutive volleys, in seconds.
+    pub fire_delay: f32,
+    /// Bullets fired in a single volley, as (vertical offset, angle in degrees).
+    pub volley: Vec<(f32, f32)>,
+}
+
+/// Stats of a player ship.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ShipDescriptor {
+    pub name: String,
+    pub description: String,
+    /// Path of the GLTF scene of the ship model.
+    pub model: String,
+    /// Scale of the model.
+    pub scale: f32,
+    /// Color multiplied with the colors of the model, to tell apart the ships sharing
+    /// the same model.
+    #[serde(default)]
+    pub tint: Option<[f32; 3]>,
+    /// Half size of the ship on screen, in world units, used as a margin to keep the
+    /// whole ship inside the screen bounds.
+    pub half_size: [f32; 2],
+    /// Movement speed, in world units per second.
+    pub speed: f32,
+    /// Radius of the hurt box, in world units. Usually much smaller than the model,
+    /// which is why it's not derived from the model bounds like for enemies; only its
+    /// position at the cockpit and the larger graze and collection boxes are.
+    pub hitbox_radius: f32,
+    /// Offset of the bullet spawn point from the ship origin, in world units.
+    pub fire_offset: [f32; 3],
+    /// Weapon power tiers, by increasing power. The first tier is the initial one.
+    pub power_tiers: Vec<PowerTier>,
+    /// Stage to reach, starting from 1, to unlock the ship, or `None` if available
+    /// from the start.
+    #[serde(default)]
+    pub unlock_stage: Option<usize>,
+}
+
+impl ShipDescriptor {
+    /// Ship used if the ship database fails to load, to keep the game playable.
+    fn stock() -> ShipDescriptor {
+        ShipDescriptor {
+            name: "Kaizen-1".to_string(),
+            description: String::new(),
+            model: "ship1.glb#Scene0".to_string(),
+            scale: 0.3,
+            tint: None,
+            half_size: [0.12, 0.08],
+            speed: 1.6,
+            hitbox_radius: 0.06,
+            fire_offset: [0.087, 0., -0.033],
+            power_tiers: vec![PowerTier {
+                power: 0,
+                fire_delay: 0.084,
+                volley: vec![(0., 0.), (0.1, 0.), (-0.1, 0.)],
+            }],
+            unlock_stage: None,
+        }
+    }
+
+    /// Bullets fired per second at the highest power tier.
+    fn max_fire_rate(&self) -> f32 {
+        self.power_tiers.last().map_or(0., |tier| {
+            tier.volley.len() as f32 / tier.fire_delay.max(f32::EPSILON)
+        })
+    }
+}
+
+/// Ships available to the player, loaded from `assets/ship_db.json`.
+#[derive(Debug, Clone, Deserialize)]
+struct ShipDatabase {
+    ships: Vec<ShipDescriptor>,
+}
+
+impl ShipDatabase {
+    fn parse() -> serde_json::Result<ShipDatabase> {
+        let database: ShipDatabase = serde_json::from_str(include_str!("../assets/ship_db.json"))?;
+        if database.ships.is_empty() {
+            return Err(serde_json::Error::custom("no ship"));
+        }
+        if database.ships[0].unlock_stage.is_some() {
+            return Err(serde_json::Error::custom("first ship is locked"));
+        }
+        if let Some(ship) = database
+            .ships
+            .iter()
+            .find(|ship| ship.power_tiers.is_empty())
+        {
+            return Err(serde_json::Error::custom(format!(
+                "ship '{}' has no power tier",
+                ship.name
+            )));
+        }
+        Ok(database)
+    }
+}
+
+/// Error parsing the ship database, if any, to report it during boot. The game still
+/// runs with a single stock ship in that case.
+pub fn ship_database_error() -> Option<String> {
+    ShipDatabase::parse().err().map(|err| err.to_string())
+}
+
+/// Ships available to the player, and the one selected for the next game.
+pub struct Hangar {
+    database: ShipDatabase,
+    /// Index of the selected ship. Kept between games, so that the selection screen
+    /// starts on the previous choice.
+    selected: usize,
+}
+
+impl Hangar {
+    fn load() -> Hangar {
+        let database = ShipDatabase::parse().unwrap_or_else(|err| {
+            error!(target: "ship", "Failed to parse ship database: {}", err);
+            ShipDatabase {
+                ships: vec![ShipDescriptor::stock()],
+            }
+        });
+        Hangar {
+            database,
+            selected: 0,
+        }
+    }
+
+    /// Ship selected for the next game.
+    pub fn ship(&self) -> &ShipDescriptor {
+        &self.database.ships[self.selected]
+    }
+
+    /// All the ships, locked or not.
+    pub fn ships(&self) -> &[ShipDescriptor] {
+        &self.database.ships
+    }
+
+    /// Can the ship at the given index be selected with the progress of the profile?
+    fn is_unlocked(&self, index: usize, profile: &Profile) -> bool {
+        let ship = &self.database.ships[index];
+        ship.unlock_stage.is_none() || profile.is_ship_unlocked(&ship.name)
+    }
+
+    /// Rating in `[0:1]` of each stat of a ship relative to the best ship for that stat,
+    /// as (speed, fire rate, hitbox).
+    fn ratings(&self, ship: &ShipDescriptor) -> (f32, f32, f32) {
+        let ships = &self.database.ships;
+        let max_speed = ships.iter().map(|ship| ship.speed).fold(0., f32::max);
+        let max_fire_rate = ships
+            .iter()
+            .map(|ship| ship.max_fire_rate())
+            .fold(0., f32::max);
+        let min_hitbox = ships
+            .iter()
+            .map(|ship| ship.hitbox_radius)
+            .fold(f32::MAX, f32::min);
+        (
+            ship.speed / max_speed.max(f32::EPSILON),
+            ship.max_fire_rate() / max_fire_rate.max(f32::EPSILON),
+            // Smaller is better
+            min_hitbox / ship.hitbox_radius.max(f32::EPSILON),
+        )
+    }
+
+    /// Description and stat bars of the ship at the given index, or how to unlock it.
+    fn stats_text(&self, index: usize, profile: &Profile) -> String {
+        let ship = &self.database.ships[index];
+        if !self.is_unlocked(index, profile) {
+            return format!(
+                "Reach stage {} to unlock this ship.",
+                ship.unlock_stage.unwrap_or(1)
+            );
+        }
+        let (speed, fire_rate, hitbox) = self.ratings(ship);
+        format!(
+            "{}\n\nSpeed    {}\nFire     {}\nHitbox   {}",
+            ship.description,
+            stat_bar(speed),
+            stat_bar(fire_rate),
+            stat_bar(hitbox)
+        )
+    }
+}
+
+/// Text bar showing a rating in `[0:1]`.
+fn stat_bar(rating: f32) -> String {
+    let len = ((rating * STAT_BAR_LEN as f32).round() as usize).clamp(1, STAT_BAR_LEN);
+    format!("{}{}", "|".repeat(len), ".".repeat(STAT_BAR_LEN - len))
+}
+
+#[derive(Component, Default)]
+struct ShipSelectMenu {
+    sound_click: Handle<KiraAudioSource>,
+}
+
+/// Marker for all entities of the ship select screen, for cleanup.
+#[derive(Component)]
+struct ShipSelectScreen;
+
+/// Text describing the ship which has the focus.
+#[derive(Component)]
+struct ShipStats;
+
+fn ship_select_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut hangar: ResMut<Hangar>,
+    profile: Res<Profile>,
+) {
+    debug!(target: "setup", "ship_select_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(ShipSelectScreen);
+
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    let text_color = Color::rgb_u8(32, 32, 32);
+    let focus_color = Color::rgb_u8(57, 194, 190);
+    let text = |value: String, font_size: f32| {
+        Text::with_section(
+            value,
+            TextStyle {
+                font: font.clone(),
+                font_size,
+                color: text_color,
+            },
+            Default::default(),
+        )
+    };
+
+    let mut menu = ShipSelectMenu::default();
+    menu.sound_click = asset_server.load("sounds/click4.ogg");
+
+    // The previous choice may have been locked again by a progress reset
+    if !hangar.is_unlocked(hangar.selected, &*profile) {
+        hangar.selected = 0;
+    }
+
+    // All ships, then Back
+    let ship_count = hangar.database.ships.len();
+    let mut nav_bundle = NavMenuBundle::new(ship_count + 1);
+    nav_bundle.menu.focus(hangar.selected);
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect::all(Val::Px(0.)),
+                margin: Rect::all(Val::Px(16.)),
+                padding: Rect::all(Val::Px(16.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("ship_select"))
+        .insert(ShipSelectScreen)
+        .insert(menu)
+        .insert_bundle(nav_bundle)
+        .with_children(|parent| {
+            let menu_entity = parent.parent_entity();
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(24.)),
+                    ..Default::default()
+                },
+                text: text("Select Ship".to_string(), 64.),
+                ..Default::default()
+            });
+
+            for (index, ship) in hangar.database.ships.iter().enumerate() {
+                let name = if hangar.is_unlocked(index, &*profile) {
+                    ship.name.clone()
+                } else {
+                    "???".to_string()
+                };
+                parent
+                    .spawn_bundle(TextBundle {
+                        style: Style {
+                            margin: Rect::all(Val::Px(4.)),
+                            ..Default::default()
+                        },
+                        text: text(name, 36.),
+                        ..Default::default()
+                    })
+                    .insert(FocusHighlight {
+                        menu: menu_entity,
+                        index,
+                        color: text_color,
+                        focus_color,
+                    });
+            }
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(24.)),
+                        min_size: Size::new(Val::Px(640.), Val::Undefined),
+                        ..Default::default()
+                    },
+                    text: text(hangar.stats_text(hangar.selected, &*profile), 24.),
+                    ..Default::default()
+                })
+                .insert(ShipStats);
+
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(4.)),
+                        ..Default::default()
+                    },
+                    text: text("Back".to_string(), 36.),
+                    ..Default::default()
+                })
+                .insert(FocusHighlight {
+                    menu: menu_entity,
+                    index: ship_count,
+                    color: text_color,
+                    focus_color,
+                });
+        });
+}
+
+fn ship_select_run(
+    mut q_menu: Query<(&ShipSelectMenu, &mut NavMenu, &ActionState<NavAction>)>,
+    mut q_stats: Query<&mut Text, With<ShipStats>>,
+    mut hangar: ResMut<Hangar>,
+    profile: Res<Profile>,
+    audio_facade: AudioFacade,
+    mut state_requests: EventWriter<RequestStateChange>,
+) {
+    let (menu, mut nav, action_state) = q_menu.single_mut();
+    let ship_count = hangar.database.ships.len();
+    if nav.navigate(action_state) {
+        audio_facade.play_ui(menu.sound_click.clone());
+        // Keep describing the last ship focused while on Back
+        if nav.focused < ship_count {
+            let stats = hangar.stats_text(nav.focused, &*profile);
+            for mut text in q_stats.iter_mut() {
+                text.sections[0].value = stats.clone();
+            }
+        }
+    }
+
+    if action_state.just_pressed(NavAction::Back)
+        || (nav.focused == ship_count && action_state.just_pressed(NavAction::Confirm))
+    {
+        audio_facade.play_ui(menu.sound_click.clone());
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+    } else if action_state.just_pressed(NavAction::Confirm)
+        && hangar.is_unlocked(nav.focused, &*profile)
+    {
+        audio_facade.play_ui(menu.sound_click.clone());
+        hangar.selected = nav.focused;
+        info!(target: "ship", "Selected ship '{}'", hangar.ship().name);
+        state_requests.send(RequestStateChange::fade_to(AppState::InGame));
+    }
+}
+
+fn ship_select_cleanup(mut commands: Commands, query: Query<Entity, With<ShipSelectScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
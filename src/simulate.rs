@@ -0,0 +1,196 @@
+use bevy::{app::AppExit, prelude::*};
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    cli::LaunchOptions,
+    game::{
+        move_enemy_bullets, Damage, EnemyBullet, MainCamera, PlayerAction, PlayerController,
+        RunSeed, ENEMY_BULLET_DAMAGE,
+    },
+    replay::Replay,
+    timestep::{FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+/// Headless simulation mode, enabled with `--simulate`. Plays a stage for a fixed
+/// duration with a scripted player, or one following a replay, then logs statistics
+/// about the enemy bullets and the entity count and exits, to check the balance and
+/// the performance budget of the patterns without playing.
+pub struct SimulatePlugin;
+
+impl Plugin for SimulatePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Simulation>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(simulation_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(drive_player),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_simulation.after(move_enemy_bullets)),
+            );
+    }
+}
+
+/// Number of columns of the screen regions the bullet damage is accumulated into.
+const GRID_COLUMNS: usize = 4;
+
+/// Number of rows of the screen regions the bullet damage is accumulated into.
+const GRID_ROWS: usize = 3;
+
+/// Horizontal position of the scripted player, in fraction of the screen width from
+/// the left edge.
+const PILOT_X: f32 = 0.2;
+
+/// Vertical extent of the sweep of the scripted player, in fraction of the half
+/// screen height.
+const PILOT_SWEEP_AMPLITUDE: f32 = 0.7;
+
+/// Duration of a full sweep of the scripted player, in seconds.
+const PILOT_SWEEP_PERIOD: f32 = 8.;
+
+/// Distance to its target under which the player stops moving along an axis, in world
+/// units.
+const PILOT_DEADZONE: f32 = 0.05;
+
+#[derive(Default)]
+struct Simulation {
+    /// Replay the player follows, if any, instead of the scripted sweep.
+    replay: Option<Replay>,
+    /// Simulated game time, in seconds.
+    time: f32,
+    /// Number of enemy bullets spawned.
+    bullets_spawned: u32,
+    /// Damage of the enemy bullets inside each screen region, integrated over time,
+    /// in row-major order from the top left region.
+    damage: [f32; GRID_COLUMNS * GRID_ROWS],
+    /// Peak number of enemy bullets alive at once.
+    peak_bullets: usize,
+    /// Peak number of entities alive at once.
+    peak_entities: usize,
+}
+
+fn simulation_setup(mut simulation: ResMut<Simulation>, launch: Res<LaunchOptions>) {
+    let replay = launch.replay.as_deref().and_then(Replay::load_file);
+    *simulation = Simulation {
+        replay,
+        ..Default::default()
+    };
+}
+
+/// Fire continuously and move the player toward its target, like the touch controls
+/// do, since the input manager releases all the actions on each update.
+fn drive_player(
+    simulation: Res<Simulation>,
+    q_camera: Query<&MainCamera>,
+    mut q_player: Query<(&mut ActionState<PlayerAction>, &Transform), With<PlayerController>>,
+) {
+    let (mut action_state, transform) = if let Ok(player) = q_player.get_single_mut() {
+        player
+    } else {
+        return;
+    };
+    action_state.press(PlayerAction::ShootPrimary);
+
+    let replay_target = simulation
+        .replay
+        .as_ref()
+        .and_then(|replay| replay.position_at(simulation.time));
+    let target = if let Some(target) = replay_target {
+        target
+    } else if let Ok(main_camera) = q_camera.get_single() {
+        let bounds = main_camera.screen_bounds;
+        let phase = simulation.time / PILOT_SWEEP_PERIOD * std::f32::consts::TAU;
+        Vec2::new(
+            bounds.left + (bounds.right - bounds.left) * PILOT_X,
+            phase.sin() * bounds.top * PILOT_SWEEP_AMPLITUDE,
+        )
+    } else {
+        return;
+    };
+
+    let delta = target - transform.translation.truncate();
+    if delta.x > PILOT_DEADZONE {
+        action_state.press(PlayerAction::MoveRight);
+    } else if delta.x < -PILOT_DEADZONE {
+        action_state.press(PlayerAction::MoveLeft);
+    }
+    if delta.y > PILOT_DEADZONE {
+        action_state.press(PlayerAction::MoveUp);
+    } else if delta.y < -PILOT_DEADZONE {
+        action_state.press(PlayerAction::MoveDown);
+    }
+}
+
+/// Accumulate the statistics of each gameplay step, and log them once the simulation
+/// ran for its duration.
+fn update_simulation(
+    mut simulation: ResMut<Simulation>,
+    launch: Res<LaunchOptions>,
+    run_seed: Res<RunSeed>,
+    q_camera: Query<&MainCamera>,
+    q_bullets: Query<(&Transform, Option<&Damage>), With<EnemyBullet>>,
+    q_added: Query<(), Added<EnemyBullet>>,
+    q_entities: Query<Entity>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let duration = if let Some(duration) = launch.simulate {
+        duration
+    } else {
+        return;
+    };
+    if simulation.time >= duration {
+        return;
+    }
+    simulation.time += FIXED_DELTA_SECONDS;
+
+    simulation.bullets_spawned += q_added.iter().count() as u32;
+    simulation.peak_bullets = simulation.peak_bullets.max(q_bullets.iter().count());
+    simulation.peak_entities = simulation.peak_entities.max(q_entities.iter().count());
+    if let Ok(main_camera) = q_camera.get_single() {
+        let bounds = main_camera.screen_bounds;
+        let width = (bounds.right - bounds.left).max(f32::EPSILON);
+        let height = (bounds.top - bounds.bottom).max(f32::EPSILON);
+        for (transform, damage) in q_bullets.iter() {
+            let x = (transform.translation.x - bounds.left) / width;
+            let y = (bounds.top - transform.translation.y) / height;
+            if !(0. ..1.).contains(&x) || !(0. ..1.).contains(&y) {
+                continue;
+            }
+            let column = (x * GRID_COLUMNS as f32) as usize;
+            let row = (y * GRID_ROWS as f32) as usize;
+            let damage = damage.map_or(ENEMY_BULLET_DAMAGE, |damage| damage.0);
+            simulation.damage[row * GRID_COLUMNS + column] += damage * FIXED_DELTA_SECONDS;
+        }
+    }
+
+    if simulation.time < duration {
+        return;
+    }
+    info!(
+        target: "simulate",
+        "SIMULATE: stage={} duration={:.1}s seed={} player={} bullets_spawned={} peak_bullets={} peak_entities={}",
+        launch.start_stage() + 1,
+        simulation.time,
+        run_seed.seed,
+        if simulation.replay.is_some() { "replay" } else { "scripted" },
+        simulation.bullets_spawned,
+        simulation.peak_bullets,
+        simulation.peak_entities
+    );
+    // Average damage of the bullets on screen within each region, which a player
+    // standing there would be exposed to
+    for row in 0..GRID_ROWS {
+        let densities = simulation.damage[row * GRID_COLUMNS..(row + 1) * GRID_COLUMNS]
+            .iter()
+            .map(|damage| format!("{:8.2}", damage / simulation.time))
+            .collect::<Vec<_>>();
+        info!(target: "simulate", "SIMULATE: damage density row {}: {}", row, densities.join(" "));
+    }
+    exit.send(AppExit);
+}
@@ -0,0 +1,300 @@
+use bevy::prelude::*;
+
+use crate::{
+    cli::LaunchOptions,
+    enemy::PatternCaptures,
+    game::{GameOverEvent, GameTime, InGameEntity, RunSeed, StageClearEvent},
+    profile::Profile,
+    settings::Settings,
+    stats::RunStats,
+    AppState,
+};
+
+pub struct SpeedrunPlugin;
+
+impl Plugin for SpeedrunPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunTimer>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(speedrun_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_run_timer)
+                    .with_system(update_run_timer_hud.after(update_run_timer)),
+            );
+    }
+}
+
+/// Split of a single stage.
+#[derive(Debug, Clone, Copy)]
+pub struct Split {
+    /// Run time at the end of the stage, in seconds.
+    pub time: f32,
+    /// Difference with the best time for that stage, if any. Negative if faster.
+    pub delta: Option<f32>,
+}
+
+/// Timer of the current run, with per-stage splits.
+#[derive(Default)]
+pub struct RunTimer {
+    /// Run time, in seconds.
+    pub time: f32,
+    /// Is the timer running? It stops on game over or after clearing the last stage.
+    pub running: bool,
+    /// Splits of all stages cleared so far.
+    pub splits: Vec<Split>,
+}
+
+#[derive(Component)]
+struct RunTimerText;
+
+/// Results panel shown at the end of the run, with the stage splits if the run timer
+/// is enabled, the run statistics, and the boss pattern capture statistics.
+#[derive(Component)]
+struct RunResults;
+
+const COLOR_TEXT: Color = Color::rgb(0.125, 0.125, 0.125);
+const COLOR_AHEAD: Color = Color::rgb(0.1, 0.6, 0.2);
+const COLOR_BEHIND: Color = Color::rgb(0.8, 0.15, 0.1);
+
+/// Format a time in seconds as `mm:ss.cc`.
+fn format_time(seconds: f32) -> String {
+    let centis = (seconds.max(0.) * 100.) as u32;
+    format!(
+        "{:02}:{:02}.{:02}",
+        centis / 6000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
+
+/// Format a split delta as `+s.cc` or `-s.cc`, with its color.
+fn format_delta(delta: Option<f32>) -> (String, Color) {
+    match delta {
+        Some(delta) if delta < 0. => (format!("-{:.2}", -delta), COLOR_AHEAD),
+        Some(delta) => (format!("+{:.2}", delta), COLOR_BEHIND),
+        None => ("".to_string(), COLOR_TEXT),
+    }
+}
+
+fn speedrun_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(RunTimer {
+        running: true,
+        ..Default::default()
+    });
+
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let style = TextStyle {
+        font,
+        font_size: 26.0,
+        color: COLOR_TEXT,
+    };
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(20.0),
+                    right: Val::Px(50.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "".to_string(),
+                        style: style.clone(),
+                    },
+                    TextSection {
+                        value: "".to_string(),
+                        style,
+                    },
+                ],
+                alignment: TextAlignment {
+                    horizontal: HorizontalAlign::Right,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .insert(Name::new("RunTimer"))
+        .insert(InGameEntity)
+        .insert(RunTimerText);
+}
+
+fn update_run_timer(
+    mut commands: Commands,
+    mut run_timer: ResMut<RunTimer>,
+    mut profile: ResMut<Profile>,
+    launch: Res<LaunchOptions>,
+    settings: Res<Settings>,
+    captures: Res<PatternCaptures>,
+    stats: Res<RunStats>,
+    run_seed: Res<RunSeed>,
+    game_time: Res<GameTime>,
+    asset_server: Res<AssetServer>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    mut game_over_events: EventReader<GameOverEvent>,
+) {
+    if !run_timer.running {
+        return;
+    }
+
+    run_timer.time += game_time.delta_seconds();
+
+    let mut finished = false;
+    for ev in stage_clear_events.iter() {
+        let time = run_timer.time;
+        // Splits are only comparable between runs started from the first stage
+        let delta = if launch.start_stage() == 0 {
+            profile.record_split(ev.stage, time)
+        } else {
+            None
+        };
+        run_timer.splits.push(Split { time, delta });
+        info!(
+            target: "speedrun",
+            "SPLIT: stage={} time={} delta={:?}",
+            ev.stage + 1,
+            format_time(time),
+            delta
+        );
+        finished |= ev.is_last;
+    }
+    if game_over_events.iter().count() > 0 {
+        finished = true;
+    }
+
+    if finished {
+        run_timer.running = false;
+        profile.save();
+        let run_timer = settings.gameplay.show_run_timer.then(|| &*run_timer);
+        spawn_results(
+            &mut commands,
+            &asset_server,
+            run_timer,
+            &*stats,
+            &*captures,
+            &*run_seed,
+        );
+    }
+}
+
+fn update_run_timer_hud(
+    run_timer: Res<RunTimer>,
+    settings: Res<Settings>,
+    mut query: Query<(&mut Text, &mut Visibility), With<RunTimerText>>,
+) {
+    let show = settings.gameplay.show_run_timer;
+    for (mut text, mut visibility) in query.iter_mut() {
+        if visibility.is_visible != show {
+            visibility.is_visible = show;
+        }
+        if !show || !run_timer.is_changed() {
+            continue;
+        }
+        text.sections[0].value = format_time(run_timer.time);
+        if let Some(split) = run_timer.splits.last() {
+            let (delta, color) = format_delta(split.delta);
+            text.sections[1].value = format!("\nStage {} {}", run_timer.splits.len(), delta);
+            text.sections[1].style.color = color;
+        }
+    }
+}
+
+fn spawn_results(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    run_timer: Option<&RunTimer>,
+    stats: &RunStats,
+    captures: &PatternCaptures,
+    run_seed: &RunSeed,
+) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let style = TextStyle {
+        font,
+        font_size: 32.0,
+        color: COLOR_TEXT,
+    };
+
+    let mut sections = vec![];
+    if let Some(run_timer) = run_timer {
+        for (index, split) in run_timer.splits.iter().enumerate() {
+            sections.push(TextSection {
+                value: format!("Stage {}  {}  ", index + 1, format_time(split.time)),
+                style: style.clone(),
+            });
+            let (delta, color) = format_delta(split.delta);
+            sections.push(TextSection {
+                value: format!("{}\n", delta),
+                style: TextStyle {
+                    color,
+                    ..style.clone()
+                },
+            });
+        }
+        sections.push(TextSection {
+            value: format!("Total     {}\n", format_time(run_timer.time)),
+            style: style.clone(),
+        });
+    }
+    let run = &stats.run;
+    sections.push(TextSection {
+        value: format!(
+            "Kills {}  Max chain {}  Graze {}  Bombs {}\n\
+             Accuracy {:.0}%  ({}/{})\n",
+            run.kills,
+            run.max_chain,
+            run.grazes,
+            run.bombs,
+            run.accuracy() * 100.,
+            run.hits,
+            run.shots
+        ),
+        style: style.clone(),
+    });
+    sections.push(TextSection {
+        value: format!(
+            "Pattern captures  {}/{}\n",
+            captures.captured, captures.attempted
+        ),
+        style: style.clone(),
+    });
+    sections.push(TextSection {
+        value: format!(
+            "{}  {}",
+            if run_seed.daily { "Daily run" } else { "Seed" },
+            run_seed.seed
+        ),
+        style,
+    });
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    bottom: Val::Px(80.0),
+                    left: Val::Px(50.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections,
+                alignment: TextAlignment {
+                    horizontal: HorizontalAlign::Left,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        })
+        .insert(Name::new("RunResults"))
+        .insert(InGameEntity)
+        .insert(RunResults);
+}
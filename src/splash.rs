@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+use bevy_tweening::{lens::*, *};
+use std::time::Duration;
+
+use crate::{flow::RequestStateChange, AppState};
+
+/// Splash screen shown once loading completes, before the main menu: the studio and
+/// engine logos fading in and out one after the other. Any input skips it.
+pub struct SplashPlugin;
+
+impl Plugin for SplashPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::Splash).with_system(splash_setup))
+            .add_system_set(SystemSet::on_update(AppState::Splash).with_system(splash_run))
+            .add_system_set(SystemSet::on_exit(AppState::Splash).with_system(splash_cleanup));
+    }
+}
+
+/// Logo of the splash screen, as a small caption above a large title.
+struct SplashLogo {
+    caption: &'static str,
+    title: &'static str,
+}
+
+/// Logos of the splash screen, in display order.
+const SPLASH_LOGOS: &[SplashLogo] = &[
+    SplashLogo {
+        caption: "a game by",
+        title: "djeedai",
+    },
+    SplashLogo {
+        caption: "made with",
+        title: "Bevy",
+    },
+];
+
+/// Duration of the fade in and fade out of each logo, in seconds.
+const FADE_DURATION: f32 = 0.6;
+
+/// Duration each logo stays fully visible, in seconds.
+const HOLD_DURATION: f32 = 1.2;
+
+/// Delay before the first logo and after the last one, in seconds.
+const BLANK_DURATION: f32 = 0.4;
+
+/// Color of the logo texts once faded in.
+const LOGO_COLOR: Color = Color::rgb(0.125, 0.125, 0.125);
+
+/// Marker for all entities of the splash screen, for cleanup.
+#[derive(Component)]
+struct SplashScreen;
+
+/// Time left before the splash screen ends, in seconds.
+#[derive(Component)]
+struct SplashTimer(f32);
+
+fn splash_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    debug!(target: "setup", "splash_setup");
+    commands
+        .spawn_bundle(UiCameraBundle::default())
+        .insert(SplashScreen);
+
+    let font = asset_server.load("fonts/FiraSans-Regular.ttf");
+    let fade = Duration::from_secs_f32(FADE_DURATION);
+    let logo_duration = FADE_DURATION * 2. + HOLD_DURATION;
+
+    for (index, logo) in SPLASH_LOGOS.iter().enumerate() {
+        let start = BLANK_DURATION + index as f32 * logo_duration;
+        let section_tween = |section| {
+            Delay::new(Duration::from_secs_f32(start))
+                .then(Tween::new(
+                    EaseFunction::QuadraticOut,
+                    TweeningType::Once,
+                    fade,
+                    TextColorLens {
+                        start: Color::NONE,
+                        end: LOGO_COLOR,
+                        section,
+                    },
+                ))
+                .then(Delay::new(Duration::from_secs_f32(HOLD_DURATION)))
+                .then(Tween::new(
+                    EaseFunction::QuadraticIn,
+                    TweeningType::Once,
+                    fade,
+                    TextColorLens {
+                        start: LOGO_COLOR,
+                        end: Color::NONE,
+                        section,
+                    },
+                ))
+        };
+        // Both sections fade together, each with its own sequence
+        let tween = Tracks::new([section_tween(0), section_tween(1)]);
+
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect::all(Val::Px(0.)),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..Default::default()
+                },
+                color: UiColor(Color::NONE),
+                ..Default::default()
+            })
+            .insert(Name::new(format!("splash:{}", logo.title)))
+            .insert(SplashScreen)
+            .with_children(|parent| {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text {
+                            sections: vec![
+                                TextSection {
+                                    value: format!("{}\n", logo.caption),
+                                    style: TextStyle {
+                                        font: font.clone(),
+                                        font_size: 32.,
+                                        color: Color::NONE,
+                                    },
+                                },
+                                TextSection {
+                                    value: logo.title.to_string(),
+                                    style: TextStyle {
+                                        font: font.clone(),
+                                        font_size: 128.,
+                                        color: Color::NONE,
+                                    },
+                                },
+                            ],
+                            alignment: TextAlignment {
+                                vertical: VerticalAlign::Center,
+                                horizontal: HorizontalAlign::Center,
+                            },
+                        },
+                        ..Default::default()
+                    })
+                    .insert(Animator::new(tween));
+            });
+    }
+
+    let total = BLANK_DURATION * 2. + SPLASH_LOGOS.len() as f32 * logo_duration;
+    commands
+        .spawn()
+        .insert(SplashScreen)
+        .insert(SplashTimer(total));
+}
+
+fn splash_run(
+    mut q_timer: Query<&mut SplashTimer>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    mouse_input: Res<Input<MouseButton>>,
+    mut state_requests: EventWriter<RequestStateChange>,
+    time: Res<Time>,
+) {
+    let mut timer = q_timer.single_mut();
+    timer.0 -= time.delta_seconds();
+    let any_input = keyboard_input.get_just_pressed().next().is_some()
+        || gamepad_input.get_just_pressed().next().is_some()
+        || mouse_input.get_just_pressed().next().is_some();
+    if any_input || timer.0 <= 0. {
+        state_requests.send(RequestStateChange::fade_to(AppState::Menu));
+    }
+}
+
+fn splash_cleanup(mut commands: Commands, query: Query<Entity, With<SplashScreen>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+use crate::{
+    banner::update_stage_banners,
+    enemy::EnemyKilledEvent,
+    game::{DamageEvent, DamageSource, PlayerController, ShotEvent, StageClearEvent},
+    rank::GrazeEvent,
+    timestep::{FixedUpdateStage, FIXED_DELTA_SECONDS},
+    AppState,
+};
+
+/// Statistics of the run and of the current stage, shown on the stage clear banners
+/// and the end of run results, and feeding into the stage clear bonus.
+pub struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RunStats>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(stats_setup),
+            )
+            .add_system_set_to_stage(
+                FixedUpdateStage,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_run_stats.before(update_stage_banners)),
+            );
+    }
+}
+
+/// Time after a kill during which the next kill extends the chain, in seconds.
+const CHAIN_WINDOW: f32 = 2.;
+
+/// Gameplay counters, for either the whole run or a single stage.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatCounters {
+    /// Player bullets fired.
+    pub shots: u32,
+    /// Player bullets which hit an enemy. A piercing shot can hit several times.
+    pub hits: u32,
+    pub kills: u32,
+    pub grazes: u32,
+    pub bombs: u32,
+    /// Longest chain of kills, each within [`CHAIN_WINDOW`] of the previous one.
+    pub max_chain: u32,
+}
+
+impl StatCounters {
+    /// Ratio of hits to shots, in `[0:1]`.
+    pub fn accuracy(&self) -> f32 {
+        if self.shots == 0 {
+            0.
+        } else {
+            (self.hits as f32 / self.shots as f32).min(1.)
+        }
+    }
+
+    fn add_kill(&mut self, chain: u32) {
+        self.kills += 1;
+        self.max_chain = self.max_chain.max(chain);
+    }
+}
+
+/// Statistics of a cleared stage.
+#[derive(Debug, Clone, Copy)]
+pub struct StageStats {
+    pub counters: StatCounters,
+    /// Time spent in the stage, in seconds.
+    pub time: f32,
+}
+
+/// Statistics of the current run.
+///
+/// Updated in the gameplay steps, after the enemies and before the stage banners,
+/// which read the statistics of a stage in the step it's cleared.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    /// Counters of the whole run.
+    pub run: StatCounters,
+    /// Counters of the current stage.
+    pub stage: StatCounters,
+    /// Time spent in the current stage, in seconds.
+    pub stage_time: f32,
+    /// Statistics of each stage cleared so far.
+    pub cleared: Vec<StageStats>,
+    /// Length of the current kill chain.
+    chain: u32,
+    /// Time left to extend the current kill chain, in seconds.
+    chain_timer: f32,
+    /// Bombs used by the player so far, to count the new ones.
+    bombs_used: u32,
+}
+
+fn stats_setup(mut commands: Commands) {
+    commands.insert_resource(RunStats::default());
+}
+
+/// Count the events of the gameplay step. Runs after the enemies, which send the kill
+/// and stage clear events in the same step.
+pub fn update_run_stats(
+    mut stats: ResMut<RunStats>,
+    mut shot_events: EventReader<ShotEvent>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut killed_events: EventReader<EnemyKilledEvent>,
+    mut graze_events: EventReader<GrazeEvent>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    q_player: Query<&PlayerController>,
+) {
+    let stats = &mut *stats;
+    stats.stage_time += FIXED_DELTA_SECONDS;
+
+    for ev in shot_events.iter() {
+        stats.run.shots += ev.bullets;
+        stats.stage.shots += ev.bullets;
+    }
+    let hits = damage_events
+        .iter()
+        .filter(|ev| ev.source == DamageSource::PlayerBullet)
+        .count() as u32;
+    stats.run.hits += hits;
+    stats.stage.hits += hits;
+    let grazes = graze_events.iter().count() as u32;
+    stats.run.grazes += grazes;
+    stats.stage.grazes += grazes;
+
+    // The bomb event is sent after this system, so compare the counter of the player
+    if let Ok(controller) = q_player.get_single() {
+        let bombs_used = controller.bombs_used();
+        if bombs_used > stats.bombs_used {
+            let bombs = bombs_used - stats.bombs_used;
+            stats.run.bombs += bombs;
+            stats.stage.bombs += bombs;
+            stats.bombs_used = bombs_used;
+        }
+    }
+
+    stats.chain_timer -= FIXED_DELTA_SECONDS;
+    if stats.chain_timer <= 0. {
+        stats.chain = 0;
+    }
+    for _ in killed_events.iter() {
+        stats.chain += 1;
+        stats.chain_timer = CHAIN_WINDOW;
+        stats.run.add_kill(stats.chain);
+        stats.stage.add_kill(stats.chain);
+    }
+
+    for ev in stage_clear_events.iter() {
+        debug!(
+            target: "stats",
+            "STATS: stage={} time={:.2} {:?}",
+            ev.stage + 1,
+            stats.stage_time,
+            stats.stage
+        );
+        let cleared = StageStats {
+            counters: std::mem::take(&mut stats.stage),
+            time: std::mem::take(&mut stats.stage_time),
+        };
+        stats.cleared.push(cleared);
+    }
+}
@@ -0,0 +1,180 @@
+use bevy::{
+    ecs::{event::Events, schedule::ShouldRun, system::Resource},
+    prelude::*,
+    transform::TransformSystem,
+};
+use bevy_tweening::*;
+use std::time::Duration;
+
+use crate::game::{update_game_time, GameTime};
+
+/// Replacement for the `TweeningPlugin`, which must not be added.
+///
+/// Runs the gameplay simulation in the [`FixedUpdateStage`], a fixed number of times
+/// per second whatever the frame rate, so that games and replays play the same on any
+/// monitor. The translation of the entities simulated there is interpolated between
+/// the last two steps for rendering, and their transform animations advance with the
+/// fixed steps too.
+pub struct FixedTimestepPlugin;
+
+impl Plugin for FixedTimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_stage_after(
+            CoreStage::Update,
+            FixedUpdateStage,
+            SystemStage::parallel().with_run_criteria(run_fixed_steps),
+        )
+        .add_event::<TweenCompleted>()
+        .add_system_to_stage(CoreStage::First, restore_fixed_transforms)
+        .add_system(animate_transforms)
+        .add_system(component_animator_system::<Text>)
+        .add_system(component_animator_system::<Style>)
+        .add_system_to_stage(FixedUpdateStage, animate_fixed_transforms)
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            interpolate_fixed_transforms.before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Number of gameplay steps per second.
+pub const FIXED_STEPS_PER_SECOND: f32 = 60.;
+
+/// Duration of a single gameplay step, in seconds. Systems of the [`FixedUpdateStage`]
+/// advance by this delta time instead of the frame one.
+pub const FIXED_DELTA_SECONDS: f32 = 1. / FIXED_STEPS_PER_SECOND;
+
+/// Stage running after [`CoreStage::Update`] once per pending gameplay step, that is
+/// zero, one or several times per frame depending on the frame rate.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, StageLabel)]
+pub struct FixedUpdateStage;
+
+/// Entity simulated in the [`FixedUpdateStage`].
+///
+/// Its translation is interpolated between the last two steps for rendering, and its
+/// `Animator<Transform>` ticks with the steps instead of the frames.
+#[derive(Component, Default)]
+pub struct FixedStep {
+    /// Translation before the last step.
+    previous: Vec3,
+    /// Translation after the last step, which is the one of the simulation.
+    current: Vec3,
+    /// Have the translations been captured at least once?
+    is_initialized: bool,
+}
+
+/// Run the stage once per gameplay step of the current frame, capturing the
+/// translation of the simulated entities before each step.
+fn run_fixed_steps(
+    mut game_time: ResMut<GameTime>,
+    mut query: Query<(&mut FixedStep, &Transform)>,
+) -> ShouldRun {
+    if !game_time.consume_step() {
+        return ShouldRun::No;
+    }
+    for (mut fixed, transform) in query.iter_mut() {
+        fixed.previous = transform.translation;
+    }
+    ShouldRun::YesAndCheckAgain
+}
+
+/// Put the simulated entities back at their simulation translation, undoing the
+/// interpolation of the last frame.
+fn restore_fixed_transforms(mut query: Query<(&FixedStep, &mut Transform)>) {
+    for (fixed, mut transform) in query.iter_mut() {
+        if fixed.is_initialized {
+            transform.translation = fixed.current;
+        }
+    }
+}
+
+/// Move the simulated entities between the translations of the last two steps, by how
+/// far the game time went past the last step.
+fn interpolate_fixed_transforms(
+    mut query: Query<(&mut FixedStep, &mut Transform)>,
+    game_time: Res<GameTime>,
+) {
+    let alpha = game_time.overstep_fraction();
+    for (mut fixed, mut transform) in query.iter_mut() {
+        // Also captures any change made outside of the fixed steps
+        fixed.current = transform.translation;
+        if !fixed.is_initialized {
+            fixed.previous = fixed.current;
+            fixed.is_initialized = true;
+        }
+        transform.translation = fixed.previous.lerp(fixed.current, alpha);
+    }
+}
+
+/// Tick the transform animations of the entities not simulated in the fixed steps,
+/// like the `TweeningPlugin` does.
+fn animate_transforms(
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Transform, &mut Animator<Transform>), Without<FixedStep>>,
+    mut event_writer: EventWriter<TweenCompleted>,
+) {
+    for (entity, mut transform, mut animator) in query.iter_mut() {
+        tick_animator(
+            time.delta(),
+            entity,
+            &mut transform,
+            &mut animator,
+            &mut event_writer,
+        );
+    }
+}
+
+fn animate_fixed_transforms(
+    mut query: Query<(Entity, &mut Transform, &mut Animator<Transform>), With<FixedStep>>,
+    mut event_writer: EventWriter<TweenCompleted>,
+) {
+    let delta = Duration::from_secs_f32(FIXED_DELTA_SECONDS);
+    for (entity, mut transform, mut animator) in query.iter_mut() {
+        tick_animator(
+            delta,
+            entity,
+            &mut transform,
+            &mut animator,
+            &mut event_writer,
+        );
+    }
+}
+
+fn tick_animator(
+    delta: Duration,
+    entity: Entity,
+    transform: &mut Transform,
+    animator: &mut Animator<Transform>,
+    event_writer: &mut EventWriter<TweenCompleted>,
+) {
+    if animator.state == AnimatorState::Paused {
+        return;
+    }
+    if let Some(tweenable) = animator.tweenable_mut() {
+        tweenable.tick(delta, transform, entity, event_writer);
+    }
+}
+
+/// Registration of events read by the systems of the [`FixedUpdateStage`].
+pub trait AddFixedEvent {
+    /// Like `add_event()`, but only clear the events on frames running at least one
+    /// gameplay step. Otherwise events sent during a frame without any step could be
+    /// cleared before any step reads them, on monitors refreshing faster than the
+    /// steps.
+    fn add_fixed_event<T: Resource>(&mut self) -> &mut Self;
+}
+
+impl AddFixedEvent for App {
+    fn add_fixed_event<T: Resource>(&mut self) -> &mut Self {
+        self.init_resource::<Events<T>>().add_system_to_stage(
+            CoreStage::First,
+            update_fixed_events::<T>.after(update_game_time),
+        )
+    }
+}
+
+fn update_fixed_events<T: Resource>(game_time: Res<GameTime>, mut events: ResMut<Events<T>>) {
+    if game_time.steps() > 0 {
+        events.update();
+    }
+}
@@ -0,0 +1,283 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+
+use crate::{
+    game::{InGameEntity, PlayerAction, PlayerController},
+    settings::Settings,
+    AppState,
+};
+
+/// Touch controls: a virtual joystick on the left half of the screen, auto-fire while
+/// touching the right half, and a bomb button in the bottom right corner. Enabled from
+/// the settings, or automatically on the web once the screen is touched.
+pub struct TouchPlugin;
+
+impl Plugin for TouchPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TouchControls>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(touch_setup))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(apply_touch_input)
+                    .with_system(update_touch_visuals.after(apply_touch_input)),
+            );
+    }
+}
+
+/// Distance the joystick touch must move from where it started before the ship moves,
+/// in logical pixels.
+const JOYSTICK_DEADZONE: f32 = 12.;
+
+/// Distance from its base at which the joystick knob stops following the touch, in
+/// logical pixels.
+const JOYSTICK_RADIUS: f32 = 60.;
+
+/// Minimum component of the normalized joystick direction to press a move action.
+/// About `sin(22.5°)`, so that the joystick has 8 evenly sized directions.
+const JOYSTICK_AXIS_THRESHOLD: f32 = 0.38;
+
+/// Size of the joystick knob, in logical pixels.
+const KNOB_SIZE: f32 = 48.;
+
+/// Size of the bomb button, in logical pixels.
+const BOMB_BUTTON_SIZE: f32 = 96.;
+
+/// Distance of the bomb button from the bottom right corner of the screen, in logical
+/// pixels.
+const BOMB_BUTTON_MARGIN: f32 = 24.;
+
+const TOUCH_BASE_COLOR: Color = Color::rgba(1., 1., 1., 0.15);
+const TOUCH_KNOB_COLOR: Color = Color::rgba(1., 1., 1., 0.35);
+
+/// State of the touch controls.
+#[derive(Debug, Default)]
+pub struct TouchControls {
+    /// Was the screen touched at least once? Enables the touch controls on the web.
+    touched: bool,
+    /// Touch driving the joystick, and its start position, if any.
+    joystick: Option<(u64, Vec2)>,
+    /// Offset of the joystick knob from its base, in logical pixels.
+    knob_offset: Vec2,
+}
+
+impl TouchControls {
+    /// Are the touch controls enabled?
+    pub fn is_active(&self, settings: &Settings) -> bool {
+        settings.controls.touch_controls || (cfg!(target_arch = "wasm32") && self.touched)
+    }
+}
+
+/// Part of the on-screen touch controls.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+enum TouchWidget {
+    JoystickBase,
+    JoystickKnob,
+    BombButton,
+}
+
+/// Convert a touch position to the bottom-left origin of the UI. Touch positions come
+/// from the top-left corner, except on mobile platforms where they are flipped already.
+fn to_ui_position(position: Vec2, window_height: f32) -> Vec2 {
+    if cfg!(any(target_os = "android", target_os = "ios")) {
+        position
+    } else {
+        Vec2::new(position.x, window_height - position.y)
+    }
+}
+
+/// Is a position, in UI coordinates, over the bomb button?
+fn is_on_bomb_button(position: Vec2, window_width: f32) -> bool {
+    position.x >= window_width - BOMB_BUTTON_MARGIN - BOMB_BUTTON_SIZE
+        && position.x <= window_width - BOMB_BUTTON_MARGIN
+        && position.y >= BOMB_BUTTON_MARGIN
+        && position.y <= BOMB_BUTTON_MARGIN + BOMB_BUTTON_SIZE
+}
+
+fn touch_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let font = asset_server.load("fonts/ShareTechMono-Regular.ttf");
+    let hidden = Visibility { is_visible: false };
+
+    // Joystick base and knob, positioned where the joystick touch starts
+    for (widget, size, color) in [
+        (
+            TouchWidget::JoystickBase,
+            JOYSTICK_RADIUS * 2.,
+            TOUCH_BASE_COLOR,
+        ),
+        (TouchWidget::JoystickKnob, KNOB_SIZE, TOUCH_KNOB_COLOR),
+    ] {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    size: Size::new(Val::Px(size), Val::Px(size)),
+                    ..Default::default()
+                },
+                color: UiColor(color),
+                visibility: hidden.clone(),
+                ..Default::default()
+            })
+            .insert(Name::new(format!("{:?}", widget)))
+            .insert(widget)
+            .insert(InGameEntity);
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(BOMB_BUTTON_MARGIN),
+                    bottom: Val::Px(BOMB_BUTTON_MARGIN),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(BOMB_BUTTON_SIZE), Val::Px(BOMB_BUTTON_SIZE)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            color: UiColor(TOUCH_BASE_COLOR),
+            visibility: hidden.clone(),
+            ..Default::default()
+        })
+        .insert(Name::new("BombButton"))
+        .insert(TouchWidget::BombButton)
+        .insert(InGameEntity)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    "BOMB",
+                    TextStyle {
+                        font,
+                        font_size: 24.0,
+                        color: TOUCH_KNOB_COLOR,
+                    },
+                    Default::default(),
+                ),
+                visibility: hidden,
+                ..Default::default()
+            });
+        });
+}
+
+/// Press the player actions from the touches. Runs after the input manager updated the
+/// actions from the other inputs, so only ever adds presses.
+pub fn apply_touch_input(
+    mut touch_controls: ResMut<TouchControls>,
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    settings: Res<Settings>,
+    mut query: Query<&mut ActionState<PlayerAction>, With<PlayerController>>,
+) {
+    if touches.iter().next().is_some() {
+        touch_controls.touched = true;
+    }
+    if !touch_controls.is_active(&*settings) {
+        touch_controls.joystick = None;
+        return;
+    }
+    let (width, height) = if let Some(window) = windows.get_primary() {
+        (window.width(), window.height())
+    } else {
+        return;
+    };
+    let mut action_state = if let Ok(action_state) = query.get_single_mut() {
+        action_state
+    } else {
+        return;
+    };
+
+    // Release the joystick once its touch ends, and grab a new touch starting on the
+    // left half of the screen if free
+    if let Some((id, _)) = touch_controls.joystick {
+        if touches.get_pressed(id).is_none() {
+            touch_controls.joystick = None;
+        }
+    }
+    if touch_controls.joystick.is_none() {
+        touch_controls.joystick = touches
+            .iter_just_pressed()
+            .find(|touch| touch.start_position().x < width / 2.)
+            .map(|touch| (touch.id(), to_ui_position(touch.start_position(), height)));
+    }
+
+    touch_controls.knob_offset = Vec2::ZERO;
+    for touch in touches.iter() {
+        let position = to_ui_position(touch.position(), height);
+        if let Some((id, start)) = touch_controls.joystick {
+            if touch.id() == id {
+                let offset = position - start;
+                touch_controls.knob_offset = offset.clamp_length_max(JOYSTICK_RADIUS);
+                if offset.length() < JOYSTICK_DEADZONE {
+                    continue;
+                }
+                let dir = offset.normalize();
+                if dir.x > JOYSTICK_AXIS_THRESHOLD {
+                    action_state.press(PlayerAction::MoveRight);
+                } else if dir.x < -JOYSTICK_AXIS_THRESHOLD {
+                    action_state.press(PlayerAction::MoveLeft);
+                }
+                if dir.y > JOYSTICK_AXIS_THRESHOLD {
+                    action_state.press(PlayerAction::MoveUp);
+                } else if dir.y < -JOYSTICK_AXIS_THRESHOLD {
+                    action_state.press(PlayerAction::MoveDown);
+                }
+                continue;
+            }
+        }
+        let start = to_ui_position(touch.start_position(), height);
+        if is_on_bomb_button(start, width) {
+            // Only on the first frame, since the input manager releases the action on
+            // each update, and the bomb fires on a fresh press
+            if touches.just_pressed(touch.id()) {
+                action_state.press(PlayerAction::Bomb);
+            }
+        } else if touch.position().x >= width / 2. {
+            action_state.press(PlayerAction::ShootPrimary);
+        }
+    }
+}
+
+/// Show the touch controls while enabled, with the joystick under its touch.
+fn update_touch_visuals(
+    touch_controls: Res<TouchControls>,
+    settings: Res<Settings>,
+    mut query: Query<(&TouchWidget, &mut Style, &mut Visibility, Option<&Children>)>,
+    mut q_text: Query<&mut Visibility, (With<Text>, Without<TouchWidget>)>,
+) {
+    let is_active = touch_controls.is_active(&*settings);
+    for (widget, mut style, mut visibility, children) in query.iter_mut() {
+        let is_visible = match widget {
+            TouchWidget::JoystickBase | TouchWidget::JoystickKnob => {
+                is_active && touch_controls.joystick.is_some()
+            }
+            TouchWidget::BombButton => is_active,
+        };
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+        // Visibility is not inherited, so update the button label too
+        for child in children.iter().flat_map(|children| children.iter()) {
+            if let Ok(mut visibility) = q_text.get_mut(*child) {
+                if visibility.is_visible != is_visible {
+                    visibility.is_visible = is_visible;
+                }
+            }
+        }
+        if !is_visible {
+            continue;
+        }
+        if let Some((_, start)) = touch_controls.joystick {
+            let (center, size) = match widget {
+                TouchWidget::JoystickBase => (start, JOYSTICK_RADIUS * 2.),
+                TouchWidget::JoystickKnob => (start + touch_controls.knob_offset, KNOB_SIZE),
+                TouchWidget::BombButton => continue,
+            };
+            style.position = Rect {
+                left: Val::Px(center.x - size / 2.),
+                bottom: Val::Px(center.y - size / 2.),
+                ..Default::default()
+            };
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+use crate::save::{self, SaveFormat};
+
+/// Gameplay constants read by the gameplay systems from the [`Tuning`] resource, so
+/// that the tuning panel of debug builds can edit them live.
+pub struct TuningPlugin;
+
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tuning>();
+    }
+}
+
+/// Directory of the tuning presets, relative to the working directory.
+const PRESET_DIR: &str = "tuning";
+
+/// Format of the tuning preset files.
+const TUNING_FORMAT: SaveFormat = SaveFormat {
+    version: 1,
+    migrate: migrate_tuning,
+};
+
+fn migrate_tuning(version: u32, _json: &mut serde_json::Value) -> Result<(), String> {
+    match version {
+        // Unversioned files have the same layout as version 1
+        0 => Ok(()),
+        _ => Err(format!("no migration from version {}", version)),
+    }
+}
+
+/// Gameplay constants, at their default values unless edited from the tuning panel of
+/// debug builds, toggled with Ctrl+T. The values can be saved to named presets, to
+/// compare balance changes across runs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tuning {
+    /// Multiplier of the movement speed of the player ship.
+    pub player_speed: f32,
+    /// Multiplier of the delay between two shots of the primary weapon. Lower values
+    /// fire faster.
+    pub primary_fire_delay: f32,
+    /// Speed of a regular bullet of the player, in world units per second.
+    pub player_bullet_speed: f32,
+    /// Speed of a charge shot, in world units per second.
+    pub charge_shot_speed: f32,
+    /// Multiplier of the speed of the enemy bullets, on top of the rank and the
+    /// difficulty ones.
+    pub enemy_bullet_speed: f32,
+    pub rank: RankTuning,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            player_speed: 1.,
+            primary_fire_delay: 1.,
+            player_bullet_speed: 5.,
+            charge_shot_speed: 7.,
+            enemy_bullet_speed: 1.,
+            rank: RankTuning::default(),
+        }
+    }
+}
+
+/// Gains and penalties of the dynamic difficulty [`Rank`](crate::rank::Rank).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankTuning {
+    /// Rank gained per enemy killed.
+    pub kill_gain: f32,
+    /// Rank gained per boss killed.
+    pub boss_kill_gain: f32,
+    /// Rank gained per enemy bullet grazed.
+    pub graze_gain: f32,
+    /// Rank gained per second survived since the last death.
+    pub survival_gain: f32,
+    /// Rank lost when the player dies.
+    pub death_penalty: f32,
+    /// Rank lost when the player uses a bomb.
+    pub bomb_penalty: f32,
+}
+
+impl Default for RankTuning {
+    fn default() -> Self {
+        RankTuning {
+            kill_gain: 0.04,
+            boss_kill_gain: 0.5,
+            graze_gain: 0.01,
+            survival_gain: 0.02,
+            death_penalty: 1.,
+            bomb_penalty: 0.4,
+        }
+    }
+}
+
+impl Tuning {
+    /// Name, value and slider range of each constant, for the tuning panel.
+    pub fn params_mut(&mut self) -> Vec<(&'static str, &mut f32, RangeInclusive<f32>)> {
+        let rank = &mut self.rank;
+        vec![
+            ("player_speed", &mut self.player_speed, 0.25..=3.),
+            (
+                "primary_fire_delay",
+                &mut self.primary_fire_delay,
+                0.25..=3.,
+            ),
+            (
+                "player_bullet_speed",
+                &mut self.player_bullet_speed,
+                1.0..=15.,
+            ),
+            ("charge_shot_speed", &mut self.charge_shot_speed, 1.0..=15.),
+            (
+                "enemy_bullet_speed",
+                &mut self.enemy_bullet_speed,
+                0.25..=3.,
+            ),
+            ("rank.kill_gain", &mut rank.kill_gain, 0.0..=0.5),
+            ("rank.boss_kill_gain", &mut rank.boss_kill_gain, 0.0..=2.),
+            ("rank.graze_gain", &mut rank.graze_gain, 0.0..=0.1),
+            ("rank.survival_gain", &mut rank.survival_gain, 0.0..=0.2),
+            ("rank.death_penalty", &mut rank.death_penalty, 0.0..=5.),
+            ("rank.bomb_penalty", &mut rank.bomb_penalty, 0.0..=2.),
+        ]
+    }
+
+    /// Load the preset with the given name from the preset directory.
+    pub fn load_preset(name: &str) -> Result<Tuning, String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = std::path::Path::new(PRESET_DIR).join(format!("{}.json", name));
+            let loaded = save::load(&path, &TUNING_FORMAT).map_err(|err| err.to_string())?;
+            if let Some(version) = loaded.migrated_from {
+                info!(
+                    target: "tuning",
+                    "Migrated tuning preset {:?} from version {} (backup: {:?})",
+                    path,
+                    version,
+                    loaded.backup
+                );
+            }
+            Ok(loaded.value)
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            Err(format!("cannot load preset {} on the web", name))
+        }
+    }
+
+    /// Save the tuning to a preset with the given name in the preset directory,
+    /// overwriting any existing one.
+    pub fn save_preset(&self, name: &str) -> Result<(), String> {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let path = std::path::Path::new(PRESET_DIR).join(format!("{}.json", name));
+            std::fs::create_dir_all(PRESET_DIR)
+                .map_err(|err| err.to_string())
+                .and_then(|_| save::to_json(self, &TUNING_FORMAT, true))
+                .and_then(|json| save::write(&path, &json).map_err(|err| err.to_string()))
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            Err(format!("cannot save preset {} on the web", name))
+        }
+    }
+
+    /// List the names of the saved presets, in alphabetical order.
+    pub fn list_presets() -> Vec<String> {
+        let mut names = vec![];
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Ok(entries) = std::fs::read_dir(PRESET_DIR) {
+            for entry in entries.flatten() {
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if let Some(name) = file_name.strip_suffix(".json") {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort_unstable();
+        names
+    }
+}
@@ -0,0 +1,325 @@
+use bevy::prelude::*;
+use leafwing_input_manager::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    cli::LaunchOptions,
+    game::{EnemyBullet, InGameEntity, MainCamera, PlayerAction, PlayerController},
+    letterbox::PlayfieldNode,
+    profile::Profile,
+    AppState,
+};
+
+/// Prompts teaching the controls during the first game of the player, shown one at a
+/// time next to the ship, each dismissed once the player performs the action it asks
+/// for. Completing them all is recorded into the [`Profile`].
+pub struct TutorialPlugin;
+
+impl Plugin for TutorialPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TutorialState::load())
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(tutorial_setup),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame).with_system(update_tutorial),
+            );
+    }
+}
+
+/// Width of the prompt node, centered on the ship, in percent of the playfield width.
+const PROMPT_WIDTH: f32 = 60.;
+
+/// Vertical distance of the prompt from the ship, in percent of the playfield height.
+const PROMPT_OFFSET: f32 = 8.;
+
+/// Lowest position of the prompt from the top of the playfield, in percent of the
+/// playfield height, past which it shows above the ship instead of below it.
+const PROMPT_MAX_TOP: f32 = 90.;
+
+const PROMPT_TEXT_COLOR: Color = Color::rgb(1., 0.85, 0.3);
+
+/// Action the player performs to dismiss a prompt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+enum TutorialAction {
+    /// Any of the movement directions.
+    #[serde(alias = "move")]
+    Move,
+    #[serde(alias = "shoot_primary")]
+    ShootPrimary,
+    #[serde(alias = "shoot_secondary")]
+    ShootSecondary,
+    #[serde(alias = "bomb")]
+    Bomb,
+    #[serde(alias = "overdrive")]
+    Overdrive,
+}
+
+impl TutorialAction {
+    fn is_pressed(&self, action_state: &ActionState<PlayerAction>) -> bool {
+        let actions: &[PlayerAction] = match self {
+            TutorialAction::Move => &[
+                PlayerAction::MoveUp,
+                PlayerAction::MoveDown,
+                PlayerAction::MoveLeft,
+                PlayerAction::MoveRight,
+            ],
+            TutorialAction::ShootPrimary => &[PlayerAction::ShootPrimary],
+            TutorialAction::ShootSecondary => &[PlayerAction::ShootSecondary],
+            TutorialAction::Bomb => &[PlayerAction::Bomb],
+            TutorialAction::Overdrive => &[PlayerAction::Overdrive],
+        };
+        actions.iter().any(|action| action_state.pressed(*action))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TutorialStep {
+    action: TutorialAction,
+    text: String,
+    /// Delay before showing the prompt, after the previous one was dismissed, in
+    /// seconds.
+    #[serde(default)]
+    delay: f32,
+    /// Time the action must be held to dismiss the prompt, in seconds. Zero dismisses
+    /// it on the first press.
+    #[serde(default)]
+    hold: f32,
+    /// Number of enemy bullets on screen required to show the prompt, for the actions
+    /// only useful in a pinch.
+    #[serde(default)]
+    min_enemy_bullets: usize,
+}
+
+/// Tutorial steps, loaded from `assets/tutorial_db.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+struct TutorialDatabase {
+    steps: Vec<TutorialStep>,
+}
+
+impl TutorialDatabase {
+    fn parse() -> serde_json::Result<TutorialDatabase> {
+        serde_json::from_str(include_str!("../assets/tutorial_db.json"))
+    }
+}
+
+/// Error parsing the tutorial database, if any, to report it during boot. The game
+/// still runs without any tutorial in that case.
+pub fn tutorial_database_error() -> Option<String> {
+    TutorialDatabase::parse().err().map(|err| err.to_string())
+}
+
+/// Tutorial database, and progress through its steps during the current game.
+struct TutorialState {
+    database: TutorialDatabase,
+    /// Index of the current step, or `None` if the tutorial doesn't run this game.
+    step: Option<usize>,
+    /// Remaining delay before showing the prompt of the current step, in seconds.
+    delay: f32,
+    /// Is the prompt of the current step shown?
+    is_shown: bool,
+    /// Time the action of the current step has been held since the prompt showed, in
+    /// seconds.
+    held: f32,
+}
+
+impl TutorialState {
+    fn load() -> TutorialState {
+        let database = TutorialDatabase::parse().unwrap_or_else(|err| {
+            error!(target: "tutorial", "Failed to parse tutorial database: {}", err);
+            TutorialDatabase::default()
+        });
+        TutorialState {
+            database,
+            step: None,
+            delay: 0.,
+            is_shown: false,
+            held: 0.,
+        }
+    }
+
+    /// Move to the step with the given index, or stop the tutorial if there's none.
+    fn start_step(&mut self, index: usize) {
+        self.step = self.database.steps.get(index).map(|_| index);
+        self.delay = self
+            .step
+            .map_or(0., |index| self.database.steps[index].delay);
+        self.is_shown = false;
+        self.held = 0.;
+    }
+
+    fn current_step(&self) -> Option<&TutorialStep> {
+        self.step.map(|index| &self.database.steps[index])
+    }
+}
+
+/// Root of the prompt being displayed, laid out over the playfield.
+#[derive(Component)]
+struct TutorialPrompt;
+
+/// Node of the prompt text, following the ship.
+#[derive(Component)]
+struct TutorialPromptText;
+
+/// Run the tutorial during the first game only, and never while playing back a replay,
+/// benchmarking, simulating or previewing patterns.
+fn tutorial_setup(
+    mut tutorial: ResMut<TutorialState>,
+    profile: Res<Profile>,
+    launch: Res<LaunchOptions>,
+) {
+    let is_playback = launch.bench
+        || launch.simulate.is_some()
+        || launch.replay.is_some()
+        || launch.pattern.is_some();
+    if profile.is_tutorial_completed() || is_playback {
+        tutorial.step = None;
+    } else {
+        debug!(target: "tutorial", "Start tutorial");
+        tutorial.start_step(0);
+    }
+}
+
+fn update_tutorial(
+    mut commands: Commands,
+    mut tutorial: ResMut<TutorialState>,
+    mut profile: ResMut<Profile>,
+    q_player: Query<(&PlayerController, &ActionState<PlayerAction>, &Transform)>,
+    q_bullets: Query<(), With<EnemyBullet>>,
+    q_camera: Query<&MainCamera>,
+    q_prompt: Query<Entity, With<TutorialPrompt>>,
+    mut q_text: Query<&mut Style, With<TutorialPromptText>>,
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+) {
+    let step = match tutorial.current_step() {
+        Some(step) => step.clone(),
+        None => return,
+    };
+
+    // Without a player, wait for the respawn to show the prompt again
+    let (controller, action_state, transform) = if let Ok(player) = q_player.get_single() {
+        player
+    } else {
+        for entity in q_prompt.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        tutorial.is_shown = false;
+        return;
+    };
+
+    // Follow the ship
+    let position = q_camera.get_single().map_or_else(
+        |_| Rect::default(),
+        |main_camera| prompt_position(main_camera.playfield_percent(transform.translation)),
+    );
+
+    if !tutorial.is_shown {
+        tutorial.delay -= time.delta_seconds();
+        if tutorial.delay > 0. || q_bullets.iter().count() < step.min_enemy_bullets {
+            return;
+        }
+        debug!(target: "tutorial", "Show prompt: {}", step.text);
+        spawn_prompt(&mut commands, &asset_server, &step.text, position);
+        tutorial.is_shown = true;
+        tutorial.held = 0.;
+        return;
+    }
+
+    // Dismiss the prompt once the action is performed. The auto-fire already fires
+    // the primary weapon without pressing anything.
+    let is_auto_fired = step.action == TutorialAction::ShootPrimary && controller.is_auto_firing();
+    if is_auto_fired || step.action.is_pressed(action_state) {
+        tutorial.held += time.delta_seconds();
+    } else {
+        tutorial.held = 0.;
+    }
+    if is_auto_fired || tutorial.held >= step.hold {
+        for entity in q_prompt.iter() {
+            commands.entity(entity).despawn_recursive();
+        }
+        let next = tutorial.step.unwrap() + 1;
+        tutorial.start_step(next);
+        if tutorial.step.is_none() {
+            info!(target: "tutorial", "Tutorial completed");
+            profile.complete_tutorial();
+            profile.save();
+        }
+        return;
+    }
+
+    for mut style in q_text.iter_mut() {
+        style.position = position;
+    }
+}
+
+/// Position of the prompt text node for the ship at the given position on screen, in
+/// percent, below the ship unless too close to the bottom of the playfield.
+fn prompt_position(ship_position: Vec2) -> Rect<Val> {
+    let (x, y) = (ship_position.x, ship_position.y);
+    let top = if y + PROMPT_OFFSET > PROMPT_MAX_TOP {
+        y - PROMPT_OFFSET * 1.5
+    } else {
+        y + PROMPT_OFFSET
+    };
+    Rect {
+        left: Val::Percent(x - PROMPT_WIDTH / 2.),
+        top: Val::Percent(top),
+        ..Default::default()
+    }
+}
+
+fn spawn_prompt(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    text: &str,
+    position: Rect<Val>,
+) {
+    let font = asset_server.load("fonts/FiraMono-Regular.ttf");
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                ..Default::default()
+            },
+            color: UiColor(Color::NONE),
+            ..Default::default()
+        })
+        .insert(Name::new("TutorialPrompt"))
+        .insert(InGameEntity)
+        .insert(PlayfieldNode)
+        .insert(TutorialPrompt)
+        .with_children(|parent| {
+            // Moved by update_tutorial() to follow the ship, centered on it
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position,
+                        size: Size::new(Val::Percent(PROMPT_WIDTH), Val::Auto),
+                        justify_content: JustifyContent::Center,
+                        ..Default::default()
+                    },
+                    color: UiColor(Color::NONE),
+                    ..Default::default()
+                })
+                .insert(TutorialPromptText)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle {
+                        text: Text::with_section(
+                            text.to_string(),
+                            TextStyle {
+                                font,
+                                font_size: 24.,
+                                color: PROMPT_TEXT_COLOR,
+                            },
+                            Default::default(),
+                        ),
+                        ..Default::default()
+                    });
+                });
+        });
+}
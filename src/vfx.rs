@@ -0,0 +1,592 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use rand::prelude::*;
+use std::f32::consts::TAU;
+
+use crate::{
+    game::{Bullet, EnemyBullet, GameTime, InGameEntity, PendingDespawn},
+    settings::Settings,
+    AppState,
+};
+
+pub struct VfxPlugin;
+
+impl Plugin for VfxPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ExplosionEvent>()
+            .add_event::<SparkEvent>()
+            .add_event::<MuzzleFlashEvent>()
+            .init_resource::<VfxAssets>()
+            .init_resource::<BossExplosions>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame).with_system(setup_vfx),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(spawn_explosions)
+                    .with_system(spawn_sparks)
+                    .with_system(spawn_muzzle_flashes)
+                    .with_system(spawn_bullet_trails)
+                    .with_system(update_boss_explosions)
+                    .with_system(update_particles)
+                    .with_system(update_background_dimmer),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExplosionKind {
+    /// Single burst for a regular enemy.
+    Enemy,
+    /// Chain of bursts over the boss body, ending with a large blast.
+    Boss,
+}
+
+/// Event to play an explosion, typically when an enemy is killed.
+#[derive(Debug, Clone, Copy)]
+pub struct ExplosionEvent {
+    /// World-space position of the explosion center.
+    pub position: Vec3,
+    pub kind: ExplosionKind,
+}
+
+/// Event to play a small burst of sparks where a bullet hit something.
+#[derive(Debug, Clone, Copy)]
+pub struct SparkEvent {
+    /// World-space position of the impact.
+    pub position: Vec3,
+}
+
+/// Event to play a muzzle flash where the player or an enemy fired a volley.
+#[derive(Debug, Clone, Copy)]
+pub struct MuzzleFlashEvent {
+    /// World-space position of the muzzle.
+    pub position: Vec3,
+    /// Firing direction the flash stretches along, or `None` for a round flash, for
+    /// volleys fired all around like rings.
+    pub direction: Option<Vec3>,
+}
+
+/// Speed above which a bullet leaves a trail, in world units per second.
+const TRAIL_MIN_SPEED: f32 = 4.;
+
+/// Delay between two quads of the trail of a bullet, in seconds.
+const TRAIL_INTERVAL: f32 = 0.025;
+
+/// Size of a quad of the trail of a bullet, in world units.
+const TRAIL_SIZE: f32 = 0.04;
+
+/// Lifetime of a quad of the trail of a bullet, in seconds.
+const TRAIL_LIFETIME: f32 = 0.1;
+
+/// Number of bursts in the chain of a boss explosion, before the final blast.
+const BOSS_EXPLOSION_BURSTS: u32 = 8;
+
+/// Delay between two bursts of a boss explosion, in seconds.
+const BOSS_EXPLOSION_INTERVAL: f32 = 0.15;
+
+/// Number of enemy bullets on screen below which the background is not dimmed.
+const DIM_MIN_BULLETS: f32 = 80.;
+
+/// Number of enemy bullets on screen at which the background is fully dimmed.
+const DIM_MAX_BULLETS: f32 = 400.;
+
+/// Opacity of the dimming layer when the background is fully dimmed.
+const DIM_MAX_OPACITY: f32 = 0.55;
+
+/// Speed at which the dimming layer fades toward its target opacity, in opacity units
+/// per second.
+const DIM_FADE_SPEED: f32 = 0.8;
+
+/// Depth of the dimming layer, between the background and the bullet plane at Z=0.
+const DIM_DEPTH: f32 = -0.5;
+
+/// Dark layer drawn over the background to improve bullet readability during dense
+/// patterns, its opacity following the number of enemy bullets on screen.
+#[derive(Component, Default)]
+struct BackgroundDimmer {
+    opacity: f32,
+}
+
+/// Single quad particle, moving in a straight line and shrinking until it dies.
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec3,
+    /// Velocity damping, as the fraction of velocity lost per second.
+    drag: f32,
+    /// Initial size, in world units.
+    size: f32,
+    /// Ratio of the width over the height of the particle.
+    stretch: f32,
+    age: f32,
+    lifetime: f32,
+}
+
+#[derive(Default)]
+struct VfxAssets {
+    mesh: Handle<Mesh>,
+    fire_material: Handle<StandardMaterial>,
+    smoke_material: Handle<StandardMaterial>,
+    spark_material: Handle<StandardMaterial>,
+    flash_material: Handle<StandardMaterial>,
+    muzzle_material: Handle<StandardMaterial>,
+    trail_material: Handle<StandardMaterial>,
+    dimmer_material: Handle<StandardMaterial>,
+}
+
+/// Time before a fast bullet emits the next quad of its trail.
+#[derive(Component, Default)]
+struct TrailEmitter {
+    next: f32,
+}
+
+/// Parameters of a radial burst of particles.
+#[derive(Debug, Clone, Copy)]
+struct Burst {
+    /// Number of particles.
+    count: u32,
+    /// Maximum initial speed of the particles, in world units per second.
+    speed: f32,
+    /// Maximum initial size of the particles, in world units.
+    size: f32,
+    /// Maximum lifetime of the particles, in seconds.
+    lifetime: f32,
+}
+
+impl Burst {
+    /// Keep only a fraction of the particles, at least one, for the lower graphics
+    /// qualities.
+    fn thinned(self, density: f32) -> Burst {
+        Burst {
+            count: ((self.count as f32 * density).round() as u32).clamp(1, self.count),
+            ..self
+        }
+    }
+}
+
+/// Boss explosion in progress.
+struct BossExplosion {
+    position: Vec3,
+    /// Time until the next burst, in seconds.
+    next_burst: f32,
+    /// Number of bursts left, including the final blast.
+    bursts_left: u32,
+}
+
+/// All boss explosions in progress.
+#[derive(Default)]
+struct BossExplosions(Vec<BossExplosion>);
+
+fn setup_vfx(
+    mut commands: Commands,
+    mut vfx_assets: ResMut<VfxAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut material = |color: Color| {
+        materials.add(StandardMaterial {
+            base_color: color,
+            unlit: true,
+            alpha_mode: AlphaMode::Blend,
+            ..Default::default()
+        })
+    };
+    vfx_assets.fire_material = material(Color::rgba(1., 0.55, 0.1, 0.9));
+    vfx_assets.smoke_material = material(Color::rgba(0.3, 0.3, 0.3, 0.6));
+    vfx_assets.spark_material = material(Color::rgb(1., 0.95, 0.6));
+    vfx_assets.flash_material = material(Color::rgba(1., 1., 1., 0.8));
+    vfx_assets.muzzle_material = material(Color::rgba(1., 0.85, 0.5, 0.9));
+    vfx_assets.trail_material = material(Color::rgba(1., 0.9, 0.7, 0.35));
+    vfx_assets.dimmer_material = material(Color::rgba(0., 0., 0., 0.));
+    vfx_assets.mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::ONE,
+        flip: false,
+    }));
+
+    commands.insert_resource(BossExplosions::default());
+
+    // Large enough to cover the screen for both camera projections
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: vfx_assets.mesh.clone(),
+            material: vfx_assets.dimmer_material.clone(),
+            transform: Transform::from_xyz(0., 0., DIM_DEPTH).with_scale(Vec3::new(20., 12., 1.)),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(Name::new("BackgroundDimmer"))
+        .insert(InGameEntity)
+        .insert(BackgroundDimmer::default())
+        .insert(NotShadowCaster)
+        .insert(NotShadowReceiver);
+}
+
+/// Spawn a radial burst of particles.
+fn spawn_burst(
+    commands: &mut Commands,
+    rng: &mut impl Rng,
+    mesh: &Handle<Mesh>,
+    material: &Handle<StandardMaterial>,
+    position: Vec3,
+    burst: Burst,
+) {
+    for _ in 0..burst.count {
+        let angle = rng.gen::<f32>() * TAU;
+        let speed = burst.speed * (0.4 + rng.gen::<f32>() * 0.6);
+        let velocity = Quat::from_rotation_z(angle).mul_vec3(Vec3::X * speed);
+        let size = burst.size * (0.6 + rng.gen::<f32>() * 0.4);
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(position)
+                    .with_rotation(Quat::from_rotation_z(angle))
+                    .with_scale(Vec3::splat(size)),
+                ..Default::default()
+            })
+            .insert(Particle {
+                velocity,
+                drag: 3.,
+                size,
+                stretch: 1.,
+                age: 0.,
+                lifetime: burst.lifetime * (0.7 + rng.gen::<f32>() * 0.3),
+            })
+            .insert(InGameEntity)
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver);
+    }
+}
+
+/// Spawn a single explosion burst, with a flash unless `flash` is false, some fire and
+/// some smoke, keeping only the `density` fraction of the fire and smoke particles.
+fn spawn_explosion(
+    commands: &mut Commands,
+    rng: &mut impl Rng,
+    vfx_assets: &VfxAssets,
+    position: Vec3,
+    scale: f32,
+    flash: bool,
+    density: f32,
+) {
+    let mesh = &vfx_assets.mesh;
+    if flash {
+        spawn_burst(
+            commands,
+            rng,
+            mesh,
+            &vfx_assets.flash_material,
+            position,
+            Burst {
+                count: 1,
+                speed: 0.,
+                size: 0.35 * scale,
+                lifetime: 0.12,
+            },
+        );
+    }
+    spawn_burst(
+        commands,
+        rng,
+        mesh,
+        &vfx_assets.fire_material,
+        position,
+        Burst {
+            count: (10. * scale) as u32,
+            speed: 1.5 * scale,
+            size: 0.1 * scale,
+            lifetime: 0.45,
+        }
+        .thinned(density),
+    );
+    spawn_burst(
+        commands,
+        rng,
+        mesh,
+        &vfx_assets.smoke_material,
+        position,
+        Burst {
+            count: (6. * scale) as u32,
+            speed: 0.8 * scale,
+            size: 0.12 * scale,
+            lifetime: 0.8,
+        }
+        .thinned(density),
+    );
+}
+
+fn spawn_explosions(
+    mut commands: Commands,
+    mut explosion_events: EventReader<ExplosionEvent>,
+    mut boss_explosions: ResMut<BossExplosions>,
+    vfx_assets: Res<VfxAssets>,
+    settings: Res<Settings>,
+) {
+    let mut rng = thread_rng();
+    let flash = !settings.accessibility.reduce_flashing;
+    let density = settings.video.graphics_quality.particle_density();
+    for ev in explosion_events.iter() {
+        match ev.kind {
+            ExplosionKind::Enemy => {
+                spawn_explosion(
+                    &mut commands,
+                    &mut rng,
+                    &vfx_assets,
+                    ev.position,
+                    1.,
+                    flash,
+                    density,
+                );
+            }
+            ExplosionKind::Boss => boss_explosions.0.push(BossExplosion {
+                position: ev.position,
+                next_burst: 0.,
+                bursts_left: BOSS_EXPLOSION_BURSTS + 1,
+            }),
+        }
+    }
+}
+
+fn spawn_sparks(
+    mut commands: Commands,
+    mut spark_events: EventReader<SparkEvent>,
+    vfx_assets: Res<VfxAssets>,
+    settings: Res<Settings>,
+) {
+    let mut rng = thread_rng();
+    let density = settings.video.graphics_quality.particle_density();
+    for ev in spark_events.iter() {
+        spawn_burst(
+            &mut commands,
+            &mut rng,
+            &vfx_assets.mesh,
+            &vfx_assets.spark_material,
+            ev.position,
+            Burst {
+                count: 4,
+                speed: 2.,
+                size: 0.025,
+                lifetime: 0.15,
+            }
+            .thinned(density),
+        );
+    }
+}
+
+/// Spawn a single particle which doesn't move, only shrinks and dies.
+fn spawn_static_particle(
+    commands: &mut Commands,
+    vfx_assets: &VfxAssets,
+    material: &Handle<StandardMaterial>,
+    transform: Transform,
+    size: f32,
+    stretch: f32,
+    lifetime: f32,
+) {
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: vfx_assets.mesh.clone(),
+            material: material.clone(),
+            transform: transform.with_scale(Vec3::new(size * stretch, size, 1.)),
+            ..Default::default()
+        })
+        .insert(Particle {
+            velocity: Vec3::ZERO,
+            drag: 0.,
+            size,
+            stretch,
+            age: 0.,
+            lifetime,
+        })
+        .insert(InGameEntity)
+        .insert(NotShadowCaster)
+        .insert(NotShadowReceiver);
+}
+
+fn spawn_muzzle_flashes(
+    mut commands: Commands,
+    mut flash_events: EventReader<MuzzleFlashEvent>,
+    vfx_assets: Res<VfxAssets>,
+    settings: Res<Settings>,
+) {
+    if !settings.video.graphics_quality.has_muzzle_flashes()
+        || settings.accessibility.reduce_flashing
+    {
+        return;
+    }
+    for ev in flash_events.iter() {
+        if let Some(direction) = ev.direction {
+            // Stretched forward, along the firing direction
+            spawn_static_particle(
+                &mut commands,
+                &vfx_assets,
+                &vfx_assets.muzzle_material,
+                Transform::from_translation(ev.position + direction * 0.03)
+                    .with_rotation(Quat::from_rotation_arc(Vec3::X, direction)),
+                0.05,
+                2.5,
+                0.05,
+            );
+        } else {
+            spawn_static_particle(
+                &mut commands,
+                &vfx_assets,
+                &vfx_assets.muzzle_material,
+                Transform::from_translation(ev.position),
+                0.15,
+                1.,
+                0.08,
+            );
+        }
+    }
+}
+
+/// Leave a fading trail of quads behind the fast bullets.
+fn spawn_bullet_trails(
+    mut commands: Commands,
+    mut query: Query<
+        (Entity, &Bullet, &Transform, Option<&mut TrailEmitter>),
+        Without<PendingDespawn>,
+    >,
+    vfx_assets: Res<VfxAssets>,
+    settings: Res<Settings>,
+    game_time: Res<GameTime>,
+) {
+    if !settings.video.graphics_quality.has_bullet_trails() {
+        return;
+    }
+    let dt = game_time.delta_seconds();
+    for (entity, bullet, transform, emitter) in query.iter_mut() {
+        if bullet.0.length_squared() < TRAIL_MIN_SPEED * TRAIL_MIN_SPEED {
+            continue;
+        }
+        let mut emitter = if let Some(emitter) = emitter {
+            emitter
+        } else {
+            commands.entity(entity).insert(TrailEmitter::default());
+            continue;
+        };
+        emitter.next -= dt;
+        if emitter.next > 0. {
+            continue;
+        }
+        emitter.next += TRAIL_INTERVAL;
+        spawn_static_particle(
+            &mut commands,
+            &vfx_assets,
+            &vfx_assets.trail_material,
+            Transform::from_translation(transform.translation).with_rotation(transform.rotation),
+            TRAIL_SIZE,
+            1.,
+            TRAIL_LIFETIME,
+        );
+    }
+}
+
+fn update_boss_explosions(
+    mut commands: Commands,
+    mut boss_explosions: ResMut<BossExplosions>,
+    vfx_assets: Res<VfxAssets>,
+    game_time: Res<GameTime>,
+    settings: Res<Settings>,
+) {
+    let dt = game_time.delta_seconds();
+    let mut rng = thread_rng();
+    let flash = !settings.accessibility.reduce_flashing;
+    let density = settings.video.graphics_quality.particle_density();
+    for explosion in boss_explosions.0.iter_mut() {
+        explosion.next_burst -= dt;
+        if explosion.next_burst > 0. {
+            continue;
+        }
+        explosion.next_burst += BOSS_EXPLOSION_INTERVAL;
+        explosion.bursts_left -= 1;
+        if explosion.bursts_left > 0 {
+            // Small bursts scattered over the boss body
+            let offset = Vec3::new(rng.gen_range(-0.3..0.3), rng.gen_range(-0.3..0.3), 0.);
+            spawn_explosion(
+                &mut commands,
+                &mut rng,
+                &vfx_assets,
+                explosion.position + offset,
+                0.8,
+                flash,
+                density,
+            );
+        } else {
+            // Final blast
+            spawn_explosion(
+                &mut commands,
+                &mut rng,
+                &vfx_assets,
+                explosion.position,
+                3.,
+                flash,
+                density,
+            );
+        }
+    }
+    boss_explosions
+        .0
+        .retain(|explosion| explosion.bursts_left > 0);
+}
+
+fn update_particles(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Particle, &mut Transform), Without<PendingDespawn>>,
+    game_time: Res<GameTime>,
+) {
+    let dt = game_time.delta_seconds();
+    for (entity, mut particle, mut transform) in query.iter_mut() {
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).insert(PendingDespawn);
+            continue;
+        }
+        let damping = (1. - particle.drag * dt).max(0.);
+        particle.velocity *= damping;
+        transform.translation += particle.velocity * dt;
+        let t = particle.age / particle.lifetime;
+        let size = particle.size * (1. - t * t);
+        transform.scale = Vec3::new(size * particle.stretch, size, 1.);
+    }
+}
+
+/// Fade the background dimming layer according to the number of enemy bullets.
+fn update_background_dimmer(
+    mut q_dimmer: Query<(&mut BackgroundDimmer, &mut Visibility)>,
+    q_bullets: Query<(), (With<EnemyBullet>, Without<PendingDespawn>)>,
+    vfx_assets: Res<VfxAssets>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    game_time: Res<GameTime>,
+) {
+    let (mut dimmer, mut visibility) = if let Ok(dimmer) = q_dimmer.get_single_mut() {
+        dimmer
+    } else {
+        return;
+    };
+
+    let target = if settings.gameplay.dim_background {
+        let count = q_bullets.iter().count() as f32;
+        let t = ((count - DIM_MIN_BULLETS) / (DIM_MAX_BULLETS - DIM_MIN_BULLETS)).clamp(0., 1.);
+        t * DIM_MAX_OPACITY
+    } else {
+        0.
+    };
+
+    let max_delta = DIM_FADE_SPEED * game_time.delta_seconds();
+    let opacity = dimmer.opacity + (target - dimmer.opacity).clamp(-max_delta, max_delta);
+    if opacity == dimmer.opacity {
+        return;
+    }
+    dimmer.opacity = opacity;
+    visibility.is_visible = opacity > 0.;
+    if let Some(material) = materials.get_mut(&vfx_assets.dimmer_material) {
+        material.base_color.set_a(opacity);
+    }
+}
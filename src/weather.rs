@@ -0,0 +1,295 @@
+use bevy::{
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+};
+use bevy_atmosphere::*;
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    cli::LaunchOptions,
+    game::{roll_run_seed, GameTime, InGameEntity, RunSeed, StageClearEvent},
+    AppState,
+};
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<WeatherEvent>()
+            .add_event::<LightningEvent>()
+            .init_resource::<Weather>()
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_enter(AppState::InGame)
+                    .with_system(weather_setup.after(roll_run_seed)),
+            )
+            .add_system_set_to_stage(
+                CoreStage::Update,
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(update_weather)
+                    .with_system(update_rain.after(update_weather)),
+            );
+    }
+}
+
+/// Conditions of the weather, either those of a stage or set from the timeline. Purely
+/// decorative, the weather never affects the gameplay.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct WeatherParams {
+    /// Intensity of the rain, in `[0:1]`.
+    pub rain: f32,
+    /// Density of the fog thickening the sky, in `[0:1]`.
+    pub fog: f32,
+    /// Range of the delay between two lightning flashes, in seconds, or `None` for no
+    /// lightning at all.
+    pub lightning_interval: Option<(f32, f32)>,
+}
+
+impl Default for WeatherParams {
+    fn default() -> Self {
+        WeatherParams {
+            rain: 0.,
+            fog: 0.,
+            lightning_interval: None,
+        }
+    }
+}
+
+/// Weather of each stage, indexed by stage. Stages past the end use the last entry.
+const STAGE_WEATHERS: &[WeatherParams] = &[
+    WeatherParams {
+        rain: 0.,
+        fog: 0.,
+        lightning_interval: None,
+    },
+    WeatherParams {
+        rain: 0.3,
+        fog: 0.3,
+        lightning_interval: Some((8., 16.)),
+    },
+    WeatherParams {
+        rain: 1.,
+        fog: 0.6,
+        lightning_interval: Some((3., 8.)),
+    },
+];
+
+/// Event to change the weather, overriding the one of the stage until the next one
+/// starts.
+#[derive(Debug, Clone, Copy)]
+pub struct WeatherEvent(pub WeatherParams);
+
+/// Event sent when lightning strikes, to flash the sunlight.
+#[derive(Debug, Clone, Copy)]
+pub struct LightningEvent;
+
+/// Base stream of the [`RunSeed`] generators of the weather. The stage index is added
+/// to it, so that each stage draws its own sequence.
+const WEATHER_STREAM: u64 = 0x3000;
+
+/// Rate at which the rain and fog intensities move toward those of the current weather,
+/// per second.
+const WEATHER_BLEND_RATE: f32 = 0.25;
+
+/// Number of rain streaks at full rain intensity.
+const RAIN_STREAK_COUNT: usize = 120;
+
+/// Velocity of the rain streaks, in world units per second. Slanted by the wind and the
+/// forward motion of the player.
+const RAIN_VELOCITY: Vec3 = bevy::math::const_vec3!([-3., -9., 0.]);
+
+/// Extent of the area covered by the rain, in world units, wide enough to cover the
+/// screen at any aspect ratio.
+const RAIN_EXTENT: Rect<f32> = Rect {
+    left: -5.,
+    right: 5.,
+    bottom: -2.5,
+    top: 2.5,
+};
+
+/// Depth of the rain streaks, in front of the gameplay plane.
+const RAIN_DEPTH: f32 = 0.3;
+
+/// Mie scattering coefficient of the sky at full fog density, relative to the default.
+const FOG_MIE_MULTIPLIER: f32 = 12.;
+
+/// Weather state of the current stage.
+struct Weather {
+    /// Index of the current stage.
+    stage: usize,
+    /// Index of the stage whose weather is applied, if any.
+    applied_stage: Option<usize>,
+    /// Generator of the current stage, seeded from the [`RunSeed`].
+    rng: StdRng,
+    /// Weather toward which the current intensities move.
+    target: WeatherParams,
+    /// Current intensity of the rain.
+    rain: f32,
+    /// Current density of the fog.
+    fog: f32,
+    /// Time before the next lightning strike, in seconds, if any.
+    next_lightning: Option<f32>,
+    /// Mie scattering coefficient of the sky without any fog.
+    clear_mie_coefficient: f32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather {
+            stage: 0,
+            applied_stage: None,
+            rng: StdRng::seed_from_u64(0),
+            target: WeatherParams::default(),
+            rain: 0.,
+            fog: 0.,
+            next_lightning: None,
+            clear_mie_coefficient: AtmosphereMat::default().mie_coefficient,
+        }
+    }
+}
+
+impl Weather {
+    /// Change the target weather, and draw the delay before the first lightning strike.
+    fn set_target(&mut self, params: WeatherParams) {
+        self.target = params;
+        self.roll_next_lightning();
+    }
+
+    /// Draw the delay before the next lightning strike, if any.
+    fn roll_next_lightning(&mut self) {
+        self.next_lightning = self
+            .target
+            .lightning_interval
+            .map(|(min, max)| self.rng.gen_range(min..=max));
+    }
+}
+
+fn stage_weather(stage: usize) -> WeatherParams {
+    STAGE_WEATHERS[stage.min(STAGE_WEATHERS.len() - 1)]
+}
+
+/// Streak of the rain, visible only when the rain is intense enough.
+#[derive(Component)]
+struct RainStreak(usize);
+
+fn weather_setup(
+    mut commands: Commands,
+    mut weather: ResMut<Weather>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    run_seed: Res<RunSeed>,
+    launch: Res<LaunchOptions>,
+) {
+    *weather = Weather::default();
+    weather.stage = launch.start_stage();
+
+    let mesh = meshes.add(Mesh::from(shape::Quad {
+        size: Vec2::new(0.008, 0.25),
+        flip: false,
+    }));
+    let material = materials.add(StandardMaterial {
+        base_color: Color::rgba(0.75, 0.8, 0.9, 0.35),
+        unlit: true,
+        alpha_mode: AlphaMode::Blend,
+        ..Default::default()
+    });
+    // Align the streaks with their velocity
+    let rotation = Quat::from_rotation_arc(-Vec3::Y, RAIN_VELOCITY.normalize());
+    let mut rng = run_seed.rng(WEATHER_STREAM);
+    for index in 0..RAIN_STREAK_COUNT {
+        let x = rng.gen_range(RAIN_EXTENT.left..RAIN_EXTENT.right);
+        let y = rng.gen_range(RAIN_EXTENT.bottom..RAIN_EXTENT.top);
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_xyz(x, y, RAIN_DEPTH).with_rotation(rotation),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(Name::new("RainStreak"))
+            .insert(RainStreak(index))
+            .insert(InGameEntity)
+            .insert(NotShadowCaster)
+            .insert(NotShadowReceiver);
+    }
+}
+
+/// Track the current stage and apply its weather, blend the rain and fog toward the
+/// current weather, and strike lightning from time to time.
+fn update_weather(
+    mut weather: ResMut<Weather>,
+    run_seed: Res<RunSeed>,
+    mut stage_clear_events: EventReader<StageClearEvent>,
+    mut weather_events: EventReader<WeatherEvent>,
+    mut lightning_events: EventWriter<LightningEvent>,
+    mut sky_mat: ResMut<AtmosphereMat>,
+    game_time: Res<GameTime>,
+) {
+    for ev in stage_clear_events.iter() {
+        if !ev.is_last {
+            weather.stage = ev.stage + 1;
+        }
+    }
+    if weather.applied_stage != Some(weather.stage) {
+        let stage = weather.stage;
+        weather.applied_stage = Some(stage);
+        weather.rng = run_seed.rng(WEATHER_STREAM + 1 + stage as u64);
+        debug!(target: "weather", "WEATHER: stage={}", stage + 1);
+        weather.set_target(stage_weather(stage));
+    }
+    for ev in weather_events.iter() {
+        debug!(target: "weather", "WEATHER: {:?}", ev.0);
+        weather.set_target(ev.0);
+    }
+
+    let dt = game_time.delta_seconds();
+    let step = WEATHER_BLEND_RATE * dt;
+    let blend = |value: f32, target: f32| value + (target - value).clamp(-step, step);
+    weather.rain = blend(weather.rain, weather.target.rain);
+    weather.fog = blend(weather.fog, weather.target.fog);
+
+    if let Some(next_lightning) = weather.next_lightning {
+        let next_lightning = next_lightning - dt;
+        if next_lightning <= 0. {
+            lightning_events.send(LightningEvent);
+            weather.roll_next_lightning();
+        } else {
+            weather.next_lightning = Some(next_lightning);
+        }
+    }
+
+    let mie_coefficient =
+        weather.clear_mie_coefficient * (1. + (FOG_MIE_MULTIPLIER - 1.) * weather.fog);
+    if sky_mat.mie_coefficient != mie_coefficient {
+        sky_mat.mie_coefficient = mie_coefficient;
+    }
+}
+
+/// Make the rain streaks fall, wrapping them to the top once past the bottom, and show
+/// as many of them as the rain intensity calls for.
+fn update_rain(
+    weather: Res<Weather>,
+    mut query: Query<(&RainStreak, &mut Transform, &mut Visibility)>,
+    game_time: Res<GameTime>,
+) {
+    let dt = game_time.delta_seconds();
+    let mut rng = rand::thread_rng();
+    let visible_count = (weather.rain * RAIN_STREAK_COUNT as f32).round() as usize;
+    for (streak, mut transform, mut visibility) in query.iter_mut() {
+        let is_visible = streak.0 < visible_count;
+        if visibility.is_visible != is_visible {
+            visibility.is_visible = is_visible;
+        }
+        if !is_visible {
+            continue;
+        }
+        transform.translation += RAIN_VELOCITY * dt;
+        if transform.translation.y < RAIN_EXTENT.bottom {
+            transform.translation.y += RAIN_EXTENT.top - RAIN_EXTENT.bottom;
+            transform.translation.x = rng.gen_range(RAIN_EXTENT.left..RAIN_EXTENT.right);
+        }
+    }
+}
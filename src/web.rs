@@ -0,0 +1,62 @@
+use bevy::prelude::*;
+
+/// Web-specific plumbing, to make the canvas track the size and pixel density of the
+/// browser window. Only built for the `wasm32` target.
+pub struct WebPlugin;
+
+impl Plugin for WebPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PreUpdate, fit_canvas_to_browser);
+    }
+}
+
+/// Browser window size and device pixel ratio last applied to the canvas.
+#[derive(Default)]
+struct BrowserSize {
+    width: f32,
+    height: f32,
+    pixel_ratio: f64,
+}
+
+/// Resize the primary window to the browser window when the latter is resized or
+/// zoomed. The camera projection and the UI layout follow the window size on their own,
+/// and the letterboxing keeps the playfield at a fixed aspect ratio.
+fn fit_canvas_to_browser(mut windows: ResMut<Windows>, mut last_size: Local<BrowserSize>) {
+    let browser = if let Some(browser) = web_sys::window() {
+        browser
+    } else {
+        return;
+    };
+    let size = |value: Result<wasm_bindgen::JsValue, wasm_bindgen::JsValue>| {
+        value.ok().and_then(|value| value.as_f64()).unwrap_or(0.) as f32
+    };
+    let width = size(browser.inner_width());
+    let height = size(browser.inner_height());
+    let pixel_ratio = browser.device_pixel_ratio();
+    if width <= 0. || height <= 0. {
+        return;
+    }
+    if width == last_size.width
+        && height == last_size.height
+        && pixel_ratio == last_size.pixel_ratio
+    {
+        return;
+    }
+    *last_size = BrowserSize {
+        width,
+        height,
+        pixel_ratio,
+    };
+
+    if let Some(window) = windows.get_primary_mut() {
+        debug!(
+            target: "web",
+            "Browser window resized: {}x{} pixel_ratio={}",
+            width, height, pixel_ratio
+        );
+        // Render at the native resolution of high-DPI screens, while keeping the logical
+        // size of the window equal to the browser viewport
+        window.set_scale_factor_override(Some(pixel_ratio));
+        window.set_resolution(width, height);
+    }
+}